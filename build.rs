@@ -4,6 +4,7 @@ use std::process::Command;
 fn main() {
     build_ver();
     platform_cfg();
+    grpc();
 }
 
 fn build_ver() {
@@ -45,6 +46,19 @@ fn github_ver() -> Option<String> {
     }
 }
 
+#[cfg(feature = "grpc")]
+fn grpc() {
+    println!("cargo:rerun-if-changed=proto/neolink.proto");
+    // Only the server is used and the generated client needs the 2021 prelude
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/neolink.proto"], &["proto"])
+        .unwrap();
+}
+
+#[cfg(not(feature = "grpc"))]
+fn grpc() {}
+
 #[cfg(target_os = "windows")]
 fn platform_cfg() {
     let gstreamer_dir = env::var_os("GSTREAMER_1_0_ROOT_X86_64")
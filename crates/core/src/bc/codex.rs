@@ -26,6 +26,9 @@ impl BcCodex {
             context: BcContext::new(credentials),
         }
     }
+    pub(crate) fn set_encrypted(&mut self, encryption_protocol: EncryptionProtocol) {
+        self.context.set_encrypted(encryption_protocol);
+    }
 }
 
 impl Encoder<Bc> for BcCodex {
@@ -93,42 +96,8 @@ impl Decoder for BcCodex {
             Err(e) => return Err(e),
         };
         // Update context
-        if let Bc {
-            meta:
-                BcMeta {
-                    msg_id: 1,
-                    response_code,
-                    ..
-                },
-            body:
-                BcBody::ModernMsg(ModernMsg {
-                    payload:
-                        Some(BcPayloads::BcXml(BcXml {
-                            encryption: Some(Encryption { nonce, .. }),
-                            ..
-                        })),
-                    ..
-                }),
-        } = &bc
-        {
-            if response_code >> 8 == 0xdd {
-                // Login reply has the encryption info
-                // Set that the encryption type now
-                let encryption_protocol_byte = (response_code & 0xff) as usize;
-                match encryption_protocol_byte {
-                    0x00 => self.context.set_encrypted(EncryptionProtocol::Unencrypted),
-                    0x01 => self.context.set_encrypted(EncryptionProtocol::BCEncrypt),
-                    0x02 => self.context.set_encrypted(EncryptionProtocol::aes(
-                        self.context.credentials.make_aeskey(nonce),
-                    )),
-                    0x12 => self.context.set_encrypted(EncryptionProtocol::full_aes(
-                        self.context.credentials.make_aeskey(nonce),
-                    )),
-                    _ => {
-                        return Err(Error::UnknownEncryption(encryption_protocol_byte));
-                    }
-                }
-            }
+        if let Some(encryption_protocol) = login_encryption(&self.context.credentials, &bc)? {
+            self.context.set_encrypted(encryption_protocol);
         }
 
         if let BcBody::ModernMsg(ModernMsg {
@@ -150,3 +119,44 @@ impl Decoder for BcCodex {
         Ok(Some(bc))
     }
 }
+
+/// The encryption that the camera chose if this is its reply to a login
+pub(crate) fn login_encryption(
+    credentials: &Credentials,
+    bc: &Bc,
+) -> Result<Option<EncryptionProtocol>> {
+    if let Bc {
+        meta: BcMeta {
+            msg_id: 1,
+            response_code,
+            ..
+        },
+        body:
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        encryption: Some(Encryption { nonce, .. }),
+                        ..
+                    })),
+                ..
+            }),
+    } = bc
+    {
+        if response_code >> 8 == 0xdd {
+            // Login reply has the encryption info
+            let encryption_protocol_byte = (response_code & 0xff) as usize;
+            return match encryption_protocol_byte {
+                0x00 => Ok(Some(EncryptionProtocol::Unencrypted)),
+                0x01 => Ok(Some(EncryptionProtocol::BCEncrypt)),
+                0x02 => Ok(Some(EncryptionProtocol::aes(
+                    credentials.make_aeskey(nonce),
+                ))),
+                0x12 => Ok(Some(EncryptionProtocol::full_aes(
+                    credentials.make_aeskey(nonce),
+                ))),
+                _ => Err(Error::UnknownEncryption(encryption_protocol_byte)),
+            };
+        }
+    }
+    Ok(None)
+}
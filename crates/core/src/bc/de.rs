@@ -51,9 +51,13 @@ fn bc_body<'a>(context: &BcContext, header: &BcHeader, buf: &'a [u8]) -> IResult
         let (buf, body) = bc_modern_msg(context, header, buf)?;
         Ok((buf, BcBody::ModernMsg(body)))
     } else {
-        let (buf, body) = match header.msg_id {
-            MSG_ID_LOGIN => bc_legacy_login_msg(buf)?,
-            _ => (buf, LegacyMsg::UnknownMsg),
+        // Only take the body so that a header only login upgrade does not
+        // wait on the bytes of the next message
+        let (buf, body_buf) = take(header.body_len)(buf)?;
+        let body = match header.msg_id {
+            MSG_ID_LOGIN if body_buf.is_empty() => LegacyMsg::LoginUpgrade,
+            MSG_ID_LOGIN => bc_legacy_login_msg(body_buf)?.1,
+            _ => LegacyMsg::UnknownMsg,
         };
         Ok((buf, BcBody::LegacyMsg(body)))
     }
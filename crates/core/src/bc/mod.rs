@@ -42,3 +42,12 @@ pub mod xml;
 pub mod crypto;
 
 pub(crate) mod codex;
+
+/// Decodes captured or proxied traffic between a client and a camera
+pub mod sniff;
+
+/// Frames the camera's side of a conversation to simulate a camera
+pub mod server;
+
+/// Records conversations and replays them as the camera for tests
+pub mod session;
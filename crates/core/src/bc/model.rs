@@ -18,16 +18,32 @@ pub const MSG_ID_LOGOUT: u32 = 2;
 pub const MSG_ID_VIDEO: u32 = 3;
 /// ID used to stop the video stream
 pub const MSG_ID_VIDEO_STOP: u32 = 4;
+/// Stop a download of a recording
+pub const MSG_ID_FILE_INFO_LIST_STOP: u32 = 7;
+/// Download a recording from the camera's storage
+pub const MSG_ID_FILE_INFO_LIST_DL_VIDEO: u32 = 8;
 /// TalkAbility messages have this ID
 pub const MSG_ID_TALKABILITY: u32 = 10;
 /// TalkReset messages have this ID
 pub const MSG_ID_TALKRESET: u32 = 11;
+/// Open a search of the recordings, the reply has the handle of the search
+pub const MSG_ID_FILE_INFO_LIST_OPEN: u32 = 14;
+/// Get the next page of recordings of a search
+pub const MSG_ID_FILE_INFO_LIST_GET: u32 = 15;
+/// Close a search of the recordings
+pub const MSG_ID_FILE_INFO_LIST_CLOSE: u32 = 16;
 /// PtzControl messages have this ID
 pub const MSG_ID_PTZ_CONTROL: u32 = 18;
 /// PTZ goto preset position
 pub const MSG_ID_PTZ_CONTROL_PRESET: u32 = 19;
 /// Reboot messages have this ID
 pub const MSG_ID_REBOOT: u32 = 23;
+/// Set the image (ISP) settings
+pub const MSG_ID_SET_VIDEO_INPUT: u32 = 25;
+/// Get the image (ISP) settings
+pub const MSG_ID_GET_VIDEO_INPUT: u32 = 26;
+/// Upload a firmware file to the camera
+pub const MSG_ID_UPGRADE: u32 = 27;
 /// Request motion detection messages
 pub const MSG_ID_MOTION_REQUEST: u32 = 31;
 /// Motion detection messages
@@ -36,10 +52,26 @@ pub const MSG_ID_MOTION: u32 = 33;
 pub const MSG_ID_SET_SERVICE_PORTS: u32 = 36;
 /// Get service ports
 pub const MSG_ID_GET_SERVICE_PORTS: u32 = 37;
+/// Get the text drawn over the image, the channel name and the time
+pub const MSG_ID_GET_OSD: u32 = 44;
+/// Set the text drawn over the image, the channel name and the time
+pub const MSG_ID_SET_OSD: u32 = 45;
+/// Get the encoding of the streams
+pub const MSG_ID_GET_COMPRESSION: u32 = 56;
+/// Set the encoding of the streams
+pub const MSG_ID_SET_COMPRESSION: u32 = 57;
+/// Get the camera's user accounts
+pub const MSG_ID_GET_USER_LIST: u32 = 58;
+/// Add, modify or delete the camera's user accounts
+pub const MSG_ID_SET_USER_LIST: u32 = 59;
 /// Version messages have this ID
 pub const MSG_ID_VERSION: u32 = 80;
 /// Ping messages have this ID
 pub const MSG_ID_PING: u32 = 93;
+/// Get the storage (SD card/HDD) info list
+pub const MSG_ID_GET_HDD_INFO_LIST: u32 = 102;
+/// Format a storage device
+pub const MSG_ID_HDD_INIT_LIST: u32 = 103;
 /// General system info messages have this ID
 pub const MSG_ID_GET_GENERAL: u32 = 104;
 /// Setting general system info (clock mostly) messages have this ID
@@ -48,6 +80,14 @@ pub const MSG_ID_SET_GENERAL: u32 = 105;
 pub const MSG_ID_SNAP: u32 = 109;
 /// Used to grab the UID
 pub const MSG_ID_UID: u32 = 114;
+/// Get the wifi signal strength
+pub const MSG_ID_GET_WIFI_SIGNAL: u32 = 115;
+/// Get the wifi network the camera joins
+pub const MSG_ID_GET_WIFI: u32 = 116;
+/// Set the wifi network the camera joins
+pub const MSG_ID_SET_WIFI: u32 = 117;
+/// Scan for the wifi networks near the camera
+pub const MSG_ID_SCAN_WIFI: u32 = 118;
 /// Used to pass the token and client ID for push notifications
 pub const MSG_ID_PUSH_INFO: u32 = 124;
 /// StreamInfoList messages have this ID
@@ -70,6 +110,18 @@ pub const MSG_ID_SET_LED_STATUS: u32 = 209;
 pub const MSG_ID_GET_PIR_ALARM: u32 = 212;
 /// Setting PIR status messages have this ID
 pub const MSG_ID_START_PIR_ALARM: u32 = 213;
+/// Get the email notification task
+pub const MSG_ID_GET_EMAIL_TASK: u32 = 216;
+/// Set the email notification task
+pub const MSG_ID_SET_EMAIL_TASK: u32 = 217;
+/// Get the cloud push notification task
+pub const MSG_ID_GET_PUSH_TASK: u32 = 218;
+/// Set the cloud push notification task
+pub const MSG_ID_SET_PUSH_TASK: u32 = 219;
+/// Get the FTP upload task
+pub const MSG_ID_GET_FTP_TASK: u32 = 220;
+/// Set the FTP upload task
+pub const MSG_ID_SET_FTP_TASK: u32 = 221;
 /// UDP Keep alive
 pub const MSG_ID_UDP_KEEP_ALIVE: u32 = 234;
 /// Battery message initiaed by the camera
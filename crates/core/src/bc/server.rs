@@ -0,0 +1,62 @@
+//! Encodes and decodes the camera's side of a Baichuan TCP conversation so
+//! that a camera can be simulated, such as to test a client without the
+//! hardware
+//!
+//! The codec starts unencrypted. The camera picks the encryption in its reply
+//! to the legacy login, so [`BcServerCodex::set_encrypted`] must be called
+//! before that reply is sent. AES is not offered as the client would have to
+//! be given the key
+//!
+use super::{codex::BcCodex, model::Bc};
+use crate::{bc::crypto::EncryptionProtocol, Credentials, Error, Result};
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Frames the messages a camera receives and sends
+pub struct BcServerCodex {
+    codex: BcCodex,
+}
+
+impl BcServerCodex {
+    /// Create the codec for a camera that accepts these credentials
+    pub fn new(username: &str, password: Option<&str>) -> Self {
+        Self {
+            codex: BcCodex::new(Credentials::new(username, password)),
+        }
+    }
+
+    /// Set the encryption of both directions, call it with the encryption
+    /// of the login reply before it is sent
+    pub fn set_encrypted(&mut self, encryption_protocol: EncryptionProtocol) {
+        self.codex.set_encrypted(encryption_protocol);
+    }
+}
+
+impl Encoder<Bc> for BcServerCodex {
+    type Error = Error;
+
+    fn encode(&mut self, item: Bc, dst: &mut BytesMut) -> Result<()> {
+        self.codex.encode(item, dst)
+    }
+}
+
+impl Decoder for BcServerCodex {
+    type Item = Bc;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        self.codex.decode(src)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>> {
+        self.codex.decode_eof(buf)
+    }
+}
+
+/// The hash a client sends in the modern login for a username or password
+/// and the nonce of the login reply
+pub fn login_hash(value: &str, nonce: &str) -> String {
+    let mut md5 = format!("{:X}", md5::compute(format!("{value}{nonce}")));
+    md5.truncate(31);
+    md5
+}
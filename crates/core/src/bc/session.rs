@@ -0,0 +1,331 @@
+//! Records a Baichuan conversation to a session file and replays it as the
+//! camera, so that a capture from a user's camera can be run against
+//! [`crate::bc_protocol::BcCamera`] in a test
+//!
+//! The messages are stored unencrypted and as they were decoded, so xml that
+//! is not known to this library is left out. The login hashes, passwords and
+//! push tokens are replaced so that a session can be shared without the
+//! credentials of the camera. The login reply is changed to say that the
+//! conversation is unencrypted so that the replayed client does not encrypt
+//! either
+//!
+//! The file is `BCSESSION1` followed by each message as a `u8` that is `1`
+//! when it was sent to the camera, the length of the message as a little
+//! endian `u32` and then the message
+//!
+use super::{codex::BcCodex, model::*};
+use crate::{Credentials, Error, Result};
+use futures::StreamExt;
+use std::{collections::HashMap, io::Write};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+};
+use tokio_util::codec::FramedRead;
+
+const MAGIC: &[u8] = b"BCSESSION1";
+const REDACTED: &str = "REDACTED";
+
+/// Writes the messages of a conversation to a session file
+pub struct BcRecorder<W: Write> {
+    out: W,
+}
+
+impl<W: Write> BcRecorder<W> {
+    /// Start a session file
+    pub fn new(mut out: W) -> Result<Self> {
+        out.write_all(MAGIC)?;
+        Ok(Self { out })
+    }
+
+    /// Add a message, in the order that it was sent
+    pub fn record(&mut self, to_camera: bool, mut bc: Bc) -> Result<()> {
+        // Cannot be written back
+        if let BcBody::LegacyMsg(LegacyMsg::UnknownMsg) = bc.body {
+            return Ok(());
+        }
+        redact(&mut bc);
+        let bytes = bc.serialize(vec![], &EncryptionProtocol::Unencrypted)?;
+        self.out.write_all(&[to_camera as u8])?;
+        self.out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.out.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// The writer of the session
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Remove the secrets and the encryption from a message
+fn redact(bc: &mut Bc) {
+    if bc.meta.msg_id == MSG_ID_LOGIN && bc.meta.response_code >> 8 == 0xdd {
+        bc.meta.response_code = 0xdd00;
+    }
+    match &mut bc.body {
+        BcBody::LegacyMsg(LegacyMsg::LoginMsg { username, password }) => {
+            // The legacy login must be 32 characters to be written
+            *username = "0".repeat(32);
+            *password = "0".repeat(32);
+        }
+        BcBody::ModernMsg(ModernMsg {
+            payload: Some(BcPayloads::BcXml(xml)),
+            ..
+        }) => {
+            if let Some(login_user) = xml.login_user.as_mut() {
+                login_user.user_name = REDACTED.to_string();
+                login_user.password = REDACTED.to_string();
+            }
+            if let Some(key) = xml.wifi.as_mut().and_then(|wifi| wifi.key.as_mut()) {
+                *key = REDACTED.to_string();
+            }
+            if let Some(user_list) = xml.user_list.as_mut() {
+                for password in user_list
+                    .user
+                    .iter_mut()
+                    .filter_map(|user| user.password.as_mut())
+                {
+                    *password = REDACTED.to_string();
+                }
+            }
+            if let Some(push_info) = xml.push_info.as_mut() {
+                push_info.token = REDACTED.to_string();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A recorded conversation that can be replayed as the camera
+///
+/// Each message of the client is matched with the next recorded message to
+/// the camera with the same msg_id, skipping any that the client did not
+/// send. The camera's messages that followed it are sent back with the
+/// msg_num that the client used. A message that cannot be matched is refused
+/// with a 400
+pub struct BcSession {
+    messages: Vec<Recorded>,
+}
+
+struct Recorded {
+    to_camera: bool,
+    msg_id: u32,
+    msg_num: u16,
+    bytes: Vec<u8>,
+}
+
+/// Where the msg_num is in the header
+const MSG_NUM: std::ops::Range<usize> = 14..16;
+
+impl BcSession {
+    /// Read a session from the contents of its file
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut data = data
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| Error::OtherString("Not a BC session file".to_string()))?;
+        let truncated = || Error::OtherString("The BC session file is truncated".to_string());
+        let mut messages = vec![];
+        while !data.is_empty() {
+            if data.len() < 5 {
+                return Err(truncated());
+            }
+            let (head, rest) = data.split_at(5);
+            let len = u32::from_le_bytes([head[1], head[2], head[3], head[4]]) as usize;
+            if rest.len() < len {
+                return Err(truncated());
+            }
+            let (bytes, rest) = rest.split_at(len);
+            // Every message has at least the 20 bytes of the legacy header
+            if bytes.len() < 20 {
+                return Err(truncated());
+            }
+            messages.push(Recorded {
+                to_camera: head[0] != 0,
+                msg_id: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+                msg_num: u16::from_le_bytes([bytes[MSG_NUM.start], bytes[MSG_NUM.start + 1]]),
+                bytes: bytes.to_vec(),
+            });
+            data = rest;
+        }
+        Ok(Self { messages })
+    }
+
+    /// Replay the session to each client that connects, one at a time and
+    /// each from the start
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            if let Err(e) = self.replay(stream).await {
+                log::debug!("Replay to {peer} ended: {e:?}");
+            }
+        }
+    }
+
+    /// Act as the camera on a connection until the client disconnects
+    pub async fn replay(&self, stream: TcpStream) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut client = FramedRead::new(read, BcCodex::new(Credentials::default()));
+        let mut next = 0;
+        // The recorded msg_num of each request to the one the client used
+        let mut msg_nums = HashMap::<u16, u16>::new();
+        while let Some(msg) = client.next().await {
+            let msg = msg?;
+            let Some(found) = self.messages[next..]
+                .iter()
+                .position(|recorded| recorded.to_camera && recorded.msg_id == msg.meta.msg_id)
+            else {
+                log::debug!(
+                    "Replay: Refusing msg_id {} as it is not recorded",
+                    msg.meta.msg_id
+                );
+                let refused = Bc::new_from_meta(BcMeta {
+                    response_code: 400,
+                    class: 0x0000,
+                    ..msg.meta
+                });
+                let bytes = refused.serialize(vec![], &EncryptionProtocol::Unencrypted)?;
+                write.write_all(&bytes).await?;
+                continue;
+            };
+            next += found;
+            msg_nums.insert(self.messages[next].msg_num, msg.meta.msg_num);
+            next += 1;
+            while let Some(reply) = self.messages.get(next).filter(|m| !m.to_camera) {
+                let mut bytes = reply.bytes.clone();
+                if let Some(msg_num) = msg_nums.get(&reply.msg_num) {
+                    bytes[MSG_NUM].copy_from_slice(&msg_num.to_le_bytes());
+                }
+                write.write_all(&bytes).await?;
+                next += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bc::xml::*;
+    use crate::bc_protocol::{BcCamera, BcCameraOpt, ConnectionProtocol, DiscoveryMethods};
+
+    fn meta(msg_id: u32, msg_num: u16, response_code: u16, class: u16) -> BcMeta {
+        BcMeta {
+            msg_id,
+            channel_id: 0,
+            msg_num,
+            stream_type: 0,
+            response_code,
+            class,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_login() -> Result<()> {
+        let mut recorder = BcRecorder::new(vec![])?;
+        recorder.record(
+            true,
+            Bc {
+                meta: meta(MSG_ID_LOGIN, 7, 0xdc12, 0x6514),
+                body: BcBody::LegacyMsg(LegacyMsg::LoginUpgrade),
+            },
+        )?;
+        recorder.record(
+            false,
+            Bc::new_from_xml(
+                meta(MSG_ID_LOGIN, 7, 0xdd12, 0x6614),
+                BcXml {
+                    encryption: Some(Encryption {
+                        version: xml_ver(),
+                        type_: "md5".to_string(),
+                        nonce: "0-AhnEZyUg6eKrJFIWgXPF".to_string(),
+                    }),
+                    ..Default::default()
+                },
+            ),
+        )?;
+        recorder.record(
+            true,
+            Bc::new_from_xml(
+                meta(MSG_ID_LOGIN, 7, 0, 0x6414),
+                BcXml {
+                    login_user: Some(LoginUser {
+                        version: xml_ver(),
+                        user_name: "9F07915E819A076E2E14169830769D6".to_string(),
+                        password: "8EFECD610524A98390F118D2789BE3B".to_string(),
+                        user_ver: 1,
+                    }),
+                    login_net: Some(LoginNet::default()),
+                    ..Default::default()
+                },
+            ),
+        )?;
+        recorder.record(
+            false,
+            Bc::new_from_xml(
+                meta(MSG_ID_LOGIN, 7, 200, 0x0000),
+                BcXml {
+                    device_info: Some(DeviceInfo {
+                        version: Some(xml_ver()),
+                        resolution: Some(Resolution {
+                            name: "2304*1296".to_string(),
+                            width: 2304,
+                            height: 1296,
+                        }),
+                    }),
+                    ..Default::default()
+                },
+            ),
+        )?;
+        recorder.record(
+            true,
+            Bc::new_from_meta(meta(MSG_ID_ABILITY_INFO, 8, 0, 0x6414)),
+        )?;
+        recorder.record(
+            false,
+            Bc::new_from_xml(
+                meta(MSG_ID_ABILITY_INFO, 8, 200, 0x0000),
+                BcXml {
+                    ability_info: Some(AbilityInfo {
+                        username: "admin".to_string(),
+                        system: Some(AbilityInfoToken {
+                            sub_module: vec![AbilityInfoSubModule {
+                                channel_id: None,
+                                ability_value: "general_rw, version_ro".to_string(),
+                            }],
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ),
+        )?;
+        let data = recorder.into_inner();
+        assert!(!data
+            .windows(10)
+            .any(|w| w == b"9F07915E81" || w == b"8EFECD6105"));
+
+        let session = BcSession::from_bytes(&data)?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        tokio::spawn(async move { session.serve(listener).await });
+
+        let camera = BcCamera::new(&BcCameraOpt {
+            name: "Replay".to_string(),
+            channel_id: 0,
+            addrs: vec!["127.0.0.1".parse()?],
+            uid: None,
+            port: Some(port),
+            protocol: ConnectionProtocol::Tcp,
+            discovery: DiscoveryMethods::None,
+            max_discovery_retries: 0,
+            credentials: Credentials::new("admin", Some("password")),
+            debug: false,
+        })
+        .await?;
+        let device_info = camera.login().await?;
+        assert_eq!(device_info.resolution.map(|r| r.width), Some(2304));
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! Decodes both directions of a Baichuan TCP conversation that was not made
+//! by this library, such as one between the official client and a camera
+//!
+//! The login reply from the camera sets the encryption of both directions,
+//! so the bytes must be given in the order they were sent. UDP connections
+//! carry the same stream inside [`UdpData`] packets
+//!
+use super::{
+    codex::{login_encryption, BcCodex},
+    model::Bc,
+};
+use crate::{bcudp::model::UdpData, Credentials, Result};
+use bytes::BytesMut;
+use tokio_util::codec::Decoder;
+
+/// Decodes the messages of a conversation as its bytes arrive
+pub struct BcSniffer {
+    credentials: Credentials,
+    to_camera: (BcCodex, BytesMut),
+    from_camera: (BcCodex, BytesMut),
+    last_udp_to_camera: Option<u32>,
+    last_udp_from_camera: Option<u32>,
+}
+
+impl BcSniffer {
+    /// Create a sniffer for a conversation
+    ///
+    /// The credentials are those the client logs in with, they are needed
+    /// to decrypt AES encrypted messages
+    pub fn new(username: &str, password: Option<&str>) -> Self {
+        let credentials = Credentials::new(username, password);
+        Self {
+            to_camera: (BcCodex::new(credentials.clone()), BytesMut::new()),
+            from_camera: (BcCodex::new(credentials.clone()), BytesMut::new()),
+            last_udp_to_camera: None,
+            last_udp_from_camera: None,
+            credentials,
+        }
+    }
+
+    /// Decode the bytes sent by the client to the camera
+    ///
+    /// A message that cannot be decoded is returned as an error and the
+    /// rest of the bytes given with it are skipped
+    pub fn to_camera(&mut self, data: &[u8]) -> Vec<Result<Bc>> {
+        let (codex, buf) = &mut self.to_camera;
+        decode_all(codex, buf, data)
+    }
+
+    /// Decode the bytes sent by the camera to the client
+    ///
+    /// A message that cannot be decoded is returned as an error and the
+    /// rest of the bytes given with it are skipped
+    pub fn from_camera(&mut self, data: &[u8]) -> Vec<Result<Bc>> {
+        let (codex, buf) = &mut self.from_camera;
+        let msgs = decode_all(codex, buf, data);
+        // The camera codex sets its own encryption from the login reply,
+        // the client's side needs to be told
+        for msg in msgs.iter().flatten() {
+            if let Ok(Some(encryption_protocol)) = login_encryption(&self.credentials, msg) {
+                self.to_camera.0.set_encrypted(encryption_protocol);
+            }
+        }
+        msgs
+    }
+
+    /// Decode a UDP data packet sent by the client to the camera
+    ///
+    /// Resent packets are skipped
+    pub fn udp_to_camera(&mut self, data: &UdpData) -> Vec<Result<Bc>> {
+        if !is_next(&mut self.last_udp_to_camera, data.packet_id) {
+            return vec![];
+        }
+        self.to_camera(&data.payload)
+    }
+
+    /// Decode a UDP data packet sent by the camera to the client
+    ///
+    /// Resent packets are skipped
+    pub fn udp_from_camera(&mut self, data: &UdpData) -> Vec<Result<Bc>> {
+        if !is_next(&mut self.last_udp_from_camera, data.packet_id) {
+            return vec![];
+        }
+        self.from_camera(&data.payload)
+    }
+}
+
+/// Whether the packet is new, packets that arrive out of order cannot be
+/// decoded so they are skipped too
+fn is_next(last: &mut Option<u32>, packet_id: u32) -> bool {
+    match last {
+        Some(last) if packet_id <= *last => false,
+        _ => {
+            *last = Some(packet_id);
+            true
+        }
+    }
+}
+
+fn decode_all(codex: &mut BcCodex, buf: &mut BytesMut, data: &[u8]) -> Vec<Result<Bc>> {
+    buf.extend_from_slice(data);
+    let mut msgs = vec![];
+    loop {
+        match codex.decode(buf) {
+            Ok(Some(msg)) => msgs.push(Ok(msg)),
+            Ok(None) => break,
+            Err(e) => {
+                // Nothing after a bad message can be framed so start again
+                // with the next bytes
+                buf.clear();
+                msgs.push(Err(e));
+                break;
+            }
+        }
+    }
+    msgs
+}
@@ -132,6 +132,54 @@ pub struct BcXml {
     /// For changing rtmp server port
     #[serde(rename = "OnvifPort", skip_serializing_if = "Option::is_none")]
     pub onvif_port: Option<OnvifPort>,
+    /// The storage devices (SD cards/HDDs) of the camera
+    #[serde(rename = "HddInfoList", skip_serializing_if = "Option::is_none")]
+    pub hdd_info_list: Option<HddInfoList>,
+    /// The signal strength of the wifi connection
+    #[serde(rename = "WifiSignal", skip_serializing_if = "Option::is_none")]
+    pub wifi_signal: Option<WifiSignal>,
+    /// The wifi network the camera joins
+    #[serde(rename = "Wifi", skip_serializing_if = "Option::is_none")]
+    pub wifi: Option<Wifi>,
+    /// The wifi networks found by a scan
+    #[serde(rename = "WifiScanList", skip_serializing_if = "Option::is_none")]
+    pub wifi_scan_list: Option<WifiScanList>,
+    /// The email notification task
+    #[serde(rename = "EmailTask", skip_serializing_if = "Option::is_none")]
+    pub email_task: Option<NotificationTask>,
+    /// The cloud push notification task
+    #[serde(rename = "PushTask", skip_serializing_if = "Option::is_none")]
+    pub push_task: Option<NotificationTask>,
+    /// The FTP upload task
+    #[serde(rename = "FtpTask", skip_serializing_if = "Option::is_none")]
+    pub ftp_task: Option<NotificationTask>,
+    /// Used to search for and download recordings
+    #[serde(rename = "FileInfoList", skip_serializing_if = "Option::is_none")]
+    pub file_info_list: Option<FileInfoList>,
+    /// The user accounts of the camera
+    #[serde(rename = "UserList", skip_serializing_if = "Option::is_none")]
+    pub user_list: Option<UserList>,
+    /// Sent before a firmware file is uploaded
+    #[serde(rename = "UpgradeFileInfo", skip_serializing_if = "Option::is_none")]
+    pub upgrade_file_info: Option<UpgradeFileInfo>,
+    /// Used to format a storage device
+    #[serde(rename = "HddInitList", skip_serializing_if = "Option::is_none")]
+    pub hdd_init_list: Option<HddInitList>,
+    /// The basic image settings
+    #[serde(rename = "VideoInput", skip_serializing_if = "Option::is_none")]
+    pub video_input: Option<VideoInput>,
+    /// The advanced image settings, sent along with the [VideoInput]
+    #[serde(rename = "InputAdvanceCfg", skip_serializing_if = "Option::is_none")]
+    pub input_advance_cfg: Option<InputAdvanceCfg>,
+    /// The channel name drawn over the image
+    #[serde(rename = "OsdChannelName", skip_serializing_if = "Option::is_none")]
+    pub osd_channel_name: Option<OsdChannelName>,
+    /// The time drawn over the image, sent along with the [OsdChannelName]
+    #[serde(rename = "OsdDatetime", skip_serializing_if = "Option::is_none")]
+    pub osd_datetime: Option<OsdDatetime>,
+    /// The encoding of the streams
+    #[serde(rename = "Compression", skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
 }
 
 impl BcXml {
@@ -409,7 +457,8 @@ pub struct LedState {
     pub led_version: Option<u32>,
     /// State of the IR LEDs values are "auto", "open", "close"
     pub state: String,
-    /// State of the LED status light (blue on light), values are "open", "close"
+    /// State of the LED status light (blue on light), values are "open", "close" and
+    /// "auto" on cameras that turn it off at night
     #[serde(rename = "lightState")]
     pub light_state: String,
 }
@@ -701,7 +750,7 @@ pub struct BatteryList {
 }
 
 /// The individual battery info
-#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
 pub struct BatteryInfo {
     /// The channel the for the camera usually 0
     #[serde(rename = "channelId")]
@@ -1062,7 +1111,7 @@ pub struct StartZoomFocus {
     /// Channel ID
     #[serde(rename = "channelId")]
     pub channel_id: u8,
-    /// Command: Observed values: zoomPos. (Write Only)
+    /// Command: `zoomPos` or `focusPos`. (Write Only)
     pub command: String,
     /// Target Position: Observed Values: 2994, 2508, 2888, 3089, 3194, 3163. (Write Only)
     #[serde(rename = "movePos")]
@@ -1380,7 +1429,7 @@ pub struct AudioPlayInfo {
     /// Channel ID
     #[serde(rename = "channelId")]
     pub channel_id: u8,
-    /// Playmode: 0
+    /// Playmode: 0 plays `play_times` times, 1 is manual control with `on_off`
     #[serde(rename = "playMode")]
     pub play_mode: u32,
     /// Duration: 0
@@ -1389,7 +1438,7 @@ pub struct AudioPlayInfo {
     /// Times to play: 1
     #[serde(rename = "playTimes")]
     pub play_times: u32,
-    /// On or Off: 0
+    /// On or Off in manual mode: 0 or 1
     #[serde(rename = "onOff")]
     pub on_off: u32,
 }
@@ -1478,6 +1527,531 @@ pub struct OnvifPort {
     pub enable: Option<u32>,
 }
 
+/// HddInfoList xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct HddInfoList {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// One entry per storage device, empty when no SD card is inserted
+    #[serde(default, rename = "HddInfo")]
+    pub hdd_info: Vec<HddInfo>,
+}
+
+/// The individual storage device of a [HddInfoList]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct HddInfo {
+    /// The index of the storage device
+    pub number: u8,
+    /// Total size in MB
+    pub capacity: u64,
+    /// Free space in MB
+    #[serde(rename = "remainSize", skip_serializing_if = "Option::is_none")]
+    pub remain_size: Option<u64>,
+    /// Known values `1` mounted and `0` not mounted (missing or failed)
+    pub mount: u8,
+    /// Known values `1` formatted and `0` needs a format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<u8>,
+    /// Observed values `1` HDD and `2` SD card
+    #[serde(rename = "storageType", skip_serializing_if = "Option::is_none")]
+    pub storage_type: Option<u8>,
+}
+
+/// HddInitList xml
+///
+/// Sent to format the storage devices listed
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct HddInitList {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// The storage devices to format
+    #[serde(default, rename = "HddInit")]
+    pub hdd_init: Vec<HddInit>,
+}
+
+/// A storage device of a [HddInitList]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct HddInit {
+    /// The `number` of the [HddInfo] to format
+    #[serde(rename = "initId")]
+    pub init_id: u8,
+    /// Known values `1` for a full format
+    #[serde(rename = "type")]
+    pub init_type: u8,
+}
+
+/// WifiSignal xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct WifiSignal {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// The RSSI in dBm
+    pub signal: i32,
+}
+
+/// Wifi xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Wifi {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Name of the network
+    pub ssid: String,
+    /// Password of the network, only sent to the camera never recieved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+/// WifiScanList xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct WifiScanList {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// The networks that were found
+    #[serde(default, rename = "WifiScan")]
+    pub wifi_scan: Vec<WifiScan>,
+}
+
+/// A network of a [WifiScanList]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct WifiScan {
+    /// Name of the network
+    pub ssid: String,
+    /// The RSSI in dBm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    /// Observed values `none`, `wep` and `wpa2`
+    #[serde(rename = "encrypt", skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<String>,
+}
+
+/// The EmailTask, PushTask and FtpTask xml which all share this layout
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+pub struct NotificationTask {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Known values `1` enabled and `0` disabled
+    pub enable: u8,
+    /// When the task is active, kept so that it survives a toggle of `enable`
+    #[serde(rename = "timeBlockList", skip_serializing_if = "Option::is_none")]
+    pub time_block_list: Option<TimeBlockList>,
+}
+
+/// VideoInput xml, the basic image settings
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct VideoInput {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Brightness, observed range is 0-255
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bright: Option<u32>,
+    /// Contrast, observed range is 0-255
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contrast: Option<u32>,
+    /// Saturation, observed range is 0-255
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<u32>,
+    /// Hue, observed range is 0-255
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hue: Option<u32>,
+    /// Sharpness, observed range is 0-255
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sharpen: Option<u32>,
+}
+
+/// InputAdvanceCfg xml, the advanced image settings
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct InputAdvanceCfg {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Observed value is `1`
+    #[serde(rename = "digitalChannel", skip_serializing_if = "Option::is_none")]
+    pub digital_channel: Option<u8>,
+    /// Anti flicker for the mains frequency
+    #[serde(rename = "PowerLineFrequency", skip_serializing_if = "Option::is_none")]
+    pub power_line_frequency: Option<PowerLineFrequency>,
+    /// Exposure
+    #[serde(rename = "Exposure", skip_serializing_if = "Option::is_none")]
+    pub exposure: Option<Exposure>,
+    /// White balance
+    #[serde(rename = "Scene", skip_serializing_if = "Option::is_none")]
+    pub scene: Option<Scene>,
+    /// Day and night mode
+    #[serde(rename = "DayNight", skip_serializing_if = "Option::is_none")]
+    pub day_night: Option<DayNight>,
+    /// Back light compensation
+    #[serde(rename = "BLC", skip_serializing_if = "Option::is_none")]
+    pub blc: Option<Blc>,
+    /// Known values `1` mirrored and `0` not
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<u8>,
+    /// Known values `1` flipped and `0` not
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flip: Option<u8>,
+    /// Iris
+    #[serde(rename = "Iris", skip_serializing_if = "Option::is_none")]
+    pub iris: Option<Iris>,
+    /// 3D noise reduction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nr3d: Option<Nr3d>,
+}
+
+/// The PowerLineFrequency of an [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct PowerLineFrequency {
+    /// Known values `50hz` and `60hz`
+    pub mode: String,
+    /// Known values `1` enabled and `0` disabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable: Option<u8>,
+}
+
+/// The Exposure of an [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Exposure {
+    /// Observed values `auto`
+    pub mode: String,
+    /// Gain limits
+    #[serde(rename = "Gainctl", skip_serializing_if = "Option::is_none")]
+    pub gain_ctl: Option<ExposureRange>,
+    /// Shutter limits
+    #[serde(rename = "Shutterctl", skip_serializing_if = "Option::is_none")]
+    pub shutter_ctl: Option<ExposureRange>,
+    /// Observed values such as `1/30`
+    #[serde(rename = "shutterLevel", skip_serializing_if = "Option::is_none")]
+    pub shutter_level: Option<String>,
+    /// Gain
+    #[serde(rename = "gainLevel", skip_serializing_if = "Option::is_none")]
+    pub gain_level: Option<String>,
+}
+
+/// The limits of the gain or shutter of an [Exposure]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct ExposureRange {
+    /// Lowest value of the camera
+    #[serde(rename = "defMin")]
+    pub def_min: u32,
+    /// Highest value of the camera
+    #[serde(rename = "defMax")]
+    pub def_max: u32,
+    /// Lowest value in use
+    #[serde(rename = "curMin")]
+    pub cur_min: u32,
+    /// Highest value in use
+    #[serde(rename = "curMax")]
+    pub cur_max: u32,
+}
+
+/// The Scene of an [InputAdvanceCfg], its white balance
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Scene {
+    /// Known values `auto` and `manual`
+    pub mode: String,
+    /// The modes of the camera, sent by the camera only
+    #[serde(rename = "modeList", skip_serializing_if = "Option::is_none")]
+    pub mode_list: Option<String>,
+    /// Red gain in `manual`
+    #[serde(rename = "Redgain", skip_serializing_if = "Option::is_none")]
+    pub red_gain: Option<IspRange>,
+    /// Blue gain in `manual`
+    #[serde(rename = "Bluegain", skip_serializing_if = "Option::is_none")]
+    pub blue_gain: Option<IspRange>,
+}
+
+/// A value of the image settings with its limits
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct IspRange {
+    /// Lowest value
+    pub min: u32,
+    /// Highest value
+    pub max: u32,
+    /// Value in use
+    pub cur: u32,
+}
+
+/// The DayNight of an [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct DayNight {
+    /// Observed values `auto`
+    pub mode: String,
+    /// Observed values `ir`
+    #[serde(rename = "IrcutMode", skip_serializing_if = "Option::is_none")]
+    pub ircut_mode: Option<String>,
+    /// Observed values `medium`
+    #[serde(rename = "Threshold", skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<String>,
+}
+
+/// The BLC of an [InputAdvanceCfg], its back light compensation
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Blc {
+    /// Known values `1` enabled and `0` disabled
+    pub enable: u8,
+    /// Observed values `backLight`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// Dynamic range
+    #[serde(rename = "dynamicrange", skip_serializing_if = "Option::is_none")]
+    pub dynamic_range: Option<IspRange>,
+    /// Back light
+    #[serde(rename = "backlight", skip_serializing_if = "Option::is_none")]
+    pub back_light: Option<IspRange>,
+}
+
+/// The Iris of an [InputAdvanceCfg]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Iris {
+    /// Known values `1` enabled and `0` disabled
+    pub enable: u8,
+    /// Observed values `success`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// Observed values `0`
+    #[serde(rename = "focusAutoiris", skip_serializing_if = "Option::is_none")]
+    pub focus_auto_iris: Option<u8>,
+}
+
+/// The nr3d of an [InputAdvanceCfg], its 3D noise reduction
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Nr3d {
+    /// Observed values `high`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Known values `1` enabled and `0` disabled
+    pub enable: u8,
+}
+
+/// OsdChannelName xml, the channel name drawn over the image
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct OsdChannelName {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// The text
+    pub name: String,
+    /// Known values `1` shown and `0` hidden
+    pub enable: u8,
+    /// Position of the text
+    #[serde(rename = "topLeftX")]
+    pub top_left_x: u32,
+    /// Position of the text
+    #[serde(rename = "topLeftY")]
+    pub top_left_y: u32,
+    /// Known values `1` enabled and `0` disabled
+    #[serde(rename = "enWatermark", skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<u8>,
+    /// Known values `1` enabled and `0` disabled
+    #[serde(rename = "enBgcolor", skip_serializing_if = "Option::is_none")]
+    pub background: Option<u8>,
+}
+
+/// OsdDatetime xml, the time drawn over the image
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct OsdDatetime {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Known values `1` shown and `0` hidden
+    pub enable: u8,
+    /// Position of the time
+    #[serde(rename = "topLeftX")]
+    pub top_left_x: u32,
+    /// Position of the time
+    #[serde(rename = "topLeftY")]
+    pub top_left_y: u32,
+    /// Sent by the camera only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// Sent by the camera only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Observed values `Chinese`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+/// Compression xml, the encoding of each stream
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Compression {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Sent by the camera only
+    #[serde(rename = "isNoTranslateFrame", skip_serializing_if = "Option::is_none")]
+    pub no_translate_frame: Option<u8>,
+    /// The main stream
+    #[serde(rename = "mainStream", skip_serializing_if = "Option::is_none")]
+    pub main_stream: Option<CompressionStream>,
+    /// The sub stream
+    #[serde(rename = "subStream", skip_serializing_if = "Option::is_none")]
+    pub sub_stream: Option<CompressionStream>,
+    /// The third stream, zero sized on cameras without one
+    #[serde(rename = "thirdStream", skip_serializing_if = "Option::is_none")]
+    pub third_stream: Option<CompressionStream>,
+}
+
+/// The encoding of a stream in a [Compression]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct CompressionStream {
+    /// Known values `1` with audio and `0` without
+    pub audio: u8,
+    /// Such as `2304*1296`
+    #[serde(rename = "resolutionName")]
+    pub resolution_name: String,
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Known values `cbr` and `vbr`
+    #[serde(rename = "encoderType")]
+    pub encoder_type: String,
+    /// Frames per second
+    pub frame: u32,
+    /// Kbps
+    #[serde(rename = "bitRate")]
+    pub bit_rate: u32,
+    /// Observed values `high` and `default`
+    #[serde(rename = "encoderProfile", skip_serializing_if = "Option::is_none")]
+    pub encoder_profile: Option<String>,
+}
+
+/// FileInfoList xml
+///
+/// Used both to ask for recordings and for the camera's reply
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct FileInfoList {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// The files, or the search when sent to the camera
+    #[serde(default, rename = "FileInfo")]
+    pub file_info: Vec<FileInfo>,
+}
+
+/// A single recording of a [FileInfoList]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct FileInfo {
+    /// Channel of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Handle of an open search
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handle: Option<u32>,
+    /// Stream recorded, known values `mainStream` and `subStream`
+    #[serde(rename = "streamType", skip_serializing_if = "Option::is_none")]
+    pub stream_type: Option<String>,
+    /// What triggered the recording. A comma separated list such as
+    /// `"manual, sched, md, pir"` when searching
+    #[serde(rename = "recordType", skip_serializing_if = "Option::is_none")]
+    pub record_type: Option<String>,
+    /// The name of the file on the camera, used to download it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Size of the file in bytes
+    #[serde(rename = "fileSize", skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    /// Start of the recording, or of the search
+    #[serde(rename = "startTime", skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<RecordTime>,
+    /// End of the recording, or of the search
+    #[serde(rename = "endTime", skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<RecordTime>,
+}
+
+/// A time of a [FileInfo] in the camera's local time
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct RecordTime {
+    /// Year such as `2023`
+    pub year: i32,
+    /// Month `1` to `12`
+    pub month: u8,
+    /// Day of the month
+    pub day: u8,
+    /// Hour `0` to `23`
+    pub hour: u8,
+    /// Minute
+    pub minute: u8,
+    /// Second
+    pub second: u8,
+}
+
+/// UserList xml
+///
+/// Lists the camera's accounts or, when sent, the accounts to change
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct UserList {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// The accounts
+    #[serde(default, rename = "User")]
+    pub user: Vec<User>,
+}
+
+/// A single account of a [UserList]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct User {
+    /// Name used to login
+    #[serde(rename = "userName")]
+    pub user_name: String,
+    /// Password, only sent to the camera never recieved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Id of the account on the camera
+    #[serde(rename = "userId", skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<u32>,
+    /// Level of the account, known values `0` for a guest and `1` for an admin
+    #[serde(rename = "userLevel", skip_serializing_if = "Option::is_none")]
+    pub user_level: Option<u8>,
+    /// Whether the account is logged in now
+    #[serde(rename = "loginState", skip_serializing_if = "Option::is_none")]
+    pub login_state: Option<u8>,
+    /// The change to make when sent, known values `add`, `modify` and `delete`
+    #[serde(rename = "userSetState", skip_serializing_if = "Option::is_none")]
+    pub user_set_state: Option<String>,
+}
+
+/// UpgradeFileInfo xml
+///
+/// Describes the firmware file that follows as binary
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct UpgradeFileInfo {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Name of the file such as `IPC_523128M5MP.4417_2112291024.RLC-510A.pak`
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    /// Size of the file in bytes
+    #[serde(rename = "fileSize")]
+    pub file_size: u64,
+}
+
 /// Convience function to return the xml version used throughout the library
 pub fn xml_ver() -> String {
     "1.1".to_string()
@@ -1827,3 +2401,481 @@ fn test_empty_floodlight_status_list() {
         _ => panic!(),
     }
 }
+
+#[test]
+fn test_hdd_info_list() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <HddInfoList version="1.1">
+        <HddInfo>
+        <number>0</number>
+        <capacity>30436</capacity>
+        <remainSize>21002</remainSize>
+        <mount>1</mount>
+        <format>1</format>
+        <storageType>2</storageType>
+        </HddInfo>
+        </HddInfoList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            hdd_info_list: Some(HddInfoList { version, hdd_info }),
+            ..
+        } if version == "1.1"
+            && hdd_info
+                == vec![HddInfo {
+                    number: 0,
+                    capacity: 30436,
+                    remain_size: Some(21002),
+                    mount: 1,
+                    format: Some(1),
+                    storage_type: Some(2),
+                }] => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_wifi_signal() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <WifiSignal version="1.1">
+        <signal>-62</signal>
+        </WifiSignal>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            wifi_signal: Some(WifiSignal { version, signal }),
+            ..
+        } if version == "1.1" && signal == -62 => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_push_task() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <PushTask version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <timeBlockList>
+        <timeBlock>
+        <enable>1</enable>
+        <weekDay>Sunday</weekDay>
+        <beginHour>0</beginHour>
+        <endHour>23</endHour>
+        </timeBlock>
+        </timeBlockList>
+        </PushTask>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            push_task:
+                Some(NotificationTask {
+                    version,
+                    channel_id: 0,
+                    enable: 1,
+                    time_block_list: Some(time_block_list),
+                }),
+            ..
+        } if version == "1.1" && time_block_list.time_block.len() == 1 => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_file_info_list() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <FileInfoList version="1.1">
+        <FileInfo>
+        <channelId>0</channelId>
+        <handle>4</handle>
+        <streamType>mainStream</streamType>
+        <recordType>md</recordType>
+        <name>01_20230518140240.mp4</name>
+        <fileSize>10485760</fileSize>
+        <startTime>
+        <year>2023</year>
+        <month>5</month>
+        <day>18</day>
+        <hour>14</hour>
+        <minute>2</minute>
+        <second>40</second>
+        </startTime>
+        <endTime>
+        <year>2023</year>
+        <month>5</month>
+        <day>18</day>
+        <hour>14</hour>
+        <minute>3</minute>
+        <second>10</second>
+        </endTime>
+        </FileInfo>
+        </FileInfoList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let files = b.file_info_list.unwrap().file_info;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name.as_deref(), Some("01_20230518140240.mp4"));
+    assert_eq!(files[0].file_size, Some(10485760));
+    assert_eq!(files[0].start_time.map(|t| t.second), Some(40));
+    assert!(files[0].start_time < files[0].end_time);
+}
+
+#[test]
+fn test_user_list() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <UserList version="1.1">
+        <User>
+        <userName>admin</userName>
+        <userId>0</userId>
+        <userLevel>1</userLevel>
+        <loginState>1</loginState>
+        </User>
+        <User>
+        <userName>viewer</userName>
+        <userId>1</userId>
+        <userLevel>0</userLevel>
+        <loginState>0</loginState>
+        </User>
+        </UserList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let users = b.user_list.unwrap().user;
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0].user_name, "admin");
+    assert_eq!(users[0].user_level, Some(1));
+    assert_eq!(users[1].user_name, "viewer");
+    assert_eq!(users[1].login_state, Some(0));
+    assert_eq!(users[1].password, None);
+}
+
+#[test]
+fn test_upgrade_file_info() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <UpgradeFileInfo version="1.1">
+        <fileName>IPC_523128M5MP.4417_2112291024.RLC-510A.pak</fileName>
+        <fileSize>41943040</fileSize>
+        </UpgradeFileInfo>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let info = b.upgrade_file_info.unwrap();
+    assert_eq!(
+        info.file_name,
+        "IPC_523128M5MP.4417_2112291024.RLC-510A.pak"
+    );
+    assert_eq!(info.file_size, 41943040);
+}
+
+#[test]
+fn test_hdd_init_list() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <HddInitList version="1.1">
+        <HddInit>
+        <initId>0</initId>
+        <type>1</type>
+        </HddInit>
+        </HddInitList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let hdd_init = b.hdd_init_list.unwrap().hdd_init;
+    assert_eq!(hdd_init.len(), 1);
+    assert_eq!(hdd_init[0].init_id, 0);
+    assert_eq!(hdd_init[0].init_type, 1);
+}
+
+#[test]
+fn test_wifi() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <Wifi version="1.1">
+        <ssid>HomeNetwork</ssid>
+        </Wifi>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let wifi = b.wifi.unwrap();
+    assert_eq!(wifi.ssid, "HomeNetwork");
+    assert_eq!(wifi.key, None);
+}
+
+#[test]
+fn test_wifi_scan_list() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <WifiScanList version="1.1">
+        <WifiScan>
+        <ssid>HomeNetwork</ssid>
+        <signal>-52</signal>
+        <encrypt>wpa2</encrypt>
+        </WifiScan>
+        <WifiScan>
+        <ssid>Guest</ssid>
+        <signal>-80</signal>
+        <encrypt>none</encrypt>
+        </WifiScan>
+        </WifiScanList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let networks = b.wifi_scan_list.unwrap().wifi_scan;
+    assert_eq!(networks.len(), 2);
+    assert_eq!(networks[0].ssid, "HomeNetwork");
+    assert_eq!(networks[0].signal, Some(-52));
+    assert_eq!(networks[1].encryption.as_deref(), Some("none"));
+}
+
+#[test]
+fn test_video_input() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <VideoInput version="1.1">
+        <channelId>0</channelId>
+        <bright>128</bright>
+        <contrast>128</contrast>
+        <saturation>128</saturation>
+        <hue>128</hue>
+        <sharpen>128</sharpen>
+        </VideoInput>
+        <InputAdvanceCfg version="1.1">
+        <channelId>0</channelId>
+        <digitalChannel>1</digitalChannel>
+        <PowerLineFrequency>
+        <mode>50hz</mode>
+        <enable>0</enable>
+        </PowerLineFrequency>
+        <Exposure>
+        <mode>auto</mode>
+        <Gainctl>
+        <defMin>1</defMin>
+        <defMax>100</defMax>
+        <curMin>1</curMin>
+        <curMax>62</curMax>
+        </Gainctl>
+        <shutterLevel>1/30</shutterLevel>
+        <gainLevel>50</gainLevel>
+        </Exposure>
+        <Scene>
+        <mode>auto</mode>
+        <modeList>auto, manual</modeList>
+        <Redgain>
+        <min>0</min>
+        <max>255</max>
+        <cur>128</cur>
+        </Redgain>
+        </Scene>
+        <DayNight>
+        <mode>auto</mode>
+        <IrcutMode>ir</IrcutMode>
+        <Threshold>medium</Threshold>
+        </DayNight>
+        <BLC>
+        <enable>0</enable>
+        <mode>backLight</mode>
+        <backlight>
+        <min>0</min>
+        <max>255</max>
+        <cur>128</cur>
+        </backlight>
+        <dynamicrange>
+        <min>0</min>
+        <max>255</max>
+        <cur>100</cur>
+        </dynamicrange>
+        </BLC>
+        <mirror>0</mirror>
+        <flip>1</flip>
+        <nr3d>
+        <value>high</value>
+        <enable>1</enable>
+        </nr3d>
+        </InputAdvanceCfg>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let video_input = b.video_input.as_ref().unwrap();
+    assert_eq!(video_input.bright, Some(128));
+    assert_eq!(video_input.sharpen, Some(128));
+    let cfg = b.input_advance_cfg.as_ref().unwrap();
+    assert_eq!(
+        cfg.exposure
+            .as_ref()
+            .unwrap()
+            .gain_ctl
+            .as_ref()
+            .unwrap()
+            .cur_max,
+        62
+    );
+    assert_eq!(
+        cfg.scene.as_ref().unwrap().mode_list.as_deref(),
+        Some("auto, manual")
+    );
+    assert_eq!(
+        cfg.blc
+            .as_ref()
+            .unwrap()
+            .dynamic_range
+            .as_ref()
+            .unwrap()
+            .cur,
+        100
+    );
+    assert_eq!(cfg.flip, Some(1));
+    assert_eq!(cfg.nr3d.as_ref().unwrap().value.as_deref(), Some("high"));
+
+    // Sent back to the camera as it was received
+    let mut out = vec![];
+    b.serialize(&mut out).unwrap();
+    assert_eq!(BcXml::try_parse(out.as_slice()).unwrap(), b);
+}
+
+#[test]
+fn test_osd() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <OsdChannelName version="1.1">
+        <channelId>0</channelId>
+        <name>Cammy02</name>
+        <enable>1</enable>
+        <topLeftX>65536</topLeftX>
+        <topLeftY>65536</topLeftY>
+        <enWatermark>0</enWatermark>
+        <enBgcolor>0</enBgcolor>
+        </OsdChannelName>
+        <OsdDatetime version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <topLeftX>65537</topLeftX>
+        <topLeftY>1</topLeftY>
+        <width>0</width>
+        <height>0</height>
+        <language>Chinese</language>
+        </OsdDatetime>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            osd_channel_name:
+                Some(OsdChannelName {
+                    name,
+                    enable: 1,
+                    top_left_x: 65536,
+                    ..
+                }),
+            osd_datetime:
+                Some(OsdDatetime {
+                    top_left_x: 65537,
+                    top_left_y: 1,
+                    language: Some(language),
+                    ..
+                }),
+            ..
+        } if name == "Cammy02" && language == "Chinese" => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_compression() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <Compression version="1.1">
+        <channelId>0</channelId>
+        <isNoTranslateFrame>1</isNoTranslateFrame>
+        <mainStream>
+        <audio>1</audio>
+        <resolutionName>2304*1296</resolutionName>
+        <width>2304</width>
+        <height>1296</height>
+        <encoderType>cbr</encoderType>
+        <frame>15</frame>
+        <bitRate>2560</bitRate>
+        <encoderProfile>high</encoderProfile>
+        </mainStream>
+        <subStream>
+        <audio>1</audio>
+        <resolutionName>896*512</resolutionName>
+        <width>896</width>
+        <height>512</height>
+        <encoderType>cbr</encoderType>
+        <frame>15</frame>
+        <bitRate>512</bitRate>
+        <encoderProfile>high</encoderProfile>
+        </subStream>
+        <thirdStream>
+        <audio>0</audio>
+        <resolutionName></resolutionName>
+        <width>0</width>
+        <height>0</height>
+        <encoderType>vbr</encoderType>
+        <frame>0</frame>
+        <bitRate>0</bitRate>
+        <encoderProfile>default</encoderProfile>
+        </thirdStream>
+        </Compression>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let compression = b.compression.unwrap();
+    assert_eq!(compression.no_translate_frame, Some(1));
+    let main = compression.main_stream.unwrap();
+    assert_eq!(main.resolution_name, "2304*1296");
+    assert_eq!(main.bit_rate, 2560);
+    assert_eq!(compression.sub_stream.unwrap().width, 896);
+    let third = compression.third_stream.unwrap();
+    assert_eq!(third.resolution_name, "");
+    assert_eq!(third.width, 0);
+}
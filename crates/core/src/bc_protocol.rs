@@ -17,15 +17,21 @@ mod battery;
 mod connection;
 mod credentials;
 mod errors;
+mod firmware;
 mod floodlight;
+mod hddinfo;
+mod image;
 mod keepalive;
+mod lan;
 mod ledstate;
 mod link;
 mod login;
 mod logout;
 mod motion;
+mod notifytasks;
 mod ping;
 mod pirstate;
+mod playback;
 mod ptz;
 mod pushinfo;
 mod reboot;
@@ -39,20 +45,28 @@ mod support;
 mod talk;
 mod time;
 mod uid;
+mod users;
 mod version;
+mod wifi;
 
+pub use connection::UdpStats;
 pub(crate) use connection::*;
 pub use credentials::*;
 pub use errors::Error;
+pub use image::{Isp, Osd};
+pub use lan::{locate_uid, search_lan};
 pub use ledstate::LightState;
 pub use login::MaxEncryption;
-pub use motion::{MotionData, MotionStatus};
+pub use motion::{MotionData, MotionDetails, MotionStatus};
+pub use notifytasks::NotificationKind;
 pub use pirstate::PirState;
+pub use playback::FileDownload;
 pub use ptz::Direction;
 pub use pushinfo::PhoneType;
 pub use resolution::*;
 use std::sync::Arc;
 pub use stream::{StreamData, StreamKind};
+pub use users::UserLevel;
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
@@ -74,6 +88,7 @@ pub struct BcCamera {
     // Certain commands such as logout require the username/pass in plain text.... why....???
     credentials: Credentials,
     abilities: RwLock<HashMap<String, ReadKind>>,
+    udp_stats: Option<Arc<UdpCounters>>,
     #[allow(dead_code)]
     cancel: CancellationToken,
 }
@@ -328,6 +343,7 @@ impl BcCamera {
         let username: String = options.credentials.username.clone();
         let passwd: Option<String> = options.credentials.password.clone();
 
+        let mut udp_stats = None;
         let (sink, source): (BcConnSink, BcConnSource) = {
             match BcCamera::find_camera(options).await? {
                 CameraLocation::Tcp(addr) => {
@@ -337,14 +353,15 @@ impl BcCamera {
                     (Box::new(x), Box::new(r))
                 }
                 CameraLocation::Udp(discovery) => {
-                    let (x, r) = UdpSource::new_from_discovery(
+                    let udp_source = UdpSource::new_from_discovery(
                         discovery,
                         &username,
                         passwd.as_ref(),
                         options.debug,
                     )
-                    .await?
-                    .split();
+                    .await?;
+                    udp_stats = Some(udp_source.stats());
+                    let (x, r) = udp_source.split();
                     (Box::new(x), Box::new(r))
                 }
             }
@@ -360,6 +377,7 @@ impl BcCamera {
             logged_in: AtomicBool::new(false),
             credentials: Credentials::new(username, passwd),
             abilities: Default::default(),
+            udp_stats,
             cancel: CancellationToken::new(),
         };
         me.keepalive().await?;
@@ -375,6 +393,11 @@ impl BcCamera {
         self.connection.clone()
     }
 
+    /// The packet counts of the connection, this is `None` when connected over TCP
+    pub fn udp_stats(&self) -> Option<UdpStats> {
+        self.udp_stats.as_ref().map(|stats| stats.get())
+    }
+
     // Certains commands like logout need the username and password
     // this command will return
     // This will only work after login
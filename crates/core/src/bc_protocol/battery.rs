@@ -10,6 +10,7 @@ use crate::{
     bc::{model::*, xml::BatteryInfo},
     Error,
 };
+use tokio::sync::mpsc::{channel, Receiver};
 
 impl BcCamera {
     /// Create a handller to respond to battery messages
@@ -77,6 +78,43 @@ impl BcCamera {
         Ok(())
     }
 
+    /// Get the battery infos that the camera sends as events, these are sent
+    /// on login and when the battery changes
+    ///
+    /// This replaces the handler of [`BcCamera::monitor_battery`]
+    pub async fn listen_on_battery(&self) -> Result<Receiver<BatteryInfo>> {
+        let connection = self.get_connection();
+        let (tx, rx) = channel(10);
+        // Only one handler is allowed per message so remove any earlier one
+        connection.unhandle_msg(MSG_ID_BATTERY_INFO_LIST).await?;
+        connection
+            .handle_msg(MSG_ID_BATTERY_INFO_LIST, move |bc| {
+                let tx = tx.clone();
+                Box::pin(async move {
+                    if let Bc {
+                        body:
+                            BcBody::ModernMsg(ModernMsg {
+                                payload:
+                                    Some(BcPayloads::BcXml(BcXml {
+                                        battery_list: Some(battery_list),
+                                        ..
+                                    })),
+                                ..
+                            }),
+                        ..
+                    } = bc
+                    {
+                        for battery in battery_list.battery_info.iter() {
+                            let _ = tx.send(battery.clone()).await;
+                        }
+                    }
+                    Option::<Bc>::None
+                })
+            })
+            .await?;
+        Ok(rx)
+    }
+
     /// Requests the current battery status of the camera
     pub async fn battery_info(&self) -> Result<BatteryInfo> {
         let connection = self.get_connection();
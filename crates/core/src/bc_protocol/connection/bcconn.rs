@@ -6,13 +6,20 @@ use futures::stream::{Stream, StreamExt};
 use log::*;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 use tokio::sync::mpsc::{channel, Sender};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
 use tokio::{sync::RwLock, task::JoinSet};
 
+/// The id of the next connection, so that the lines of one connection can be told apart
+static NEXT_CONN_ID: AtomicU64 = AtomicU64::new(1);
+
 type MsgHandler = dyn 'static + Send + Sync + for<'a> Fn(&'a Bc) -> BoxFuture<'a, Option<Bc>>;
 
 #[derive(Default)]
@@ -45,6 +52,8 @@ impl BcConnection {
     pub async fn new(mut sink: BcConnSink, mut source: BcConnSource) -> Result<BcConnection> {
         let (sinker, sinker_rx) = channel::<Result<Bc>>(100);
         let cancel = CancellationToken::new();
+        let id = NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed);
+        let span = tracing::info_span!("bc", conn = id);
 
         let (poll_commander, poll_commanded) = channel(200);
         let mut poller = Poller {
@@ -64,12 +73,15 @@ impl BcConnection {
                 v = async {
                     let sender = thread_poll_commander;
                     while let Some(bc) = source.next().await {
+                        if let Ok(bc) = &bc {
+                            tracing::trace!(msg_id = bc.meta.msg_id, msg_num = bc.meta.msg_num, "Received");
+                        }
                         sender.send(PollCommand::Bc(Box::new(bc))).await?;
                     }
                     Result::Ok(())
                 } => v
             }
-        });
+        }.instrument(span.clone()));
 
         let thread_cancel = cancel.clone();
         rx_thread.spawn(async move {
@@ -78,27 +90,32 @@ impl BcConnection {
                 v = async {
                     let mut stream = ReceiverStream::new(sinker_rx);
                     while let Some(packet) = stream.next().await {
-                        sink.send(packet?).await?;
+                        let packet = packet?;
+                        tracing::trace!(msg_id = packet.meta.msg_id, msg_num = packet.meta.msg_num, "Sent");
+                        sink.send(packet).await?;
                     }
                     Ok(())
                 } => v
             }
-        });
+        }.instrument(span.clone()));
 
         let thread_cancel = cancel.clone();
-        rx_thread.spawn(async move {
-            tokio::select! {
-                _ = thread_cancel.cancelled() => Result::Ok(()),
-                v = async {
-                    loop {
-                        if let n @ Err(_) = poller.run().await {
-                            trace!("Polling has ended");
-                            return n;
+        rx_thread.spawn(
+            async move {
+                tokio::select! {
+                    _ = thread_cancel.cancelled() => Result::Ok(()),
+                    v = async {
+                        loop {
+                            if let n @ Err(_) = poller.run().await {
+                                trace!("Polling has ended");
+                                return n;
+                            }
                         }
-                    }
-                }=> v
+                    }=> v
+                }
             }
-        });
+            .instrument(span),
+        );
 
         Ok(BcConnection {
             sink: sinker,
@@ -135,7 +152,6 @@ impl BcConnection {
     }
 
     /// Stop a message handler created using [`handle_msg`]
-    #[allow(dead_code)] // Currently unused but added for future use
     pub async fn unhandle_msg(&self, msg_id: u32) -> Result<()> {
         self.poll_commander
             .send(PollCommand::RemoveHandler(msg_id))
@@ -18,7 +18,7 @@ use log::*;
 use rand::{seq::SliceRandom, thread_rng, Rng};
 use std::collections::{btree_map::Entry, BTreeMap, HashSet};
 use std::convert::TryInto;
-use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
 use std::sync::Arc;
 use tokio::time::MissedTickBehavior;
 use tokio::{
@@ -1073,6 +1073,38 @@ impl Discovery {
         Ok(reg_result)
     }
 
+    // Broadcast a C2D_S so that every camera on the local network replies
+    //
+    // The cameras reply with binary data to port 3000. We do not know its
+    // layout so only the address it came from is used
+    pub(crate) async fn search(&self, wait: Duration) -> Result<Vec<IpAddr>> {
+        let listener = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 3000))).await?;
+        let msg = BcUdp::Discovery(UdpDiscovery {
+            tid: generate_tid(),
+            payload: UdpXml::C2dS(C2dS {
+                to: PortList { port: 3000 },
+            }),
+        });
+        for addr in get_broadcasts(&[2015])? {
+            self.discoverer.send(msg.clone(), addr).await?;
+        }
+
+        let mut found = vec![];
+        let mut buf = vec![0; MTU as usize];
+        let _ = timeout(wait, async {
+            loop {
+                if let Ok((_, addr)) = listener.recv_from(&mut buf).await {
+                    trace!("Search reply from {}", addr);
+                    if !found.contains(&addr.ip()) {
+                        found.push(addr.ip());
+                    }
+                }
+            }
+        })
+        .await;
+        Ok(found)
+    }
+
     // Check if TCP is possible
     //
     // To do this we send a dummy login  and see if it replies with any BC packet
@@ -14,9 +14,10 @@ mod discovery;
 mod tcpsource;
 mod udpsource;
 
+pub use self::udpsource::UdpStats;
 pub(crate) use self::{
     bcconn::BcConnection, bcconn::*, bcsub::BcSubscription, discovery::Discovery,
-    tcpsource::TcpSource, udpsource::UdpSource,
+    tcpsource::TcpSource, udpsource::UdpCounters, udpsource::UdpSource,
 };
 
 pub(crate) struct DiscoveryResult {
@@ -14,7 +14,10 @@ use std::collections::BTreeMap;
 use std::io::{Error as IoError, ErrorKind, Result as IoResult};
 use std::net::SocketAddr;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 use std::task::{Context, Poll};
 
 use tokio::{
@@ -40,6 +43,41 @@ const UDPDATA_HEADER_SIZE: usize = 20;
 pub(crate) type InnerFramed = Framed<Compat<IntoAsyncRead<UdpPayloadSource>>, BcCodex>;
 pub(crate) struct UdpSource {
     inner: Pin<Box<InnerFramed>>,
+    stats: Arc<UdpCounters>,
+}
+
+/// Counts of the UDP data packets of a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UdpStats {
+    /// Data packets recieved from the camera including resends
+    pub packets_recieved: u64,
+    /// Packets that were skipped over when they arrived, the camera has to resend these
+    pub packets_missed: u64,
+    /// Packets that were recieved more than once
+    pub packets_duplicated: u64,
+    /// Packets that we had to resend to the camera
+    pub packets_resent: u64,
+}
+
+/// Shared between the connection and the camera so that the stats can
+/// be read while the connection is in use
+#[derive(Debug, Default)]
+pub(crate) struct UdpCounters {
+    recieved: AtomicU64,
+    missed: AtomicU64,
+    duplicated: AtomicU64,
+    resent: AtomicU64,
+}
+
+impl UdpCounters {
+    pub(crate) fn get(&self) -> UdpStats {
+        UdpStats {
+            packets_recieved: self.recieved.load(Ordering::Relaxed),
+            packets_missed: self.missed.load(Ordering::Relaxed),
+            packets_duplicated: self.duplicated.load(Ordering::Relaxed),
+            packets_resent: self.resent.load(Ordering::Relaxed),
+        }
+    }
 }
 
 impl UdpSource {
@@ -91,6 +129,7 @@ impl UdpSource {
     ) -> Result<Self> {
         let bcudp_source = BcUdpSource::new_from_socket(stream, addr).await?;
         let payload_source = bcudp_source.into_payload_source(client_id, camera_id).await;
+        let stats = payload_source.stats.clone();
         let async_read = payload_source.into_async_read().compat();
         let codex = if debug {
             BcCodex::new_with_debug(Credentials::new(username, password))
@@ -101,9 +140,15 @@ impl UdpSource {
 
         Ok(Self {
             inner: Box::pin(framed),
+            stats,
         })
     }
 
+    /// The packet counts of this connection, they keep updating after the source is split
+    pub(crate) fn stats(&self) -> Arc<UdpCounters> {
+        self.stats.clone()
+    }
+
     // pub(crate) async fn send(&mut self, bc: Bc) -> Result<()> {
     //     self.inner.send(bc).await
     // }
@@ -273,6 +318,7 @@ pub(crate) struct UdpPayloadSource {
     inner_sink: PollSender<Vec<u8>>,
     set: JoinSet<Result<()>>,
     cancel_token: CancellationToken,
+    stats: Arc<UdpCounters>,
 }
 
 impl Drop for UdpPayloadSource {
@@ -308,6 +354,7 @@ struct UdpPayloadInner {
     /// This `resend_interval` controls how ofen we do this
     resend_interval: Interval,
     ack_latency: AckLatency,
+    stats: Arc<UdpCounters>,
     cancel: CancellationToken,
     set: JoinSet<Result<()>>,
 }
@@ -318,6 +365,7 @@ impl UdpPayloadInner {
         thread_sink: ReceiverStream<Vec<u8>>,
         client_id: i32,
         camera_id: i32,
+        stats: Arc<UdpCounters>,
     ) -> Self {
         let mut set = JoinSet::new();
         let camera_addr = inner.addr;
@@ -472,6 +520,7 @@ impl UdpPayloadInner {
             recieved: Default::default(),
             resend_interval: interval(Duration::from_millis(500)), // Offical Client does resend every 500ms
             ack_latency: Default::default(),
+            stats,
             cancel,
             set,
         }
@@ -484,6 +533,7 @@ impl UdpPayloadInner {
                 for (_, resend) in self.sent.iter() {
                     self.socket_in.feed(BcUdp::Data(resend.clone())).await?;
                 }
+                self.stats.resent.fetch_add(self.sent.len() as u64, Ordering::Relaxed);
                 self.ack_tx.send_replace(self.build_send_ack()); // Ensure we update the ack packet sometimes too
                 Result::Ok(())
             },
@@ -535,6 +585,7 @@ impl UdpPayloadInner {
                         BcUdp::Data(data)  => {
                             if data.connection_id == self.client_id {
                                 let packet_id = data.packet_id;
+                                self.count_recieved(packet_id);
                                 if packet_id >= self.packets_want {
                                     // error!("packets_want: {}", this.packets_want);
                                     self.recieved.insert(packet_id, data.payload);
@@ -562,6 +613,27 @@ impl UdpPayloadInner {
         Ok(())
     }
 
+    /// Update the stats before the packet is stored
+    fn count_recieved(&self, packet_id: u32) {
+        self.stats.recieved.fetch_add(1, Ordering::Relaxed);
+        if packet_id < self.packets_want || self.recieved.contains_key(&packet_id) {
+            self.stats.duplicated.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let next = self
+            .recieved
+            .keys()
+            .max()
+            .map(|&last| last + 1)
+            .unwrap_or(self.packets_want)
+            .max(self.packets_want);
+        if packet_id > next {
+            self.stats
+                .missed
+                .fetch_add((packet_id - next) as u64, Ordering::Relaxed);
+        }
+    }
+
     fn build_send_ack(&self) -> UdpAck {
         if self.packets_want > 0 {
             let mut first_missing: u32 = self.packets_want;
@@ -633,12 +705,14 @@ impl UdpPayloadSource {
         let (inner_sink, thread_sink) = channel(100);
         let (thread_stream, inner_stream) = channel(100);
 
+        let stats = Arc::new(UdpCounters::default());
         let mut payload_inner = UdpPayloadInner::new(
             inner,
             PollSender::new(thread_stream),
             ReceiverStream::new(thread_sink),
             client_id,
             camera_id,
+            stats.clone(),
         );
         let cancel_token = tokio_util::sync::CancellationToken::new();
 
@@ -679,6 +753,7 @@ impl UdpPayloadSource {
             inner_sink: PollSender::new(inner_sink),
             set,
             cancel_token,
+            stats,
         }
     }
 }
@@ -0,0 +1,87 @@
+//! Uploads new firmware to the camera
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+/// Size of each binary message of the firmware file
+const CHUNK_SIZE: usize = 32 * 1024;
+
+impl BcCamera {
+    /// Upload a firmware file to the camera
+    ///
+    /// The camera acknowledges each part of the file, after each one
+    /// `progress` is called with the bytes sent and the total. Once the
+    /// upload is complete the camera writes the firmware and reboots
+    pub async fn upgrade_firmware<F: FnMut(usize, usize)>(
+        &self,
+        file_name: &str,
+        data: &[u8],
+        mut progress: F,
+    ) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_upgrade = connection.subscribe(MSG_ID_UPGRADE, msg_num).await?;
+        let start = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_UPGRADE,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    upgrade_file_info: Some(UpgradeFileInfo {
+                        version: xml_ver(),
+                        file_name: file_name.to_string(),
+                        file_size: data.len() as u64,
+                    }),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_upgrade.send(start).await?;
+        let msg = sub_upgrade.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        let mut sent = 0;
+        for chunk in data.chunks(CHUNK_SIZE) {
+            let msg = Bc {
+                meta: BcMeta {
+                    msg_id: MSG_ID_UPGRADE,
+                    channel_id: self.channel_id,
+                    msg_num,
+                    response_code: 0,
+                    stream_type: 0,
+                    class: 0x6414,
+                },
+                body: BcBody::ModernMsg(ModernMsg {
+                    extension: Some(Extension {
+                        binary_data: Some(1),
+                        ..Default::default()
+                    }),
+                    payload: Some(BcPayloads::Binary(chunk.to_vec())),
+                }),
+            };
+            sub_upgrade.send(msg).await?;
+            let msg = sub_upgrade.recv().await?;
+            if msg.meta.response_code != 200 {
+                return Err(Error::CameraServiceUnavailable {
+                    id: msg.meta.msg_id,
+                    code: msg.meta.response_code,
+                });
+            }
+            sent += chunk.len();
+            progress(sent, data.len());
+        }
+
+        Ok(())
+    }
+}
@@ -212,4 +212,21 @@ impl BcCamera {
         let curr_state = self.get_flightlight_tasks().await?;
         Ok(curr_state.enable == 1)
     }
+
+    /// Convience function: Get the current brightness of the Flood Light in percent
+    pub async fn get_floodlight_brightness(&self) -> Result<u32> {
+        let curr_state = self.get_flightlight_tasks().await?;
+        Ok(curr_state.brightness_cur)
+    }
+
+    /// Convience function: Set the brightness of the Flood Light in percent
+    ///
+    /// The value is clamped to the range the camera reports
+    pub async fn set_floodlight_brightness(&self, brightness: u32) -> Result<()> {
+        let mut curr_state = self.get_flightlight_tasks().await?;
+        let min = curr_state.brightness_min.unwrap_or(0);
+        let max = curr_state.brightness_max.unwrap_or(100).max(min);
+        curr_state.brightness_cur = brightness.clamp(min, max);
+        self.set_flightlight_tasks(curr_state).await
+    }
 }
@@ -0,0 +1,96 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [HddInfoList] xml which contains the size and state of the SD card
+    pub async fn get_hdd_info(&self) -> Result<HddInfoList> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection
+            .subscribe(MSG_ID_GET_HDD_INFO_LIST, msg_num)
+            .await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_HDD_INFO_LIST,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    hdd_info_list: Some(data),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(data)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected HddInfoList xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Format the storage device with the `number` of its [HddInfo]
+    ///
+    /// This erases all recordings on the device
+    pub async fn format_hdd(&self, number: u8) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_HDD_INIT_LIST, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_HDD_INIT_LIST,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    hdd_init_list: Some(HddInitList {
+                        version: xml_ver(),
+                        hdd_init: vec![HddInit {
+                            init_id: number,
+                            init_type: 1,
+                        }],
+                    }),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        Ok(())
+    }
+}
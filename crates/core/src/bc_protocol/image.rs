@@ -0,0 +1,238 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+use serde::{Deserialize, Serialize};
+
+/// The image settings of the camera, the camera sends and takes both parts together
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Isp {
+    /// The basic settings such as the brightness
+    #[serde(rename = "VideoInput")]
+    pub video_input: VideoInput,
+    /// The advanced settings such as the exposure, missing on simpler cameras
+    #[serde(rename = "InputAdvanceCfg", skip_serializing_if = "Option::is_none")]
+    pub input_advance_cfg: Option<InputAdvanceCfg>,
+}
+
+/// The text drawn over the image, the camera sends and takes both parts together
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Osd {
+    /// The channel name
+    #[serde(rename = "OsdChannelName")]
+    pub channel_name: OsdChannelName,
+    /// The time
+    #[serde(rename = "OsdDatetime")]
+    pub datetime: OsdDatetime,
+}
+
+impl BcCamera {
+    /// Helper to get one of the channel's settings, the reply holds its xml
+    async fn get_channel_xml(&self, msg_id: u32) -> Result<Bc> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(msg_id, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+        Ok(msg)
+    }
+
+    /// Helper to set the xml of one of the channel's settings
+    async fn set_channel_xml(&self, msg_id: u32, xml: BcXml) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(msg_id, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(xml)),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        if let Ok(reply) =
+            tokio::time::timeout(tokio::time::Duration::from_millis(500), sub_set.recv()).await
+        {
+            let msg = reply?;
+            if msg.meta.response_code == 200 {
+                Ok(())
+            } else {
+                Err(Error::CameraServiceUnavailable {
+                    id: msg.meta.msg_id,
+                    code: msg.meta.response_code,
+                })
+            }
+        } else {
+            // Some cameras seem to just not send a reply on success, so after 500ms we return Ok
+            Ok(())
+        }
+    }
+
+    /// Get the image settings, the [VideoInput] and [InputAdvanceCfg] xml
+    pub async fn get_isp(&self) -> Result<Isp> {
+        self.has_ability_ro("ispBasic").await?;
+        let msg = self.get_channel_xml(MSG_ID_GET_VIDEO_INPUT).await?;
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    video_input: Some(video_input),
+                    input_advance_cfg,
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(Isp {
+                video_input,
+                input_advance_cfg,
+            })
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected VideoInput xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the image settings
+    pub async fn set_isp(&self, mut isp: Isp) -> Result<()> {
+        self.has_ability_rw("ispBasic").await?;
+        // Only sent by the camera
+        if let Some(scene) = isp
+            .input_advance_cfg
+            .as_mut()
+            .and_then(|cfg| cfg.scene.as_mut())
+        {
+            scene.mode_list = None;
+        }
+        self.set_channel_xml(
+            MSG_ID_SET_VIDEO_INPUT,
+            BcXml {
+                video_input: Some(isp.video_input),
+                input_advance_cfg: isp.input_advance_cfg,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Get the text drawn over the image, the [OsdChannelName] and [OsdDatetime] xml
+    pub async fn get_osd(&self) -> Result<Osd> {
+        self.has_ability_ro("osdName").await?;
+        let msg = self.get_channel_xml(MSG_ID_GET_OSD).await?;
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    osd_channel_name: Some(channel_name),
+                    osd_datetime: Some(datetime),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(Osd {
+                channel_name,
+                datetime,
+            })
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected OsdChannelName and OsdDatetime xml but they were not recieved",
+            })
+        }
+    }
+
+    /// Set the text drawn over the image
+    pub async fn set_osd(&self, mut osd: Osd) -> Result<()> {
+        self.has_ability_rw("osdName").await?;
+        // Only sent by the camera
+        osd.datetime.width = None;
+        osd.datetime.height = None;
+        self.set_channel_xml(
+            MSG_ID_SET_OSD,
+            BcXml {
+                osd_channel_name: Some(osd.channel_name),
+                osd_datetime: Some(osd.datetime),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Get the [Compression] xml which contains the encoding of each stream
+    pub async fn get_compression(&self) -> Result<Compression> {
+        let msg = self.get_channel_xml(MSG_ID_GET_COMPRESSION).await?;
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    compression: Some(compression),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(compression)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected Compression xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the encoding of the streams using the [Compression] xml
+    pub async fn set_compression(&self, mut compression: Compression) -> Result<()> {
+        // Only sent by the camera
+        compression.no_translate_frame = None;
+        // Cameras without a third stream send it with no size but do not take it back
+        if compression
+            .third_stream
+            .as_ref()
+            .map(|stream| stream.width == 0)
+            .unwrap_or(false)
+        {
+            compression.third_stream = None;
+        }
+        self.set_channel_xml(
+            MSG_ID_SET_COMPRESSION,
+            BcXml {
+                compression: Some(compression),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+}
@@ -0,0 +1,21 @@
+//! Finds cameras on the local network without an address
+//!
+//! These do not need a [`BcCamera`](super::BcCamera) and can be used to
+//! fill in the address of a camera before connecting to it
+use super::{Discovery, Result};
+use std::net::IpAddr;
+use tokio::time::Duration;
+
+/// Broadcast on the local network and return the addresses of every
+/// camera that replies within `wait`
+pub async fn search_lan(wait: Duration) -> Result<Vec<IpAddr>> {
+    let discovery = Discovery::new().await?;
+    discovery.search(wait).await
+}
+
+/// Find the address of the camera with this UID on the local network
+pub async fn locate_uid(uid: &str) -> Result<IpAddr> {
+    let discovery = Discovery::new().await?;
+    let result = discovery.local(uid, None).await?;
+    Ok(result.get_addr().ip())
+}
@@ -128,17 +128,47 @@ impl BcCamera {
     ///
     /// This is for the little blue on light of some camera
     pub async fn led_light_set(&self, state: bool) -> Result<()> {
+        self.led_light_set_state(match state {
+            true => LightState::On,
+            false => LightState::Off,
+        })
+        .await
+    }
+
+    /// Get the mode of the LED light
+    ///
+    /// [`LightState::Auto`] is the camera's schedule where the light is turned
+    /// off at night so that it does not reflect in the IR image
+    pub async fn led_light_get(&self) -> Result<LightState> {
+        let led_state = self.get_ledstate().await?;
+        match led_state.light_state.as_str() {
+            "open" => Ok(LightState::On),
+            "close" => Ok(LightState::Off),
+            "auto" => Ok(LightState::Auto),
+            other => Err(Error::OtherString(format!(
+                "Unknown status light state {other}"
+            ))),
+        }
+    }
+
+    /// Set the mode of the LED light
+    ///
+    /// This is for the little blue on light of some camera, use
+    /// [`LightState::Auto`] to follow the camera's night schedule
+    pub async fn led_light_set_state(&self, state: LightState) -> Result<()> {
         let mut led_state = self.get_ledstate().await?;
         led_state.light_state = match state {
-            true => "open".to_string(),
-            false => "close".to_string(),
+            LightState::On => "open".to_string(),
+            LightState::Off => "close".to_string(),
+            LightState::Auto => "auto".to_string(),
         };
         self.set_ledstate(led_state).await?;
         Ok(())
     }
 }
 
-/// This is pased to `irled_light_set` to turn it on, off or set it to light based auto
+/// This is pased to `irled_light_set` and `led_light_set_state` to turn it on, off or set it to auto
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LightState {
     /// Turn the light on
     On,
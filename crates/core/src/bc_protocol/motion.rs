@@ -1,6 +1,6 @@
 use super::{BcCamera, Error, Result};
 use crate::bc::{model::*, xml::*};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::mpsc::{channel, error::TryRecvError, Receiver};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
@@ -16,14 +16,60 @@ pub enum MotionStatus {
     NoChange(Instant),
 }
 
+/// Details of the alarm that caused the last motion status
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MotionDetails {
+    /// The channel the alarm was for
+    pub channel_id: u8,
+    /// What was detected. Known values are `"md"`, `"people"`, `"vehicle"` and `"animal"`,
+    /// other AI types are passed on as the camera reports them
+    pub detections: Vec<String>,
+    /// When the alarm was received
+    pub at: SystemTime,
+}
+
+impl MotionDetails {
+    fn new(channel_id: u8) -> Self {
+        Self {
+            channel_id,
+            detections: vec![],
+            at: SystemTime::now(),
+        }
+    }
+
+    fn from_alarm(alarm_event: &AlarmEvent) -> Self {
+        let mut details = Self::new(alarm_event.channel_id);
+        if alarm_event.status != "none" {
+            details.detections.push("md".to_string());
+        }
+        if let Some(ai_type) = alarm_event.ai_type.as_ref() {
+            for ai in ai_type
+                .split(',')
+                .map(|ai| ai.trim().to_lowercase())
+                .filter(|ai| !ai.is_empty() && ai != "none")
+            {
+                let ai = match ai.as_str() {
+                    "dog_cat" | "pet" => "animal".to_string(),
+                    _ => ai,
+                };
+                if !details.detections.contains(&ai) {
+                    details.detections.push(ai);
+                }
+            }
+        }
+        details
+    }
+}
+
 /// A handle on current motion related events comming from the camera
 ///
 /// When this object is dropped the motion events are stopped
 pub struct MotionData {
     handle: JoinSet<Result<()>>,
     cancel: CancellationToken,
-    rx: Receiver<Result<MotionStatus>>,
+    rx: Receiver<Result<(MotionStatus, MotionDetails)>>,
     last_update: MotionStatus,
+    last_details: MotionDetails,
 }
 
 impl MotionData {
@@ -60,7 +106,11 @@ impl MotionData {
         let mut results: Vec<MotionStatus> = vec![];
         loop {
             match self.rx.try_recv() {
-                Ok(motion) => results.push(motion?),
+                Ok(motion) => {
+                    let (motion, details) = motion?;
+                    self.last_details = details;
+                    results.push(motion);
+                }
                 Err(TryRecvError::Empty) => break,
                 Err(e) => return Err(Error::from(e)),
             }
@@ -71,6 +121,11 @@ impl MotionData {
         Ok(results)
     }
 
+    /// Get the details of the alarm behind the last consumed motion event
+    pub fn last_details(&self) -> &MotionDetails {
+        &self.last_details
+    }
+
     /// Await a new motion event
     ///
     ///
@@ -79,8 +134,9 @@ impl MotionData {
         if let Some(last) = motions.last() {
             Ok(*last)
         } else if let Some(moition) = self.rx.recv().await {
-            let moition = moition?;
+            let (moition, details) = moition?;
             self.last_update = moition;
+            self.last_details = details;
             Ok(moition)
         } else {
             Err(Error::Other("Motion dropped"))
@@ -236,27 +292,22 @@ impl BcCamera {
                                     ..
                                 }) = motion_msg.body
                                 {
-                                    let mut result = MotionStatus::NoChange(Instant::now());
+                                    let mut result = (MotionStatus::NoChange(Instant::now()), MotionDetails::new(channel_id));
                                     for alarm_event in &alarm_event_list.alarm_events {
                                         if alarm_event.channel_id == channel_id {
-                                            if alarm_event.status != "none"
-                                                || alarm_event
-                                                    .ai_type
-                                                    .as_ref()
-                                                    .map(|ai_type| ai_type != "none")
-                                                    .unwrap_or(false)
-                                            {
-                                                result = MotionStatus::Start(Instant::now());
+                                            let details = MotionDetails::from_alarm(alarm_event);
+                                            if !details.detections.is_empty() {
+                                                result = (MotionStatus::Start(Instant::now()), details);
                                                 break;
                                             } else {
-                                                result = MotionStatus::Stop(Instant::now());
+                                                result = (MotionStatus::Stop(Instant::now()), details);
                                                 break;
                                             }
                                         }
                                     }
                                     Ok(result)
                                 } else {
-                                    Ok(MotionStatus::NoChange(Instant::now()))
+                                    Ok((MotionStatus::NoChange(Instant::now()), MotionDetails::new(channel_id)))
                                 }
                             }
                             // On connection drop we stop
@@ -278,6 +329,7 @@ impl BcCamera {
             cancel,
             rx,
             last_update: MotionStatus::NoChange(Instant::now()),
+            last_details: MotionDetails::new(channel_id),
         })
     }
 }
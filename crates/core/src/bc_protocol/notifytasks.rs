@@ -0,0 +1,151 @@
+//! The email, cloud push and FTP upload tasks
+//!
+//! These all share the [NotificationTask] layout and only differ in the message ID
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+/// The notification tasks of the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    /// Email alarms
+    Email,
+    /// Push notifications through the reolink cloud
+    Push,
+    /// Uploads to an FTP server
+    Ftp,
+}
+
+impl NotificationKind {
+    fn msg_ids(&self) -> (u32, u32) {
+        match self {
+            NotificationKind::Email => (MSG_ID_GET_EMAIL_TASK, MSG_ID_SET_EMAIL_TASK),
+            NotificationKind::Push => (MSG_ID_GET_PUSH_TASK, MSG_ID_SET_PUSH_TASK),
+            NotificationKind::Ftp => (MSG_ID_GET_FTP_TASK, MSG_ID_SET_FTP_TASK),
+        }
+    }
+
+    fn wrap(&self, task: NotificationTask) -> BcXml {
+        match self {
+            NotificationKind::Email => BcXml {
+                email_task: Some(task),
+                ..Default::default()
+            },
+            NotificationKind::Push => BcXml {
+                push_task: Some(task),
+                ..Default::default()
+            },
+            NotificationKind::Ftp => BcXml {
+                ftp_task: Some(task),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn take(&self, xml: &mut BcXml) -> Option<NotificationTask> {
+        match self {
+            NotificationKind::Email => xml.email_task.take(),
+            NotificationKind::Push => xml.push_task.take(),
+            NotificationKind::Ftp => xml.ftp_task.take(),
+        }
+    }
+}
+
+impl BcCamera {
+    /// Get the [NotificationTask] xml of the email, push or FTP task
+    pub async fn get_notification_task(&self, kind: NotificationKind) -> Result<NotificationTask> {
+        let (msg_id, _) = kind.msg_ids();
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(msg_id, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let mut msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload: Some(BcPayloads::BcXml(xml)),
+            ..
+        }) = &mut msg.body
+        {
+            if let Some(task) = kind.take(xml) {
+                return Ok(task);
+            }
+        }
+        Err(Error::UnintelligibleReply {
+            reply: std::sync::Arc::new(Box::new(msg)),
+            why: "Expected a notification task xml but it was not recieved",
+        })
+    }
+
+    /// Set the email, push or FTP task from the [NotificationTask] xml
+    pub async fn set_notification_task(
+        &self,
+        kind: NotificationKind,
+        task: NotificationTask,
+    ) -> Result<()> {
+        let (_, msg_id) = kind.msg_ids();
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(msg_id, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(kind.wrap(task))),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+        Ok(())
+    }
+
+    /// This is a convience function to turn a notification task on or off
+    /// while keeping its schedule
+    pub async fn notification_task_set(&self, kind: NotificationKind, state: bool) -> Result<()> {
+        let mut task = self.get_notification_task(kind).await?;
+        task.enable = match state {
+            true => 1,
+            false => 0,
+        };
+        self.set_notification_task(kind, task).await
+    }
+}
@@ -0,0 +1,260 @@
+//! Searches for and downloads the recordings on the camera's storage
+use super::{BcCamera, Error, Result, StreamKind};
+use crate::bc::{model::*, xml::*};
+use tokio::{
+    sync::mpsc::{channel, Receiver},
+    task::{self, JoinHandle},
+};
+use tokio_util::sync::CancellationToken;
+
+/// A download of a recording from the camera's storage
+///
+/// The data is the raw BcMedia of the file, the download is stopped when
+/// this is dropped
+pub struct FileDownload {
+    handle: Option<JoinHandle<Result<()>>>,
+    rx: Receiver<Result<Vec<u8>>>,
+    abort_handle: CancellationToken,
+}
+
+impl FileDownload {
+    /// Get the next chunk of the file, `None` once the whole file has been received
+    pub async fn get_data(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.rx.recv().await {
+            Some(data) => data.map(Some),
+            None => {
+                if let Some(handle) = self.handle.take() {
+                    handle.await??;
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Drop for FileDownload {
+    fn drop(&mut self) {
+        log::trace!("Drop FileDownload");
+        self.abort_handle.cancel();
+        if let Some(handle) = self.handle.take() {
+            let _gt = tokio::runtime::Handle::current().enter();
+            tokio::task::spawn(async move {
+                let _ = handle.await;
+            });
+        }
+        log::trace!("Dropped FileDownload");
+    }
+}
+
+impl BcCamera {
+    /// Get the recordings of the stream between `start` and `end`
+    ///
+    /// `record_type` is a comma seperated list of the triggers to include
+    /// such as `"md, pir"`
+    pub async fn get_file_list(
+        &self,
+        stream: StreamKind,
+        start: RecordTime,
+        end: RecordTime,
+        record_type: &str,
+    ) -> Result<Vec<FileInfo>> {
+        let stream_type = match stream {
+            StreamKind::Main => "mainStream",
+            StreamKind::Sub => "subStream",
+            StreamKind::Extern => "externStream",
+        };
+        let search = FileInfo {
+            channel_id: self.channel_id,
+            stream_type: Some(stream_type.to_string()),
+            record_type: Some(record_type.to_string()),
+            start_time: Some(start),
+            end_time: Some(end),
+            ..Default::default()
+        };
+        let handle = self
+            .file_info_list_request(MSG_ID_FILE_INFO_LIST_OPEN, search)
+            .await?
+            .first()
+            .and_then(|file| file.handle)
+            .ok_or(Error::Other("The camera did not return a search handle"))?;
+        let page_request = FileInfo {
+            channel_id: self.channel_id,
+            handle: Some(handle),
+            ..Default::default()
+        };
+
+        // The files come in pages, keep asking until there are no more
+        let mut files = vec![];
+        let result: Result<()> = async {
+            loop {
+                match self
+                    .file_info_list_request(MSG_ID_FILE_INFO_LIST_GET, page_request.clone())
+                    .await
+                {
+                    Ok(page) if page.iter().any(|file| file.name.is_some()) => {
+                        files.extend(page.into_iter().filter(|file| file.name.is_some()));
+                    }
+                    Ok(_) | Err(Error::CameraServiceUnavailable { .. }) => return Ok(()),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        .await;
+        if let Err(e) = self
+            .file_info_list_request(MSG_ID_FILE_INFO_LIST_CLOSE, page_request)
+            .await
+        {
+            log::debug!("Failed to close the file search: {:?}", e);
+        }
+        result?;
+        Ok(files)
+    }
+
+    async fn file_info_list_request(
+        &self,
+        msg_id: u32,
+        file_info: FileInfo,
+    ) -> Result<Vec<FileInfo>> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(msg_id, msg_num).await?;
+        let get = Bc::new_from_xml(
+            BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            BcXml {
+                file_info_list: Some(FileInfoList {
+                    version: xml_ver(),
+                    file_info: vec![file_info],
+                }),
+                ..Default::default()
+            },
+        );
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    file_info_list: Some(data),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(data.file_info)
+        } else {
+            // No xml means no files
+            Ok(vec![])
+        }
+    }
+
+    /// Download a recording by the name given in [`BcCamera::get_file_list`]
+    pub async fn download_file(&self, name: &str) -> Result<FileDownload> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let channel_id = self.channel_id;
+        let file_info_list = FileInfoList {
+            version: xml_ver(),
+            file_info: vec![FileInfo {
+                channel_id,
+                name: Some(name.to_string()),
+                ..Default::default()
+            }],
+        };
+
+        let abort_handle = CancellationToken::new();
+        let abort_handle_thread = abort_handle.clone();
+        let (tx, rx) = channel(100);
+
+        let handle = task::spawn(async move {
+            let mut sub_get = connection
+                .subscribe(MSG_ID_FILE_INFO_LIST_DL_VIDEO, msg_num)
+                .await?;
+            let get = Bc::new_from_xml(
+                BcMeta {
+                    msg_id: MSG_ID_FILE_INFO_LIST_DL_VIDEO,
+                    channel_id,
+                    msg_num,
+                    response_code: 0,
+                    stream_type: 0,
+                    class: 0x6414,
+                },
+                BcXml {
+                    file_info_list: Some(file_info_list.clone()),
+                    ..Default::default()
+                },
+            );
+            sub_get.send(get).await?;
+            let msg = sub_get.recv().await?;
+            if msg.meta.response_code != 200 {
+                return Err(Error::CameraServiceUnavailable {
+                    id: msg.meta.msg_id,
+                    code: msg.meta.response_code,
+                });
+            }
+
+            let result = tokio::select! {
+                _ = abort_handle_thread.cancelled() => Ok(()),
+                v = async {
+                    loop {
+                        // Like the snap the camera sends 200 while more is to come
+                        let msg = sub_get.recv().await?;
+                        let finished = msg.meta.response_code != 200;
+                        if let BcBody::ModernMsg(ModernMsg {
+                            payload: Some(BcPayloads::Binary(data)),
+                            ..
+                        }) = msg.body
+                        {
+                            if tx.send(Ok(data)).await.is_err() {
+                                return Ok(()); // Download dropped
+                            }
+                        }
+                        if finished {
+                            return Ok(());
+                        }
+                    }
+                } => v,
+            };
+
+            let stop = Bc::new_from_xml(
+                BcMeta {
+                    msg_id: MSG_ID_FILE_INFO_LIST_STOP,
+                    channel_id,
+                    msg_num,
+                    response_code: 0,
+                    stream_type: 0,
+                    class: 0x6414,
+                },
+                BcXml {
+                    file_info_list: Some(file_info_list),
+                    ..Default::default()
+                },
+            );
+            let sub_stop = connection
+                .subscribe(MSG_ID_FILE_INFO_LIST_STOP, msg_num)
+                .await?;
+            sub_stop.send(stop).await?;
+
+            result
+        });
+
+        Ok(FileDownload {
+            handle: Some(handle),
+            rx,
+            abort_handle,
+        })
+    }
+}
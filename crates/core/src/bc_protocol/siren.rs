@@ -6,6 +6,34 @@ use crate::bc::{model::*, xml::*};
 impl BcCamera {
     /// Trigger the siren
     pub async fn siren(&self) -> Result<()> {
+        self.play_audio(AudioPlayInfo {
+            channel_id: self.channel_id,
+            play_mode: 0,
+            play_duration: 0,
+            play_times: 1,
+            on_off: 0,
+        })
+        .await
+    }
+
+    /// Turn the siren on or off
+    ///
+    /// In manual mode the siren sounds until it is turned off
+    pub async fn siren_manual(&self, on: bool) -> Result<()> {
+        self.play_audio(AudioPlayInfo {
+            channel_id: self.channel_id,
+            play_mode: 1,
+            play_duration: 0,
+            play_times: 0,
+            on_off: match on {
+                true => 1,
+                false => 0,
+            },
+        })
+        .await
+    }
+
+    async fn play_audio(&self, audio_play_info: AudioPlayInfo) -> Result<()> {
         let connection = self.get_connection();
         let msg_num = self.new_message_num();
         let mut sub_get = connection.subscribe(MSG_ID_PLAY_AUDIO, msg_num).await?;
@@ -24,13 +52,7 @@ impl BcCamera {
                     ..Default::default()
                 }),
                 payload: Some(BcPayloads::BcXml(BcXml {
-                    audio_play_info: Some(AudioPlayInfo {
-                        channel_id: self.channel_id,
-                        play_mode: 0,
-                        play_duration: 0,
-                        play_times: 1,
-                        on_off: 0,
-                    }),
+                    audio_play_info: Some(audio_play_info),
                     ..Default::default()
                 })),
             }),
@@ -1,11 +1,21 @@
 // use futures::{StreamExt, TryStreamExt};
 
-use super::{BcCamera, Error, Result};
+use super::{BcCamera, Error, Result, StreamKind};
 use crate::bc::{model::*, xml::*};
 
 impl BcCamera {
     /// Get the snapshot image
     pub async fn get_snapshot(&self) -> Result<Vec<u8>> {
+        self.get_snapshot_from(StreamKind::Main).await
+    }
+
+    /// Get the snapshot image at the resolution of the given stream
+    pub async fn get_snapshot_from(&self, stream: StreamKind) -> Result<Vec<u8>> {
+        let stream_type = match stream {
+            StreamKind::Main => "main",
+            StreamKind::Sub => "sub",
+            StreamKind::Extern => "extern",
+        };
         let connection = self.get_connection();
         let msg_num = self.new_message_num();
         let mut sub_get = connection.subscribe(MSG_ID_SNAP, msg_num).await?;
@@ -30,7 +40,7 @@ impl BcCamera {
                         logic_channel: Some(self.channel_id),
                         time: 0,
                         full_frame: Some(0),
-                        stream_type: Some("main".to_string()),
+                        stream_type: Some(stream_type.to_string()),
                         ..Default::default()
                     }),
                     ..Default::default()
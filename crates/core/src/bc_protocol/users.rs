@@ -0,0 +1,147 @@
+//! Manages the user accounts of the camera
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+/// The level of a user account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserLevel {
+    /// Can view but not change settings
+    Guest,
+    /// Full control of the camera
+    Admin,
+}
+
+impl UserLevel {
+    fn to_xml(self) -> u8 {
+        match self {
+            UserLevel::Guest => 0,
+            UserLevel::Admin => 1,
+        }
+    }
+}
+
+impl BcCamera {
+    /// Get the [UserList] xml which contains the camera's accounts
+    pub async fn get_users(&self) -> Result<UserList> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_USER_LIST, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_USER_LIST,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    user_list: Some(data),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(data)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected UserList xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Add a new account
+    pub async fn add_user(&self, name: &str, password: &str, level: UserLevel) -> Result<()> {
+        self.set_user(User {
+            user_name: name.to_string(),
+            password: Some(password.to_string()),
+            user_level: Some(level.to_xml()),
+            user_set_state: Some("add".to_string()),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Delete an account
+    pub async fn delete_user(&self, name: &str) -> Result<()> {
+        self.set_user(User {
+            user_name: name.to_string(),
+            user_set_state: Some("delete".to_string()),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Change the password of an account
+    pub async fn set_user_password(&self, name: &str, password: &str) -> Result<()> {
+        let user = self
+            .get_users()
+            .await?
+            .user
+            .into_iter()
+            .find(|user| user.user_name == name)
+            .ok_or(Error::Other("No user with that name"))?;
+        self.set_user(User {
+            password: Some(password.to_string()),
+            user_set_state: Some("modify".to_string()),
+            login_state: None,
+            ..user
+        })
+        .await
+    }
+
+    async fn set_user(&self, user: User) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_USER_LIST, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_USER_LIST,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    user_list: Some(UserList {
+                        version: xml_ver(),
+                        user: vec![user],
+                    }),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        Ok(())
+    }
+}
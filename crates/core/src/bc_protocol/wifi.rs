@@ -0,0 +1,143 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [WifiSignal] xml which contains the RSSI of the wifi connection
+    pub async fn get_wifi_signal(&self) -> Result<WifiSignal> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection
+            .subscribe(MSG_ID_GET_WIFI_SIGNAL, msg_num)
+            .await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_WIFI_SIGNAL,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    wifi_signal: Some(data),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(data)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected WifiSignal xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Get the [Wifi] xml which contains the network the camera joins
+    pub async fn get_wifi(&self) -> Result<Wifi> {
+        let msg = self.wifi_request(MSG_ID_GET_WIFI, None).await?;
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    wifi: Some(data), ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(data)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected Wifi xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Scan for the wifi networks that the camera can see
+    pub async fn scan_wifi(&self) -> Result<Vec<WifiScan>> {
+        let msg = self.wifi_request(MSG_ID_SCAN_WIFI, None).await?;
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    wifi_scan_list: Some(data),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(data.wifi_scan)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected WifiScanList xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the wifi network that the camera joins
+    ///
+    /// The camera drops its current connection to join the new network
+    pub async fn set_wifi(&self, ssid: &str, password: &str) -> Result<()> {
+        self.wifi_request(
+            MSG_ID_SET_WIFI,
+            Some(BcXml {
+                wifi: Some(Wifi {
+                    version: xml_ver(),
+                    ssid: ssid.to_string(),
+                    key: Some(password.to_string()),
+                }),
+                ..Default::default()
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn wifi_request(&self, msg_id: u32, xml: Option<BcXml>) -> Result<Bc> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub = connection.subscribe(msg_id, msg_num).await?;
+        let msg = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: xml.map(BcPayloads::BcXml),
+            }),
+        };
+
+        sub.send(msg).await?;
+        let msg = sub.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+        Ok(msg)
+    }
+}
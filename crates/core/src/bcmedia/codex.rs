@@ -8,6 +8,7 @@ use bytes::BytesMut;
 use log::*;
 use tokio_util::codec::{Decoder, Encoder};
 
+/// Decodes BcMedia packets, such as those of a downloaded recording
 pub struct BcMediaCodex {
     /// If true we will not search for the start of the next packet
     /// in the event that the stream appears to be corrupted
@@ -16,7 +17,8 @@ pub struct BcMediaCodex {
 }
 
 impl BcMediaCodex {
-    pub(crate) fn new(strict: bool) -> Self {
+    /// Create the codec, a `strict` codec errors instead of skipping corrupt data
+    pub fn new(strict: bool) -> Self {
         Self {
             strict,
             amount_skipped: 0,
@@ -1,4 +1,5 @@
-pub(crate) mod codex;
+/// Codec to read BcMedia from a byte stream
+pub mod codex;
 /// Deserlizer for BCMedia
 pub mod de;
 /// Structure model for BCMedia
@@ -20,6 +20,11 @@ where
 }
 
 impl BcUdp {
+    /// Decode a single UDP datagram such as one read from a capture
+    pub fn from_datagram(data: &[u8]) -> Result<BcUdp, Error> {
+        Self::deserialize(&mut BytesMut::from(data))
+    }
+
     pub(crate) fn deserialize(buf: &mut BytesMut) -> Result<BcUdp, Error> {
         const TYPICAL_HEADER: usize = 20;
         let (result, len) = match consumed(bcudp)(buf) {
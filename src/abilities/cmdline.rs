@@ -0,0 +1,11 @@
+use clap::Parser;
+
+/// The abilities command will print what the camera reports that it supports
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Print the xml instead of json
+    #[arg(long)]
+    pub raw: bool,
+}
@@ -0,0 +1,78 @@
+///
+/// # Neolink Abilities
+///
+/// This module handles the abilities subcommand
+///
+/// The subcommand prints the version, ability and support information of
+/// the camera. This is what neolink uses to decide which features a camera
+/// has so it is useful to include it when reporting an issue
+///
+/// # Usage
+///
+/// ```bash
+/// neolink abilities --config=config.toml CameraName
+/// neolink abilities --config=config.toml CameraName --raw
+/// ```
+///
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+mod cmdline;
+
+use crate::{common::NeoReactor, AnyResult};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the abilities subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let (version, ability_info, support) = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let ability_info = cam
+                    .get_abilityinfo()
+                    .await
+                    .context("Could not get the abilities of the camera")?;
+                // Not all cameras reply to these, what they do reply is still useful
+                let version = cam.version().await;
+                if let Err(e) = version.as_ref() {
+                    log::warn!("Could not get the version of the camera: {e}");
+                }
+                let support = cam.get_support().await;
+                if let Err(e) = support.as_ref() {
+                    log::warn!("Could not get the support of the camera: {e}");
+                }
+                AnyResult::Ok((version.ok(), ability_info, support.ok()))
+            })
+        })
+        .await?;
+
+    if opt.raw {
+        if let Some(version) = version.as_ref() {
+            println!("{}", to_xml(version)?);
+        }
+        println!("{}", to_xml(&ability_info)?);
+        if let Some(support) = support.as_ref() {
+            println!("{}", to_xml(support)?);
+        }
+    } else {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "version": version,
+                "ability_info": ability_info,
+                "support": support,
+            }))?
+        );
+    }
+
+    Ok(())
+}
+
+fn to_xml<T: Serialize>(value: &T) -> Result<String> {
+    let mut buf = bytes::BytesMut::new();
+    quick_xml::se::to_writer(&mut buf, value)?;
+    Ok(String::from_utf8(buf.to_vec())?)
+}
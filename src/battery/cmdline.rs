@@ -1,8 +1,14 @@
 use clap::Parser;
 
-/// The battery command will dump the battery status to XML
+/// The battery command will print the battery status of the camera
 #[derive(Parser, Debug)]
 pub struct Opt {
     /// The name of the camera. Must be a name in the config
     pub camera: String,
+    /// The output format
+    #[arg(short, long, default_value = "human", value_parser = ["human", "json", "xml"])]
+    pub format: String,
+    /// Keep running and print the status each time the camera reports a change
+    #[arg(long)]
+    pub follow: bool,
 }
@@ -2,19 +2,23 @@
 /// # Neolink Battery
 ///
 /// This module handles the printing of the Battery status
-/// in xml format
+///
+/// The status is printed as text, json or xml. With `--follow` the command
+/// keeps running and prints the status each time the camera sends an update
 ///
 /// # Usage
 ///
 /// ```bash
 /// neolink battery --config=config.toml CameraName
+/// neolink battery --config=config.toml CameraName --format=json --follow
 /// ```
 ///
 use anyhow::{Context, Result};
+use neolink_core::bc::xml::BatteryInfo;
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::{common::NeoReactor, AnyResult};
 
 pub(crate) use cmdline::Opt;
 
@@ -33,14 +37,46 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             })
         })
         .await?;
+    print_state(&state, &opt.format)?;
+
+    if opt.follow {
+        let format = opt.format.clone();
+        camera
+            .run_task(|cam| {
+                let format = format.clone();
+                Box::pin(async move {
+                    let mut updates = cam
+                        .listen_on_battery()
+                        .await
+                        .context("Unable to listen for battery updates")?;
+                    while let Some(state) = updates.recv().await {
+                        print_state(&state, &format)?;
+                    }
+                    AnyResult::Ok(())
+                })
+            })
+            .await?;
+    }
 
-    let ser = String::from_utf8({
-        let mut buf = bytes::BytesMut::new();
-        quick_xml::se::to_writer(&mut buf, &state).expect("Should Ser the struct");
-        buf.to_vec()
-    })
-    .expect("Should be UTF8");
-    println!("{}", ser);
+    Ok(())
+}
 
+fn print_state(state: &BatteryInfo, format: &str) -> Result<()> {
+    match format {
+        "json" => println!("{}", serde_json::to_string(state)?),
+        "xml" => {
+            let ser = String::from_utf8({
+                let mut buf = bytes::BytesMut::new();
+                quick_xml::se::to_writer(&mut buf, state).expect("Should Ser the struct");
+                buf.to_vec()
+            })
+            .expect("Should be UTF8");
+            println!("{}", ser);
+        }
+        _ => println!(
+            "Battery: {}%, Charge: {}, Temperature: {}°C",
+            state.battery_percent, state.charge_status, state.temperature
+        ),
+    }
     Ok(())
 }
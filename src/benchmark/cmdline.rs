@@ -0,0 +1,18 @@
+use clap::Parser;
+
+/// The benchmark command pulls the streams of cameras and reports how well they arrive
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The names of the cameras. Must be names in the config
+    #[arg(required = true)]
+    pub cameras: Vec<String>,
+    /// How many seconds to pull the streams for
+    #[arg(short, long, default_value = "30")]
+    pub duration: u64,
+    /// The stream to pull
+    #[arg(short, long, default_value = "main", value_parser = ["main", "sub", "extern"])]
+    pub stream: String,
+    /// Print the results as json
+    #[arg(long)]
+    pub json: bool,
+}
@@ -0,0 +1,310 @@
+///
+/// # Neolink Benchmark
+///
+/// This module handles the benchmark subcommand
+///
+/// The streams of the cameras are pulled straight from the camera without
+/// gstreamer for a while. Afterwards the throughput, the time between the
+/// video frames, the UDP packet loss and the number of reconnects are
+/// reported. This helps to tell if a choppy stream is the network or the
+/// camera, and to compare TCP with UDP by connecting the camera by `address`
+/// or by `uid`
+///
+/// # Usage
+///
+/// ```bash
+/// neolink benchmark --config=config.toml CameraName OtherCamera --duration=60
+/// ```
+///
+use anyhow::{anyhow, Result};
+use neolink_core::{
+    bc_protocol::{StreamKind, UdpStats},
+    bcmedia::model::BcMedia,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::{
+    sync::Mutex,
+    task::JoinSet,
+    time::{timeout, Duration, Instant},
+};
+
+mod cmdline;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// What has been seen of one camera so far
+#[derive(Default)]
+struct Counts {
+    bytes: u64,
+    video_frames: u64,
+    keyframes: u64,
+    audio_frames: u64,
+    last_frame: Option<Instant>,
+    intervals: Vec<Duration>,
+    stream_starts: u64,
+    /// UDP counts of the earlier connections
+    udp_previous: Option<UdpStats>,
+    /// UDP counts of the connection in use
+    udp_current: Option<UdpStats>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    camera: String,
+    transport: &'static str,
+    seconds: f64,
+    megabits_per_second: f64,
+    video_frames: u64,
+    frames_per_second: f64,
+    keyframes: u64,
+    audio_frames: u64,
+    interval_mean_ms: f64,
+    interval_p95_ms: f64,
+    interval_max_ms: f64,
+    /// Gaps between frames of more than twice the median
+    stalls: usize,
+    udp_packets: Option<u64>,
+    udp_packets_missed: Option<u64>,
+    udp_loss_percent: Option<f64>,
+    udp_packets_resent: Option<u64>,
+    reconnects: u64,
+    stream_restarts: u64,
+}
+
+/// Entry point for the benchmark subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let stream_kind = match opt.stream.as_str() {
+        "main" => StreamKind::Main,
+        "sub" => StreamKind::Sub,
+        "extern" => StreamKind::Extern,
+        other => return Err(anyhow!("Unknown stream {other}")),
+    };
+    let duration = Duration::from_secs(opt.duration);
+
+    let mut set = JoinSet::new();
+    for (index, name) in opt.cameras.iter().enumerate() {
+        let camera = reactor.get(name).await?;
+        let name = name.clone();
+        set.spawn(async move { (index, bench(name, camera, stream_kind, duration).await) });
+    }
+    log::info!(
+        "Pulling the {} stream for {}s",
+        opt.stream,
+        duration.as_secs()
+    );
+    let mut reports = vec![];
+    while let Some(result) = set.join_next().await {
+        reports.push(result?);
+    }
+    reports.sort_by_key(|(index, _)| *index);
+    let reports = reports
+        .into_iter()
+        .map(|(_, report)| report)
+        .collect::<Vec<_>>();
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in reports.iter() {
+            print_report(report);
+        }
+    }
+    Ok(())
+}
+
+/// Pull the stream for the duration and count what arrives
+async fn bench(
+    name: String,
+    camera: NeoInstance,
+    stream_kind: StreamKind,
+    duration: Duration,
+) -> Report {
+    let counts = Arc::new(Mutex::new(Counts::default()));
+    let reconnects = Arc::new(Mutex::new(0u64));
+
+    // Each new camera in the watch after the first is a reconnect
+    let mut camera_watch = camera.camera();
+    let thread_reconnects = reconnects.clone();
+    let watch_reconnects = async move {
+        while camera_watch.changed().await.is_ok() {
+            if camera_watch.borrow_and_update().upgrade().is_some() {
+                *thread_reconnects.lock().await += 1;
+            }
+        }
+    };
+
+    let strict = match camera.config().await {
+        Ok(config) => config.borrow().strict,
+        Err(_) => false,
+    };
+    let start = Instant::now();
+    let thread_counts = counts.clone();
+    let pull = camera.run_task(|cam| {
+        let counts = thread_counts.clone();
+        Box::pin(async move {
+            {
+                let mut counts = counts.lock().await;
+                counts.stream_starts += 1;
+                // The counts restart with each connection
+                if let Some(current) = counts.udp_current.take() {
+                    counts.udp_previous = Some(add_udp(counts.udp_previous, current));
+                }
+                // Don't count the time waiting for the new stream as a gap
+                counts.last_frame = None;
+            }
+            let mut stream_data = cam.start_video(stream_kind, 0, strict).await?;
+            counts.lock().await.udp_current = cam.udp_stats();
+            let result: AnyResult<()> = async {
+                loop {
+                    let data = stream_data.get_data().await??;
+                    let now = Instant::now();
+                    let mut counts = counts.lock().await;
+                    match data {
+                        BcMedia::Iframe(frame) => {
+                            counts.bytes += frame.data.len() as u64;
+                            counts.keyframes += 1;
+                            count_video(&mut counts, now);
+                        }
+                        BcMedia::Pframe(frame) => {
+                            counts.bytes += frame.data.len() as u64;
+                            count_video(&mut counts, now);
+                        }
+                        BcMedia::Aac(frame) => {
+                            counts.bytes += frame.data.len() as u64;
+                            counts.audio_frames += 1;
+                        }
+                        BcMedia::Adpcm(frame) => {
+                            counts.bytes += frame.data.len() as u64;
+                            counts.audio_frames += 1;
+                        }
+                        BcMedia::InfoV1(_) | BcMedia::InfoV2(_) => {}
+                    }
+                    counts.udp_current = cam.udp_stats();
+                }
+            }
+            .await;
+            result
+        })
+    });
+    let result: Result<()> = tokio::select! {
+        v = timeout(duration, pull) => match v {
+            Ok(Err(e)) => Err(e),
+            _ => Ok(()),
+        },
+        _ = watch_reconnects => Ok(()),
+    };
+    if let Err(e) = result {
+        log::warn!("{name}: The stream stopped early: {e:?}");
+    }
+    let seconds = start.elapsed().as_secs_f64();
+
+    let counts = counts.lock().await;
+    let udp = match (counts.udp_previous, counts.udp_current) {
+        (Some(previous), Some(current)) => Some(add_udp(Some(previous), current)),
+        (previous, current) => previous.or(current),
+    };
+    let mut intervals = counts
+        .intervals
+        .iter()
+        .map(|interval| interval.as_secs_f64() * 1000.0)
+        .collect::<Vec<_>>();
+    intervals.sort_by(f64::total_cmp);
+    let percentile = |p: f64| {
+        intervals
+            .get(((intervals.len() as f64 - 1.0) * p).round() as usize)
+            .copied()
+            .unwrap_or(0.0)
+    };
+    let median = percentile(0.5);
+    let reconnects = *reconnects.lock().await;
+    Report {
+        camera: name,
+        transport: if udp.is_some() { "udp" } else { "tcp" },
+        seconds,
+        megabits_per_second: counts.bytes as f64 * 8.0 / 1_000_000.0 / seconds,
+        video_frames: counts.video_frames,
+        frames_per_second: counts.video_frames as f64 / seconds,
+        keyframes: counts.keyframes,
+        audio_frames: counts.audio_frames,
+        interval_mean_ms: if intervals.is_empty() {
+            0.0
+        } else {
+            intervals.iter().sum::<f64>() / intervals.len() as f64
+        },
+        interval_p95_ms: percentile(0.95),
+        interval_max_ms: intervals.last().copied().unwrap_or(0.0),
+        stalls: intervals
+            .iter()
+            .filter(|&&interval| interval > median * 2.0)
+            .count(),
+        udp_packets: udp.map(|udp| udp.packets_recieved),
+        udp_packets_missed: udp.map(|udp| udp.packets_missed),
+        udp_loss_percent: udp.map(|udp| {
+            let expected = udp.packets_recieved - udp.packets_duplicated + udp.packets_missed;
+            if expected == 0 {
+                0.0
+            } else {
+                udp.packets_missed as f64 * 100.0 / expected as f64
+            }
+        }),
+        udp_packets_resent: udp.map(|udp| udp.packets_resent),
+        reconnects,
+        stream_restarts: counts.stream_starts.saturating_sub(1),
+    }
+}
+
+fn count_video(counts: &mut Counts, now: Instant) {
+    counts.video_frames += 1;
+    if let Some(last) = counts.last_frame.replace(now) {
+        counts.intervals.push(now - last);
+    }
+}
+
+fn add_udp(previous: Option<UdpStats>, current: UdpStats) -> UdpStats {
+    let previous = previous.unwrap_or_default();
+    UdpStats {
+        packets_recieved: previous.packets_recieved + current.packets_recieved,
+        packets_missed: previous.packets_missed + current.packets_missed,
+        packets_duplicated: previous.packets_duplicated + current.packets_duplicated,
+        packets_resent: previous.packets_resent + current.packets_resent,
+    }
+}
+
+fn print_report(report: &Report) {
+    println!("{} ({})", report.camera, report.transport);
+    println!(
+        "  Throughput:  {:.2} Mbit/s over {:.1}s",
+        report.megabits_per_second, report.seconds
+    );
+    println!(
+        "  Video:       {} frames, {:.1} fps, {} keyframes",
+        report.video_frames, report.frames_per_second, report.keyframes
+    );
+    println!("  Audio:       {} frames", report.audio_frames);
+    println!(
+        "  Intervals:   mean {:.1}ms, p95 {:.1}ms, max {:.1}ms, {} stalls",
+        report.interval_mean_ms, report.interval_p95_ms, report.interval_max_ms, report.stalls
+    );
+    if let (Some(packets), Some(missed), Some(loss), Some(resent)) = (
+        report.udp_packets,
+        report.udp_packets_missed,
+        report.udp_loss_percent,
+        report.udp_packets_resent,
+    ) {
+        println!(
+            "  UDP:         {} packets, {} missed ({:.2}%), {} resent by us",
+            packets, missed, loss, resent
+        );
+    }
+    println!(
+        "  Reconnects:  {}, stream restarts {}",
+        report.reconnects, report.stream_restarts
+    );
+}
@@ -0,0 +1,12 @@
+use clap::Parser;
+
+/// The check-config command checks the config file for errors without starting any services
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Also connect and login to each enabled camera
+    #[arg(long)]
+    pub connect: bool,
+    /// How long to wait for each camera to login in seconds
+    #[arg(long, default_value = "15")]
+    pub timeout: u64,
+}
@@ -0,0 +1,104 @@
+///
+/// # Neolink Check Config
+///
+/// This module handles the check-config subcommand
+///
+/// The subcommand parses and validates the config file, including the
+/// checks that span several fields such as duplicate camera names, unknown
+/// users or ports used twice, and keys that are not part of the config. Each
+/// problem is printed as `file:line: field: message`
+/// and the exit code is non zero if any are found, so it can be used in
+/// CI before deploying a config
+///
+/// # Usage
+///
+/// ```bash
+/// neolink check-config --config=config.toml
+/// neolink check-config --config=config.toml --connect
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use std::{fs, path::PathBuf};
+use tokio::time::{timeout, Duration};
+use validator::Validate;
+
+mod cmdline;
+
+use crate::{
+    config::{find_line, flatten_errors, Config, ConfigError},
+    utils::connect_and_login,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the check-config subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, config_path: Option<PathBuf>) -> Result<()> {
+    let config_path = config_path.context("Must supply --config file")?;
+    let source = fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed to read {:?}", config_path))?;
+    let file = config_path.display();
+
+    let (config, mut problems) = match Config::parse(&source, &config_path) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            match e.downcast_ref::<ConfigError>() {
+                Some(ConfigError {
+                    line: Some(line),
+                    message,
+                }) => println!("{file}:{line}: {message}"),
+                Some(ConfigError {
+                    line: None,
+                    message,
+                }) => println!("{file}: {message}"),
+                None => println!("{file}: {e:#}"),
+            }
+            return Err(anyhow!("The config file could not be parsed"));
+        }
+    };
+
+    if let Err(errors) = config.validate() {
+        flatten_errors("", &errors, &mut problems);
+    }
+    problems.extend(config.conflicts());
+    problems.extend(config.cross_field_errors());
+
+    for (field, message) in problems.iter() {
+        match find_line(&source, field) {
+            Some(line) => println!("{file}:{line}: {field}: {message}"),
+            None => println!("{file}: {field}: {message}"),
+        }
+    }
+
+    let mut failed_cameras = 0;
+    if opt.connect && problems.is_empty() {
+        for camera_config in config.cameras.iter().filter(|c| c.enabled) {
+            let r = timeout(
+                Duration::from_secs(opt.timeout),
+                connect_and_login(camera_config),
+            )
+            .await
+            .map_err(|_| anyhow!("Timed out"))
+            .and_then(|r| r);
+            match r {
+                Ok(camera) => {
+                    println!("{}: OK", camera_config.name);
+                    let _ = camera.logout().await;
+                }
+                Err(e) => {
+                    println!("{}: {:#}", camera_config.name, e);
+                    failed_cameras += 1;
+                }
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        Err(anyhow!("{} problems found in {file}", problems.len()))
+    } else if failed_cameras > 0 {
+        Err(anyhow!("{failed_cameras} cameras could not be reached"))
+    } else {
+        println!("{file}: OK");
+        Ok(())
+    }
+}
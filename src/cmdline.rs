@@ -32,4 +32,40 @@ pub enum Command {
     Image(super::image::Opt),
     Battery(super::battery::Opt),
     Services(super::services::Opt),
+    Siren(super::siren::Opt),
+    Snapshot(super::snapshot::Opt),
+    Users(super::users::Opt),
+    Firmware(super::firmware::Opt),
+    Sdcard(super::sdcard::Opt),
+    Abilities(super::abilities::Opt),
+    Wifi(super::wifi::Opt),
+    Proxy(super::proxy::Opt),
+    PcapDecode(super::pcap_decode::Opt),
+    MockCamera(super::mock_camera::Opt),
+    Shell(super::shell::Opt),
+    Benchmark(super::benchmark::Opt),
+    Discover(super::discover::Opt),
+    CheckConfig(super::check_config::Opt),
+    #[cfg(feature = "events")]
+    Events(super::events::Opt),
+    #[cfg(feature = "gstreamer")]
+    Download(super::download::Opt),
+    #[cfg(feature = "gstreamer")]
+    Record(super::record::Opt),
+    #[cfg(feature = "gstreamer")]
+    Timelapse(super::timelapse::Opt),
+    #[cfg(windows)]
+    WindowsService(super::winservice::Opt),
+}
+
+impl Command {
+    /// If the command keeps running as a service rather than doing one thing
+    pub fn is_service(&self) -> bool {
+        match self {
+            #[cfg(feature = "gstreamer")]
+            Command::Rtsp(_) | Command::MqttRtsp(_) => true,
+            Command::Mqtt(_) => true,
+            _ => false,
+        }
+    }
 }
@@ -0,0 +1,36 @@
+//! Feeds the frames of a camera into the appsrc of a gstreamer pipeline
+//!
+//! The pipelines of hls, the outputs, the recordings, the record subcommand,
+//! webrtc and sip all get their sources and push the frames this way
+use anyhow::anyhow;
+use gstreamer::{prelude::*, ClockTime, Pipeline};
+use gstreamer_app::AppSrc;
+use tokio::time::Duration;
+
+use super::StampedData;
+use crate::AnyResult;
+
+/// The appsrc of the pipeline with this name
+pub(crate) fn get_source(pipeline: &Pipeline, name: &str) -> AnyResult<AppSrc> {
+    pipeline
+        .by_name(name)
+        .and_then(|source| source.dynamic_cast::<AppSrc>().ok())
+        .ok_or_else(|| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))
+}
+
+/// Push a frame with its time from `start`, the bytes are not copied
+pub(crate) fn push_frame(source: &AppSrc, start: Duration, frame: &StampedData) -> AnyResult<()> {
+    let mut buf = gstreamer::Buffer::from_slice(frame.data.clone());
+    {
+        let buf = buf
+            .get_mut()
+            .ok_or_else(|| anyhow!("Could not write to the gstreamer buffer"))?;
+        let ts = ClockTime::from_nseconds(frame.ts.saturating_sub(start).as_nanos() as u64);
+        buf.set_pts(ts);
+        buf.set_dts(ts);
+    }
+    source
+        .push_buffer(buf)
+        .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    Ok(())
+}
@@ -5,7 +5,11 @@ use tokio::{
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::{config::CameraConfig, utils::connect_and_login, AnyResult};
+use crate::{
+    config::{CameraConfig, GiveUp},
+    utils::connect_and_login,
+    AnyResult,
+};
 use neolink_core::bc_protocol::BcCamera;
 
 #[derive(Eq, PartialEq, Copy, Clone)]
@@ -14,11 +18,22 @@ pub(crate) enum NeoCamThreadState {
     Disconnected,
 }
 
+/// Why neolink stopped trying to connect to the camera
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub(crate) enum NeoCamFailure {
+    /// It failed `retry.max_attempts` times in a row
+    GaveUp,
+    /// The username and password were not accepted
+    LoginRejected,
+}
+
 pub(crate) struct NeoCamThread {
     state: WatchReceiver<NeoCamThreadState>,
     config: WatchReceiver<CameraConfig>,
     cancel: CancellationToken,
     camera_watch: WatchSender<Weak<BcCamera>>,
+    /// Set while neolink has stopped trying to connect
+    failed: WatchSender<Option<NeoCamFailure>>,
 }
 
 impl NeoCamThread {
@@ -26,6 +41,7 @@ impl NeoCamThread {
         watch_state_rx: WatchReceiver<NeoCamThreadState>,
         watch_config_rx: WatchReceiver<CameraConfig>,
         camera_watch_tx: WatchSender<Weak<BcCamera>>,
+        failed_tx: WatchSender<Option<NeoCamFailure>>,
         cancel: CancellationToken,
     ) -> Self {
         Self {
@@ -33,6 +49,7 @@ impl NeoCamThread {
             config: watch_config_rx,
             cancel,
             camera_watch: camera_watch_tx,
+            failed: failed_tx,
         }
     }
     async fn run_camera(&mut self, config: &CameraConfig) -> AnyResult<()> {
@@ -48,6 +65,8 @@ impl NeoCamThread {
         sleep(Duration::from_secs(2)).await; // Delay a little since some calls will error if camera is waking up
 
         self.camera_watch.send_replace(Arc::downgrade(&camera));
+        self.failed
+            .send_if_modified(|failed| failed.take().is_some());
 
         let cancel_check = self.cancel.clone();
         // Now we wait for a disconnect
@@ -105,21 +124,26 @@ impl NeoCamThread {
     // A watch sender is used to send the new camera
     // whenever it changes
     pub(crate) async fn run(&mut self) -> AnyResult<()> {
-        const MAX_BACKOFF: Duration = Duration::from_secs(5);
-        const MIN_BACKOFF: Duration = Duration::from_millis(50);
-
-        let mut backoff = MIN_BACKOFF;
+        let mut backoff = None;
+        // Failed connections in a row
+        let mut attempts = 0;
 
         loop {
-            self.state
-                .clone()
-                .wait_for(|state| matches!(state, NeoCamThreadState::Connected))
-                .await?;
+            let mut connected = self.state.clone();
+            tokio::select! {
+                _ = self.cancel.cancelled() => return Ok(()),
+                v = connected.wait_for(|state| matches!(state, NeoCamThreadState::Connected)) => {
+                    v?;
+                }
+            }
             let mut config_rec = self.config.clone();
 
             let config = config_rec.borrow_and_update().clone();
             let now = Instant::now();
             let name = config.name.clone();
+            let retry = config.retry.clone();
+            let min_backoff = Duration::from_millis(retry.min_backoff);
+            let max_backoff = Duration::from_millis(retry.max_backoff);
 
             let mut state = self.state.clone();
 
@@ -150,11 +174,12 @@ impl NeoCamThread {
 
             if now.elapsed() > Duration::from_secs(60) {
                 // Command ran long enough to be considered a success
-                backoff = MIN_BACKOFF;
-            }
-            if backoff > MAX_BACKOFF {
-                backoff = MAX_BACKOFF;
+                backoff = None;
+                attempts = 0;
             }
+            let wait = backoff
+                .unwrap_or(min_backoff)
+                .clamp(min_backoff, max_backoff);
 
             match result {
                 Ok(()) => {
@@ -169,17 +194,57 @@ impl NeoCamThread {
                     let e_inner = e.downcast_ref::<neolink_core::Error>();
                     match e_inner {
                         Some(neolink_core::Error::CameraLoginFail) => {
-                            // Fatal
-                            log::error!("{name}: Login credentials were not accepted");
-                            self.cancel.cancel();
-                            return Err(e);
+                            // Trying again could lock the account so wait
+                            // for a new password
+                            log::error!("{name}: Login credentials were not accepted, waiting for a new password in the config or a reconnect");
+                            self.failed.send_replace(Some(NeoCamFailure::LoginRejected));
+                            tokio::select! {
+                                _ = self.cancel.cancelled() => return Ok(()),
+                                Ok(_) = config_rec.changed() => {},
+                                Ok(_) = state.wait_for(|state| matches!(state, NeoCamThreadState::Disconnected)) => {},
+                                else => {
+                                    self.cancel.cancel();
+                                    return Err(e);
+                                }
+                            }
+                            backoff = None;
+                            attempts = 0;
+                            continue;
                         }
                         _ => {
                             // Non fatal
+                            attempts += 1;
+                            if retry.max_attempts.is_some_and(|max| attempts >= max) {
+                                if retry.give_up == GiveUp::Exit {
+                                    log::error!("{name}: Giving up after {attempts} failed connections, stopping neolink: {e:?}");
+                                    crate::shutdown::request_failed();
+                                    return Err(e.context(format!(
+                                        "Gave up on {name} after {attempts} failed connections"
+                                    )));
+                                }
+                                log::error!(
+                                    "{name}: Giving up after {attempts} failed connections, trying again in {}s: {e:?}",
+                                    retry.give_up_retry
+                                );
+                                self.failed.send_replace(Some(NeoCamFailure::GaveUp));
+                                // A new config or a reconnect also tries again
+                                tokio::select! {
+                                    _ = self.cancel.cancelled() => return Ok(()),
+                                    _ = sleep(Duration::from_secs(retry.give_up_retry)) => {},
+                                    Ok(_) = config_rec.changed() => {},
+                                    Ok(_) = state.wait_for(|state| matches!(state, NeoCamThreadState::Disconnected)) => {},
+                                }
+                                backoff = None;
+                                attempts = 0;
+                                continue;
+                            }
                             log::warn!("{name}: Connection Lost: {:?}", e);
-                            log::info!("{name}: Attempt reconnect in {:?}", backoff);
-                            sleep(backoff).await;
-                            backoff *= 2;
+                            log::info!("{name}: Attempt reconnect in {:?}", wait);
+                            tokio::select! {
+                                _ = self.cancel.cancelled() => return Ok(()),
+                                _ = sleep(wait) => {},
+                            }
+                            backoff = Some(wait * 2);
                         }
                     }
                 }
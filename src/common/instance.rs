@@ -21,7 +21,9 @@ use tokio_util::sync::CancellationToken;
 use super::PushNoti;
 #[cfg(feature = "gstreamer")]
 use super::StreamInstance;
-use super::{MdState, NeoCamCommand, NeoCamThreadState, Permit};
+use super::{
+    MdState, NeoCamCommand, NeoCamFailure, NeoCamThreadState, Permit, RtspClientInfo, TaskFailure,
+};
 use crate::{config::CameraConfig, AnyResult, Result};
 use neolink_core::bc_protocol::BcCamera;
 #[cfg(feature = "gstreamer")]
@@ -323,6 +325,24 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// The clients currently connected to this camera over rtsp
+    pub(crate) async fn rtsp_clients(&self) -> Result<WatchReceiver<Vec<RtspClientInfo>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::RtspClients(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    #[cfg(feature = "gstreamer")]
+    pub(crate) async fn set_rtsp_clients(&self, clients: Vec<RtspClientInfo>) -> Result<()> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::SetRtspClients(clients, instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
     pub(crate) async fn config(&self) -> Result<WatchReceiver<CameraConfig>> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -351,6 +371,19 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// Drop the connection to the camera and establish a new one
+    pub(crate) async fn reconnect(&self) -> Result<()> {
+        self.disconnect().await?;
+        // Wait for the old connection to go so the connect is not merged into the disconnect
+        let mut camera_watch = self.camera();
+        let _ = tokio::time::timeout(
+            Duration::from_secs(10),
+            camera_watch.wait_for(|cam| cam.upgrade().is_none()),
+        )
+        .await;
+        self.connect().await
+    }
+
     #[allow(dead_code)]
     pub(crate) async fn get_state(&self) -> Result<NeoCamThreadState> {
         let (instance_tx, instance_rx) = oneshot();
@@ -360,6 +393,32 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// Set while neolink has stopped trying to connect to the camera
+    pub(crate) async fn failed(&self) -> Result<WatchReceiver<Option<NeoCamFailure>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Failed(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// The last task of the camera that failed, such as its stream
+    pub(crate) async fn task_failed(&self) -> Result<WatchReceiver<Option<TaskFailure>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::TaskFailed(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    pub(crate) async fn set_task_failed(&self, failure: TaskFailure) -> Result<()> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::SetTaskFailed(failure, instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
     pub(crate) async fn permit(&self) -> Result<Permit> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -15,11 +15,11 @@ use tokio_util::sync::CancellationToken;
 
 use super::NeoInstance;
 use crate::{AnyResult, Result};
-use neolink_core::bc_protocol::MotionStatus;
+use neolink_core::bc_protocol::{MotionDetails, MotionStatus};
 
 #[allow(dead_code)]
 pub(crate) enum MdState {
-    Start(Instant),
+    Start(Instant, MotionDetails),
     Stop(Instant),
     Unknown,
 }
@@ -78,7 +78,7 @@ impl NeoCamMdThread {
                                 match event {
                                     MotionStatus::Start(at) => {
                                         watcher.send_replace(
-                                            MdState::Start(at.into())
+                                            MdState::Start(at.into(), md.last_details().clone())
                                         );
                                     }
                                     MotionStatus::Stop(at) => {
@@ -1,3 +1,5 @@
+#[cfg(feature = "gstreamer")]
+mod appsrc;
 mod camthread;
 mod instance;
 mod mdthread;
@@ -5,10 +7,14 @@ mod neocam;
 #[cfg(feature = "pushnoti")]
 mod pushnoti;
 mod reactor;
+mod rtspclients;
 #[cfg(feature = "gstreamer")]
 mod streamthread;
+mod supervise;
 mod usecounter;
 
+#[cfg(feature = "gstreamer")]
+pub(crate) use appsrc::*;
 pub(crate) use camthread::*;
 pub(crate) use instance::*;
 pub(crate) use mdthread::*;
@@ -16,6 +22,8 @@ pub(crate) use neocam::*;
 #[cfg(feature = "pushnoti")]
 pub(crate) use pushnoti::*;
 pub(crate) use reactor::*;
+pub(crate) use rtspclients::*;
 #[cfg(feature = "gstreamer")]
 pub(crate) use streamthread::*;
+pub(crate) use supervise::*;
 pub(crate) use usecounter::*;
@@ -6,7 +6,7 @@
 //!    Clonable interface to share amongst threadsanyhow::anyhow;
 use anyhow::Context;
 use futures::{stream::StreamExt, TryFutureExt};
-use std::sync::Weak;
+use std::{future::Future, sync::Weak};
 use tokio::{
     sync::{
         mpsc::{channel as mpsc, Sender as MpscSender},
@@ -18,10 +18,11 @@ use tokio::{
 };
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span};
 
 use super::{
-    MdRequest, MdState, NeoCamMdThread, NeoCamThread, NeoCamThreadState, NeoInstance, Permit,
-    UseCounter,
+    MdRequest, MdState, NeoCamFailure, NeoCamMdThread, NeoCamThread, NeoCamThreadState,
+    NeoInstance, Permit, RtspClientInfo, TaskFailure, TaskSupervisor, UseCounter,
 };
 #[cfg(feature = "gstreamer")]
 use super::{NeoCamStreamThread, StreamInstance, StreamRequest};
@@ -49,10 +50,15 @@ pub(crate) enum NeoCamCommand {
     Disconnect(OneshotSender<()>),
     Connect(OneshotSender<()>),
     State(OneshotSender<NeoCamThreadState>),
+    Failed(OneshotSender<WatchReceiver<Option<NeoCamFailure>>>),
     GetPermit(OneshotSender<Permit>),
     #[cfg(feature = "pushnoti")]
     PushNoti(OneshotSender<WatchReceiver<Option<PushNoti>>>),
     GetUid(OneshotSender<String>),
+    RtspClients(OneshotSender<WatchReceiver<Vec<RtspClientInfo>>>),
+    SetRtspClients(Vec<RtspClientInfo>, OneshotSender<()>),
+    TaskFailed(OneshotSender<WatchReceiver<Option<TaskFailure>>>),
+    SetTaskFailed(TaskFailure, OneshotSender<()>),
 }
 /// The underlying camera binding
 pub(crate) struct NeoCam {
@@ -61,9 +67,19 @@ pub(crate) struct NeoCam {
     commander: MpscSender<NeoCamCommand>,
     camera_watch: WatchReceiver<Weak<BcCamera>>,
     set: JoinSet<AnyResult<()>>,
+    /// The `camera` span that its tasks run in
+    span: Span,
 }
 
 impl NeoCam {
+    /// Run a task of the camera in its span
+    fn spawn<F>(&mut self, task: F)
+    where
+        F: Future<Output = AnyResult<()>> + Send + 'static,
+    {
+        self.set.spawn(task.instrument(self.span.clone()));
+    }
+
     pub(crate) async fn new(
         config: CameraConfig,
         #[cfg(feature = "pushnoti")] pn_request_tx: MpscSender<PnRequest>,
@@ -76,6 +92,9 @@ impl NeoCam {
         let (md_request_tx, md_request_rx) = mpsc(100);
         let (state_tx, state_rx) = watch(NeoCamThreadState::Connected);
         let (uid_tx, uid_rx) = watch(config.camera_uid.clone());
+        let (rtsp_clients_tx, _) = watch(Vec::<RtspClientInfo>::new());
+        let (failed_tx, failed_rx) = watch(None);
+        let (task_failed_tx, _) = watch(None);
 
         let set = JoinSet::new();
         let users = UseCounter::new().await;
@@ -86,6 +105,7 @@ impl NeoCam {
             commander: commander_tx.clone(),
             camera_watch: camera_watch_rx.clone(),
             set,
+            span: tracing::info_span!("camera", camera = %config.name),
         };
 
         // This thread recieves messages from the instances
@@ -102,7 +122,7 @@ impl NeoCam {
         #[cfg(feature = "pushnoti")]
         let thread_pn_request_tx = pn_request_tx.clone();
 
-        me.set.spawn(async move {
+        me.spawn(async move {
             let thread_cancel = sender_cancel.clone();
             let res = tokio::select! {
                 _ = sender_cancel.cancelled() => {
@@ -182,6 +202,9 @@ impl NeoCam {
                             NeoCamCommand::State(sender) => {
                                 let _ = sender.send(*state_tx.borrow());
                             }
+                            NeoCamCommand::Failed(sender) => {
+                                let _ = sender.send(failed_rx.clone());
+                            }
                             NeoCamCommand::GetPermit(sender) => {
                                 let _ = sender.send(users.create_activated().await?);
                             }
@@ -201,6 +224,27 @@ impl NeoCam {
                                     AnyResult::Ok(())
                                 });
                             },
+                            NeoCamCommand::RtspClients(sender) => {
+                                let _ = sender.send(rtsp_clients_tx.subscribe());
+                            },
+                            NeoCamCommand::SetRtspClients(clients, sender) => {
+                                rtsp_clients_tx.send_if_modified(|old| {
+                                    if *old != clients {
+                                        *old = clients;
+                                        true
+                                    } else {
+                                        false
+                                    }
+                                });
+                                let _ = sender.send(());
+                            },
+                            NeoCamCommand::TaskFailed(sender) => {
+                                let _ = sender.send(task_failed_tx.subscribe());
+                            },
+                            NeoCamCommand::SetTaskFailed(failure, sender) => {
+                                task_failed_tx.send_replace(Some(failure));
+                                let _ = sender.send(());
+                            },
                         }
                     }
                     Ok(())
@@ -226,10 +270,11 @@ impl NeoCam {
             state_rx,
             thread_watch_config_rx,
             camera_watch_tx,
+            failed_tx,
             me.cancel.clone(),
         )
         .await;
-        me.set.spawn(async move { cam_thread.run().await });
+        me.spawn(async move { cam_thread.run().await });
 
         // This thread maintains the streams
         #[cfg(feature = "gstreamer")]
@@ -238,10 +283,14 @@ impl NeoCam {
             let stream_cancel = me.cancel.clone();
             let mut stream_thread =
                 NeoCamStreamThread::new(stream_request_rx, stream_instance).await?;
-            me.set.spawn(async move {
+            let mut supervisor = TaskSupervisor::new("stream", instance.subscribe().await?);
+            me.spawn(async move {
                 tokio::select! {
                     _ = stream_cancel.cancelled() => AnyResult::Ok(()),
-                    v = stream_thread.run() => {
+                    v = async {
+                        while supervisor.run(stream_thread.run()).await? {}
+                        AnyResult::Ok(())
+                    } => {
                         v
                     },
                 }
@@ -252,10 +301,14 @@ impl NeoCam {
         let md_instance = instance.subscribe().await?;
         let md_cancel = me.cancel.clone();
         let mut md_thread = NeoCamMdThread::new(md_request_rx, md_instance).await?;
-        me.set.spawn(async move {
+        let mut supervisor = TaskSupervisor::new("motion", instance.subscribe().await?);
+        me.spawn(async move {
             tokio::select! {
                 _ = md_cancel.cancelled() => AnyResult::Ok(()),
-                v = md_thread.run() => {
+                v = async {
+                    while supervisor.run(md_thread.run()).await? {}
+                    AnyResult::Ok(())
+                } => {
                     v
                 },
             }
@@ -265,7 +318,7 @@ impl NeoCam {
         let report_instance = instance.subscribe().await?;
         let report_cancel = me.cancel.clone();
         let report_name = config.name.clone();
-        me.set.spawn(async move {
+        me.spawn(async move {
             tokio::select! {
                 _ = report_cancel.cancelled() => {
                     AnyResult::Ok(())
@@ -299,7 +352,7 @@ impl NeoCam {
         // We cache this in the uid_rx
         let uid_instance = instance.clone();
         let uid_cancel = me.cancel.clone();
-        me.set.spawn(async move {
+        me.spawn(async move {
             tokio::select! {
                 _ = uid_cancel.cancelled() => {
                     AnyResult::Ok(())
@@ -321,7 +374,7 @@ impl NeoCam {
             let pn_root_instance = instance.subscribe().await?;
             let pn_cancel = me.cancel.clone();
             let thread_pn_request_tx = pn_request_tx.clone();
-            me.set.spawn(async move {
+            me.spawn(async move {
                 tokio::select!{
                     _ = pn_cancel.cancelled() => {
                         AnyResult::Ok(())
@@ -339,6 +392,7 @@ impl NeoCam {
                             rx.await??;
 
                             let pn_permit_instance = pn_root_instance.subscribe().await?;
+                            let wake_rx = config_rx.clone();
                             let r = tokio::select! {
                                 // Push notification permits
                                 v = async {
@@ -346,8 +400,9 @@ impl NeoCam {
                                     let mut pn = pn_permit_instance.push_notifications().await?;
                                     loop{
                                         prev_noti = pn.wait_for(|noti| noti != &prev_noti && noti.is_some()).await.map(|noti| noti.clone())?;
+                                        let wake = Duration::from_secs(wake_rx.borrow().push_wake);
                                         let _permit = pn_permit_instance.permit().await?;
-                                        sleep(Duration::from_secs(30)).await; // Push notification will wake us up for 30s
+                                        sleep(wake).await; // Push notification will wake us up for a while
                                     }
                                 } => v,
                                 // Continue loop on Red light
@@ -371,7 +426,7 @@ impl NeoCam {
         // MD permits
         let md_permit_instance = instance.subscribe().await?;
         let md_permit_cancel = me.cancel.clone();
-        me.set.spawn(async move {
+        me.spawn(async move {
             tokio::select! {
                 _ = md_permit_cancel.cancelled() => {
                     AnyResult::Ok(())
@@ -379,7 +434,7 @@ impl NeoCam {
                 v = async {
                     let mut md = md_permit_instance.motion().await.with_context(|| "Unable to acquire motion watcher")?;
                     loop{
-                        md.wait_for(|md| matches!(md, MdState::Start(_))).await.with_context(|| "MD Watcher lost")?;
+                        md.wait_for(|md| matches!(md, MdState::Start(..))).await.with_context(|| "MD Watcher lost")?;
                         let _permit = md_permit_instance.permit().await.with_context(|| "Unuable to acquire motion permit")?;
                         md.wait_for(|md| matches!(md, MdState::Stop(_))).await.with_context(|| "MD Watcher lost")?;
                         // Try waiting for 30s
@@ -387,7 +442,7 @@ impl NeoCam {
                         // loop early to reaquire the permit
                         tokio::select!{
                             _ = sleep(Duration::from_secs(30)) => {},
-                            v = md.wait_for(|md| matches!(md, MdState::Start(_))) => {v.with_context(|| "MD Watcher lost")?;},
+                            v = md.wait_for(|md| matches!(md, MdState::Start(..))) => {v.with_context(|| "MD Watcher lost")?;},
                         }
                     }
                 } => {
@@ -403,7 +458,7 @@ impl NeoCam {
         // notifications are observed
         let connect_instance = instance.subscribe().await?;
         let connect_cancel = me.cancel.clone();
-        me.set.spawn(async move {
+        me.spawn(async move {
             tokio::select!{
                 _ = connect_cancel.cancelled() => {
                     AnyResult::Ok(())
@@ -461,9 +516,24 @@ impl NeoCam {
     }
 
     pub(crate) async fn update_config(&self, config: CameraConfig) -> Result<()> {
-        self.config_watch.send_replace(config);
+        // An unchanged camera must not see a change or it would reconnect
+        self.config_watch.send_if_modified(|current| {
+            if *current != config {
+                *current = config;
+                true
+            } else {
+                false
+            }
+        });
         Ok(())
     }
+
+    /// Stop the threads and wait for the camera to logout
+    pub(crate) async fn shutdown(mut self) {
+        let mut set = std::mem::take(&mut self.set);
+        let _ = self.commander.send(NeoCamCommand::HangUp).await;
+        while set.join_next().await.is_some() {}
+    }
 }
 
 impl Drop for NeoCam {
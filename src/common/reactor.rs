@@ -1,5 +1,10 @@
 //! This is the highest level to a camera
 //! it represents a collection of managed cameras
+//!
+//! Each camera has a single [`NeoCam`] and so a single connection, that is
+//! shared by all of the services. Rtsp, mqtt and the others subscribe to it
+//! with [`NeoReactor::get`] rather than connecting themselves. Running
+//! `mqtt-rtsp` rather than two neolink processes uses one connection for both
 use anyhow::anyhow;
 use std::{
     collections::{hash_map::Entry, HashMap},
@@ -25,6 +30,7 @@ use crate::{config::Config, AnyResult, Result};
 #[allow(clippy::large_enum_variant)]
 enum NeoReactorCommand {
     HangUp,
+    Shutdown(OneshotSender<()>),
     Config(OneshotSender<WatchReceiver<Config>>),
     UpdateConfig(Config, OneshotSender<Result<()>>),
     Get(String, OneshotSender<Result<Option<NeoInstance>>>),
@@ -69,6 +75,12 @@ impl NeoReactor {
                                 cancel2.cancel();
                                 return Result::<(), anyhow::Error>::Ok(());
                             }
+                            NeoReactorCommand::Shutdown(reply) =>  {
+                                futures::future::join_all(instances.drain().map(|(_, cam)| cam.shutdown())).await;
+                                cancel2.cancel();
+                                let _ = reply.send(());
+                                return Result::<(), anyhow::Error>::Ok(());
+                            }
                             NeoReactorCommand::Config(reply) =>  {
                                 let _ = reply.send(thread_config_tx.subscribe());
                             }
@@ -186,6 +198,16 @@ impl NeoReactor {
 
         sender_rx.await?
     }
+
+    /// Stop all of the cameras and wait for them to logout
+    pub(crate) async fn shutdown(&self) -> Result<()> {
+        let (sender_tx, sender_rx) = oneshot();
+        self.commander
+            .send(NeoReactorCommand::Shutdown(sender_tx))
+            .await?;
+        sender_rx.await?;
+        Ok(())
+    }
 }
 
 impl Drop for NeoReactor {
@@ -0,0 +1,20 @@
+//! Information about the clients connected to the rtsp server
+//!
+//! This is shared through the [`super::NeoCam`] so that other
+//! subsystems such as mqtt can report on it
+use serde::Serialize;
+
+/// A client that is currently watching a camera
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct RtspClientInfo {
+    /// Address of the client
+    pub(crate) ip: String,
+    /// User the client authenticated as
+    pub(crate) user: String,
+    /// Path that is being played
+    pub(crate) path: String,
+    /// Time since the client connected in seconds
+    pub(crate) uptime: u64,
+    /// Total bytes of media sent to the client
+    pub(crate) bytes_sent: u64,
+}
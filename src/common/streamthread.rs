@@ -3,6 +3,7 @@
 //! If there are no listeners to the broadcast
 //! then it will hangup
 
+use bytes::Bytes;
 use futures::stream::{FuturesUnordered, StreamExt};
 use std::{
     collections::{hash_map::Entry, HashMap, VecDeque},
@@ -99,7 +100,7 @@ impl NeoCamStreamThread {
                                 StreamKind::Sub,
                             ];
                             let config = self.instance.config().await?.borrow().clone();
-                            let config_streams = config.stream.as_stream_kinds();
+                            let config_streams = config.stream_kinds();
                             for name in streams.drain(..) {
                                 if config_streams.contains(&name) {
                                     // Fill it in
@@ -130,7 +131,7 @@ impl NeoCamStreamThread {
                                 StreamKind::Main,
                             ];
                             let config = self.instance.config().await?.borrow().clone();
-                            let config_streams = config.stream.as_stream_kinds();
+                            let config_streams = config.stream_kinds();
                             for name in streams.drain(..) {
                                 if config_streams.contains(&name) {
                                     // Fill it in
@@ -156,7 +157,7 @@ impl NeoCamStreamThread {
                             sender
                         } => {
                             let config = self.instance.config().await?.borrow_and_update().clone();
-                            let streams = config.stream.as_stream_kinds();
+                            let streams = config.stream_kinds();
                             for stream in streams.iter().copied() {
                                 if let Entry::Vacant(vac) = self.streams.entry(stream) {
                                     vac.insert(
@@ -275,7 +276,9 @@ impl StreamConfig {
 #[derive(Debug, Clone)]
 pub(crate) struct StampedData {
     pub(crate) keyframe: bool,
-    pub(crate) data: Arc<Vec<u8>>,
+    /// Shared without copying by the broadcast, the history and the gstreamer
+    /// buffers
+    pub(crate) data: Bytes,
     pub(crate) ts: Duration,
 }
 
@@ -589,7 +592,7 @@ impl StreamData {
                                                     BcMedia::Iframe(BcMediaIframe{data, ..}) => {
                                                         let d = StampedData{
                                                                 keyframe: true,
-                                                                data: Arc::new(data),
+                                                                data: Bytes::from(data),
                                                                 ts: *master_ts.read().await,
                                                         };
                                                         let _ = vid_tx.send(d.clone());
@@ -612,7 +615,7 @@ impl StreamData {
                                                     BcMedia::Pframe(BcMediaPframe{data, ..}) if recieved_iframe => {
                                                         let d = StampedData{
                                                             keyframe: false,
-                                                            data: Arc::new(data),
+                                                            data: Bytes::from(data),
                                                             ts: *master_ts.read().await,
                                                         };
                                                         let _ = vid_tx.send(d.clone());
@@ -631,7 +634,7 @@ impl StreamData {
                                                         let m_ts =  *master_ts.read().await;
                                                         let d = StampedData{
                                                             keyframe: aud_keyframe,
-                                                            data: Arc::new(data),
+                                                            data: Bytes::from(data),
                                                             ts: m_ts,
                                                         };
                                                         aud_keyframe = false;
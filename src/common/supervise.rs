@@ -0,0 +1,145 @@
+//! Restarts the tasks of a camera when they fail
+//!
+//! A task such as the stream or the motion of a camera that panics or
+//! returns an error is logged, reported with [`NeoInstance::task_failed`]
+//! so that mqtt can publish it and then started again after a backoff,
+//! rather than stopping silently or taking the whole process down
+//!
+//! What is done after a failure is the `task_restart` of `[cameras.retry]`.
+//! The services that are not tied to a camera, such as the http api, are
+//! always restarted
+use futures::FutureExt;
+use std::{any::Any, future::Future, panic::AssertUnwindSafe};
+use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use super::NeoInstance;
+use crate::{config::TaskRestart, AnyResult};
+
+/// The wait after the first failure, it doubles on each failure
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// The longest wait before a task is started again
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A task that ran this long is no longer failing
+const RESET_AFTER: Duration = Duration::from_secs(60);
+
+/// A task of the camera that failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TaskFailure {
+    pub(crate) task: String,
+    pub(crate) error: String,
+    /// Failures in a row
+    pub(crate) failures: u32,
+    /// Seconds until it is started again, none if it is not
+    pub(crate) retry_in: Option<u64>,
+}
+
+/// Runs a task of a camera and decides if it is started again
+pub(crate) struct TaskSupervisor {
+    task: &'static str,
+    /// The camera of the task, none for a service of neolink
+    camera: Option<NeoInstance>,
+    /// Ends the backoff early when the task is no longer wanted
+    cancel: CancellationToken,
+    backoff: Duration,
+    failures: u32,
+}
+
+impl TaskSupervisor {
+    pub(crate) fn new(task: &'static str, camera: NeoInstance) -> Self {
+        Self {
+            task,
+            camera: Some(camera),
+            cancel: CancellationToken::new(),
+            backoff: MIN_BACKOFF,
+            failures: 0,
+        }
+    }
+
+    /// Supervise a service of neolink, it is not started again once `cancel`
+    /// is cancelled
+    pub(crate) fn service(task: &'static str, cancel: CancellationToken) -> Self {
+        Self {
+            task,
+            camera: None,
+            cancel,
+            backoff: MIN_BACKOFF,
+            failures: 0,
+        }
+    }
+
+    /// Run the task once, true if it failed and should be started again
+    ///
+    /// After a failure the backoff is waited out before returning. A task
+    /// that is stopped or that stops neolink returns false
+    pub(crate) async fn run<F>(&mut self, task: F) -> AnyResult<bool>
+    where
+        F: Future<Output = AnyResult<()>>,
+    {
+        let started = Instant::now();
+        let error = match AssertUnwindSafe(task).catch_unwind().await {
+            Ok(Ok(())) => return Ok(false),
+            Ok(Err(e)) => format!("{e:?}"),
+            Err(panic) => format!("Panicked: {}", panic_message(panic.as_ref())),
+        };
+        if started.elapsed() > RESET_AFTER {
+            self.backoff = MIN_BACKOFF;
+            self.failures = 0;
+        }
+        self.failures += 1;
+
+        let task = self.task;
+        let (name, policy) = match self.camera.as_ref() {
+            Some(camera) => {
+                let config = camera.config().await?;
+                let config = config.borrow();
+                (config.name.clone(), config.retry.task_restart)
+            }
+            None => ("neolink".to_string(), TaskRestart::Restart),
+        };
+        if let Some(camera) = self.camera.as_ref() {
+            let retry_in = (policy == TaskRestart::Restart).then_some(self.backoff.as_secs());
+            let _ = camera
+                .set_task_failed(TaskFailure {
+                    task: task.to_string(),
+                    error: error.clone(),
+                    failures: self.failures,
+                    retry_in,
+                })
+                .await;
+        }
+        match policy {
+            TaskRestart::Exit => {
+                log::error!("{name}: The {task} task failed, stopping neolink: {error}");
+                crate::shutdown::request_failed();
+                Ok(false)
+            }
+            TaskRestart::Stop => {
+                log::error!("{name}: The {task} task failed, it is not restarted: {error}");
+                Ok(false)
+            }
+            TaskRestart::Restart => {
+                log::error!(
+                    "{name}: The {task} task failed, restarting it in {}s: {error}",
+                    self.backoff.as_secs()
+                );
+                tokio::select! {
+                    _ = sleep(self.backoff) => {}
+                    _ = self.cancel.cancelled() => return Ok(false),
+                }
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                Ok(true)
+            }
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
@@ -0,0 +1,260 @@
+//!
+//! # Neolink Control
+//!
+//! This module serves a control socket on a unix socket so that local
+//! scripts and health probes can ask for the state of the cameras and send
+//! them commands without opening a network port
+//!
+//! It is enabled with a `[control]` table in the config
+//!
+//! ```toml
+//! [control]
+//! socket = "/run/neolink/control.sock"
+//! ```
+//!
+//! Each line sent is a json command and each reply is one line of json with
+//! `"ok": true` or `"ok": false` and an `"error"`
+//!
+//! - `{"command": "health"}` How many cameras there are and how many are connected
+//! - `{"command": "cameras"}` The cameras, if they are connected and if there is motion
+//! - `{"command": "status", "camera": "Door"}` The state of a camera
+//! - `{"command": "reboot", "camera": "Door"}` Reboot the camera
+//! - `{"command": "reconnect", "camera": "Door"}` Drop the connection and connect again
+//! - `{"command": "floodlight", "camera": "Door", "on": true, "duration": 180}`
+//!   Turn the floodlight on or off
+//! - `{"command": "siren", "camera": "Door"}` Sound the siren once
+//!
+//! such as `echo '{"command": "health"}' | socat - UNIX-CONNECT:/run/neolink/control.sock`
+//!
+use anyhow::{anyhow, Context};
+use log::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    task::JoinSet,
+};
+
+use crate::{
+    common::{MdState, NeoCamThreadState, NeoInstance, NeoReactor},
+    config::ControlConfig,
+    AnyResult,
+};
+
+/// The longest line that is read as a command
+const MAX_LINE: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case", deny_unknown_fields)]
+enum Command {
+    Health,
+    Cameras,
+    Status {
+        camera: String,
+    },
+    Reboot {
+        camera: String,
+    },
+    Reconnect {
+        camera: String,
+    },
+    Floodlight {
+        camera: String,
+        on: bool,
+        /// Seconds to stay on for
+        #[serde(default = "default_floodlight_duration")]
+        duration: u16,
+    },
+    Siren {
+        camera: String,
+    },
+}
+
+fn default_floodlight_duration() -> u16 {
+    180
+}
+
+/// Serve the control socket until the program stops
+pub(crate) async fn main(control_config: ControlConfig, reactor: NeoReactor) -> AnyResult<()> {
+    let path = &control_config.socket;
+    // A socket left by a neolink that did not stop cleanly stops the bind
+    if tokio::fs::symlink_metadata(path).await.is_ok() {
+        tokio::fs::remove_file(path)
+            .await
+            .with_context(|| format!("Failed to remove the old control socket {path:?}"))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind the control socket {path:?}"))?;
+    info!("Starting the control socket at {path:?}");
+
+    let mut set = JoinSet::new();
+    loop {
+        let (stream, _) = listener.accept().await?;
+        // Reap finished connections
+        while set.try_join_next().is_some() {}
+        let reactor = reactor.clone();
+        set.spawn(async move {
+            if let Err(e) = handle_connection(stream, reactor).await {
+                debug!("Control socket connection: {e:?}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(stream: UnixStream, reactor: NeoReactor) -> AnyResult<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if (&mut lines)
+            .take(MAX_LINE as u64 + 1)
+            .read_line(&mut line)
+            .await?
+            == 0
+        {
+            return Ok(());
+        }
+        if line.len() > MAX_LINE {
+            return Err(anyhow!("The command is too long"));
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => match run(command, &reactor).await {
+                Ok(mut reply) => {
+                    reply["ok"] = json!(true);
+                    reply
+                }
+                Err(e) => json!({ "ok": false, "error": format!("{e}") }),
+            },
+            Err(e) => json!({ "ok": false, "error": format!("Invalid command: {e}") }),
+        };
+        let mut reply = reply.to_string();
+        reply.push('\n');
+        write.write_all(reply.as_bytes()).await?;
+    }
+}
+
+async fn run(command: Command, reactor: &NeoReactor) -> AnyResult<Value> {
+    match command {
+        Command::Health => {
+            let mut cameras = 0;
+            let mut connected = 0;
+            for name in camera_names(reactor).await? {
+                cameras += 1;
+                if is_connected(&reactor.get(&name).await?).await {
+                    connected += 1;
+                }
+            }
+            Ok(json!({ "cameras": cameras, "connected": connected }))
+        }
+        Command::Cameras => {
+            let mut list = vec![];
+            for name in camera_names(reactor).await? {
+                let camera = reactor.get(&name).await?;
+                list.push(json!({
+                    "name": name,
+                    "connected": is_connected(&camera).await,
+                    "motion": motion(&camera).await?,
+                }));
+            }
+            Ok(json!({ "cameras": list }))
+        }
+        Command::Status { camera: name } => {
+            let camera = camera(reactor, &name).await?;
+            let streams = camera
+                .config()
+                .await?
+                .borrow()
+                .stream_kinds()
+                .iter()
+                .map(|kind| format!("{kind:?}").to_lowercase())
+                .collect::<Vec<_>>();
+            Ok(json!({
+                "name": name,
+                "connected": is_connected(&camera).await,
+                "motion": motion(&camera).await?,
+                "streams": streams,
+            }))
+        }
+        Command::Reboot { camera: name } => {
+            camera(reactor, &name)
+                .await?
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.reboot().await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await?;
+            Ok(json!({}))
+        }
+        Command::Reconnect { camera: name } => {
+            camera(reactor, &name).await?.reconnect().await?;
+            Ok(json!({}))
+        }
+        Command::Floodlight {
+            camera: name,
+            on,
+            duration,
+        } => {
+            camera(reactor, &name)
+                .await?
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.set_floodlight_manual(on, duration).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await?;
+            Ok(json!({}))
+        }
+        Command::Siren { camera: name } => {
+            camera(reactor, &name)
+                .await?
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.siren().await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await?;
+            Ok(json!({}))
+        }
+    }
+}
+
+async fn camera_names(reactor: &NeoReactor) -> AnyResult<Vec<String>> {
+    Ok(reactor
+        .config()
+        .await?
+        .borrow()
+        .cameras
+        .iter()
+        .filter(|cam| cam.enabled)
+        .map(|cam| cam.name.clone())
+        .collect())
+}
+
+async fn camera(reactor: &NeoReactor, name: &str) -> AnyResult<NeoInstance> {
+    if !camera_names(reactor).await?.iter().any(|n| n == name) {
+        return Err(anyhow!("No such camera {name}"));
+    }
+    reactor.get(name).await
+}
+
+async fn is_connected(camera: &NeoInstance) -> bool {
+    matches!(camera.get_state().await, Ok(NeoCamThreadState::Connected))
+}
+
+/// If there is motion, `None` when the camera has not said yet
+async fn motion(camera: &NeoInstance) -> AnyResult<Option<bool>> {
+    Ok(match *camera.motion().await?.borrow() {
+        MdState::Start(..) => Some(true),
+        MdState::Stop(..) => Some(false),
+        MdState::Unknown => None,
+    })
+}
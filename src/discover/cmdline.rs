@@ -0,0 +1,24 @@
+use clap::Parser;
+
+/// The discover command will search the local network for cameras
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Also look for the camera with this UID, can be given more than once
+    #[arg(long)]
+    pub uid: Vec<String>,
+    /// Seconds to wait for replies to the broadcast
+    #[arg(short, long, default_value_t = 3)]
+    pub timeout: u64,
+    /// Username used to log in and read the UID, model and channels
+    #[arg(short, long, default_value = "admin")]
+    pub username: String,
+    /// Password used to log in and read the UID, model and channels
+    #[arg(short, long)]
+    pub password: Option<String>,
+    /// Print the cameras as json instead of a table
+    #[arg(long, conflicts_with = "config_stanzas")]
+    pub json: bool,
+    /// Print a `[[cameras]]` config block for each camera
+    #[arg(long)]
+    pub config_stanzas: bool,
+}
@@ -0,0 +1,179 @@
+///
+/// # Neolink Discover
+///
+/// This module handles the discover subcommand
+///
+/// The subcommand broadcasts on the local network and prints the cameras
+/// that reply. Cameras that ignore the broadcast can be found by their UID
+/// instead. It does not need a config file
+///
+/// # Usage
+///
+/// ```bash
+/// neolink discover
+/// neolink discover --uid=95270000YGAKNWKJ --password=secret --json
+/// neolink discover --password=secret --config-stanzas >> config.toml
+/// ```
+///
+use anyhow::Result;
+use neolink_core::bc_protocol::{
+    locate_uid, search_lan, BcCamera, BcCameraOpt, ConnectionProtocol, Credentials,
+    DiscoveryMethods,
+};
+use serde::Serialize;
+use std::net::IpAddr;
+use tokio::time::{timeout, Duration};
+
+mod cmdline;
+
+use crate::AnyResult;
+pub(crate) use cmdline::Opt;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct FoundCamera {
+    pub(crate) ip: IpAddr,
+    pub(crate) uid: Option<String>,
+    pub(crate) model: Option<String>,
+    pub(crate) channels: Option<u32>,
+}
+
+/// Entry point for the discover subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt) -> Result<()> {
+    let mut found = search_lan(Duration::from_secs(opt.timeout))
+        .await?
+        .into_iter()
+        .map(|ip| FoundCamera {
+            ip,
+            uid: None,
+            model: None,
+            channels: None,
+        })
+        .collect::<Vec<_>>();
+
+    for uid in opt.uid.iter() {
+        match locate_uid(uid).await {
+            Ok(ip) => match found.iter_mut().find(|cam| cam.ip == ip) {
+                Some(cam) => cam.uid = Some(uid.clone()),
+                None => found.push(FoundCamera {
+                    ip,
+                    uid: Some(uid.clone()),
+                    model: None,
+                    channels: None,
+                }),
+            },
+            Err(e) => log::warn!("Could not find {uid} on the local network: {e}"),
+        }
+    }
+
+    for cam in found.iter_mut() {
+        if let Err(e) = fill_details(cam, &opt.username, &opt.password).await {
+            log::info!("{}: Could not log in for details: {e}", cam.ip);
+        }
+    }
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&found)?);
+    } else if opt.config_stanzas {
+        print_stanzas(&found, &opt);
+    } else {
+        print_table(&found);
+    }
+
+    Ok(())
+}
+
+/// Log in to the camera to read the details that the broadcast does not give
+pub(crate) async fn fill_details(
+    cam: &mut FoundCamera,
+    username: &str,
+    password: &Option<String>,
+) -> AnyResult<()> {
+    let options = BcCameraOpt {
+        name: cam.ip.to_string(),
+        channel_id: 0,
+        addrs: vec![cam.ip],
+        port: None,
+        uid: None,
+        protocol: ConnectionProtocol::Tcp,
+        discovery: DiscoveryMethods::None,
+        credentials: Credentials {
+            username: username.to_string(),
+            password: password.clone(),
+        },
+        debug: false,
+        max_discovery_retries: 0,
+    };
+    timeout(Duration::from_secs(10), async {
+        let camera = BcCamera::new(&options).await?;
+        camera.login().await?;
+        if cam.uid.is_none() {
+            cam.uid = camera.uid().await.ok();
+        }
+        cam.model = camera.version().await.ok().and_then(|v| v.model);
+        cam.channels = camera.get_support().await.ok().and_then(|s| s.channel_num);
+        let _ = camera.logout().await;
+        AnyResult::Ok(())
+    })
+    .await?
+}
+
+fn print_table(found: &[FoundCamera]) {
+    let rows = found
+        .iter()
+        .map(|cam| {
+            [
+                cam.ip.to_string(),
+                cam.uid.clone().unwrap_or_else(|| "-".to_string()),
+                cam.model.clone().unwrap_or_else(|| "-".to_string()),
+                cam.channels
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["IP", "UID", "MODEL", "CHANNELS"].map(String::from);
+    let widths = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .chain(std::iter::once(&header))
+                .map(|row| row[i].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, &width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+fn print_stanzas(found: &[FoundCamera], opt: &Opt) {
+    for (i, cam) in found.iter().enumerate() {
+        // An NVR needs one camera per channel
+        for channel in 0..cam.channels.unwrap_or(1).max(1) {
+            let name = match cam.channels {
+                Some(n) if n > 1 => format!("Camera{:02}_{channel:02}", i + 1),
+                _ => format!("Camera{:02}", i + 1),
+            };
+            println!("[[cameras]]");
+            println!("name = \"{name}\"");
+            println!("username = \"{}\"", opt.username);
+            println!("password = \"{}\"", opt.password.as_deref().unwrap_or(""));
+            println!("address = \"{}:9000\"", cam.ip);
+            if let Some(uid) = cam.uid.as_ref() {
+                println!("uid = \"{uid}\"");
+            }
+            if channel > 0 {
+                println!("channel_id = {channel}");
+            }
+            println!();
+        }
+    }
+}
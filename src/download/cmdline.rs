@@ -0,0 +1,28 @@
+use clap::Parser;
+use neolink_core::bc::xml::RecordTime;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::utils::parse_record_time;
+
+/// The download command will save recordings from the camera's SD card as mp4s
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera to download from. Must be a name in the config
+    pub camera: String,
+    /// Start of the recordings to download as `YYYY-MM-DD HH:MM[:SS]` in the camera's time
+    #[arg(long, value_parser = parse_record_time)]
+    pub start: RecordTime,
+    /// End of the recordings to download as `YYYY-MM-DD HH:MM[:SS]` in the camera's time
+    #[arg(long, value_parser = parse_record_time)]
+    pub end: RecordTime,
+    /// Only download the recordings that were triggered by motion
+    #[arg(long)]
+    pub motion_only: bool,
+    /// The directory to save the recordings in
+    #[arg(short, long, default_value = ".", value_parser = PathBuf::from_str)]
+    pub dir: PathBuf,
+    /// The stream of the recordings
+    #[arg(short, long, default_value = "main", value_parser = ["main", "sub"])]
+    pub stream: String,
+}
@@ -0,0 +1,133 @@
+///
+/// # Neolink Download
+///
+/// This module handles the download subcommand
+///
+/// The recordings on the camera's SD card between two times are listed and
+/// each is downloaded and muxed into an mp4.
+///
+/// The raw download is kept in a `.part` file until it is complete. If
+/// neolink is stopped the next run continues from where it got to, recordings
+/// that already have an mp4 are skipped.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink download --config=config.toml CameraName --start="2023-05-18 14:00" --end="2023-05-18 15:00" --dir=./out
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::StreamKind;
+use std::path::Path;
+use tokio::{fs, io::AsyncWriteExt};
+
+mod cmdline;
+mod mp4;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the download subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let stream = match opt.stream.as_str() {
+        "sub" => StreamKind::Sub,
+        _ => StreamKind::Main,
+    };
+    let record_type = if opt.motion_only {
+        "md, pir"
+    } else {
+        "manual, sched, io, md, pir"
+    };
+    let (start, end) = (opt.start, opt.end);
+
+    let files = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let files = cam
+                    .get_file_list(stream, start, end, record_type)
+                    .await
+                    .context("Could not list the recordings on the camera")?;
+                AnyResult::Ok(files)
+            })
+        })
+        .await?;
+    log::info!("{}: Found {} recordings", opt.camera, files.len());
+
+    fs::create_dir_all(&opt.dir).await?;
+    for name in files.into_iter().filter_map(|file| file.name) {
+        // Names can include the folder on the SD card
+        let stem = name
+            .rsplit('/')
+            .next()
+            .unwrap_or(&name)
+            .trim_end_matches(".mp4")
+            .to_string();
+        let mp4 = opt.dir.join(format!("{stem}.mp4"));
+        if fs::try_exists(&mp4).await? {
+            log::info!(
+                "{}: Skipping {name} as it is already downloaded",
+                opt.camera
+            );
+            continue;
+        }
+        let part = opt.dir.join(format!("{stem}.bcmedia.part"));
+
+        log::info!("{}: Downloading {name}", opt.camera);
+        download(&camera, &name, &part).await?;
+
+        // Mux to a temporary name so that an interrupted mux is not mistaken for a finished one
+        let muxing = opt.dir.join(format!("{stem}.mp4.part"));
+        let (raw, out) = (part.clone(), muxing.clone());
+        tokio::task::spawn_blocking(move || mp4::bcmedia_to_mp4(&raw, &out))
+            .await?
+            .with_context(|| format!("Could not create the mp4 of {name}"))?;
+        fs::rename(&muxing, &mp4).await?;
+        fs::remove_file(&part).await?;
+        log::info!("{}: Saved {}", opt.camera, mp4.display());
+    }
+
+    Ok(())
+}
+
+/// Download the raw recording to `part`, continuing from what is already in it
+///
+/// The camera always sends the file from the start so what is already on
+/// disk is skipped rather than downloaded twice
+async fn download(camera: &NeoInstance, name: &str, part: &Path) -> Result<()> {
+    let name = name.to_string();
+    let part = part.to_path_buf();
+    camera
+        .run_task(|cam| {
+            let name = name.clone();
+            let part = part.clone();
+            Box::pin(async move {
+                let mut skip = match fs::metadata(&part).await {
+                    Ok(meta) => meta.len() as usize,
+                    Err(_) => 0,
+                };
+                if skip > 0 {
+                    log::info!("Resuming {name} after {skip} bytes");
+                }
+                let mut out = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&part)
+                    .await?;
+                let mut download = cam.download_file(&name).await?;
+                while let Some(data) = download.get_data().await? {
+                    let skipped = skip.min(data.len());
+                    skip -= skipped;
+                    out.write_all(&data[skipped..]).await?;
+                }
+                out.flush().await?;
+                AnyResult::Ok(())
+            })
+        })
+        .await
+}
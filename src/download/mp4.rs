@@ -0,0 +1,145 @@
+//! Muxes the raw BcMedia of a downloaded recording into an mp4
+use anyhow::{anyhow, Context, Result};
+use bytes::BytesMut;
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use neolink_core::bcmedia::{codex::BcMediaCodex, model::*};
+use std::{fs::File, io::Read, path::Path};
+use tokio_util::codec::Decoder;
+
+/// Mux the video of the BcMedia file into an mp4
+///
+/// This blocks until gstreamer has written the whole file
+pub(super) fn bcmedia_to_mp4(raw: &Path, mp4: &Path) -> Result<()> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+    let mut file = File::open(raw)?;
+    let mut codec = BcMediaCodex::new(false);
+    let mut buf = BytesMut::new();
+    let mut chunk = vec![0; 64 * 1024];
+    let mut muxer: Option<Muxer> = None;
+
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+        while let Some(media) = codec.decode(&mut buf)? {
+            let (video_type, microseconds, data) = match media {
+                BcMedia::Iframe(BcMediaIframe {
+                    video_type,
+                    microseconds,
+                    data,
+                    ..
+                }) => (video_type, microseconds, data),
+                BcMedia::Pframe(BcMediaPframe {
+                    video_type,
+                    microseconds,
+                    data,
+                }) => (video_type, microseconds, data),
+                _ => continue,
+            };
+            let muxer = match muxer.as_mut() {
+                Some(muxer) => muxer,
+                // The first frame of a recording is always a keyframe
+                None => muxer.insert(Muxer::new(video_type, microseconds, mp4)?),
+            };
+            muxer.push(microseconds, data)?;
+        }
+    }
+
+    muxer
+        .ok_or_else(|| anyhow!("The recording has no video"))?
+        .finish()
+}
+
+struct Muxer {
+    pipeline: Pipeline,
+    source: AppSrc,
+    start: u32,
+}
+
+impl Muxer {
+    fn new(video_type: VideoType, start: u32, mp4: &Path) -> Result<Self> {
+        let parser = match video_type {
+            VideoType::H264 => "h264parse",
+            VideoType::H265 => "h265parse",
+        };
+        let launch_str = format!(
+            "appsrc name=thesource format=time block=true \
+            ! {parser} \
+            ! mp4mux \
+            ! filesink location=\"{}\"",
+            mp4.display()
+        );
+        let pipeline = launch_full(&launch_str, None, ParseFlags::empty()).context(
+            "Unable to load gstreamer pipeline ensure all gstramer plugins are installed",
+        )?;
+        let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+        let source = pipeline
+            .by_name("thesource")
+            .and_then(|source| source.dynamic_cast::<AppSrc>().ok())
+            .ok_or_else(|| {
+                anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins")
+            })?;
+        pipeline.set_state(State::Playing)?;
+        Ok(Self {
+            pipeline,
+            source,
+            start,
+        })
+    }
+
+    fn push(&self, microseconds: u32, data: Vec<u8>) -> Result<()> {
+        let mut buf = gstreamer::Buffer::from_slice(data);
+        {
+            let buf = buf
+                .get_mut()
+                .ok_or_else(|| anyhow!("Could not write to the gstreamer buffer"))?;
+            let ts = ClockTime::from_useconds(microseconds.wrapping_sub(self.start) as u64);
+            buf.set_pts(ts);
+            buf.set_dts(ts);
+        }
+        self.source
+            .push_buffer(buf)
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        self.source
+            .end_of_stream()
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        let bus = self
+            .pipeline
+            .bus()
+            .expect("Pipeline without bus. Shouldn't happen!");
+        let mut res = Ok(());
+        for msg in bus.iter_timed(ClockTime::NONE) {
+            match msg.view() {
+                MessageView::Eos(..) => break,
+                MessageView::Error(err) => {
+                    res = Err(anyhow!(
+                        "Error from gstreamer while writing the mp4: {err:?}"
+                    ));
+                    break;
+                }
+                _ => (),
+            }
+        }
+        res
+    }
+}
+
+impl Drop for Muxer {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipeline.set_state(State::Null) {
+            log::warn!("Error in gstreamer when setting state to Null: {e:?}");
+        }
+    }
+}
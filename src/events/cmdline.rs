@@ -0,0 +1,23 @@
+use clap::Parser;
+use std::time::Duration;
+
+use crate::utils::parse_duration;
+
+/// The events command prints the events kept in the events database
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Only print the events of this camera
+    pub camera: Option<String>,
+    /// How far back to print such as `30m`, `24h` or `7d`
+    #[arg(short, long, default_value = "24h", value_parser = parse_duration)]
+    pub since: Duration,
+    /// Only print the events of this kind
+    #[arg(short, long, value_parser = ["motion_start", "motion_stop", "doorbell", "connected", "disconnected", "battery_low"])]
+    pub kind: Option<String>,
+    /// The most events to print, the newest are kept
+    #[arg(short, long, default_value = "1000")]
+    pub limit: usize,
+    /// The output format
+    #[arg(short, long, default_value = "human", value_parser = ["human", "json"])]
+    pub format: String,
+}
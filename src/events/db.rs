@@ -0,0 +1,133 @@
+//! The SQLite database that the events are kept in
+use anyhow::Context;
+use rusqlite::{params, params_from_iter, types::Value, Connection};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{now, Event, EventQuery};
+use crate::AnyResult;
+
+#[derive(Clone)]
+pub(crate) struct EventDb(Arc<Mutex<Connection>>);
+
+impl EventDb {
+    /// Open the database, creating it if it does not exist
+    pub(crate) fn open(path: &Path) -> AnyResult<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open the events database {}", path.display()))?;
+        // The http server reads while the events are written
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            CREATE TABLE IF NOT EXISTS events (
+                id INTEGER PRIMARY KEY,
+                time INTEGER NOT NULL,
+                camera TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                detections TEXT NOT NULL DEFAULT ''
+            );
+            CREATE INDEX IF NOT EXISTS events_time ON events (time);
+            CREATE INDEX IF NOT EXISTS events_camera_time ON events (camera, time);",
+        )
+        .with_context(|| format!("Failed to set up the events database {}", path.display()))?;
+        Ok(Self(Arc::new(Mutex::new(conn))))
+    }
+
+    pub(crate) async fn insert(&self, event: Event) -> AnyResult<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO events (time, camera, kind, detections) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    event.time as i64,
+                    event.camera,
+                    event.kind,
+                    event.detections.join(",")
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The events that match the query, newest first
+    pub(crate) async fn query(&self, query: EventQuery) -> AnyResult<Vec<Event>> {
+        self.with_conn(move |conn| {
+            let mut sql = "SELECT time, camera, kind, detections FROM events WHERE 1".to_string();
+            let mut values = vec![];
+            if let Some(camera) = query.camera {
+                sql.push_str(" AND camera = ?");
+                values.push(Value::Text(camera));
+            }
+            if let Some(kind) = query.kind {
+                sql.push_str(" AND kind = ?");
+                values.push(Value::Text(kind));
+            }
+            if let Some(from) = query.from {
+                sql.push_str(" AND time >= ?");
+                values.push(Value::Integer(from as i64));
+            }
+            if let Some(to) = query.to {
+                sql.push_str(" AND time <= ?");
+                values.push(Value::Integer(to as i64));
+            }
+            sql.push_str(" ORDER BY time DESC, id DESC LIMIT ?");
+            values.push(Value::Integer(query.limit as i64));
+
+            let mut statement = conn.prepare(&sql)?;
+            let rows = statement.query_map(params_from_iter(values), |row| {
+                let detections: String = row.get(3)?;
+                Ok(Event {
+                    time: row.get::<_, i64>(0)? as u64,
+                    camera: row.get(1)?,
+                    kind: row.get(2)?,
+                    detections: detections
+                        .split(',')
+                        .filter(|detection| !detection.is_empty())
+                        .map(|detection| detection.to_string())
+                        .collect(),
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Remove the events older than `max_age` and the oldest above `max_events`
+    ///
+    /// Returns how many were removed
+    pub(crate) async fn prune(&self, max_age: Duration, max_events: u64) -> AnyResult<usize> {
+        self.with_conn(move |conn| {
+            let old = conn.execute(
+                "DELETE FROM events WHERE time < ?1",
+                params![now().saturating_sub(max_age.as_secs()) as i64],
+            )?;
+            let over = conn.execute(
+                "DELETE FROM events WHERE id <= \
+                (SELECT id FROM events ORDER BY id DESC LIMIT 1 OFFSET ?1)",
+                params![max_events as i64],
+            )?;
+            Ok(old + over)
+        })
+        .await
+    }
+
+    /// Run on the connection away from the async threads
+    async fn with_conn<T, F>(&self, f: F) -> AnyResult<T>
+    where
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.0.clone();
+        Ok(tokio::task::spawn_blocking(move || f(&conn.lock().unwrap())).await??)
+    }
+}
@@ -0,0 +1,200 @@
+///
+/// # Neolink Events
+///
+/// This module keeps the events of the cameras in an SQLite database so that
+/// their history survives restarts, and handles the events subcommand that
+/// prints them. The database needs neolink to be built with the events feature
+///
+/// The database is set in the `[events]` table, while the rtsp server runs the
+/// motion, doorbell and connection events of the cameras are written to it
+///
+/// ```toml
+/// [events]
+/// database = "/var/lib/neolink/events.db"
+/// retention_days = 30
+/// max_events = 100000
+/// ```
+///
+/// # Usage
+///
+/// ```bash
+/// neolink events --config=config.toml --since 24h
+/// neolink events --config=config.toml CameraName --kind motion_start --format json
+/// ```
+///
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "events")]
+mod cmdline;
+#[cfg(feature = "events")]
+mod db;
+mod watch;
+
+// The database is only in the build with the events feature
+#[cfg(feature = "events")]
+use {
+    crate::{
+        common::NeoReactor,
+        config::{Config, EventsConfig},
+        utils::utc,
+        AnyResult,
+    },
+    anyhow::{Context, Result},
+    log::*,
+    tokio::{
+        sync::mpsc::unbounded_channel,
+        time::{interval, Duration},
+    },
+    tokio_util::sync::CancellationToken,
+};
+
+#[cfg(feature = "events")]
+pub(crate) use cmdline::Opt;
+#[cfg(feature = "events")]
+pub(crate) use db::EventDb;
+pub(crate) use watch::watch;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Event {
+    /// Unix time in seconds
+    pub(crate) time: u64,
+    pub(crate) camera: String,
+    /// `motion_start`, `motion_stop`, `doorbell`, `connected`, `disconnected` or
+    /// `battery_low`
+    pub(crate) kind: String,
+    /// What was detected for `motion_start`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) detections: Vec<String>,
+}
+
+impl Event {
+    pub(crate) fn new(camera: &str, kind: &str) -> Self {
+        Self {
+            time: now(),
+            camera: camera.to_string(),
+            kind: kind.to_string(),
+            detections: vec![],
+        }
+    }
+}
+
+/// Which events to look up
+#[derive(Debug, Clone)]
+pub(crate) struct EventQuery {
+    pub(crate) camera: Option<String>,
+    pub(crate) kind: Option<String>,
+    /// Unix time in seconds of the oldest event
+    pub(crate) from: Option<u64>,
+    /// Unix time in seconds of the newest event
+    pub(crate) to: Option<u64>,
+    /// The most events to return, the newest are kept
+    pub(crate) limit: usize,
+}
+
+impl EventQuery {
+    pub(crate) fn matches(&self, event: &Event) -> bool {
+        self.camera
+            .as_ref()
+            .is_none_or(|camera| &event.camera == camera)
+            && self.kind.as_ref().is_none_or(|kind| &event.kind == kind)
+            && self.from.is_none_or(|from| event.time >= from)
+            && self.to.is_none_or(|to| event.time <= to)
+    }
+}
+
+#[cfg(feature = "events")]
+/// Entry point for the events subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, config: Config) -> Result<()> {
+    let events_config = config
+        .events
+        .context("There is no [events] database in the config")?;
+    let db = EventDb::open(&events_config.database)?;
+    let mut events = db
+        .query(EventQuery {
+            camera: opt.camera,
+            kind: opt.kind,
+            from: Some(now().saturating_sub(opt.since.as_secs())),
+            to: None,
+            limit: opt.limit,
+        })
+        .await?;
+    // Oldest first like a log
+    events.reverse();
+
+    match opt.format.as_str() {
+        "json" => println!("{}", serde_json::to_string_pretty(&events)?),
+        _ => {
+            for event in events.iter() {
+                let (year, month, day, hour, minute, second) =
+                    utc(UNIX_EPOCH + Duration::from_secs(event.time));
+                let detections = if event.detections.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", event.detections.join(", "))
+                };
+                println!(
+                    "{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02} UTC {}: {}{detections}",
+                    event.camera, event.kind
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "events")]
+/// Write the events of the cameras to the database until cancelled
+///
+/// The events past the retention limits are removed every hour
+pub(crate) async fn record(
+    events_config: EventsConfig,
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let db = EventDb::open(&events_config.database)?;
+    info!("Keeping the events in {}", events_config.database.display());
+
+    let (tx, mut rx) = unbounded_channel();
+    let watching = async {
+        tokio::select! {
+            v = watch(reactor, cancel, move |event| {
+                let _ = tx.send(event);
+            }) => v,
+            v = prune(&db, &events_config) => v,
+        }
+    };
+    let storing = async {
+        while let Some(event) = rx.recv().await {
+            if let Err(e) = db.insert(event).await {
+                warn!("Failed to write an event: {e:?}");
+            }
+        }
+    };
+    let (watched, _) = tokio::join!(watching, storing);
+    watched
+}
+
+#[cfg(feature = "events")]
+async fn prune(db: &EventDb, events_config: &EventsConfig) -> AnyResult<()> {
+    let max_age = Duration::from_secs(events_config.retention_days * 24 * 60 * 60);
+    let mut check = interval(Duration::from_secs(60 * 60));
+    loop {
+        check.tick().await;
+        match db.prune(max_age, events_config.max_events).await {
+            Ok(0) => {}
+            Ok(removed) => debug!("Removed {removed} old events"),
+            Err(e) => warn!("Failed to remove the old events: {e:?}"),
+        }
+    }
+}
+
+/// Unix time in seconds
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
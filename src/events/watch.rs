@@ -0,0 +1,146 @@
+//! Watches the cameras for their events
+//!
+//! The motion of each camera is watched, its connection is checked every few
+//! seconds and its battery every few minutes
+use std::collections::{HashMap, HashSet};
+use tokio::{
+    task::JoinSet,
+    time::{interval, timeout, Duration},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::Event;
+use crate::{
+    common::{MdState, NeoCamThreadState, NeoInstance, NeoReactor},
+    AnyResult,
+};
+
+/// Watch the cameras until cancelled, following the cameras in the config
+///
+/// Each event is given to `on_event` as it happens
+pub(crate) async fn watch<F>(
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+    on_event: F,
+) -> AnyResult<()>
+where
+    F: Fn(Event) + Clone + Send + Sync + 'static,
+{
+    let mut config = reactor.config().await?;
+    let mut running: HashMap<String, CancellationToken> = HashMap::new();
+    let mut set = JoinSet::new();
+    loop {
+        let names = config
+            .borrow_and_update()
+            .cameras
+            .iter()
+            .filter(|cam| cam.enabled)
+            .map(|cam| cam.name.clone())
+            .collect::<HashSet<_>>();
+        running.retain(|name, token| {
+            if names.contains(name) {
+                true
+            } else {
+                token.cancel();
+                false
+            }
+        });
+        for name in names.into_iter() {
+            if running.contains_key(&name) {
+                continue;
+            }
+            let token = cancel.child_token();
+            running.insert(name.clone(), token.clone());
+            let on_event = on_event.clone();
+            let thread_reactor = reactor.clone();
+            set.spawn(async move {
+                tokio::select! {
+                    _ = token.cancelled() => AnyResult::Ok(()),
+                    v = async {
+                        let camera = thread_reactor.get(&name).await?;
+                        watch_camera(&camera, &name, &on_event).await
+                    } => v,
+                }
+            });
+        }
+
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            v = config.changed() => v?,
+            Some(joined) = set.join_next() => {
+                if let Ok(Err(e)) = joined {
+                    log::debug!("Stopped watching the events of a camera: {e:?}");
+                }
+            }
+        }
+    }
+    set.shutdown().await;
+    Ok(())
+}
+
+async fn watch_camera<F>(camera: &NeoInstance, name: &str, on_event: &F) -> AnyResult<()>
+where
+    F: Fn(Event),
+{
+    let mut motion = camera.motion().await?;
+    let mut connected = None;
+    let mut check = interval(Duration::from_secs(5));
+    let mut battery_check = interval(Duration::from_secs(10 * 60));
+    let mut has_battery = true;
+    let mut low_battery = false;
+    loop {
+        tokio::select! {
+            v = motion.changed() => {
+                v?;
+                let events = match &*motion.borrow_and_update() {
+                    MdState::Start(_, details) => {
+                        let mut events = vec![Event {
+                            detections: details.detections.clone(),
+                            ..Event::new(name, "motion_start")
+                        }];
+                        // Doorbell presses arrive as a visitor detection
+                        if details.detections.iter().any(|d| d == "visitor") {
+                            events.push(Event::new(name, "doorbell"));
+                        }
+                        events
+                    }
+                    MdState::Stop(_) => vec![Event::new(name, "motion_stop")],
+                    MdState::Unknown => continue,
+                };
+                events.into_iter().for_each(on_event);
+            }
+            _ = check.tick() => {
+                let now = matches!(camera.get_state().await?, NeoCamThreadState::Connected);
+                if connected != Some(now) {
+                    on_event(Event::new(name, if now { "connected" } else { "disconnected" }));
+                    connected = Some(now);
+                }
+            }
+            _ = battery_check.tick(), if has_battery => {
+                let battery = timeout(
+                    Duration::from_secs(30),
+                    camera.run_passive_task(|cam| {
+                        Box::pin(async move { AnyResult::Ok(cam.battery_info().await?) })
+                    }),
+                )
+                .await;
+                match battery {
+                    Ok(Ok(battery)) => {
+                        let low = battery.low_power != 0;
+                        if low && !low_battery {
+                            on_event(Event::new(name, "battery_low"));
+                        }
+                        low_battery = low;
+                    }
+                    Ok(Err(e)) => match e.downcast_ref::<neolink_core::Error>() {
+                        Some(neolink_core::Error::CameraServiceUnavailable { .. }) => {
+                            has_battery = false;
+                        }
+                        _ => log::debug!("{name}: Could not check the battery: {e:?}"),
+                    },
+                    Err(_) => log::debug!("{name}: Timed out checking the battery"),
+                }
+            }
+        }
+    }
+}
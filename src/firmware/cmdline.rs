@@ -0,0 +1,16 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+/// The firmware command will print the firmware version of the camera or upgrade it
+#[derive(Parser, Debug)]
+#[command(group(clap::ArgGroup::new("action").required(true).args(["check", "upgrade"])))]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Print the installed firmware and hardware versions
+    #[arg(long)]
+    pub check: bool,
+    /// Upload this firmware file (`.pak`) to the camera
+    #[arg(long, value_name = "FILE")]
+    pub upgrade: Option<PathBuf>,
+}
@@ -0,0 +1,80 @@
+///
+/// # Neolink Firmware
+///
+/// This module handles the firmware subcommand
+///
+/// The subcommand prints the installed firmware or uploads a new firmware
+/// file. The progress of the upload is printed to stderr, the camera reboots
+/// once it has written the new firmware
+///
+/// # Usage
+///
+/// ```bash
+/// neolink firmware --config=config.toml CameraName --check
+/// neolink firmware --config=config.toml CameraName --upgrade=IPC_523128M5MP.pak
+/// ```
+///
+use anyhow::{Context, Result};
+use std::{io::Write, sync::Arc};
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the firmware subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    if opt.check {
+        let version = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.version()
+                        .await
+                        .context("Could not get the version of the camera")
+                })
+            })
+            .await?;
+        println!("Model: {}", version.model.as_deref().unwrap_or("unknown"));
+        println!("Firmware: {}", version.firmwareVersion);
+        println!("Hardware: {}", version.hardwareVersion);
+        println!("Build: {}", version.buildDay);
+    } else if let Some(path) = opt.upgrade {
+        let data = Arc::new(
+            tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("Could not read {:?}", path))?,
+        );
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        camera
+            .run_task(|cam| {
+                let data = data.clone();
+                let file_name = file_name.clone();
+                Box::pin(async move {
+                    let mut last_percent = None;
+                    cam.upgrade_firmware(&file_name, &data, |sent, total| {
+                        let percent = sent * 100 / total.max(1);
+                        if last_percent != Some(percent) {
+                            last_percent = Some(percent);
+                            eprint!("\rUploading {file_name}: {percent}%");
+                            let _ = std::io::stderr().flush();
+                        }
+                    })
+                    .await
+                    .context("Could not upgrade the firmware of the camera")?;
+                    eprintln!();
+                    Ok(())
+                })
+            })
+            .await?;
+        println!("The firmware was uploaded, the camera will now install it and reboot");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,334 @@
+//!
+//! # Neolink gRPC
+//!
+//! This module serves the controls of the cameras over gRPC so that other
+//! programs can run neolink as a sidecar with typed clients
+//!
+//! It is built with the `grpc` feature and enabled with a `[grpc]` table in
+//! the config
+//!
+//! ```toml
+//! [grpc]
+//! bind = "0.0.0.0"
+//! port = 50051
+//! tokens = ["a-long-secret"]
+//! ```
+//!
+//! The service is in `proto/neolink.proto`. It lists the cameras, streams
+//! their events, takes snapshots, moves PTZ cameras and gets and sets the
+//! config. With `tokens` each call needs `authorization: Bearer <token>` in
+//! its metadata
+//!
+use anyhow::Context;
+use log::*;
+use neolink_core::bc_protocol::Direction as BcDirection;
+use std::{convert::TryFrom, net::SocketAddr, pin::Pin};
+use tokio::{
+    sync::mpsc::unbounded_channel,
+    time::{sleep, Duration},
+};
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream};
+use tokio_util::sync::CancellationToken;
+use tonic::{transport::Server, Request, Response, Status};
+use validator::Validate;
+
+use crate::{
+    common::{NeoCamThreadState, NeoInstance, NeoReactor},
+    config::{Config, GrpcConfig},
+    events::watch,
+    AnyResult,
+};
+
+mod proto {
+    tonic::include_proto!("neolink");
+}
+
+use proto::{
+    neolink_server::{Neolink, NeolinkServer},
+    ptz_request::Action,
+    Camera, Direction, Event, GetConfigRequest, GetConfigResponse, ListCamerasRequest,
+    ListCamerasResponse, PtzRequest, PtzResponse, SetConfigRequest, SetConfigResponse,
+    SnapshotRequest, SnapshotResponse, WatchEventsRequest,
+};
+
+/// Serve the gRPC api until cancelled
+// The interceptor has to return the `Status` of tonic
+#[allow(clippy::result_large_err)]
+pub(crate) async fn main(
+    grpc_config: GrpcConfig,
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let addr: SocketAddr = format!("{}:{}", grpc_config.bind_addr, grpc_config.port)
+        .parse()
+        .with_context(|| {
+            format!(
+                "Invalid gRPC address {}:{}",
+                grpc_config.bind_addr, grpc_config.port
+            )
+        })?;
+    info!("Starting gRPC Server at {addr}");
+
+    let service = Service {
+        reactor,
+        cancel: cancel.clone(),
+    };
+    let tokens = grpc_config.tokens;
+    let service = NeolinkServer::with_interceptor(service, move |request: Request<()>| {
+        if tokens.is_empty() {
+            return Ok(request);
+        }
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if tokens.iter().any(|t| t == token.trim()) => Ok(request),
+            Some(_) => Err(Status::unauthenticated("Invalid token")),
+            None => Err(Status::unauthenticated("No token")),
+        }
+    });
+    Server::builder()
+        .add_service(service)
+        .serve_with_shutdown(addr, cancel.cancelled())
+        .await
+        .with_context(|| format!("Failed to serve gRPC at {addr}"))?;
+    Ok(())
+}
+
+struct Service {
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+}
+
+impl Service {
+    async fn camera(&self, name: &str) -> Result<NeoInstance, Status> {
+        let exists = self
+            .reactor
+            .config()
+            .await
+            .map_err(internal)?
+            .borrow()
+            .cameras
+            .iter()
+            .any(|cam| cam.enabled && cam.name == name);
+        if !exists {
+            return Err(Status::not_found(format!("No such camera {name}")));
+        }
+        self.reactor.get(name).await.map_err(internal)
+    }
+}
+
+#[tonic::async_trait]
+impl Neolink for Service {
+    async fn list_cameras(
+        &self,
+        _request: Request<ListCamerasRequest>,
+    ) -> Result<Response<ListCamerasResponse>, Status> {
+        let config = self
+            .reactor
+            .config()
+            .await
+            .map_err(internal)?
+            .borrow()
+            .clone();
+        let mut cameras = vec![];
+        for camera_config in config.cameras.iter().filter(|cam| cam.enabled) {
+            let camera = self
+                .reactor
+                .get(&camera_config.name)
+                .await
+                .map_err(internal)?;
+            cameras.push(Camera {
+                name: camera_config.name.clone(),
+                connected: matches!(camera.get_state().await, Ok(NeoCamThreadState::Connected)),
+                streams: camera_config
+                    .stream_kinds()
+                    .iter()
+                    .map(|kind| format!("{kind:?}").to_lowercase())
+                    .collect(),
+            });
+        }
+        Ok(Response::new(ListCamerasResponse { cameras }))
+    }
+
+    type WatchEventsStream = Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send>>;
+
+    async fn watch_events(
+        &self,
+        request: Request<WatchEventsRequest>,
+    ) -> Result<Response<Self::WatchEventsStream>, Status> {
+        let WatchEventsRequest { cameras, kinds } = request.into_inner();
+        let (tx, rx) = unbounded_channel();
+        let closed = tx.clone();
+        let reactor = self.reactor.clone();
+        let cancel = self.cancel.child_token();
+        tokio::spawn(async move {
+            let watching = watch(reactor, cancel.clone(), move |event| {
+                if (cameras.is_empty() || cameras.contains(&event.camera))
+                    && (kinds.is_empty() || kinds.contains(&event.kind))
+                {
+                    let _ = tx.send(Ok(Event {
+                        time: event.time,
+                        camera: event.camera,
+                        kind: event.kind,
+                        detections: event.detections,
+                    }));
+                }
+            });
+            // Stop watching when the client goes
+            tokio::select! {
+                v = watching => {
+                    if let Err(e) = v {
+                        warn!("Stopped watching the events for gRPC: {e:?}");
+                        let _ = closed.send(Err(internal(e)));
+                    }
+                }
+                _ = closed.closed() => cancel.cancel(),
+            }
+        });
+        Ok(Response::new(Box::pin(UnboundedReceiverStream::new(rx))))
+    }
+
+    async fn snapshot(
+        &self,
+        request: Request<SnapshotRequest>,
+    ) -> Result<Response<SnapshotResponse>, Status> {
+        let camera = self.camera(&request.get_ref().camera).await?;
+        let jpeg = crate::http::snapshot::snapshot(&camera)
+            .await
+            .map_err(unavailable)?;
+        Ok(Response::new(SnapshotResponse { jpeg }))
+    }
+
+    async fn ptz(&self, request: Request<PtzRequest>) -> Result<Response<PtzResponse>, Status> {
+        let PtzRequest { camera, action } = request.into_inner();
+        let camera = self.camera(&camera).await?;
+        match action {
+            Some(Action::Preset(preset)) => {
+                let preset = u8::try_from(preset)
+                    .map_err(|_| Status::invalid_argument("The preset must be under 256"))?;
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.moveto_ptz_preset(preset).await?;
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await
+                    .map_err(unavailable)?;
+            }
+            Some(Action::Zoom(zoom)) => {
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.zoom_to((zoom * 1000.0) as u32).await?;
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await
+                    .map_err(unavailable)?;
+            }
+            Some(Action::Move(movement)) => {
+                let direction = match Direction::try_from(movement.direction) {
+                    Ok(Direction::Stop) => BcDirection::Stop,
+                    Ok(Direction::Up) => BcDirection::Up,
+                    Ok(Direction::Down) => BcDirection::Down,
+                    Ok(Direction::Left) => BcDirection::Left,
+                    Ok(Direction::Right) => BcDirection::Right,
+                    Err(_) => return Err(Status::invalid_argument("Unknown direction")),
+                };
+                let speed = if movement.speed > 0.0 {
+                    movement.speed
+                } else {
+                    32.0
+                };
+                let duration = if movement.duration > 0.0 {
+                    movement.duration
+                } else {
+                    1.0
+                };
+                // So that a call cannot hold the camera for ever
+                if duration >= 10.0 {
+                    return Err(Status::invalid_argument("The duration must be under 10s"));
+                }
+                // On drop send the stop command again just to make sure it stops
+                let _drop_command = camera.clone().drop_command(
+                    move |cam| {
+                        Box::pin(async move {
+                            cam.send_ptz(BcDirection::Stop, speed).await?;
+                            AnyResult::Ok(())
+                        })
+                    },
+                    Duration::from_millis(100),
+                );
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.send_ptz(direction, speed).await?;
+                            if !matches!(direction, BcDirection::Stop) {
+                                sleep(Duration::from_secs_f32(duration)).await;
+                                cam.send_ptz(BcDirection::Stop, speed).await?;
+                            }
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await
+                    .map_err(unavailable)?;
+            }
+            None => return Err(Status::invalid_argument("Give a move, a preset or a zoom")),
+        }
+        Ok(Response::new(PtzResponse {}))
+    }
+
+    async fn get_config(
+        &self,
+        _request: Request<GetConfigRequest>,
+    ) -> Result<Response<GetConfigResponse>, Status> {
+        let config = self
+            .reactor
+            .config()
+            .await
+            .map_err(internal)?
+            .borrow()
+            .clone();
+        let toml = toml::to_string(&config).map_err(internal)?;
+        Ok(Response::new(GetConfigResponse { toml }))
+    }
+
+    async fn set_config(
+        &self,
+        request: Request<SetConfigRequest>,
+    ) -> Result<Response<SetConfigResponse>, Status> {
+        let mut config: Config = toml::from_str(&request.get_ref().toml)
+            .map_err(|e| Status::invalid_argument(format!("Failed to parse the config: {e}")))?;
+        let curr_config = self
+            .reactor
+            .config()
+            .await
+            .map_err(internal)?
+            .borrow()
+            .clone();
+        config
+            .restore_secrets(&curr_config)
+            .map_err(|e| Status::permission_denied(format!("{e:#}")))?;
+        config
+            .validate()
+            .map_err(|e| Status::invalid_argument(format!("Failed to validate the config: {e}")))?;
+        if config != curr_config {
+            self.reactor.update_config(config).await.map_err(internal)?;
+            info!("Updated config from gRPC");
+        }
+        Ok(Response::new(SetConfigResponse {}))
+    }
+}
+
+fn internal<E: std::fmt::Display>(e: E) -> Status {
+    Status::internal(format!("{e}"))
+}
+
+/// The camera could not do what was asked
+fn unavailable(e: anyhow::Error) -> Status {
+    Status::unavailable(format!("{e}"))
+}
@@ -0,0 +1,250 @@
+//!
+//! # Neolink Health
+//!
+//! This module serves a health check over http so that Docker or Kubernetes
+//! can restart neolink when a camera is stuck rather than only when the
+//! process dies
+//!
+//! It is enabled with a `[health]` table in the config
+//!
+//! ```toml
+//! [health]
+//! bind = "0.0.0.0"
+//! port = 8090
+//! unhealthy_after = 300
+//! max_unhealthy = 0
+//! ```
+//!
+//! - `GET /healthz` The state of neolink and each camera as json, with a 200
+//!   when healthy and a 503 when not
+//! - `GET /livez` A 200 for as long as neolink is answering
+//!
+//! Each camera is checked every few seconds. It is
+//!
+//! - `connected` when it is logged in
+//! - `idle` when neolink has disconnected it on purpose, such as with
+//!   `idle_disconnect`
+//! - `connecting` when it should be connected but is not
+//! - `failed` when it was given up on after `retry.max_attempts`
+//! - `login_failed` when the login was refused
+//! - `unresponsive` when its thread does not answer or has stopped
+//!
+//! A camera that has been in one of the last four states for longer than
+//! `unhealthy_after` seconds is unhealthy, and neolink is unhealthy once more
+//! than `max_unhealthy` of its cameras are
+//!
+use anyhow::Context;
+use log::*;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    net::TcpListener,
+    time::{interval, timeout, Duration, Instant, MissedTickBehavior},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    common::{NeoCamFailure, NeoCamThreadState, NeoInstance, NeoReactor},
+    config::HealthConfig,
+    http::server::{self, HttpRequest, HttpResponse},
+    AnyResult,
+};
+
+/// How often the cameras are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a camera's thread has to answer a check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CameraState {
+    Connected,
+    Idle,
+    Connecting,
+    Failed,
+    LoginFailed,
+    Unresponsive,
+}
+
+impl CameraState {
+    fn name(&self) -> &'static str {
+        match self {
+            CameraState::Connected => "connected",
+            CameraState::Idle => "idle",
+            CameraState::Connecting => "connecting",
+            CameraState::Failed => "failed",
+            CameraState::LoginFailed => "login_failed",
+            CameraState::Unresponsive => "unresponsive",
+        }
+    }
+}
+
+struct CameraHealth {
+    state: CameraState,
+    /// When it changed to this state
+    since: Instant,
+}
+
+#[derive(Default)]
+struct Checked {
+    /// `None` until the first check is done
+    at: Option<Instant>,
+    cameras: HashMap<String, CameraHealth>,
+}
+
+/// Serve the health check until the program stops
+pub(crate) async fn main(health_config: HealthConfig, reactor: NeoReactor) -> AnyResult<()> {
+    let listener = TcpListener::bind((health_config.bind_addr.as_str(), health_config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind the health check to {}:{}",
+                health_config.bind_addr, health_config.port
+            )
+        })?;
+    info!(
+        "Starting the health check at {}:{}",
+        health_config.bind_addr, health_config.port
+    );
+
+    let started = Instant::now();
+    let checked = Arc::new(Mutex::new(Checked::default()));
+    let thread_checked = checked.clone();
+    let server = server::serve(listener, CancellationToken::new(), move |request| {
+        let reply = handle(
+            &request,
+            &health_config,
+            &thread_checked.lock().unwrap(),
+            started,
+        );
+        async move { reply }
+    });
+
+    tokio::select! {
+        v = check(&reactor, &checked) => v,
+        v = server => v,
+    }
+}
+
+/// Keep the state of the cameras up to date
+async fn check(reactor: &NeoReactor, checked: &Mutex<Checked>) -> AnyResult<()> {
+    let mut config = reactor.config().await?;
+    let mut ticker = interval(CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let names = config
+            .borrow_and_update()
+            .cameras
+            .iter()
+            .filter(|cam| cam.enabled)
+            .map(|cam| cam.name.clone())
+            .collect::<Vec<_>>();
+        let mut states = vec![];
+        for name in names {
+            let state = match reactor.get(&name).await {
+                Ok(camera) => camera_state(&camera).await,
+                Err(_) => CameraState::Unresponsive,
+            };
+            states.push((name, state));
+        }
+
+        let now = Instant::now();
+        let mut checked = checked.lock().unwrap();
+        let mut cameras = std::mem::take(&mut checked.cameras);
+        checked.cameras = states
+            .into_iter()
+            .map(|(name, state)| {
+                let since = match cameras.remove(&name) {
+                    Some(prev) if prev.state == state => prev.since,
+                    _ => {
+                        debug!("{name}: Health is now {}", state.name());
+                        now
+                    }
+                };
+                (name, CameraHealth { state, since })
+            })
+            .collect();
+        checked.at = Some(now);
+    }
+}
+
+async fn camera_state(camera: &NeoInstance) -> CameraState {
+    match timeout(CHECK_TIMEOUT, camera.get_state()).await {
+        Ok(Ok(NeoCamThreadState::Connected)) => {
+            if camera.camera().borrow().upgrade().is_some() {
+                return CameraState::Connected;
+            }
+            match timeout(CHECK_TIMEOUT, camera.failed()).await {
+                Ok(Ok(failed)) => match *failed.borrow() {
+                    None => CameraState::Connecting,
+                    Some(NeoCamFailure::GaveUp) => CameraState::Failed,
+                    Some(NeoCamFailure::LoginRejected) => CameraState::LoginFailed,
+                },
+                Ok(Err(_)) | Err(_) => CameraState::Unresponsive,
+            }
+        }
+        Ok(Ok(NeoCamThreadState::Disconnected)) => CameraState::Idle,
+        Ok(Err(_)) | Err(_) => CameraState::Unresponsive,
+    }
+}
+
+fn handle(
+    request: &HttpRequest,
+    health_config: &HealthConfig,
+    checked: &Checked,
+    started: Instant,
+) -> HttpResponse {
+    if request.method != "GET" && request.method != "HEAD" {
+        return HttpResponse::text(405, "Method Not Allowed");
+    }
+    let path = request.path.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    match path.as_slice() {
+        ["livez"] => HttpResponse::text(200, "ok"),
+        ["healthz"] => healthz(health_config, checked, started),
+        _ => HttpResponse::not_found(),
+    }
+}
+
+fn healthz(health_config: &HealthConfig, checked: &Checked, started: Instant) -> HttpResponse {
+    let unhealthy_after = Duration::from_secs(health_config.unhealthy_after);
+    let mut unhealthy = 0;
+    let cameras = checked
+        .cameras
+        .iter()
+        .map(|(name, health)| {
+            let failing = !matches!(health.state, CameraState::Connected | CameraState::Idle);
+            let healthy = !(failing && health.since.elapsed() > unhealthy_after);
+            if !healthy {
+                unhealthy += 1;
+            }
+            (
+                name.clone(),
+                json!({
+                    "state": health.state.name(),
+                    "since": health.since.elapsed().as_secs(),
+                    "healthy": healthy,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let status = match checked.at {
+        None => "starting",
+        // The checks themselves are stuck
+        Some(at) if at.elapsed() > CHECK_INTERVAL * 2 + unhealthy_after => "unhealthy",
+        Some(_) if unhealthy > health_config.max_unhealthy => "unhealthy",
+        Some(_) => "ok",
+    };
+    HttpResponse::json(
+        if status == "unhealthy" { 503 } else { 200 },
+        &json!({
+            "status": status,
+            "uptime": started.elapsed().as_secs(),
+            "unhealthy": unhealthy,
+            "cameras": cameras,
+        }),
+    )
+    .with_header("Cache-Control", "no-store")
+}
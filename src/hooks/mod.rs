@@ -0,0 +1,149 @@
+//!
+//! # Neolink Hooks
+//!
+//! This module runs the commands in the `[hooks]` of the config on the events
+//! of the cameras while the rtsp server runs
+//!
+//! ```toml
+//! [hooks]
+//! on_motion_start = "/usr/local/bin/notify.sh {camera} {type}"
+//! on_doorbell = "/usr/local/bin/chime.sh"
+//! max_running = 4
+//! timeout = 60
+//! ```
+//!
+//! The command is split into its arguments like a shell would, with quotes,
+//! but is not run by a shell. `{camera}`, `{type}`, `{time}` and
+//! `{detections}` are replaced in each argument and are also set in the
+//! environment as `NEOLINK_CAMERA`, `NEOLINK_EVENT`, `NEOLINK_TIME`,
+//! `NEOLINK_DETECTIONS` and the json of the event as `NEOLINK_EVENT_JSON`
+//!
+use anyhow::{anyhow, Context};
+use log::*;
+use std::{process::Stdio, sync::Arc};
+use tokio::{
+    process::Command,
+    sync::{mpsc::unbounded_channel, Semaphore},
+    time::{timeout, Duration},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    common::NeoReactor,
+    config::HooksConfig,
+    events::{watch, Event},
+    AnyResult,
+};
+
+/// Run the hooks of the events of the cameras until cancelled
+///
+/// The commands are those of the config file when neolink started, a change
+/// to them needs a restart so that a config sent over mqtt or gRPC cannot run
+/// commands
+pub(crate) async fn main(
+    hooks: HooksConfig,
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let running = Arc::new(Semaphore::new(hooks.max_running));
+    let limit = Duration::from_secs(hooks.timeout);
+
+    let (tx, mut rx) = unbounded_channel();
+    let watching = watch(reactor, cancel, move |event| {
+        let _ = tx.send(event);
+    });
+    let running_hooks = async {
+        while let Some(event) = rx.recv().await {
+            let Some(command) = hooks.command(&event.kind).map(str::to_string) else {
+                continue;
+            };
+            let running = running.clone();
+            tokio::spawn(async move {
+                let Ok(_permit) = running.acquire_owned().await else {
+                    return;
+                };
+                if let Err(e) = run(&command, &event, limit).await {
+                    warn!(
+                        "{}: The {} hook `{command}` failed: {e:?}",
+                        event.camera, event.kind
+                    );
+                }
+            });
+        }
+    };
+    let (watched, _) = tokio::join!(watching, running_hooks);
+    watched
+}
+
+async fn run(command: &str, event: &Event, limit: Duration) -> AnyResult<()> {
+    let time = event.time.to_string();
+    let detections = event.detections.join(",");
+    let fill = |arg: String| {
+        arg.replace("{camera}", &event.camera)
+            .replace("{type}", &event.kind)
+            .replace("{time}", &time)
+            .replace("{detections}", &detections)
+    };
+    let args = split_command(command)
+        .into_iter()
+        .map(fill)
+        .collect::<Vec<_>>();
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("The command is empty"))?;
+
+    debug!(
+        "{}: Running the {} hook {program}",
+        event.camera, event.kind
+    );
+    let mut child = Command::new(program)
+        .args(args)
+        .env("NEOLINK_CAMERA", &event.camera)
+        .env("NEOLINK_EVENT", &event.kind)
+        .env("NEOLINK_TIME", &time)
+        .env("NEOLINK_DETECTIONS", &detections)
+        .env("NEOLINK_EVENT_JSON", serde_json::to_string(event)?)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Could not start {program}"))?;
+    let status = timeout(limit, child.wait())
+        .await
+        .map_err(|_| anyhow!("Killed after {} seconds", limit.as_secs()))??;
+    if !status.success() {
+        return Err(anyhow!("It exited with {status}"));
+    }
+    Ok(())
+}
+
+/// Split a command into its arguments at the spaces that are not quoted
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut arg = None::<String>;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                if let Some(c) = chars.next() {
+                    arg.get_or_insert_with(String::new).push(c);
+                }
+            }
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, '\\') => {
+                if let Some(c) = chars.next() {
+                    arg.get_or_insert_with(String::new).push(c);
+                }
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
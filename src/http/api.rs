@@ -0,0 +1,529 @@
+//! A json api for the state and controls of the cameras
+//!
+//! The api is for scripts and other programs that do not use MQTT. It is
+//! enabled with `api = true` in the `[http]` table. Requests are accepted with
+//! `Authorization: Bearer <token>` for one of the `api_tokens`, or with the
+//! basic auth of the users like the other endpoints
+//!
+//! - `GET /api/cameras`: The cameras and if they are connected
+//! - `GET /api/cameras/<camera>`: The state of a camera
+//! - `GET /api/cameras/<camera>/motion`: If there is motion
+//! - `GET /api/cameras/<camera>/battery`: The battery of the camera
+//! - `GET /api/cameras/<camera>/wifi`: The wifi signal of the camera
+//! - `GET /api/cameras/<camera>/events`: The recent events, newest first. They
+//!   can be filtered with `?since=24h`, `from` and `to` in unix seconds, `kind`
+//!   and `limit`
+//! - `GET /api/cameras/<camera>/snapshot.jpg`: A jpeg of the current frame
+//! - `GET /api/cameras/<camera>/thumbnails`: The thumbnails of the recording,
+//!   oldest first, from the last day or `from` and `to` in unix seconds.
+//!   `count` spreads that many over the range for a timeline
+//! - `GET /api/cameras/<camera>/thumbnails/<date>/<file>`: The jpeg of a thumbnail
+//! - `POST /api/cameras/<camera>/reboot`: Reboot the camera
+//! - `POST /api/cameras/<camera>/ptz`: Move the camera with a json body of
+//!   `{"direction": "left", "speed": 32, "duration": 1.0}`, `{"preset": 1}`
+//!   or `{"zoom": 2.0}`
+//! - `POST /api/cameras/<camera>/floodlight`: Turn the floodlight on or off
+//!   with `{"on": true, "duration": 180}`
+//! - `POST /api/cameras`: Add a camera with its `[[cameras]]` table as json,
+//!   this needs `managed_cameras` in the config and one of the `api_tokens`
+//! - `PUT /api/cameras/<camera>`: Replace the table of an added camera, the
+//!   password is kept when none is given
+//! - `DELETE /api/cameras/<camera>`: Remove an added camera
+use neolink_core::bc_protocol::Direction as BcDirection;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::PathBuf;
+use tokio::time::{sleep, Duration};
+
+use super::{
+    authorise,
+    events::Events,
+    secure_eq,
+    server::{HttpRequest, HttpResponse},
+    snapshot, thumbnails,
+};
+use crate::{
+    common::{MdState, NeoCamThreadState, NeoInstance, NeoReactor},
+    config::{CameraConfig, Config, HttpConfig},
+    events::{now, EventQuery},
+    managed::{self, ManageError},
+    utils::parse_duration,
+    AnyResult,
+};
+
+/// The most events or thumbnails returned by one request
+const MAX_EVENTS: usize = 1000;
+/// The longest time in seconds that thumbnails are listed for at once
+const MAX_THUMBNAIL_RANGE: u64 = 31 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PtzRequest {
+    direction: Option<String>,
+    #[serde(default = "default_speed")]
+    speed: f32,
+    /// Seconds to move for
+    #[serde(default = "default_duration")]
+    duration: f32,
+    preset: Option<u8>,
+    zoom: Option<f32>,
+}
+
+fn default_speed() -> f32 {
+    32.0
+}
+
+fn default_duration() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FloodlightRequest {
+    on: bool,
+    /// Seconds to stay on for
+    #[serde(default = "default_floodlight_duration")]
+    duration: u16,
+}
+
+fn default_floodlight_duration() -> u16 {
+    180
+}
+
+/// Handle a request below `/api`
+pub(super) async fn handle(
+    request: &HttpRequest,
+    path: &[&str],
+    reactor: &NeoReactor,
+    config: &Config,
+    http_config: &HttpConfig,
+    events: &Events,
+) -> AnyResult<HttpResponse> {
+    let cameras = config
+        .cameras
+        .iter()
+        .filter(|cam| cam.enabled)
+        .collect::<Vec<_>>();
+    match path {
+        ["cameras"] if request.method == "POST" => {
+            if let Err(response) = authorise_manage(request, http_config) {
+                return Ok(response);
+            }
+            let camera = match camera_table(request) {
+                Ok(camera) => camera,
+                Err(response) => return Ok(response),
+            };
+            Ok(managed(managed::add(reactor, camera).await, 201))
+        }
+        ["cameras", name] if request.method == "PUT" || request.method == "DELETE" => {
+            if let Err(response) = authorise_manage(request, http_config) {
+                return Ok(response);
+            }
+            if request.method == "DELETE" {
+                return Ok(managed(managed::remove(reactor, name).await, 200));
+            }
+            let camera = match camera_table(request) {
+                Ok(camera) => camera,
+                Err(response) => return Ok(response),
+            };
+            Ok(managed(managed::update(reactor, name, camera).await, 200))
+        }
+        ["cameras"] => {
+            if request.method != "GET" {
+                return Ok(error(405, "Method Not Allowed"));
+            }
+            let mut permitted = vec![];
+            let mut denied = None;
+            for camera_config in cameras.iter() {
+                match authorise_api(request, config, http_config, camera_config) {
+                    Ok(()) => permitted.push(*camera_config),
+                    Err(response) => denied = Some(response),
+                }
+            }
+            if let (true, Some(response)) = (permitted.is_empty(), denied) {
+                return Ok(response);
+            }
+            let mut list = vec![];
+            for camera_config in permitted {
+                let camera = reactor.get(&camera_config.name).await?;
+                list.push(json!({
+                    "name": camera_config.name,
+                    "connected": connected(&camera).await,
+                }));
+            }
+            Ok(HttpResponse::json(200, &list))
+        }
+        ["cameras", name, rest @ ..] => {
+            let Some(camera_config) = cameras.iter().find(|cam| cam.name == *name) else {
+                return Ok(error(404, "No such camera"));
+            };
+            if let Err(response) = authorise_api(request, config, http_config, camera_config) {
+                return Ok(response);
+            }
+            let camera = reactor.get(name).await?;
+            let recordings = config
+                .recording
+                .as_ref()
+                .map(|recording| recording.directory.join(&camera_config.name));
+            camera_request(request, rest, &camera, camera_config, events, recordings).await
+        }
+        _ => Ok(error(404, "Not Found")),
+    }
+}
+
+async fn camera_request(
+    request: &HttpRequest,
+    path: &[&str],
+    camera: &NeoInstance,
+    camera_config: &CameraConfig,
+    events: &Events,
+    recordings: Option<PathBuf>,
+) -> AnyResult<HttpResponse> {
+    match (request.method.as_str(), path) {
+        ("GET", []) => {
+            let motion = motion(camera).await?;
+            Ok(HttpResponse::json(
+                200,
+                &json!({
+                    "name": camera_config.name,
+                    "connected": connected(camera).await,
+                    "motion": motion,
+                    "streams": camera_config.stream_kinds()
+                        .iter()
+                        .map(|kind| format!("{kind:?}").to_lowercase())
+                        .collect::<Vec<_>>(),
+                }),
+            ))
+        }
+        ("GET", ["motion"]) => Ok(HttpResponse::json(
+            200,
+            &json!({ "motion": motion(camera).await? }),
+        )),
+        ("GET", ["battery"]) => {
+            let battery = camera
+                .run_passive_task(|cam| {
+                    Box::pin(async move { AnyResult::Ok(cam.battery_info().await?) })
+                })
+                .await;
+            Ok(match battery {
+                Ok(battery) => HttpResponse::json(
+                    200,
+                    &json!({
+                        "percent": battery.battery_percent,
+                        "charge_status": battery.charge_status,
+                        "adapter_status": battery.adapter_status,
+                        "temperature": battery.temperature,
+                        "low_power": battery.low_power != 0,
+                    }),
+                ),
+                Err(e) => error(502, &format!("Could not get the battery: {e}")),
+            })
+        }
+        ("GET", ["wifi"]) => {
+            let wifi = camera
+                .run_passive_task(|cam| {
+                    Box::pin(async move { AnyResult::Ok(cam.get_wifi_signal().await?) })
+                })
+                .await;
+            Ok(match wifi {
+                Ok(wifi) => HttpResponse::json(200, &json!({ "signal": wifi.signal })),
+                Err(e) => error(502, &format!("Could not get the wifi signal: {e}")),
+            })
+        }
+        ("GET", ["events"]) => {
+            let query = match event_query(request, &camera_config.name) {
+                Ok(query) => query,
+                Err(e) => return Ok(error(400, &format!("Invalid events query: {e}"))),
+            };
+            Ok(HttpResponse::json(200, &events.query(query).await?))
+        }
+        ("GET", ["thumbnails"]) => match recordings {
+            Some(directory) => thumbnails_request(request, directory).await,
+            None => Ok(error(404, "The cameras are not recorded")),
+        },
+        ("GET" | "HEAD", ["thumbnails", date, file]) => {
+            let Some(directory) = recordings else {
+                return Ok(error(404, "The cameras are not recorded"));
+            };
+            match thumbnails::read(directory, date, file).await? {
+                Some(jpeg) => {
+                    Ok(HttpResponse::jpeg(jpeg).with_header("Cache-Control", "max-age=86400"))
+                }
+                None => Ok(error(404, "No such thumbnail")),
+            }
+        }
+        ("GET" | "HEAD", ["snapshot.jpg"]) => {
+            Ok(HttpResponse::jpeg(snapshot::snapshot(camera).await?))
+        }
+        ("POST", ["reboot"]) => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.reboot().await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            Ok(result(res))
+        }
+        ("POST", ["ptz"]) => {
+            let ptz = match serde_json::from_slice::<PtzRequest>(&request.body) {
+                Ok(ptz) => ptz,
+                Err(e) => return Ok(error(400, &format!("Invalid ptz request: {e}"))),
+            };
+            ptz_request(camera, ptz).await
+        }
+        ("POST", ["floodlight"]) => {
+            let FloodlightRequest { on, duration } =
+                match serde_json::from_slice::<FloodlightRequest>(&request.body) {
+                    Ok(floodlight) => floodlight,
+                    Err(e) => return Ok(error(400, &format!("Invalid floodlight request: {e}"))),
+                };
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.set_floodlight_manual(on, duration).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            Ok(result(res))
+        }
+        (_, [] | ["motion"] | ["battery"] | ["wifi"] | ["events"] | ["snapshot.jpg"])
+        | (_, ["thumbnails"] | ["thumbnails", _, _])
+        | (_, ["reboot"] | ["ptz"] | ["floodlight"]) => Ok(error(405, "Method Not Allowed")),
+        _ => Ok(error(404, "Not Found")),
+    }
+}
+
+/// The events of a camera asked for in the query string
+fn event_query(request: &HttpRequest, camera: &str) -> AnyResult<EventQuery> {
+    let (from, to) = time_range(request)?;
+    Ok(EventQuery {
+        camera: Some(camera.to_string()),
+        kind: request.query.get("kind").cloned(),
+        from,
+        to,
+        limit: query_number(request, "limit")?
+            .unwrap_or(100)
+            .min(MAX_EVENTS),
+    })
+}
+
+/// The unix times in seconds of `from` and `to`, or `since` a duration ago
+fn time_range(request: &HttpRequest) -> AnyResult<(Option<u64>, Option<u64>)> {
+    let since = request
+        .query
+        .get("since")
+        .map(|since| parse_duration(since))
+        .transpose()?
+        .map(|since| now().saturating_sub(since.as_secs()));
+    let from = query_number::<u64>(request, "from")?.max(since);
+    let to = query_number(request, "to")?;
+    Ok((from, to))
+}
+
+fn query_number<T: std::str::FromStr>(request: &HttpRequest, key: &str) -> AnyResult<Option<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    Ok(request
+        .query
+        .get(key)
+        .map(|value| value.parse::<T>())
+        .transpose()?)
+}
+
+async fn thumbnails_request(request: &HttpRequest, directory: PathBuf) -> AnyResult<HttpResponse> {
+    let (from, to) = match time_range(request) {
+        Ok(range) => range,
+        Err(e) => return Ok(error(400, &format!("Invalid thumbnails query: {e}"))),
+    };
+    let to = to.unwrap_or_else(now);
+    let from = from.unwrap_or_else(|| to.saturating_sub(24 * 60 * 60));
+    if from > to || to - from > MAX_THUMBNAIL_RANGE {
+        return Ok(error(
+            400,
+            "The thumbnails can be asked for up to 31 days at a time",
+        ));
+    }
+    let count = match query_number(request, "count") {
+        Ok(count) => count.unwrap_or(100).clamp(1, MAX_EVENTS),
+        Err(e) => return Ok(error(400, &format!("Invalid thumbnails count: {e}"))),
+    };
+    Ok(HttpResponse::json(
+        200,
+        &thumbnails::list(directory, from, to, count).await?,
+    ))
+}
+
+async fn ptz_request(camera: &NeoInstance, ptz: PtzRequest) -> AnyResult<HttpResponse> {
+    if let Some(preset) = ptz.preset {
+        let res = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.moveto_ptz_preset(preset).await?;
+                    AnyResult::Ok(())
+                })
+            })
+            .await;
+        return Ok(result(res));
+    }
+    if let Some(zoom) = ptz.zoom {
+        let res = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.zoom_to((zoom * 1000.0) as u32).await?;
+                    AnyResult::Ok(())
+                })
+            })
+            .await;
+        return Ok(result(res));
+    }
+    let direction = match ptz
+        .direction
+        .as_deref()
+        .map(|d| d.to_lowercase())
+        .as_deref()
+    {
+        Some("up") => BcDirection::Up,
+        Some("down") => BcDirection::Down,
+        Some("left") => BcDirection::Left,
+        Some("right") => BcDirection::Right,
+        Some("stop") => BcDirection::Stop,
+        _ => {
+            return Ok(error(
+                400,
+                "Give a direction of up|down|left|right|stop, a preset or a zoom",
+            ))
+        }
+    };
+    // So that a request cannot hold the camera for ever
+    if !(0.0..10.0).contains(&ptz.duration) {
+        return Ok(error(400, "The duration must be under 10s"));
+    }
+    let (speed, duration) = (ptz.speed, ptz.duration);
+    // On drop send the stop command again just to make sure it stops
+    let _drop_command = camera.clone().drop_command(
+        move |cam| {
+            Box::pin(async move {
+                cam.send_ptz(BcDirection::Stop, speed).await?;
+                AnyResult::Ok(())
+            })
+        },
+        Duration::from_millis(100),
+    );
+    let res = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.send_ptz(direction, speed).await?;
+                if !matches!(direction, BcDirection::Stop) {
+                    sleep(Duration::from_secs_f32(duration)).await;
+                    cam.send_ptz(BcDirection::Stop, speed).await?;
+                }
+                AnyResult::Ok(())
+            })
+        })
+        .await;
+    Ok(result(res))
+}
+
+/// Check the bearer token of the request, or else the basic auth of the users
+fn authorise_api(
+    request: &HttpRequest,
+    config: &Config,
+    http_config: &HttpConfig,
+    camera_config: &CameraConfig,
+) -> Result<(), HttpResponse> {
+    if crate::supervisor::is_ingress(request) {
+        return Ok(());
+    }
+    if let Some(token) = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return if http_config
+            .api_tokens
+            .iter()
+            .any(|t| secure_eq(t, token.trim()))
+        {
+            Ok(())
+        } else {
+            Err(error(401, "Invalid token"))
+        };
+    }
+    // With only tokens set there are no users for the basic auth to pass
+    if !http_config.api_tokens.is_empty() && config.users.is_empty() {
+        return Err(error(401, "Unauthorized").with_header("WWW-Authenticate", "Bearer"));
+    }
+    authorise(request, config, camera_config)
+}
+
+/// Managing the cameras needs one of the `api_tokens`, the users of the basic
+/// auth only have their own cameras
+fn authorise_manage(request: &HttpRequest, http_config: &HttpConfig) -> Result<(), HttpResponse> {
+    if crate::supervisor::is_ingress(request) {
+        return Ok(());
+    }
+    match request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        Some(token)
+            if http_config
+                .api_tokens
+                .iter()
+                .any(|t| secure_eq(t, token.trim())) =>
+        {
+            Ok(())
+        }
+        Some(_) => Err(error(401, "Invalid token")),
+        None => Err(error(401, "Unauthorized").with_header("WWW-Authenticate", "Bearer")),
+    }
+}
+
+/// The `[[cameras]]` table in the json body
+fn camera_table(request: &HttpRequest) -> Result<toml::Table, HttpResponse> {
+    match serde_json::from_slice::<toml::Value>(&request.body) {
+        Ok(toml::Value::Table(camera)) => Ok(camera),
+        Ok(_) => Err(error(400, "The body should be a json object")),
+        Err(e) => Err(error(400, &format!("Invalid body: {e}"))),
+    }
+}
+
+fn managed(res: AnyResult<()>, status: u16) -> HttpResponse {
+    match res {
+        Ok(()) => HttpResponse::json(status, &json!({ "result": "ok" })),
+        Err(e) => match e.downcast_ref::<ManageError>() {
+            Some(ManageError::Disabled) => error(409, &format!("{e}")),
+            Some(ManageError::NotFound(_)) => error(404, &format!("{e}")),
+            Some(ManageError::Conflict(_)) => error(409, &format!("{e}")),
+            Some(ManageError::Invalid(_)) => error(400, &format!("{e}")),
+            None => error(500, &format!("{e:#}")),
+        },
+    }
+}
+
+async fn connected(camera: &NeoInstance) -> bool {
+    matches!(camera.get_state().await, Ok(NeoCamThreadState::Connected))
+}
+
+/// If there is motion, `None` when the camera has not said yet
+async fn motion(camera: &NeoInstance) -> AnyResult<Option<bool>> {
+    Ok(match *camera.motion().await?.borrow() {
+        MdState::Start(..) => Some(true),
+        MdState::Stop(..) => Some(false),
+        MdState::Unknown => None,
+    })
+}
+
+fn result(res: AnyResult<()>) -> HttpResponse {
+    match res {
+        Ok(()) => HttpResponse::json(200, &json!({ "result": "ok" })),
+        Err(e) => error(502, &format!("{e}")),
+    }
+}
+
+fn error(status: u16, message: &str) -> HttpResponse {
+    HttpResponse::json(status, &json!({ "error": message }))
+}
@@ -0,0 +1,78 @@
+//! The events of the cameras for the api and the web ui
+//!
+//! When there is an `[events]` database the events are read from it,
+//! otherwise the recent events are watched and held in memory
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    common::NeoReactor,
+    events::{watch, Event, EventQuery},
+    AnyResult,
+};
+
+/// How many events are kept for each camera in memory
+const KEEP: usize = 100;
+
+#[derive(Clone)]
+pub(crate) enum Events {
+    Recent(RecentEvents),
+    /// Written by the events recorder that runs alongside the http server
+    #[cfg(feature = "events")]
+    Database(crate::events::EventDb),
+}
+
+impl Events {
+    /// The events that match the query, newest first
+    pub(crate) async fn query(&self, query: EventQuery) -> AnyResult<Vec<Event>> {
+        match self {
+            Self::Recent(recent) => Ok(recent.query(&query)),
+            #[cfg(feature = "events")]
+            Self::Database(db) => db.query(query).await,
+        }
+    }
+
+    /// Keep the recent events until cancelled if they are held in memory
+    pub(crate) async fn run(
+        &self,
+        reactor: NeoReactor,
+        cancel: CancellationToken,
+    ) -> AnyResult<()> {
+        match self {
+            Self::Recent(recent) => {
+                let recent = recent.clone();
+                watch(reactor, cancel, move |event| recent.push(event)).await
+            }
+            #[cfg(feature = "events")]
+            Self::Database(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct RecentEvents(Arc<Mutex<HashMap<String, VecDeque<Event>>>>);
+
+impl RecentEvents {
+    fn query(&self, query: &EventQuery) -> Vec<Event> {
+        let events = self.0.lock().unwrap();
+        let mut found = events
+            .values()
+            .flat_map(|events| events.iter().rev())
+            .filter(|event| query.matches(event))
+            .cloned()
+            .collect::<Vec<_>>();
+        found.sort_by_key(|event| std::cmp::Reverse(event.time));
+        found.truncate(query.limit);
+        found
+    }
+
+    fn push(&self, event: Event) {
+        let mut events = self.0.lock().unwrap();
+        let events = events.entry(event.camera.clone()).or_default();
+        events.push_back(event);
+        while events.len() > KEEP {
+            events.pop_front();
+        }
+    }
+}
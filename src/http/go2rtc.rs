@@ -0,0 +1,228 @@
+//! Endpoints in the form of the go2rtc api
+//!
+//! Players and cards made for go2rtc, such as the ones of Frigate and the
+//! Home Assistant dashboards, can use neolink as their server when
+//! `go2rtc = true` is in the `[http]` table. A stream is named
+//! `<camera>/<stream>` with `<camera>` alone being the main stream
+//!
+//! - `GET /api/streams`: The streams and their rtsp urls
+//! - `GET /api/frame.jpeg?src=<stream>`: A jpeg of the current frame
+//! - `GET /api/stream.m3u8?src=<stream>`: Redirects to the HLS of the stream
+//! - `POST /api/webrtc?src=<stream>`: Answers an sdp offer, either as it is
+//!   or as `{"type": "offer", "sdp": ".."}`. It needs the `[webrtc]` table
+//!
+//! The websocket of go2rtc at `/api/ws`, which plays MSE, is not served so the
+//! players should be set to their WebRTC or HLS modes
+use anyhow::{anyhow, Context};
+use neolink_core::bc_protocol::StreamKind;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use super::{
+    authorise,
+    server::{HttpRequest, HttpResponse},
+    snapshot,
+};
+use crate::{
+    common::NeoReactor,
+    config::{CameraConfig, Config},
+    AnyResult,
+};
+
+/// The offer and answer of `/api/webrtc` when they are json
+#[derive(Deserialize)]
+struct SessionDescription {
+    #[serde(rename = "type")]
+    kind: String,
+    sdp: String,
+}
+
+/// Handle a request to `/api/<endpoint>`
+pub(super) async fn handle(
+    request: &HttpRequest,
+    endpoint: &str,
+    reactor: &NeoReactor,
+    config: &Config,
+) -> AnyResult<HttpResponse> {
+    let response = match (request.method.as_str(), endpoint) {
+        // The preflight of the players on other sites
+        ("OPTIONS", _) => HttpResponse::new(204, "text/plain", vec![])
+            .with_header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
+            .with_header(
+                "Access-Control-Allow-Headers",
+                "Authorization, Content-Type",
+            ),
+        ("GET", "streams") => streams(request, config),
+        ("GET" | "HEAD", "frame.jpeg") => match source(request, config) {
+            Ok((camera_config, _)) => {
+                let camera = reactor.get(&camera_config.name).await?;
+                HttpResponse::jpeg(snapshot::snapshot(&camera).await?)
+            }
+            Err(response) => response,
+        },
+        ("GET" | "HEAD", "stream.m3u8") => match source(request, config) {
+            Ok((camera_config, stream)) if cfg!(feature = "gstreamer") => {
+                HttpResponse::new(302, "text/plain", vec![]).with_header(
+                    "Location",
+                    &format!("/{}/{stream}/hls/index.m3u8", camera_config.name),
+                )
+            }
+            Ok(_) => HttpResponse::text(501, "Neolink was built without HLS"),
+            Err(response) => response,
+        },
+        ("POST", "webrtc") => match source(request, config) {
+            Ok((camera_config, stream)) => webrtc(request, config, camera_config, stream).await?,
+            Err(response) => response,
+        },
+        ("GET", "ws") => HttpResponse::text(
+            501,
+            "The websocket is not served, use the WebRTC or HLS mode of the player",
+        ),
+        _ => HttpResponse::text(405, "Method Not Allowed"),
+    };
+    Ok(response.with_header("Access-Control-Allow-Origin", "*"))
+}
+
+/// The streams that the request may watch in the form of go2rtc
+fn streams(request: &HttpRequest, config: &Config) -> HttpResponse {
+    let mut permitted = vec![];
+    let mut denied = None;
+    for camera_config in config.cameras.iter().filter(|cam| cam.enabled) {
+        match authorise(request, config, camera_config) {
+            Ok(()) => permitted.push(camera_config),
+            Err(response) => denied = Some(response),
+        }
+    }
+    if let (true, Some(response)) = (permitted.is_empty(), denied) {
+        return response;
+    }
+    let host = host(request, config);
+    let mut streams = Map::new();
+    for camera_config in permitted {
+        for kind in camera_config.stream_kinds() {
+            let stream = stream_name(kind);
+            let name = match kind {
+                StreamKind::Main => camera_config.name.clone(),
+                _ => format!("{}/{stream}", camera_config.name),
+            };
+            let Some(path) = camera_config.rtsp_paths(kind).into_iter().next() else {
+                continue;
+            };
+            let url = format!("rtsp://{host}:{}{path}", config.bind_port);
+            streams.insert(
+                name,
+                json!({ "producers": [{ "url": url }], "consumers": [] }),
+            );
+        }
+    }
+    HttpResponse::json(200, &Value::Object(streams))
+}
+
+/// The camera and stream of `?src=`, or the response if it is not one the request may watch
+fn source<'a>(
+    request: &HttpRequest,
+    config: &'a Config,
+) -> Result<(&'a CameraConfig, &'static str), HttpResponse> {
+    let Some(src) = request
+        .query
+        .get("src")
+        .or_else(|| request.query.get("name"))
+    else {
+        return Err(HttpResponse::text(400, "The src is missing"));
+    };
+    let (name, stream) = src.rsplit_once('/').unwrap_or((src, "main"));
+    let kind = match stream {
+        "main" => StreamKind::Main,
+        "sub" => StreamKind::Sub,
+        "extern" => StreamKind::Extern,
+        _ => return Err(HttpResponse::not_found()),
+    };
+    let Some(camera_config) = config
+        .cameras
+        .iter()
+        .find(|cam| cam.enabled && cam.name == name)
+        .filter(|cam| cam.stream_kinds().contains(&kind))
+    else {
+        return Err(HttpResponse::not_found());
+    };
+    authorise(request, config, camera_config)?;
+    Ok((camera_config, stream_name(kind)))
+}
+
+/// Pass the offer to the WHEP endpoint of the webrtc server and reply with its answer
+async fn webrtc(
+    request: &HttpRequest,
+    config: &Config,
+    camera_config: &CameraConfig,
+    stream: &str,
+) -> AnyResult<HttpResponse> {
+    let Some(webrtc_config) = config.webrtc.as_ref() else {
+        return Ok(HttpResponse::text(501, "WebRTC needs the [webrtc] table"));
+    };
+    let offer = match serde_json::from_slice::<SessionDescription>(&request.body) {
+        Ok(offer) if offer.kind == "offer" => Some(offer.sdp),
+        Ok(_) => return Ok(HttpResponse::text(400, "Expected an offer")),
+        Err(_) => None,
+    };
+    let json = offer.is_some();
+    let offer = match offer {
+        Some(offer) => offer,
+        None => String::from_utf8(request.body.clone()).context("The offer is not text")?,
+    };
+
+    // The webrtc server checks the users again so the auth is passed on
+    let host = match webrtc_config.bind_addr.as_str() {
+        "0.0.0.0" => "127.0.0.1",
+        "::" | "[::]" => "[::1]",
+        host => host,
+    };
+    let mut url = Url::parse(&format!("http://{host}:{}/", webrtc_config.port))?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("The webrtc bind address is not a host"))?
+        .extend([camera_config.name.as_str(), stream, "whep"]);
+    let mut whep = reqwest::Client::new()
+        .post(url.clone())
+        .header("Content-Type", "application/sdp")
+        .body(offer);
+    if let Some(authorization) = request.header("authorization") {
+        whep = whep.header("Authorization", authorization);
+    }
+    let reply = whep
+        .send()
+        .await
+        .with_context(|| format!("Could not reach the webrtc server at {url}"))?;
+    let status = reply.status().as_u16();
+    let answer = reply.text().await?;
+    if !(200..300).contains(&status) {
+        return Ok(HttpResponse::text(
+            502,
+            format!("The webrtc server replied {status}: {answer}"),
+        ));
+    }
+    Ok(if json {
+        HttpResponse::json(201, &json!({ "type": "answer", "sdp": answer }))
+    } else {
+        HttpResponse::new(201, "application/sdp", answer.into_bytes())
+    })
+}
+
+fn stream_name(kind: StreamKind) -> &'static str {
+    match kind {
+        StreamKind::Main => "main",
+        StreamKind::Sub => "sub",
+        StreamKind::Extern => "extern",
+    }
+}
+
+/// The host that the request was sent to, for the rtsp urls
+fn host(request: &HttpRequest, config: &Config) -> String {
+    match request.header("host") {
+        // Strip the port but not the end of an IPv6 address
+        Some(host) => match host.rsplit_once(':') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host.to_string(),
+            _ => host.to_string(),
+        },
+        None => config.bind_addr.clone(),
+    }
+}
@@ -0,0 +1,568 @@
+//! HLS of the streams for web pages and iOS
+//!
+//! The stream is muxed into fragmented mp4 as it is, without re-encoding, and
+//! each fragment is a segment of the playlist. With `hls_low_latency` the
+//! fragments are made of parts which are listed as they arrive and the
+//! playlist supports the blocking reloads of Low-Latency HLS.
+//!
+//! The segments are kept in memory. A stream is started by the first request
+//! for it and stopped when nothing has been requested for a while
+use anyhow::{anyhow, Context};
+use futures::stream::StreamExt;
+use gstreamer::{
+    parse::launch_full, prelude::*, BufferFlags, BufferRef, ClockTime, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::{AppSink, AppSrc};
+use neolink_core::bc_protocol::StreamKind;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::{
+    sync::{
+        watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
+        Mutex as AsyncMutex,
+    },
+    time::{interval, timeout, Duration, Instant},
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_util::sync::CancellationToken;
+
+use super::server::HttpResponse;
+use crate::{
+    common::{get_source, push_frame, AudFormat, NeoInstance, StampedData, VidFormat},
+    config::HttpConfig,
+    AnyResult,
+};
+
+/// How long a stream stays up without any requests
+const IDLE: Duration = Duration::from_secs(30);
+/// How long the first request waits for the first segment
+const START_TIMEOUT: Duration = Duration::from_secs(20);
+
+struct Part {
+    /// Seconds
+    duration: f64,
+    data: Arc<Vec<u8>>,
+    /// Starts with a keyframe
+    independent: bool,
+}
+
+#[derive(Default)]
+struct Segment {
+    /// The media sequence number
+    msn: u64,
+    parts: Vec<Part>,
+}
+
+impl Segment {
+    fn duration(&self) -> f64 {
+        self.parts.iter().map(|part| part.duration).sum()
+    }
+
+    fn data(&self) -> Vec<u8> {
+        self.parts
+            .iter()
+            .flat_map(|part| part.data.iter().copied())
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Playlist {
+    /// The `moov` of the stream
+    init: Option<Arc<Vec<u8>>>,
+    /// The complete segments, oldest first
+    segments: VecDeque<Segment>,
+    /// The segment that is being received, only listed with low latency
+    current: Segment,
+}
+
+impl Playlist {
+    fn is_ready(&self, low_latency: bool) -> bool {
+        self.init.is_some()
+            && (!self.segments.is_empty() || (low_latency && !self.current.parts.is_empty()))
+    }
+
+    fn add(&mut self, part: Part, new_fragment: bool, keep: usize) {
+        if new_fragment && !self.current.parts.is_empty() {
+            self.finish_segment(keep);
+        }
+        self.current.parts.push(part);
+    }
+
+    fn finish_segment(&mut self, keep: usize) {
+        let msn = self.current.msn + 1;
+        let done = std::mem::replace(&mut self.current, Segment { msn, parts: vec![] });
+        self.segments.push_back(done);
+        while self.segments.len() > keep {
+            self.segments.pop_front();
+        }
+    }
+
+    /// Whether a blocking reload for the part `part` of segment `msn` can be answered
+    fn has(&self, msn: u64, part: Option<usize>) -> bool {
+        match part {
+            Some(part) => {
+                msn < self.current.msn
+                    || (msn == self.current.msn && part < self.current.parts.len())
+            }
+            None => msn < self.current.msn,
+        }
+    }
+
+    fn find(&self, msn: u64) -> Option<&Segment> {
+        self.segments
+            .iter()
+            .chain(std::iter::once(&self.current))
+            .find(|segment| segment.msn == msn)
+    }
+
+    fn m3u8(&self, http_config: &HttpConfig) -> String {
+        let low_latency = http_config.hls_low_latency;
+        let part_target = http_config.hls_part_duration as f64 / 1000.0;
+        let target = self
+            .segments
+            .iter()
+            .map(|segment| segment.duration())
+            .fold(http_config.hls_segment_duration as f64, f64::max)
+            .ceil();
+        let first = self
+            .segments
+            .front()
+            .map(|segment| segment.msn)
+            .unwrap_or(self.current.msn);
+
+        let mut m3u8 = format!(
+            "#EXTM3U\n#EXT-X-VERSION:{}\n#EXT-X-TARGETDURATION:{target}\n#EXT-X-MEDIA-SEQUENCE:{first}\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+            if low_latency { 9 } else { 7 }
+        );
+        if low_latency {
+            m3u8.push_str(&format!(
+                "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3}\n#EXT-X-PART-INF:PART-TARGET={part_target:.3}\n",
+                part_target * 3.0
+            ));
+        }
+        m3u8.push_str("#EXT-X-MAP:URI=\"init.mp4\"\n");
+        for segment in self.segments.iter() {
+            if low_latency {
+                m3u8.push_str(&parts(segment));
+            }
+            m3u8.push_str(&format!(
+                "#EXTINF:{:.3},\nsegment{}.m4s\n",
+                segment.duration(),
+                segment.msn
+            ));
+        }
+        if low_latency {
+            m3u8.push_str(&parts(&self.current));
+        }
+        m3u8
+    }
+}
+
+fn parts(segment: &Segment) -> String {
+    segment
+        .parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            format!(
+                "#EXT-X-PART:DURATION={:.3},URI=\"part{}.{i}.m4s\"{}\n",
+                part.duration,
+                segment.msn,
+                if part.independent {
+                    ",INDEPENDENT=YES"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect()
+}
+
+struct HlsStream {
+    playlist: WatchReceiver<Playlist>,
+    last_used: Mutex<Instant>,
+}
+
+impl HlsStream {
+    fn touch(&self) {
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+
+    fn is_idle(&self) -> bool {
+        self.last_used.lock().unwrap().elapsed() > IDLE
+    }
+}
+
+type Key = (String, StreamKind);
+
+/// The running HLS streams by camera and stream
+#[derive(Clone)]
+pub(crate) struct HlsStreams {
+    streams: Arc<AsyncMutex<HashMap<Key, Arc<HlsStream>>>>,
+    http_config: HttpConfig,
+    cancel: CancellationToken,
+}
+
+impl HlsStreams {
+    pub(crate) fn new(http_config: HttpConfig, cancel: CancellationToken) -> Self {
+        Self {
+            streams: Default::default(),
+            http_config,
+            cancel,
+        }
+    }
+
+    /// Answer a request for `file` of the HLS of a stream
+    pub(crate) async fn handle(
+        &self,
+        camera: &NeoInstance,
+        name: &str,
+        kind: StreamKind,
+        file: &str,
+        query: &HashMap<String, String>,
+    ) -> AnyResult<HttpResponse> {
+        let low_latency = self.http_config.hls_low_latency;
+        let stream = self.get(camera, name, kind).await?;
+        stream.touch();
+        let mut playlist = stream.playlist.clone();
+        match timeout(
+            START_TIMEOUT,
+            playlist.wait_for(|playlist| playlist.is_ready(low_latency)),
+        )
+        .await
+        {
+            Ok(ready) => {
+                ready.with_context(|| "The HLS stream stopped")?;
+            }
+            Err(_) => return Ok(HttpResponse::text(503, "The stream is not ready yet")),
+        }
+
+        if file == "index.m3u8" {
+            let msn = query
+                .get("_HLS_msn")
+                .and_then(|msn| msn.parse::<u64>().ok());
+            if let (true, Some(msn)) = (low_latency, msn) {
+                let part = query
+                    .get("_HLS_part")
+                    .and_then(|part| part.parse::<usize>().ok());
+                if msn > playlist.borrow().current.msn + 2 {
+                    return Ok(HttpResponse::text(400, "The segment is too far ahead"));
+                }
+                // Three target durations is what the spec allows the client to wait
+                let wait = Duration::from_secs(self.http_config.hls_segment_duration * 3);
+                if let Ok(ready) =
+                    timeout(wait, playlist.wait_for(|playlist| playlist.has(msn, part))).await
+                {
+                    ready.with_context(|| "The HLS stream stopped")?;
+                }
+            }
+            let m3u8 = playlist.borrow().m3u8(&self.http_config);
+            return Ok(
+                HttpResponse::new(200, "application/vnd.apple.mpegurl", m3u8.into_bytes())
+                    .with_header("Cache-Control", "no-cache"),
+            );
+        }
+
+        let playlist = playlist.borrow();
+        let data = if file == "init.mp4" {
+            playlist.init.as_ref().map(|init| init.as_ref().clone())
+        } else if let Some(msn) = file
+            .strip_prefix("segment")
+            .and_then(|file| file.strip_suffix(".m4s"))
+            .and_then(|msn| msn.parse::<u64>().ok())
+        {
+            // The current segment is not complete
+            playlist
+                .segments
+                .iter()
+                .find(|segment| segment.msn == msn)
+                .map(|segment| segment.data())
+        } else if let Some((msn, part)) = file
+            .strip_prefix("part")
+            .and_then(|file| file.strip_suffix(".m4s"))
+            .and_then(|file| file.split_once('.'))
+        {
+            match (msn.parse::<u64>(), part.parse::<usize>()) {
+                (Ok(msn), Ok(part)) => playlist
+                    .find(msn)
+                    .and_then(|segment| segment.parts.get(part))
+                    .map(|part| part.data.as_ref().clone()),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        Ok(match data {
+            Some(data) => {
+                HttpResponse::new(200, "video/mp4", data).with_header("Cache-Control", "max-age=60")
+            }
+            None => HttpResponse::not_found(),
+        })
+    }
+
+    /// The stream, started if it is not running
+    async fn get(
+        &self,
+        camera: &NeoInstance,
+        name: &str,
+        kind: StreamKind,
+    ) -> AnyResult<Arc<HlsStream>> {
+        let key = (name.to_string(), kind);
+        // Held while starting so that two requests do not start the same stream
+        let mut streams = self.streams.lock().await;
+        if let Some(stream) = streams.get(&key) {
+            return Ok(stream.clone());
+        }
+
+        let stream = camera.stream(kind).await?;
+        let mut stream_config = stream.config.clone();
+        let vid_format = timeout(
+            Duration::from_secs(15),
+            stream_config.wait_for(|config| config.vid_ready()),
+        )
+        .await
+        .with_context(|| "Timed out waiting for the stream")??
+        .vid_format;
+        // Audio is optional so only wait a short while for it
+        let _ = timeout(
+            Duration::from_secs(2),
+            stream_config.wait_for(|config| config.aud_ready()),
+        )
+        .await;
+        let aud_format = stream_config.borrow().aud_format;
+
+        let mut pipeline = HlsPipeline::new(vid_format, aud_format, &self.http_config)?;
+        let (playlist_tx, playlist_rx) = watch(Playlist::default());
+        let hls_stream = Arc::new(HlsStream {
+            playlist: playlist_rx,
+            last_used: Mutex::new(Instant::now()),
+        });
+        log::info!("{name}: Starting the HLS stream {kind:?}");
+
+        let sink = pipeline.sink.clone();
+        let http_config = self.http_config.clone();
+        tokio::task::spawn_blocking(move || read_fragments(sink, playlist_tx, &http_config));
+
+        let thread_stream = hls_stream.clone();
+        let thread_streams = self.streams.clone();
+        let thread_cancel = self.cancel.clone();
+        let thread_name = name.to_string();
+        tokio::task::spawn(async move {
+            let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+            let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+            let mut check = interval(Duration::from_secs(5));
+            loop {
+                tokio::select! {
+                    _ = thread_cancel.cancelled() => break,
+                    frame = vid.next() => {
+                        let Some(frame) = frame else {
+                            log::debug!("{thread_name}: The stream stopped");
+                            break;
+                        };
+                        // Lagged frames are skipped, the player recovers at the next keyframe
+                        let Ok(frame) = frame else {
+                            continue;
+                        };
+                        if let Err(e) = pipeline.push_video(&frame) {
+                            log::warn!("{thread_name}: HLS stream failed: {e:?}");
+                            break;
+                        }
+                    }
+                    Some(frame) = aud.next() => {
+                        if let Ok(frame) = frame {
+                            let _ = pipeline.push_audio(&frame);
+                        }
+                    }
+                    _ = check.tick() => {
+                        if thread_stream.is_idle() {
+                            break;
+                        }
+                    }
+                }
+            }
+            log::info!("{thread_name}: Stopping the HLS stream {:?}", key.1);
+            let mut streams = thread_streams.lock().await;
+            if streams
+                .get(&key)
+                .is_some_and(|stream| Arc::ptr_eq(stream, &thread_stream))
+            {
+                streams.remove(&key);
+            }
+            // Stopping the pipeline also ends `read_fragments`
+            drop(pipeline);
+            drop(stream);
+        });
+
+        streams.insert((name.to_string(), kind), hls_stream.clone());
+        Ok(hls_stream)
+    }
+}
+
+/// Move the fragments from the muxer into the playlist until the pipeline stops
+fn read_fragments(sink: AppSink, playlist: WatchSender<Playlist>, http_config: &HttpConfig) {
+    let fallback = if http_config.hls_low_latency {
+        http_config.hls_part_duration as f64 / 1000.0
+    } else {
+        http_config.hls_segment_duration as f64
+    };
+    while let Ok(sample) = sink.pull_sample() {
+        let buffers: Vec<&BufferRef> = match (sample.buffer_list(), sample.buffer()) {
+            (Some(list), _) => list.iter().collect(),
+            (None, Some(buffer)) => vec![buffer],
+            (None, None) => continue,
+        };
+        let mut header = vec![];
+        let mut data = vec![];
+        // The first buffer after the header is the `moof`, which holds the duration
+        let mut first = None;
+        for buffer in buffers {
+            let Ok(map) = buffer.map_readable() else {
+                continue;
+            };
+            if buffer.flags().contains(BufferFlags::HEADER) {
+                header.extend_from_slice(map.as_slice());
+            } else {
+                first.get_or_insert((
+                    !buffer.flags().contains(BufferFlags::DELTA_UNIT),
+                    buffer.duration(),
+                ));
+                data.extend_from_slice(map.as_slice());
+            }
+        }
+
+        playlist.send_modify(|playlist| {
+            if !header.is_empty() {
+                playlist.init = Some(Arc::new(header));
+            }
+            if let Some((new_fragment, duration)) = first {
+                let part = Part {
+                    duration: duration
+                        .map(|duration| duration.nseconds() as f64 / 1e9)
+                        .unwrap_or(fallback),
+                    data: Arc::new(data),
+                    independent: new_fragment,
+                };
+                playlist.add(part, new_fragment, http_config.hls_segments);
+                // Without parts the fragment is the whole segment
+                if !http_config.hls_low_latency {
+                    playlist.finish_segment(http_config.hls_segments);
+                }
+            }
+        });
+    }
+}
+
+/// Muxes the frames of a stream into fragmented mp4
+struct HlsPipeline {
+    pipeline: Pipeline,
+    vid_source: AppSrc,
+    aud_source: Option<AppSrc>,
+    sink: AppSink,
+    start: Option<Duration>,
+}
+
+impl HlsPipeline {
+    fn new(
+        vid_format: VidFormat,
+        aud_format: AudFormat,
+        http_config: &HttpConfig,
+    ) -> AnyResult<Self> {
+        gstreamer::init()
+            .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+        // Both can be in fragmented mp4, only Safari plays H265 though
+        let parser = match vid_format {
+            VidFormat::H264 => "h264parse",
+            VidFormat::H265 => "h265parse",
+            VidFormat::None => return Err(anyhow!("Video format is not yet known")),
+        };
+        // The caps of the source and how to get AAC from it
+        let audio = match aud_format {
+            AudFormat::Aac => Some((String::new(), "aacparse")),
+            AudFormat::Adpcm(block_size) => Some((
+                format!(
+                    " caps=\"audio/x-adpcm,layout=dvi,block_align={block_size},channels=1,rate=8000\""
+                ),
+                "adpcmdec ! audioconvert ! audioresample ! avenc_aac ! aacparse",
+            )),
+            AudFormat::None => None,
+        };
+        let fragment_duration = ClockTime::from_seconds(http_config.hls_segment_duration);
+        let chunk_duration = if http_config.hls_low_latency {
+            format!(
+                " chunk-duration={}",
+                ClockTime::from_mseconds(http_config.hls_part_duration).nseconds()
+            )
+        } else {
+            String::new()
+        };
+        let mut launch_str = format!(
+            "isofmp4mux name=themux fragment-duration={}{chunk_duration} \
+            ! appsink name=thesink sync=false buffer-list=true \
+            appsrc name=thevidsource format=time \
+            ! {parser} \
+            ! themux.",
+            fragment_duration.nseconds()
+        );
+        if let Some((caps, audio)) = audio.as_ref() {
+            launch_str.push_str(&format!(
+                " appsrc name=theaudsource format=time{caps} \
+                ! {audio} \
+                ! themux."
+            ));
+        }
+        let pipeline = launch_full(&launch_str, None, ParseFlags::empty()).context(
+            "Unable to load the HLS pipeline, ensure isofmp4mux from gst-plugins-rs is installed",
+        )?;
+        let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+        let vid_source = get_source(&pipeline, "thevidsource")?;
+        let aud_source = if audio.is_some() {
+            Some(get_source(&pipeline, "theaudsource")?)
+        } else {
+            None
+        };
+        let sink = pipeline
+            .by_name("thesink")
+            .and_then(|sink| sink.dynamic_cast::<AppSink>().ok())
+            .ok_or_else(|| {
+                anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins")
+            })?;
+        pipeline.set_state(State::Playing)?;
+        Ok(Self {
+            pipeline,
+            vid_source,
+            aud_source,
+            sink,
+            start: None,
+        })
+    }
+
+    fn push_video(&mut self, frame: &StampedData) -> AnyResult<()> {
+        // Fragments have to start at a keyframe
+        if self.start.is_none() && !frame.keyframe {
+            return Ok(());
+        }
+        let start = *self.start.get_or_insert(frame.ts);
+        push_frame(&self.vid_source, start, frame)
+    }
+
+    fn push_audio(&mut self, frame: &StampedData) -> AnyResult<()> {
+        // Audio before the first video frame has nothing to play with
+        if let (Some(source), Some(start)) = (self.aud_source.as_ref(), self.start) {
+            if frame.ts >= start {
+                push_frame(source, start, frame)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for HlsPipeline {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipeline.set_state(State::Null) {
+            log::warn!("Error in gstreamer when setting state to Null: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//!
+//! # Neolink HTTP
+//!
+//! This module serves http endpoints alongside the rtsp server
+//!
+//! It is enabled by adding an `[http]` table to the config
+//!
+//! ```toml
+//! [http]
+//! bind = "0.0.0.0"
+//! port = 8080
+//! ```
+//!
+//! # Endpoints
+//!
+//! - `GET /<camera>/snapshot.jpg`: A jpeg of the current frame
+//! - `GET /<camera>/<stream>/hls/index.m3u8`: The stream as HLS
+//! - `/api/..`: The json api when `api = true`, see [`api`]
+//! - `/api/streams`, `/api/webrtc`, ..: The go2rtc api when `go2rtc = true`,
+//!   see [`go2rtc`]
+//! - `GET /`: The web ui when `ui = true`
+//!
+//! When users are defined in the config the endpoints require http basic
+//! auth with one of those users. The camera's `permitted_users` are respected.
+//! Requests through the ingress of Home Assistant are not asked for auth, see
+//! [`crate::supervisor`]
+//!
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use log::*;
+use subtle::ConstantTimeEq;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+mod api;
+mod events;
+mod go2rtc;
+#[cfg(feature = "gstreamer")]
+pub(crate) mod hls;
+pub(crate) mod server;
+pub(crate) mod snapshot;
+mod thumbnails;
+
+use crate::{
+    common::NeoReactor,
+    config::{CameraConfig, Config, HttpConfig},
+    AnyResult,
+};
+use server::{HttpRequest, HttpResponse};
+
+const UI: &str = include_str!("ui.html");
+
+/// Run the http server until cancelled
+pub(crate) async fn main(
+    http_config: HttpConfig,
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let listener = TcpListener::bind((http_config.bind_addr.as_str(), http_config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind http server to {}:{}",
+                http_config.bind_addr, http_config.port
+            )
+        })?;
+    info!(
+        "Starting HTTP Server at {}:{}",
+        http_config.bind_addr, http_config.port
+    );
+
+    #[cfg(feature = "events")]
+    let events = match reactor.config().await?.borrow().events.as_ref() {
+        Some(events_config) => {
+            events::Events::Database(crate::events::EventDb::open(&events_config.database)?)
+        }
+        None => events::Events::Recent(Default::default()),
+    };
+    #[cfg(not(feature = "events"))]
+    let events = events::Events::Recent(Default::default());
+    let events_cancel = cancel.clone();
+    let watching = async {
+        if http_config.api || http_config.ui {
+            events.run(reactor.clone(), events_cancel).await
+        } else {
+            AnyResult::Ok(())
+        }
+    };
+
+    #[cfg(feature = "gstreamer")]
+    let hls = hls::HlsStreams::new(http_config.clone(), cancel.clone());
+    let thread_events = events.clone();
+    let thread_reactor = reactor.clone();
+    let thread_config = http_config.clone();
+    let server = server::serve(listener, cancel, move |request| {
+        let reactor = thread_reactor.clone();
+        let http_config = thread_config.clone();
+        let events = thread_events.clone();
+        #[cfg(feature = "gstreamer")]
+        let hls = hls.clone();
+        async move {
+            match handle(
+                request,
+                &reactor,
+                &http_config,
+                &events,
+                #[cfg(feature = "gstreamer")]
+                &hls,
+            )
+            .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("HTTP request failed: {e:?}");
+                    HttpResponse::text(500, format!("{e}"))
+                }
+            }
+        }
+    });
+
+    let (watched, served) = tokio::join!(watching, server);
+    if let Err(e) = watched {
+        warn!("Stopped keeping the recent events: {e:?}");
+    }
+    served
+}
+
+async fn handle(
+    request: HttpRequest,
+    reactor: &NeoReactor,
+    http_config: &HttpConfig,
+    events: &events::Events,
+    #[cfg(feature = "gstreamer")] hls: &hls::HlsStreams,
+) -> AnyResult<HttpResponse> {
+    let config = reactor.config().await?.borrow().clone();
+    let path = request.path.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    match path.as_slice() {
+        ["api", endpoint @ ("streams" | "frame.jpeg" | "stream.m3u8" | "webrtc" | "ws")]
+            if http_config.go2rtc =>
+        {
+            go2rtc::handle(&request, endpoint, reactor, &config).await
+        }
+        ["api", rest @ ..] if http_config.api || http_config.ui => {
+            api::handle(&request, rest, reactor, &config, http_config, events).await
+        }
+        [] | ["index.html"] if http_config.ui => {
+            if request.method != "GET" && request.method != "HEAD" {
+                return Ok(HttpResponse::text(405, "Method Not Allowed"));
+            }
+            // The page holds nothing private, the api calls it makes are authorised
+            Ok(
+                HttpResponse::new(200, "text/html; charset=utf-8", UI.as_bytes().to_vec())
+                    .with_header("Cache-Control", "no-store"),
+            )
+        }
+        [name, "snapshot.jpg"] => {
+            if request.method != "GET" && request.method != "HEAD" {
+                return Ok(HttpResponse::text(405, "Method Not Allowed"));
+            }
+            let Some(camera_config) = config
+                .cameras
+                .iter()
+                .find(|cam| cam.enabled && cam.name == *name)
+            else {
+                return Ok(HttpResponse::not_found());
+            };
+            if let Err(response) = authorise(&request, &config, camera_config) {
+                return Ok(response);
+            }
+            let camera = reactor.get(name).await?;
+            let jpeg = snapshot::snapshot(&camera).await?;
+            Ok(HttpResponse::jpeg(jpeg))
+        }
+        #[cfg(feature = "gstreamer")]
+        [name, stream, "hls", file] => {
+            use neolink_core::bc_protocol::StreamKind;
+            if request.method != "GET" && request.method != "HEAD" {
+                return Ok(HttpResponse::text(405, "Method Not Allowed"));
+            }
+            let Some(camera_config) = config
+                .cameras
+                .iter()
+                .find(|cam| cam.enabled && cam.name == *name)
+            else {
+                return Ok(HttpResponse::not_found());
+            };
+            let kind = match *stream {
+                "main" => StreamKind::Main,
+                "sub" => StreamKind::Sub,
+                "extern" => StreamKind::Extern,
+                _ => return Ok(HttpResponse::not_found()),
+            };
+            if !camera_config.stream_kinds().contains(&kind) {
+                return Ok(HttpResponse::not_found());
+            }
+            if let Err(response) = authorise(&request, &config, camera_config) {
+                return Ok(response);
+            }
+            let camera = reactor.get(name).await?;
+            Ok(hls
+                .handle(&camera, name, kind, file, &request.query)
+                .await?
+                // So that pages on other sites can embed the stream
+                .with_header("Access-Control-Allow-Origin", "*"))
+        }
+        _ => Ok(HttpResponse::not_found()),
+    }
+}
+
+/// Compare a password or token in a time that does not depend on how much
+/// of it matches
+pub(crate) fn secure_eq(expected: &str, given: &str) -> bool {
+    bool::from(expected.as_bytes().ct_eq(given.as_bytes()))
+}
+
+/// Check the basic auth of the request against the users in the config
+pub(crate) fn authorise(
+    request: &HttpRequest,
+    config: &Config,
+    camera_config: &CameraConfig,
+) -> Result<(), HttpResponse> {
+    // Home Assistant has checked the user of its ingress
+    if config.users.is_empty() || crate::supervisor::is_ingress(request) {
+        return Ok(());
+    }
+    let realm = config.auth_realm.as_deref().unwrap_or("Neolink");
+
+    let credentials = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    let Some((name, pass)) = credentials
+        .as_deref()
+        .and_then(|credentials| credentials.split_once(':'))
+    else {
+        return Err(HttpResponse::unauthorized(realm));
+    };
+    if !config
+        .users
+        .iter()
+        .any(|user| user.name == name && secure_eq(user.pass.as_deref().unwrap_or(""), pass))
+    {
+        return Err(HttpResponse::unauthorized(realm));
+    }
+
+    match &camera_config.permitted_users {
+        Some(permitted) if !permitted.iter().any(|u| u == "anyone" || u == name) => {
+            Err(HttpResponse::text(403, "Forbidden"))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,374 @@
+//! A minimal HTTP/1.1 server
+//!
+//! This only supports what neolink needs: one request per
+//! connection, a `Content-Length` body and a complete response
+use anyhow::{anyhow, Context};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
+    time::{timeout, Duration},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::AnyResult;
+
+/// Largest body we will accept
+const MAX_BODY: usize = 1024 * 1024;
+/// Largest header section we will accept
+const MAX_HEADER: usize = 64 * 1024;
+
+pub(crate) struct HttpRequest {
+    pub(crate) method: String,
+    /// The decoded path components, without empty ones
+    pub(crate) path: Vec<String>,
+    pub(crate) query: HashMap<String, String>,
+    /// Header names are lower case
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: Vec<u8>,
+    /// The address that the request came from
+    pub(crate) peer: IpAddr,
+}
+
+impl HttpRequest {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(|s| s.as_str())
+    }
+}
+
+pub(crate) struct HttpResponse {
+    pub(crate) status: u16,
+    pub(crate) content_type: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub(crate) fn new<T: Into<String>>(status: u16, content_type: T, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            content_type: content_type.into(),
+            headers: vec![],
+            body,
+        }
+    }
+
+    pub(crate) fn text<T: AsRef<str>>(status: u16, text: T) -> Self {
+        Self::new(
+            status,
+            "text/plain; charset=utf-8",
+            text.as_ref().as_bytes().to_vec(),
+        )
+    }
+
+    pub(crate) fn json<T: serde::Serialize>(status: u16, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self::new(status, "application/json", body),
+            Err(e) => Self::text(500, format!("Failed to serialise reply: {e}")),
+        }
+    }
+
+    pub(crate) fn jpeg(body: Vec<u8>) -> Self {
+        Self::new(200, "image/jpeg", body).with_header("Cache-Control", "no-store")
+    }
+
+    pub(crate) fn not_found() -> Self {
+        Self::text(404, "Not Found")
+    }
+
+    pub(crate) fn unauthorized(realm: &str) -> Self {
+        Self::text(401, "Unauthorized")
+            .with_header("WWW-Authenticate", &format!("Basic realm=\"{realm}\""))
+    }
+
+    pub(crate) fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            204 => "No Content",
+            302 => "Found",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            _ => "Unknown",
+        }
+    }
+}
+
+/// Accept connections until cancelled and pass each request to the handler
+pub(crate) async fn serve<F, Fut>(
+    listener: TcpListener,
+    cancel: CancellationToken,
+    handler: F,
+) -> AnyResult<()>
+where
+    F: Fn(HttpRequest) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = HttpResponse> + Send,
+{
+    let mut set = JoinSet::new();
+    loop {
+        let (stream, addr) = tokio::select! {
+            _ = cancel.cancelled() => break,
+            v = listener.accept() => v?,
+        };
+        // Reap finished connections
+        while set.try_join_next().is_some() {}
+
+        let handler = handler.clone();
+        let cancel = cancel.clone();
+        set.spawn(async move {
+            tokio::select! {
+                _ = cancel.cancelled() => {},
+                v = handle_connection(stream, addr.ip(), handler) => {
+                    if let Err(e) = v {
+                        log::debug!("HTTP connection from {addr}: {e:?}");
+                    }
+                },
+            }
+        });
+    }
+    set.shutdown().await;
+    Ok(())
+}
+
+async fn handle_connection<F, Fut>(mut stream: TcpStream, peer: IpAddr, handler: F) -> AnyResult<()>
+where
+    F: Fn(HttpRequest) -> Fut,
+    Fut: Future<Output = HttpResponse>,
+{
+    let request = timeout(Duration::from_secs(10), read_request(&mut stream, peer))
+        .await
+        .with_context(|| "Timeout reading request")?;
+    let response = match request {
+        Ok(request) => handler(request).await,
+        Err(e) => {
+            log::debug!("Bad HTTP request: {e:?}");
+            HttpResponse::text(400, "Bad Request")
+        }
+    };
+    write_response(&mut stream, &response).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Read a line of at most `limit` bytes, so that a line without an end cannot
+/// grow without bound
+async fn read_line<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    line: &mut String,
+    limit: usize,
+) -> AnyResult<usize> {
+    let read = reader.take(limit as u64).read_line(line).await?;
+    if read == limit && !line.ends_with('\n') {
+        return Err(anyhow!("Header section is too large"));
+    }
+    Ok(read)
+}
+
+async fn read_request<R: AsyncRead + Unpin>(stream: R, peer: IpAddr) -> AnyResult<HttpRequest> {
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    let mut header_size = read_line(&mut reader, &mut line, MAX_HEADER).await?;
+    let mut parts = line.split_whitespace();
+    let method = parts
+        .next()
+        .ok_or_else(|| anyhow!("Missing method"))?
+        .to_uppercase();
+    let target = parts
+        .next()
+        .ok_or_else(|| anyhow!("Missing target"))?
+        .to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        let read = read_line(&mut reader, &mut line, MAX_HEADER - header_size).await?;
+        header_size += read;
+        if read == 0 {
+            return Err(anyhow!("Header section is invalid"));
+        }
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = trimmed.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .map(|v| v.parse::<usize>())
+        .transpose()?
+        .unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(anyhow!("Body is too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+    let path = path
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .map(percent_decode)
+        .collect();
+    let query = query
+        .split('&')
+        .filter(|c| !c.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect();
+
+    Ok(HttpRequest {
+        method,
+        path,
+        query,
+        headers,
+        body,
+        peer,
+    })
+}
+
+async fn write_response(stream: &mut TcpStream, response: &HttpResponse) -> AnyResult<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len()
+    );
+    for (name, value) in response.headers.iter() {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(b) => {
+                        out.push(b);
+                        i += 3;
+                    }
+                    None => {
+                        out.push(b'%');
+                        i += 1;
+                    }
+                }
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    async fn parse(raw: &str) -> AnyResult<HttpRequest> {
+        read_request(raw.as_bytes(), IpAddr::V4(Ipv4Addr::LOCALHOST)).await
+    }
+
+    #[tokio::test]
+    async fn test_request_line_and_headers() {
+        let request =
+            parse("get /Garage/snapshot.jpg HTTP/1.1\r\nHost: neolink\r\nX-Token:  abc \r\n\r\n")
+                .await
+                .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, vec!["Garage", "snapshot.jpg"]);
+        assert_eq!(request.header("host"), Some("neolink"));
+        assert_eq!(request.header("X-TOKEN"), Some("abc"));
+        assert!(request.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_path_and_query_are_decoded() {
+        let request = parse("GET //api/cameras/Front%20Door/?a=1&b=x%2Fy&flag HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(request.path, vec!["api", "cameras", "Front Door"]);
+        assert_eq!(request.query.get("a").map(String::as_str), Some("1"));
+        assert_eq!(request.query.get("b").map(String::as_str), Some("x/y"));
+        assert_eq!(request.query.get("flag").map(String::as_str), Some(""));
+    }
+
+    #[tokio::test]
+    async fn test_body_is_read_to_its_length() {
+        let request = parse("POST /api HTTP/1.1\r\nContent-Length: 4\r\n\r\n{}{}trailing")
+            .await
+            .unwrap();
+        assert_eq!(request.body, b"{}{}");
+    }
+
+    #[tokio::test]
+    async fn test_bad_requests() {
+        assert!(parse("").await.is_err());
+        assert!(parse("GET\r\n\r\n").await.is_err());
+        // The headers never end
+        assert!(parse("GET / HTTP/1.1\r\nHost: neolink\r\n").await.is_err());
+        assert!(parse("POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n")
+            .await
+            .is_err());
+        assert!(parse("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort")
+            .await
+            .is_err());
+        let too_large = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert!(parse(&too_large).await.is_err());
+        // A request line or header that is larger than the header section
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEADER));
+        assert!(parse(&long_line).await.is_err());
+        let long_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_HEADER));
+        assert!(parse(&long_header).await.is_err());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b"), "a b");
+        assert_eq!(percent_decode("%41%42"), "AB");
+        // Invalid or cut off escapes are kept as they are
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}
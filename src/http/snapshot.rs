@@ -0,0 +1,25 @@
+//! Creates still images for the http server
+//!
+//! The still is taken from the shared stream so that a camera which is
+//! already streaming does not need a second connection. If that fails, or
+//! gstreamer is not available, the camera's SNAP command is used instead
+use crate::{common::NeoInstance, AnyResult};
+
+/// Get a jpeg of the current frame of the camera
+pub(crate) async fn snapshot(camera: &NeoInstance) -> AnyResult<Vec<u8>> {
+    #[cfg(feature = "gstreamer")]
+    match crate::image::jpeg_from_stream(camera, neolink_core::bc_protocol::StreamKind::Main).await
+    {
+        Ok(jpeg) => return Ok(jpeg),
+        Err(e) => log::debug!("Could not get snapshot from the stream: {e:?}"),
+    }
+
+    camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let data = cam.get_snapshot().await?;
+                AnyResult::Ok(data)
+            })
+        })
+        .await
+}
@@ -0,0 +1,102 @@
+//! The thumbnails of the recordings for the timeline of the web ui
+//!
+//! The recording writes them to `<date>/thumbnails/<time>.jpg`, or
+//! `<time>-motion.jpg` at the start of a motion, below the directory of the
+//! camera with the date and time in UTC
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    utils::{from_utc, utc},
+    AnyResult,
+};
+
+static RE_DATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d{4})-(\d{2})-(\d{2})$").unwrap());
+static RE_FILE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d{2})-(\d{2})-(\d{2})(-motion)?\.jpg$").unwrap());
+
+#[derive(Debug, Serialize)]
+pub(super) struct Thumbnail {
+    /// Unix time in seconds
+    time: u64,
+    /// If it was taken at the start of a motion
+    motion: bool,
+    /// `<date>/<file>` below `/api/cameras/<camera>/thumbnails/`
+    file: String,
+}
+
+/// At most `count` thumbnails between two unix times spread over the range, oldest first
+pub(super) async fn list(
+    directory: PathBuf,
+    from: u64,
+    to: u64,
+    count: usize,
+) -> AnyResult<Vec<Thumbnail>> {
+    tokio::task::spawn_blocking(move || {
+        let mut found = vec![];
+        for day in (from / 86400)..=(to / 86400) {
+            let (year, month, mday, ..) = utc(UNIX_EPOCH + Duration::from_secs(day * 86400));
+            let date = format!("{year:04}-{month:02}-{mday:02}");
+            let Ok(entries) = std::fs::read_dir(directory.join(&date).join("thumbnails")) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let file = entry.file_name().to_string_lossy().to_string();
+                let Some(caps) = RE_FILE.captures(&file) else {
+                    continue;
+                };
+                let time = from_utc(
+                    year,
+                    month,
+                    mday,
+                    caps[1].parse()?,
+                    caps[2].parse()?,
+                    caps[3].parse()?,
+                )
+                .duration_since(UNIX_EPOCH)?
+                .as_secs();
+                if (from..=to).contains(&time) {
+                    found.push(Thumbnail {
+                        time,
+                        motion: caps.get(4).is_some(),
+                        file: format!("{date}/{file}"),
+                    });
+                }
+            }
+        }
+        found.sort_by_key(|thumbnail| thumbnail.time);
+
+        // Evenly spaced across the range so that the timeline covers all of it
+        if found.len() > count {
+            let len = found.len();
+            let mut index = 0;
+            found.retain(|_| {
+                let keep = (index * count) / len != ((index + 1) * count) / len;
+                index += 1;
+                keep
+            });
+        }
+        Ok(found)
+    })
+    .await?
+}
+
+/// The jpeg of a thumbnail from [`list`], `None` if there is no such thumbnail
+pub(super) async fn read(directory: PathBuf, date: &str, file: &str) -> AnyResult<Option<Vec<u8>>> {
+    // Only the names that the recording writes so the path stays in the directory
+    if !RE_DATE.is_match(date) || !RE_FILE.is_match(file) {
+        return Ok(None);
+    }
+    let path = directory.join(date).join("thumbnails").join(file);
+    tokio::task::spawn_blocking(move || match std::fs::read(&path) {
+        Ok(jpeg) => Ok(Some(jpeg)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    })
+    .await?
+}
@@ -1,10 +1,11 @@
 use std::path::Path;
 
 use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
 use gstreamer::{
     parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
 };
-use gstreamer_app::AppSrc;
+use gstreamer_app::{AppSink, AppSrc};
 use tokio::{
     sync::{
         self,
@@ -14,11 +15,14 @@ use tokio::{
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::{common::VidFormat, AnyResult};
+use crate::{
+    common::{StampedData, VidFormat},
+    AnyResult,
+};
 
 #[derive(Debug)]
 enum GstControl {
-    Data(std::sync::Arc<Vec<u8>>),
+    Data(Bytes),
     Eos,
 }
 
@@ -30,7 +34,7 @@ pub(super) struct GstSender {
 }
 
 impl GstSender {
-    pub(super) async fn send(&self, buf: std::sync::Arc<Vec<u8>>) -> Result<()> {
+    pub(super) async fn send(&self, buf: Bytes) -> Result<()> {
         self.sender
             .send(GstControl::Data(buf))
             .await
@@ -95,13 +99,7 @@ async fn output(pipeline: Pipeline) -> Result<GstSender> {
                     tokio::task::yield_now().await;
                     match control {
                         GstControl::Data(buf) => {
-                            let mut gst_buf = gstreamer::Buffer::with_size(buf.len()).unwrap();
-                            {
-                                let gst_buf_mut = gst_buf.get_mut().unwrap();
-                                let mut gst_buf_data = gst_buf_mut.map_writable().unwrap();
-                                gst_buf_data.copy_from_slice(&buf);
-                            }
-                            source.push_buffer(gst_buf).map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+                            source.push_buffer(gstreamer::Buffer::from_slice(buf)).map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
                         }
                         GstControl::Eos => {
                             source.end_of_stream().map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
@@ -162,6 +160,166 @@ fn start_pipeline(pipeline: Pipeline) -> Result<()> {
     Ok(())
 }
 
+/// Decode a single keyframe into a jpeg in memory
+///
+/// This blocks until gstreamer has produced the image
+pub(crate) fn frame_to_jpeg(format: VidFormat, frame: &[u8]) -> Result<Vec<u8>> {
+    decode_to_jpeg(format, frame, "")
+}
+
+/// Decode a single keyframe into a jpeg scaled down to `width`
+///
+/// This blocks until gstreamer has produced the image
+pub(crate) fn frame_to_thumbnail(format: VidFormat, frame: &[u8], width: u32) -> Result<Vec<u8>> {
+    decode_to_jpeg(
+        format,
+        frame,
+        &format!("! videoscale ! video/x-raw,width={width},pixel-aspect-ratio=1/1 "),
+    )
+}
+
+/// Decode a keyframe with `scale` as the elements between the decoding and the jpeg
+fn decode_to_jpeg(format: VidFormat, frame: &[u8], scale: &str) -> Result<Vec<u8>> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+    let parser = match format {
+        VidFormat::H264 => "h264parse",
+        VidFormat::H265 => "h265parse",
+        VidFormat::None => return Err(anyhow!("Video format is not yet known")),
+    };
+    let launch_str = format!(
+        "appsrc name=thesource \
+        ! {parser} \
+        ! decodebin \
+        ! videoconvert \
+        {scale}! jpegenc snapshot=TRUE \
+        ! appsink name=thesink sync=false"
+    );
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?;
+    let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+        anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+    })?;
+    let source = get_source(&pipeline)?;
+    let sink = pipeline
+        .by_name("thesink")
+        .and_then(|sink| sink.dynamic_cast::<AppSink>().ok())
+        .ok_or_else(|| anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins"))?;
+
+    pipeline.set_state(State::Playing)?;
+    let res = (|| {
+        source
+            .push_buffer(gstreamer::Buffer::from_slice(frame.to_vec()))
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        source
+            .end_of_stream()
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        let sample = sink
+            .try_pull_sample(ClockTime::from_seconds(10))
+            .ok_or_else(|| anyhow!("Timed out decoding the frame"))?;
+        let buffer = sample
+            .buffer()
+            .ok_or_else(|| anyhow!("Decoded sample has no buffer"))?;
+        let map = buffer
+            .map_readable()
+            .map_err(|e| anyhow!("Could not read decoded buffer: {e:?}"))?;
+        Ok(map.as_slice().to_vec())
+    })();
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+    res
+}
+
+/// Mux (`mp4`) or transcode (`gif`) a run of frames into a clip in memory
+///
+/// The frames should start on a keyframe. This blocks until gstreamer has produced the clip
+pub(crate) fn frames_to_clip(
+    format: VidFormat,
+    frames: &[StampedData],
+    clip_format: &str,
+) -> Result<Vec<u8>> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+    let parser = match format {
+        VidFormat::H264 => "h264parse",
+        VidFormat::H265 => "h265parse",
+        VidFormat::None => return Err(anyhow!("Video format is not yet known")),
+    };
+    let encoder = match clip_format {
+        "gif" => {
+            "decodebin \
+            ! videoconvert \
+            ! videoscale \
+            ! video/x-raw,width=480,pixel-aspect-ratio=1/1 \
+            ! videorate \
+            ! video/x-raw,framerate=5/1 \
+            ! gifenc"
+        }
+        // Fragmented so that it can be written without seeking back to the start
+        _ => "mp4mux fragment-duration=1000 streamable=true",
+    };
+    let launch_str = format!(
+        "appsrc name=thesource format=time \
+        ! {parser} \
+        ! {encoder} \
+        ! appsink name=thesink sync=false"
+    );
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?;
+    let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+        anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+    })?;
+    let source = get_source(&pipeline)?;
+    let sink = pipeline
+        .by_name("thesink")
+        .and_then(|sink| sink.dynamic_cast::<AppSink>().ok())
+        .ok_or_else(|| anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins"))?;
+
+    pipeline.set_state(State::Playing)?;
+    let res = (|| {
+        let start = frames.first().map(|frame| frame.ts).unwrap_or_default();
+        for frame in frames {
+            let mut buf = gstreamer::Buffer::from_slice(frame.data.clone());
+            {
+                let buf = buf
+                    .get_mut()
+                    .ok_or_else(|| anyhow!("Could not write to the gstreamer buffer"))?;
+                let ts = ClockTime::from_nseconds(frame.ts.saturating_sub(start).as_nanos() as u64);
+                buf.set_pts(ts);
+                buf.set_dts(ts);
+            }
+            source
+                .push_buffer(buf)
+                .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        }
+        source
+            .end_of_stream()
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        let mut clip = vec![];
+        while !sink.is_eos() {
+            let Some(sample) = sink.try_pull_sample(ClockTime::from_seconds(30)) else {
+                if sink.is_eos() {
+                    break;
+                }
+                return Err(anyhow!("Timed out encoding the clip"));
+            };
+            let buffer = sample
+                .buffer()
+                .ok_or_else(|| anyhow!("Encoded sample has no buffer"))?;
+            let map = buffer
+                .map_readable()
+                .map_err(|e| anyhow!("Could not read encoded buffer: {e:?}"))?;
+            clip.extend_from_slice(map.as_slice());
+        }
+        Ok(clip)
+    })();
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+    res
+}
+
 fn get_source(pipeline: &Pipeline) -> Result<AppSrc> {
     let source = pipeline
         .by_name("thesource")
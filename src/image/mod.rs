@@ -27,8 +27,12 @@ use tokio_stream::wrappers::BroadcastStream;
 mod cmdline;
 mod gst;
 
-use crate::common::{NeoReactor, StampedData};
+use crate::{
+    common::{NeoInstance, NeoReactor, StampedData},
+    AnyResult,
+};
 pub(crate) use cmdline::Opt;
+pub(crate) use gst::{frame_to_jpeg, frame_to_thumbnail, frames_to_clip};
 
 /// Entry point for the image subcommand
 ///
@@ -102,3 +106,45 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
 
     Ok(())
 }
+
+/// Decode the next keyframe of the stream into a jpeg
+///
+/// The stream is shared so a camera which is already streaming does not
+/// need a second connection
+pub(crate) async fn jpeg_from_stream(
+    camera: &NeoInstance,
+    stream_kind: StreamKind,
+) -> AnyResult<Vec<u8>> {
+    use tokio::time::{timeout, Duration};
+
+    // Holding the instance keeps the stream active until we have our frame
+    let stream = camera.stream(stream_kind).await?;
+
+    let mut stream_config = stream.config.clone();
+    let vid_format = timeout(
+        Duration::from_secs(15),
+        stream_config.wait_for(|config| config.vid_ready()),
+    )
+    .await
+    .with_context(|| "Timed out waiting for the stream")??
+    .vid_format;
+
+    // Wait for the next keyframe so the image is current
+    let mut frames = BroadcastStream::new(stream.vid.resubscribe());
+    let frame = timeout(Duration::from_secs(15), async {
+        while let Some(frame) = frames.next().await {
+            if let Ok(frame) = frame {
+                if frame.keyframe {
+                    return Some(frame.data);
+                }
+            }
+        }
+        None
+    })
+    .await
+    .with_context(|| "Timed out waiting for a keyframe")?
+    .with_context(|| "Stream ended before a keyframe")?;
+    drop(stream);
+
+    tokio::task::spawn_blocking(move || frame_to_jpeg(vid_format, &frame)).await?
+}
@@ -0,0 +1,317 @@
+//! Writes the log to a file and rotates it
+//!
+//! The file is rotated when it grows past `max_size` or when the hour or day
+//! of `rotate` changes. `neolink.log` becomes `neolink.log.1`, the older ones
+//! move up by one and those past `max_files` are removed
+use chrono::{DateTime, Datelike, Local, Timelike};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::config::LogConfig;
+
+/// When the file is rotated by the time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rotate {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl Rotate {
+    /// The hour or day of the time, the file is rotated when it changes
+    fn period(&self, time: DateTime<Local>) -> Option<(i32, u32, u32)> {
+        match self {
+            Rotate::Never => None,
+            Rotate::Hourly => Some((time.year(), time.ordinal(), time.hour())),
+            Rotate::Daily => Some((time.year(), time.ordinal(), 0)),
+        }
+    }
+}
+
+/// The file settings of the `[log]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct FileSettings {
+    path: PathBuf,
+    /// Bytes, 0 for no limit
+    max_size: u64,
+    rotate: Rotate,
+    max_files: usize,
+}
+
+impl FileSettings {
+    pub(super) fn new(log: &LogConfig) -> Option<Self> {
+        Some(Self {
+            path: log.file.clone()?,
+            max_size: log.max_size.saturating_mul(1024 * 1024),
+            rotate: match log.rotate.as_str() {
+                "never" => Rotate::Never,
+                "hourly" => Rotate::Hourly,
+                _ => Rotate::Daily,
+            },
+            max_files: log.max_files,
+        })
+    }
+}
+
+struct LogFile {
+    settings: FileSettings,
+    file: Option<File>,
+    size: u64,
+    period: Option<(i32, u32, u32)>,
+}
+
+impl LogFile {
+    fn open(&mut self) -> io::Result<()> {
+        if let Some(parent) = self.settings.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.settings.path)?;
+        let metadata = file.metadata()?;
+        self.size = metadata.len();
+        // A file from an earlier run is rotated if its period is over
+        let modified = match metadata.modified() {
+            Ok(modified) if self.size > 0 => DateTime::<Local>::from(modified),
+            _ => Local::now(),
+        };
+        self.period = self.settings.rotate.period(modified);
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        let max_files = self.settings.max_files;
+        remove(&numbered(&self.settings.path, max_files.max(1)))?;
+        for n in (1..max_files).rev() {
+            rename(
+                &numbered(&self.settings.path, n),
+                &numbered(&self.settings.path, n + 1),
+            )?;
+        }
+        if max_files > 0 {
+            rename(&self.settings.path, &numbered(&self.settings.path, 1))?;
+        } else {
+            remove(&self.settings.path)?;
+        }
+        self.open()
+    }
+
+    fn needs_rotating(&self, len: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_big = self.settings.max_size > 0 && self.size + len as u64 > self.settings.max_size;
+        too_big || self.settings.rotate.period(Local::now()) != self.period
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.needs_rotating(buf.len()) {
+            self.rotate()?;
+        } else if self.size == 0 {
+            // An empty file is for the period of its first line
+            self.period = self.settings.rotate.period(Local::now());
+        }
+        let file = self
+            .file
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "The log file is not open"))?;
+        file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The log file shared by the lines of all of the tasks
+#[derive(Clone)]
+pub(super) struct FileWriter(Arc<Mutex<LogFile>>);
+
+impl FileWriter {
+    pub(super) fn new(settings: FileSettings) -> io::Result<Self> {
+        let mut file = LogFile {
+            settings,
+            file: None,
+            size: 0,
+            period: None,
+        };
+        file.open()
+            .map_err(|e| io::Error::new(e.kind(), format!("{:?}: {e}", file.settings.path)))?;
+        Ok(Self(Arc::new(Mutex::new(file))))
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for FileWriter {
+    type Writer = FileWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut numbered = path.as_os_str().to_owned();
+    numbered.push(format!(".{n}"));
+    PathBuf::from(numbered)
+}
+
+/// Rename a file if it exists
+fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        v => v,
+    }
+}
+
+/// Remove a file if it exists
+fn remove(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        v => v,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    /// A log file in a directory of its own that is removed afterwards
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("neolink-log-{}", Uuid::new_v4().simple())))
+        }
+
+        fn settings(&self, max_size: u64, rotate: Rotate, max_files: usize) -> FileSettings {
+            FileSettings {
+                path: self.0.join("neolink.log"),
+                max_size,
+                rotate,
+                max_files,
+            }
+        }
+
+        fn read(&self, name: &str) -> Option<String> {
+            std::fs::read_to_string(self.0.join(name)).ok()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_period() {
+        let time = Local.with_ymd_and_hms(2024, 2, 3, 14, 30, 0).unwrap();
+        assert_eq!(Rotate::Never.period(time), None);
+        assert_eq!(Rotate::Hourly.period(time), Some((2024, 34, 14)));
+        assert_eq!(Rotate::Daily.period(time), Some((2024, 34, 0)));
+        let later = Local.with_ymd_and_hms(2024, 2, 3, 15, 0, 0).unwrap();
+        assert_ne!(Rotate::Hourly.period(time), Rotate::Hourly.period(later));
+        assert_eq!(Rotate::Daily.period(time), Rotate::Daily.period(later));
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = TestDir::new();
+        let mut writer = FileWriter::new(dir.settings(10, Rotate::Never, 2)).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        assert_eq!(dir.read("neolink.log").as_deref(), Some("fourth\n"));
+        assert_eq!(dir.read("neolink.log.1").as_deref(), Some("third\n"));
+        assert_eq!(dir.read("neolink.log.2").as_deref(), Some("second\n"));
+        // Past `max_files` the oldest is removed
+        assert_eq!(dir.read("neolink.log.3"), None);
+    }
+
+    #[test]
+    fn test_lines_fit_under_the_size() {
+        let dir = TestDir::new();
+        let mut writer = FileWriter::new(dir.settings(12, Rotate::Never, 2)).unwrap();
+        for line in ["first\n", "other\n", "third\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        assert_eq!(dir.read("neolink.log").as_deref(), Some("third\n"));
+        assert_eq!(dir.read("neolink.log.1").as_deref(), Some("first\nother\n"));
+    }
+
+    #[test]
+    fn test_no_old_files() {
+        let dir = TestDir::new();
+        let mut writer = FileWriter::new(dir.settings(1, Rotate::Never, 0)).unwrap();
+        writer.write_all(b"first\n").unwrap();
+        writer.write_all(b"second\n").unwrap();
+        assert_eq!(dir.read("neolink.log").as_deref(), Some("second\n"));
+        assert_eq!(dir.read("neolink.log.1"), None);
+    }
+
+    #[test]
+    fn test_rotate_by_period() {
+        let dir = TestDir::new();
+        let mut writer = FileWriter::new(dir.settings(0, Rotate::Daily, 3)).unwrap();
+        writer.write_all(b"today\n").unwrap();
+        writer.write_all(b"still today\n").unwrap();
+        assert_eq!(dir.read("neolink.log.1"), None);
+
+        // As if the day changed since the last line
+        writer.0.lock().unwrap().period = Some((2000, 1, 0));
+        writer.write_all(b"tomorrow\n").unwrap();
+        assert_eq!(dir.read("neolink.log").as_deref(), Some("tomorrow\n"));
+        assert_eq!(
+            dir.read("neolink.log.1").as_deref(),
+            Some("today\nstill today\n")
+        );
+    }
+
+    #[test]
+    fn test_reopen_appends() {
+        let dir = TestDir::new();
+        let settings = dir.settings(20, Rotate::Never, 1);
+        FileWriter::new(settings.clone())
+            .unwrap()
+            .write_all(b"first run\n")
+            .unwrap();
+        // The size of the earlier run counts towards `max_size`
+        let mut writer = FileWriter::new(settings).unwrap();
+        assert_eq!(writer.0.lock().unwrap().size, 10);
+        writer.write_all(b"second run\n").unwrap();
+        assert_eq!(dir.read("neolink.log").as_deref(), Some("second run\n"));
+        assert_eq!(dir.read("neolink.log.1").as_deref(), Some("first run\n"));
+    }
+}
@@ -0,0 +1,494 @@
+//!
+//! # Neolink Logging
+//!
+//! This module writes the log with `tracing` and filters it with the levels
+//! of the config, which can be set for each camera and for each part of
+//! neolink so that one camera can be debugged without the debug lines of all
+//! the others
+//!
+//! ```toml
+//! [log]
+//! level = "info"
+//! format = "json"
+//!   [log.subsystems]
+//!   mqtt = "debug"
+//!   core = "warn"
+//!
+//! [[cameras]]
+//! name = "Driveway"
+//! log_level = "debug"
+//! ```
+//!
+//! The tasks of a camera run in a span with its name such as
+//! `camera{camera=Driveway}`, and those of rtsp, mqtt and recording in spans
+//! of their own. The connections to the camera are in a `bc{conn=1}` span and
+//! its messages are logged with their `msg_id` and `msg_num`. The `log` lines
+//! of neolink and its libraries are written as tracing events in the span
+//! that they are in
+//!
+//! The level of a camera applies to the lines in its spans and is used over
+//! the level of the subsystem. A subsystem is
+//! a module of neolink such as `rtsp`, `mqtt` or `onvif`, `core` for the
+//! camera protocol, or the module path of a library such as `rumqttc`. The
+//! `level` is used for everything else, `RUST_LOG` is used over it when it is
+//! set
+//!
+//! `format = "json"` writes each line as a json object with its spans and
+//! fields, for shipping to a log store such as Loki
+//!
+//! `file` also writes the log to a file, for when nothing keeps the output of
+//! neolink. It is rotated when it is larger than `max_size` megabytes or each
+//! hour or day of `rotate`, and `max_files` of the old files are kept
+//!
+//! ```toml
+//! [log]
+//! file = "/var/log/neolink/neolink.log"
+//! max_size = 10
+//! rotate = "daily"
+//! max_files = 7
+//! ```
+//!
+//! The levels, format and file are applied again when the config is reloaded
+//!
+use log::LevelFilter;
+use once_cell::sync::Lazy;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::{Debug, Write},
+    io::IsTerminal,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+};
+use tracing::{
+    callsite,
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    subscriber::Interest,
+    Event, Level, Metadata, Subscriber,
+};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::{
+    filter::Targets,
+    fmt,
+    layer::{Context, Layered, SubscriberExt},
+    registry::LookupSpan,
+    reload,
+    util::SubscriberInitExt,
+    Layer, Registry,
+};
+
+use crate::{common::NeoReactor, config::Config, AnyResult};
+
+mod file;
+
+use file::{FileSettings, FileWriter};
+
+type Filtered = Layered<NeoFilter, Registry>;
+type Output = Box<dyn Layer<Filtered> + Send + Sync>;
+
+/// The levels that the lines are filtered with
+struct Levels {
+    /// If [`init`] set up the log, the event log of Windows is left alone
+    installed: bool,
+    /// The filter of `RUST_LOG`, if it is set it is used over `base`
+    env: Option<Targets>,
+    /// The most verbose level of `RUST_LOG`
+    env_max: LevelFilter,
+    /// The `level` of the config
+    base: Option<LevelFilter>,
+    /// Module paths and their level, the longest path first
+    modules: Vec<(String, LevelFilter)>,
+    cameras: HashMap<String, LevelFilter>,
+    /// The most verbose of all of the levels
+    max: LevelFilter,
+    /// The `format` and file of the config and the handle to change them
+    settings: OutputSettings,
+    output: Option<reload::Handle<Output, Filtered>>,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self {
+            installed: false,
+            env: None,
+            env_max: LevelFilter::Info,
+            base: None,
+            modules: vec![],
+            cameras: HashMap::new(),
+            max: LevelFilter::Info,
+            settings: Default::default(),
+            output: None,
+        }
+    }
+}
+
+/// Where and how the lines are written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct OutputSettings {
+    format: String,
+    file: Option<FileSettings>,
+}
+
+static LEVELS: Lazy<RwLock<Levels>> = Lazy::new(Default::default);
+/// If a camera has a level, then the lines in its spans are checked one by one
+static CAMERA_LEVELS: AtomicBool = AtomicBool::new(false);
+
+/// The name of the camera that a span is for
+struct CameraSpan(String);
+
+/// Filters the lines with the [`Levels`]
+struct NeoFilter;
+
+impl<S> Layer<S> for NeoFilter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        // Cached until the levels change, see `apply`
+        LEVELS.read().unwrap().interest(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _: Context<'_, S>) -> bool {
+        // The camera is only known from the span so the full check is in
+        // `event_enabled`
+        metadata.is_span() || log_level(metadata.level()) <= LEVELS.read().unwrap().max
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::new("camera");
+        attrs.record(&mut visitor);
+        if let (Some(camera), Some(span)) = (visitor.value, ctx.span(id)) {
+            span.extensions_mut().insert(CameraSpan(camera));
+        }
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        // Otherwise the interest of the callsite has already decided
+        if !event.is_log() && !CAMERA_LEVELS.load(Ordering::Relaxed) {
+            return true;
+        }
+        let levels = LEVELS.read().unwrap();
+        let metadata = event.metadata();
+        if !levels.cameras.is_empty() {
+            if let Some(camera_level) =
+                camera_of(event, &ctx).and_then(|camera| levels.cameras.get(&camera))
+            {
+                return log_level(metadata.level()) <= *camera_level;
+            }
+        }
+        // Lines from the `log` crate have the target of the line
+        match event.normalized_metadata() {
+            Some(normalized) => levels.target_enabled(normalized.target(), metadata.level()),
+            None => levels.target_enabled(metadata.target(), metadata.level()),
+        }
+    }
+}
+
+impl Levels {
+    /// If the lines of a target at a level are written outside of the spans
+    /// of the cameras
+    fn target_enabled(&self, target: &str, level: &Level) -> bool {
+        let level_filter = log_level(level);
+        if let Some((_, module_level)) = self
+            .modules
+            .iter()
+            .find(|(module, _)| in_module(target, module))
+        {
+            return level_filter <= *module_level;
+        }
+        match (self.base, self.env.as_ref()) {
+            (_, Some(env)) => env.would_enable(target, level),
+            (Some(base), None) => level_filter <= base,
+            (None, None) => level_filter <= LevelFilter::Info,
+        }
+    }
+
+    /// If the lines of a callsite are always, never or only sometimes written
+    fn interest(&self, metadata: &Metadata<'_>) -> Interest {
+        if metadata.is_span() {
+            // The spans are needed to find the camera of the lines
+            return Interest::always();
+        }
+        let level = log_level(metadata.level());
+        if level > self.max {
+            return Interest::never();
+        }
+        // The `log` crate has one callsite for each level, the target is only
+        // known from each line
+        if metadata.fields().field("log.target").is_some() {
+            return Interest::sometimes();
+        }
+        let enabled = self.target_enabled(metadata.target(), metadata.level());
+        // The level of a camera is used in its spans, so unless the cameras
+        // agree each line is checked
+        if self
+            .cameras
+            .values()
+            .all(|camera_level| enabled == (level <= *camera_level))
+        {
+            if enabled {
+                Interest::always()
+            } else {
+                Interest::never()
+            }
+        } else {
+            Interest::sometimes()
+        }
+    }
+}
+
+/// The camera of the closest span that has one
+fn camera_of<S>(event: &Event<'_>, ctx: &Context<'_, S>) -> Option<String>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    ctx.event_scope(event)?.find_map(|span| {
+        span.extensions()
+            .get::<CameraSpan>()
+            .map(|camera| camera.0.clone())
+    })
+}
+
+/// Finds the value of one field
+struct FieldVisitor {
+    name: &'static str,
+    value: Option<String>,
+}
+
+impl FieldVisitor {
+    fn new(name: &'static str) -> Self {
+        Self { name, value: None }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.name {
+            self.value = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == self.name {
+            let mut text = String::new();
+            let _ = write!(text, "{value:?}");
+            self.value = Some(text);
+        }
+    }
+}
+
+/// Log to stderr with the levels of `RUST_LOG` until the config is read
+pub(crate) fn init() -> AnyResult<()> {
+    let env = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|value| Targets::from_str(&value).ok());
+    let env_max = env
+        .as_ref()
+        .map(|env| {
+            env.iter()
+                .map(|(_, level)| filter_level(level))
+                .chain(env.default_level().map(filter_level))
+                .fold(LevelFilter::Off, std::cmp::max)
+        })
+        .unwrap_or(LevelFilter::Info);
+    let settings = OutputSettings {
+        format: "text".to_string(),
+        file: None,
+    };
+    let (output, handle) = reload::Layer::new(output_layer(&settings.format, None));
+    {
+        let mut levels = LEVELS.write().unwrap();
+        levels.installed = true;
+        levels.env = env;
+        levels.env_max = env_max;
+        levels.max = env_max;
+        levels.settings = settings;
+        levels.output = Some(handle);
+    }
+    Registry::default()
+        .with(NeoFilter)
+        .with(output)
+        .try_init()?;
+    log::set_max_level(env_max);
+    Ok(())
+}
+
+/// Write to stderr and the file if there is one
+fn output_layer(format: &str, file: Option<FileWriter>) -> Output {
+    let stderr = format_layer(format, std::io::stderr, std::io::stderr().is_terminal());
+    match file {
+        Some(file) => Box::new(stderr.and_then(format_layer(format, file, false))),
+        None => stderr,
+    }
+}
+
+fn format_layer<W>(format: &str, writer: W, ansi: bool) -> Output
+where
+    W: for<'w> fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer);
+    match format {
+        "json" => Box::new(layer.json().with_current_span(true).with_span_list(true)),
+        _ => Box::new(layer.with_ansi(ansi)),
+    }
+}
+
+/// Use the levels and format of the config
+pub(crate) fn apply(config: &Config) {
+    let mut levels = LEVELS.write().unwrap();
+    if !levels.installed {
+        return;
+    }
+    let log = config.log.as_ref();
+    levels.base = log.and_then(|log| log.level.as_deref()).and_then(level);
+    levels.modules = log
+        .map(|log| {
+            log.subsystems
+                .iter()
+                .filter_map(|(name, value)| Some((name, level(value)?)))
+                .flat_map(|(name, filter)| {
+                    module_paths(name)
+                        .into_iter()
+                        .map(move |path| (path, filter))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    levels.modules.sort_by_key(|(path, _)| Reverse(path.len()));
+    levels.cameras = config
+        .cameras
+        .iter()
+        .filter_map(|cam| Some((cam.name.clone(), level(cam.log_level.as_deref()?)?)))
+        .collect();
+
+    let base = match (levels.base, levels.env.is_some()) {
+        (Some(level), false) => level,
+        _ => levels.env_max,
+    };
+    let max = levels
+        .modules
+        .iter()
+        .map(|(_, level)| *level)
+        .chain(levels.cameras.values().copied())
+        .fold(base, std::cmp::max);
+    levels.max = max;
+    log::set_max_level(max);
+    CAMERA_LEVELS.store(!levels.cameras.is_empty(), Ordering::Relaxed);
+    // The callsites work out their interest again from the new levels, they
+    // read the levels so the lock is let go meanwhile
+    let mut levels = {
+        drop(levels);
+        callsite::rebuild_interest_cache();
+        LEVELS.write().unwrap()
+    };
+
+    let settings = OutputSettings {
+        format: log
+            .map(|log| log.format.clone())
+            .unwrap_or_else(|| "text".to_string()),
+        file: log.and_then(FileSettings::new),
+    };
+    if settings == levels.settings {
+        return;
+    }
+    levels.settings = settings.clone();
+    let handle = levels.output.clone();
+    // The lines are not filtered while the output is changed
+    drop(levels);
+    let file = settings.file.and_then(|file| {
+        FileWriter::new(file)
+            .map_err(|e| log::error!("Failed to open the log file: {e}"))
+            .ok()
+    });
+    if let Some(handle) = handle {
+        if let Err(e) = handle.reload(output_layer(&settings.format, file)) {
+            log::error!("Failed to change the log output: {e}");
+        }
+    }
+}
+
+/// Apply the levels each time the config changes until the program stops
+pub(crate) async fn main(reactor: NeoReactor) -> AnyResult<()> {
+    let mut config = reactor.config().await?;
+    loop {
+        config.changed().await?;
+        apply(&config.borrow_and_update());
+    }
+}
+
+fn level(value: &str) -> Option<LevelFilter> {
+    LevelFilter::from_str(value).ok()
+}
+
+/// The `log` level of a `tracing` level so that both are compared alike
+fn log_level(level: &Level) -> log::Level {
+    match *level {
+        Level::ERROR => log::Level::Error,
+        Level::WARN => log::Level::Warn,
+        Level::INFO => log::Level::Info,
+        Level::DEBUG => log::Level::Debug,
+        Level::TRACE => log::Level::Trace,
+    }
+}
+
+fn filter_level(level: tracing::level_filters::LevelFilter) -> LevelFilter {
+    level
+        .into_level()
+        .map(|level| log_level(&level).to_level_filter())
+        .unwrap_or(LevelFilter::Off)
+}
+
+/// The module paths of a subsystem, a name such as `mqtt` is the module of
+/// neolink or a library of that name
+fn module_paths(name: &str) -> Vec<String> {
+    match name {
+        "core" => vec!["neolink_core".to_string()],
+        name if name.contains("::") => vec![name.to_string()],
+        name => vec![format!("neolink::{name}"), name.to_string()],
+    }
+}
+
+fn in_module(target: &str, module: &str) -> bool {
+    target
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_enabled() {
+        let levels = Levels {
+            base: Some(LevelFilter::Warn),
+            modules: vec![
+                ("neolink::mqtt::mirror".to_string(), LevelFilter::Off),
+                ("neolink::mqtt".to_string(), LevelFilter::Debug),
+            ],
+            ..Default::default()
+        };
+        assert!(levels.target_enabled("neolink::mqtt", &Level::DEBUG));
+        assert!(levels.target_enabled("neolink::mqtt::mqttc", &Level::DEBUG));
+        assert!(!levels.target_enabled("neolink::mqtt::mqttc", &Level::TRACE));
+        assert!(!levels.target_enabled("neolink::mqtt::mirror", &Level::ERROR));
+        assert!(!levels.target_enabled("neolink::mqttx", &Level::INFO));
+        assert!(levels.target_enabled("neolink::rtsp", &Level::WARN));
+        assert!(!levels.target_enabled("neolink::rtsp", &Level::INFO));
+    }
+
+    #[test]
+    fn test_target_enabled_env() {
+        let levels = Levels {
+            env: Targets::from_str("warn,rumqttc=trace").ok(),
+            base: Some(LevelFilter::Debug),
+            ..Default::default()
+        };
+        assert!(levels.target_enabled("rumqttc::state", &Level::TRACE));
+        assert!(!levels.target_enabled("neolink::rtsp", &Level::INFO));
+    }
+}
@@ -29,30 +29,79 @@ use tikv_jemallocator::Jemalloc;
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Result};
 use clap::Parser;
-use env_logger::Env;
 use log::*;
-use std::fs;
-use validator::Validate;
+use std::path::PathBuf;
 
+mod abilities;
 mod battery;
+mod benchmark;
+mod check_config;
 mod cmdline;
 mod common;
 mod config;
+#[cfg(unix)]
+mod control;
+mod discover;
+#[cfg(feature = "gstreamer")]
+mod download;
+mod events;
+mod firmware;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod health;
+mod hooks;
+mod http;
 #[cfg(feature = "gstreamer")]
 mod image;
+mod logging;
+mod managed;
+mod mock_camera;
 mod mqtt;
+mod onboard;
+#[cfg(feature = "gstreamer")]
+mod onvif;
+#[cfg(feature = "gstreamer")]
+mod output;
+mod pcap_decode;
 mod pir;
+mod proxy;
 mod ptz;
 mod reboot;
 #[cfg(feature = "gstreamer")]
+mod record;
+#[cfg(feature = "gstreamer")]
+mod recording;
+mod reload;
+#[cfg(feature = "gstreamer")]
 mod rtsp;
+mod runner;
+mod schedule;
+mod sdcard;
 mod services;
+mod shell;
+mod shutdown;
+#[cfg(feature = "gstreamer")]
+mod sip;
+mod siren;
+mod snapshot;
 mod statusled;
+mod supervisor;
+#[cfg(unix)]
+mod systemd;
 #[cfg(feature = "gstreamer")]
 mod talk;
+#[cfg(feature = "gstreamer")]
+mod timelapse;
+mod users;
 mod utils;
+mod webhooks;
+#[cfg(feature = "gstreamer")]
+mod webrtc;
+mod wifi;
+#[cfg(windows)]
+mod winservice;
 
 use cmdline::{Command, Opt};
 use common::NeoReactor;
@@ -62,7 +111,16 @@ pub(crate) type AnyResult<T> = Result<T, anyhow::Error>;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+
+    // A Windows service has no console so it logs to the event log
+    #[cfg(windows)]
+    let event_log = winservice::init_event_log(&opt.cmd)?;
+    #[cfg(not(windows))]
+    let event_log = false;
+    if !event_log {
+        logging::init()?;
+    }
 
     info!(
         "Neolink {} {}",
@@ -70,22 +128,157 @@ async fn main() -> Result<()> {
         env!("NEOLINK_PROFILE")
     );
 
-    let opt = Opt::parse();
+    // Discover is used to write the config, pcap-decode works on files and
+    // mock-camera is a camera itself so they run without one, check-config
+    // reports the errors that would stop the config loading. The Windows
+    // service runs the rest itself once Windows has started it
+    let cmd = match opt.cmd {
+        Some(Command::Discover(opts)) => return discover::main(opts).await,
+        Some(Command::CheckConfig(opts)) => return check_config::main(opts, opt.config).await,
+        Some(Command::PcapDecode(opts)) => return pcap_decode::main(opts).await,
+        Some(Command::MockCamera(opts)) => return mock_camera::main(opts).await,
+        #[cfg(windows)]
+        Some(Command::WindowsService(opts)) => return winservice::main(opts, opt.config).await,
+        cmd => cmd,
+    };
 
-    let conf_path = opt.config.context("Must supply --config file")?;
-    let config: Config = toml::from_str(
-        &fs::read_to_string(&conf_path)
-            .with_context(|| format!("Failed to read {:?}", conf_path))?,
-    )
-    .with_context(|| format!("Failed to parse the {:?} config file", conf_path))?;
+    run(opt.config, cmd).await
+}
+
+/// Load the config and run a command that needs it
+async fn run(config_path: Option<PathBuf>, cmd: Option<Command>) -> Result<()> {
+    // As a Home Assistant add-on the config comes from the add-on options
+    let (config, conf_path): (Config, _) = match config_path.as_ref() {
+        Some(conf_path) => (Config::from_file(conf_path)?, conf_path.clone()),
+        None if supervisor::detected() => {
+            info!("Reading the config from the Home Assistant add-on options");
+            (supervisor::config().await?, supervisor::OPTIONS.into())
+        }
+        None => return Err(anyhow!("Must supply --config file")),
+    };
 
-    config
-        .validate()
-        .with_context(|| format!("Failed to validate the {:?} config file", conf_path))?;
+    config.check(&conf_path)?;
+    logging::apply(&config);
 
     let neo_reactor = NeoReactor::new(config.clone()).await;
 
-    match opt.cmd {
+    // The log levels follow the changes to the config
+    if cmd.as_ref().map(Command::is_service).unwrap_or(true) {
+        let reactor = neo_reactor.clone();
+        tokio::spawn(async move {
+            if let Err(e) = logging::main(reactor).await {
+                error!("Stopped applying the log levels of the config: {e:?}");
+            }
+        });
+    }
+
+    // The control socket is for the commands that keep running, without a
+    // command that is rtsp or mqtt
+    #[cfg(unix)]
+    if let Some(control_config) = config.control.clone() {
+        if cmd.as_ref().map(Command::is_service).unwrap_or(true) {
+            let reactor = neo_reactor.clone();
+            tokio::spawn(async move {
+                if let Err(e) = control::main(control_config, reactor).await {
+                    error!("The control socket stopped: {e:?}");
+                }
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    if config.control.is_some() {
+        warn!("The [control] socket is only on unix");
+    }
+
+    #[cfg(unix)]
+    if systemd::detected() && cmd.as_ref().map(Command::is_service).unwrap_or(true) {
+        let reactor = neo_reactor.clone();
+        tokio::spawn(async move {
+            if let Err(e) = systemd::main(reactor).await {
+                error!("Stopped notifying systemd: {e:?}");
+            }
+        });
+    }
+
+    if let Some(health_config) = config.health.clone() {
+        if cmd.as_ref().map(Command::is_service).unwrap_or(true) {
+            let reactor = neo_reactor.clone();
+            tokio::spawn(async move {
+                if let Err(e) = health::main(health_config, reactor).await {
+                    error!("The health check stopped: {e:?}");
+                }
+            });
+        }
+    }
+
+    if let Some(onboard_config) = config.onboard.clone() {
+        if cmd.as_ref().map(Command::is_service).unwrap_or(true) {
+            let reactor = neo_reactor.clone();
+            tokio::spawn(async move {
+                if let Err(e) = onboard::main(onboard_config, reactor).await {
+                    error!("Stopped looking for new cameras: {e:?}");
+                }
+            });
+        }
+    }
+
+    // Changes to the config file are applied while the services run
+    if let (Some(config_path), true) = (config_path, config.reload) {
+        if cmd.as_ref().map(Command::is_service).unwrap_or(true) {
+            let reactor = neo_reactor.clone();
+            tokio::spawn(async move {
+                if let Err(e) = reload::main(config_path, reactor).await {
+                    error!("Stopped watching the config file: {e:?}");
+                }
+            });
+        }
+    }
+
+    let services = cmd.as_ref().map(Command::is_service).unwrap_or(true);
+    let command = run_command(cmd, config, conf_path, neo_reactor.clone());
+    if services {
+        // The http api, onvif, recordings and the others run beside rtsp
+        // and mqtt until the command ends
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let runner = runner::main(neo_reactor.clone(), cancel.clone());
+        let command = async {
+            let (v, services) = tokio::join!(
+                async {
+                    let v = command.await;
+                    cancel.cancel();
+                    v
+                },
+                runner
+            );
+            if let Err(e) = services {
+                error!("Could not start the services: {e:?}");
+            }
+            v
+        };
+        // A SIGTERM or SIGINT stops the services and logs out of the cameras
+        shutdown::run(command, neo_reactor).await?;
+    } else {
+        // A camera that is given up on stops the command too
+        tokio::select! {
+            v = command => v?,
+            _ = shutdown::requested() => shutdown::failure()?,
+        }
+    }
+
+    #[cfg(unix)]
+    systemd::stopping();
+
+    Ok(())
+}
+
+/// Run the command with the loaded config
+async fn run_command(
+    cmd: Option<Command>,
+    config: Config,
+    conf_path: PathBuf,
+    neo_reactor: NeoReactor,
+) -> Result<()> {
+    match cmd {
         #[cfg(feature = "gstreamer")]
         None => {
             warn!(
@@ -128,10 +321,11 @@ async fn main() -> Result<()> {
         }
         #[cfg(feature = "gstreamer")]
         Some(Command::MqttRtsp(opts)) => {
-            tokio::select! {
-                v = mqtt::main(opts, neo_reactor.clone()) => v,
-                v = rtsp::main(rtsp::Opt {}, neo_reactor.clone()) => v,
-            }?;
+            // Both run until shutdown so that each can stop cleanly
+            tokio::try_join!(
+                mqtt::main(opts, neo_reactor.clone()),
+                rtsp::main(rtsp::Opt {}, neo_reactor.clone()),
+            )?;
         }
         #[cfg(feature = "gstreamer")]
         Some(Command::Image(opts)) => {
@@ -143,7 +337,58 @@ async fn main() -> Result<()> {
         Some(Command::Services(opts)) => {
             services::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Siren(opts)) => {
+            siren::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Snapshot(opts)) => {
+            snapshot::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Firmware(opts)) => {
+            firmware::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Sdcard(opts)) => {
+            sdcard::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Abilities(opts)) => {
+            abilities::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Wifi(opts)) => {
+            wifi::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Proxy(opts)) => {
+            proxy::main(opts, config).await?;
+        }
+        #[cfg(feature = "events")]
+        Some(Command::Events(opts)) => {
+            events::main(opts, config).await?;
+        }
+        Some(Command::Benchmark(opts)) => {
+            benchmark::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Shell(opts)) => {
+            shell::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Users(opts)) => {
+            users::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Record(opts)) => {
+            record::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Timelapse(opts)) => {
+            timelapse::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Discover(_))
+        | Some(Command::CheckConfig(_))
+        | Some(Command::PcapDecode(_))
+        | Some(Command::MockCamera(_)) => unreachable!(),
+        #[cfg(windows)]
+        Some(Command::WindowsService(_)) => unreachable!(),
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Download(opts)) => {
+            download::main(opts, neo_reactor.clone()).await?;
+        }
     }
-
     Ok(())
 }
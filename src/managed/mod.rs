@@ -0,0 +1,317 @@
+//!
+//! # Neolink Managed Cameras
+//!
+//! This module adds, changes and removes cameras while neolink runs for the
+//! http api and mqtt, so that a UI or an orchestration system can manage the
+//! cameras without editing the config file
+//!
+//! The cameras are kept in the file of `managed_cameras`, which is included
+//! in the config like `include`, so they are still there after a restart
+//!
+//! ```toml
+//! managed_cameras = "managed_cameras.toml"
+//! ```
+//!
+//! Only the cameras in that file can be changed or removed, the ones in the
+//! config file are left to it. Each change is applied by reading the config
+//! again as a [reload](crate::reload) does, a camera that does not validate is
+//! refused and the file is put back
+//!
+//! The tables come from the network so they cannot read files, run commands
+//! or the keyring with the `*_file`, `password_cmd` and `password_keyring`
+//! keys, and a `${VAR}` in them is written as `$${VAR}` so that it is kept as
+//! it is instead of being replaced by the environment
+//!
+use anyhow::{anyhow, Context};
+use log::*;
+use once_cell::sync::Lazy;
+use std::{
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+};
+use tokio::sync::Mutex;
+
+use crate::{common::NeoReactor, AnyResult};
+
+/// Only one change is made to the file at a time
+static CHANGING: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// The keys that hold the password of a camera
+const PASSWORD_KEYS: [&str; 5] = [
+    "password",
+    "pass",
+    "password_file",
+    "password_cmd",
+    "password_keyring",
+];
+
+/// The keys that would let a camera from the network run a command or read
+/// the keyring
+const LOCAL_KEYS: [&str; 2] = ["password_cmd", "password_keyring"];
+
+/// Why a change was refused
+#[derive(Debug)]
+pub(crate) enum ManageError {
+    /// `managed_cameras` is not set
+    Disabled,
+    NotFound(String),
+    /// The camera is already there or is in the config file
+    Conflict(String),
+    Invalid(String),
+}
+
+impl Display for ManageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManageError::Disabled => write!(
+                f,
+                "Set managed_cameras in the config file to manage the cameras while running"
+            ),
+            ManageError::NotFound(name) => write!(f, "There is no camera {name:?}"),
+            ManageError::Conflict(message) | ManageError::Invalid(message) => {
+                write!(f, "{message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManageError {}
+
+/// Add a camera from its `[[cameras]]` table
+pub(crate) async fn add(reactor: &NeoReactor, mut camera: toml::Table) -> AnyResult<()> {
+    let _changing = CHANGING.lock().await;
+    sanitize(&mut camera)?;
+    let name = camera_name(&camera)?;
+    let (file, managed) = files(reactor).await?;
+    let config = reactor.config().await?.borrow().clone();
+    if config.cameras.iter().any(|cam| cam.name == name) {
+        return Err(ManageError::Conflict(format!("There is already a camera {name:?}")).into());
+    }
+
+    let mut cameras = read(&managed)?;
+    cameras.push(camera);
+    apply(&file, &managed, cameras, reactor).await?;
+    info!("{name}: Added the camera to {:?}", managed);
+    Ok(())
+}
+
+/// Replace the table of a camera, the password is kept when none is given
+pub(crate) async fn update(
+    reactor: &NeoReactor,
+    name: &str,
+    mut camera: toml::Table,
+) -> AnyResult<()> {
+    let _changing = CHANGING.lock().await;
+    sanitize(&mut camera)?;
+    if !camera.contains_key("name") {
+        camera.insert("name".to_string(), name.into());
+    }
+    let new_name = camera_name(&camera)?;
+    let (file, managed) = files(reactor).await?;
+    let mut cameras = read(&managed)?;
+    let index = find(reactor, &cameras, name).await?;
+    let config = reactor.config().await?.borrow().clone();
+    if new_name != name && config.cameras.iter().any(|cam| cam.name == new_name) {
+        return Err(
+            ManageError::Conflict(format!("There is already a camera {new_name:?}")).into(),
+        );
+    }
+
+    if !PASSWORD_KEYS.iter().any(|key| camera.contains_key(*key)) {
+        for key in PASSWORD_KEYS {
+            if let Some(password) = cameras[index].get(key) {
+                camera.insert(key.to_string(), password.clone());
+            }
+        }
+    }
+    cameras[index] = camera;
+    apply(&file, &managed, cameras, reactor).await?;
+    info!("{name}: Changed the camera in {:?}", managed);
+    Ok(())
+}
+
+/// Remove a camera
+pub(crate) async fn remove(reactor: &NeoReactor, name: &str) -> AnyResult<()> {
+    let _changing = CHANGING.lock().await;
+    let (file, managed) = files(reactor).await?;
+    let mut cameras = read(&managed)?;
+    let index = find(reactor, &cameras, name).await?;
+    cameras.remove(index);
+    apply(&file, &managed, cameras, reactor).await?;
+    info!("{name}: Removed the camera from {:?}", managed);
+    Ok(())
+}
+
+/// The config file and the file of the managed cameras
+async fn files(reactor: &NeoReactor) -> AnyResult<(PathBuf, PathBuf)> {
+    let config = reactor.config().await?.borrow().clone();
+    match (config.file, config.managed_cameras) {
+        (Some(file), Some(managed)) => Ok((file, managed)),
+        _ => Err(ManageError::Disabled.into()),
+    }
+}
+
+fn camera_name(camera: &toml::Table) -> AnyResult<String> {
+    match camera.get("name") {
+        Some(toml::Value::String(name)) if !name.is_empty() => Ok(name.clone()),
+        _ => Err(ManageError::Invalid("The camera needs a name".to_string()).into()),
+    }
+}
+
+/// Refuse the keys that read files, run commands or use the keyring and
+/// escape `${` so that the environment is not substituted when the config is
+/// read again
+fn sanitize(table: &mut toml::Table) -> AnyResult<()> {
+    for (key, value) in table.iter_mut() {
+        if key.ends_with("_file") || LOCAL_KEYS.contains(&key.as_str()) {
+            return Err(ManageError::Invalid(format!(
+                "`{key}` can only be set in the config file"
+            ))
+            .into());
+        }
+        sanitize_value(value)?;
+    }
+    Ok(())
+}
+
+fn sanitize_value(value: &mut toml::Value) -> AnyResult<()> {
+    match value {
+        toml::Value::String(text) => *text = text.replace("${", "$${"),
+        toml::Value::Array(values) => {
+            for value in values.iter_mut() {
+                sanitize_value(value)?;
+            }
+        }
+        toml::Value::Table(table) => sanitize(table)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The index of a camera in the managed file
+async fn find(reactor: &NeoReactor, cameras: &[toml::Table], name: &str) -> AnyResult<usize> {
+    if let Some(index) = cameras
+        .iter()
+        .position(|camera| camera.get("name").and_then(|n| n.as_str()) == Some(name))
+    {
+        return Ok(index);
+    }
+    let config = reactor.config().await?.borrow().clone();
+    if config.cameras.iter().any(|cam| cam.name == name) {
+        Err(ManageError::Conflict(format!(
+            "The camera {name:?} is in the config file, only the managed cameras can be changed"
+        ))
+        .into())
+    } else {
+        Err(ManageError::NotFound(name.to_string()).into())
+    }
+}
+
+/// The cameras of the managed file
+fn read(managed: &Path) -> AnyResult<Vec<toml::Table>> {
+    if !managed.exists() {
+        return Ok(vec![]);
+    }
+    let source = std::fs::read_to_string(managed)
+        .with_context(|| format!("Failed to read {:?}", managed))?;
+    let mut table: toml::Table =
+        toml::from_str(&source).with_context(|| format!("Failed to parse {:?}", managed))?;
+    match table.remove("cameras") {
+        None => Ok(vec![]),
+        Some(toml::Value::Array(cameras)) => cameras
+            .into_iter()
+            .map(|camera| match camera {
+                toml::Value::Table(camera) => Ok(camera),
+                _ => Err(anyhow!("The cameras of {:?} should be tables", managed)),
+            })
+            .collect(),
+        Some(_) => Err(anyhow!(
+            "The cameras of {:?} should be [[cameras]]",
+            managed
+        )),
+    }
+}
+
+/// Write the managed file and read the config again, the file is put back if
+/// the config is not valid with it
+async fn apply(
+    file: &Path,
+    managed: &Path,
+    cameras: Vec<toml::Table>,
+    reactor: &NeoReactor,
+) -> AnyResult<()> {
+    let previous = std::fs::read(managed).ok();
+    let mut table = toml::Table::new();
+    table.insert(
+        "cameras".to_string(),
+        toml::Value::Array(cameras.into_iter().map(toml::Value::Table).collect()),
+    );
+    write(
+        managed,
+        format!(
+            "# The cameras that are managed over the api, this file is rewritten by neolink\n\n{}",
+            toml::to_string(&table)?
+        )
+        .as_bytes(),
+    )?;
+
+    if let Err(e) = crate::reload::reload(file, reactor).await {
+        match previous {
+            Some(previous) => write(managed, &previous)?,
+            None => std::fs::remove_file(managed)?,
+        }
+        return Err(ManageError::Invalid(format!("{e:#}")).into());
+    }
+    Ok(())
+}
+
+/// Replace the file in one step so that a reload never reads half of it
+fn write(path: &Path, contents: &[u8]) -> AnyResult<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    std::fs::write(&temp, contents).with_context(|| format!("Failed to write {:?}", temp))?;
+    std::fs::rename(&temp, path).with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(source: &str) -> toml::Table {
+        toml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn test_sanitize_escapes_the_environment() {
+        let mut camera = table(
+            r#"
+            name = "${HOME}"
+            password = "a$${b}"
+            [[pause]]
+            mode = "${MODE:-none}"
+            "#,
+        );
+        sanitize(&mut camera).unwrap();
+        assert_eq!(camera["name"].as_str(), Some("$${HOME}"));
+        assert_eq!(camera["password"].as_str(), Some("a$$${b}"));
+        assert_eq!(camera["pause"][0]["mode"].as_str(), Some("$${MODE:-none}"));
+    }
+
+    #[test]
+    fn test_sanitize_refuses_local_keys() {
+        for source in [
+            "password_file = \"/etc/shadow\"",
+            "password_cmd = \"id\"",
+            "password_keyring = \"admin\"",
+            "[mqtt]\nca_file = \"/etc/shadow\"",
+        ] {
+            let mut camera = table(&format!("name = \"Cam\"\n{}", source));
+            assert!(
+                sanitize(&mut camera).is_err(),
+                "{:?} should be refused",
+                source
+            );
+        }
+    }
+}
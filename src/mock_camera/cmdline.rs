@@ -0,0 +1,39 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The mock-camera command pretends to be a camera so that clients can be tested without one
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The address to listen for clients on
+    #[arg(short, long, default_value = "0.0.0.0:9000")]
+    pub listen: String,
+    /// The username that clients must login with
+    #[arg(short, long, default_value = "admin")]
+    pub username: String,
+    /// The password that clients must login with
+    #[arg(short, long)]
+    pub password: Option<String>,
+    /// An Annex B H264 file, or H265 if it ends in .h265 or .hevc, that is streamed in a loop.
+    /// Without it the streams are refused
+    #[arg(long, value_parser = PathBuf::from_str)]
+    pub video: Option<PathBuf>,
+    /// The frames per second of the video
+    #[arg(long, default_value = "25", value_parser = clap::value_parser!(u8).range(1..))]
+    pub fps: u8,
+    /// The width of the video
+    #[arg(long, default_value = "1920")]
+    pub width: u32,
+    /// The height of the video
+    #[arg(long, default_value = "1080")]
+    pub height: u32,
+    /// Seconds between the starts of motion, 0 for no motion
+    #[arg(long, default_value = "60")]
+    pub motion_interval: u64,
+    /// Seconds that the motion lasts for
+    #[arg(long, default_value = "10")]
+    pub motion_duration: u64,
+    /// Do not encrypt the messages, as older cameras do
+    #[arg(long)]
+    pub unencrypted: bool,
+}
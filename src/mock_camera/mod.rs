@@ -0,0 +1,640 @@
+///
+/// # Neolink Mock Camera
+///
+/// This module handles the mock-camera subcommand
+///
+/// The subcommand pretends to be a camera on TCP so that neolink, its
+/// services and frontends can be run without the hardware. It takes the
+/// login, streams a video file in a loop, starts and stops motion at an
+/// interval and keeps the state of a floodlight. Other messages are refused
+/// with a 400 as a camera without that ability would. It does not need a
+/// config file
+///
+/// # Usage
+///
+/// ```bash
+/// neolink mock-camera --password=secret --video=clip.h264 --motion-interval=30
+/// ```
+///
+/// Then add it to the config of another neolink as a camera
+///
+/// ```toml
+/// [[cameras]]
+/// name = "Mock"
+/// username = "admin"
+/// password = "secret"
+/// address = "127.0.0.1:9000"
+/// ```
+///
+use anyhow::{anyhow, Context};
+use bytes::BytesMut;
+use futures::{SinkExt, StreamExt};
+use neolink_core::{
+    bc::{
+        model::*,
+        server::{login_hash, BcServerCodex},
+        xml::*,
+    },
+    bcmedia::{
+        codex::BcMediaCodex,
+        model::{BcMedia, BcMediaIframe, BcMediaInfoV1, BcMediaPframe},
+    },
+};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{channel, Sender},
+        watch, Mutex,
+    },
+    time::{interval, sleep, Duration},
+};
+use tokio_util::{
+    codec::{Encoder, Framed},
+    sync::CancellationToken,
+};
+
+mod cmdline;
+mod video;
+
+use crate::{utils::describe_bc, AnyResult};
+pub(crate) use cmdline::Opt;
+use video::Video;
+
+/// The state shared by all the clients
+struct Camera {
+    opt: Opt,
+    video: Option<Video>,
+    motion: watch::Sender<bool>,
+    floodlight: watch::Sender<bool>,
+    /// Counts the floodlight commands so that a timer only turns off its own
+    floodlight_commands: AtomicU64,
+    /// The time as it was last set by a client
+    clock: Mutex<Clock>,
+}
+
+/// The time fields of SystemGeneral
+#[derive(Clone, Copy)]
+struct Clock {
+    time_zone: i32,
+    year: i32,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl Default for Clock {
+    /// Like a camera that has lost its time
+    fn default() -> Self {
+        Self {
+            time_zone: 0,
+            year: 1999,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        }
+    }
+}
+
+/// Entry point for the mock-camera subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt) -> AnyResult<()> {
+    let video = match &opt.video {
+        Some(path) => {
+            let video = Video::read(path).await?;
+            log::info!(
+                "Streaming {} {:?} frames from {path:?}",
+                video.frames.len(),
+                video.video_type
+            );
+            Some(video)
+        }
+        None => None,
+    };
+    let listener = TcpListener::bind(&opt.listen)
+        .await
+        .with_context(|| format!("Could not listen on {}", opt.listen))?;
+    log::info!("Mock camera listening on {}", opt.listen);
+
+    let camera = Arc::new(Camera {
+        video,
+        motion: watch::channel(false).0,
+        floodlight: watch::channel(false).0,
+        floodlight_commands: AtomicU64::new(0),
+        clock: Mutex::new(Clock::default()),
+        opt,
+    });
+
+    if camera.opt.motion_interval > 0 {
+        let camera = camera.clone();
+        tokio::spawn(async move {
+            let duration = Duration::from_secs(camera.opt.motion_duration);
+            let interval = Duration::from_secs(camera.opt.motion_interval);
+            loop {
+                sleep(interval.saturating_sub(duration)).await;
+                log::info!("Motion started");
+                camera.motion.send_replace(true);
+                sleep(duration).await;
+                log::info!("Motion stopped");
+                camera.motion.send_replace(false);
+            }
+        });
+    }
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        log::info!("Client connected from {peer}");
+        let camera = camera.clone();
+        tokio::spawn(async move {
+            let r = handle_client(stream, peer, camera).await;
+            log::info!("Client from {peer} disconnected: {r:?}");
+        });
+    }
+}
+
+async fn handle_client(stream: TcpStream, peer: SocketAddr, camera: Arc<Camera>) -> AnyResult<()> {
+    let mut framed = Framed::new(
+        stream,
+        BcServerCodex::new(&camera.opt.username, camera.opt.password.as_deref()),
+    );
+    if !login(&mut framed, &camera).await? {
+        log::warn!("Client from {peer} failed to login");
+        return Ok(());
+    }
+    log::info!("Client from {peer} logged in");
+
+    let (mut sink, mut stream) = framed.split();
+    // Bounded so that the video waits on a slow client
+    let (tx, mut rx) = channel::<Bc>(100);
+    // Stops the video and the pushed messages of this client
+    let cancel = CancellationToken::new();
+
+    // Tell the client when the floodlight changes
+    {
+        let tx = tx.clone();
+        let cancel = cancel.clone();
+        let mut floodlight = camera.floodlight.subscribe();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    v = floodlight.changed() => if v.is_err() { break },
+                }
+                let on = *floodlight.borrow_and_update();
+                if tx.send(floodlight_status(on)).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let reading = async {
+        let mut videos = HashMap::<u16, CancellationToken>::new();
+        let mut watching_motion = false;
+        let r = async {
+            while let Some(msg) = stream.next().await {
+                let msg = msg?;
+                let meta = &msg.meta;
+                match meta.msg_id {
+                    MSG_ID_LOGOUT => break,
+                    MSG_ID_PING => tx.send(reply(meta, 200, None)).await?,
+                    MSG_ID_ABILITY_INFO => {
+                        let xml = BcXml {
+                            ability_info: Some(abilities(&camera.opt.username)),
+                            ..Default::default()
+                        };
+                        tx.send(reply(meta, 200, Some(xml))).await?;
+                    }
+                    MSG_ID_VERSION => {
+                        let xml = BcXml {
+                            version_info: Some(version()),
+                            ..Default::default()
+                        };
+                        tx.send(reply(meta, 200, Some(xml))).await?;
+                    }
+                    MSG_ID_GET_GENERAL => {
+                        let clock = *camera.clock.lock().await;
+                        let xml = BcXml {
+                            system_general: Some(SystemGeneral {
+                                version: xml_ver(),
+                                time_zone: Some(clock.time_zone),
+                                year: Some(clock.year),
+                                month: Some(clock.month),
+                                day: Some(clock.day),
+                                hour: Some(clock.hour),
+                                minute: Some(clock.minute),
+                                second: Some(clock.second),
+                                osd_format: Some("DMY".to_string()),
+                                time_format: Some(0),
+                                language: Some("English".to_string()),
+                                device_name: Some("Mock".to_string()),
+                            }),
+                            ..Default::default()
+                        };
+                        tx.send(reply(meta, 200, Some(xml))).await?;
+                    }
+                    MSG_ID_SET_GENERAL => {
+                        if let Some(SystemGeneral {
+                            time_zone: Some(time_zone),
+                            year: Some(year),
+                            month: Some(month),
+                            day: Some(day),
+                            hour: Some(hour),
+                            minute: Some(minute),
+                            second: Some(second),
+                            ..
+                        }) = xml(&msg).and_then(|xml| xml.system_general.as_ref())
+                        {
+                            *camera.clock.lock().await = Clock {
+                                time_zone: *time_zone,
+                                year: *year,
+                                month: *month,
+                                day: *day,
+                                hour: *hour,
+                                minute: *minute,
+                                second: *second,
+                            };
+                        }
+                        tx.send(reply(meta, 200, None)).await?;
+                    }
+                    MSG_ID_VIDEO if camera.video.is_some() => {
+                        let stream_cancel = cancel.child_token();
+                        if let Some(old) = videos.insert(meta.msg_num, stream_cancel.clone()) {
+                            old.cancel();
+                        }
+                        tokio::spawn(stream_video(
+                            camera.clone(),
+                            meta.channel_id,
+                            meta.msg_num,
+                            meta.stream_type,
+                            tx.clone(),
+                            stream_cancel,
+                        ));
+                    }
+                    MSG_ID_VIDEO_STOP => {
+                        if let Some(video) = videos.remove(&meta.msg_num) {
+                            video.cancel();
+                        }
+                        tx.send(reply(meta, 200, None)).await?;
+                    }
+                    MSG_ID_MOTION_REQUEST => {
+                        tx.send(reply(meta, 200, None)).await?;
+                        if !watching_motion {
+                            watching_motion = true;
+                            tokio::spawn(push_motion(camera.clone(), tx.clone(), cancel.clone()));
+                        }
+                    }
+                    MSG_ID_FLOODLIGHT_MANUAL => {
+                        let Some(FloodlightManual {
+                            status, duration, ..
+                        }) = xml(&msg).and_then(|xml| xml.floodlight_manual.as_ref())
+                        else {
+                            tx.send(reply(meta, 400, None)).await?;
+                            continue;
+                        };
+                        set_floodlight(&camera, *status != 0, *duration);
+                        tx.send(reply(meta, 200, None)).await?;
+                    }
+                    MSG_ID_REBOOT => {
+                        log::info!("Rebooting by dropping the client from {peer}");
+                        tx.send(reply(meta, 200, None)).await?;
+                        break;
+                    }
+                    _ => {
+                        log::debug!("Refusing {}", describe_bc(&msg));
+                        tx.send(reply(meta, 400, None)).await?;
+                    }
+                }
+            }
+            AnyResult::Ok(())
+        }
+        .await;
+        // Let the writer finish once the tasks of this client are gone
+        cancel.cancel();
+        drop(tx);
+        r
+    };
+    let writing = async {
+        while let Some(msg) = rx.recv().await {
+            sink.send(msg).await?;
+        }
+        AnyResult::Ok(())
+    };
+    let (read, write) = tokio::join!(reading, writing);
+    read.and(write)
+}
+
+/// Do the legacy then the modern login, false if the credentials are wrong
+async fn login(framed: &mut Framed<TcpStream, BcServerCodex>, camera: &Camera) -> AnyResult<bool> {
+    let Some(msg) = framed.next().await.transpose()? else {
+        return Ok(false);
+    };
+    if msg.meta.msg_id != MSG_ID_LOGIN || !matches!(msg.body, BcBody::LegacyMsg(_)) {
+        return Err(anyhow!("Expected a login but got {}", describe_bc(&msg)));
+    }
+    // The client asks for the most it supports in the low byte
+    let (response_code, encryption) =
+        if camera.opt.unencrypted || msg.meta.response_code & 0xff == 0 {
+            (0xdd00, EncryptionProtocol::Unencrypted)
+        } else {
+            (0xdd01, EncryptionProtocol::BCEncrypt)
+        };
+    let nonce = uuid::Uuid::new_v4().simple().to_string();
+    framed.codec_mut().set_encrypted(encryption);
+    framed
+        .send(Bc::new_from_xml(
+            BcMeta {
+                msg_id: MSG_ID_LOGIN,
+                channel_id: msg.meta.channel_id,
+                msg_num: msg.meta.msg_num,
+                stream_type: 0,
+                response_code,
+                class: 0x6614,
+            },
+            BcXml {
+                encryption: Some(Encryption {
+                    version: xml_ver(),
+                    type_: "md5".to_string(),
+                    nonce: nonce.clone(),
+                }),
+                ..Default::default()
+            },
+        ))
+        .await?;
+
+    let Some(msg) = framed.next().await.transpose()? else {
+        return Ok(false);
+    };
+    let Some(LoginUser {
+        user_name,
+        password,
+        ..
+    }) = xml(&msg).and_then(|xml| xml.login_user.as_ref())
+    else {
+        return Err(anyhow!(
+            "Expected a modern login but got {}",
+            describe_bc(&msg)
+        ));
+    };
+    let expected_password = camera.opt.password.as_deref().unwrap_or_default();
+    if *user_name != login_hash(&camera.opt.username, &nonce)
+        || *password != login_hash(expected_password, &nonce)
+    {
+        framed.send(reply(&msg.meta, 400, None)).await?;
+        return Ok(false);
+    }
+    let xml = BcXml {
+        device_info: Some(DeviceInfo {
+            version: Some(xml_ver()),
+            resolution: Some(Resolution {
+                name: format!("{}*{}", camera.opt.width, camera.opt.height),
+                width: camera.opt.width,
+                height: camera.opt.height,
+            }),
+        }),
+        ..Default::default()
+    };
+    framed.send(reply(&msg.meta, 200, Some(xml))).await?;
+    Ok(true)
+}
+
+/// Send the video file in a loop until the client stops it
+async fn stream_video(
+    camera: Arc<Camera>,
+    channel_id: u8,
+    msg_num: u16,
+    stream_type: u8,
+    tx: Sender<Bc>,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let Some(video) = camera.video.as_ref() else {
+        return Ok(());
+    };
+    let meta = || BcMeta {
+        msg_id: MSG_ID_VIDEO,
+        channel_id,
+        msg_num,
+        stream_type,
+        response_code: 200,
+        class: 0x0000,
+    };
+    // The reply turns on the binary mode of this msg_num
+    tx.send(Bc::new_from_ext(
+        meta(),
+        Extension {
+            binary_data: Some(1),
+            channel_id: Some(channel_id),
+            ..Default::default()
+        },
+    ))
+    .await?;
+
+    let mut codex = BcMediaCodex::new(false);
+    let mut send = |media: BcMedia| {
+        let mut buf = BytesMut::new();
+        codex.encode(media, &mut buf)?;
+        AnyResult::Ok(Bc::new(
+            meta(),
+            None,
+            Some(BcPayloads::Binary(buf.to_vec())),
+        ))
+    };
+
+    let fps = camera.opt.fps.max(1);
+    tx.send(send(BcMedia::InfoV1(BcMediaInfoV1 {
+        video_width: camera.opt.width,
+        video_height: camera.opt.height,
+        fps,
+        start_year: 0,
+        start_month: 0,
+        start_day: 0,
+        start_hour: 0,
+        start_min: 0,
+        start_seconds: 0,
+        end_year: 0,
+        end_month: 0,
+        end_day: 0,
+        end_hour: 0,
+        end_min: 0,
+        end_seconds: 0,
+    }))?)
+    .await?;
+
+    let mut ticks = interval(Duration::from_secs(1) / fps as u32);
+    for (n, frame) in video.frames.iter().cycle().enumerate() {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = ticks.tick() => {},
+        }
+        // The camera's clock wraps like this too
+        let microseconds = (n as u64 * 1_000_000 / fps as u64) as u32;
+        let media = if frame.key {
+            BcMedia::Iframe(BcMediaIframe {
+                video_type: video.video_type.clone(),
+                microseconds,
+                time: Some(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs() as u32)
+                        .unwrap_or_default(),
+                ),
+                data: frame.data.clone(),
+            })
+        } else {
+            BcMedia::Pframe(BcMediaPframe {
+                video_type: video.video_type.clone(),
+                microseconds,
+                data: frame.data.clone(),
+            })
+        };
+        tx.send(send(media)?).await?;
+    }
+    Ok(())
+}
+
+/// Send the motion state now and whenever it changes
+async fn push_motion(camera: Arc<Camera>, tx: Sender<Bc>, cancel: CancellationToken) {
+    let mut motion = camera.motion.subscribe();
+    loop {
+        let md = *motion.borrow_and_update();
+        let msg = Bc::new_from_xml(
+            push_meta(MSG_ID_MOTION),
+            BcXml {
+                alarm_event_list: Some(AlarmEventList {
+                    version: xml_ver(),
+                    alarm_events: vec![AlarmEvent {
+                        version: xml_ver(),
+                        channel_id: 0,
+                        status: if md { "MD" } else { "none" }.to_string(),
+                        ai_type: Some(if md { "people" } else { "none" }.to_string()),
+                        recording: 0,
+                        timeStamp: 0,
+                    }],
+                }),
+                ..Default::default()
+            },
+        );
+        if tx.send(msg).await.is_err() {
+            break;
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            v = motion.changed() => if v.is_err() { break },
+        }
+    }
+}
+
+/// Turn the floodlight on or off, on turns itself off after the duration
+fn set_floodlight(camera: &Arc<Camera>, on: bool, duration: u16) {
+    let command = camera.floodlight_commands.fetch_add(1, Ordering::SeqCst) + 1;
+    log::info!("Floodlight {}", if on { "on" } else { "off" });
+    camera.floodlight.send_replace(on);
+    if on && duration > 0 {
+        let camera = camera.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(duration as u64)).await;
+            if camera.floodlight_commands.load(Ordering::SeqCst) == command {
+                log::info!("Floodlight off after {duration}s");
+                camera.floodlight.send_replace(false);
+            }
+        });
+    }
+}
+
+fn floodlight_status(on: bool) -> Bc {
+    Bc::new_from_xml(
+        push_meta(MSG_ID_FLOODLIGHT_STATUS_LIST),
+        BcXml {
+            floodlight_status_list: Some(FloodlightStatusList {
+                version: xml_ver(),
+                floodlight_status_list: vec![FloodlightStatus {
+                    channel_id: 0,
+                    status: on as u8,
+                }],
+            }),
+            ..Default::default()
+        },
+    )
+}
+
+/// The meta of a message that the camera sends without being asked
+fn push_meta(msg_id: u32) -> BcMeta {
+    BcMeta {
+        msg_id,
+        channel_id: 0,
+        msg_num: 0,
+        stream_type: 0,
+        response_code: 200,
+        class: 0x0000,
+    }
+}
+
+fn reply(meta: &BcMeta, response_code: u16, xml: Option<BcXml>) -> Bc {
+    Bc::new(
+        BcMeta {
+            msg_id: meta.msg_id,
+            channel_id: meta.channel_id,
+            msg_num: meta.msg_num,
+            stream_type: meta.stream_type,
+            response_code,
+            class: 0x0000,
+        },
+        None,
+        xml.map(BcPayloads::BcXml),
+    )
+}
+
+fn xml(msg: &Bc) -> Option<&BcXml> {
+    match &msg.body {
+        BcBody::ModernMsg(ModernMsg {
+            payload: Some(BcPayloads::BcXml(xml)),
+            ..
+        }) => Some(xml),
+        _ => None,
+    }
+}
+
+/// What the mock camera can do, the names are those checked by the client
+fn abilities(username: &str) -> AbilityInfo {
+    let token = |ability_value: &str| {
+        Some(AbilityInfoToken {
+            sub_module: vec![AbilityInfoSubModule {
+                channel_id: Some(0),
+                ability_value: ability_value.to_string(),
+            }],
+        })
+    };
+    AbilityInfo {
+        username: username.to_string(),
+        system: token("general_rw, version_ro, reboot_rw"),
+        alarm: token("motion_rw"),
+        video: token("preview_rw, floodLight_rw"),
+        ..Default::default()
+    }
+}
+
+fn version() -> VersionInfo {
+    VersionInfo {
+        name: "Mock".to_string(),
+        model: Some("Neolink Mock Camera".to_string()),
+        serialNumber: "00000000000000".to_string(),
+        buildDay: format!("build {}", env!("CARGO_PKG_VERSION")),
+        hardwareVersion: "NEOLINK_MOCK".to_string(),
+        cfgVersion: "v1.0.0.0".to_string(),
+        firmwareVersion: env!("NEOLINK_VERSION").to_string(),
+        detail: "NEOLINK_MOCK".to_string(),
+    }
+}
@@ -0,0 +1,165 @@
+//! Splits an Annex B H264 or H265 file into the frames that a camera sends
+//!
+//! A frame is every NAL up to the next first slice of a picture, with the
+//! parameter sets kept in front of the key frame that follows them
+//!
+use anyhow::{anyhow, Context};
+use neolink_core::bcmedia::model::VideoType;
+use std::path::Path;
+
+use crate::AnyResult;
+
+/// The frames of a video file
+pub(super) struct Video {
+    pub(super) video_type: VideoType,
+    pub(super) frames: Vec<Frame>,
+}
+
+pub(super) struct Frame {
+    /// An IDR frame for H264 or an IRAP frame for H265
+    pub(super) key: bool,
+    /// The NALs of the frame with their start codes
+    pub(super) data: Vec<u8>,
+}
+
+impl Video {
+    /// Read a file, it is H265 if it ends in `.h265` or `.hevc`
+    pub(super) async fn read(path: &Path) -> AnyResult<Self> {
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Could not read {path:?}"))?;
+        let video_type = match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_deref()
+        {
+            Some("h265") | Some("hevc") => VideoType::H265,
+            _ => VideoType::H264,
+        };
+        let frames = split(&data, &video_type);
+        if !frames.iter().any(|frame| frame.key) {
+            return Err(anyhow!(
+                "No key frames in {path:?}, it must be an Annex B {video_type:?} file"
+            ));
+        }
+        Ok(Self { video_type, frames })
+    }
+}
+
+fn split(data: &[u8], video_type: &VideoType) -> Vec<Frame> {
+    let mut frames = vec![];
+    let mut current = Frame {
+        key: false,
+        data: vec![],
+    };
+    let mut has_slice = false;
+    for nal in nals(data) {
+        let Some(kind) = classify(nal, video_type) else {
+            continue;
+        };
+        // A new picture or the parameter sets of the next one
+        let starts_frame = match kind {
+            Nal::Slice { first, .. } => first,
+            Nal::Prefix => true,
+            Nal::Suffix => false,
+        };
+        if has_slice && starts_frame {
+            frames.push(std::mem::replace(
+                &mut current,
+                Frame {
+                    key: false,
+                    data: vec![],
+                },
+            ));
+            has_slice = false;
+        }
+        if let Nal::Slice { key, .. } = kind {
+            has_slice = true;
+            current.key |= key;
+        }
+        current.data.extend_from_slice(&[0, 0, 0, 1]);
+        current.data.extend_from_slice(nal);
+    }
+    if has_slice {
+        frames.push(current);
+    }
+    // Start at a key frame so the stream can be decoded from its first frame
+    if let Some(first_key) = frames.iter().position(|frame| frame.key) {
+        frames.drain(..first_key);
+    }
+    frames
+}
+
+enum Nal {
+    Slice {
+        key: bool,
+        first: bool,
+    },
+    /// Comes before the picture it is for, such as the parameter sets
+    Prefix,
+    /// Comes after the picture it is for
+    Suffix,
+}
+
+fn classify(nal: &[u8], video_type: &VideoType) -> Option<Nal> {
+    match video_type {
+        VideoType::H264 => {
+            let nal_type = nal.first()? & 0x1f;
+            match nal_type {
+                1..=5 => Some(Nal::Slice {
+                    key: nal_type == 5,
+                    // first_mb_in_slice is 0, which as exp-golomb is a single 1 bit
+                    first: nal.get(1)? & 0x80 != 0,
+                }),
+                _ => Some(Nal::Prefix),
+            }
+        }
+        VideoType::H265 => {
+            let nal_type = (nal.first()? >> 1) & 0x3f;
+            match nal_type {
+                0..=31 => Some(Nal::Slice {
+                    key: (16..=23).contains(&nal_type),
+                    // first_slice_segment_in_pic_flag
+                    first: nal.get(2)? & 0x80 != 0,
+                }),
+                // Suffix SEI
+                40 => Some(Nal::Suffix),
+                _ => Some(Nal::Prefix),
+            }
+        }
+    }
+}
+
+/// The NALs between the start codes
+fn nals(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = vec![];
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|&start| {
+            // The start code before it, with the zero of a four byte one
+            let end = start - 3;
+            if end > 0 && data[end - 1] == 0 {
+                end - 1
+            } else {
+                end
+            }
+        })
+        .chain(std::iter::once(data.len()))
+        .collect::<Vec<_>>();
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(move |(start, end)| &data[start..end.max(start)])
+        .filter(|nal| !nal.is_empty())
+}
@@ -0,0 +1,299 @@
+//! Wraps the MQTT 3.1.1 and MQTT 5 clients of rumqttc so that the
+//! rest of the mqtt module does not need to care which one is in use
+use anyhow::anyhow;
+use bytes::Bytes;
+use rumqttc::{
+    v5::{
+        self,
+        mqttbytes::{
+            v5::{
+                ConnectReturnCode as V5ConnectReturnCode, LastWill as V5LastWill,
+                Packet as V5Packet, PublishProperties,
+            },
+            QoS as V5QoS,
+        },
+        ConnectionError as V5ConnectionError, StateError as V5StateError,
+    },
+    valid_topic, AsyncClient, ClientError, ConnectReturnCode, Event, EventLoop, Incoming, LastWill,
+    MqttOptions, QoS,
+};
+use std::time::Duration;
+
+use crate::{config::MqttServerConfig, utils::lookup_password, AnyResult};
+
+/// Extra data that is only sent on MQTT 5 connections
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PublishMeta {
+    /// Seconds until the broker discards the message
+    pub(crate) expiry: Option<u32>,
+    pub(crate) correlation_data: Option<Bytes>,
+    pub(crate) user_properties: Vec<(String, String)>,
+}
+
+/// Where the reply to an MQTT 5 request should be sent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct MqttResponse {
+    pub(crate) topic: String,
+    pub(crate) correlation_data: Option<Bytes>,
+}
+
+pub(crate) enum Notification {
+    ConnAck(bool),
+    Publish {
+        topic: String,
+        payload: Bytes,
+        response: Option<MqttResponse>,
+    },
+    Other,
+}
+
+pub(crate) enum Client {
+    V3(AsyncClient),
+    V5(v5::AsyncClient),
+}
+
+pub(crate) enum Connection {
+    V3(Box<EventLoop>),
+    V5(Box<v5::EventLoop>),
+}
+
+/// Create the client and its connection to the broker
+///
+/// The `password_cmd` or `password_keyring` is looked up each time
+pub(crate) async fn connect(
+    config: &MqttServerConfig,
+    client_id: String,
+    last_will: (String, String, QoS, bool),
+    use_v5: bool,
+) -> AnyResult<(Client, Connection)> {
+    let max_size = 100 * (1024 * 1024);
+    let transport = super::tls::transport(config)?;
+    let (will_topic, will_message, will_qos, will_retain) = last_will;
+    let credentials = match &config.username {
+        Some(username) if config.password_cmd.is_some() || config.password_keyring.is_some() => {
+            let password = lookup_password(
+                "MQTT",
+                &None,
+                &config.password_cmd,
+                &config.password_keyring,
+            )
+            .await?
+            .unwrap_or_default();
+            Some((username.clone(), password))
+        }
+        _ => config.credentials.clone(),
+    };
+    if use_v5 {
+        let mut mqttoptions = v5::MqttOptions::new(client_id, &config.broker_addr, config.port);
+        mqttoptions.set_max_packet_size(Some(max_size as u32));
+        if let Some(transport) = transport {
+            mqttoptions.set_transport(transport);
+        }
+        if let Some((username, password)) = &credentials {
+            mqttoptions.set_credentials(username, password);
+        }
+        mqttoptions.set_keep_alive(Duration::from_secs(5));
+        mqttoptions.set_last_will(V5LastWill::new(
+            will_topic,
+            will_message,
+            v5_qos(will_qos),
+            will_retain,
+            None,
+        ));
+        let (client, connection) = v5::AsyncClient::new(mqttoptions, 100);
+        Ok((Client::V5(client), Connection::V5(Box::new(connection))))
+    } else {
+        let mut mqttoptions = MqttOptions::new(client_id, &config.broker_addr, config.port);
+        mqttoptions.set_max_packet_size(max_size, max_size);
+        if let Some(transport) = transport {
+            mqttoptions.set_transport(transport);
+        }
+        if let Some((username, password)) = &credentials {
+            mqttoptions.set_credentials(username, password);
+        }
+        mqttoptions.set_keep_alive(Duration::from_secs(5));
+        mqttoptions.set_last_will(LastWill::new(
+            will_topic,
+            will_message,
+            will_qos,
+            will_retain,
+        ));
+        let (client, connection) = AsyncClient::new(mqttoptions, 100);
+        Ok((Client::V3(client), Connection::V3(Box::new(connection))))
+    }
+}
+
+/// Whether the broker refused the MQTT 5 CONNECT itself, a 3.1.1 broker either
+/// answers with a reason code about the protocol or a ConnAck that does not parse
+pub(crate) fn v5_rejected(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<V5ConnectionError>(),
+            Some(V5ConnectionError::ConnectionRefused(
+                V5ConnectReturnCode::UnsupportedProtocolVersion
+                    | V5ConnectReturnCode::ProtocolError
+                    | V5ConnectReturnCode::MalformedPacket
+            )) | Some(V5ConnectionError::MqttState(V5StateError::Deserialization(
+                _
+            )))
+        )
+    })
+}
+
+/// Whether a publish failed because the connection is down or its queue is
+/// full, it can then be sent again once the client reconnects
+pub(crate) fn is_retryable(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause.downcast_ref::<ClientError>().is_some()
+            || cause.downcast_ref::<v5::ClientError>().is_some()
+    })
+}
+
+fn v5_qos(qos: QoS) -> V5QoS {
+    match qos {
+        QoS::AtMostOnce => V5QoS::AtMostOnce,
+        QoS::AtLeastOnce => V5QoS::AtLeastOnce,
+        QoS::ExactlyOnce => V5QoS::ExactlyOnce,
+    }
+}
+
+impl Client {
+    pub(crate) async fn publish<T: Into<Vec<u8>>>(
+        &self,
+        topic: String,
+        qos: QoS,
+        retain: bool,
+        payload: T,
+        meta: PublishMeta,
+    ) -> AnyResult<()> {
+        // rumqttc reports an invalid topic like a closed connection
+        if !valid_topic(&topic) {
+            return Err(anyhow!("Invalid MQTT topic: {topic}"));
+        }
+        match self {
+            Client::V3(client) => client.publish(topic, qos, retain, payload).await?,
+            Client::V5(client) => {
+                let properties = PublishProperties {
+                    message_expiry_interval: meta.expiry,
+                    correlation_data: meta.correlation_data,
+                    user_properties: meta.user_properties,
+                    ..Default::default()
+                };
+                client
+                    .publish_with_properties(topic, v5_qos(qos), retain, payload.into(), properties)
+                    .await?
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn subscribe(&self, topic: &str, qos: QoS) -> AnyResult<()> {
+        match self {
+            Client::V3(client) => client.subscribe(topic, qos).await?,
+            Client::V5(client) => client.subscribe(topic, v5_qos(qos)).await?,
+        }
+        Ok(())
+    }
+}
+
+impl Connection {
+    pub(crate) async fn poll(&mut self) -> AnyResult<Notification> {
+        Ok(match self {
+            Connection::V3(connection) => match connection.poll().await? {
+                Event::Incoming(Incoming::ConnAck(connected)) => {
+                    Notification::ConnAck(connected.code == ConnectReturnCode::Success)
+                }
+                Event::Incoming(Incoming::Publish(published_message)) => Notification::Publish {
+                    topic: published_message.topic,
+                    payload: published_message.payload,
+                    response: None,
+                },
+                _ => Notification::Other,
+            },
+            Connection::V5(connection) => match connection.poll().await? {
+                v5::Event::Incoming(V5Packet::ConnAck(connected)) => {
+                    Notification::ConnAck(connected.code == V5ConnectReturnCode::Success)
+                }
+                v5::Event::Incoming(V5Packet::Publish(published_message)) => {
+                    let response = published_message.properties.and_then(|properties| {
+                        let correlation_data = properties.correlation_data;
+                        properties.response_topic.map(|topic| MqttResponse {
+                            topic,
+                            correlation_data,
+                        })
+                    });
+                    Notification::Publish {
+                        topic: String::from_utf8_lossy(published_message.topic.as_ref())
+                            .into_owned(),
+                        payload: published_message.payload,
+                        response,
+                    }
+                }
+                _ => Notification::Other,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqttc::v5::mqttbytes::Error as V5ParseError;
+
+    #[test]
+    fn test_v5_rejected() {
+        let refused = |code| anyhow::Error::new(V5ConnectionError::ConnectionRefused(code));
+        assert!(v5_rejected(&refused(
+            V5ConnectReturnCode::UnsupportedProtocolVersion
+        )));
+        assert!(v5_rejected(
+            &refused(V5ConnectReturnCode::ProtocolError).context("MQTT connection dropped")
+        ));
+        // A 3.1.1 ConnAck refusing the protocol is code 1, which MQTT 5 does not have
+        assert!(v5_rejected(&anyhow::Error::new(
+            V5ConnectionError::MqttState(V5StateError::Deserialization(
+                V5ParseError::InvalidConnectReturnCode(1)
+            ))
+        )));
+
+        assert!(!v5_rejected(&refused(
+            V5ConnectReturnCode::BadUserNamePassword
+        )));
+        assert!(!v5_rejected(&anyhow::Error::new(V5ConnectionError::Io(
+            std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused")
+        ))));
+        assert!(!v5_rejected(&anyhow!("Some other error")));
+    }
+
+    #[tokio::test]
+    async fn test_is_retryable() {
+        let (client, connection) =
+            AsyncClient::new(MqttOptions::new("test", "localhost", 1883), 10);
+        let client = Client::V3(client);
+        // Without its event loop the client is disconnected
+        drop(connection);
+        let e = client
+            .publish(
+                "neolink/cam/status".to_string(),
+                QoS::AtLeastOnce,
+                false,
+                "on",
+                PublishMeta::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(is_retryable(&e));
+
+        let e = client
+            .publish(
+                "neolink/+/status".to_string(),
+                QoS::AtLeastOnce,
+                false,
+                "on",
+                PublishMeta::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(!is_retryable(&e));
+    }
+}
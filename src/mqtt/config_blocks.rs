@@ -0,0 +1,113 @@
+//! Round trips blocks of the camera's own configuration as json
+//!
+//! The json is the camera's xml structure as serde sees it, so the output
+//! of a query can be edited and sent back as is
+use anyhow::anyhow;
+use neolink_core::{
+    bc::xml::{HttpPort, HttpsPort, OnvifPort, RtmpPort, RtspPort, ServerPort},
+    bc_protocol::NotificationKind,
+};
+
+use crate::{common::NeoInstance, AnyResult};
+
+/// Get a config block of the camera as json
+pub(crate) async fn get_block(camera: &NeoInstance, block: &str) -> AnyResult<String> {
+    let block = block.to_string();
+    camera
+        .run_task(|cam| {
+            let block = block.clone();
+            Box::pin(async move {
+                let json = match block.as_str() {
+                    "ability" => serde_json::to_string(&cam.get_abilityinfo().await?)?,
+                    "floodlight_tasks" => {
+                        serde_json::to_string(&cam.get_flightlight_tasks().await?)?
+                    }
+                    "led" => serde_json::to_string(&cam.get_ledstate().await?)?,
+                    "link" => serde_json::to_string(&cam.get_linktype().await?)?,
+                    "pir" => serde_json::to_string(&cam.get_pirstate().await?)?,
+                    "ptz_preset" => serde_json::to_string(&cam.get_ptz_preset().await?)?,
+                    "stream" => serde_json::to_string(&cam.get_compression().await?)?,
+                    "stream_info" => serde_json::to_string(&cam.get_stream_info().await?)?,
+                    "osd" => serde_json::to_string(&cam.get_osd().await?)?,
+                    "isp" => serde_json::to_string(&cam.get_isp().await?)?,
+                    "email" => serde_json::to_string(
+                        &cam.get_notification_task(NotificationKind::Email).await?,
+                    )?,
+                    "support" => serde_json::to_string(&cam.get_support().await?)?,
+                    "server_port" => serde_json::to_string(&cam.get_serverport().await?)?,
+                    "http_port" => serde_json::to_string(&cam.get_http().await?)?,
+                    "https_port" => serde_json::to_string(&cam.get_https().await?)?,
+                    "rtsp_port" => serde_json::to_string(&cam.get_rtsp().await?)?,
+                    "rtmp_port" => serde_json::to_string(&cam.get_rtmp().await?)?,
+                    "onvif_port" => serde_json::to_string(&cam.get_onvif().await?)?,
+                    _ => return Err(anyhow!("Unknown config block {block}")),
+                };
+                AnyResult::Ok(json)
+            })
+        })
+        .await
+}
+
+/// Set a config block of the camera from json
+pub(crate) async fn set_block(camera: &NeoInstance, block: &str, json: &str) -> AnyResult<()> {
+    let block = block.to_string();
+    let json = json.to_string();
+    camera
+        .run_task(|cam| {
+            let block = block.clone();
+            let json = json.clone();
+            Box::pin(async move {
+                match block.as_str() {
+                    "floodlight_tasks" => {
+                        cam.set_flightlight_tasks(serde_json::from_str(&json)?)
+                            .await?
+                    }
+                    "led" => cam.set_ledstate(serde_json::from_str(&json)?).await?,
+                    "pir" => cam.set_pirstate(serde_json::from_str(&json)?).await?,
+                    "stream" => cam.set_compression(serde_json::from_str(&json)?).await?,
+                    "osd" => cam.set_osd(serde_json::from_str(&json)?).await?,
+                    "isp" => cam.set_isp(serde_json::from_str(&json)?).await?,
+                    "email" => {
+                        cam.set_notification_task(
+                            NotificationKind::Email,
+                            serde_json::from_str(&json)?,
+                        )
+                        .await?
+                    }
+                    "server_port" => {
+                        let port: ServerPort = serde_json::from_str(&json)?;
+                        cam.set_serverport(port.enable.map(|e| e == 1), Some(port.port))
+                            .await?
+                    }
+                    "http_port" => {
+                        let port: HttpPort = serde_json::from_str(&json)?;
+                        cam.set_http(port.enable.map(|e| e == 1), Some(port.port))
+                            .await?
+                    }
+                    "https_port" => {
+                        let port: HttpsPort = serde_json::from_str(&json)?;
+                        cam.set_https(port.enable.map(|e| e == 1), Some(port.port))
+                            .await?
+                    }
+                    "rtsp_port" => {
+                        let port: RtspPort = serde_json::from_str(&json)?;
+                        cam.set_rtsp(port.enable.map(|e| e == 1), Some(port.port))
+                            .await?
+                    }
+                    "rtmp_port" => {
+                        let port: RtmpPort = serde_json::from_str(&json)?;
+                        cam.set_rtmp(port.enable.map(|e| e == 1), Some(port.port))
+                            .await?
+                    }
+                    "onvif_port" => {
+                        let port: OnvifPort = serde_json::from_str(&json)?;
+                        cam.set_onvif(port.enable.map(|e| e == 1), Some(port.port))
+                            .await?
+                    }
+                    _ => return Err(anyhow!("Config block {block} can not be set")),
+                }
+                AnyResult::Ok(())
+            })
+        })
+        .await
+}
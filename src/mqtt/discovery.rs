@@ -5,6 +5,7 @@
 use anyhow::{Context, Result};
 use heck::ToTitleCase;
 use log::*;
+use std::collections::HashSet;
 
 use super::mqttc::MqttInstance;
 use crate::{common::NeoInstance, config::MqttDiscoveryConfig};
@@ -18,6 +19,8 @@ pub(crate) enum Discoveries {
     Camera,
     #[serde(alias = "motion", alias = "md", alias = "pir")]
     Motion,
+    #[serde(alias = "doorbell", alias = "visitor")]
+    Doorbell,
     #[serde(alias = "led")]
     Led,
     #[serde(alias = "ir")]
@@ -28,8 +31,17 @@ pub(crate) enum Discoveries {
     Pt,
     #[serde(alias = "battery", alias = "power")]
     Battery,
+    #[serde(alias = "wifi", alias = "signal")]
+    Wifi,
     #[serde(alias = "siren", alias = "alarm")]
     Siren,
+    #[serde(alias = "pir_switch", alias = "pir_control")]
+    PirSwitch,
+    #[serde(alias = "notifications", alias = "notify")]
+    Notifications,
+    /// Use the features the camera reports that it supports
+    #[serde(alias = "auto")]
+    Auto,
 }
 
 #[derive(Debug, Clone)]
@@ -69,6 +81,13 @@ struct DiscoveryAvaliablity {
     payload_not_available: Option<String>,
 }
 
+/// Entities are only available when all of the topics say so
+#[derive(Serialize, Debug, Clone)]
+struct DiscoveryAvailabilities {
+    availability: Vec<DiscoveryAvaliablity>,
+    availability_mode: String,
+}
+
 #[derive(Serialize, Debug)]
 struct DiscoveryLight {
     name: String,
@@ -76,7 +95,8 @@ struct DiscoveryLight {
     #[serde(skip_serializing_if = "Option::is_none")]
     icon: Option<String>,
     device: DiscoveryDevice,
-    availability: DiscoveryAvaliablity,
+    #[serde(flatten)]
+    availability: DiscoveryAvailabilities,
     // Light specific
     #[serde(skip_serializing_if = "Option::is_none")]
     state_topic: Option<String>,
@@ -86,10 +106,16 @@ struct DiscoveryLight {
     command_topic: Option<String>,
     payload_on: String,
     payload_off: String,
+    // - Brightness
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness_command_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness_state_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness_scale: Option<u32>,
 }
 
 #[derive(Serialize, Debug)]
-#[allow(dead_code)]
 enum Encoding {
     None,
     #[serde(rename = "b64")]
@@ -109,7 +135,8 @@ struct DiscoveryCamera {
     #[serde(skip_serializing_if = "Option::is_none")]
     icon: Option<String>,
     device: DiscoveryDevice,
-    availability: DiscoveryAvaliablity,
+    #[serde(flatten)]
+    availability: DiscoveryAvailabilities,
     // Camera specific
     topic: String,
     #[serde(skip_serializing_if = "Encoding::is_none")]
@@ -123,7 +150,8 @@ struct DiscoverySwitch {
     #[serde(skip_serializing_if = "Option::is_none")]
     icon: Option<String>,
     device: DiscoveryDevice,
-    availability: DiscoveryAvaliablity,
+    #[serde(flatten)]
+    availability: DiscoveryAvailabilities,
     // Switch specific
     // - Control
     command_topic: String,
@@ -145,7 +173,8 @@ struct DiscoverySelect {
     #[serde(skip_serializing_if = "Option::is_none")]
     icon: Option<String>,
     device: DiscoveryDevice,
-    availability: DiscoveryAvaliablity,
+    #[serde(flatten)]
+    availability: DiscoveryAvailabilities,
     // Switch specific
     // - Control
     command_topic: String,
@@ -162,12 +191,15 @@ struct DiscoveryBinarySensor {
     #[serde(skip_serializing_if = "Option::is_none")]
     icon: Option<String>,
     device: DiscoveryDevice,
-    availability: DiscoveryAvaliablity,
+    #[serde(flatten)]
+    availability: DiscoveryAvailabilities,
     // BinarySensor specific
     payload_off: String,
     payload_on: String,
     // - State
     state_topic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_template: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -177,7 +209,8 @@ struct DiscoveryButton {
     #[serde(skip_serializing_if = "Option::is_none")]
     icon: Option<String>,
     device: DiscoveryDevice,
-    availability: DiscoveryAvaliablity,
+    #[serde(flatten)]
+    availability: DiscoveryAvailabilities,
     // Button specific
     command_topic: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -191,11 +224,14 @@ struct DiscoverySensor {
     #[serde(skip_serializing_if = "Option::is_none")]
     icon: Option<String>,
     device: DiscoveryDevice,
-    availability: DiscoveryAvaliablity,
+    #[serde(flatten)]
+    availability: DiscoveryAvailabilities,
     // Button specific
     state_topic: String,
     state_class: String,
     unit_of_measurement: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_class: Option<String>,
 }
 
 /// Enables MQTT discovery for a camera. See docs at https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery
@@ -239,13 +275,47 @@ pub(crate) async fn enable_discovery(
         sw_version: Some(env!("CARGO_PKG_VERSION").to_string()),
     };
 
-    let availability = DiscoveryAvaliablity {
-        topic: format!("neolink/{}/status", cam_config.name),
-        payload_available: Some("connected".to_string()),
-        payload_not_available: None,
+    let availability = DiscoveryAvailabilities {
+        availability: vec![
+            DiscoveryAvaliablity {
+                topic: mqtt.global_topic("status"),
+                payload_available: Some("connected".to_string()),
+                payload_not_available: None,
+            },
+            DiscoveryAvaliablity {
+                topic: mqtt.topic("status/availability"),
+                payload_available: Some("online".to_string()),
+                payload_not_available: Some("offline".to_string()),
+            },
+        ],
+        availability_mode: "all".to_string(),
     };
 
-    for feature in &discovery_config.features {
+    let mut features = discovery_config
+        .features
+        .iter()
+        .filter(|f| **f != Discoveries::Auto)
+        .copied()
+        .collect::<HashSet<_>>();
+    if discovery_config.features.contains(&Discoveries::Auto) {
+        match detect_features(camera, cam_config.channel_id).await {
+            Ok(detected) => {
+                debug!(
+                    "{}: Detected discovery features {:?}",
+                    cam_config.name, detected
+                );
+                features.extend(detected);
+            }
+            Err(e) => {
+                warn!(
+                    "{}: Could not detect the camera's features for discovery: {e:?}",
+                    cam_config.name
+                );
+            }
+        }
+    }
+
+    for feature in &features {
         match feature {
             Discoveries::Floodlight => {
                 let config_data = DiscoveryLight {
@@ -260,14 +330,19 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:spotlight-beam".to_string()),
 
                     // State
-                    state_topic: Some(format!("neolink/{}/status/floodlight", cam_config.name)),
-                    state_value_template: Some("{{ value_json.state }}".to_string()),
+                    state_topic: Some(mqtt.topic("status/floodlight")),
+                    state_value_template: None,
 
                     // Control
-                    command_topic: Some(format!("neolink/{}/control/floodlight", cam_config.name)),
+                    command_topic: Some(mqtt.topic("control/floodlight")),
                     // Lowercase payloads to match neolink convention
                     payload_on: "on".to_string(),
                     payload_off: "off".to_string(),
+
+                    // Brightness in percent
+                    brightness_command_topic: Some(mqtt.topic("control/floodlight/brightness")),
+                    brightness_state_topic: Some(mqtt.topic("status/floodlight/brightness")),
+                    brightness_scale: Some(100),
                 };
 
                 // Each feature needs to be individually registered
@@ -301,15 +376,12 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:spotlight-beam".to_string()),
 
                     // State
-                    state_topic: Some(format!(
-                        "neolink/{}/status/floodlight_tasks",
-                        cam_config.name
-                    )),
+                    state_topic: Some(mqtt.topic("status/floodlight_tasks")),
                     state_on: Some("on".to_string()),
                     state_off: Some("off".to_string()),
 
                     // Control
-                    command_topic: format!("neolink/{}/control/floodlight_tasks", cam_config.name),
+                    command_topic: mqtt.topic("control/floodlight_tasks"),
                     // Lowercase payloads to match neolink convention
                     payload_on: "on".to_string(),
                     payload_off: "off".to_string(),
@@ -346,8 +418,11 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:camera-iris".to_string()),
 
                     // Camera specific
-                    topic: format!("neolink/{}/status/preview", cam_config.name),
-                    image_encoding: Encoding::Base64,
+                    topic: mqtt.topic("status/preview"),
+                    image_encoding: match cam_config.mqtt.preview_format.as_str() {
+                        "jpeg" => Encoding::None,
+                        _ => Encoding::Base64,
+                    },
                 };
 
                 // Each feature needs to be individually registered
@@ -381,12 +456,12 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:led-on".to_string()),
 
                     // Switch specific
-                    command_topic: format!("neolink/{}/control/led", cam_config.name),
+                    command_topic: mqtt.topic("control/led"),
                     payload_off: "off".to_string(),
                     payload_on: "on".to_string(),
-                    state_topic: None,
-                    state_off: None,
-                    state_on: None,
+                    state_topic: Some(mqtt.topic("status/led")),
+                    state_off: Some("off".to_string()),
+                    state_on: Some("on".to_string()),
                 };
 
                 // Each feature needs to be individually registered
@@ -420,9 +495,9 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:lightbulb-night".to_string()),
 
                     // Switch specific
-                    command_topic: format!("neolink/{}/control/ir", cam_config.name),
+                    command_topic: mqtt.topic("control/ir"),
                     options: vec!["on".to_string(), "off".to_string(), "auto".to_string()],
-                    state_topic: None,
+                    state_topic: Some(mqtt.topic("status/ir")),
                 };
 
                 // Each feature needs to be individually registered
@@ -456,7 +531,8 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:motion-sensor".to_string()),
 
                     // Switch specific
-                    state_topic: format!("neolink/{}/status/motion", cam_config.name),
+                    state_topic: mqtt.topic("status/motion/json"),
+                    value_template: Some("{{ value_json.state }}".to_string()),
                     payload_off: "off".to_string(),
                     payload_on: "on".to_string(),
                 };
@@ -480,6 +556,44 @@ pub(crate) async fn enable_discovery(
                     )
                 })?;
             }
+            Discoveries::Doorbell => {
+                let config_data = DiscoveryBinarySensor {
+                    // Common across all potential features
+                    device: device.clone(),
+                    availability: availability.clone(),
+
+                    // Identifiers
+                    name: format!("{} Doorbell", friendly_name.as_str()),
+                    unique_id: format!("neolink_{}_doorbell", cam_config.name),
+                    icon: Some("mdi:doorbell".to_string()),
+
+                    // Switch specific
+                    state_topic: mqtt.topic("status/doorbell"),
+                    value_template: None,
+                    payload_off: "off".to_string(),
+                    payload_on: "on".to_string(),
+                };
+
+                // Each feature needs to be individually registered
+                mqtt.send_message_with_root_topic(
+                    &format!(
+                        "{}/binary_sensor/{}",
+                        discovery_config.topic, &config_data.unique_id
+                    ),
+                    "config",
+                    &serde_json::to_string(&config_data).with_context(|| {
+                        "Cound not serialise discovery doorbell config into json"
+                    })?,
+                    true,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to publish doorbell auto-discover data on over MQTT for {}",
+                        cam_config.name
+                    )
+                })?;
+            }
             Discoveries::Reboot => {
                 let config_data = DiscoveryButton {
                     // Common across all potential features
@@ -492,7 +606,7 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:restart".to_string()),
 
                     // Switch specific
-                    command_topic: format!("neolink/{}/control/reboot", cam_config.name),
+                    command_topic: mqtt.topic("control/reboot"),
                     payload_press: None,
                 };
 
@@ -514,6 +628,44 @@ pub(crate) async fn enable_discovery(
                         cam_config.name
                     )
                 })?;
+
+                let config_data = DiscoveryButton {
+                    // Common across all potential features
+                    device: device.clone(),
+                    // Only needs neolink since it is most useful when the camera is offline
+                    availability: DiscoveryAvailabilities {
+                        availability: vec![availability.availability[0].clone()],
+                        availability_mode: "all".to_string(),
+                    },
+
+                    // Identifiers
+                    name: format!("{} Reconnect", friendly_name.as_str()),
+                    unique_id: format!("neolink_{}_reconnect", cam_config.name),
+                    icon: Some("mdi:lan-connect".to_string()),
+
+                    // Switch specific
+                    command_topic: mqtt.topic("control/reconnect"),
+                    payload_press: None,
+                };
+
+                mqtt.send_message_with_root_topic(
+                    &format!(
+                        "{}/button/{}",
+                        discovery_config.topic, &config_data.unique_id
+                    ),
+                    "config",
+                    &serde_json::to_string(&config_data).with_context(|| {
+                        "Cound not serialise discovery reconnect config into json"
+                    })?,
+                    true,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to publish reconnect auto-discover data on over MQTT for {}",
+                        cam_config.name
+                    )
+                })?;
             }
             Discoveries::Pt => {
                 for dir in ["left", "right", "up", "down"] {
@@ -528,7 +680,7 @@ pub(crate) async fn enable_discovery(
                         icon: Some(format!("mdi:pan-{}", dir)),
 
                         // Switch specific
-                        command_topic: format!("neolink/{}/control/ptz", cam_config.name),
+                        command_topic: mqtt.topic("control/ptz"),
                         payload_press: Some(dir.to_string()),
                     };
 
@@ -564,9 +716,10 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:battery".to_string()),
 
                     // Camera specific
-                    state_topic: format!("neolink/{}/status/battery_level", cam_config.name),
+                    state_topic: mqtt.topic("status/battery_level"),
                     state_class: "measurement".to_string(),
                     unit_of_measurement: "%".to_string(),
+                    device_class: None,
                 };
 
                 // Each feature needs to be individually registered
@@ -589,6 +742,151 @@ pub(crate) async fn enable_discovery(
                     )
                 })?;
             }
+            Discoveries::Wifi => {
+                for (suffix, name, topic, unit, device_class) in [
+                    (
+                        "wifi_rssi",
+                        "Wifi Signal",
+                        "status/wifi/rssi",
+                        "dBm",
+                        Some("signal_strength"),
+                    ),
+                    (
+                        "wifi_quality",
+                        "Wifi Quality",
+                        "status/wifi/quality",
+                        "%",
+                        None,
+                    ),
+                ] {
+                    let config_data = DiscoverySensor {
+                        // Common across all potential features
+                        device: device.clone(),
+                        availability: availability.clone(),
+
+                        // Identifiers
+                        name: format!("{} {}", friendly_name.as_str(), name),
+                        unique_id: format!("neolink_{}_{}", cam_config.name, suffix),
+                        icon: Some("mdi:wifi".to_string()),
+
+                        // Sensor specific
+                        state_topic: mqtt.topic(topic),
+                        state_class: "measurement".to_string(),
+                        unit_of_measurement: unit.to_string(),
+                        device_class: device_class.map(|c| c.to_string()),
+                    };
+
+                    // Each feature needs to be individually registered
+                    mqtt.send_message_with_root_topic(
+                        &format!(
+                            "{}/sensor/{}",
+                            discovery_config.topic, &config_data.unique_id
+                        ),
+                        "config",
+                        &serde_json::to_string(&config_data).with_context(|| {
+                            "Cound not serialise discovery wifi config into json"
+                        })?,
+                        true,
+                    )
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to publish wifi auto-discover data on over MQTT for {}",
+                            cam_config.name
+                        )
+                    })?;
+                }
+            }
+            Discoveries::PirSwitch => {
+                let config_data = DiscoverySwitch {
+                    // Common across all potential features
+                    device: device.clone(),
+                    availability: availability.clone(),
+
+                    // Identifiers
+                    name: format!("{} PIR", friendly_name.as_str()),
+                    unique_id: format!("neolink_{}_pir", cam_config.name),
+                    icon: Some("mdi:motion-sensor".to_string()),
+
+                    // Switch specific
+                    command_topic: mqtt.topic("control/pir"),
+                    payload_off: "off".to_string(),
+                    payload_on: "on".to_string(),
+                    state_topic: None,
+                    state_off: None,
+                    state_on: None,
+                };
+
+                // Each feature needs to be individually registered
+                mqtt.send_message_with_root_topic(
+                    &format!(
+                        "{}/switch/{}",
+                        discovery_config.topic, &config_data.unique_id
+                    ),
+                    "config",
+                    &serde_json::to_string(&config_data)
+                        .with_context(|| "Cound not serialise discovery pir config into json")?,
+                    true,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to publish pir auto-discover data on over MQTT for {}",
+                        cam_config.name
+                    )
+                })?;
+            }
+            Discoveries::Notifications => {
+                for (kind, name, icon) in [
+                    ("email", "Email", "mdi:email"),
+                    ("push", "Push", "mdi:cellphone-message"),
+                    ("ftp", "FTP", "mdi:upload-network"),
+                ] {
+                    let config_data = DiscoverySwitch {
+                        // Common across all potential features
+                        device: device.clone(),
+                        availability: availability.clone(),
+
+                        // Identifiers
+                        name: format!("{} {} Notification", friendly_name.as_str(), name),
+                        unique_id: format!("neolink_{}_notify_{}", cam_config.name, kind),
+                        icon: Some(icon.to_string()),
+
+                        // Switch specific
+                        command_topic: mqtt.topic(&format!("control/notify/{kind}")),
+                        payload_off: "off".to_string(),
+                        payload_on: "on".to_string(),
+                        state_topic: Some(mqtt.topic(&format!("status/notify/{kind}"))),
+                        state_off: Some("off".to_string()),
+                        state_on: Some("on".to_string()),
+                    };
+
+                    // Each feature needs to be individually registered
+                    mqtt.send_message_with_root_topic(
+                        &format!(
+                            "{}/switch/{}",
+                            discovery_config.topic, &config_data.unique_id
+                        ),
+                        "config",
+                        &serde_json::to_string(&config_data).with_context(|| {
+                            "Cound not serialise discovery notification config into json"
+                        })?,
+                        true,
+                    )
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to publish notification auto-discover data on over MQTT for {}",
+                            cam_config.name
+                        )
+                    })?;
+                    // The switch has no state until it is first queried
+                    super::publish_notification_state(mqtt, camera, kind).await?;
+                }
+            }
+            Discoveries::Auto => {
+                // Already expanded into the detected features
+            }
             Discoveries::Siren => {
                 let config_data = DiscoveryButton {
                     // Common across all potential features
@@ -601,7 +899,7 @@ pub(crate) async fn enable_discovery(
                     icon: Some("mdi:bell".to_string()),
 
                     // Switch specific
-                    command_topic: format!("neolink/{}/control/siren", cam_config.name),
+                    command_topic: mqtt.topic("control/siren"),
                     payload_press: Some("on".to_string()),
                 };
 
@@ -635,3 +933,67 @@ pub(crate) async fn enable_discovery(
 
     Ok(())
 }
+
+/// Work out which features the camera supports from its support xml
+async fn detect_features(camera: &NeoInstance, channel_id: u8) -> Result<HashSet<Discoveries>> {
+    let (support, has_floodlight, model) = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let support = cam.get_support().await?;
+                let has_floodlight = cam.get_flightlight_tasks().await.is_ok();
+                // Older firmware does not report the model, the doorbell is just not detected then
+                let model = cam
+                    .version()
+                    .await
+                    .ok()
+                    .and_then(|version| version.model)
+                    .unwrap_or_default();
+                Ok((support, has_floodlight, model))
+            })
+        })
+        .await?;
+
+    let enabled = |v: Option<u32>| v.unwrap_or(0) > 0;
+    // These work on every camera we know of
+    let mut features = HashSet::from([
+        Discoveries::Motion,
+        Discoveries::Camera,
+        Discoveries::Reboot,
+    ]);
+    if has_floodlight {
+        features.insert(Discoveries::Floodlight);
+    }
+    if model.to_lowercase().contains("doorbell") {
+        features.insert(Discoveries::Doorbell);
+    }
+    if enabled(support.email) || enabled(support.push_alarm) || enabled(support.ftp) {
+        features.insert(Discoveries::Notifications);
+    }
+    if enabled(support.wifi) {
+        features.insert(Discoveries::Wifi);
+    }
+    if enabled(support.audio_alarm) {
+        features.insert(Discoveries::Siren);
+    }
+    if let Some(item) = support
+        .items
+        .iter()
+        .find(|item| item.chn_id == channel_id as u32)
+    {
+        if enabled(item.battery) {
+            features.insert(Discoveries::Battery);
+        }
+        if enabled(item.led_ctrl) {
+            // The LedState holds both the status led and the ir lights
+            features.insert(Discoveries::Led);
+            features.insert(Discoveries::Ir);
+        }
+        if enabled(item.rf_cfg) {
+            features.insert(Discoveries::PirSwitch);
+        }
+        if enabled(item.ptz_control) || enabled(item.ptz_type) {
+            features.insert(Discoveries::Pt);
+        }
+    }
+    Ok(features)
+}
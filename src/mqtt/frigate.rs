@@ -0,0 +1,249 @@
+//! Publishes the AI detections of the cameras in the topics of Frigate
+//!
+//! With a `[frigate]` table the detections are sent like Frigate sends its
+//! own, so that its Home Assistant integration, cards and automations can use
+//! the person and vehicle detection of the cameras
+//!
+//! - `frigate/events` A json `{"type": "new|end", "before": .., "after": ..}`
+//!   for each object that the camera starts or stops detecting
+//! - `frigate/<camera>/<object>` How many of that object are detected, `1` or `0`
+//! - `frigate/<camera>/all` How many objects are detected
+//! - `frigate/<camera>/motion` `ON` or `OFF`
+//!
+//! The cameras only tell what they see, so there is one object of each label
+//! and the score, box and zones are not filled. With `go2rtc` and `restream`
+//! the rtsp streams are added to the go2rtc of Frigate as `<camera>` and
+//! `<camera>_sub` so that they can be its inputs
+use log::*;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::sync::{mpsc::unbounded_channel, watch::Receiver as WatchReceiver};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::mqttc::MqttInstance;
+use crate::{
+    common::NeoReactor,
+    config::{Config, FrigateConfig},
+    events::{watch, Event},
+    AnyResult,
+};
+use neolink_core::bc_protocol::StreamKind;
+
+/// Publish the detections while there is a `[frigate]` table, following its changes
+pub(crate) async fn run(
+    mut config: WatchReceiver<Config>,
+    reactor: NeoReactor,
+    mqtt: MqttInstance,
+) -> AnyResult<()> {
+    loop {
+        let frigate = config.borrow_and_update().frigate.clone();
+        let thread_config = config.clone();
+        tokio::select! {
+            v = config.wait_for(|new_config| new_config.frigate != frigate) => {
+                v?;
+            }
+            v = async {
+                match frigate.as_ref() {
+                    Some(frigate) => run_frigate(frigate, &reactor, &mqtt, thread_config).await,
+                    None => futures::future::pending().await,
+                }
+            } => v?,
+        }
+    }
+}
+
+async fn run_frigate(
+    frigate: &FrigateConfig,
+    reactor: &NeoReactor,
+    mqtt: &MqttInstance,
+    config: WatchReceiver<Config>,
+) -> AnyResult<()> {
+    // Stops the watching when the config changes
+    let cancel = CancellationToken::new();
+    let _drop_cancel = cancel.clone().drop_guard();
+    let (tx, mut rx) = unbounded_channel();
+    let watching = watch(reactor.clone(), cancel, move |event| {
+        let _ = tx.send(event);
+    });
+    let publishing = async {
+        // The open events of each camera by their label
+        let mut objects: HashMap<String, HashMap<String, Value>> = HashMap::new();
+        while let Some(event) = rx.recv().await {
+            if !frigate.cameras.is_empty() && !frigate.cameras.contains(&event.camera) {
+                continue;
+            }
+            let open = objects.entry(event.camera.clone()).or_default();
+            if let Err(e) = publish(frigate, mqtt, &event, open).await {
+                warn!("{}: Failed to publish to frigate: {e:?}", event.camera);
+            }
+        }
+    };
+    let registering = async {
+        match (frigate.go2rtc.as_ref(), frigate.restream.as_ref()) {
+            (Some(go2rtc), Some(restream)) => register(go2rtc, restream, frigate, config).await,
+            _ => AnyResult::Ok(()),
+        }
+    };
+    let (watched, _, registered) = tokio::join!(watching, publishing, registering);
+    registered?;
+    watched
+}
+
+/// Open and close the events of the labels of the camera to match the event
+async fn publish(
+    frigate: &FrigateConfig,
+    mqtt: &MqttInstance,
+    event: &Event,
+    open: &mut HashMap<String, Value>,
+) -> AnyResult<()> {
+    let labels = match event.kind.as_str() {
+        "motion_start" => event
+            .detections
+            .iter()
+            .filter_map(|detection| label(detection))
+            .collect::<Vec<_>>(),
+        "motion_stop" | "disconnected" => vec![],
+        _ => return Ok(()),
+    };
+    let prefix = frigate.prefix.as_str();
+    let camera = event.camera.as_str();
+    let time = event.time as f64;
+
+    // The camera sends more detections as the motion goes on, they are kept until it stops
+    for label in labels.iter() {
+        if open.contains_key(*label) {
+            continue;
+        }
+        let after = object(camera, label, time);
+        send(
+            mqtt,
+            prefix,
+            "events",
+            &json!({ "type": "new", "before": after, "after": after }).to_string(),
+        )
+        .await?;
+        send(mqtt, prefix, &format!("{camera}/{label}"), "1").await?;
+        open.insert(label.to_string(), after);
+    }
+    if labels.is_empty() {
+        for (label, before) in open.drain() {
+            let mut after = before.clone();
+            after["end_time"] = json!(time);
+            after["active"] = json!(false);
+            send(
+                mqtt,
+                prefix,
+                "events",
+                &json!({ "type": "end", "before": before, "after": after }).to_string(),
+            )
+            .await?;
+            send(mqtt, prefix, &format!("{camera}/{label}"), "0").await?;
+        }
+    }
+    send(
+        mqtt,
+        prefix,
+        &format!("{camera}/all"),
+        &open.len().to_string(),
+    )
+    .await?;
+    if event.kind != "disconnected" {
+        let motion = if event.kind == "motion_start" {
+            "ON"
+        } else {
+            "OFF"
+        };
+        send(mqtt, prefix, &format!("{camera}/motion"), motion).await?;
+    }
+    Ok(())
+}
+
+/// The Frigate label of an AI detection of the camera
+fn label(detection: &str) -> Option<&str> {
+    match detection {
+        "people" => Some("person"),
+        "vehicle" => Some("car"),
+        // Plain motion and doorbell presses are not objects
+        "md" | "visitor" => None,
+        other => Some(other),
+    }
+}
+
+/// An object in the form of the events of Frigate
+fn object(camera: &str, label: &str, time: f64) -> Value {
+    let id = Uuid::new_v4().simple().to_string();
+    json!({
+        "id": format!("{time:.6}-{}", &id[..6]),
+        "camera": camera,
+        "frame_time": time,
+        "label": label,
+        "sub_label": null,
+        // The camera does not give a score
+        "score": 1.0,
+        "top_score": 1.0,
+        "start_time": time,
+        "end_time": null,
+        "false_positive": false,
+        "stationary": false,
+        "active": true,
+        "current_zones": [],
+        "entered_zones": [],
+        "has_clip": false,
+        "has_snapshot": false,
+    })
+}
+
+async fn send(mqtt: &MqttInstance, prefix: &str, topic: &str, message: &str) -> AnyResult<()> {
+    mqtt.send_message_with_root_topic(prefix, topic, message, false)
+        .await
+}
+
+/// Add the streams to go2rtc, again whenever the config changes
+async fn register(
+    go2rtc: &str,
+    restream: &str,
+    frigate: &FrigateConfig,
+    mut config: WatchReceiver<Config>,
+) -> AnyResult<()> {
+    let client = Client::new();
+    let url = format!("{}/api/streams", go2rtc.trim_end_matches('/'));
+    let restream = restream.trim_end_matches('/');
+    loop {
+        let streams = config
+            .borrow_and_update()
+            .cameras
+            .iter()
+            .filter(|cam| cam.enabled && cam.rtsp)
+            .filter(|cam| frigate.cameras.is_empty() || frigate.cameras.contains(&cam.name))
+            .flat_map(|cam| {
+                cam.stream_kinds()
+                    .into_iter()
+                    .filter_map(|kind| {
+                        let suffix = match kind {
+                            StreamKind::Main => "",
+                            StreamKind::Sub => "_sub",
+                            StreamKind::Extern => "_extern",
+                        };
+                        let path = cam.rtsp_paths(kind).into_iter().next()?;
+                        Some((format!("{}{suffix}", cam.name), format!("{restream}{path}")))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for (name, src) in streams.iter() {
+            let res = client
+                .put(&url)
+                .query(&[("name", name.as_str()), ("src", src.as_str())])
+                .send()
+                .await
+                .and_then(|reply| reply.error_for_status());
+            match res {
+                Ok(_) => debug!("Registered {name} with go2rtc"),
+                Err(e) => warn!("Failed to register {name} with go2rtc at {go2rtc}: {e}"),
+            }
+        }
+        config.changed().await?;
+    }
+}
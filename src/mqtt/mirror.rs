@@ -0,0 +1,282 @@
+//! Copies a subset of the published topics to other brokers
+//!
+//! Only messages that are published to the main broker are mirrored,
+//! the mirrors are never subscribed to so they cannot control the cameras
+use anyhow::anyhow;
+use rumqttc::QoS;
+use std::{collections::HashMap, sync::Arc};
+use tokio::{
+    sync::{
+        broadcast::{error::RecvError, Receiver as BroadcastReceiver, Sender as BroadcastSender},
+        watch::Receiver as WatchReceiver,
+    },
+    task::JoinSet,
+    time::{sleep, Duration},
+};
+use uuid::Uuid;
+
+use super::{
+    client::{connect, v5_rejected, Notification, PublishMeta},
+    topics::TopicFormat,
+};
+use crate::{
+    config::{Config, MqttMirrorConfig, MqttProtocol},
+    AnyResult,
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct MirrorMessage {
+    pub(crate) topic: String,
+    pub(crate) payload: Arc<Vec<u8>>,
+    pub(crate) retain: bool,
+}
+
+/// Run the mirrors, restarting them when their config changes
+pub(crate) async fn run(
+    mut config: WatchReceiver<Config>,
+    tx: BroadcastSender<MirrorMessage>,
+) -> AnyResult<()> {
+    loop {
+        let mirrors = config.borrow().mqtt_mirrors.clone();
+        let mut set = JoinSet::new();
+        for mirror in mirrors.iter().cloned() {
+            let rx = tx.subscribe();
+            let config = config.clone();
+            set.spawn(async move {
+                let name = mirror.name.clone();
+                let r = run_mirror(mirror, rx, config).await;
+                log::debug!("MQTT mirror {name} stopped: {r:?}");
+            });
+        }
+        config
+            .wait_for(|new_config| new_config.mqtt_mirrors != mirrors)
+            .await?;
+        set.shutdown().await;
+    }
+}
+
+async fn run_mirror(
+    mirror: MqttMirrorConfig,
+    mut rx: BroadcastReceiver<MirrorMessage>,
+    config: WatchReceiver<Config>,
+) -> AnyResult<()> {
+    let starts_with_v5 = mirror.server.protocol != MqttProtocol::V311;
+    let mut use_v5 = starts_with_v5;
+    let status_topic = TopicFormat::new(&mirror.server).topic("", "status");
+    // The last retained message of each topic, a new broker or one that lost
+    // its retained messages gets them again on connect
+    let mut retained: HashMap<String, MirrorMessage> = HashMap::new();
+    loop {
+        let mut connected = false;
+        let r: AnyResult<()> = async {
+            let (client, mut connection) = connect(
+                &mirror.server,
+                format!("Neolink_{}_{}", mirror.name, Uuid::new_v4()),
+                (
+                    status_topic.clone(),
+                    "offline".to_string(),
+                    QoS::AtLeastOnce,
+                    true,
+                ),
+                use_v5,
+            )
+            .await?;
+            loop {
+                tokio::select! {
+                    v = connection.poll() => {
+                        if let Notification::ConnAck(true) = v? {
+                            connected = true;
+                            client
+                                .publish(
+                                    status_topic.clone(),
+                                    QoS::AtLeastOnce,
+                                    true,
+                                    "connected",
+                                    PublishMeta::default(),
+                                )
+                                .await?;
+                            for msg in retained.values() {
+                                if should_mirror(&mirror, &config.borrow(), &msg.topic) {
+                                    client
+                                        .publish(
+                                            msg.topic.clone(),
+                                            QoS::AtLeastOnce,
+                                            true,
+                                            msg.payload.as_ref().clone(),
+                                            PublishMeta::default(),
+                                        )
+                                        .await?;
+                                }
+                            }
+                        }
+                    }
+                    v = rx.recv() => {
+                        let msg = match v {
+                            Ok(msg) => msg,
+                            Err(RecvError::Lagged(n)) => {
+                                log::warn!("MQTT mirror {} dropped {n} messages", mirror.name);
+                                continue;
+                            }
+                            Err(RecvError::Closed) => return Err(anyhow!("Mirror source closed")),
+                        };
+                        if should_mirror(&mirror, &config.borrow(), &msg.topic) {
+                            if msg.retain {
+                                // An empty retained message clears the topic
+                                if msg.payload.is_empty() {
+                                    retained.remove(&msg.topic);
+                                } else {
+                                    retained.insert(msg.topic.clone(), msg.clone());
+                                }
+                            }
+                            client
+                                .publish(
+                                    msg.topic,
+                                    QoS::AtLeastOnce,
+                                    msg.retain,
+                                    msg.payload.as_ref().clone(),
+                                    PublishMeta::default(),
+                                )
+                                .await?;
+                        }
+                    }
+                }
+            }
+        }
+        .await;
+        log::error!("MQTT mirror {} connection failed: {r:?}", mirror.name);
+        let refused_v5 = r.as_ref().err().map(v5_rejected).unwrap_or(false);
+        if use_v5 && mirror.server.protocol == MqttProtocol::Auto && !connected && refused_v5 {
+            log::info!(
+                "MQTT mirror {} refused MQTT 5, falling back to MQTT 3.1.1",
+                mirror.name
+            );
+            use_v5 = false;
+        } else {
+            use_v5 = starts_with_v5;
+        }
+        sleep(Duration::from_secs(2)).await;
+    }
+}
+
+/// Whether the topic belongs to a camera assigned to this mirror and matches its filters
+fn should_mirror(mirror: &MqttMirrorConfig, config: &Config, topic: &str) -> bool {
+    let Some((camera, sub_topic)) = config
+        .mqtt
+        .as_ref()
+        .and_then(|server| TopicFormat::new(server).parse(topic))
+    else {
+        return false;
+    };
+    let assigned = config
+        .cameras
+        .iter()
+        .any(|c| c.name == camera && c.mqtt.mirrors.contains(&mirror.name));
+    assigned
+        && mirror
+            .topics
+            .iter()
+            .any(|filter| topic_matches(filter, &sub_topic))
+}
+
+/// Match a topic against an MQTT filter with `+` and `#` wildcards
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for filter_level in filter.split('/') {
+        match (filter_level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (f, Some(t)) if f == t => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+[mqtt]
+broker_addr = "localhost"
+port = 1883
+
+[[mqtt_mirrors]]
+name = "backup"
+broker_addr = "backup"
+port = 1883
+topics = ["status/+", "event/#"]
+
+[[cameras]]
+name = "driveway"
+username = "admin"
+address = "192.168.1.10:9000"
+mqtt = { mirrors = ["backup"] }
+
+[[cameras]]
+name = "garden"
+username = "admin"
+address = "192.168.1.11:9000"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("#", "status"));
+        assert!(topic_matches("#", "status/motion/json"));
+        assert!(topic_matches("status/#", "status/motion"));
+        assert!(topic_matches("status/#", "status/motion/json"));
+        assert!(topic_matches("status/+", "status/motion"));
+        assert!(topic_matches("+/motion", "status/motion"));
+        assert!(topic_matches("status/motion", "status/motion"));
+
+        assert!(!topic_matches("status/+", "status/motion/json"));
+        assert!(!topic_matches("status/+", "status"));
+        assert!(!topic_matches("status/motion", "status/motion/json"));
+        assert!(!topic_matches("status/motion/json", "status/motion"));
+        assert!(!topic_matches("event/#", "status/motion"));
+    }
+
+    #[test]
+    fn test_should_mirror() {
+        let config = config();
+        let mirror = &config.mqtt_mirrors[0];
+        assert!(should_mirror(
+            mirror,
+            &config,
+            "neolink/driveway/status/motion"
+        ));
+        assert!(should_mirror(
+            mirror,
+            &config,
+            "neolink/driveway/event/sdcard"
+        ));
+        // Not matched by the filters
+        assert!(!should_mirror(
+            mirror,
+            &config,
+            "neolink/driveway/status/motion/json"
+        ));
+        assert!(!should_mirror(
+            mirror,
+            &config,
+            "neolink/driveway/control/led"
+        ));
+        // A camera that is not assigned to the mirror
+        assert!(!should_mirror(
+            mirror,
+            &config,
+            "neolink/garden/status/motion"
+        ));
+        // Outside of the topics of neolink
+        assert!(!should_mirror(
+            mirror,
+            &config,
+            "other/driveway/status/motion"
+        ));
+    }
+}
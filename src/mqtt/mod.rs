@@ -5,34 +5,97 @@
 //!
 //! This acts as a bridge between cameras and MQTT servers
 //!
-//! Messages are prefixed with `neolink/{CAMERANAME}`
+//! Messages are prefixed with `neolink/{CAMERANAME}`, this layout
+//! can be changed with the `prefix` and `topic_template` of the mqtt config
 //!
 //! Control messages:
 //!
-//! - `/control/floodlight [on|off]` Turns floodlight (if equipped) on/off
+//! - `/control/floodlight [on|off] (duration)` Turns floodlight (if equipped) on/off,
+//!   duration is in seconds and defaults to 180
+//! - `/control/floodlight brightness [0-100]` Sets the floodlight brightness in percent
+//! - `/control/floodlight/brightness [0-100]` Sets the floodlight brightness in percent
 //! - `/control/led [on|off]` Turns status LED on/off
 //! - `/control/pir [on|off]` Turns PIR on/off
 //! - `/control/ir [on|off|auto]` Turn IR lights on/off or automatically via light detection
+//! - `/control/config/<block> [json]` Set a block of the camera's config from json
+//! - `/control/notify/[email|push|ftp] [on|off]` Turn the camera's own email, cloud push
+//!   or FTP upload on/off
 //! - `/control/reboot` Reboot the camera
+//! - `/control/reconnect` Drop the connection to the camera and connect again
+//! - `/control/siren [on|off] (duration)` Sound the siren once, or for duration seconds, or stop it
+//! - `/control/quick_reply [name]` Play one of the `quick_replies` audio files through the speaker
 //! - `/control/ptz` [up|down|left|right|in|out] (amount) Control the PTZ movements, amount defaults to 32.0
+//! - `/control/ptz/move` [up|down|left|right] (speed) (seconds) Move the camera at a speed for a duration
+//! - `/control/ptz/stop` Stop any PTZ movement
 //! - `/control/ptz/preset` [id] Move the camera to a known preset
 //! - `/control/ptz/assign` [id] [name] Assign the current ptz position to an ID and name
+//! - `/control/ptz/save` [id] [name] Same as `/control/ptz/assign`
 //!
 //! Status Messages:
 //!
-//! `/status offline` Sent when the neolink goes offline this is a LastWill message
+//! `/status offline` Sent when the neolink goes offline this is a LastWill message,
+//!    it is also sent when neolink shuts down
 //! `/status disconnected` Sent when the camera goes offline
+//! `/status failed` Sent when the camera is given up on after `retry.max_attempts`
+//! `/status login_failed` Sent when the camera does not accept the login, it is not
+//!    tried again until the config changes or a `/control/reconnect`
+//! `/status/availability [online|offline]` Retained camera availability, `offline` is
+//!    also the LastWill so that it is set if neolink drops
+//! `/status/motion [on|off]` Sent when motion starts or stops, disabled with `motion_legacy = false`
+//! `/status/motion/json` A json object with the `state`, `timestamp`, `detections` and `channel`
+//!    of the motion, sent when motion starts or stops
+//!    The motion is not sent outside of the `[cameras.schedule]` of the camera
+//! `/status/motion/clip` A short mp4 or gif of the stream, sent when motion starts with
+//!    `enable_motion_clip = true`
+//! `/status/motion/clip/file` The path of the clip instead, when `motion_clip_dir` is set
+//! `/status/doorbell [on|off]` Sent when the doorbell button is pressed, neither is
+//!    retained so a reconnect never looks like a new press
 //! `/status/battery` Sent in reply to a `/query/battery`
+//! `/status/floodlight [on|off]` Sent when the floodlight turns on or off
+//! `/status/floodlight/brightness` Sent in reply to a `/query/floodlight`
+//! `/status/config/<block>` The json of a camera config block, sent in reply to a
+//!    `/query/config/<block>` or after a `/control/config/<block>`
+//! `/status/ir [on|off|auto]` Sent after a `/control/ir` or in reply to a `/query/ir`
+//! `/status/led [on|off|auto]` Sent after a `/control/led` or in reply to a `/query/led`
 //! `/status/pir` Sent in reply to a `/query/pir`
+//! `/status/notify/[email|push|ftp] [on|off]` Sent after a `/control/notify/..` or in
+//!    reply to a `/query/notify/..`
+//! `/status/sdcard` A json object with the `state` (ok|full|error|missing), `capacity`,
+//!    `free` (in MB) and `used_percent` of the SD card, sent every `sdcard_update` ms
+//! `/event/failed` A json object with the `give_up` and the seconds until it is tried again
+//!    in `retry_in`, sent once when the camera is given up on
+//! `/event/login_failed` A json object with the `username` and if there is a `secondary`
+//!    password, sent once when the camera does not accept the login
+//! `/event/task_failed` A json object with the `task`, `error`, `failures` and `retry_in` of a
+//!    task of the camera such as its stream or motion that failed and is restarted
+//! `/event/sdcard` The same json as `/status/sdcard`, sent once when the card fails or
+//!    passes `sdcard_full` percent
+//! `/status/wifi/rssi` The wifi signal strength in dBm, sent every `wifi_update` ms
+//! `/status/wifi/quality` The wifi link quality in percent, sent with `/status/wifi/rssi`
+//! `/status/json` A json document of the connection, uptime, model, firmware, battery,
+//!    wifi signal, SD card and streams of the camera, sent every `status_json_update` ms
 //! `/status/ptz/preset` Sent in reply to a `/query/ptz/preset`
+//! `/status/ptz/preset/current` The preset the camera last moved to or `none` after a manual move
+//! `/status/ptz/zoom` The zoom factor, sent after zooming or in reply to a `/query/ptz/zoom`
+//! `/status/rtsp_clients` A json list of the rtsp clients watching the camera,
+//!    published when a client connects or disconnects
 //!
 //! Query Messages:
 //!
 //! `/query/battery` Request that the camera reports its battery level
+//! `/query/floodlight` Request that the camera reports its floodlight brightness
+//! `/query/config/<block>` Request that the camera reports a block of its config as json
+//! `/query/ir` Request that the camera reports its ir light mode
+//! `/query/led` Request that the camera reports its status led
 //! `/query/pir` Request that the camera reports its pir status
+//! `/query/notify/[email|push|ftp]` Request that the camera reports if the notification is on
 //! `/query/ptz/preset` Request that the camera reports the PTZ presets
-//! `/query/preview` Request that the camera post a base64 encoded jpeg
-//!    of the stream to `/status/preview`
+//! `/query/ptz/zoom` Request that the camera reports the zoom factor
+//! `/query/preview` Request that the camera post a jpeg of the stream
+//!    to `/status/preview`, base64 encoded unless `preview_format = "jpeg"`
+//!
+//! With a `[frigate]` table the AI detections are also published in the topics
+//! of Frigate, see [`frigate`]
 //!
 //!
 //! # Usage
@@ -60,25 +123,45 @@
 //! `credentials` are the username and password required to identify with the mqtt server
 //!
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tokio::{
     sync::mpsc::channel as mpsc,
     task::JoinSet,
-    time::{interval, sleep, Duration, MissedTickBehavior},
+    time::{interval, sleep, timeout, Duration, Instant, MissedTickBehavior},
 };
 use tokio_stream::{wrappers::IntervalStream, StreamExt};
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 use validator::Validate;
 
-use neolink_core::bc_protocol::{Direction as BcDirection, LightState};
+use neolink_core::bc_protocol::{
+    Direction as BcDirection, LightState, MotionDetails, NotificationKind,
+};
 
+mod client;
 mod cmdline;
+mod config_blocks;
 mod discovery;
+mod frigate;
+mod mirror;
+#[cfg(feature = "gstreamer")]
+mod motion_clip;
 mod mqttc;
+mod schedule;
+mod sdcard;
+mod status_json;
+mod tls;
+mod topics;
 
 use crate::{
-    common::{MdState, NeoInstance, NeoReactor},
+    common::{MdState, NeoCamFailure, NeoInstance, NeoReactor, TaskSupervisor},
     config::Config,
+    managed,
+    schedule::{is_active, Gate},
     AnyResult,
 };
 use anyhow::{anyhow, Context, Result};
@@ -86,6 +169,10 @@ pub(crate) use cmdline::Opt;
 pub(crate) use discovery::Discoveries;
 use log::*;
 use mqttc::{Mqtt, MqttReplyRef};
+use schedule::PublishSchedule;
+use sdcard::SdCardStatus;
+use status_json::StatusJson;
+pub(crate) use topics::TOPIC_KINDS;
 
 use self::{
     discovery::enable_discovery,
@@ -117,10 +204,10 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
                 let mut config_names = HashSet::new();
                 loop {
                     thread_config.wait_for(|config| {
-                        let current_names = config.cameras.iter().filter(|a| a.enabled).map(|cam_config| cam_config.name.clone()).collect::<HashSet<_>>();
+                        let current_names = config.cameras.iter().filter(|a| a.enabled && a.mqtt.enabled).map(|cam_config| cam_config.name.clone()).collect::<HashSet<_>>();
                         current_names != config_names
                     }).await.with_context(|| "Camera Config Watcher")?;
-                    config_names = thread_config.borrow().clone().cameras.iter().filter(|a| a.enabled).map(|cam_config| cam_config.name.clone()).collect::<HashSet<_>>();
+                    config_names = thread_config.borrow().clone().cameras.iter().filter(|a| a.enabled && a.mqtt.enabled).map(|cam_config| cam_config.name.clone()).collect::<HashSet<_>>();
 
                     for name in config_names.iter() {
                         log::info!("{name}: MQTT Starting");
@@ -132,28 +219,28 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
                             let thread_reactor2 = thread_reactor.clone();
                             let mqtt_instance = thread_instance.subscribe(name).await?;
                             let name = name.clone();
+                            let span = tracing::info_span!("mqtt", camera = %name);
                             set.spawn(async move {
+                                let mut supervisor = TaskSupervisor::new("mqtt", thread_reactor2.get(&name).await?);
                                 loop {
                                     let camera = thread_reactor2.get(&name).await?;
                                     let mqtt_instance = mqtt_instance.resubscribe().await?;
-                                    let r = tokio::select!{
+                                    let restart = tokio::select!{
                                         _ = thread_global_cancel.cancelled() => {
-                                            AnyResult::Ok(())
+                                            false
                                         },
                                         _ = local_cancel.cancelled() => {
-                                            AnyResult::Ok(())
+                                            false
                                         },
-                                        v = listen_on_camera(camera, mqtt_instance) => {
-                                            v
+                                        v = supervisor.run(listen_on_camera(camera, mqtt_instance)) => {
+                                            v?
                                         },
                                     };
-                                    if let Ok(()) = &r {
-                                        break r
-                                    } else {
-                                        continue;
+                                    if !restart {
+                                        break AnyResult::Ok(());
                                     }
                                 }
-                            }) ;
+                            }.instrument(span));
                         }
                     }
 
@@ -215,36 +302,12 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
                         let mut config = config?;
 
                         // Fill in skipped passwords
-                        if let (Some(mqtt), Some(curr_mqtt)) = (config.mqtt.as_mut(), curr_config.mqtt.as_ref()) {
-                            if mqtt.credentials.is_none() {
-                                mqtt.credentials = curr_mqtt.credentials.clone();
-                            }
-                            if mqtt.ca.is_none() {
-                                mqtt.ca = curr_mqtt.ca.clone();
-                            }
-                            if mqtt.client_auth.is_none() {
-                                mqtt.client_auth = curr_mqtt.client_auth.clone();
-                            }
-                        }
-                        for cam in config.cameras.iter_mut() {
-                            let name = cam.name.clone();
-                            let cur_cam = curr_config.cameras.iter().find(|c| c.name == name);
-                            if let Some(cur_cam) = cur_cam.as_ref() {
-                                if cam.password.is_none() {
-                                    cam.password = cur_cam.password.clone();
-                                }
-                            }
-                        }
-                        for user in config.users.iter_mut() {
-                            let name = user.name.clone();
-                            let cur_user = curr_config.users.iter().find(|c| c.name == name);
-                            if let Some(cur_user) = cur_user.as_ref() {
-                                if user.pass.is_none() {
-                                    user.pass = cur_user.pass.clone();
-                                }
-                            }
+                        if let Err(e) = config.restore_secrets(&curr_config) {
+                            thread_instance
+                                .send_message("config/status", &format!("{:?}", e), false)
+                                .await?;
+                            continue;
                         }
-                        // Passwords should now be restored if they were not set
 
                         let validate = config.validate().with_context(|| {
                             format!("Failed to validate the MQTT {:?} config file", msg.topic)
@@ -265,6 +328,38 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
                             .send_message("config/status", &format!("{:?}", result), false)
                             .await?;
                         log::info!("Updated config");
+                    } else if let Some(action) = msg.topic.strip_prefix("cameras/") {
+                        let manage = thread_config
+                            .borrow()
+                            .mqtt
+                            .as_ref()
+                            .is_some_and(|mqtt| mqtt.manage_cameras);
+                        let result = match action {
+                            "add" | "update" | "remove" if !manage => Err(anyhow!(
+                                "Set manage_cameras in the [mqtt] config to manage the cameras over mqtt"
+                            )),
+                            "add" => match camera_table(&msg.message) {
+                                Ok(camera) => managed::add(&thread_reactor, camera).await,
+                                Err(e) => Err(e),
+                            },
+                            "update" => match camera_table(&msg.message) {
+                                Ok(camera) => match camera.get("name").and_then(|n| n.as_str()).map(str::to_string) {
+                                    Some(name) => managed::update(&thread_reactor, &name, camera).await,
+                                    None => Err(anyhow!("The camera needs a name")),
+                                },
+                                Err(e) => Err(e),
+                            },
+                            "remove" => managed::remove(&thread_reactor, msg.message.trim()).await,
+                            // Such as our own `cameras/status`
+                            _ => continue,
+                        };
+                        let status = match result {
+                            Ok(()) => "OK".to_string(),
+                            Err(e) => format!("FAIL: {e:#}"),
+                        };
+                        thread_instance
+                            .send_message("cameras/status", &status, false)
+                            .await?;
                     }
                 }
                 AnyResult::Ok(())
@@ -272,6 +367,51 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
         }
     });
 
+    // This thread publishes the detections for frigate
+    let thread_config = config.clone();
+    let thread_instance = mqtt.subscribe("").await?;
+    let thread_reactor = reactor.clone();
+    let thread_cancel = global_cancel.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            v = frigate::run(thread_config, thread_reactor, thread_instance) => v,
+        }
+    });
+
+    // On shutdown the cameras are published as offline, as their LastWill
+    // would be, and then the other threads are stopped. The messages are sent
+    // first as they would be lost with the threads that are cancelled
+    let thread_config = config.clone();
+    let thread_instance = mqtt.subscribe("").await?;
+    let thread_cancel = global_cancel.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            _ = crate::shutdown::requested() => {
+                let names = thread_config.borrow().cameras.iter().filter(|a| a.enabled && a.mqtt.enabled).map(|cam_config| cam_config.name.clone()).collect::<Vec<_>>();
+                let offline = async {
+                    for name in names {
+                        let instance = thread_instance.subscribe(&name).await?;
+                        instance.send_message("status", "disconnected", true).await?;
+                        instance.send_message("status/availability", "offline", true).await?;
+                    }
+                    AnyResult::Ok(())
+                };
+                // The sends wait for a reconnect so a lost broker does not hold up the shutdown
+                let r = match timeout(Duration::from_secs(5), offline).await {
+                    Ok(r) => r,
+                    Err(_) => {
+                        warn!("Timed out publishing that the cameras are offline");
+                        Ok(())
+                    }
+                };
+                thread_cancel.cancel();
+                r
+            },
+        }
+    });
+
     while let Some(result) = set.join_next().await {
         if let Err(_) | Ok(Err(_)) = &result {
             global_cancel.cancel();
@@ -279,16 +419,25 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
         }
     }
 
+    if crate::shutdown::is_requested() {
+        mqtt.hang_up().await?;
+        info!("Published that neolink is offline");
+    }
     drop(cancel_drop);
     Ok(())
 }
 
+/// Stops the timer of the last `control/siren` with a duration, so that a
+/// newer siren command is not cut short by it
+type SirenTimer = Arc<Mutex<CancellationToken>>;
+
 async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> Result<()> {
     let mut watch_config = camera.config().await?;
     let camera_name = watch_config.borrow().name.clone();
     let mut config;
     let cancel = CancellationToken::new();
     let drop_cancel = cancel.clone().drop_guard();
+    let siren_timer: SirenTimer = Default::default();
     let r = loop {
         config = watch_config.borrow().clone().mqtt;
         break tokio::select! {
@@ -303,6 +452,11 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         .await
                         .with_context(|| format!("Failed to publish status for {}", camera_name))?;
                 let _drop_message = mqtt_instance.last_will("status", "disconnected").await?;
+                mqtt_instance
+                    .send_message("status/availability", "offline", true)
+                    .await
+                    .with_context(|| format!("Failed to publish availability for {}", camera_name))?;
+                let _drop_availability = mqtt_instance.last_will("status/availability", "offline").await?;
                 mqtt_instance
                     .send_message("status/motion", "unknown", true)
                     .await
@@ -320,6 +474,7 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                 let camera_msg = camera.clone();
                 let mut mqtt_msg = mqtt_instance.resubscribe().await?;
                 let cancel_msg = cancel.clone();
+                let siren_msg = siren_timer.clone();
                 let mut set_msg = JoinSet::new();
 
                 let mut camera_watch = camera.camera();
@@ -331,11 +486,24 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                 let camera_motion = camera.clone();
                 let mqtt_motion = mqtt_instance.resubscribe().await?;
 
+                #[cfg(feature = "gstreamer")]
+                let camera_clip = camera.clone();
+                #[cfg(feature = "gstreamer")]
+                let mqtt_clip = mqtt_instance.resubscribe().await?;
+
+                let camera_doorbell = camera.clone();
+                let mqtt_doorbell = mqtt_instance.resubscribe().await?;
+
                 #[cfg(feature = "pushnoti")]
                 let camera_pn = camera.clone();
                 #[cfg(feature = "pushnoti")]
                 let mqtt_pn = mqtt_instance.resubscribe().await?;
 
+                #[cfg(feature = "pushnoti")]
+                let camera_pn_motion = camera.clone();
+                #[cfg(feature = "pushnoti")]
+                let mqtt_pn_motion = mqtt_instance.resubscribe().await?;
+
                 let camera_snap = camera.clone();
                 let mqtt_snap = mqtt_instance.resubscribe().await?;
 
@@ -345,6 +513,24 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                 let camera_floodlight_tasks = camera.clone();
                 let mqtt_floodlight_tasks = mqtt_instance.resubscribe().await?;
 
+                let camera_sdcard = camera.clone();
+                let mqtt_sdcard = mqtt_instance.resubscribe().await?;
+
+                let camera_wifi = camera.clone();
+                let mqtt_wifi = mqtt_instance.resubscribe().await?;
+
+                let camera_status = camera.clone();
+                let mqtt_status = mqtt_instance.resubscribe().await?;
+
+                let camera_clients = camera.clone();
+                let mqtt_clients = mqtt_instance.resubscribe().await?;
+
+                let camera_failed = camera.clone();
+                let mut failed_watch = camera.failed().await?;
+                let mqtt_failed = mqtt_instance.resubscribe().await?;
+                let mut task_failed_watch = camera.task_failed().await?;
+                let mqtt_task_failed = mqtt_instance.resubscribe().await?;
+
                 tokio::select! {
                     _ = cancel.cancelled() => AnyResult::Ok(()),
                     // Handles incomming requests
@@ -354,15 +540,16 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             v = async {
                                 log::debug!("Listening to message on {}", mqtt_msg.get_name());
                                 while let Ok(msg) = mqtt_msg.recv().await {
-                                    let mqtt_msg = mqtt_msg.resubscribe().await?;
+                                    let mqtt_msg = mqtt_msg.resubscribe().await?.with_response(msg.response.clone());
                                     let camera_msg = camera_msg.clone();
                                     let tx = tx.clone();
                                     let cancel_msg = cancel_msg.clone();
+                                    let siren_msg = siren_msg.clone();
                                     set_msg.spawn(async move {
                                         tokio::select!{
                                             _ = cancel_msg.cancelled() => AnyResult::Ok(()),
                                             v = async {
-                                                let res = handle_mqtt_message(msg, &mqtt_msg, &camera_msg).await;
+                                                let res = handle_mqtt_message(msg, &mqtt_msg, &camera_msg, &siren_msg).await;
                                                 if res.is_err() {
                                                     tx.send(res).await?;
                                                 }
@@ -391,12 +578,73 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             mqtt_watch.send_message("status", "connected", true).await.with_context(|| {
                                 format!("{}: Failed to publish connected", camera_name)
                             })?;
+                            mqtt_watch.send_message("status/availability", "online", true).await.with_context(|| {
+                                format!("{}: Failed to publish online", camera_name)
+                            })?;
                             camera_watch.wait_for(|cam| cam.upgrade().is_none()).await.with_context(|| {
                                 format!("{}: Disconnect Watch Dropped", camera_name)
                             })?;
                             mqtt_watch.send_message("status", "disconnected", true).await.with_context(|| {
                                 format!("{}: Failed to publish disconnected", camera_name)
                             })?;
+                            mqtt_watch.send_message("status/availability", "offline", true).await.with_context(|| {
+                                format!("{}: Failed to publish offline", camera_name)
+                            })?;
+                        }
+                    } => {
+                        v
+                    },
+                    // Handle neolink giving up on the camera
+                    v = async {
+                        loop {
+                            let failure = *failed_watch.wait_for(|failed| failed.is_some()).await.with_context(|| {
+                                format!("{}: Failed Watch Dropped", camera_name)
+                            })?;
+                            let Some(failure) = failure else {
+                                continue;
+                            };
+                            let camera_config = camera_failed.config().await?.borrow().clone();
+                            let (status, event) = match failure {
+                                NeoCamFailure::GaveUp => ("failed", serde_json::json!({
+                                    "give_up": camera_config.retry.give_up,
+                                    "retry_in": camera_config.retry.give_up_retry,
+                                })),
+                                NeoCamFailure::LoginRejected => ("login_failed", serde_json::json!({
+                                    "username": camera_config.username,
+                                    "secondary": camera_config.secondary_password.is_some(),
+                                })),
+                            };
+                            mqtt_failed.send_message("status", status, true).await.with_context(|| {
+                                format!("{}: Failed to publish {status}", camera_name)
+                            })?;
+                            mqtt_failed.send_message(&format!("event/{status}"), &event.to_string(), false).await.with_context(|| {
+                                format!("{}: Failed to publish the {status} event", camera_name)
+                            })?;
+                            failed_watch.wait_for(|failed| *failed != Some(failure)).await.with_context(|| {
+                                format!("{}: Failed Watch Dropped", camera_name)
+                            })?;
+                        }
+                    } => {
+                        v
+                    },
+                    // Handle a task of the camera failing
+                    v = async {
+                        loop {
+                            task_failed_watch.changed().await.with_context(|| {
+                                format!("{}: Task Failed Watch Dropped", camera_name)
+                            })?;
+                            let Some(failure) = task_failed_watch.borrow_and_update().clone() else {
+                                continue;
+                            };
+                            let event = serde_json::json!({
+                                "task": failure.task,
+                                "error": failure.error,
+                                "failures": failure.failures,
+                                "retry_in": failure.retry_in,
+                            });
+                            mqtt_task_failed.send_message("event/task_failed", &event.to_string(), false).await.with_context(|| {
+                                format!("{}: Failed to publish the task_failed event", camera_name)
+                            })?;
                         }
                     } => {
                         v
@@ -454,16 +702,32 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         let mut md = camera_motion.motion().await?;
                         loop {
                             let v = async {
-                                md.wait_for(|state| matches!(state, MdState::Start(_))).await.with_context(|| {
+                                let details = match &*md.wait_for(|state| matches!(state, MdState::Start(..))).await.with_context(|| {
                                     format!("{}: MdStart Watch Dropped", camera_name)
-                                })?;
-                                mqtt_motion.send_message("status/motion", "on", true).await.with_context(|| {
+                                })? {
+                                    MdState::Start(_, details) => details.clone(),
+                                    _ => unreachable!(),
+                                };
+                                let scheduled = is_active(camera_motion.config().await?.borrow().schedule.as_ref(), Gate::Motion);
+                                if !scheduled {
+                                    log::debug!("{}: Not publishing motion outside of the schedule", camera_name);
+                                    md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
+                                        format!("{}: MdStop Watch Dropped", camera_name)
+                                    })?;
+                                    return AnyResult::Ok(());
+                                }
+                                publish_motion(&mqtt_motion, config.motion_legacy, true, &details).await.with_context(|| {
                                     format!("{}: Failed to publish motion start", camera_name)
                                 })?;
                                 md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
                                     format!("{}: MdStop Watch Dropped", camera_name)
                                 })?;
-                                mqtt_motion.send_message("status/motion", "off", true).await.with_context(|| {
+                                let details = MotionDetails {
+                                    detections: vec![],
+                                    at: SystemTime::now(),
+                                    ..details
+                                };
+                                publish_motion(&mqtt_motion, config.motion_legacy, false, &details).await.with_context(|| {
                                     format!("{}: Failed to publish motion stop", camera_name)
                                 })?;
                                 AnyResult::Ok(())
@@ -476,15 +740,60 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             }?;
                         }
                     }, if config.enable_motion => v,
+                    // Record a clip when motion starts
+                    v = async {
+                        #[cfg(feature = "gstreamer")]
+                        {
+                            let mut md = camera_clip.motion().await?;
+                            loop {
+                                md.wait_for(|state| matches!(state, MdState::Start(..))).await.with_context(|| {
+                                    format!("{}: MdStart Watch Dropped", camera_name)
+                                })?;
+                                if is_active(camera_clip.config().await?.borrow().schedule.as_ref(), Gate::Motion) {
+                                    let duration = Duration::from_secs(config.motion_clip_duration);
+                                    match motion_clip::record(&camera_clip, duration, &config.motion_clip_format).await {
+                                        Ok(clip) => publish_motion_clip(&mqtt_clip, &camera_name, &config, clip).await.with_context(|| {
+                                            format!("{}: Failed to publish motion clip", camera_name)
+                                        })?,
+                                        Err(e) => log::warn!("{}: Failed to record motion clip: {:?}", camera_name, e),
+                                    }
+                                }
+                                md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
+                                    format!("{}: MdStop Watch Dropped", camera_name)
+                                })?;
+                            }
+                        }
+                        #[cfg(not(feature = "gstreamer"))]
+                        unreachable!()
+                    }, if cfg!(feature = "gstreamer") && config.enable_motion_clip => v,
+                    // Handle the doorbell presses, these arrive as a visitor detection
+                    v = async {
+                        let mut md = camera_doorbell.motion().await?;
+                        mqtt_doorbell.send_message("status/doorbell", "off", false).await.with_context(|| {
+                            format!("{}: Failed to publish doorbell", camera_name)
+                        })?;
+                        loop {
+                            md.wait_for(is_doorbell_press).await.with_context(|| {
+                                format!("{}: Doorbell Watch Dropped", camera_name)
+                            })?;
+                            // Events are never retained so that a restart of HA does not ring again
+                            mqtt_doorbell.send_message("status/doorbell", "on", false).await.with_context(|| {
+                                format!("{}: Failed to publish doorbell press", camera_name)
+                            })?;
+                            md.wait_for(|state| !is_doorbell_press(state)).await.with_context(|| {
+                                format!("{}: Doorbell Watch Dropped", camera_name)
+                            })?;
+                            mqtt_doorbell.send_message("status/doorbell", "off", false).await.with_context(|| {
+                                format!("{}: Failed to publish doorbell release", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_doorbell => v,
                     // Handle the SNAP (image preview)
                     v = async {
-                        let mut wait = IntervalStream::new({
-                            let mut i = interval(Duration::from_millis(config.preview_update));
-                            i.set_missed_tick_behavior(MissedTickBehavior::Skip);
-                            i
-                        });
-                        let v = async {
-                            while wait.next().await.is_some() {
+                        let mut schedule = PublishSchedule::new(config.preview_mode, config.preview_update, &camera_snap).await?;
+                        let v: AnyResult<()> = async {
+                            loop {
+                                schedule.tick().await?;
                                 let image = camera_snap.run_passive_task(|cam| {
                                     Box::pin(async move {
                                         let image = cam.get_snapshot().await?;
@@ -502,14 +811,15 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                                     }
                                     n => n,
                                 }?;
-                                mqtt_snap
-                                        .send_message("status/preview", BASE64.encode(image).as_str(), true)
+                                if !schedule.should_publish(&image) {
+                                    continue;
+                                }
+                                publish_preview(&mqtt_snap, image, &config.preview_format)
                                         .await
                                         .with_context(|| {
                                             format!("{}: Failed to publish preview", camera_name)
                                         })?;
                             }
-                            AnyResult::Ok(())
                         }.await;
                         match v.map_err(|e| e.downcast::<neolink_core::Error>()) {
                             Err(Ok(neolink_core::Error::UnintelligibleReply{..})) => futures::future::pending().await,
@@ -521,14 +831,11 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                     }, if config.enable_preview => v,
                     // Handle the battery publish
                     v = async {
-                        let mut wait = IntervalStream::new({
-                            let mut i = interval(Duration::from_millis(config.battery_update));
-                            i.set_missed_tick_behavior(MissedTickBehavior::Skip);
-                            i
-                        });
+                        let mut schedule = PublishSchedule::new(config.battery_mode, config.battery_update, &camera_battery).await?;
 
-                        let v = async {
-                            while wait.next().await.is_some() {
+                        let v: AnyResult<()> = async {
+                            loop {
+                                schedule.tick().await?;
                                 let xml = camera_battery.run_passive_task(|cam| {
                                     Box::pin(async move {
                                         let xml = cam.battery_info().await?;
@@ -546,14 +853,17 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                                     }
                                     n => n,
                                 }?;
+                                let level = format!("{}", xml.battery_percent);
+                                if !schedule.should_publish(level.as_bytes()) {
+                                    continue;
+                                }
                                 mqtt_battery
-                                        .send_message("status/battery_level", format!("{}", xml.battery_percent).as_str(), true)
+                                        .send_message("status/battery_level", level.as_str(), true)
                                         .await
                                         .with_context(|| {
                                             format!("{}: Failed to publish battery", camera_name)
                                         })?;
                             }
-                            AnyResult::Ok(())
                         }.await;
                         match v.map_err(|e| e.downcast::<neolink_core::Error>()) {
                             Err(Ok(neolink_core::Error::UnintelligibleReply{..})) => futures::future::pending().await,
@@ -591,6 +901,61 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         #[cfg(not(feature = "pushnoti"))]
                         unreachable!()
                     }, if cfg!(feature = "pushnoti") => v,
+                    // Publish the motion of a push notification while the camera wakes
+                    v = async {
+                        #[cfg(feature = "pushnoti")]
+                        {
+                            let (wake, channel_id) = {
+                                let camera_config = camera_pn_motion.config().await?;
+                                let camera_config = camera_config.borrow();
+                                (Duration::from_secs(camera_config.push_wake), camera_config.channel_id)
+                            };
+                            let mut pn = camera_pn_motion.push_notifications().await?;
+                            let mut md = camera_pn_motion.motion().await?;
+                            let mut prev_noti = None;
+                            loop {
+                                let noti = pn.wait_for(|noti| noti != &prev_noti && noti.is_some()).await.with_context(|| {
+                                    format!("{}: PushNoti Watch Dropped", camera_name)
+                                })?.clone();
+                                prev_noti = noti.clone();
+                                if matches!(&*md.borrow(), MdState::Start(..)) {
+                                    // Awake and already reporting the motion
+                                    continue;
+                                }
+                                if !is_active(camera_pn_motion.config().await?.borrow().schedule.as_ref(), Gate::Motion) {
+                                    continue;
+                                }
+                                let details = MotionDetails {
+                                    channel_id,
+                                    detections: push_detections(&noti.unwrap().message),
+                                    at: SystemTime::now(),
+                                };
+                                publish_motion(&mqtt_pn_motion, config.motion_legacy, true, &details).await.with_context(|| {
+                                    format!("{}: Failed to publish push notification motion", camera_name)
+                                })?;
+                                tokio::select! {
+                                    v = async { md.wait_for(|state| matches!(state, MdState::Start(..))).await.map(|_| ()) } => {
+                                        // The camera has connected, its motion takes over
+                                        v.with_context(|| {
+                                            format!("{}: MdStart Watch Dropped", camera_name)
+                                        })?;
+                                    }
+                                    _ = sleep(wake) => {
+                                        let details = MotionDetails {
+                                            detections: vec![],
+                                            at: SystemTime::now(),
+                                            ..details
+                                        };
+                                        publish_motion(&mqtt_pn_motion, config.motion_legacy, false, &details).await.with_context(|| {
+                                            format!("{}: Failed to publish push notification motion", camera_name)
+                                        })?;
+                                    }
+                                }
+                            }
+                        }
+                        #[cfg(not(feature = "pushnoti"))]
+                        unreachable!()
+                    }, if cfg!(feature = "pushnoti") && config.enable_motion && config.push_motion => v,
                     // Handle the floodlight task activation
                     v = async {
                         let flt_status = camera_floodlight_tasks.run_passive_task(|cam| Box::pin(async move {
@@ -630,6 +995,158 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         }
                         AnyResult::Ok(())
                     }, if config.enable_floodlight => v,
+                    // Handle the SD card status
+                    v = async {
+                        let mut schedule = PublishSchedule::new(config.sdcard_mode, config.sdcard_update, &camera_sdcard).await?;
+                        let mut last_state = None;
+                        let v: AnyResult<()> = async {
+                            loop {
+                                schedule.tick().await?;
+                                let hdd_info = camera_sdcard.run_passive_task(|cam| {
+                                    Box::pin(async move {
+                                        let hdd_info = cam.get_hdd_info().await?;
+                                        AnyResult::Ok(hdd_info)
+                                    })
+                                }).await;
+                                let hdd_info = match hdd_info {
+                                    Err(e) => match e.downcast::<neolink_core::Error>() {
+                                        Ok(neolink_core::Error::CameraServiceUnavailable{..}) => {
+                                            log::debug!("SD card info not supported");
+                                            futures::future::pending().await
+                                        },
+                                        Ok(e) => Err(e.into()),
+                                        Err(e) => Err(e),
+                                    }
+                                    n => n,
+                                }?;
+                                let status = SdCardStatus::new(&hdd_info, config.sdcard_full);
+                                let json = serde_json::to_string(&status)?;
+                                if schedule.should_publish(json.as_bytes()) {
+                                    mqtt_sdcard
+                                        .send_message("status/sdcard", &json, true)
+                                        .await
+                                        .with_context(|| {
+                                            format!("{}: Failed to publish sd card", camera_name)
+                                        })?;
+                                }
+                                // Only raised once when the card goes bad, not on every update
+                                if status.is_alert() && last_state != Some(status.state) {
+                                    mqtt_sdcard
+                                        .send_message("event/sdcard", &json, false)
+                                        .await
+                                        .with_context(|| {
+                                            format!("{}: Failed to publish sd card event", camera_name)
+                                        })?;
+                                }
+                                last_state = Some(status.state);
+                            }
+                        }.await;
+                        match v.map_err(|e| e.downcast::<neolink_core::Error>()) {
+                            Err(Ok(neolink_core::Error::UnintelligibleReply{..})) => futures::future::pending().await,
+                            Ok(()) => AnyResult::Ok(()),
+                            Err(Ok(e)) => Err(e.into()),
+                            Err(Err(e)) => Err(e),
+                        }?;
+                        AnyResult::Ok(())
+                    }, if config.enable_sdcard => v,
+                    // Handle the wifi signal
+                    v = async {
+                        let mut schedule = PublishSchedule::new(config.wifi_mode, config.wifi_update, &camera_wifi).await?;
+                        let v: AnyResult<()> = async {
+                            loop {
+                                schedule.tick().await?;
+                                let wifi = camera_wifi.run_passive_task(|cam| {
+                                    Box::pin(async move {
+                                        let wifi = cam.get_wifi_signal().await?;
+                                        AnyResult::Ok(wifi)
+                                    })
+                                }).await;
+                                let wifi = match wifi {
+                                    Err(e) => match e.downcast::<neolink_core::Error>() {
+                                        Ok(neolink_core::Error::CameraServiceUnavailable{..}) => {
+                                            log::debug!("Wifi signal not supported");
+                                            futures::future::pending().await
+                                        },
+                                        Ok(e) => Err(e.into()),
+                                        Err(e) => Err(e),
+                                    }
+                                    n => n,
+                                }?;
+                                let rssi = format!("{}", wifi.signal);
+                                if !schedule.should_publish(rssi.as_bytes()) {
+                                    continue;
+                                }
+                                mqtt_wifi
+                                    .send_message("status/wifi/rssi", rssi.as_str(), true)
+                                    .await
+                                    .with_context(|| {
+                                        format!("{}: Failed to publish wifi rssi", camera_name)
+                                    })?;
+                                mqtt_wifi
+                                    .send_message("status/wifi/quality", &format!("{}", wifi_quality(wifi.signal)), true)
+                                    .await
+                                    .with_context(|| {
+                                        format!("{}: Failed to publish wifi quality", camera_name)
+                                    })?;
+                            }
+                        }.await;
+                        match v.map_err(|e| e.downcast::<neolink_core::Error>()) {
+                            Err(Ok(neolink_core::Error::UnintelligibleReply{..})) => futures::future::pending().await,
+                            Ok(()) => AnyResult::Ok(()),
+                            Err(Ok(e)) => Err(e.into()),
+                            Err(Err(e)) => Err(e),
+                        }?;
+                        AnyResult::Ok(())
+                    }, if config.enable_wifi => v,
+                    // Handle the combined status document
+                    v = async {
+                        let mut schedule = PublishSchedule::new(config.status_json_mode, config.status_json_update, &camera_status).await?;
+                        let mut camera_watch = camera_status.camera();
+                        let mut connected_at = camera_watch.borrow_and_update().upgrade().map(|_| Instant::now());
+                        loop {
+                            tokio::select! {
+                                v = schedule.tick() => v?,
+                                v = camera_watch.changed() => {
+                                    v.with_context(|| format!("{}: Camera Watch Dropped", camera_name))?;
+                                    connected_at = camera_watch.borrow_and_update().upgrade().map(|_| Instant::now());
+                                }
+                            }
+                            let status = match connected_at {
+                                Some(connected_at) => StatusJson::gather(&camera_status, connected_at, config.sdcard_full).await?,
+                                None => StatusJson::disconnected(),
+                            };
+                            let json = serde_json::to_string(&status)?;
+                            if !schedule.should_publish(json.as_bytes()) {
+                                continue;
+                            }
+                            mqtt_status.send_message("status/json", &json, true).await.with_context(|| {
+                                format!("{}: Failed to publish status json", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_status_json => v,
+                    // Handle the rtsp clients list
+                    v = async {
+                        let mut clients = camera_clients.rtsp_clients().await?;
+                        let mut published = None;
+                        loop {
+                            // The uptime and bytes change on each poll so only
+                            // publish when a client connects or disconnects
+                            let (json, connected) = {
+                                let clients = clients.borrow_and_update();
+                                let connected = clients.iter().map(|client| (client.ip.clone(), client.user.clone(), client.path.clone())).collect::<Vec<_>>();
+                                (serde_json::to_string(&*clients)?, connected)
+                            };
+                            if published.as_ref() != Some(&connected) {
+                                mqtt_clients.send_message("status/rtsp_clients", &json, true).await.with_context(|| {
+                                    format!("{}: Failed to publish rtsp clients", camera_name)
+                                })?;
+                                published = Some(connected);
+                            }
+                            clients.changed().await.with_context(|| {
+                                format!("{}: Rtsp Clients Watch Dropped", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_clients => v,
                 }?;
                 AnyResult::Ok(())
             } => v,
@@ -645,6 +1162,7 @@ async fn handle_mqtt_message(
     msg: MqttReply,
     mqtt: &MqttInstance,
     camera: &NeoInstance,
+    siren_timer: &SirenTimer,
 ) -> Result<()> {
     match msg.as_ref() {
         MqttReplyRef {
@@ -664,49 +1182,60 @@ async fn handle_mqtt_message(
         }
         MqttReplyRef {
             topic: "control/floodlight",
-            message: "on",
+            message,
         } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.set_floodlight_manual(true, 180).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the floodlight light: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
+            let lowercase_message = message.to_lowercase();
+            let mut words = lowercase_message.split_whitespace();
+            let reply = match (words.next(), words.next()) {
+                (Some(state @ ("on" | "off")), duration) => {
+                    let state = state == "on";
+                    match duration.map(|d| d.parse::<u16>()).transpose() {
+                        Ok(duration) => {
+                            let duration = duration.unwrap_or(180);
+                            let res = camera
+                                .run_task(|cam| {
+                                    Box::pin(async move {
+                                        cam.set_floodlight_manual(state, duration).await?;
+                                        AnyResult::Ok(())
+                                    })
+                                })
+                                .await;
+                            if res.is_err() {
+                                error!("Failed to set the floodlight light: {:?}", res.err());
+                                "FAIL"
+                            } else {
+                                "OK"
+                            }
+                        }
+                        Err(_) => {
+                            error!("Floodlight duration was not a valid number");
+                            "FAIL"
+                        }
+                    }
+                }
+                (Some("brightness"), Some(brightness)) => {
+                    set_floodlight_brightness(brightness, mqtt, camera).await?
+                }
+                _ => {
+                    error!("Unrecognized floodlight command \"{}\"", message);
+                    "FAIL"
+                }
             }
             .to_string();
             mqtt.send_message("control/floodlight", &reply, false)
                 .await
-                .with_context(|| "Failed to publish camera status light on")?;
+                .with_context(|| "Failed to publish floodlight")?;
         }
         MqttReplyRef {
-            topic: "control/floodlight",
-            message: "off",
+            topic: "control/floodlight/brightness",
+            message,
         } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.set_floodlight_manual(false, 180).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the floodlight light: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/floodlight", &reply, false)
+            let reply = set_floodlight_brightness(message, mqtt, camera)
+                .await?
+                .to_string();
+            mqtt.send_message("control/floodlight/brightness", &reply, false)
                 .await
-                .with_context(|| "Failed to publish camera status light off")?;
+                .with_context(|| "Failed to publish floodlight brightness")?;
         }
         MqttReplyRef {
             topic: "control/led",
@@ -730,6 +1259,9 @@ async fn handle_mqtt_message(
             mqtt.send_message("control/led", &reply, false)
                 .await
                 .with_context(|| "Failed to publish led on")?;
+            if reply == "OK" {
+                publish_light_state(mqtt, camera).await?;
+            }
         }
         MqttReplyRef {
             topic: "control/led",
@@ -753,6 +1285,9 @@ async fn handle_mqtt_message(
             mqtt.send_message("control/led", &reply, false)
                 .await
                 .with_context(|| "Failed to publish led off")?;
+            if reply == "OK" {
+                publish_light_state(mqtt, camera).await?;
+            }
         }
         MqttReplyRef {
             topic: "control/ir",
@@ -776,6 +1311,9 @@ async fn handle_mqtt_message(
             mqtt.send_message("control/ir", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ir on")?;
+            if reply == "OK" {
+                publish_light_state(mqtt, camera).await?;
+            }
         }
         MqttReplyRef {
             topic: "control/ir",
@@ -799,6 +1337,9 @@ async fn handle_mqtt_message(
             mqtt.send_message("control/ir", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ir off")?;
+            if reply == "OK" {
+                publish_light_state(mqtt, camera).await?;
+            }
         }
         MqttReplyRef {
             topic: "control/ir",
@@ -822,6 +1363,9 @@ async fn handle_mqtt_message(
             mqtt.send_message("control/ir", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ir auto")?;
+            if reply == "OK" {
+                publish_light_state(mqtt, camera).await?;
+            }
         }
         MqttReplyRef {
             topic: "control/reboot",
@@ -842,10 +1386,26 @@ async fn handle_mqtt_message(
                 "OK"
             }
             .to_string();
-            mqtt.send_message("control/ir", &reply, false)
+            mqtt.send_message("control/reboot", &reply, false)
                 .await
                 .with_context(|| "Failed to publish reboot on the camera")?;
         }
+        MqttReplyRef {
+            topic: "control/reconnect",
+            ..
+        } => {
+            let res = camera.reconnect().await;
+            let reply = if let Err(e) = res {
+                error!("Failed to reconnect to the camera: {:?}", e);
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string();
+            mqtt.send_message("control/reconnect", &reply, false)
+                .await
+                .with_context(|| "Failed to publish reconnect of the camera")?;
+        }
         MqttReplyRef {
             topic: "control/zoom",
             message,
@@ -863,6 +1423,7 @@ async fn handle_mqtt_message(
                     error!("Failed to send PTZ: {:?}", e);
                     format!("FAIL: {e:?}")
                 } else {
+                    publish_ptz_zoom(mqtt, camera).await;
                     "OK".to_string()
                 }
             } else {
@@ -885,63 +1446,45 @@ async fn handle_mqtt_message(
             let mut words = lowercase_message.split_whitespace();
             let reply = if let Some(direction_txt) = words.next() {
                 // Target amount to move
-                let speed = 32f32;
                 let amount = words.next().unwrap_or("32.0");
+                let speed = words.next().unwrap_or("32.0");
 
-                if let Ok(amount) = amount.parse::<f32>() {
-                    let seconds = amount / speed;
-                    // range checking on seconds so that you can't sleep for 3.4E+38 seconds
-                    let seconds = match seconds {
-                        x if (0.0..10.0).contains(&x) => Some(seconds),
-                        _ => {
-                            error!("seconds was not a valid number (out of range)");
-                            None
-                        }
-                    };
-
-                    let bc_direction = match direction_txt {
-                        "up" => Some(BcDirection::Up),
-                        "down" => Some(BcDirection::Down),
-                        "left" => Some(BcDirection::Left),
-                        "right" => Some(BcDirection::Right),
-                        n => {
-                            error!("Unrecognized PTZ direction \"{}\"", n);
-                            None
-                        }
-                    };
-
-                    if let (Some(seconds), Some(bc_direction)) = (seconds, bc_direction) {
-                        // On drop send the stop command again just to make sure it stops
-                        let _drop_command = camera.clone().drop_command(
-                            move |cam| {
-                                Box::pin(async move {
-                                    cam.send_ptz(BcDirection::Stop, speed).await?;
-                                    AnyResult::Ok(())
-                                })
-                            },
-                            Duration::from_millis(100),
-                        );
-                        if let Err(e) = camera
-                            .run_task(|cam| {
-                                Box::pin(async move {
-                                    cam.send_ptz(bc_direction, speed).await?;
-                                    sleep(Duration::from_secs_f32(seconds)).await;
-                                    cam.send_ptz(BcDirection::Stop, speed).await?;
-                                    AnyResult::Ok(())
+                if let (Ok(amount), Ok(speed)) = (amount.parse::<f32>(), speed.parse::<f32>()) {
+                    match direction_txt {
+                        "in" | "out" => {
+                            let zoom_in = direction_txt == "in";
+                            let res = camera
+                                .run_task(|cam| {
+                                    Box::pin(async move {
+                                        let zoom = cam.get_zoom().await?.zoom;
+                                        // An amount of 32 is a tenth of the zoom range
+                                        let step = ((zoom.max_pos - zoom.min_pos) as f32 * amount
+                                            / 320.0)
+                                            as u32;
+                                        let target = if zoom_in {
+                                            zoom.cur_pos.saturating_add(step)
+                                        } else {
+                                            zoom.cur_pos.saturating_sub(step)
+                                        };
+                                        cam.zoom_to(target).await?;
+                                        AnyResult::Ok(())
+                                    })
                                 })
-                            })
-                            .await
-                        {
-                            error!("Failed to send PTZ: {:?}", e);
-                            "FAIL"
-                        } else {
-                            "OK"
+                                .await;
+                            if let Err(e) = res {
+                                error!("Failed to send PTZ zoom: {:?}", e);
+                                "FAIL"
+                            } else {
+                                publish_ptz_zoom(mqtt, camera).await;
+                                "OK"
+                            }
+                        }
+                        direction_txt => {
+                            ptz_move(mqtt, camera, direction_txt, speed, amount / speed).await?
                         }
-                    } else {
-                        "FAIL"
                     }
                 } else {
-                    error!("No PTZ speed as a valid number");
+                    error!("No PTZ amount or speed as a valid number");
                     "FAIL"
                 }
             } else {
@@ -954,6 +1497,50 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish ptz on the camera")?;
         }
+        MqttReplyRef {
+            topic: "control/ptz/move",
+            message,
+        } => {
+            let lowercase_message = message.to_lowercase();
+            let mut words = lowercase_message.split_whitespace();
+            let direction_txt = words.next().unwrap_or("");
+            let speed = words.next().unwrap_or("32.0").parse::<f32>();
+            let seconds = words.next().unwrap_or("1.0").parse::<f32>();
+            let reply = if let (Ok(speed), Ok(seconds)) = (speed, seconds) {
+                ptz_move(mqtt, camera, direction_txt, speed, seconds).await?
+            } else {
+                error!("PTZ speed and duration must be numbers");
+                "FAIL"
+            }
+            .to_string();
+
+            mqtt.send_message("control/ptz/move", &reply, false)
+                .await
+                .with_context(|| "Failed to publish ptz move")?;
+        }
+        MqttReplyRef {
+            topic: "control/ptz/stop",
+            ..
+        } => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.send_ptz(BcDirection::Stop, 32.0).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            let reply = if res.is_err() {
+                error!("Failed to stop ptz: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string();
+            mqtt.send_message("control/ptz/stop", &reply, false)
+                .await
+                .with_context(|| "Failed to publish ptz stop")?;
+        }
         MqttReplyRef {
             topic: "control/ptz/preset",
             message,
@@ -971,6 +1558,9 @@ async fn handle_mqtt_message(
                     error!("Failed to move to ptz preset: {:?}", res.err());
                     "FAIL"
                 } else {
+                    mqtt.send_message("status/ptz/preset/current", &id.to_string(), true)
+                        .await
+                        .with_context(|| "Failed to publish current ptz preset")?;
                     "OK"
                 }
             } else {
@@ -978,12 +1568,12 @@ async fn handle_mqtt_message(
                 "FAIL"
             }
             .to_string();
-            mqtt.send_message("control/ir", &reply, false)
+            mqtt.send_message("control/ptz/preset", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ptz move")?;
         }
         MqttReplyRef {
-            topic: "control/ptz/assign",
+            topic: topic @ ("control/ptz/assign" | "control/ptz/save"),
             message,
         } => {
             let mut words = message.split_whitespace();
@@ -1005,6 +1595,9 @@ async fn handle_mqtt_message(
                     error!("Failed to assign ptz preset: {:?}", res.err());
                     "FAIL"
                 } else {
+                    mqtt.send_message("status/ptz/preset/current", &id.to_string(), true)
+                        .await
+                        .with_context(|| "Failed to publish current ptz preset")?;
                     "OK"
                 }
             } else if let (Some(Err(_)), _) = (id.map(|id| id.parse::<u8>()), name) {
@@ -1017,9 +1610,9 @@ async fn handle_mqtt_message(
                 "FAIL"
             }
             .to_string();
-            mqtt.send_message("control/ir", &reply, false)
+            mqtt.send_message(topic, &reply, false)
                 .await
-                .with_context(|| "Failed to publish ptz move")?;
+                .with_context(|| "Failed to publish ptz assign")?;
         }
         MqttReplyRef {
             topic: "control/pir",
@@ -1142,16 +1735,71 @@ async fn handle_mqtt_message(
         }
         MqttReplyRef {
             topic: "control/siren",
-            message: "on",
+            message,
         } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.siren().await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
+            let lowercase_message = message.to_lowercase();
+            let mut words = lowercase_message.split_whitespace();
+            let command = match (words.next(), words.next()) {
+                (Some("on"), None) => Ok(None),
+                (Some("off"), None) => Ok(Some(None)),
+                (Some("on"), Some(duration)) | (Some(duration), None) => duration
+                    .parse::<u64>()
+                    .map(|d| Some(Some(Duration::from_secs(d))))
+                    .map_err(|_| anyhow!("Siren duration was not a valid number")),
+                _ => Err(anyhow!("Unrecognized siren command \"{}\"", message)),
+            };
+            // A new command replaces the timer of the last one
+            let timer = CancellationToken::new();
+            if command.is_ok() {
+                let last = std::mem::replace(&mut *siren_timer.lock().unwrap(), timer.clone());
+                last.cancel();
+            }
+            let res = match command {
+                // Sound the siren once
+                Ok(None) => {
+                    camera
+                        .run_task(|cam| {
+                            Box::pin(async move {
+                                cam.siren().await?;
+                                AnyResult::Ok(())
+                            })
+                        })
+                        .await
+                }
+                // Sound the siren until it is turned off
+                Ok(Some(duration)) => {
+                    let res = camera
+                        .run_task(move |cam| {
+                            Box::pin(async move {
+                                cam.siren_manual(duration.is_some()).await?;
+                                AnyResult::Ok(())
+                            })
+                        })
+                        .await;
+                    if let (Ok(()), Some(duration)) = (&res, duration) {
+                        let camera = camera.clone();
+                        tokio::task::spawn(async move {
+                            tokio::select! {
+                                _ = sleep(duration) => {},
+                                _ = timer.cancelled() => return,
+                            }
+                            let res = camera
+                                .run_task(|cam| {
+                                    Box::pin(async move {
+                                        cam.siren_manual(false).await?;
+                                        AnyResult::Ok(())
+                                    })
+                                })
+                                .await;
+                            if let Err(e) = res {
+                                error!("Failed to stop siren: {:?}", e);
+                            }
+                        });
+                    }
+                    res
+                }
+                Err(e) => Err(e),
+            };
             let reply = if let Err(e) = res {
                 error!("Failed to trigger siren: {:?}", e);
                 format!("FAIL: {e:?}")
@@ -1163,6 +1811,46 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish siren")?;
         }
+        #[cfg(feature = "gstreamer")]
+        MqttReplyRef {
+            topic: "control/quick_reply",
+            message,
+        } => {
+            let config = camera.config().await?.borrow().mqtt.clone();
+            match config.quick_replies.get(message.trim()).cloned() {
+                Some(path) => {
+                    // The reply is sent once the file has played, which can take
+                    // a while so it does not hold up this message
+                    let camera = camera.clone();
+                    let mqtt = mqtt.resubscribe().await?;
+                    tokio::task::spawn(async move {
+                        let reply = match crate::talk::play_file(&camera, &path, 1.0).await {
+                            Ok(()) => "OK".to_string(),
+                            Err(e) => {
+                                error!("Failed to play quick reply: {:?}", e);
+                                format!("FAIL: {e}")
+                            }
+                        };
+                        if let Err(e) = mqtt
+                            .send_message("control/quick_reply", &reply, false)
+                            .await
+                        {
+                            error!("Failed to publish quick reply: {:?}", e);
+                        }
+                    });
+                }
+                None => {
+                    error!("Unknown quick reply \"{}\"", message.trim());
+                    mqtt.send_message(
+                        "control/quick_reply",
+                        &format!("FAIL: Unknown quick reply \"{}\"", message.trim()),
+                        false,
+                    )
+                    .await
+                    .with_context(|| "Failed to publish quick reply")?;
+                }
+            }
+        }
         MqttReplyRef {
             topic: "query/battery",
             ..
@@ -1210,6 +1898,90 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish battery query")?;
         }
+        MqttReplyRef {
+            topic: "query/ptz/zoom",
+            ..
+        } => {
+            publish_ptz_zoom(mqtt, camera).await;
+        }
+        MqttReplyRef {
+            topic: "query/floodlight",
+            ..
+        } => {
+            if let Err(e) = publish_floodlight_brightness(mqtt, camera).await {
+                error!("Failed to get floodlight brightness: {:?}", e);
+                mqtt.send_message("query/floodlight", "FAIL", false)
+                    .await
+                    .with_context(|| "Failed to publish floodlight query")?;
+            }
+        }
+        MqttReplyRef { topic, message } if topic.starts_with("control/config/") => {
+            let block = topic.trim_start_matches("control/config/");
+            let reply = match config_blocks::set_block(camera, block, message).await {
+                Ok(()) => {
+                    publish_config_block(mqtt, camera, block).await?;
+                    "OK".to_string()
+                }
+                Err(e) => {
+                    error!("Failed to set the {block} config: {:?}", e);
+                    format!("FAIL: {e}")
+                }
+            };
+            mqtt.send_message(topic, &reply, false)
+                .await
+                .with_context(|| "Failed to publish config reply")?;
+        }
+        MqttReplyRef { topic, message } if topic.starts_with("control/notify/") => {
+            let name = topic.trim_start_matches("control/notify/");
+            let res = match (notification_kind(name), message.to_lowercase().as_str()) {
+                (Some(kind), state @ ("on" | "off")) => {
+                    let on = state == "on";
+                    camera
+                        .run_task(move |cam| {
+                            Box::pin(async move {
+                                cam.notification_task_set(kind, on).await?;
+                                AnyResult::Ok(())
+                            })
+                        })
+                        .await
+                }
+                (None, _) => Err(anyhow!("Unknown notification {name}")),
+                (Some(_), _) => Err(anyhow!("Notification can only be turned on or off")),
+            };
+            let reply = match res {
+                Ok(()) => publish_notification_state(mqtt, camera, name).await?,
+                Err(e) => {
+                    error!("Failed to set the {name} notification: {:?}", e);
+                    format!("FAIL: {e}")
+                }
+            };
+            mqtt.send_message(topic, &reply, false)
+                .await
+                .with_context(|| "Failed to publish notification reply")?;
+        }
+        MqttReplyRef { topic, .. } if topic.starts_with("query/notify/") => {
+            let name = topic.trim_start_matches("query/notify/");
+            let reply = publish_notification_state(mqtt, camera, name).await?;
+            mqtt.send_message(topic, &reply, false)
+                .await
+                .with_context(|| "Failed to publish notification query")?;
+        }
+        MqttReplyRef { topic, .. } if topic.starts_with("query/config/") => {
+            let block = topic.trim_start_matches("query/config/");
+            let reply = publish_config_block(mqtt, camera, block).await?;
+            mqtt.send_message(topic, &reply, false)
+                .await
+                .with_context(|| "Failed to publish config query")?;
+        }
+        MqttReplyRef {
+            topic: topic @ ("query/led" | "query/ir"),
+            ..
+        } => {
+            let reply = publish_light_state(mqtt, camera).await?;
+            mqtt.send_message(topic, reply, false)
+                .await
+                .with_context(|| "Failed to publish light query")?;
+        }
         MqttReplyRef {
             topic: "query/pir", ..
         } => {
@@ -1321,8 +2093,8 @@ async fn handle_mqtt_message(
                     "FAIL"
                 }
                 Ok(bytes) => {
-                    if let Err(e) = mqtt
-                        .send_message("status/preview", BASE64.encode(bytes).as_str(), true)
+                    let format = camera.config().await?.borrow().mqtt.preview_format.clone();
+                    if let Err(e) = publish_preview(mqtt, bytes, &format)
                         .await
                         .with_context(|| "Failed to publish preview")
                     {
@@ -1342,3 +2114,349 @@ async fn handle_mqtt_message(
     }
     Ok(())
 }
+
+/// Set the floodlight brightness from a percentage in an mqtt message
+async fn set_floodlight_brightness(
+    brightness: &str,
+    mqtt: &MqttInstance,
+    camera: &NeoInstance,
+) -> Result<&'static str> {
+    let Ok(brightness) = brightness.trim().parse::<u32>() else {
+        error!("Floodlight brightness was not a valid number");
+        return Ok("FAIL");
+    };
+    let res = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.set_floodlight_brightness(brightness).await?;
+                AnyResult::Ok(())
+            })
+        })
+        .await;
+    if res.is_err() {
+        error!("Failed to set the floodlight brightness: {:?}", res.err());
+        return Ok("FAIL");
+    }
+    if let Err(e) = publish_floodlight_brightness(mqtt, camera).await {
+        error!("Failed to get floodlight brightness: {:?}", e);
+    }
+    Ok("OK")
+}
+
+/// Query the camera for the floodlight brightness and publish it
+async fn publish_floodlight_brightness(mqtt: &MqttInstance, camera: &NeoInstance) -> Result<()> {
+    let brightness = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let brightness = cam.get_floodlight_brightness().await?;
+                AnyResult::Ok(brightness)
+            })
+        })
+        .await?;
+    mqtt.send_message(
+        "status/floodlight/brightness",
+        &brightness.to_string(),
+        true,
+    )
+    .await
+    .with_context(|| "Failed to publish floodlight brightness")?;
+    Ok(())
+}
+
+/// Move the camera in a direction at a speed for a number of seconds
+async fn ptz_move(
+    mqtt: &MqttInstance,
+    camera: &NeoInstance,
+    direction_txt: &str,
+    speed: f32,
+    seconds: f32,
+) -> Result<&'static str> {
+    // range checking on seconds so that you can't sleep for 3.4E+38 seconds
+    if !(0.0..10.0).contains(&seconds) {
+        error!("seconds was not a valid number (out of range)");
+        return Ok("FAIL");
+    }
+
+    let bc_direction = match direction_txt {
+        "up" => BcDirection::Up,
+        "down" => BcDirection::Down,
+        "left" => BcDirection::Left,
+        "right" => BcDirection::Right,
+        n => {
+            error!("Unrecognized PTZ direction \"{}\"", n);
+            return Ok("FAIL");
+        }
+    };
+
+    // On drop send the stop command again just to make sure it stops
+    let _drop_command = camera.clone().drop_command(
+        move |cam| {
+            Box::pin(async move {
+                cam.send_ptz(BcDirection::Stop, speed).await?;
+                AnyResult::Ok(())
+            })
+        },
+        Duration::from_millis(100),
+    );
+    if let Err(e) = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.send_ptz(bc_direction, speed).await?;
+                sleep(Duration::from_secs_f32(seconds)).await;
+                cam.send_ptz(BcDirection::Stop, speed).await?;
+                AnyResult::Ok(())
+            })
+        })
+        .await
+    {
+        error!("Failed to send PTZ: {:?}", e);
+        Ok("FAIL")
+    } else {
+        // No longer at a preset
+        mqtt.send_message("status/ptz/preset/current", "none", true)
+            .await
+            .with_context(|| "Failed to publish current ptz preset")?;
+        Ok("OK")
+    }
+}
+
+/// Query the camera for the zoom level and publish it
+async fn publish_ptz_zoom(mqtt: &MqttInstance, camera: &NeoInstance) {
+    let res = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let zoom = cam.get_zoom().await?;
+                AnyResult::Ok(zoom.zoom.cur_pos)
+            })
+        })
+        .await;
+    match res {
+        Ok(zoom) => {
+            let zoom = format!("{}", zoom as f32 / 1000.0);
+            if let Err(e) = mqtt.send_message("status/ptz/zoom", &zoom, true).await {
+                error!("Failed to publish ptz zoom: {:?}", e);
+            }
+        }
+        Err(e) => {
+            error!("Failed to get ptz zoom: {:?}", e);
+        }
+    }
+}
+
+/// Publish a jpeg to `status/preview` in the configured format
+async fn publish_preview(mqtt: &MqttInstance, image: Vec<u8>, format: &str) -> Result<()> {
+    match format {
+        "jpeg" => mqtt.send_bytes("status/preview", image, true).await,
+        _ => {
+            mqtt.send_message("status/preview", BASE64.encode(image).as_str(), true)
+                .await
+        }
+    }
+}
+
+async fn publish_motion(
+    mqtt: &MqttInstance,
+    legacy: bool,
+    on: bool,
+    details: &MotionDetails,
+) -> AnyResult<()> {
+    let state = if on { "on" } else { "off" };
+    let timestamp = details
+        .at
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let payload = serde_json::json!({
+        "state": state,
+        "timestamp": timestamp,
+        "detections": details.detections,
+        "channel": details.channel_id,
+    });
+    mqtt.send_message("status/motion/json", &payload.to_string(), true)
+        .await?;
+    if legacy {
+        mqtt.send_message("status/motion", state, true).await?;
+    }
+    Ok(())
+}
+
+/// What a push notification was sent for, the alarm type is only known from
+/// the text of the notification so motion is assumed when none match
+#[cfg(feature = "pushnoti")]
+fn push_detections(message: &str) -> Vec<String> {
+    let message = message.to_lowercase();
+    let mut detections: Vec<String> = vec![];
+    for (needle, detection) in [
+        ("people", "people"),
+        ("person", "people"),
+        ("vehicle", "vehicle"),
+        ("animal", "animal"),
+        ("dog_cat", "animal"),
+    ] {
+        if message.contains(needle) && !detections.iter().any(|d| d == detection) {
+            detections.push(detection.to_string());
+        }
+    }
+    if detections.is_empty() {
+        detections.push("md".to_string());
+    }
+    detections
+}
+
+/// Link quality in percent from the RSSI, -100dBm or worse is 0% and -50dBm or better is 100%
+fn wifi_quality(rssi: i32) -> u8 {
+    (2 * (rssi + 100)).clamp(0, 100) as u8
+}
+
+fn is_doorbell_press(state: &MdState) -> bool {
+    matches!(state, MdState::Start(_, details) if details.detections.iter().any(|d| d == "visitor"))
+}
+
+/// Publish the clip to `status/motion/clip`, or write it to the `motion_clip_dir` and
+/// publish its path to `status/motion/clip/file`
+#[cfg(feature = "gstreamer")]
+async fn publish_motion_clip(
+    mqtt: &MqttInstance,
+    camera_name: &str,
+    config: &crate::config::MqttConfig,
+    clip: Vec<u8>,
+) -> AnyResult<()> {
+    if let Some(dir) = config.motion_clip_dir.as_ref() {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = dir.join(format!(
+            "{camera_name}_{timestamp}.{}",
+            config.motion_clip_format
+        ));
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(&path, clip)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        mqtt.send_message(
+            "status/motion/clip/file",
+            &path.display().to_string(),
+            false,
+        )
+        .await
+    } else {
+        mqtt.send_bytes("status/motion/clip", clip, false).await
+    }
+}
+
+/// Publish a config block of the camera as json to `status/config/<block>`
+async fn publish_config_block(
+    mqtt: &MqttInstance,
+    camera: &NeoInstance,
+    block: &str,
+) -> AnyResult<String> {
+    match config_blocks::get_block(camera, block).await {
+        Ok(json) => {
+            mqtt.send_message(&format!("status/config/{block}"), &json, false)
+                .await
+                .with_context(|| "Failed to publish config block")?;
+            Ok("OK".to_string())
+        }
+        Err(e) => {
+            error!("Failed to get the {block} config: {:?}", e);
+            Ok(format!("FAIL: {e}"))
+        }
+    }
+}
+
+/// A `[[cameras]]` table sent as toml or json
+fn camera_table(message: &str) -> Result<toml::Table> {
+    if message.trim_start().starts_with('{') {
+        Ok(serde_json::from_str(message)?)
+    } else {
+        Ok(toml::from_str(message)?)
+    }
+}
+
+fn notification_kind(name: &str) -> Option<NotificationKind> {
+    match name {
+        "email" => Some(NotificationKind::Email),
+        "push" => Some(NotificationKind::Push),
+        "ftp" => Some(NotificationKind::Ftp),
+        _ => None,
+    }
+}
+
+/// Publish whether the email, push or ftp notification is on to `status/notify/<name>`
+async fn publish_notification_state(
+    mqtt: &MqttInstance,
+    camera: &NeoInstance,
+    name: &str,
+) -> AnyResult<String> {
+    let Some(kind) = notification_kind(name) else {
+        return Ok(format!("FAIL: Unknown notification {name}"));
+    };
+    let res = camera
+        .run_task(move |cam| {
+            Box::pin(async move {
+                let task = cam.get_notification_task(kind).await?;
+                AnyResult::Ok(task.enable)
+            })
+        })
+        .await;
+    match res {
+        Ok(enable) => {
+            let state = if enable == 1 { "on" } else { "off" };
+            mqtt.send_message(&format!("status/notify/{name}"), state, true)
+                .await
+                .with_context(|| "Failed to publish notification state")?;
+            Ok("OK".to_string())
+        }
+        Err(e) => {
+            error!("Failed to get the {name} notification: {:?}", e);
+            Ok(format!("FAIL: {e}"))
+        }
+    }
+}
+
+/// Publish the ir and status led state to `status/ir` and `status/led`
+async fn publish_light_state(mqtt: &MqttInstance, camera: &NeoInstance) -> AnyResult<&'static str> {
+    let res = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let state = cam.get_ledstate().await?;
+                AnyResult::Ok(state)
+            })
+        })
+        .await;
+    match res {
+        Err(e) => {
+            error!("Failed to get the led state: {:?}", e);
+            Ok("FAIL")
+        }
+        Ok(state) => {
+            let ir = match state.state.as_str() {
+                "open" => "on",
+                "close" => "off",
+                _ => "auto",
+            };
+            let led = match state.light_state.as_str() {
+                "open" => "on",
+                "auto" => "auto",
+                _ => "off",
+            };
+            mqtt.send_message("status/ir", ir, true)
+                .await
+                .with_context(|| "Failed to publish ir state")?;
+            mqtt.send_message("status/led", led, true)
+                .await
+                .with_context(|| "Failed to publish led state")?;
+            Ok("OK")
+        }
+    }
+}
+
+/// The `[mqtt]` config of a local broker with the `extra` keys
+#[cfg(test)]
+fn test_config(extra: &str) -> crate::config::MqttServerConfig {
+    toml::from_str(&format!(
+        "broker_addr = \"localhost\"\nport = 1883\n{extra}"
+    ))
+    .unwrap()
+}
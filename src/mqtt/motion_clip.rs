@@ -0,0 +1,65 @@
+//! Records a short clip of the stream when motion starts
+//!
+//! The clip starts at the last keyframe that the stream has buffered so
+//! that it shows what triggered the motion when the stream was already running
+use anyhow::Context;
+use futures::stream::StreamExt;
+use neolink_core::bc_protocol::StreamKind;
+use tokio::time::{timeout, Duration, Instant};
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::{common::NeoInstance, AnyResult};
+
+/// Record `duration` of the sub stream and encode it as `mp4` or `gif`
+pub(crate) async fn record(
+    camera: &NeoInstance,
+    duration: Duration,
+    clip_format: &str,
+) -> AnyResult<Vec<u8>> {
+    // Holding the instance keeps the stream active until the clip is recorded
+    let stream = camera.stream(StreamKind::Sub).await?;
+
+    let mut stream_config = stream.config.clone();
+    let vid_format = timeout(
+        Duration::from_secs(15),
+        stream_config.wait_for(|config| config.vid_ready()),
+    )
+    .await
+    .with_context(|| "Timed out waiting for the stream")??
+    .vid_format;
+
+    let mut live = BroadcastStream::new(stream.vid.resubscribe());
+    let mut frames = {
+        let history = stream.vid_history.borrow();
+        let start = history.iter().rposition(|frame| frame.keyframe);
+        start
+            .map(|start| history.iter().skip(start).cloned().collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    let last_ts = frames.last().map(|frame| frame.ts);
+
+    let end = Instant::now() + duration;
+    while let Ok(Some(frame)) = tokio::time::timeout_at(end, live.next()).await {
+        // Lagged frames are skipped, the clip just drops a few frames
+        let Ok(frame) = frame else {
+            continue;
+        };
+        if last_ts.is_some_and(|last_ts| frame.ts <= last_ts) {
+            continue;
+        }
+        if frames.is_empty() && !frame.keyframe {
+            continue;
+        }
+        frames.push(frame);
+    }
+    drop(stream);
+
+    if frames.is_empty() {
+        return Err(anyhow::anyhow!("No keyframe was received for the clip"));
+    }
+    let clip_format = clip_format.to_string();
+    tokio::task::spawn_blocking(move || {
+        crate::image::frames_to_clip(vid_format, &frames, &clip_format)
+    })
+    .await?
+}
@@ -1,14 +1,15 @@
+use super::{
+    client::{connect, is_retryable, v5_rejected, MqttResponse, Notification, PublishMeta},
+    mirror::{self, MirrorMessage},
+    topics::TopicFormat,
+};
 use crate::{
-    config::{Config, MqttServerConfig},
+    config::{Config, MqttProtocol, MqttServerConfig},
     AnyResult,
 };
 use anyhow::{anyhow, Context, Result};
 use futures::future::FutureExt;
-use log::*;
-use rumqttc::{
-    AsyncClient, ConnectReturnCode, Event, Incoming, LastWill, MqttOptions, QoS, TlsConfiguration,
-    Transport,
-};
+use rumqttc::QoS;
 use std::sync::Arc;
 use tokio::task::JoinSet;
 use tokio::{
@@ -37,6 +38,18 @@ impl Mqtt {
         let cancel = CancellationToken::new();
         let mut set = JoinSet::<AnyResult<()>>::new();
 
+        // Copies of the published messages for the mirror brokers
+        let (mirror_tx, _) = broadcast::<MirrorMessage>(100);
+        let mirror_cancel = cancel.clone();
+        let mirror_config = config.clone();
+        let mirror_thread_tx = mirror_tx.clone();
+        set.spawn(async move {
+            tokio::select! {
+                _ = mirror_cancel.cancelled() => AnyResult::Ok(()),
+                v = mirror::run(mirror_config, mirror_thread_tx) => v,
+            }
+        });
+
         // Thread that handles the mqttc side
         // including restarting it if the config changes
         let thread_cancel = cancel.clone();
@@ -45,26 +58,41 @@ impl Mqtt {
         let thread_outgoing_tx = outgoing_tx.clone();
         set.spawn(async move {
             let mut mqtt_config = thread_config.borrow().mqtt.clone();
+            let mut use_v5 = starts_with_v5(mqtt_config.as_ref());
             let r = loop {
                 break tokio::select! {
                     _ = thread_cancel.cancelled() => AnyResult::Ok(()),
                     v = thread_config.wait_for(|config| config.mqtt != mqtt_config).map(|res| res.map(|r| r.clone())) =>
                     {
                         mqtt_config = v?.mqtt.clone();
+                        use_v5 = starts_with_v5(mqtt_config.as_ref());
                         continue;
                     }
-                    v = async {
+                    (v, connected) = async {
                         let mut backend = MqttBackend {
                             incomming_tx: thread_incoming_tx.clone(),
                             outgoing_rx: &mut outgoing_rx,
                             outgoing_tx: thread_outgoing_tx.clone(),
                             config: mqtt_config.as_ref().unwrap(),
                             cancel: CancellationToken::new(),
+                            use_v5,
+                            connected: false,
+                            mirror_tx: mirror_tx.clone(),
                         };
-                        backend.run().await
+                        let v = backend.run().await;
+                        (v, backend.connected)
                     }, if mqtt_config.is_some() => {
                         if let Err(e) = &v {
                             log::error!("MQTT Client Connection Failed: {:?}", e);
+                            let auto = mqtt_config.as_ref().map(|c| c.protocol == MqttProtocol::Auto).unwrap_or(false);
+                            // Only a broker that refuses MQTT 5 gets 3.1.1, any other
+                            // failure tries MQTT 5 again
+                            if use_v5 && auto && !connected && v5_rejected(e) {
+                                log::info!("MQTT broker refused MQTT 5, falling back to MQTT 3.1.1");
+                                use_v5 = false;
+                            } else {
+                                use_v5 = starts_with_v5(mqtt_config.as_ref());
+                            }
                             sleep(Duration::from_secs(2)).await;
                             continue;
                         }
@@ -84,6 +112,14 @@ impl Mqtt {
         }
     }
 
+    /// Publish that neolink is offline and disconnect from the broker
+    pub(crate) async fn hang_up(&self) -> AnyResult<()> {
+        let (tx, rx) = oneshot();
+        self.outgoing_tx.send(MqttRequest::HangUp(tx)).await?;
+        rx.await?;
+        Ok(())
+    }
+
     pub async fn subscribe<T: Into<String>>(&self, name: T) -> AnyResult<MqttInstance> {
         let (tx, rx) = oneshot();
         self.outgoing_tx
@@ -120,71 +156,42 @@ struct MqttBackend<'a> {
     outgoing_tx: MpscSender<MqttRequest>,
     config: &'a MqttServerConfig,
     cancel: CancellationToken,
+    use_v5: bool,
+    /// Set once the broker accepts the connection
+    connected: bool,
+    mirror_tx: BroadcastSender<MirrorMessage>,
 }
 
 impl<'a> MqttBackend<'a> {
     async fn run(&mut self) -> AnyResult<()> {
         log::trace!("Run MQTT Server");
-        let mut mqttoptions = MqttOptions::new(
-            format!("Neolink{}", Uuid::new_v4()),
-            &self.config.broker_addr,
-            self.config.port,
-        );
-        let max_size = 100 * (1024 * 1024);
-        mqttoptions.set_max_packet_size(max_size, max_size);
-
-        // Use TLS if ca path is set
-        if let Some(ca_path) = &self.config.ca {
-            if let Ok(ca) = std::fs::read(ca_path) {
-                // Use client_auth if they have cert and key
-                let client_auth = if let Some((cert_path, key_path)) = &self.config.client_auth {
-                    if let (Ok(cert_buf), Ok(key_buf)) =
-                        (std::fs::read(cert_path), std::fs::read(key_path))
-                    {
-                        Some((cert_buf, key_buf))
-                    } else {
-                        error!("Failed to set client tls");
-                        None
-                    }
-                } else {
-                    None
-                };
-
-                let transport = Transport::Tls(TlsConfiguration::Simple {
-                    ca,
-                    alpn: None,
-                    client_auth,
-                });
-                mqttoptions.set_transport(transport);
-            } else {
-                error!("Failed to set CA");
-            }
-        };
-
-        if let Some((username, password)) = &self.config.credentials {
-            mqttoptions.set_credentials(username, password);
-        }
-
-        mqttoptions.set_keep_alive(Duration::from_secs(5));
+        let topics = TopicFormat::new(self.config);
+        let status_topic = topics.topic("", "status");
+        let status = publish_options(self.config, &status_topic, true, None);
 
         // On unclean disconnect send this
-        mqttoptions.set_last_will(LastWill::new(
-            "neolink/status".to_string(),
-            "offline",
-            QoS::AtLeastOnce,
-            true,
-        ));
-
-        let (client, mut connection) = AsyncClient::new(mqttoptions, 100);
+        let (client, mut connection) = connect(
+            self.config,
+            format!("Neolink{}", Uuid::new_v4()),
+            (
+                status_topic.clone(),
+                "offline".to_string(),
+                status.qos,
+                status.retain,
+            ),
+            self.use_v5,
+        )
+        .await?;
 
         let client = Arc::new(client);
         let send_client = client.clone();
         send_client
             .publish(
-                "neolink/status".to_string(),
-                QoS::AtLeastOnce,
-                true,
-                "connected".to_string(),
+                status_topic.clone(),
+                status.qos,
+                status.retain,
+                "connected",
+                status.meta.clone(),
             )
             .await?;
         log::debug!("MQTT Published Startup");
@@ -202,6 +209,8 @@ impl<'a> MqttBackend<'a> {
                     let cancel = self.cancel.clone();
                     let thread_cancel = loop_cancel.clone();
                     let server_config = self.config.clone();
+                    let use_v5 = self.use_v5;
+                    let mirror_tx = self.mirror_tx.clone();
                     tokio::task::spawn(async move {
                         tokio::select!{
                             _ = cancel.cancelled() => AnyResult::Ok(()),
@@ -209,47 +218,81 @@ impl<'a> MqttBackend<'a> {
                             v = async {
                                 match msg {
                                     MqttRequest::Send(msg, tx) =>  {
+                                        let options = publish_options(&server_config, &msg.topic, false, msg.response.as_ref());
                                         let v = send_client.publish(
                                             msg.topic.clone(),
-                                            QoS::AtLeastOnce,
-                                            false,
+                                            options.qos,
+                                            options.retain,
                                             (*msg.message).clone(),
+                                            options.meta,
                                         ).await;
                                         match &v {
                                             Ok(()) => {
+                                                if msg.response.is_none() {
+                                                    mirror_message(&mirror_tx, &msg.topic, msg.message.as_bytes(), options.retain);
+                                                }
                                                 let _ = tx.send(Ok(()));
                                             },
-                                            Err(rumqttc::ClientError::Request(_)) | Err(rumqttc::ClientError::TryRequest(_)) => {
-                                                // Requeue it
+                                            Err(e) if is_retryable(e) => {
+                                                // Requeue it until we reconnect
                                                 outgoing_tx.send(MqttRequest::Send(msg, tx)).await?;
                                             }
+                                            Err(e) => {
+                                                let _ = tx.send(Err(anyhow!("Failed to publish to {}: {:?}", msg.topic, e)));
+                                            }
                                         };
                                         v?;
                                     }
                                     MqttRequest::SendRetained(msg, tx) =>  {
+                                        let options = publish_options(&server_config, &msg.topic, true, msg.response.as_ref());
                                         let v = send_client.publish(
                                             msg.topic.clone(),
-                                            QoS::AtLeastOnce,
-                                            true,
+                                            options.qos,
+                                            options.retain,
                                             (*msg.message).clone(),
+                                            options.meta,
                                         ).await;
                                         match &v {
                                             Ok(()) => {
+                                                if msg.response.is_none() {
+                                                    mirror_message(&mirror_tx, &msg.topic, msg.message.as_bytes(), options.retain);
+                                                }
                                                 let _ = tx.send(Ok(()));
                                             },
-                                            Err(rumqttc::ClientError::Request(_)) | Err(rumqttc::ClientError::TryRequest(_)) => {
-                                                // Requeue it
-                                                outgoing_tx.send(MqttRequest::Send(msg, tx)).await?;
+                                            Err(e) if is_retryable(e) => {
+                                                // Requeue it until we reconnect
+                                                outgoing_tx.send(MqttRequest::SendRetained(msg, tx)).await?;
+                                            }
+                                            Err(e) => {
+                                                let _ = tx.send(Err(anyhow!("Failed to publish to {}: {:?}", msg.topic, e)));
                                             }
                                         };
                                         v?;
                                     }
+                                    MqttRequest::SendBytes{topic, payload, retain, reply} => {
+                                        let options = publish_options(&server_config, &topic, retain, None);
+                                        mirror_message(&mirror_tx, &topic, &payload, options.retain);
+                                        let v = send_client.publish(
+                                            topic,
+                                            options.qos,
+                                            options.retain,
+                                            payload,
+                                            options.meta,
+                                        ).await;
+                                        if v.is_ok() {
+                                            let _ = reply.send(Ok(()));
+                                        }
+                                        v?;
+                                    }
                                     MqttRequest::HangUp(reply) => {
+                                        let status_topic = TopicFormat::new(&server_config).topic("", "status");
+                                        let status = publish_options(&server_config, &status_topic, true, None);
                                         send_client.publish(
-                                            "neolink/status".to_string(),
-                                            QoS::AtLeastOnce,
-                                            true,
-                                            "disconnected".to_string(),
+                                            status_topic,
+                                            status.qos,
+                                            status.retain,
+                                            "offline",
+                                            status.meta,
                                         ).await?;
                                         let _ = reply.send(());
                                         return Err(anyhow!("Disconneting"));
@@ -257,8 +300,10 @@ impl<'a> MqttBackend<'a> {
                                     MqttRequest::Subscribe(name, reply) => {
                                         let instance = MqttInstance {
                                             name,
+                                            topics: TopicFormat::new(&server_config),
                                             incomming_rx: BroadcastStream::new(incomming_tx.subscribe()),
                                             outgoing_tx: outgoing_tx.clone(),
+                                            response: None,
                                         };
                                         let _ = reply.send(Ok(instance));
                                     },
@@ -267,6 +312,7 @@ impl<'a> MqttBackend<'a> {
                                             &server_config,
                                             topic,
                                             message,
+                                            use_v5,
                                         ).await;
                                         let _ = reply.send(last_will);
                                     }
@@ -280,44 +326,52 @@ impl<'a> MqttBackend<'a> {
                 },
                 v = connection.poll() =>  {
                     let  notification = v.with_context(|| "MQTT connection dropped")?;
+                    if let Notification::ConnAck(true) = &notification {
+                        self.connected = true;
+                    }
                     // Handle message on another thread so that we can keep polling
                     let client = client.clone();
                     let incomming_tx = self.incomming_tx.clone();
                     let cancel = self.cancel.clone();
                     let thread_cancel = loop_cancel.clone();
+                    let command_qos = qos(self.config.command_topics.qos, QoS::AtMostOnce);
+                    let status = status.clone();
+                    let status_topic = status_topic.clone();
+                    let topics = topics.clone();
                     tokio::task::spawn(async move {
                         tokio::select!{
                             _ = cancel.cancelled() => AnyResult::Ok(()),
                             _ = thread_cancel.cancelled() => AnyResult::Ok(()),
                             v = async {
                                 match notification {
-                                    Event::Incoming(Incoming::ConnAck(connected)) => {
-                                        if ConnectReturnCode::Success == connected.code {
-                                            // Publish connected now that we are online
-                                            client
-                                            .publish(
-                                                "neolink/status".to_string(),
-                                                QoS::AtLeastOnce,
-                                                true,
-                                                "connected",
-                                            )
-                                            .await?;
-                                            // We succesfully logged in. Now ask for the cameras subscription.
-                                            client
-                                            .subscribe("neolink/#".to_string(), QoS::AtMostOnce)
-                                            .await?;
-                                        }
+                                    Notification::ConnAck(true) => {
+                                        // Publish connected now that we are online
+                                        client
+                                        .publish(
+                                            status_topic,
+                                            status.qos,
+                                            status.retain,
+                                            "connected",
+                                            status.meta,
+                                        )
+                                        .await?;
+                                        // We succesfully logged in. Now ask for the cameras subscription.
+                                        client
+                                        .subscribe(&topics.subscription(), command_qos)
+                                        .await?;
                                     }
-                                    Event::Incoming(Incoming::Publish(published_message)) => {
-                                        if let Some(sub_topic) = published_message
-                                            .topic
-                                            .strip_prefix("neolink/")
-                                        {
+                                    Notification::Publish{topic, payload, response} => {
+                                        if let Some((camera, sub_topic)) = topics.parse(&topic) {
                                             let _ = incomming_tx
                                                 .send(MqttReply {
-                                                    topic: sub_topic.to_string(),
-                                                    message: Arc::new(String::from_utf8_lossy(published_message.payload.as_ref())
+                                                    topic: if camera.is_empty() {
+                                                        sub_topic
+                                                    } else {
+                                                        format!("{camera}/{sub_topic}")
+                                                    },
+                                                    message: Arc::new(String::from_utf8_lossy(payload.as_ref())
                                                         .into_owned()),
+                                                    response,
                                                 });
                                         }
                                     }
@@ -349,6 +403,9 @@ pub(crate) struct MqttInstance {
     outgoing_tx: MpscSender<MqttRequest>,
     incomming_rx: BroadcastStream<MqttReply>,
     name: String,
+    topics: TopicFormat,
+    /// Everything sent through this instance is also sent here
+    response: Option<MqttResponse>,
 }
 
 impl MqttInstance {
@@ -356,6 +413,22 @@ impl MqttInstance {
         &self.name
     }
 
+    /// The full topic on the broker of one of our sub topics
+    pub(crate) fn topic(&self, sub_topic: &str) -> String {
+        self.topics.topic(&self.name, sub_topic)
+    }
+
+    /// The full topic on the broker of one of neolink's own topics
+    pub(crate) fn global_topic(&self, sub_topic: &str) -> String {
+        self.topics.topic("", sub_topic)
+    }
+
+    /// Also send the messages to the response topic of an MQTT 5 request
+    pub(crate) fn with_response(mut self, response: Option<MqttResponse>) -> Self {
+        self.response = response;
+        self
+    }
+
     pub async fn subscribe<T: Into<String>>(&self, name: T) -> AnyResult<Self> {
         let (tx, rx) = oneshot();
         self.outgoing_tx
@@ -364,12 +437,13 @@ impl MqttInstance {
         rx.await?
     }
 
+    /// Another instance with the same name, it keeps the response topic
     pub async fn resubscribe(&self) -> AnyResult<Self> {
         let (tx, rx) = oneshot();
         self.outgoing_tx
             .send(MqttRequest::Subscribe(self.name.clone(), tx))
             .await?;
-        rx.await?
+        Ok(rx.await??.with_response(self.response.clone()))
     }
 
     pub async fn send_message_with_root_topic(
@@ -379,7 +453,7 @@ impl MqttInstance {
         message: &str,
         retain: bool,
     ) -> AnyResult<()> {
-        let topics = [
+        let topic = [
             root_topic.to_string(),
             self.name.clone(),
             sub_topic.to_string(),
@@ -387,14 +461,30 @@ impl MqttInstance {
         .iter()
         .filter(|s| !s.is_empty())
         .cloned()
-        .collect::<Vec<_>>();
+        .collect::<Vec<_>>()
+        .join("/");
+        self.send_to_topic(topic, message, retain).await
+    }
+
+    pub async fn send_message(
+        &self,
+        sub_topic: &str,
+        message: &str,
+        retain: bool,
+    ) -> AnyResult<()> {
+        self.send_to_topic(self.topic(sub_topic), message, retain)
+            .await
+    }
+
+    async fn send_to_topic(&self, topic: String, message: &str, retain: bool) -> AnyResult<()> {
         if retain {
             let (tx, rx) = oneshot();
             self.outgoing_tx
                 .send(MqttRequest::SendRetained(
                     MqttReply {
-                        topic: topics.join("/"),
+                        topic,
                         message: Arc::new(message.to_string()),
+                        response: None,
                     },
                     tx,
                 ))
@@ -405,8 +495,23 @@ impl MqttInstance {
             self.outgoing_tx
                 .send(MqttRequest::Send(
                     MqttReply {
-                        topic: topics.join("/"),
+                        topic,
+                        message: Arc::new(message.to_string()),
+                        response: None,
+                    },
+                    tx,
+                ))
+                .await?;
+            rx.await??;
+        }
+        if let Some(response) = self.response.as_ref() {
+            let (tx, rx) = oneshot();
+            self.outgoing_tx
+                .send(MqttRequest::Send(
+                    MqttReply {
+                        topic: response.topic.clone(),
                         message: Arc::new(message.to_string()),
+                        response: Some(response.clone()),
                     },
                     tx,
                 ))
@@ -416,14 +521,36 @@ impl MqttInstance {
         Ok(())
     }
 
-    pub async fn send_message(
+    /// Send a binary payload such as a jpeg
+    pub async fn send_bytes(
         &self,
         sub_topic: &str,
-        message: &str,
+        payload: Vec<u8>,
         retain: bool,
     ) -> AnyResult<()> {
-        self.send_message_with_root_topic("neolink", sub_topic, message, retain)
+        let topic = self.topic(sub_topic);
+        if let Some(response) = self.response.as_ref() {
+            let (tx, rx) = oneshot();
+            self.outgoing_tx
+                .send(MqttRequest::SendBytes {
+                    topic: response.topic.clone(),
+                    payload: payload.clone(),
+                    retain: false,
+                    reply: tx,
+                })
+                .await?;
+            rx.await??;
+        }
+        let (tx, rx) = oneshot();
+        self.outgoing_tx
+            .send(MqttRequest::SendBytes {
+                topic,
+                payload,
+                retain,
+                reply: tx,
+            })
             .await?;
+        rx.await??;
         Ok(())
     }
 
@@ -445,9 +572,12 @@ impl MqttInstance {
                 let sub_topic = topics.next();
                 // log::debug!("topics: {:?}", msg.topic);
                 // log::debug!("sub_topic: {sub_topic:?}");
-                if sub_topic
-                    .map(|subtopic| *subtopic == self.name)
-                    .unwrap_or(false)
+                // Control messages sent to `all` go to every camera
+                let to_all = sub_topic == Some("all") && msg.topic.starts_with("all/control/");
+                if to_all
+                    || sub_topic
+                        .map(|subtopic| *subtopic == self.name)
+                        .unwrap_or(false)
                 {
                     msg.topic = topics.collect::<Vec<_>>().join("/");
                     // log::debug!("new topics: {:?}", msg.topic);
@@ -458,11 +588,7 @@ impl MqttInstance {
     }
 
     pub(crate) async fn last_will(&self, topic: &str, message: &str) -> AnyResult<LastWillMqtt> {
-        let topic = if self.name.is_empty() {
-            format!("neolink/{}", topic)
-        } else {
-            format!("neolink/{}/{}", self.name, topic)
-        };
+        let topic = self.topic(topic);
 
         let (tx, rx) = oneshot();
         self.outgoing_tx
@@ -480,6 +606,8 @@ impl MqttInstance {
 pub(crate) struct MqttReply {
     pub(crate) topic: String,
     pub(crate) message: Arc<String>, // Messages can be long so avoid costly clones with an arc
+    /// Set on MQTT 5 requests that want a reply
+    pub(crate) response: Option<MqttResponse>,
 }
 
 impl MqttReply {
@@ -496,9 +624,109 @@ pub(crate) struct MqttReplyRef<'a> {
     pub(crate) message: &'a str,
 }
 
+fn mirror_message(tx: &BroadcastSender<MirrorMessage>, topic: &str, payload: &[u8], retain: bool) {
+    if tx.receiver_count() > 0 {
+        let _ = tx.send(MirrorMessage {
+            topic: topic.to_string(),
+            payload: Arc::new(payload.to_vec()),
+            retain,
+        });
+    }
+}
+
+fn starts_with_v5(config: Option<&MqttServerConfig>) -> bool {
+    config
+        .map(|c| c.protocol != MqttProtocol::V311)
+        .unwrap_or(false)
+}
+
+fn qos(level: Option<u8>, default: QoS) -> QoS {
+    match level {
+        Some(0) => QoS::AtMostOnce,
+        Some(1) => QoS::AtLeastOnce,
+        Some(2) => QoS::ExactlyOnce,
+        _ => default,
+    }
+}
+
+#[derive(Clone)]
+struct PublishOptions {
+    qos: QoS,
+    retain: bool,
+    meta: PublishMeta,
+}
+
+/// Events replay on restart if they are retained so they never are, only the
+/// state topics follow the retain flag of the sender
+///
+/// Topics outside of the `topic_template` such as discovery are always sent as requested
+fn publish_options(
+    config: &MqttServerConfig,
+    topic: &str,
+    retain: bool,
+    response: Option<&MqttResponse>,
+) -> PublishOptions {
+    let mut meta = PublishMeta {
+        correlation_data: response.and_then(|r| r.correlation_data.clone()),
+        user_properties: vec![(
+            "neolink_version".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        )],
+        ..Default::default()
+    };
+    let Some((camera, sub_topic)) = TopicFormat::new(config).parse(topic) else {
+        return PublishOptions {
+            qos: QoS::AtLeastOnce,
+            retain,
+            meta,
+        };
+    };
+    if !camera.is_empty() {
+        meta.user_properties
+            .push(("camera".to_string(), camera.to_string()));
+    }
+    if response.is_some() {
+        // A reply to a request is never retained
+        PublishOptions {
+            qos: qos(config.status_topics.qos, QoS::AtLeastOnce),
+            retain: false,
+            meta,
+        }
+    } else if is_event_topic(&sub_topic) {
+        meta.expiry = config.event_topics.expiry;
+        PublishOptions {
+            qos: qos(config.event_topics.qos, QoS::AtLeastOnce),
+            retain: false,
+            meta,
+        }
+    } else {
+        meta.expiry = config.status_topics.expiry;
+        PublishOptions {
+            qos: qos(config.status_topics.qos, QoS::AtLeastOnce),
+            retain: config.status_topics.retain.unwrap_or(retain),
+            meta,
+        }
+    }
+}
+
+/// The motion, its AI detections and the doorbell visitor are events, as are
+/// the `event/` topics
+fn is_event_topic(sub_topic: &str) -> bool {
+    sub_topic == "status/motion"
+        || sub_topic.starts_with("status/motion/")
+        || sub_topic == "status/doorbell"
+        || sub_topic.starts_with("event/")
+}
+
 enum MqttRequest {
     Send(MqttReply, OneshotSender<Result<()>>),
     SendRetained(MqttReply, OneshotSender<Result<()>>),
+    SendBytes {
+        topic: String,
+        payload: Vec<u8>,
+        retain: bool,
+        reply: OneshotSender<Result<()>>,
+    },
     HangUp(OneshotSender<()>),
     Subscribe(String, OneshotSender<Result<MqttInstance>>),
     LastWill {
@@ -517,54 +745,18 @@ impl LastWillMqtt {
         config: &MqttServerConfig,
         topic: String,
         message: String,
+        use_v5: bool,
     ) -> AnyResult<Self> {
         log::trace!("Run MQTT Last Will");
-        let mut mqttoptions = MqttOptions::new(
-            format!("NeolinkLastWill_{}_{}", topic, Uuid::new_v4()),
-            &config.broker_addr,
-            config.port,
-        );
-        let max_size = 100 * (1024 * 1024);
-        mqttoptions.set_max_packet_size(max_size, max_size);
-
-        // Use TLS if ca path is set
-        if let Some(ca_path) = &config.ca {
-            if let Ok(ca) = std::fs::read(ca_path) {
-                // Use client_auth if they have cert and key
-                let client_auth = if let Some((cert_path, key_path)) = &config.client_auth {
-                    if let (Ok(cert_buf), Ok(key_buf)) =
-                        (std::fs::read(cert_path), std::fs::read(key_path))
-                    {
-                        Some((cert_buf, key_buf))
-                    } else {
-                        error!("Failed to set client tls");
-                        None
-                    }
-                } else {
-                    None
-                };
-
-                let transport = Transport::Tls(TlsConfiguration::Simple {
-                    ca,
-                    alpn: None,
-                    client_auth,
-                });
-                mqttoptions.set_transport(transport);
-            } else {
-                error!("Failed to set CA");
-            }
-        };
-
-        if let Some((username, password)) = &config.credentials {
-            mqttoptions.set_credentials(username, password);
-        }
-
-        mqttoptions.set_keep_alive(Duration::from_secs(5));
-
         // On unclean disconnect send this
-        mqttoptions.set_last_will(LastWill::new(topic, message, QoS::AtLeastOnce, true));
-
-        let (client, mut connection) = AsyncClient::new(mqttoptions, 100);
+        let options = publish_options(config, &topic, true, None);
+        let (client, mut connection) = connect(
+            config,
+            format!("NeolinkLastWill_{}_{}", topic, Uuid::new_v4()),
+            (topic, message, options.qos, options.retain),
+            use_v5,
+        )
+        .await?;
         let client = Arc::new(client);
         let cancel = CancellationToken::new();
         let thread_cancel = cancel.clone();
@@ -596,3 +788,56 @@ impl Drop for LastWillMqtt {
         self.cancel.cancel();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mqtt::test_config;
+
+    #[test]
+    fn test_event_topics() {
+        for topic in [
+            "status/motion",
+            "status/motion/json",
+            "status/motion/clip",
+            "status/doorbell",
+            "event/sdcard",
+            "event/task_failed",
+        ] {
+            assert!(is_event_topic(topic), "{} is an event", topic);
+        }
+        for topic in [
+            "status",
+            "status/availability",
+            "status/battery_level",
+            "status/motionless",
+            "status/doorbell_chime",
+            "status/json",
+            "control/siren",
+            "events",
+        ] {
+            assert!(!is_event_topic(topic), "{} is not an event", topic);
+        }
+    }
+
+    #[test]
+    fn test_events_are_never_retained() {
+        let config =
+            test_config("[event_topics]\nretain = true\n[status_topics]\nqos = 2\nretain = true\n");
+        let options = publish_options(&config, "neolink/cam/status/motion", true, None);
+        assert!(!options.retain);
+        let options = publish_options(&config, "neolink/cam/status/doorbell", true, None);
+        assert!(!options.retain);
+        let options = publish_options(&config, "neolink/cam/status/battery_level", false, None);
+        assert!(options.retain);
+        assert_eq!(options.qos, QoS::ExactlyOnce);
+    }
+
+    #[test]
+    fn test_state_topics_follow_the_sender() {
+        let config = test_config("");
+        assert!(publish_options(&config, "neolink/cam/status/battery_level", true, None).retain);
+        assert!(!publish_options(&config, "neolink/cam/status/battery_level", false, None).retain);
+        assert!(!publish_options(&config, "neolink/cam/status/motion", true, None).retain);
+    }
+}
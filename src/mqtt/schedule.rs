@@ -0,0 +1,65 @@
+//! Decides when the periodic status topics are published
+//!
+//! - `interval`: Publish every update period
+//! - `change`: Check every update period but only publish when the value changed
+//! - `motion`: Publish when motion starts and every update period while it continues
+use anyhow::Context;
+use tokio::{
+    sync::watch::Receiver as WatchReceiver,
+    time::{interval, Duration, Interval, MissedTickBehavior},
+};
+
+use crate::{
+    common::{MdState, NeoInstance},
+    config::PublishMode,
+    AnyResult,
+};
+
+pub(crate) struct PublishSchedule {
+    mode: PublishMode,
+    interval: Interval,
+    motion: Option<WatchReceiver<MdState>>,
+    last: Option<Vec<u8>>,
+}
+
+impl PublishSchedule {
+    pub(crate) async fn new(
+        mode: PublishMode,
+        update_ms: u64,
+        camera: &NeoInstance,
+    ) -> AnyResult<Self> {
+        let mut interval = interval(Duration::from_millis(update_ms));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let motion = match mode {
+            PublishMode::OnMotion => Some(camera.motion().await?),
+            PublishMode::Interval | PublishMode::OnChange => None,
+        };
+        Ok(Self {
+            mode,
+            interval,
+            motion,
+            last: None,
+        })
+    }
+
+    /// Wait until the value should next be fetched
+    pub(crate) async fn tick(&mut self) -> AnyResult<()> {
+        if let Some(motion) = self.motion.as_mut() {
+            motion
+                .wait_for(|state| matches!(state, MdState::Start(..)))
+                .await
+                .with_context(|| "Motion Watch Dropped")?;
+        }
+        self.interval.tick().await;
+        Ok(())
+    }
+
+    /// Check if the value should be published and remember it if so
+    pub(crate) fn should_publish(&mut self, value: &[u8]) -> bool {
+        if self.mode == PublishMode::OnChange && self.last.as_deref() == Some(value) {
+            return false;
+        }
+        self.last = Some(value.to_vec());
+        true
+    }
+}
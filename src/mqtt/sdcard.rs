@@ -0,0 +1,51 @@
+//! Summarises the camera's storage info into the `status/sdcard` json
+use neolink_core::bc::xml::HddInfoList;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct SdCardStatus {
+    /// `ok`, `full`, `error` or `missing`
+    pub(crate) state: &'static str,
+    /// Total size in MB
+    pub(crate) capacity: u64,
+    /// Free space in MB
+    pub(crate) free: u64,
+    pub(crate) used_percent: u8,
+}
+
+impl SdCardStatus {
+    /// Build the status of the first card, `full_percent` is the used space at which
+    /// it counts as full
+    pub(crate) fn new(hdd_info: &HddInfoList, full_percent: u8) -> Self {
+        let Some(card) = hdd_info.hdd_info.first() else {
+            return Self {
+                state: "missing",
+                capacity: 0,
+                free: 0,
+                used_percent: 0,
+            };
+        };
+        let free = card.remain_size.unwrap_or(0).min(card.capacity);
+        let used_percent = ((card.capacity - free) * 100)
+            .checked_div(card.capacity)
+            .unwrap_or(0) as u8;
+        let state = if card.mount == 0 || card.format == Some(0) || card.capacity == 0 {
+            "error"
+        } else if used_percent >= full_percent {
+            "full"
+        } else {
+            "ok"
+        };
+        Self {
+            state,
+            capacity: card.capacity,
+            free,
+            used_percent,
+        }
+    }
+
+    /// Whether the state should be raised on the event topic
+    pub(crate) fn is_alert(&self) -> bool {
+        matches!(self.state, "error" | "full")
+    }
+}
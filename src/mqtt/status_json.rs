@@ -0,0 +1,121 @@
+//! Collects the state of a camera into the single `status/json` document
+use neolink_core::bc::xml::EncodeTable;
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+use super::sdcard::SdCardStatus;
+use crate::{common::NeoInstance, AnyResult};
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct StatusJson {
+    connected: bool,
+    /// Seconds since neolink connected to the camera
+    uptime: Option<u64>,
+    model: Option<String>,
+    firmware: Option<String>,
+    battery: Option<u32>,
+    wifi_rssi: Option<i32>,
+    sdcard: Option<SdCardStatus>,
+    streams: Vec<StreamStatus>,
+    timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct StreamStatus {
+    name: String,
+    width: u32,
+    height: u32,
+    /// In kbps
+    bitrate: u32,
+    fps: u32,
+}
+
+impl StreamStatus {
+    fn new(encode: &EncodeTable) -> Self {
+        let table = |table: &str| {
+            table
+                .split(',')
+                .filter_map(|c| c.parse().ok())
+                .collect::<Vec<u32>>()
+        };
+        // The defaults are sometimes an index into the table
+        let bitrate = table(&encode.bitrate_table)
+            .get(encode.default_bitrate as usize)
+            .copied()
+            .unwrap_or(encode.default_bitrate);
+        let fps = table(&encode.framerate_table)
+            .get(encode.default_framerate as usize)
+            .copied()
+            .unwrap_or(encode.default_framerate);
+        Self {
+            name: encode.name.clone(),
+            width: encode.resolution.width,
+            height: encode.resolution.height,
+            bitrate,
+            fps,
+        }
+    }
+}
+
+impl StatusJson {
+    /// The document of a camera that is not connected, nothing is asked of the camera
+    pub(crate) fn disconnected() -> Self {
+        Self {
+            connected: false,
+            uptime: None,
+            model: None,
+            firmware: None,
+            battery: None,
+            wifi_rssi: None,
+            sdcard: None,
+            streams: vec![],
+            timestamp: now(),
+        }
+    }
+
+    /// Ask the camera for everything in the document, the parts the camera
+    /// does not support are left as null
+    pub(crate) async fn gather(
+        camera: &NeoInstance,
+        connected_at: Instant,
+        sdcard_full: u8,
+    ) -> AnyResult<Self> {
+        let (version, battery, wifi, hdd_info, stream_info) = camera
+            .run_passive_task(|cam| {
+                Box::pin(async move {
+                    Ok((
+                        cam.version().await.ok(),
+                        cam.battery_info().await.ok(),
+                        cam.get_wifi_signal().await.ok(),
+                        cam.get_hdd_info().await.ok(),
+                        cam.get_stream_info().await.ok(),
+                    ))
+                })
+            })
+            .await?;
+        Ok(Self {
+            connected: true,
+            uptime: Some(connected_at.elapsed().as_secs()),
+            model: version.as_ref().and_then(|v| v.model.clone()),
+            firmware: version.map(|v| v.firmwareVersion),
+            battery: battery.map(|b| b.battery_percent),
+            wifi_rssi: wifi.map(|w| w.signal),
+            sdcard: hdd_info.map(|hdd_info| SdCardStatus::new(&hdd_info, sdcard_full)),
+            streams: stream_info
+                .iter()
+                .flat_map(|info| info.stream_infos.iter())
+                .flat_map(|info| info.encode_tables.iter())
+                .map(StreamStatus::new)
+                .collect(),
+            timestamp: now(),
+        })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,209 @@
+//! Builds the TLS transport for the connection to the broker
+//!
+//! TLS is used when any of `ca`, `tls` or `insecure` are set
+use anyhow::{anyhow, Context};
+use rumqttc::{
+    tokio_rustls::rustls::{
+        self,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+        ClientConfig, DigitallySignedStruct, SignatureScheme,
+    },
+    TlsConfiguration, Transport,
+};
+use std::sync::Arc;
+
+use crate::{config::MqttServerConfig, AnyResult};
+
+/// Get the transport to use for the broker, `None` for plain tcp
+pub(crate) fn transport(config: &MqttServerConfig) -> AnyResult<Option<Transport>> {
+    if config.ca.is_none() && !config.tls && !config.insecure {
+        return Ok(None);
+    }
+
+    let client_auth = config
+        .client_auth
+        .as_ref()
+        .map(|(cert_path, key_path)| {
+            let cert = std::fs::read(cert_path)
+                .with_context(|| format!("Failed to read MQTT client cert {cert_path:?}"))?;
+            let key = std::fs::read(key_path)
+                .with_context(|| format!("Failed to read MQTT client key {key_path:?}"))?;
+            AnyResult::Ok((cert, key))
+        })
+        .transpose()?;
+    let alpn = config
+        .alpn
+        .as_ref()
+        .map(|protocols| protocols.iter().map(|p| p.as_bytes().to_vec()).collect());
+
+    let tls_config = if config.insecure {
+        log::error!(
+            "INSECURE: The certificate of the MQTT broker {} is not verified because of \
+            `insecure = true`, anyone on the network can pretend to be the broker",
+            config.broker_addr
+        );
+        TlsConfiguration::Rustls(Arc::new(insecure_config(client_auth, alpn)?))
+    } else if let Some(ca_path) = &config.ca {
+        let ca = std::fs::read(ca_path)
+            .with_context(|| format!("Failed to read MQTT CA {ca_path:?}"))?;
+        TlsConfiguration::Simple {
+            ca,
+            alpn,
+            client_auth,
+        }
+    } else if client_auth.is_some() {
+        return Err(anyhow!(
+            "MQTT client_auth requires the ca to be set unless insecure is used"
+        ));
+    } else {
+        // Use the system roots
+        match TlsConfiguration::default() {
+            TlsConfiguration::Rustls(rustls_config) => {
+                let mut rustls_config = rustls_config.as_ref().clone();
+                if let Some(alpn) = alpn {
+                    rustls_config.alpn_protocols = alpn;
+                }
+                TlsConfiguration::Rustls(Arc::new(rustls_config))
+            }
+            tls_config => tls_config,
+        }
+    };
+    Ok(Some(Transport::Tls(tls_config)))
+}
+
+fn insecure_config(
+    client_auth: Option<(Vec<u8>, Vec<u8>)>,
+    alpn: Option<Vec<Vec<u8>>>,
+) -> AnyResult<ClientConfig> {
+    let builder = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoVerifier));
+    let mut tls_config = match client_auth {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(pem_certs(&cert)?, pem_key(&key)?)
+            .with_context(|| "Invalid MQTT client certificate")?,
+        None => builder.with_no_client_auth(),
+    };
+    if let Some(alpn) = alpn {
+        tls_config.alpn_protocols = alpn;
+    }
+    Ok(tls_config)
+}
+
+/// The certificates of a pem file
+fn pem_certs(data: &[u8]) -> AnyResult<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut &data[..])
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "Invalid MQTT client certificate")?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificate found in the MQTT client cert"));
+    }
+    Ok(certs)
+}
+
+/// The first pkcs8, pkcs1 or sec1 private key of a pem file
+fn pem_key(data: &[u8]) -> AnyResult<PrivateKeyDer<'static>> {
+    rustls_pemfile::private_key(&mut &data[..])
+        .with_context(|| "Invalid MQTT client key")?
+        .ok_or_else(|| anyhow!("No private key found in the MQTT client key"))
+}
+
+/// Accepts any certificate from the broker
+#[derive(Debug)]
+struct NoVerifier;
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mqtt::test_config;
+
+    const CERT: &[u8] = include_bytes!("samples/client_cert.pem");
+    const KEY_PKCS8: &[u8] = include_bytes!("samples/client_key_pkcs8.pem");
+    const KEY_SEC1: &[u8] = include_bytes!("samples/client_key_sec1.pem");
+
+    #[test]
+    fn test_pem_certs() {
+        assert_eq!(pem_certs(CERT).unwrap().len(), 1);
+        // Both in one file
+        let both = [CERT, KEY_PKCS8].concat();
+        assert_eq!(pem_certs(&both).unwrap().len(), 1);
+        assert!(pem_certs(KEY_PKCS8).is_err());
+        assert!(pem_certs(b"").is_err());
+    }
+
+    #[test]
+    fn test_pem_key() {
+        assert!(matches!(pem_key(KEY_PKCS8), Ok(PrivateKeyDer::Pkcs8(_))));
+        assert!(matches!(pem_key(KEY_SEC1), Ok(PrivateKeyDer::Sec1(_))));
+        let both = [CERT, KEY_SEC1].concat();
+        assert!(matches!(pem_key(&both), Ok(PrivateKeyDer::Sec1(_))));
+        assert!(pem_key(CERT).is_err());
+    }
+
+    #[test]
+    fn test_insecure_client_auth() {
+        let config = insecure_config(
+            Some((CERT.to_vec(), KEY_PKCS8.to_vec())),
+            Some(vec![b"mqtt".to_vec()]),
+        )
+        .unwrap();
+        assert!(config.client_auth_cert_resolver.has_certs());
+        assert_eq!(config.alpn_protocols, vec![b"mqtt".to_vec()]);
+    }
+
+    #[test]
+    fn test_verification_needs_insecure() {
+        assert!(transport(&test_config("")).unwrap().is_none());
+        assert!(matches!(
+            transport(&test_config("insecure = true")).unwrap(),
+            Some(Transport::Tls(TlsConfiguration::Rustls(_)))
+        ));
+        // The certificate is verified against the system roots
+        assert!(matches!(
+            transport(&test_config("tls = true")).unwrap(),
+            Some(Transport::Tls(_))
+        ));
+        // A client cert without a ca is only allowed when insecure
+        let client_auth = "client_auth = [\"src/mqtt/samples/client_cert.pem\", \"src/mqtt/samples/client_key_sec1.pem\"]";
+        assert!(transport(&test_config(&format!("tls = true\n{client_auth}"))).is_err());
+        assert!(transport(&test_config(&format!("insecure = true\n{client_auth}"))).is_ok());
+    }
+}
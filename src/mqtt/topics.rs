@@ -0,0 +1,257 @@
+//! Builds and parses the topics from the `topic_template`
+//!
+//! Inside neolink topics are always `<camera>/<kind>/<name>` such as
+//! `Cammy/status/motion`, or just `<kind>/<name>` for neolink's own topics.
+//! This maps them to and from the topics on the broker
+use crate::config::MqttServerConfig;
+
+/// The kinds of topic of the cameras and of neolink itself, a camera with one
+/// of these names could not be told apart from neolink's own topics
+pub(crate) const TOPIC_KINDS: &[&str] =
+    &["status", "control", "query", "event", "config", "cameras"];
+
+#[derive(Clone, Debug)]
+enum Level {
+    Literal(String),
+    Camera,
+    Kind,
+    Name,
+    Topic,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct TopicFormat {
+    prefix: String,
+    template: String,
+}
+
+impl TopicFormat {
+    pub(crate) fn new(config: &MqttServerConfig) -> Self {
+        Self {
+            prefix: config.prefix.clone(),
+            template: config.topic_template.clone(),
+        }
+    }
+
+    /// The broker topic of a camera's sub topic, use an empty camera for
+    /// neolink's own topics
+    pub(crate) fn topic(&self, camera: &str, sub_topic: &str) -> String {
+        let (kind, name) = sub_topic.split_once('/').unwrap_or((sub_topic, ""));
+        self.template
+            .replace("{prefix}", &self.prefix)
+            .replace("{camera}", camera)
+            .replace("{topic}", sub_topic)
+            .replace("{kind}", kind)
+            .replace("{name}", name)
+            .split('/')
+            .filter(|level| !level.is_empty())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Split a broker topic into its camera and sub topic, the camera is
+    /// empty for neolink's own topics
+    pub(crate) fn parse(&self, topic: &str) -> Option<(String, String)> {
+        let levels = self.levels();
+        let topic_levels = topic.split('/').collect::<Vec<_>>();
+        // A level in the place of the camera that is a kind belongs to one
+        // of neolink's own topics such as `config/status`
+        let (camera, kind, name) = match_levels(&levels, &topic_levels, false)
+            .filter(|(camera, _, _)| !TOPIC_KINDS.contains(&camera.as_str()))
+            .or_else(|| match_levels(&levels, &topic_levels, true))?;
+        let sub_topic = if name.is_empty() {
+            kind
+        } else {
+            format!("{kind}/{name}")
+        };
+        Some((camera, sub_topic))
+    }
+
+    /// The filter to subscribe to so that all topics of the template are received
+    pub(crate) fn subscription(&self) -> String {
+        let mut filter = self
+            .levels()
+            .into_iter()
+            .map_while(|level| match level {
+                Level::Literal(literal) => Some(literal),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        filter.push("#".to_string());
+        filter.join("/")
+    }
+
+    fn levels(&self) -> Vec<Level> {
+        self.template
+            .split('/')
+            .flat_map(|level| match level {
+                "{prefix}" => self
+                    .prefix
+                    .split('/')
+                    .filter(|l| !l.is_empty())
+                    .map(|l| Level::Literal(l.to_string()))
+                    .collect(),
+                "{camera}" => vec![Level::Camera],
+                "{kind}" => vec![Level::Kind],
+                "{name}" => vec![Level::Name],
+                "{topic}" => vec![Level::Topic],
+                "" => vec![],
+                literal => vec![Level::Literal(literal.to_string())],
+            })
+            .collect()
+    }
+}
+
+/// Match the topic levels against the template, `global` matches neolink's own
+/// topics that have no camera
+fn match_levels(
+    levels: &[Level],
+    topic: &[&str],
+    global: bool,
+) -> Option<(String, String, String)> {
+    match levels.split_first() {
+        None if topic.is_empty() => Some(Default::default()),
+        None => None,
+        Some((Level::Literal(literal), rest)) => {
+            if topic.first() == Some(&literal.as_str()) {
+                match_levels(rest, &topic[1..], global)
+            } else {
+                None
+            }
+        }
+        Some((Level::Camera, rest)) if global => match_levels(rest, topic, global),
+        Some((Level::Camera, rest)) => {
+            let (camera, topic) = topic.split_first()?;
+            let (_, kind, name) = match_levels(rest, topic, global)?;
+            Some((camera.to_string(), kind, name))
+        }
+        Some((Level::Kind, rest)) => {
+            let (kind, topic) = topic.split_first()?;
+            let (camera, _, name) = match_levels(rest, topic, global)?;
+            Some((camera, kind.to_string(), name))
+        }
+        Some((Level::Name, rest)) => (0..=topic.len()).rev().find_map(|n| {
+            let (camera, kind, _) = match_levels(rest, &topic[n..], global)?;
+            Some((camera, kind, topic[..n].join("/")))
+        }),
+        Some((Level::Topic, rest)) => {
+            let mut expanded = vec![Level::Kind, Level::Name];
+            expanded.extend_from_slice(rest);
+            match_levels(&expanded, topic, global)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(extra: &str) -> TopicFormat {
+        TopicFormat::new(&crate::mqtt::test_config(extra))
+    }
+
+    fn round_trip(topics: &TopicFormat, camera: &str, sub_topic: &str, expected: &str) {
+        let topic = topics.topic(camera, sub_topic);
+        assert_eq!(topic, expected);
+        assert_eq!(
+            topics.parse(&topic),
+            Some((camera.to_string(), sub_topic.to_string())),
+            "{}",
+            topic
+        );
+    }
+
+    #[test]
+    fn test_default_template() {
+        let topics = format("");
+        round_trip(
+            &topics,
+            "Cammy",
+            "status/motion",
+            "neolink/Cammy/status/motion",
+        );
+        round_trip(
+            &topics,
+            "Cammy",
+            "status/motion/json",
+            "neolink/Cammy/status/motion/json",
+        );
+        round_trip(&topics, "Cammy", "status", "neolink/Cammy/status");
+        round_trip(
+            &topics,
+            "Cammy",
+            "control/ptz/preset",
+            "neolink/Cammy/control/ptz/preset",
+        );
+        round_trip(&topics, "", "status", "neolink/status");
+        round_trip(&topics, "", "config", "neolink/config");
+        round_trip(&topics, "", "cameras/add", "neolink/cameras/add");
+        assert_eq!(topics.subscription(), "neolink/#");
+    }
+
+    #[test]
+    fn test_custom_templates() {
+        let topics = format("prefix = \"home/cams\"");
+        round_trip(
+            &topics,
+            "Cammy",
+            "status/motion",
+            "home/cams/Cammy/status/motion",
+        );
+        round_trip(&topics, "", "status", "home/cams/status");
+        assert_eq!(topics.subscription(), "home/cams/#");
+
+        let topics = format("topic_template = \"{prefix}/{kind}/{camera}/{name}\"");
+        round_trip(
+            &topics,
+            "Cammy",
+            "status/motion",
+            "neolink/status/Cammy/motion",
+        );
+        round_trip(
+            &topics,
+            "Cammy",
+            "control/ptz/preset",
+            "neolink/control/Cammy/ptz/preset",
+        );
+        round_trip(&topics, "Cammy", "status", "neolink/status/Cammy");
+        round_trip(&topics, "", "status", "neolink/status");
+        round_trip(&topics, "", "config/status", "neolink/config/status");
+
+        let topics = format("topic_template = \"cameras/{camera}/neolink/{topic}\"");
+        round_trip(
+            &topics,
+            "Cammy",
+            "status/motion",
+            "cameras/Cammy/neolink/status/motion",
+        );
+        round_trip(&topics, "", "status", "cameras/neolink/status");
+        assert_eq!(topics.subscription(), "cameras/#");
+    }
+
+    #[test]
+    fn test_ambiguous_topics() {
+        let topics = format("");
+        // Neolink's own topics are never read as a camera named after a kind
+        assert_eq!(
+            topics.parse("neolink/config/status"),
+            Some(("".to_string(), "config/status".to_string()))
+        );
+        assert_eq!(
+            topics.parse("neolink/cameras/status"),
+            Some(("".to_string(), "cameras/status".to_string()))
+        );
+        assert_eq!(
+            topics.parse("neolink/status/availability"),
+            Some(("".to_string(), "status/availability".to_string()))
+        );
+        // A camera with a name like a kind still parses as its camera topic
+        assert_eq!(
+            topics.parse("neolink/Cammy/config/status"),
+            Some(("Cammy".to_string(), "config/status".to_string()))
+        );
+        // Outside of the template
+        assert_eq!(topics.parse("other/Cammy/status"), None);
+        assert_eq!(topics.parse("neolink"), None);
+    }
+}
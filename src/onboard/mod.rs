@@ -0,0 +1,171 @@
+//!
+//! # Neolink Onboard
+//!
+//! This module searches the LAN for cameras every few minutes and adds the
+//! new ones that accept a login, so that an installer can add a camera
+//! without touching the server
+//!
+//! It is enabled with an `[onboard]` table in the config
+//!
+//! ```toml
+//! [onboard]
+//! username = "admin"
+//! password = "password"
+//! interval = 300
+//! name = "{uid}"
+//!   [onboard.template]
+//!   idle_disconnect = true
+//!   mqtt.discovery.topic = "homeassistant"
+//! ```
+//!
+//! The `template` holds the settings of the new cameras as in `[[cameras]]`,
+//! with it they can be announced to Home Assistant with mqtt discovery. A
+//! camera that is already in the config by its address or uid is skipped, as
+//! is one that does not accept the login until neolink restarts so that it is
+//! not locked by repeated failed logins
+//!
+//! The cameras that are added are kept when the config file is reloaded but
+//! are not written to it, they are logged so that they can be copied into it
+//!
+use anyhow::{anyhow, Context};
+use log::*;
+use neolink_core::bc_protocol::search_lan;
+use std::{
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use validator::Validate;
+
+use crate::{
+    common::NeoReactor,
+    config::{merge_defaults, CameraConfig, Config, OnboardConfig},
+    discover::{fill_details, FoundCamera},
+    AnyResult,
+};
+
+/// How long to wait for the replies to the broadcast
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Search for new cameras until the program stops
+pub(crate) async fn main(onboard: OnboardConfig, reactor: NeoReactor) -> AnyResult<()> {
+    info!(
+        "Looking for new cameras on the LAN every {}s",
+        onboard.interval
+    );
+    // The cameras that did not accept the login
+    let mut rejected = HashSet::new();
+    let mut ticker = interval(Duration::from_secs(onboard.interval));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = search(&onboard, &reactor, &mut rejected).await {
+            warn!("Failed to look for new cameras: {e:?}");
+        }
+    }
+}
+
+async fn search(
+    onboard: &OnboardConfig,
+    reactor: &NeoReactor,
+    rejected: &mut HashSet<IpAddr>,
+) -> AnyResult<()> {
+    let found = search_lan(SEARCH_TIMEOUT)
+        .await
+        .context("Failed to search the LAN")?;
+    for ip in found {
+        if rejected.contains(&ip) {
+            continue;
+        }
+        let mut config = reactor.config().await?.borrow().clone();
+        if config.cameras.iter().any(|cam| has_ip(cam, ip)) {
+            continue;
+        }
+        let mut found = FoundCamera {
+            ip,
+            uid: None,
+            model: None,
+            channels: None,
+        };
+        if let Err(e) = fill_details(&mut found, &onboard.username, &onboard.password).await {
+            info!("{ip}: Not adding the camera as it did not accept the login, it is skipped until neolink restarts: {e}");
+            rejected.insert(ip);
+            continue;
+        }
+        if let Some(uid) = found.uid.as_ref() {
+            if config
+                .cameras
+                .iter()
+                .any(|cam| cam.camera_uid.as_ref() == Some(uid))
+            {
+                continue;
+            }
+        }
+
+        let camera = new_camera(onboard, &found, &config)?;
+        info!(
+            "{}: Adding the camera that was found at {ip}, add it to the config file to keep it",
+            camera.name
+        );
+        config.cameras.push(camera);
+        reactor.update_config(config).await?;
+    }
+    Ok(())
+}
+
+/// If the address of the camera is this ip
+fn has_ip(camera: &CameraConfig, ip: IpAddr) -> bool {
+    camera.camera_addr.as_deref().is_some_and(|addr| {
+        SocketAddr::from_str(addr).ok().map(|addr| addr.ip()) == Some(ip)
+            || IpAddr::from_str(addr).ok() == Some(ip)
+    })
+}
+
+/// The config of the found camera from the template
+fn new_camera(
+    onboard: &OnboardConfig,
+    found: &FoundCamera,
+    config: &Config,
+) -> AnyResult<CameraConfig> {
+    let uid = found
+        .uid
+        .clone()
+        .unwrap_or_else(|| found.ip.to_string().replace(['.', ':'], "_"));
+    let base = onboard
+        .name
+        .replace("{uid}", &uid)
+        .replace("{ip}", &found.ip.to_string().replace(['.', ':'], "_"))
+        .replace("{model}", found.model.as_deref().unwrap_or("Camera"))
+        .replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-',
+            "_",
+        );
+    let mut name = base.clone();
+    let mut n = 1;
+    while config.cameras.iter().any(|cam| cam.name == name) {
+        n += 1;
+        name = format!("{base}_{n}");
+    }
+
+    let mut table = toml::Table::new();
+    table.insert("name".to_string(), name.clone().into());
+    table.insert("username".to_string(), onboard.username.clone().into());
+    if let Some(password) = onboard.password.as_ref() {
+        table.insert("password".to_string(), password.clone().into());
+    }
+    table.insert("address".to_string(), format!("{}:9000", found.ip).into());
+    if let Some(uid) = found.uid.as_ref() {
+        table.insert("uid".to_string(), uid.clone().into());
+    }
+    merge_defaults(&mut table, &onboard.template);
+
+    let mut camera: CameraConfig = toml::Value::Table(table)
+        .try_into()
+        .with_context(|| format!("{name}: The [onboard.template] is not a camera config"))?;
+    camera
+        .validate()
+        .map_err(|e| anyhow!("{name}: The [onboard.template] is not a valid camera: {e}"))?;
+    camera.onboarded = true;
+    Ok(camera)
+}
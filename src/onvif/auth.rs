@@ -0,0 +1,241 @@
+//! Checks the users of ONVIF requests against the users in the config
+//!
+//! ONVIF clients send a WS-Security UsernameToken in the SOAP header, usually
+//! with a `PasswordDigest` of `Base64(SHA1(nonce + created + password))`.
+//! A digest is only accepted while its `Created` is within a few minutes of
+//! now and its `Nonce` has not been seen before, so a captured request cannot
+//! be replayed. HTTP basic auth is also accepted since some clients use it
+//! for the snapshot
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use subtle::ConstantTimeEq;
+
+use super::soap::UsernameToken;
+use crate::config::{CameraConfig, Config};
+
+/// How far the `Created` of a digest may be from our clock
+const MAX_CLOCK_SKEW: Duration = Duration::minutes(5);
+
+/// The nonces of the digests that are still fresh with their `Created`
+#[derive(Clone, Default)]
+pub(super) struct Nonces(Arc<Mutex<HashMap<String, DateTime<Utc>>>>);
+
+impl Nonces {
+    /// Remember the nonce, false if it was already used
+    fn insert(&self, nonce: &str, created: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let mut nonces = self.0.lock().unwrap();
+        // Those that are too old to be fresh are refused by their time anyway
+        nonces.retain(|_, created| *created + MAX_CLOCK_SKEW >= now);
+        nonces.insert(nonce.to_string(), created).is_none()
+    }
+}
+
+/// Why a request was refused
+pub(super) enum Refused {
+    /// No user or the wrong password
+    Unauthorized,
+    /// A valid user that is not in the camera's `permitted_users`
+    Forbidden,
+}
+
+/// Check the UsernameToken of a SOAP request
+pub(super) fn check_token(
+    token: Option<&UsernameToken>,
+    nonces: &Nonces,
+    config: &Config,
+    camera_config: &CameraConfig,
+) -> Result<(), Refused> {
+    if config.users.is_empty() {
+        return Ok(());
+    }
+    let token = token.ok_or(Refused::Unauthorized)?;
+    let user = config
+        .users
+        .iter()
+        .find(|user| user.name == token.username)
+        .ok_or(Refused::Unauthorized)?;
+    let pass = user.pass.as_deref().unwrap_or("");
+    let valid = if token.digest {
+        check_digest(token, pass, nonces, Utc::now())
+    } else {
+        bool::from(token.password.as_bytes().ct_eq(pass.as_bytes()))
+    };
+    if !valid {
+        return Err(Refused::Unauthorized);
+    }
+    check_permitted(&user.name, camera_config)
+}
+
+/// Check the value of an HTTP `Authorization: Basic` header
+pub(super) fn check_basic(
+    authorization: Option<&str>,
+    config: &Config,
+    camera_config: &CameraConfig,
+) -> Result<(), Refused> {
+    if config.users.is_empty() {
+        return Ok(());
+    }
+    let credentials = authorization
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or(Refused::Unauthorized)?;
+    let (name, pass) = credentials.split_once(':').ok_or(Refused::Unauthorized)?;
+    if !config.users.iter().any(|user| {
+        user.name == name
+            && bool::from(
+                user.pass
+                    .as_deref()
+                    .unwrap_or("")
+                    .as_bytes()
+                    .ct_eq(pass.as_bytes()),
+            )
+    }) {
+        return Err(Refused::Unauthorized);
+    }
+    check_permitted(name, camera_config)
+}
+
+/// Check a `PasswordDigest`, it needs a `Nonce` and a fresh `Created`
+fn check_digest(token: &UsernameToken, pass: &str, nonces: &Nonces, now: DateTime<Utc>) -> bool {
+    let (Some(nonce), Some(created)) = (token.nonce.as_deref(), token.created.as_deref()) else {
+        return false;
+    };
+    let Ok(nonce_bytes) = BASE64.decode(nonce.trim()) else {
+        return false;
+    };
+    let Ok(created_time) = DateTime::parse_from_rfc3339(created.trim()) else {
+        return false;
+    };
+    let created_time = created_time.with_timezone(&Utc);
+    if created_time < now - MAX_CLOCK_SKEW || created_time > now + MAX_CLOCK_SKEW {
+        return false;
+    }
+
+    let digest = Sha1::new()
+        .chain_update(&nonce_bytes)
+        .chain_update(created.as_bytes())
+        .chain_update(pass.as_bytes())
+        .finalize();
+    let valid = bool::from(
+        BASE64
+            .encode(digest)
+            .as_bytes()
+            .ct_eq(token.password.trim().as_bytes()),
+    );
+    // Only a valid digest uses up its nonce, so others cannot be made to fail
+    valid && nonces.insert(nonce.trim(), created_time, now)
+}
+
+fn check_permitted(name: &str, camera_config: &CameraConfig) -> Result<(), Refused> {
+    match &camera_config.permitted_users {
+        Some(permitted) if !permitted.iter().any(|u| u == "anyone" || u == name) => {
+            Err(Refused::Forbidden)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example of the ONVIF Application Programmer's Guide
+    fn spec_token() -> UsernameToken {
+        UsernameToken {
+            username: "user".to_string(),
+            password: "tuOSpGlFlIXsozq4HFNeeGeFLEI=".to_string(),
+            digest: true,
+            nonce: Some("LKqI6G/AikKCQrN0zqZFlg==".to_string()),
+            created: Some("2010-09-16T07:50:45Z".to_string()),
+        }
+    }
+
+    fn spec_time() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2010-09-16T07:51:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_spec_digest() {
+        let token = spec_token();
+        assert!(check_digest(
+            &token,
+            "userpassword",
+            &Nonces::default(),
+            spec_time()
+        ));
+        assert!(!check_digest(
+            &token,
+            "otherpassword",
+            &Nonces::default(),
+            spec_time()
+        ));
+    }
+
+    #[test]
+    fn test_reused_nonce() {
+        let nonces = Nonces::default();
+        let token = spec_token();
+        assert!(check_digest(&token, "userpassword", &nonces, spec_time()));
+        assert!(!check_digest(&token, "userpassword", &nonces, spec_time()));
+        // A wrong password does not use up the nonce
+        let nonces = Nonces::default();
+        assert!(!check_digest(&token, "otherpassword", &nonces, spec_time()));
+        assert!(check_digest(&token, "userpassword", &nonces, spec_time()));
+    }
+
+    #[test]
+    fn test_created_freshness() {
+        let token = spec_token();
+        let late = spec_time() + Duration::minutes(10);
+        assert!(!check_digest(
+            &token,
+            "userpassword",
+            &Nonces::default(),
+            late
+        ));
+        let early = spec_time() - Duration::minutes(10);
+        assert!(!check_digest(
+            &token,
+            "userpassword",
+            &Nonces::default(),
+            early
+        ));
+
+        let mut token = spec_token();
+        token.created = None;
+        assert!(!check_digest(
+            &token,
+            "userpassword",
+            &Nonces::default(),
+            spec_time()
+        ));
+        let mut token = spec_token();
+        token.created = Some("yesterday".to_string());
+        assert!(!check_digest(
+            &token,
+            "userpassword",
+            &Nonces::default(),
+            spec_time()
+        ));
+    }
+
+    #[test]
+    fn test_missing_nonce() {
+        let mut token = spec_token();
+        token.nonce = None;
+        assert!(!check_digest(
+            &token,
+            "userpassword",
+            &Nonces::default(),
+            spec_time()
+        ));
+    }
+}
@@ -0,0 +1,181 @@
+//! The ONVIF device service
+//!
+//! This tells the client what the camera is and where its other services are
+use std::time::SystemTime;
+
+use super::{
+    not_supported,
+    soap::{envelope, escape, SoapRequest},
+    Device,
+};
+use crate::{config::CameraConfig, http::server::HttpResponse, utils::utc, AnyResult};
+
+/// The namespaces and paths of the services of each camera
+pub(super) const SERVICES: &[(&str, &str, u32)] = &[
+    (
+        "http://www.onvif.org/ver10/device/wsdl",
+        "device_service",
+        2,
+    ),
+    ("http://www.onvif.org/ver10/media/wsdl", "media_service", 2),
+    (
+        "http://www.onvif.org/ver20/imaging/wsdl",
+        "imaging_service",
+        2,
+    ),
+    ("http://www.onvif.org/ver10/events/wsdl", "event_service", 2),
+];
+
+pub(super) async fn handle(device: &Device<'_>, soap: &SoapRequest) -> AnyResult<HttpResponse> {
+    let body = match soap.action.as_str() {
+        "GetSystemDateAndTime" => {
+            let (year, month, day, hour, minute, second) = utc(SystemTime::now());
+            format!(
+                "<tds:GetSystemDateAndTimeResponse><tds:SystemDateAndTime>\
+                <tt:DateTimeType>Manual</tt:DateTimeType>\
+                <tt:DaylightSavings>false</tt:DaylightSavings>\
+                <tt:TimeZone><tt:TZ>UTC0</tt:TZ></tt:TimeZone>\
+                <tt:UTCDateTime>\
+                <tt:Time><tt:Hour>{hour}</tt:Hour><tt:Minute>{minute}</tt:Minute><tt:Second>{second}</tt:Second></tt:Time>\
+                <tt:Date><tt:Year>{year}</tt:Year><tt:Month>{month}</tt:Month><tt:Day>{day}</tt:Day></tt:Date>\
+                </tt:UTCDateTime>\
+                </tds:SystemDateAndTime></tds:GetSystemDateAndTimeResponse>"
+            )
+        }
+        "GetDeviceInformation" => {
+            let version = device
+                .camera
+                .run_task(|cam| Box::pin(async move { Ok(cam.version().await?) }))
+                .await;
+            let (model, firmware, serial, hardware) = match version {
+                Ok(version) => (
+                    version.model.unwrap_or(version.name),
+                    version.firmwareVersion,
+                    version.serialNumber,
+                    version.hardwareVersion,
+                ),
+                Err(e) => {
+                    log::debug!("{}: Could not get the version: {e:?}", device.name);
+                    (
+                        device.name.to_string(),
+                        String::new(),
+                        device.name.to_string(),
+                        String::new(),
+                    )
+                }
+            };
+            format!(
+                "<tds:GetDeviceInformationResponse>\
+                <tds:Manufacturer>Reolink</tds:Manufacturer>\
+                <tds:Model>{}</tds:Model>\
+                <tds:FirmwareVersion>{}</tds:FirmwareVersion>\
+                <tds:SerialNumber>{}</tds:SerialNumber>\
+                <tds:HardwareId>{}</tds:HardwareId>\
+                </tds:GetDeviceInformationResponse>",
+                escape(&model),
+                escape(&firmware),
+                escape(&serial),
+                escape(&hardware)
+            )
+        }
+        "GetCapabilities" => {
+            let url = escape(&device.service_url);
+            format!(
+                "<tds:GetCapabilitiesResponse><tds:Capabilities>\
+                <tt:Device><tt:XAddr>{url}/device_service</tt:XAddr>\
+                <tt:Network><tt:IPFilter>false</tt:IPFilter><tt:ZeroConfiguration>false</tt:ZeroConfiguration>\
+                <tt:IPVersion6>false</tt:IPVersion6><tt:DynDNS>false</tt:DynDNS></tt:Network>\
+                <tt:System><tt:DiscoveryResolve>false</tt:DiscoveryResolve><tt:DiscoveryBye>true</tt:DiscoveryBye>\
+                <tt:RemoteDiscovery>false</tt:RemoteDiscovery><tt:SystemBackup>false</tt:SystemBackup>\
+                <tt:SystemLogging>false</tt:SystemLogging><tt:FirmwareUpgrade>false</tt:FirmwareUpgrade>\
+                <tt:SupportedVersions><tt:Major>2</tt:Major><tt:Minor>0</tt:Minor></tt:SupportedVersions></tt:System>\
+                </tt:Device>\
+                <tt:Events><tt:XAddr>{url}/event_service</tt:XAddr>\
+                <tt:WSSubscriptionPolicySupport>false</tt:WSSubscriptionPolicySupport>\
+                <tt:WSPullPointSupport>true</tt:WSPullPointSupport>\
+                <tt:WSPausableSubscriptionManagerInterfaceSupport>false</tt:WSPausableSubscriptionManagerInterfaceSupport>\
+                </tt:Events>\
+                <tt:Imaging><tt:XAddr>{url}/imaging_service</tt:XAddr></tt:Imaging>\
+                <tt:Media><tt:XAddr>{url}/media_service</tt:XAddr>\
+                <tt:StreamingCapabilities><tt:RTPMulticast>false</tt:RTPMulticast>\
+                <tt:RTP_TCP>true</tt:RTP_TCP><tt:RTP_RTSP_TCP>true</tt:RTP_RTSP_TCP></tt:StreamingCapabilities>\
+                </tt:Media>\
+                </tds:Capabilities></tds:GetCapabilitiesResponse>"
+            )
+        }
+        "GetServices" => {
+            let services = SERVICES
+                .iter()
+                .map(|(namespace, path, major)| {
+                    format!(
+                        "<tds:Service><tds:Namespace>{namespace}</tds:Namespace>\
+                        <tds:XAddr>{}/{path}</tds:XAddr>\
+                        <tds:Version><tt:Major>{major}</tt:Major><tt:Minor>0</tt:Minor></tds:Version>\
+                        </tds:Service>",
+                        escape(&device.service_url)
+                    )
+                })
+                .collect::<String>();
+            format!("<tds:GetServicesResponse>{services}</tds:GetServicesResponse>")
+        }
+        "GetServiceCapabilities" => "<tds:GetServiceCapabilitiesResponse><tds:Capabilities>\
+            <tds:Network/><tds:Security/><tds:System/>\
+            </tds:Capabilities></tds:GetServiceCapabilitiesResponse>"
+            .to_string(),
+        "GetScopes" => {
+            let scopes = scopes(device.camera_config)
+                .iter()
+                .map(|scope| {
+                    format!(
+                        "<tds:Scopes><tt:ScopeDef>Fixed</tt:ScopeDef><tt:ScopeItem>{}</tt:ScopeItem></tds:Scopes>",
+                        escape(scope)
+                    )
+                })
+                .collect::<String>();
+            format!("<tds:GetScopesResponse>{scopes}</tds:GetScopesResponse>")
+        }
+        "GetHostname" => format!(
+            "<tds:GetHostnameResponse><tds:HostnameInformation>\
+            <tt:FromDHCP>false</tt:FromDHCP><tt:Name>{}</tt:Name>\
+            </tds:HostnameInformation></tds:GetHostnameResponse>",
+            escape(device.name)
+        ),
+        "SystemReboot" => {
+            device
+                .camera
+                .run_task(|cam| Box::pin(async move { Ok(cam.reboot().await?) }))
+                .await?;
+            "<tds:SystemRebootResponse><tds:Message>Rebooting</tds:Message></tds:SystemRebootResponse>"
+                .to_string()
+        }
+        _ => return Ok(not_supported(soap)),
+    };
+    Ok(envelope(&body))
+}
+
+/// The ONVIF scopes of a camera, these are also announced by discovery
+pub(super) fn scopes(camera_config: &CameraConfig) -> Vec<String> {
+    let name = camera_config
+        .name
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (b as char).to_string(),
+            b => format!("%{b:02X}"),
+        })
+        .collect::<String>();
+    let mut scopes = vec![
+        "onvif://www.onvif.org/Profile/Streaming".to_string(),
+        "onvif://www.onvif.org/Profile/S".to_string(),
+        "onvif://www.onvif.org/type/video_encoder".to_string(),
+        "onvif://www.onvif.org/hardware/Reolink".to_string(),
+        format!("onvif://www.onvif.org/name/{name}"),
+    ];
+    scopes.extend(camera_config.onvif_scopes.iter().cloned());
+    scopes
+}
+
+/// A time as an `xs:dateTime` in UTC such as `2024-01-31T12:00:00Z`
+pub(super) fn datetime(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc(time);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
@@ -0,0 +1,268 @@
+//! WS-Discovery so that NVRs on the LAN find the cameras by themselves
+//!
+//! NVRs multicast a `Probe` to `239.255.255.250:3702` and each camera that
+//! matches its types and scopes answers with a `ProbeMatch` holding the
+//! address of its device service. A `Hello` is multicast for every camera when
+//! neolink starts and a `Bye` when it stops
+use anyhow::Context;
+use log::*;
+use sha1::{Digest, Sha1};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tokio_util::sync::CancellationToken;
+use uuid::{Builder, Uuid};
+
+use super::{device::scopes, soap::escape, soap::SoapRequest};
+use crate::{
+    common::NeoReactor,
+    config::{CameraConfig, OnvifConfig},
+    AnyResult,
+};
+
+const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const PORT: u16 = 3702;
+
+/// The types of the devices in the ONVIF namespaces
+const TYPES: &[&str] = &["NetworkVideoTransmitter", "Device"];
+
+const NAMESPACES: &str = concat!(
+    r#"xmlns:s="http://www.w3.org/2003/05/soap-envelope" "#,
+    r#"xmlns:a="http://schemas.xmlsoap.org/ws/2004/08/addressing" "#,
+    r#"xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery" "#,
+    r#"xmlns:dn="http://www.onvif.org/ver10/network/wsdl" "#,
+    r#"xmlns:tds="http://www.onvif.org/ver10/device/wsdl""#,
+);
+
+struct Discovery {
+    onvif_config: OnvifConfig,
+    /// Multicasts the `Hello` and `Bye` from the configured interface
+    announce: UdpSocket,
+    /// For the `AppSequence` which lets clients order the messages
+    instance_id: u64,
+    message_number: u64,
+}
+
+/// Answer probes until cancelled
+pub(super) async fn main(
+    onvif_config: OnvifConfig,
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let interface = onvif_config.discovery_bind.unwrap_or(Ipv4Addr::UNSPECIFIED);
+    // Multicast is only received on a socket that is not bound to a unicast address
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, PORT))
+        .await
+        .with_context(|| format!("Failed to bind WS-Discovery to port {PORT}"))?;
+    socket
+        .join_multicast_v4(MULTICAST_ADDR, interface)
+        .with_context(|| format!("Failed to join {MULTICAST_ADDR} on {interface}"))?;
+    let announce = UdpSocket::bind((interface, 0))
+        .await
+        .with_context(|| format!("Failed to bind WS-Discovery to {interface}"))?;
+    info!("Starting WS-Discovery on {interface}:{PORT}");
+
+    let mut discovery = Discovery {
+        onvif_config,
+        announce,
+        instance_id: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        message_number: 0,
+    };
+    let announced = cameras(&reactor).await?;
+    for camera_config in announced.iter() {
+        discovery.hello(camera_config).await;
+    }
+
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let (len, peer) = tokio::select! {
+            _ = cancel.cancelled() => break,
+            v = socket.recv_from(&mut buf) => match v {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!("WS-Discovery receive failed: {e:?}");
+                    continue;
+                }
+            },
+        };
+        let Ok(probe) = SoapRequest::parse(&buf[..len]) else {
+            continue;
+        };
+        if probe.action != "Probe" {
+            continue;
+        }
+        for camera_config in cameras(&reactor).await? {
+            if !matches(&probe, &camera_config) {
+                continue;
+            }
+            trace!("{}: WS-Discovery probe from {peer}", camera_config.name);
+            let reply = discovery.probe_match(&probe, &camera_config, peer);
+            if let Err(e) = socket.send_to(reply.as_bytes(), peer).await {
+                debug!("WS-Discovery reply to {peer} failed: {e:?}");
+            }
+        }
+    }
+
+    for camera_config in announced.iter() {
+        discovery.bye(camera_config).await;
+    }
+    Ok(())
+}
+
+impl Discovery {
+    async fn hello(&mut self, camera_config: &CameraConfig) {
+        let xaddrs = self.xaddrs(camera_config, multicast());
+        let body = format!("<d:Hello>{}</d:Hello>", self.device(camera_config, &xaddrs));
+        let message = self.message("Hello", None, &body);
+        self.multicast(&message).await;
+    }
+
+    async fn bye(&mut self, camera_config: &CameraConfig) {
+        let body = format!(
+            "<d:Bye><a:EndpointReference><a:Address>{}</a:Address></a:EndpointReference></d:Bye>",
+            endpoint(&camera_config.name)
+        );
+        let message = self.message("Bye", None, &body);
+        self.multicast(&message).await;
+    }
+
+    fn probe_match(
+        &mut self,
+        probe: &SoapRequest,
+        camera_config: &CameraConfig,
+        peer: SocketAddr,
+    ) -> String {
+        let xaddrs = self.xaddrs(camera_config, peer);
+        let body = format!(
+            "<d:ProbeMatches><d:ProbeMatch>{}</d:ProbeMatch></d:ProbeMatches>",
+            self.device(camera_config, &xaddrs)
+        );
+        self.message("ProbeMatches", probe.header_field("MessageID"), &body)
+    }
+
+    async fn multicast(&self, message: &str) {
+        if let Err(e) = self.announce.send_to(message.as_bytes(), multicast()).await {
+            debug!("WS-Discovery multicast failed: {e:?}");
+        }
+    }
+
+    /// The address of the device service of the camera as seen from `peer`
+    fn xaddrs(&self, camera_config: &CameraConfig, peer: SocketAddr) -> String {
+        let host = match (
+            self.onvif_config.host.as_ref(),
+            self.onvif_config.discovery_bind,
+        ) {
+            (Some(host), _) => host.clone(),
+            (None, Some(interface)) => interface.to_string(),
+            (None, None) => local_ip(peer),
+        };
+        format!(
+            "http://{host}:{}/onvif/{}/device_service",
+            self.onvif_config.port, camera_config.name
+        )
+    }
+
+    /// The part of `Hello` and `ProbeMatch` that describes the camera
+    fn device(&self, camera_config: &CameraConfig, xaddrs: &str) -> String {
+        format!(
+            "<a:EndpointReference><a:Address>{}</a:Address></a:EndpointReference>\
+            <d:Types>dn:NetworkVideoTransmitter tds:Device</d:Types>\
+            <d:Scopes>{}</d:Scopes>\
+            <d:XAddrs>{}</d:XAddrs>\
+            <d:MetadataVersion>1</d:MetadataVersion>",
+            endpoint(&camera_config.name),
+            escape(&scopes(camera_config).join(" ")),
+            escape(xaddrs)
+        )
+    }
+
+    fn message(&mut self, action: &str, relates_to: Option<&str>, body: &str) -> String {
+        self.message_number += 1;
+        let (to, relates_to) = match relates_to {
+            Some(id) => (
+                "http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous",
+                format!("<a:RelatesTo>{}</a:RelatesTo>", escape(id)),
+            ),
+            None => (
+                "urn:schemas-xmlsoap-org:ws:2005:04:discovery",
+                String::new(),
+            ),
+        };
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><s:Envelope {NAMESPACES}><s:Header>\
+            <a:MessageID>urn:uuid:{message_id}</a:MessageID>{relates_to}<a:To>{to}</a:To>\
+            <a:Action>http://schemas.xmlsoap.org/ws/2005/04/discovery/{action}</a:Action>\
+            <d:AppSequence InstanceId=\"{instance_id}\" MessageNumber=\"{message_number}\"/>\
+            </s:Header><s:Body>{body}</s:Body></s:Envelope>",
+            message_id = Uuid::new_v4(),
+            instance_id = self.instance_id,
+            message_number = self.message_number,
+        )
+    }
+}
+
+/// The enabled cameras of the current config that are served over onvif
+async fn cameras(reactor: &NeoReactor) -> AnyResult<Vec<CameraConfig>> {
+    Ok(reactor
+        .config()
+        .await?
+        .borrow()
+        .cameras
+        .iter()
+        .filter(|cam| cam.enabled && cam.onvif)
+        .cloned()
+        .collect())
+}
+
+/// Whether the camera is one of the types and has all the scopes of the probe
+fn matches(probe: &SoapRequest, camera_config: &CameraConfig) -> bool {
+    let types_match = probe
+        .field("Types")
+        .unwrap_or("")
+        .split_whitespace()
+        // The prefixes are declared by the client, only the local name is compared
+        .all(|kind| TYPES.contains(&kind.rsplit(':').next().unwrap_or(kind)));
+    let ours = scopes(camera_config);
+    let scopes_match = probe
+        .field("Scopes")
+        .unwrap_or("")
+        .split_whitespace()
+        // RFC 3986 matching of the default MatchBy, whole segments of the path
+        .all(|scope| {
+            let scope = scope.trim_end_matches('/');
+            ours.iter().any(|our| {
+                our == scope
+                    || our
+                        .strip_prefix(scope)
+                        .map(|rest| rest.starts_with('/'))
+                        .unwrap_or(false)
+            })
+        });
+    types_match && scopes_match
+}
+
+/// A `urn:uuid` that stays the same for the camera across restarts
+fn endpoint(name: &str) -> String {
+    let hash = Sha1::digest(format!("neolink/onvif/{name}").as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    format!("urn:uuid:{}", Builder::from_sha1_bytes(bytes).into_uuid())
+}
+
+/// The local address that packets to `peer` are sent from
+fn local_ip(peer: SocketAddr) -> String {
+    std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect(peer)?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| Ipv4Addr::LOCALHOST.to_string())
+}
+
+fn multicast() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(MULTICAST_ADDR, PORT))
+}
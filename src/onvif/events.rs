@@ -0,0 +1,414 @@
+//! The ONVIF event service
+//!
+//! The motion, AI and doorbell alarms of the camera are offered through
+//! pull-point subscriptions. The NVR creates a subscription and then keeps
+//! pulling it, each pull waits until one of the topics changes or it times out.
+//! The first pull returns the current state of every topic
+//!
+//! The AI topics are the ones that Reolink cameras use on their own ONVIF
+//! server so that NVRs which already know those cameras understand them
+use anyhow::Context;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::{
+    sync::{watch::Receiver as WatchReceiver, Mutex as AsyncMutex},
+    time::{timeout_at, Instant},
+};
+use uuid::Uuid;
+
+use super::{
+    device::datetime,
+    not_supported,
+    soap::{envelope, escape, fault, SoapRequest},
+    Device,
+};
+use crate::{common::MdState, http::server::HttpResponse, AnyResult};
+
+/// How long a subscription lives when the client does not say
+const DEFAULT_TERMINATION: Duration = Duration::from_secs(60);
+/// The longest a client can keep a subscription without renewing it
+const MAX_TERMINATION: Duration = Duration::from_secs(3600);
+/// How long a pull waits for an event when the client does not say
+const DEFAULT_PULL_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_PULL_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Topic {
+    /// Path under `tns1:`
+    path: &'static str,
+    source: &'static [(&'static str, &'static str)],
+    /// Name of the boolean in the data of the message
+    data: &'static str,
+    /// The detection that makes the topic true, any motion when `None`
+    detection: Option<&'static str>,
+}
+
+const RULE_SOURCE: &[(&str, &str)] = &[
+    ("VideoSourceConfigurationToken", "video_source"),
+    ("VideoAnalyticsConfigurationToken", "analytics"),
+    ("Rule", "MyMotionDetectorRule"),
+];
+
+const TOPICS: &[Topic] = &[
+    Topic {
+        path: "RuleEngine/CellMotionDetector/Motion",
+        source: RULE_SOURCE,
+        data: "IsMotion",
+        detection: None,
+    },
+    Topic {
+        path: "RuleEngine/MyRuleDetector/PeopleDetect",
+        source: RULE_SOURCE,
+        data: "IsPeople",
+        detection: Some("people"),
+    },
+    Topic {
+        path: "RuleEngine/MyRuleDetector/VehicleDetect",
+        source: RULE_SOURCE,
+        data: "IsVehicle",
+        detection: Some("vehicle"),
+    },
+    Topic {
+        path: "RuleEngine/MyRuleDetector/DogCatDetect",
+        source: RULE_SOURCE,
+        data: "IsDogCat",
+        detection: Some("animal"),
+    },
+    Topic {
+        path: "RuleEngine/MyRuleDetector/Visitor",
+        source: RULE_SOURCE,
+        data: "IsVisitor",
+        detection: Some("visitor"),
+    },
+    Topic {
+        path: "VideoSource/MotionAlarm",
+        source: &[("Source", "video_source")],
+        data: "State",
+        detection: None,
+    },
+];
+
+impl Topic {
+    fn is_active(&self, state: &MdState) -> bool {
+        match (state, self.detection) {
+            (MdState::Start(..), None) => true,
+            (MdState::Start(_, details), Some(detection)) => {
+                details.detections.iter().any(|d| d == detection)
+            }
+            _ => false,
+        }
+    }
+
+    fn message(&self, active: bool, operation: &str, now: &str) -> String {
+        let source = self
+            .source
+            .iter()
+            .map(|(name, value)| format!("<tt:SimpleItem Name=\"{name}\" Value=\"{value}\"/>"))
+            .collect::<String>();
+        format!(
+            "<wsnt:NotificationMessage>\
+            <wsnt:Topic Dialect=\"http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet\">tns1:{path}</wsnt:Topic>\
+            <wsnt:Message><tt:Message UtcTime=\"{now}\" PropertyOperation=\"{operation}\">\
+            <tt:Source>{source}</tt:Source>\
+            <tt:Data><tt:SimpleItem Name=\"{data}\" Value=\"{active}\"/></tt:Data>\
+            </tt:Message></wsnt:Message></wsnt:NotificationMessage>",
+            path = self.path,
+            data = self.data,
+        )
+    }
+
+    fn description(&self) -> String {
+        let source = self
+            .source
+            .iter()
+            .map(|(name, _)| {
+                format!("<tt:SimpleItemDescription Name=\"{name}\" Type=\"tt:ReferenceToken\"/>")
+            })
+            .collect::<String>();
+        format!(
+            "<tt:MessageDescription IsProperty=\"true\">\
+            <tt:Source>{source}</tt:Source>\
+            <tt:Data><tt:SimpleItemDescription Name=\"{}\" Type=\"xs:boolean\"/></tt:Data>\
+            </tt:MessageDescription>",
+            self.data
+        )
+    }
+}
+
+struct Subscription {
+    motion: WatchReceiver<MdState>,
+    /// What the client was last told for each of the `TOPICS`, `None` before the first pull
+    last: Option<Vec<bool>>,
+    termination: SystemTime,
+}
+
+impl Subscription {
+    /// The messages for the topics that changed since the last pull
+    async fn pull(&mut self, timeout: Duration) -> AnyResult<Vec<String>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let states = {
+                let state = self.motion.borrow_and_update();
+                TOPICS
+                    .iter()
+                    .map(|topic| topic.is_active(&state))
+                    .collect::<Vec<_>>()
+            };
+            let now = datetime(SystemTime::now());
+            let messages = TOPICS
+                .iter()
+                .zip(states.iter())
+                .enumerate()
+                .filter_map(|(i, (topic, &active))| match self.last.as_ref() {
+                    None => Some(topic.message(active, "Initialized", &now)),
+                    Some(last) if last[i] != active => Some(topic.message(active, "Changed", &now)),
+                    Some(_) => None,
+                })
+                .collect::<Vec<_>>();
+            self.last = Some(states);
+            if !messages.is_empty() {
+                return Ok(messages);
+            }
+            match timeout_at(deadline, self.motion.changed()).await {
+                Ok(changed) => changed.context("The motion of the camera is no longer watched")?,
+                Err(_) => return Ok(vec![]),
+            }
+        }
+    }
+}
+
+type Shared = Arc<AsyncMutex<Subscription>>;
+
+/// The pull-point subscriptions of all cameras and the name of their camera by their id
+#[derive(Clone, Default)]
+pub(super) struct Subscriptions(Arc<Mutex<HashMap<String, (String, Shared)>>>);
+
+impl Subscriptions {
+    fn create(&self, camera: &str, subscription: Subscription) -> String {
+        let id = Uuid::new_v4().simple().to_string();
+        let mut subscriptions = self.0.lock().unwrap();
+        // Clients that go away without unsubscribing are removed here
+        let now = SystemTime::now();
+        subscriptions.retain(|_, (_, subscription)| {
+            // One that is locked is being pulled
+            subscription
+                .try_lock()
+                .map(|subscription| subscription.termination > now)
+                .unwrap_or(true)
+        });
+        subscriptions.insert(
+            id.clone(),
+            (camera.to_string(), Arc::new(AsyncMutex::new(subscription))),
+        );
+        id
+    }
+
+    fn get(&self, id: &str, camera: &str) -> Option<Shared> {
+        match self.0.lock().unwrap().get(id) {
+            Some((name, subscription)) if name == camera => Some(subscription.clone()),
+            _ => None,
+        }
+    }
+
+    fn remove(&self, id: &str) {
+        self.0.lock().unwrap().remove(id);
+    }
+}
+
+/// The event service which creates the subscriptions
+pub(super) async fn handle(
+    device: &Device<'_>,
+    soap: &SoapRequest,
+    subscriptions: &Subscriptions,
+) -> AnyResult<HttpResponse> {
+    let body = match soap.action.as_str() {
+        "GetServiceCapabilities" => "<tev:GetServiceCapabilitiesResponse>\
+            <tev:Capabilities WSSubscriptionPolicySupport=\"false\" WSPullPointSupport=\"true\" \
+            WSPausableSubscriptionManagerInterfaceSupport=\"false\"/>\
+            </tev:GetServiceCapabilitiesResponse>"
+            .to_string(),
+        "GetEventProperties" => format!(
+            "<tev:GetEventPropertiesResponse>\
+            <tev:TopicNamespaceLocation>http://www.onvif.org/onvif/ver10/topics/topicns.xml</tev:TopicNamespaceLocation>\
+            <wsnt:FixedTopicSet>true</wsnt:FixedTopicSet>\
+            <wstop:TopicSet>{}</wstop:TopicSet>\
+            <wsnt:TopicExpressionDialect>http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet</wsnt:TopicExpressionDialect>\
+            <wsnt:TopicExpressionDialect>http://docs.oasis-open.org/wsn/t-1/TopicExpression/Concrete</wsnt:TopicExpressionDialect>\
+            <tev:MessageContentFilterDialect>http://www.onvif.org/ver10/tev/messageContentFilter/ItemFilter</tev:MessageContentFilterDialect>\
+            <tev:MessageContentSchemaLocation>http://www.onvif.org/onvif/ver10/schema/onvif.xsd</tev:MessageContentSchemaLocation>\
+            </tev:GetEventPropertiesResponse>",
+            topic_set()
+        ),
+        "CreatePullPointSubscription" => {
+            let now = SystemTime::now();
+            let termination = now + termination(soap.field("InitialTerminationTime"));
+            let id = subscriptions.create(
+                device.name,
+                Subscription {
+                motion: device.camera.motion().await?,
+                last: None,
+                termination,
+            },
+            );
+            log::debug!("{}: ONVIF pull-point subscription {id}", device.name);
+            format!(
+                "<tev:CreatePullPointSubscriptionResponse>\
+                <tev:SubscriptionReference><wsa:Address>{}/subscription/{id}</wsa:Address></tev:SubscriptionReference>\
+                <wsnt:CurrentTime>{}</wsnt:CurrentTime>\
+                <wsnt:TerminationTime>{}</wsnt:TerminationTime>\
+                </tev:CreatePullPointSubscriptionResponse>",
+                escape(&device.service_url),
+                datetime(now),
+                datetime(termination)
+            )
+        }
+        _ => return Ok(not_supported(soap)),
+    };
+    Ok(envelope(&body))
+}
+
+/// The address of a subscription where the client pulls the events
+pub(super) async fn handle_subscription(
+    device: &Device<'_>,
+    soap: &SoapRequest,
+    subscriptions: &Subscriptions,
+    id: &str,
+) -> AnyResult<HttpResponse> {
+    let Some(subscription) = subscriptions.get(id, device.name) else {
+        return Ok(fault(
+            400,
+            "Sender",
+            "ter:InvalidArgVal",
+            "The subscription does not exist",
+        ));
+    };
+    let now = SystemTime::now();
+    let body = match soap.action.as_str() {
+        "PullMessages" => {
+            let timeout = soap
+                .field("Timeout")
+                .and_then(parse_duration)
+                .unwrap_or(DEFAULT_PULL_TIMEOUT)
+                .min(MAX_PULL_TIMEOUT);
+            let mut subscription = subscription.lock().await;
+            // Some NVRs never renew and only keep pulling
+            subscription.termination = subscription.termination.max(now + DEFAULT_TERMINATION);
+            let messages = subscription.pull(timeout).await?;
+            format!(
+                "<tev:PullMessagesResponse>\
+                <tev:CurrentTime>{}</tev:CurrentTime>\
+                <tev:TerminationTime>{}</tev:TerminationTime>\
+                {}</tev:PullMessagesResponse>",
+                datetime(SystemTime::now()),
+                datetime(subscription.termination),
+                messages.concat()
+            )
+        }
+        "Renew" => {
+            let mut subscription = subscription.lock().await;
+            subscription.termination = now + termination(soap.field("TerminationTime"));
+            format!(
+                "<wsnt:RenewResponse>\
+                <wsnt:TerminationTime>{}</wsnt:TerminationTime>\
+                <wsnt:CurrentTime>{}</wsnt:CurrentTime>\
+                </wsnt:RenewResponse>",
+                datetime(subscription.termination),
+                datetime(now)
+            )
+        }
+        "Unsubscribe" => {
+            subscriptions.remove(id);
+            "<wsnt:UnsubscribeResponse/>".to_string()
+        }
+        "SetSynchronizationPoint" => {
+            subscription.lock().await.last = None;
+            "<tev:SetSynchronizationPointResponse/>".to_string()
+        }
+        _ => return Ok(not_supported(soap)),
+    };
+    Ok(envelope(&body))
+}
+
+/// The `TOPICS` as a tree of elements, only the leaves are topics
+fn topic_set() -> String {
+    let mut xml = String::new();
+    let mut open: Vec<&str> = vec![];
+    for topic in TOPICS.iter() {
+        let segments = topic.path.split('/').collect::<Vec<_>>();
+        let (leaf, parents) = segments.split_last().expect("Topics have a path");
+        let common = open
+            .iter()
+            .zip(parents.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while open.len() > common {
+            let name = open.pop().expect("Checked by the length");
+            xml += &close(name, open.is_empty());
+        }
+        for parent in parents[common..].iter() {
+            xml += &format!("<{}>", element(parent, open.is_empty()));
+            open.push(parent);
+        }
+        xml += &format!(
+            "<{leaf} wstop:topic=\"true\">{}</{leaf}>",
+            topic.description()
+        );
+    }
+    while let Some(name) = open.pop() {
+        xml += &close(name, open.is_empty());
+    }
+    xml
+}
+
+/// The top level of the topic tree is in the `tns1` namespace
+fn element(name: &str, top: bool) -> String {
+    if top {
+        format!("tns1:{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+fn close(name: &str, top: bool) -> String {
+    format!("</{}>", element(name, top))
+}
+
+/// The lifetime that the client asked for, which is an `xs:duration` or an `xs:dateTime`
+fn termination(requested: Option<&str>) -> Duration {
+    requested
+        .and_then(parse_duration)
+        .unwrap_or(DEFAULT_TERMINATION)
+        .min(MAX_TERMINATION)
+}
+
+/// Parse an `xs:duration` such as `PT10S` or `PT1H30M`, months and years are not supported
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim().strip_prefix('P')?;
+    let (days, time) = match text.split_once('T') {
+        Some((days, time)) => (days, time),
+        None => (text, ""),
+    };
+    let mut secs = match days {
+        "" => 0.0,
+        days => days.strip_suffix('D')?.parse::<f64>().ok()? * 86400.0,
+    };
+    let mut number = String::new();
+    for c in time.chars() {
+        let unit = match c {
+            'H' => 3600.0,
+            'M' => 60.0,
+            'S' => 1.0,
+            c if c.is_ascii_digit() || c == '.' => {
+                number.push(c);
+                continue;
+            }
+            _ => return None,
+        };
+        secs += number.parse::<f64>().ok()? * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    Some(Duration::from_secs_f64(secs))
+}
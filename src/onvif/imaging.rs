@@ -0,0 +1,74 @@
+//! The ONVIF imaging service
+//!
+//! The only imaging setting that the BC protocol exposes is the IR lights.
+//! ONVIF calls this the IR cut filter, which is the opposite way around: with
+//! the filter `ON` the image is in colour and the IR lights are off
+use neolink_core::bc_protocol::LightState;
+
+use super::{
+    not_supported,
+    soap::{envelope, fault, SoapRequest},
+    Device,
+};
+use crate::{http::server::HttpResponse, AnyResult};
+
+pub(super) async fn handle(device: &Device<'_>, soap: &SoapRequest) -> AnyResult<HttpResponse> {
+    let body = match soap.action.as_str() {
+        "GetServiceCapabilities" => "<timg:GetServiceCapabilitiesResponse>\
+            <timg:Capabilities ImageStabilization=\"false\"/>\
+            </timg:GetServiceCapabilitiesResponse>"
+            .to_string(),
+        "GetImagingSettings" => {
+            let led_state = device
+                .camera
+                .run_task(|cam| Box::pin(async move { Ok(cam.get_ledstate().await?) }))
+                .await?;
+            let ir_cut_filter = match led_state.state.as_str() {
+                "open" => "OFF",
+                "close" => "ON",
+                _ => "AUTO",
+            };
+            format!(
+                "<timg:GetImagingSettingsResponse><timg:ImagingSettings>\
+                <tt:IrCutFilter>{ir_cut_filter}</tt:IrCutFilter>\
+                </timg:ImagingSettings></timg:GetImagingSettingsResponse>"
+            )
+        }
+        "SetImagingSettings" => {
+            let state = match soap.field("IrCutFilter") {
+                Some("ON") => LightState::Off,
+                Some("OFF") => LightState::On,
+                Some("AUTO") => LightState::Auto,
+                Some(_) => {
+                    return Ok(fault(
+                        400,
+                        "Sender",
+                        "ter:InvalidArgVal",
+                        "IrCutFilter must be ON, OFF or AUTO",
+                    ))
+                }
+                None => {
+                    return Ok(fault(
+                        400,
+                        "Sender",
+                        "ter:SettingsInvalid",
+                        "Only IrCutFilter can be set",
+                    ))
+                }
+            };
+            device
+                .camera
+                .run_task(|cam| Box::pin(async move { Ok(cam.irled_light_set(state).await?) }))
+                .await?;
+            "<timg:SetImagingSettingsResponse/>".to_string()
+        }
+        "GetOptions" => "<timg:GetOptionsResponse><timg:ImagingOptions>\
+            <tt:IrCutFilterModes>ON</tt:IrCutFilterModes>\
+            <tt:IrCutFilterModes>OFF</tt:IrCutFilterModes>\
+            <tt:IrCutFilterModes>AUTO</tt:IrCutFilterModes>\
+            </timg:ImagingOptions></timg:GetOptionsResponse>"
+            .to_string(),
+        _ => return Ok(not_supported(soap)),
+    };
+    Ok(envelope(&body))
+}
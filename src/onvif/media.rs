@@ -0,0 +1,245 @@
+//! The ONVIF media service
+//!
+//! Each stream of the camera that neolink serves over rtsp is a profile.
+//! The resolutions and rates come from the stream info of the camera
+use neolink_core::bc_protocol::StreamKind;
+
+use super::{
+    not_supported,
+    soap::{envelope, escape, SoapRequest},
+    Device,
+};
+use crate::{http::server::HttpResponse, AnyResult};
+
+/// The single video source of the camera
+const VIDEO_SOURCE: &str = "video_source";
+
+struct Profile {
+    token: &'static str,
+    /// The path of the stream on the rtsp server
+    path: String,
+    width: u32,
+    height: u32,
+    fps: u32,
+    /// kbps
+    bitrate: u32,
+}
+
+impl Profile {
+    fn video_source_configuration(&self, use_count: usize) -> String {
+        format!(
+            "<tt:Name>{VIDEO_SOURCE}</tt:Name><tt:UseCount>{use_count}</tt:UseCount>\
+            <tt:SourceToken>{VIDEO_SOURCE}</tt:SourceToken>\
+            <tt:Bounds x=\"0\" y=\"0\" width=\"{}\" height=\"{}\"/>",
+            self.width, self.height
+        )
+    }
+
+    fn video_encoder_configuration(&self) -> String {
+        format!(
+            "<tt:Name>{token}</tt:Name><tt:UseCount>1</tt:UseCount>\
+            <tt:Encoding>H264</tt:Encoding>\
+            <tt:Resolution><tt:Width>{width}</tt:Width><tt:Height>{height}</tt:Height></tt:Resolution>\
+            <tt:Quality>5</tt:Quality>\
+            <tt:RateControl><tt:FrameRateLimit>{fps}</tt:FrameRateLimit><tt:EncodingInterval>1</tt:EncodingInterval>\
+            <tt:BitrateLimit>{bitrate}</tt:BitrateLimit></tt:RateControl>\
+            <tt:H264><tt:GovLength>{gov}</tt:GovLength><tt:H264Profile>High</tt:H264Profile></tt:H264>\
+            <tt:Multicast><tt:Address><tt:Type>IPv4</tt:Type><tt:IPv4Address>0.0.0.0</tt:IPv4Address></tt:Address>\
+            <tt:Port>0</tt:Port><tt:TTL>1</tt:TTL><tt:AutoStart>false</tt:AutoStart></tt:Multicast>\
+            <tt:SessionTimeout>PT60S</tt:SessionTimeout>",
+            token = self.token,
+            width = self.width,
+            height = self.height,
+            fps = self.fps,
+            bitrate = self.bitrate,
+            gov = self.fps * 2,
+        )
+    }
+
+    fn xml(&self, element: &str, use_count: usize) -> String {
+        format!(
+            "<{element} token=\"{token}\" fixed=\"true\"><tt:Name>{token}</tt:Name>\
+            <tt:VideoSourceConfiguration token=\"{VIDEO_SOURCE}\">{}</tt:VideoSourceConfiguration>\
+            <tt:VideoEncoderConfiguration token=\"{token}\">{}</tt:VideoEncoderConfiguration>\
+            </{element}>",
+            self.video_source_configuration(use_count),
+            self.video_encoder_configuration(),
+            token = self.token,
+        )
+    }
+}
+
+pub(super) async fn handle(device: &Device<'_>, soap: &SoapRequest) -> AnyResult<HttpResponse> {
+    let body = match soap.action.as_str() {
+        "GetServiceCapabilities" => "<trt:GetServiceCapabilitiesResponse>\
+            <trt:Capabilities SnapshotUri=\"true\" Rotation=\"false\">\
+            <trt:ProfileCapabilities MaximumNumberOfProfiles=\"3\"/>\
+            <trt:StreamingCapabilities RTPMulticast=\"false\" RTP_TCP=\"true\" RTP_RTSP_TCP=\"true\"/>\
+            </trt:Capabilities></trt:GetServiceCapabilitiesResponse>"
+            .to_string(),
+        "GetProfiles" => {
+            let profiles = profiles(device).await;
+            let xml = profiles
+                .iter()
+                .map(|profile| profile.xml("trt:Profiles", profiles.len()))
+                .collect::<String>();
+            format!("<trt:GetProfilesResponse>{xml}</trt:GetProfilesResponse>")
+        }
+        "GetProfile" => {
+            let profiles = profiles(device).await;
+            let Some(profile) = find(&profiles, soap) else {
+                return Ok(no_profile());
+            };
+            format!(
+                "<trt:GetProfileResponse>{}</trt:GetProfileResponse>",
+                profile.xml("trt:Profile", profiles.len())
+            )
+        }
+        "GetStreamUri" => {
+            let profiles = profiles(device).await;
+            let Some(profile) = find(&profiles, soap) else {
+                return Ok(no_profile());
+            };
+            format!(
+                "<trt:GetStreamUriResponse>{}</trt:GetStreamUriResponse>",
+                media_uri(&format!("{}{}", device.rtsp_url, profile.path))
+            )
+        }
+        "GetSnapshotUri" => format!(
+            "<trt:GetSnapshotUriResponse>{}</trt:GetSnapshotUriResponse>",
+            media_uri(&format!("{}/snapshot.jpg", device.service_url))
+        ),
+        "GetVideoSources" => {
+            let profiles = profiles(device).await;
+            let Some(main) = profiles.first() else {
+                return Ok(no_profile());
+            };
+            format!(
+                "<trt:GetVideoSourcesResponse><trt:VideoSources token=\"{VIDEO_SOURCE}\">\
+                <tt:Framerate>{}</tt:Framerate>\
+                <tt:Resolution><tt:Width>{}</tt:Width><tt:Height>{}</tt:Height></tt:Resolution>\
+                </trt:VideoSources></trt:GetVideoSourcesResponse>",
+                main.fps, main.width, main.height
+            )
+        }
+        "GetVideoSourceConfigurations" => {
+            let profiles = profiles(device).await;
+            let Some(main) = profiles.first() else {
+                return Ok(no_profile());
+            };
+            format!(
+                "<trt:GetVideoSourceConfigurationsResponse>\
+                <trt:Configurations token=\"{VIDEO_SOURCE}\">{}</trt:Configurations>\
+                </trt:GetVideoSourceConfigurationsResponse>",
+                main.video_source_configuration(profiles.len())
+            )
+        }
+        "GetVideoEncoderConfigurations" => {
+            let xml = profiles(device)
+                .await
+                .iter()
+                .map(|profile| {
+                    format!(
+                        "<trt:Configurations token=\"{}\">{}</trt:Configurations>",
+                        profile.token,
+                        profile.video_encoder_configuration()
+                    )
+                })
+                .collect::<String>();
+            format!(
+                "<trt:GetVideoEncoderConfigurationsResponse>{xml}</trt:GetVideoEncoderConfigurationsResponse>"
+            )
+        }
+        // The camera's audio is part of the rtsp stream, there is no separate source
+        "GetAudioSources" => "<trt:GetAudioSourcesResponse/>".to_string(),
+        _ => return Ok(not_supported(soap)),
+    };
+    Ok(envelope(&body))
+}
+
+/// The profiles of the streams that neolink serves for the camera
+async fn profiles(device: &Device<'_>) -> Vec<Profile> {
+    let stream_info = device
+        .camera
+        .run_task(|cam| Box::pin(async move { Ok(cam.get_stream_info().await?) }))
+        .await;
+    let encode_tables = match stream_info {
+        Ok(stream_info) => stream_info
+            .stream_infos
+            .into_iter()
+            .flat_map(|stream_info| stream_info.encode_tables)
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            log::debug!("{}: Could not get the stream info: {e:?}", device.name);
+            vec![]
+        }
+    };
+
+    let mut kinds = device.camera_config.stream_kinds();
+    // Main first so that it is the default of clients
+    kinds.sort_by_key(|kind| match kind {
+        StreamKind::Main => 0,
+        StreamKind::Sub => 1,
+        StreamKind::Extern => 2,
+    });
+    kinds
+        .into_iter()
+        .map(|kind| {
+            let (token, table_name, fallback) = match kind {
+                StreamKind::Main => ("main", "mainStream", (1920, 1080, 25, 4096)),
+                StreamKind::Sub => ("sub", "subStream", (640, 360, 15, 512)),
+                StreamKind::Extern => ("extern", "externStream", (1280, 720, 25, 1024)),
+            };
+            // Cameras that do not report their streams still get sensible values
+            let (width, height, fps, bitrate) = encode_tables
+                .iter()
+                .find(|table| table.name == table_name)
+                .map(|table| {
+                    (
+                        table.resolution.width,
+                        table.resolution.height,
+                        table.default_framerate,
+                        table.default_bitrate,
+                    )
+                })
+                .unwrap_or(fallback);
+            Profile {
+                token,
+                path: device
+                    .camera_config
+                    .rtsp_paths(kind)
+                    .into_iter()
+                    .next()
+                    .unwrap_or_default(),
+                width,
+                height,
+                fps,
+                bitrate,
+            }
+        })
+        .collect()
+}
+
+fn find<'a>(profiles: &'a [Profile], soap: &SoapRequest) -> Option<&'a Profile> {
+    let token = soap.field("ProfileToken")?;
+    profiles.iter().find(|profile| profile.token == token)
+}
+
+fn media_uri(uri: &str) -> String {
+    format!(
+        "<trt:MediaUri><tt:Uri>{}</tt:Uri>\
+        <tt:InvalidAfterConnect>false</tt:InvalidAfterConnect>\
+        <tt:InvalidAfterReboot>false</tt:InvalidAfterReboot>\
+        <tt:Timeout>PT0S</tt:Timeout></trt:MediaUri>",
+        escape(uri)
+    )
+}
+
+fn no_profile() -> HttpResponse {
+    super::soap::fault(
+        400,
+        "Sender",
+        "ter:InvalidArgVal",
+        "The profile does not exist",
+    )
+}
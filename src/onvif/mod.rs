@@ -0,0 +1,241 @@
+//!
+//! # Neolink ONVIF
+//!
+//! This module serves each camera as an ONVIF Profile S device alongside the
+//! rtsp server so that NVRs which only speak ONVIF can add them
+//!
+//! It is enabled by adding an `[onvif]` table to the config
+//!
+//! ```toml
+//! [onvif]
+//! bind = "0.0.0.0"
+//! port = 8000
+//! ```
+//!
+//! # Endpoints
+//!
+//! Each camera is its own device at `http://<host>:<port>/onvif/<camera>/device_service`
+//!
+//! - `device_service`: Device information, capabilities, scopes and reboot
+//! - `media_service`: A profile for each stream with its rtsp and snapshot uris
+//! - `imaging_service`: The IR cut filter, backed by the IR lights of the camera
+//! - `event_service`: Pull-point subscriptions to the motion, AI and doorbell alarms
+//! - `snapshot.jpg`: A jpeg of the current frame
+//!
+//! When users are defined in the config the requests need a WS-Security
+//! UsernameToken of one of those users, the snapshot takes http basic auth.
+//! The camera's `permitted_users` are respected
+//!
+//! # Discovery
+//!
+//! The cameras also answer WS-Discovery probes on the LAN so that NVRs list
+//! them without being given an address. `discovery_bind` picks the interface
+//! and the `onvif_scopes` of a camera are added to the scopes it announces
+//!
+use anyhow::Context;
+use log::*;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+mod auth;
+mod device;
+mod discovery;
+mod events;
+mod imaging;
+mod media;
+mod soap;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    config::{CameraConfig, OnvifConfig},
+    http::{
+        server::{self, HttpRequest, HttpResponse},
+        snapshot,
+    },
+    AnyResult,
+};
+use auth::{Nonces, Refused};
+use events::Subscriptions;
+use soap::{fault, SoapRequest};
+
+/// Operations that clients make before they know the time to make the digest
+const PRE_AUTH: &[&str] = &[
+    "GetSystemDateAndTime",
+    "GetCapabilities",
+    "GetServices",
+    "GetServiceCapabilities",
+    "GetWsdlUrl",
+    "GetHostname",
+];
+
+/// What the services need to know about the camera of a request
+pub(super) struct Device<'a> {
+    pub(super) name: &'a str,
+    pub(super) camera: NeoInstance,
+    pub(super) camera_config: &'a CameraConfig,
+    /// `http://<host>:<port>/onvif/<camera>`
+    pub(super) service_url: String,
+    /// `rtsp://<host>:<port>`
+    pub(super) rtsp_url: String,
+}
+
+/// Run the onvif server until cancelled
+pub(crate) async fn main(
+    onvif_config: OnvifConfig,
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let listener = TcpListener::bind((onvif_config.bind_addr.as_str(), onvif_config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind onvif server to {}:{}",
+                onvif_config.bind_addr, onvif_config.port
+            )
+        })?;
+    info!(
+        "Starting ONVIF Server at {}:{}",
+        onvif_config.bind_addr, onvif_config.port
+    );
+
+    let discovering = {
+        let onvif_config = onvif_config.clone();
+        let reactor = reactor.clone();
+        let cancel = cancel.clone();
+        async move {
+            if onvif_config.discovery {
+                // The cameras can still be added by address without discovery
+                if let Err(e) = discovery::main(onvif_config, reactor, cancel).await {
+                    error!("WS-Discovery failed: {e:?}");
+                }
+            }
+        }
+    };
+    let subscriptions = Subscriptions::default();
+    let nonces = Nonces::default();
+    let server = server::serve(listener, cancel, move |request| {
+        let reactor = reactor.clone();
+        let onvif_config = onvif_config.clone();
+        let subscriptions = subscriptions.clone();
+        let nonces = nonces.clone();
+        async move {
+            match handle(request, &reactor, &onvif_config, &subscriptions, &nonces).await {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("ONVIF request failed: {e:?}");
+                    fault(500, "Receiver", "ter:Action", &format!("{e}"))
+                }
+            }
+        }
+    });
+    let ((), result) = tokio::join!(discovering, server);
+    result
+}
+
+async fn handle(
+    request: HttpRequest,
+    reactor: &NeoReactor,
+    onvif_config: &OnvifConfig,
+    subscriptions: &Subscriptions,
+    nonces: &Nonces,
+) -> AnyResult<HttpResponse> {
+    let config = reactor.config().await?.borrow().clone();
+    let path = request.path.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    let (name, service, subscription) = match path.as_slice() {
+        ["onvif", name, service] => (*name, *service, None),
+        ["onvif", name, "subscription", id] => (*name, "subscription", Some(*id)),
+        _ => return Ok(HttpResponse::not_found()),
+    };
+    let Some(camera_config) = config
+        .cameras
+        .iter()
+        .find(|cam| cam.enabled && cam.onvif && cam.name == name)
+    else {
+        return Ok(HttpResponse::not_found());
+    };
+    let realm = config.auth_realm.as_deref().unwrap_or("Neolink");
+
+    if service == "snapshot.jpg" {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Ok(HttpResponse::text(405, "Method Not Allowed"));
+        }
+        match auth::check_basic(request.header("authorization"), &config, camera_config) {
+            Ok(()) => {}
+            Err(Refused::Unauthorized) => return Ok(HttpResponse::unauthorized(realm)),
+            Err(Refused::Forbidden) => return Ok(HttpResponse::text(403, "Forbidden")),
+        }
+        let camera = reactor.get(name).await?;
+        return Ok(HttpResponse::jpeg(snapshot::snapshot(&camera).await?));
+    }
+
+    if request.method != "POST" {
+        return Ok(HttpResponse::text(405, "Method Not Allowed"));
+    }
+    let soap = match SoapRequest::parse(&request.body) {
+        Ok(soap) => soap,
+        Err(e) => {
+            debug!("Bad SOAP request: {e:?}");
+            return Ok(fault(
+                400,
+                "Sender",
+                "ter:WellFormed",
+                "Invalid SOAP request",
+            ));
+        }
+    };
+    if !PRE_AUTH.contains(&soap.action.as_str()) {
+        if let Err(refused) = auth::check_token(soap.token.as_ref(), nonces, &config, camera_config)
+        {
+            let reason = match refused {
+                Refused::Unauthorized => "The user or password is not valid",
+                Refused::Forbidden => "The user is not permitted to use this camera",
+            };
+            return Ok(fault(400, "Sender", "ter:NotAuthorized", reason));
+        }
+    }
+
+    let host = host(&request, onvif_config);
+    let device = Device {
+        name,
+        camera: reactor.get(name).await?,
+        camera_config,
+        service_url: format!("http://{host}:{}/onvif/{name}", onvif_config.port),
+        rtsp_url: format!("rtsp://{host}:{}", config.bind_port),
+    };
+    debug!("{name}: ONVIF {service} {}", soap.action);
+    match (service, subscription) {
+        ("device_service", _) => device::handle(&device, &soap).await,
+        ("media_service", _) => media::handle(&device, &soap).await,
+        ("imaging_service", _) => imaging::handle(&device, &soap).await,
+        ("event_service", _) => events::handle(&device, &soap, subscriptions).await,
+        ("subscription", Some(id)) => {
+            events::handle_subscription(&device, &soap, subscriptions, id).await
+        }
+        _ => Ok(HttpResponse::not_found()),
+    }
+}
+
+/// The host for the uris in the replies
+fn host(request: &HttpRequest, onvif_config: &OnvifConfig) -> String {
+    if let Some(host) = onvif_config.host.as_ref() {
+        return host.clone();
+    }
+    match request.header("host") {
+        // Strip the port but not the end of an IPv6 address
+        Some(host) => match host.rsplit_once(':') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host.to_string(),
+            _ => host.to_string(),
+        },
+        None => onvif_config.bind_addr.clone(),
+    }
+}
+
+/// The reply to an operation that is not supported
+fn not_supported(soap: &SoapRequest) -> HttpResponse {
+    fault(
+        400,
+        "Sender",
+        "ter:ActionNotSupported",
+        &format!("{} is not supported", soap.action),
+    )
+}
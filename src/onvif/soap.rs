@@ -0,0 +1,158 @@
+//! Reads SOAP requests and writes SOAP replies
+//!
+//! Only what the ONVIF services need is read from the request: the name of
+//! the operation, the text of the elements in it and the WS-Security
+//! UsernameToken. Namespaces are ignored, elements are matched by local name
+use anyhow::anyhow;
+use quick_xml::{events::Event, Reader};
+use std::collections::HashMap;
+
+use crate::{http::server::HttpResponse, AnyResult};
+
+const NAMESPACES: &str = concat!(
+    r#"xmlns:s="http://www.w3.org/2003/05/soap-envelope" "#,
+    r#"xmlns:tt="http://www.onvif.org/ver10/schema" "#,
+    r#"xmlns:tds="http://www.onvif.org/ver10/device/wsdl" "#,
+    r#"xmlns:trt="http://www.onvif.org/ver10/media/wsdl" "#,
+    r#"xmlns:timg="http://www.onvif.org/ver20/imaging/wsdl" "#,
+    r#"xmlns:tev="http://www.onvif.org/ver10/events/wsdl" "#,
+    r#"xmlns:wsnt="http://docs.oasis-open.org/wsn/b-2" "#,
+    r#"xmlns:wstop="http://docs.oasis-open.org/wsn/t-1" "#,
+    r#"xmlns:wsa="http://www.w3.org/2005/08/addressing" "#,
+    r#"xmlns:tns1="http://www.onvif.org/ver10/topics" "#,
+    r#"xmlns:xs="http://www.w3.org/2001/XMLSchema" "#,
+    r#"xmlns:ter="http://www.onvif.org/ver10/error""#,
+);
+
+/// The WS-Security UsernameToken of a request
+pub(super) struct UsernameToken {
+    pub(super) username: String,
+    pub(super) password: String,
+    /// True when the password is `Base64(SHA1(nonce + created + password))`
+    pub(super) digest: bool,
+    pub(super) nonce: Option<String>,
+    pub(super) created: Option<String>,
+}
+
+pub(super) struct SoapRequest {
+    /// Local name of the first element in the body such as `GetProfiles`
+    pub(super) action: String,
+    /// Text of the elements in the body by local name, the first one wins
+    fields: HashMap<String, String>,
+    /// Text of the elements in the header by local name, such as the `MessageID`
+    header: HashMap<String, String>,
+    pub(super) token: Option<UsernameToken>,
+}
+
+impl SoapRequest {
+    pub(super) fn parse(body: &[u8]) -> AnyResult<Self> {
+        let mut reader = Reader::from_reader(body);
+        reader.config_mut().trim_text(true);
+
+        let mut stack: Vec<String> = vec![];
+        let mut in_body = None;
+        let mut action = None;
+        let mut fields = HashMap::new();
+        let mut header = HashMap::new();
+        let mut password_type = None;
+        let mut buf = vec![];
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(e) => {
+                    let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                    if in_body.is_some() {
+                        if action.is_none() {
+                            action = Some(name.clone());
+                        }
+                    } else if name == "Body" {
+                        in_body = Some(stack.len());
+                    } else if name == "Password" {
+                        for attr in e.attributes().flatten() {
+                            if attr.key.local_name().as_ref() == b"Type" {
+                                password_type = Some(attr.unescape_value()?.to_string());
+                            }
+                        }
+                    }
+                    stack.push(name);
+                }
+                Event::Empty(e) if in_body.is_some() && action.is_none() => {
+                    action = Some(String::from_utf8_lossy(e.local_name().as_ref()).to_string());
+                }
+                Event::Text(e) => {
+                    if let Some(name) = stack.last() {
+                        let text = e.unescape()?.to_string();
+                        let target = if in_body.is_some() {
+                            &mut fields
+                        } else {
+                            &mut header
+                        };
+                        target.entry(name.clone()).or_insert(text);
+                    }
+                }
+                Event::End(_) => {
+                    stack.pop();
+                    if in_body == Some(stack.len()) {
+                        break;
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        let action = action.ok_or_else(|| anyhow!("The SOAP body is empty"))?;
+        let token = header.remove("Username").map(|username| UsernameToken {
+            username,
+            password: header.remove("Password").unwrap_or_default(),
+            digest: password_type
+                .map(|kind| kind.ends_with("#PasswordDigest"))
+                .unwrap_or(false),
+            nonce: header.remove("Nonce"),
+            created: header.remove("Created"),
+        });
+        Ok(Self {
+            action,
+            fields,
+            header,
+            token,
+        })
+    }
+
+    /// The text of an element in the body by its local name
+    pub(super) fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|s| s.as_str())
+    }
+
+    /// The text of an element in the header by its local name
+    pub(super) fn header_field(&self, name: &str) -> Option<&str> {
+        self.header.get(name).map(|s| s.as_str())
+    }
+}
+
+/// Wrap the body of a reply in a SOAP envelope
+pub(super) fn envelope(body: &str) -> HttpResponse {
+    HttpResponse::new(
+        200,
+        "application/soap+xml; charset=utf-8",
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><s:Envelope {NAMESPACES}><s:Body>{body}</s:Body></s:Envelope>"#
+        )
+        .into_bytes(),
+    )
+}
+
+/// A SOAP fault, `code` is `Sender` or `Receiver` and `subcode` an ONVIF error such as `ter:ActionNotSupported`
+pub(super) fn fault(status: u16, code: &str, subcode: &str, reason: &str) -> HttpResponse {
+    let mut response = envelope(&format!(
+        r#"<s:Fault><s:Code><s:Value>s:{code}</s:Value><s:Subcode><s:Value>{subcode}</s:Value></s:Subcode></s:Code><s:Reason><s:Text xml:lang="en">{}</s:Text></s:Reason></s:Fault>"#,
+        escape(reason)
+    ));
+    response.status = status;
+    response
+}
+
+/// Escape text for use in the xml of a reply
+pub(super) fn escape(text: &str) -> String {
+    quick_xml::escape::escape(text).to_string()
+}
@@ -0,0 +1,196 @@
+//!
+//! # Neolink Outputs
+//!
+//! This module pushes the streams of the cameras to other servers alongside
+//! the rtsp server
+//!
+//! - `[cameras.rtmp]`: To an RTMP server such as YouTube, Twitch or nginx-rtmp
+//! - `[cameras.srt]`: Over SRT as a listener or a caller
+//! - `[cameras.udp]`: As MPEG-TS over UDP to a unicast or multicast address
+//!
+//! ```toml
+//! [[cameras]]
+//! name = "Garden"
+//!   [cameras.rtmp]
+//!   url = "rtmp://a.rtmp.youtube.com/live2/{key}"
+//!   key = "xxxx-xxxx-xxxx-xxxx"
+//!   stream = "main"
+//!   reconnect_delay = 10
+//!
+//!   [cameras.srt]
+//!   enabled = true
+//!   mode = "listener"
+//!   port = 8890
+//!
+//!   [cameras.udp]
+//!   address = "239.0.0.1"
+//!   port = 5000
+//!   ttl = 1
+//! ```
+//!
+//! Each output is retried after its `reconnect_delay` seconds whenever it fails
+//!
+use anyhow::anyhow;
+use log::*;
+use neolink_core::bc_protocol::StreamKind;
+use std::collections::HashMap;
+use tokio::{
+    task::JoinSet,
+    time::{sleep, Duration},
+};
+use tokio_util::sync::CancellationToken;
+
+mod push;
+mod rtmp;
+mod srt;
+mod udp;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    config::{CameraConfig, Config, RtmpConfig, SrtConfig, UdpConfig},
+    AnyResult,
+};
+use push::Target;
+
+/// An output of a camera
+#[derive(Clone, PartialEq, Eq)]
+enum Output {
+    Rtmp(RtmpConfig),
+    Srt(SrtConfig),
+    Udp(UdpConfig),
+}
+
+impl Output {
+    /// The outputs that are enabled in the config of a camera
+    fn from_config(cam_config: &CameraConfig) -> Vec<Self> {
+        let mut outputs = vec![];
+        if cam_config.rtmp.url.is_some() {
+            outputs.push(Self::Rtmp(cam_config.rtmp.clone()));
+        }
+        if cam_config.srt.enabled {
+            outputs.push(Self::Srt(cam_config.srt.clone()));
+        }
+        if cam_config.udp.address.is_some() {
+            outputs.push(Self::Udp(cam_config.udp.clone()));
+        }
+        outputs
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Rtmp(_) => "rtmp",
+            Self::Srt(_) => "srt",
+            Self::Udp(_) => "udp",
+        }
+    }
+
+    fn stream(&self) -> &str {
+        match self {
+            Self::Rtmp(config) => &config.stream,
+            Self::Srt(config) => &config.stream,
+            Self::Udp(config) => &config.stream,
+        }
+    }
+
+    fn reconnect_delay(&self) -> Duration {
+        Duration::from_secs(match self {
+            Self::Rtmp(config) => config.reconnect_delay,
+            Self::Srt(config) => config.reconnect_delay,
+            Self::Udp(config) => config.reconnect_delay,
+        })
+    }
+
+    fn target(&self, name: &str) -> AnyResult<Target> {
+        match self {
+            Self::Rtmp(config) => rtmp::target(name, config),
+            Self::Srt(config) => Ok(srt::target(config)),
+            Self::Udp(config) => udp::target(name, config),
+        }
+    }
+}
+
+/// Run the outputs of the cameras until cancelled
+///
+/// The outputs are restarted as their settings change
+pub(crate) async fn main(reactor: NeoReactor, cancel: CancellationToken) -> AnyResult<()> {
+    let mut config = reactor.config().await?;
+    let mut running: HashMap<(String, &'static str), (Output, CancellationToken)> = HashMap::new();
+    let mut set = JoinSet::new();
+    loop {
+        let wanted = outputs(&config.borrow_and_update());
+        running.retain(|key, (output, token)| {
+            if wanted.get(key) == Some(output) {
+                true
+            } else {
+                token.cancel();
+                false
+            }
+        });
+        for (key, output) in wanted.into_iter() {
+            if running.contains_key(&key) {
+                continue;
+            }
+            let token = cancel.child_token();
+            running.insert(key.clone(), (output.clone(), token.clone()));
+            let thread_reactor = reactor.clone();
+            set.spawn(async move {
+                let name = key.0;
+                tokio::select! {
+                    _ = token.cancelled() => AnyResult::Ok(()),
+                    v = async {
+                        let camera = thread_reactor.get(&name).await?;
+                        output_main(camera, &name, &output).await
+                    } => v,
+                }
+            });
+        }
+
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            v = config.changed() => v?,
+            Some(joined) = set.join_next() => {
+                if let Ok(Err(e)) = joined {
+                    error!("Output failed: {e:?}");
+                }
+            }
+        }
+    }
+    set.shutdown().await;
+    Ok(())
+}
+
+/// The outputs of the enabled cameras
+fn outputs(config: &Config) -> HashMap<(String, &'static str), Output> {
+    config
+        .cameras
+        .iter()
+        .filter(|cam_config| cam_config.enabled)
+        .flat_map(|cam_config| {
+            Output::from_config(cam_config)
+                .into_iter()
+                .map(move |output| ((cam_config.name.clone(), output.label()), output))
+        })
+        .collect()
+}
+
+/// Run one output of a camera, reconnecting when it fails
+async fn output_main(camera: NeoInstance, name: &str, output: &Output) -> AnyResult<()> {
+    let kind = match output.stream() {
+        "main" => StreamKind::Main,
+        "sub" => StreamKind::Sub,
+        "extern" => StreamKind::Extern,
+        other => return Err(anyhow!("Unknown stream {other}")),
+    };
+    let target = output.target(name)?;
+    loop {
+        info!(
+            "{name}: Sending the {kind:?} stream to {}",
+            target.description
+        );
+        match push::push(&camera, kind, &target).await {
+            Ok(()) => info!("{name}: Sending to {} stopped", target.description),
+            Err(e) => warn!("{name}: Sending to {} failed: {e:?}", target.description),
+        }
+        sleep(output.reconnect_delay()).await;
+    }
+}
@@ -0,0 +1,196 @@
+//! Muxes a stream and sends it with the sink of an output
+use anyhow::{anyhow, Context};
+use futures::stream::StreamExt;
+use gstreamer::{
+    parse::launch_full, prelude::*, MessageType, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use neolink_core::bc_protocol::StreamKind;
+use tokio::time::{interval, timeout, Duration};
+use tokio_stream::wrappers::BroadcastStream;
+
+use crate::{
+    common::{get_source, push_frame, AudFormat, NeoInstance, StampedData, VidFormat},
+    AnyResult,
+};
+
+/// Where and how a stream is sent
+pub(super) struct Target {
+    /// The muxer element
+    pub(super) mux: &'static str,
+    /// If the muxer can take H265, else it is re-encoded to H264
+    pub(super) h265: bool,
+    /// The sink element
+    pub(super) sink: &'static str,
+    /// Properties of the sink, set after parsing so that they are not escaped
+    pub(super) properties: Vec<(&'static str, String)>,
+    /// The target in the logs, without any secrets
+    pub(super) description: String,
+}
+
+/// Push the stream to the target until the stream or the push fails
+pub(super) async fn push(camera: &NeoInstance, kind: StreamKind, target: &Target) -> AnyResult<()> {
+    // Holding the instance keeps the stream active while pushing
+    let stream = camera.stream(kind).await?;
+    let mut stream_config = stream.config.clone();
+    let vid_format = timeout(
+        Duration::from_secs(15),
+        stream_config.wait_for(|config| config.vid_ready()),
+    )
+    .await
+    .with_context(|| "Timed out waiting for the stream")??
+    .vid_format;
+    // Audio is optional so only wait a short while for it
+    let _ = timeout(
+        Duration::from_secs(2),
+        stream_config.wait_for(|config| config.aud_ready()),
+    )
+    .await;
+    let aud_format = stream_config.borrow().aud_format;
+
+    let mut pipeline = OutputPipeline::new(vid_format, aud_format, target)?;
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+    let mut check = interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            frame = vid.next() => {
+                let Some(frame) = frame else {
+                    return Ok(());
+                };
+                // Lagged frames are skipped, the server recovers at the next keyframe
+                if let Ok(frame) = frame {
+                    pipeline.push_video(&frame)?;
+                }
+            }
+            Some(frame) = aud.next() => {
+                if let Ok(frame) = frame {
+                    pipeline.push_audio(&frame)?;
+                }
+            }
+            _ = check.tick() => pipeline.check()?,
+        }
+    }
+}
+
+struct OutputPipeline {
+    pipeline: Pipeline,
+    vid_source: AppSrc,
+    aud_source: Option<AppSrc>,
+    start: Option<Duration>,
+}
+
+impl OutputPipeline {
+    fn new(vid_format: VidFormat, aud_format: AudFormat, target: &Target) -> AnyResult<Self> {
+        gstreamer::init()
+            .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+        let video = match vid_format {
+            VidFormat::H264 => "h264parse",
+            VidFormat::H265 if target.h265 => "h265parse",
+            VidFormat::H265 => {
+                "h265parse ! avdec_h265 ! videoconvert \
+                ! x264enc tune=zerolatency speed-preset=veryfast key-int-max=60 \
+                ! h264parse"
+            }
+            VidFormat::None => return Err(anyhow!("Video format is not yet known")),
+        };
+        // The caps of the source and how to get AAC from it
+        let audio = match aud_format {
+            AudFormat::Aac => Some((String::new(), "aacparse")),
+            AudFormat::Adpcm(block_size) => Some((
+                format!(
+                    " caps=\"audio/x-adpcm,layout=dvi,block_align={block_size},channels=1,rate=8000\""
+                ),
+                "adpcmdec ! audioconvert ! audioresample ! avenc_aac ! aacparse",
+            )),
+            AudFormat::None => None,
+        };
+        let mut launch_str = format!(
+            "{} name=themux \
+            ! {} name=thesink \
+            appsrc name=thevidsource is-live=true format=time \
+            ! {video} \
+            ! queue \
+            ! themux.",
+            target.mux, target.sink
+        );
+        if let Some((caps, audio)) = audio.as_ref() {
+            launch_str.push_str(&format!(
+                " appsrc name=theaudsource is-live=true format=time{caps} \
+                ! {audio} \
+                ! queue \
+                ! themux."
+            ));
+        }
+        let pipeline = launch_full(&launch_str, None, ParseFlags::empty()).context(
+            "Unable to load the output pipeline, ensure all gstramer plugins are installed",
+        )?;
+        let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+        let sink = pipeline
+            .by_name("thesink")
+            .ok_or_else(|| anyhow!("Cannot find the sink in the pipeline"))?;
+        for (name, value) in target.properties.iter() {
+            sink.set_property_from_str(name, value);
+        }
+        let vid_source = get_source(&pipeline, "thevidsource")?;
+        let aud_source = if audio.is_some() {
+            Some(get_source(&pipeline, "theaudsource")?)
+        } else {
+            None
+        };
+        pipeline.set_state(State::Playing)?;
+        Ok(Self {
+            pipeline,
+            vid_source,
+            aud_source,
+            start: None,
+        })
+    }
+
+    fn push_video(&mut self, frame: &StampedData) -> AnyResult<()> {
+        // The server can only start decoding at a keyframe
+        if self.start.is_none() && !frame.keyframe {
+            return Ok(());
+        }
+        let start = *self.start.get_or_insert(frame.ts);
+        push_frame(&self.vid_source, start, frame)
+    }
+
+    fn push_audio(&mut self, frame: &StampedData) -> AnyResult<()> {
+        // Audio before the first video frame has nothing to play with
+        if let (Some(source), Some(start)) = (self.aud_source.as_ref(), self.start) {
+            if frame.ts >= start {
+                push_frame(source, start, frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Errors if the connection to the server was lost
+    fn check(&self) -> AnyResult<()> {
+        let bus = self
+            .pipeline
+            .bus()
+            .expect("Pipeline without bus. Shouldn't happen!");
+        while let Some(msg) = bus.pop_filtered(&[MessageType::Error, MessageType::Eos]) {
+            match msg.view() {
+                MessageView::Error(err) => {
+                    return Err(anyhow!("Error from gstreamer: {}", err.error()));
+                }
+                MessageView::Eos(..) => return Err(anyhow!("The server ended the stream")),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OutputPipeline {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipeline.set_state(State::Null) {
+            log::warn!("Error in gstreamer when setting state to Null: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Pushes a stream to an RTMP server with `rtmp2sink`
+//!
+//! In the url `{key}` is replaced by the key, `{camera}` by the name of the
+//! camera and `{stream}` by the stream
+use anyhow::anyhow;
+
+use super::push::Target;
+use crate::{config::RtmpConfig, AnyResult};
+
+pub(super) fn target(name: &str, rtmp_config: &RtmpConfig) -> AnyResult<Target> {
+    let url = rtmp_config
+        .url
+        .as_ref()
+        .ok_or_else(|| anyhow!("{name}: No rtmp url"))?;
+    if !(url.starts_with("rtmp://") || url.starts_with("rtmps://")) {
+        return Err(anyhow!(
+            "{name}: The rtmp url must start with rtmp:// or rtmps://"
+        ));
+    }
+    if url.contains("{key}") && rtmp_config.key.is_none() {
+        return Err(anyhow!(
+            "{name}: The rtmp url has a {{key}} but no key is set"
+        ));
+    }
+    let location = url
+        .replace("{key}", rtmp_config.key.as_deref().unwrap_or_default())
+        .replace("{camera}", name)
+        .replace("{stream}", &rtmp_config.stream);
+    // The key is kept out of the logs
+    let description = match rtmp_config.key.as_deref() {
+        Some(key) if !key.is_empty() => location.replace(key, "****"),
+        _ => location.clone(),
+    };
+    Ok(Target {
+        // Flv only has H264
+        mux: "flvmux streamable=true",
+        h265: false,
+        sink: "rtmp2sink",
+        properties: vec![("location", location)],
+        description,
+    })
+}
@@ -0,0 +1,40 @@
+//! Sends a stream as MPEG-TS over SRT with `srtsink`
+//!
+//! As a listener any number of callers can connect to neolink, as a caller
+//! neolink connects to the remote listener
+use super::push::Target;
+use crate::config::SrtConfig;
+
+pub(super) fn target(srt_config: &SrtConfig) -> Target {
+    let mut uri = format!(
+        "srt://{}:{}?mode={}&latency={}",
+        srt_config.address, srt_config.port, srt_config.mode, srt_config.latency
+    );
+    let description = uri.clone();
+    if let Some(stream_id) = srt_config.stream_id.as_ref() {
+        uri.push_str(&format!("&streamid={}", url_encode(stream_id)));
+    }
+    if let Some(passphrase) = srt_config.passphrase.as_ref() {
+        uri.push_str(&format!("&passphrase={}", url_encode(passphrase)));
+    }
+    Target {
+        mux: "mpegtsmux alignment=7",
+        h265: true,
+        // Without a caller the listener drops the stream rather than blocking it
+        sink: "srtsink wait-for-connection=false",
+        properties: vec![("uri", uri)],
+        description,
+    }
+}
+
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
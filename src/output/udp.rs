@@ -0,0 +1,28 @@
+//! Sends a stream as MPEG-TS over UDP with `udpsink`
+//!
+//! This is for set-top boxes and headends that take a plain `udp://` stream,
+//! there is no retransmission so it is best kept on the local network
+use anyhow::anyhow;
+
+use super::push::Target;
+use crate::{config::UdpConfig, AnyResult};
+
+pub(super) fn target(name: &str, udp_config: &UdpConfig) -> AnyResult<Target> {
+    let address = udp_config
+        .address
+        .as_ref()
+        .ok_or_else(|| anyhow!("{name}: No udp address"))?;
+    Ok(Target {
+        // Seven TS packets fill a 1316 byte datagram, what most receivers expect
+        mux: "mpegtsmux alignment=7",
+        h265: true,
+        sink: "udpsink",
+        properties: vec![
+            ("host", address.clone()),
+            ("port", udp_config.port.to_string()),
+            ("ttl", udp_config.ttl.to_string()),
+            ("ttl-mc", udp_config.ttl.to_string()),
+        ],
+        description: format!("udp://{address}:{}", udp_config.port),
+    })
+}
@@ -0,0 +1,32 @@
+use clap::Parser;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The pcap-decode command will print the BC messages of a packet capture
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The pcap file, or a file of raw BC bytes from one side of a TCP connection
+    #[arg(value_parser = PathBuf::from_str)]
+    pub file: PathBuf,
+    /// The IP of the camera. If not given TCP connections on `--port` are decoded and the
+    /// camera of UDP connections is found from their discovery
+    #[arg(long)]
+    pub camera: Option<IpAddr>,
+    /// The TCP port of the camera
+    #[arg(long, default_value = "9000")]
+    pub port: u16,
+    /// The username the client logged in with, needed to decrypt AES
+    #[arg(short, long, default_value = "admin")]
+    pub username: String,
+    /// The password the client logged in with, needed to decrypt AES
+    #[arg(short, long)]
+    pub password: Option<String>,
+    /// For a raw file: the bytes were sent to the camera rather than from it
+    #[arg(long)]
+    pub to_camera: bool,
+    /// A directory to write each TCP or UDP conversation of a pcap to as a session file,
+    /// which can be replayed in tests
+    #[arg(long, value_parser = PathBuf::from_str)]
+    pub record: Option<PathBuf>,
+}
@@ -0,0 +1,225 @@
+///
+/// # Neolink Pcap Decode
+///
+/// This module handles the pcap-decode subcommand
+///
+/// The subcommand reads a packet capture of the traffic between a client and
+/// a camera and prints each BC message with its xml. TCP connections are put
+/// back in order and UDP connections are decoded from their data packets.
+/// It does not need a config file
+///
+/// # Usage
+///
+/// ```bash
+/// neolink pcap-decode capture.pcap --password=secret
+/// neolink pcap-decode capture.pcap --camera=192.168.1.10 --password=secret
+/// neolink pcap-decode stream.bin --to-camera
+/// neolink pcap-decode capture.pcap --password=secret --record=sessions/
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::{
+    bc::{model::Bc, session::BcRecorder, sniff::BcSniffer},
+    bcudp::model::BcUdp,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::BufWriter,
+    net::{IpAddr, SocketAddr},
+};
+
+mod cmdline;
+mod pcap;
+
+use crate::utils::describe_bc;
+pub(crate) use cmdline::Opt;
+use pcap::{Packet, Transport};
+
+/// A TCP or UDP conversation between a client and the camera
+struct Conversation {
+    sniffer: BcSniffer,
+    camera: SocketAddr,
+    /// The next TCP sequence number expected from each side
+    next_seq: HashMap<SocketAddr, u32>,
+    /// Writes the session file with `--record`
+    recorder: Option<BcRecorder<BufWriter<File>>>,
+}
+
+/// Entry point for the pcap-decode subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt) -> Result<()> {
+    let data = tokio::fs::read(&opt.file)
+        .await
+        .with_context(|| format!("Could not read {:?}", opt.file))?;
+
+    if !pcap::is_pcap(&data) && data.get(..4) != Some(&[0x0a, 0x0d, 0x0d, 0x0a]) {
+        let mut sniffer = BcSniffer::new(&opt.username, opt.password.as_deref());
+        let msgs = if opt.to_camera {
+            sniffer.to_camera(&data)
+        } else {
+            sniffer.from_camera(&data)
+        };
+        for msg in msgs {
+            print_msg("", &msg);
+        }
+        return Ok(());
+    }
+
+    let mut conversations: HashMap<(SocketAddr, SocketAddr), Conversation> = HashMap::new();
+    let mut udp_cameras: HashSet<IpAddr> = opt.camera.into_iter().collect();
+    for packet in pcap::packets(&data)? {
+        let key = if packet.src < packet.dst {
+            (packet.src, packet.dst)
+        } else {
+            (packet.dst, packet.src)
+        };
+        let prefix = format!("[{:.3}] {} -> {}", packet.time, packet.src, packet.dst);
+        match packet.transport {
+            Transport::Tcp { seq, syn } => {
+                let is_bc = match opt.camera {
+                    Some(camera) => packet.src.ip() == camera || packet.dst.ip() == camera,
+                    None => packet.src.port() == opt.port || packet.dst.port() == opt.port,
+                };
+                if !is_bc {
+                    continue;
+                }
+                let conversation = conversations.entry(key).or_insert_with(|| Conversation {
+                    sniffer: BcSniffer::new(&opt.username, opt.password.as_deref()),
+                    camera: camera_of(&opt, &packet),
+                    next_seq: Default::default(),
+                    recorder: None,
+                });
+                let Some(payload) = in_order(conversation, &packet, seq, syn) else {
+                    continue;
+                };
+                let to_camera = packet.dst == conversation.camera;
+                let msgs = if to_camera {
+                    conversation.sniffer.to_camera(payload)
+                } else {
+                    conversation.sniffer.from_camera(payload)
+                };
+                for msg in msgs {
+                    print_msg(&prefix, &msg);
+                    record(&opt, conversation, &packet, to_camera, msg)?;
+                }
+            }
+            Transport::Udp => {
+                // Other UDP traffic is expected in a capture so it is skipped quietly
+                let Ok(udp) = BcUdp::from_datagram(&packet.payload) else {
+                    continue;
+                };
+                match udp {
+                    BcUdp::Discovery(discovery) => {
+                        // The camera's discovery messages are all `D2C_*`
+                        if format!("{:?}", discovery.payload).starts_with("D2c") {
+                            udp_cameras.insert(packet.src.ip());
+                        }
+                        match quick_xml::se::to_string(&discovery.payload) {
+                            Ok(xml) => println!("{prefix} UDP discovery\n{xml}"),
+                            Err(e) => println!("{prefix} UDP discovery: {e}"),
+                        }
+                    }
+                    BcUdp::Ack(_) => {}
+                    BcUdp::Data(udp_data) => {
+                        let to_camera = udp_cameras.contains(&packet.dst.ip());
+                        let conversation =
+                            conversations.entry(key).or_insert_with(|| Conversation {
+                                sniffer: BcSniffer::new(&opt.username, opt.password.as_deref()),
+                                camera: if to_camera { packet.dst } else { packet.src },
+                                next_seq: Default::default(),
+                                recorder: None,
+                            });
+                        let to_camera = packet.dst == conversation.camera;
+                        let msgs = if to_camera {
+                            conversation.sniffer.udp_to_camera(&udp_data)
+                        } else {
+                            conversation.sniffer.udp_from_camera(&udp_data)
+                        };
+                        for msg in msgs {
+                            print_msg(&prefix, &msg);
+                            record(&opt, conversation, &packet, to_camera, msg)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Which side of a new TCP conversation is the camera
+fn camera_of(opt: &Opt, packet: &Packet) -> SocketAddr {
+    match opt.camera {
+        Some(camera) if packet.src.ip() == camera => packet.src,
+        Some(_) => packet.dst,
+        None if packet.src.port() == opt.port => packet.src,
+        None => packet.dst,
+    }
+}
+
+/// The part of a TCP segment that has not been seen before
+///
+/// Resent data is removed, a gap from a packet missing in the capture is
+/// reported and skipped over
+fn in_order<'a>(
+    conversation: &mut Conversation,
+    packet: &'a Packet,
+    seq: u32,
+    syn: bool,
+) -> Option<&'a [u8]> {
+    let next = conversation.next_seq.entry(packet.src).or_insert(seq);
+    if syn {
+        *next = seq.wrapping_add(1);
+        return None;
+    }
+    if packet.payload.is_empty() {
+        return None;
+    }
+    let offset = next.wrapping_sub(seq) as i32;
+    let payload = &packet.payload[..];
+    let payload = if offset < 0 {
+        println!(
+            "{} -> {}: {} bytes are missing from the capture",
+            packet.src, packet.dst, -offset
+        );
+        payload
+    } else {
+        payload.get(offset as usize..).filter(|p| !p.is_empty())?
+    };
+    *next = seq.wrapping_add(packet.payload.len() as u32);
+    Some(payload)
+}
+
+/// Add a message to the session file of its conversation with `--record`
+fn record(
+    opt: &Opt,
+    conversation: &mut Conversation,
+    packet: &Packet,
+    to_camera: bool,
+    msg: Result<Bc, neolink_core::Error>,
+) -> Result<()> {
+    let (Some(dir), Ok(msg)) = (&opt.record, msg) else {
+        return Ok(());
+    };
+    if conversation.recorder.is_none() {
+        // Named after the client so that each conversation has its own file
+        let client = if to_camera { packet.src } else { packet.dst };
+        let path = dir.join(format!("{}-{}.bcsession", client.ip(), client.port()));
+        let file = File::create(&path).with_context(|| format!("Could not create {path:?}"))?;
+        conversation.recorder = Some(BcRecorder::new(BufWriter::new(file))?);
+    }
+    if let Some(recorder) = conversation.recorder.as_mut() {
+        recorder.record(to_camera, msg)?;
+    }
+    Ok(())
+}
+
+fn print_msg(prefix: &str, msg: &Result<Bc, neolink_core::Error>) {
+    match msg {
+        Ok(msg) => println!("{prefix} {}", describe_bc(msg)),
+        Err(e) => println!("{prefix} could not be decoded: {e}"),
+    }
+}
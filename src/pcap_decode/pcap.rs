@@ -0,0 +1,443 @@
+//! Reads the TCP and UDP packets of a pcap file
+//!
+//! Only what is needed to find the BC traffic is parsed: the classic pcap
+//! format (not pcapng), Ethernet, Linux cooked and raw IP links, and IPv4 or
+//! IPv6 without extension headers
+use anyhow::{anyhow, Result};
+use std::{
+    convert::TryInto,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+pub(super) enum Transport {
+    Tcp { seq: u32, syn: bool },
+    Udp,
+}
+
+pub(super) struct Packet {
+    /// Seconds since the first packet
+    pub(super) time: f64,
+    pub(super) src: SocketAddr,
+    pub(super) dst: SocketAddr,
+    pub(super) transport: Transport,
+    pub(super) payload: Vec<u8>,
+}
+
+/// Whether the data starts with one of the pcap magic numbers
+pub(super) fn is_pcap(data: &[u8]) -> bool {
+    matches!(
+        data.get(..4),
+        Some([0xd4, 0xc3, 0xb2, 0xa1])
+            | Some([0xa1, 0xb2, 0xc3, 0xd4])
+            | Some([0x4d, 0x3c, 0xb2, 0xa1])
+            | Some([0xa1, 0xb2, 0x3c, 0x4d])
+    )
+}
+
+/// Parse all the TCP and UDP packets of the capture, other packets are skipped
+pub(super) fn packets(data: &[u8]) -> Result<Vec<Packet>> {
+    if data.get(..4) == Some(&[0x0a, 0x0d, 0x0d, 0x0a]) {
+        return Err(anyhow!(
+            "pcapng is not supported, save the capture as pcap instead"
+        ));
+    }
+    if !is_pcap(data) || data.len() < 24 {
+        return Err(anyhow!("Not a pcap file"));
+    }
+    let little_endian = data[0] == 0xd4 || data[0] == 0x4d;
+    let nanos = data[..4] == [0x4d, 0x3c, 0xb2, 0xa1] || data[..4] == [0xa1, 0xb2, 0x3c, 0x4d];
+    let u32_at = |buf: &[u8], i: usize| {
+        let bytes = [buf[i], buf[i + 1], buf[i + 2], buf[i + 3]];
+        if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    };
+    let link_type = u32_at(data, 20);
+
+    let mut packets = vec![];
+    let mut start = None;
+    let mut pos = 24;
+    while pos + 16 <= data.len() {
+        let secs = u32_at(data, pos) as f64;
+        let frac = u32_at(data, pos + 4) as f64;
+        let captured = u32_at(data, pos + 8) as usize;
+        pos += 16;
+        let Some(frame) = data.get(pos..pos + captured) else {
+            break;
+        };
+        pos += captured;
+
+        let time = secs + frac / if nanos { 1e9 } else { 1e6 };
+        let start = *start.get_or_insert(time);
+        let Some(ip) = strip_link(link_type, frame) else {
+            continue;
+        };
+        if let Some(mut packet) = parse_ip(ip) {
+            packet.time = time - start;
+            packets.push(packet);
+        }
+    }
+    Ok(packets)
+}
+
+/// Remove the link layer to leave the IP packet
+fn strip_link(link_type: u32, frame: &[u8]) -> Option<&[u8]> {
+    match link_type {
+        // Ethernet, skipping any VLAN tags
+        1 => {
+            let mut offset = 12;
+            loop {
+                let ether_type = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+                match ether_type {
+                    0x8100 | 0x88a8 => offset += 4,
+                    0x0800 | 0x86dd => return frame.get(offset + 2..),
+                    _ => return None,
+                }
+            }
+        }
+        // BSD loopback
+        0 => frame.get(4..),
+        // Raw IP
+        12 | 14 | 101 => Some(frame),
+        // Linux cooked capture v1 and v2
+        113 => frame.get(16..),
+        276 => frame.get(20..),
+        _ => None,
+    }
+}
+
+fn parse_ip(ip: &[u8]) -> Option<Packet> {
+    let (src, dst, protocol, payload) = match ip.first()? >> 4 {
+        4 => {
+            let header_len = (ip[0] & 0x0f) as usize * 4;
+            let total_len = u16::from_be_bytes([*ip.get(2)?, *ip.get(3)?]) as usize;
+            let src: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            (
+                IpAddr::from(Ipv4Addr::from(src)),
+                IpAddr::from(Ipv4Addr::from(dst)),
+                *ip.get(9)?,
+                ip.get(header_len..total_len.min(ip.len()))?,
+            )
+        }
+        6 => {
+            let payload_len = u16::from_be_bytes([*ip.get(4)?, *ip.get(5)?]) as usize;
+            let src: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            (
+                IpAddr::from(Ipv6Addr::from(src)),
+                IpAddr::from(Ipv6Addr::from(dst)),
+                *ip.get(6)?,
+                ip.get(40..(40 + payload_len).min(ip.len()))?,
+            )
+        }
+        _ => return None,
+    };
+    let src_port = u16::from_be_bytes([*payload.first()?, *payload.get(1)?]);
+    let dst_port = u16::from_be_bytes([*payload.get(2)?, *payload.get(3)?]);
+    let (transport, data) = match protocol {
+        6 => {
+            let seq = u32::from_be_bytes(payload.get(4..8)?.try_into().ok()?);
+            let data_offset = (payload.get(12)? >> 4) as usize * 4;
+            let syn = payload.get(13)? & 0x02 != 0;
+            (Transport::Tcp { seq, syn }, payload.get(data_offset..)?)
+        }
+        17 => (Transport::Udp, payload.get(8..)?),
+        _ => return None,
+    };
+    Some(Packet {
+        time: 0.0,
+        src: SocketAddr::new(src, src_port),
+        dst: SocketAddr::new(dst, dst_port),
+        transport,
+        payload: data.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: [u8; 4] = [192, 168, 1, 10];
+    const CLIENT: [u8; 4] = [192, 168, 1, 20];
+
+    /// A classic pcap file of the frames with their time in seconds and
+    /// micro or nano seconds
+    fn pcap(
+        little_endian: bool,
+        nanos: bool,
+        link_type: u32,
+        frames: &[(u32, u32, Vec<u8>)],
+    ) -> Vec<u8> {
+        let u32_bytes = |value: u32| {
+            if little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            }
+        };
+        let magic = if nanos { 0xa1b23c4d } else { 0xa1b2c3d4 };
+        let mut data = vec![];
+        data.extend(u32_bytes(magic));
+        // Version 2.4, timezone, accuracy and snap length
+        data.extend(if little_endian {
+            [2, 0, 4, 0]
+        } else {
+            [0, 2, 0, 4]
+        });
+        data.extend(u32_bytes(0));
+        data.extend(u32_bytes(0));
+        data.extend(u32_bytes(65535));
+        data.extend(u32_bytes(link_type));
+        for (secs, frac, frame) in frames {
+            data.extend(u32_bytes(*secs));
+            data.extend(u32_bytes(*frac));
+            data.extend(u32_bytes(frame.len() as u32));
+            data.extend(u32_bytes(frame.len() as u32));
+            data.extend(frame);
+        }
+        data
+    }
+
+    fn tcp(src_port: u16, dst_port: u16, seq: u32, syn: bool, data: &[u8]) -> (u8, Vec<u8>) {
+        let mut segment = vec![];
+        segment.extend(src_port.to_be_bytes());
+        segment.extend(dst_port.to_be_bytes());
+        segment.extend(seq.to_be_bytes());
+        segment.extend([0; 4]);
+        // A 24 byte header with the options
+        segment.push(6 << 4);
+        segment.push(if syn { 0x02 } else { 0x18 });
+        segment.extend([0; 6]);
+        segment.extend([1, 1, 1, 1]);
+        segment.extend(data);
+        (6, segment)
+    }
+
+    fn udp(src_port: u16, dst_port: u16, data: &[u8]) -> (u8, Vec<u8>) {
+        let mut datagram = vec![];
+        datagram.extend(src_port.to_be_bytes());
+        datagram.extend(dst_port.to_be_bytes());
+        datagram.extend((8 + data.len() as u16).to_be_bytes());
+        datagram.extend([0; 2]);
+        datagram.extend(data);
+        (17, datagram)
+    }
+
+    fn ipv4(src: [u8; 4], dst: [u8; 4], (protocol, payload): (u8, Vec<u8>)) -> Vec<u8> {
+        let mut packet = vec![0x45, 0];
+        packet.extend((20 + payload.len() as u16).to_be_bytes());
+        packet.extend([0; 5]);
+        packet.push(protocol);
+        packet.extend([0; 2]);
+        packet.extend(src);
+        packet.extend(dst);
+        packet.extend(payload);
+        packet
+    }
+
+    fn ipv6(src: Ipv6Addr, dst: Ipv6Addr, (protocol, payload): (u8, Vec<u8>)) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend((payload.len() as u16).to_be_bytes());
+        packet.push(protocol);
+        packet.push(64);
+        packet.extend(src.octets());
+        packet.extend(dst.octets());
+        packet.extend(payload);
+        packet
+    }
+
+    fn ethernet(vlans: usize, ether_type: u16, packet: Vec<u8>) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        for _ in 0..vlans {
+            frame.extend([0x81, 0x00, 0, 1]);
+        }
+        frame.extend(ether_type.to_be_bytes());
+        frame.extend(packet);
+        frame
+    }
+
+    #[test]
+    fn test_is_pcap() {
+        for magic in [
+            [0xd4, 0xc3, 0xb2, 0xa1],
+            [0xa1, 0xb2, 0xc3, 0xd4],
+            [0x4d, 0x3c, 0xb2, 0xa1],
+            [0xa1, 0xb2, 0x3c, 0x4d],
+        ] {
+            assert!(is_pcap(&magic));
+        }
+        assert!(!is_pcap(&[0x0a, 0x0d, 0x0d, 0x0a]));
+        assert!(!is_pcap(&[0xd4, 0xc3, 0xb2]));
+        assert!(!is_pcap(b"\0\0\0\x01"));
+    }
+
+    #[test]
+    fn test_not_pcap() {
+        let error = packets(&[0x0a, 0x0d, 0x0d, 0x0a, 0, 0, 0, 0])
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("pcapng"));
+        assert!(packets(b"hello world").is_err());
+        // Only the magic of the header
+        assert!(packets(&[0xd4, 0xc3, 0xb2, 0xa1]).is_err());
+        // A header without any packets
+        assert!(packets(&pcap(true, false, 1, &[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tcp_over_ethernet() {
+        let data = pcap(
+            true,
+            false,
+            1,
+            &[
+                (
+                    100,
+                    250_000,
+                    ethernet(
+                        0,
+                        0x0800,
+                        ipv4(CLIENT, CAMERA, tcp(50000, 9000, 7, true, &[])),
+                    ),
+                ),
+                (
+                    101,
+                    0,
+                    ethernet(
+                        0,
+                        0x0800,
+                        ipv4(
+                            CAMERA,
+                            CLIENT,
+                            tcp(9000, 50000, 1234, false, b"\xf0\xde\xbc\x0a"),
+                        ),
+                    ),
+                ),
+            ],
+        );
+        let packets = packets(&data).unwrap();
+        assert_eq!(packets.len(), 2);
+
+        assert_eq!(packets[0].time, 0.0);
+        assert_eq!(packets[0].src, SocketAddr::from((CLIENT, 50000)));
+        assert_eq!(packets[0].dst, SocketAddr::from((CAMERA, 9000)));
+        assert!(matches!(
+            packets[0].transport,
+            Transport::Tcp { seq: 7, syn: true }
+        ));
+        assert!(packets[0].payload.is_empty());
+
+        assert_eq!(packets[1].time, 0.75);
+        assert_eq!(packets[1].src, SocketAddr::from((CAMERA, 9000)));
+        assert!(matches!(
+            packets[1].transport,
+            Transport::Tcp {
+                seq: 1234,
+                syn: false
+            }
+        ));
+        assert_eq!(packets[1].payload, b"\xf0\xde\xbc\x0a");
+    }
+
+    #[test]
+    fn test_vlans_and_padding() {
+        let mut frame = ethernet(2, 0x0800, ipv4(CAMERA, CLIENT, udp(2018, 3000, b"bcudp")));
+        // Short frames are padded past the end of the IP packet
+        frame.extend([0; 6]);
+        let data = pcap(true, false, 1, &[(0, 0, frame)]);
+        let packets = packets(&data).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(matches!(packets[0].transport, Transport::Udp));
+        assert_eq!(packets[0].src, SocketAddr::from((CAMERA, 2018)));
+        assert_eq!(packets[0].dst, SocketAddr::from((CLIENT, 3000)));
+        assert_eq!(packets[0].payload, b"bcudp");
+    }
+
+    #[test]
+    fn test_skips_other_packets() {
+        let data = pcap(
+            true,
+            false,
+            1,
+            &[
+                // ARP
+                (0, 0, ethernet(0, 0x0806, vec![0; 28])),
+                // ICMP
+                (
+                    0,
+                    0,
+                    ethernet(0, 0x0800, ipv4(CLIENT, CAMERA, (1, vec![8, 0, 0, 0]))),
+                ),
+                // Cut short in the IP header
+                (0, 0, ethernet(0, 0x0800, vec![0x45, 0, 0])),
+                (
+                    1,
+                    0,
+                    ethernet(0, 0x0800, ipv4(CLIENT, CAMERA, udp(1, 2, b"kept"))),
+                ),
+            ],
+        );
+        let packets = packets(&data).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].payload, b"kept");
+        // The time is from the first packet even if it is skipped
+        assert_eq!(packets[0].time, 1.0);
+    }
+
+    #[test]
+    fn test_truncated_capture() {
+        let frame = ethernet(0, 0x0800, ipv4(CLIENT, CAMERA, udp(1, 2, b"first")));
+        let mut data = pcap(true, false, 1, &[(0, 0, frame.clone()), (0, 0, frame)]);
+        // The last packet was cut off while writing the file
+        data.truncate(data.len() - 10);
+        let packets = packets(&data).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].payload, b"first");
+    }
+
+    #[test]
+    fn test_big_endian_nanos_raw_ip() {
+        let data = pcap(
+            false,
+            true,
+            101,
+            &[
+                (5, 0, ipv4(CLIENT, CAMERA, udp(1, 2, b"a"))),
+                (5, 500_000_000, ipv4(CLIENT, CAMERA, udp(1, 2, b"b"))),
+            ],
+        );
+        let packets = packets(&data).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].time, 0.5);
+        assert_eq!(packets[1].payload, b"b");
+    }
+
+    #[test]
+    fn test_linux_cooked_ipv6() {
+        let camera = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 10);
+        let client = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 20);
+        let packet = ipv6(client, camera, tcp(50000, 9000, 42, false, b"bc"));
+        let mut v1 = vec![0; 16];
+        v1.extend(&packet);
+        let mut v2 = vec![0; 20];
+        v2.extend(&packet);
+        for (link_type, frame) in [(113, v1), (276, v2)] {
+            let packets = packets(&pcap(true, false, link_type, &[(0, 0, frame)])).unwrap();
+            assert_eq!(packets.len(), 1);
+            assert_eq!(packets[0].src, SocketAddr::from((client, 50000)));
+            assert_eq!(packets[0].dst, SocketAddr::from((camera, 9000)));
+            assert_eq!(packets[0].payload, b"bc");
+        }
+    }
+
+    #[test]
+    fn test_unknown_link_type() {
+        let frame = ipv4(CLIENT, CAMERA, udp(1, 2, b"a"));
+        assert!(packets(&pcap(true, false, 147, &[(0, 0, frame)]))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,19 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The proxy command will forward a client's connection to the camera and log every message
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera to forward to. Must be a name in the config
+    pub camera: String,
+    /// The address to listen for the client on
+    #[arg(short, long, default_value = "0.0.0.0:9000")]
+    pub listen: String,
+    /// The file to write the decoded messages to
+    #[arg(short, long, default_value = "neolink-proxy.log", value_parser = PathBuf::from_str)]
+    pub output: PathBuf,
+    /// A directory to write each connection to as a session file, which can be replayed in tests
+    #[arg(long, value_parser = PathBuf::from_str)]
+    pub record: Option<PathBuf>,
+}
@@ -0,0 +1,200 @@
+///
+/// # Neolink Proxy
+///
+/// This module handles the proxy subcommand
+///
+/// The subcommand listens for a client, such as the official app, and
+/// forwards its connection to the camera over TCP. Every message in both
+/// directions is decoded and written to a log, which is how new messages are
+/// found. The client must login with the credentials in the config so that
+/// AES encrypted messages can be decrypted. With `--record` each connection
+/// is also written as a session file that can be replayed in tests
+///
+/// # Usage
+///
+/// ```bash
+/// neolink proxy --config=config.toml CameraName --listen=0.0.0.0:9000 --output=capture.log
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc::{session::BcRecorder, sniff::BcSniffer};
+use std::{
+    io::BufWriter,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::Mutex,
+    time::Instant,
+};
+
+mod cmdline;
+
+use crate::{config::Config, utils::describe_bc};
+pub(crate) use cmdline::Opt;
+
+/// The log shared by all connections
+struct Log {
+    file: Mutex<File>,
+    start: Instant,
+}
+
+/// Decodes both directions of a connection, in the order they arrive
+struct Decoding {
+    sniffer: BcSniffer,
+    recorder: Option<BcRecorder<BufWriter<std::fs::File>>>,
+}
+
+/// Entry point for the proxy subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, config: Config) -> Result<()> {
+    let camera_config = config
+        .cameras
+        .iter()
+        .find(|camera| camera.name == opt.camera)
+        .with_context(|| format!("No camera called {} in the config", opt.camera))?;
+    // Only the TCP protocol can be proxied, UID cameras use UDP
+    let address = camera_config
+        .camera_addr
+        .clone()
+        .ok_or_else(|| anyhow!("The proxy needs the address of the camera"))?;
+    let address = match address.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, 9000).to_string(),
+        Err(_) if address.contains(':') => address,
+        Err(_) => format!("{address}:9000"),
+    };
+
+    let log = Arc::new(Log {
+        file: Mutex::new(
+            File::create(&opt.output)
+                .await
+                .with_context(|| format!("Could not create {:?}", opt.output))?,
+        ),
+        start: Instant::now(),
+    });
+    let listener = TcpListener::bind(&opt.listen)
+        .await
+        .with_context(|| format!("Could not listen on {}", opt.listen))?;
+    log::info!(
+        "{}: Proxying {} to {address}, logging to {}",
+        opt.camera,
+        opt.listen,
+        opt.output.display()
+    );
+
+    let mut connection_id = 0;
+    loop {
+        let (client, peer) = listener.accept().await?;
+        connection_id += 1;
+        let camera = match TcpStream::connect(&address).await {
+            Ok(camera) => camera,
+            Err(e) => {
+                log::error!("Could not connect to the camera at {address}: {e}");
+                continue;
+            }
+        };
+        log::info!("Connection {connection_id} from {peer}");
+        let recorder = match &opt.record {
+            Some(dir) => {
+                let path = dir.join(format!("connection-{connection_id}.bcsession"));
+                let file = std::fs::File::create(&path)
+                    .with_context(|| format!("Could not create {path:?}"))?;
+                Some(BcRecorder::new(BufWriter::new(file))?)
+            }
+            None => None,
+        };
+        let decoding = Arc::new(std::sync::Mutex::new(Decoding {
+            sniffer: BcSniffer::new(&camera_config.username, camera_config.password.as_deref()),
+            recorder,
+        }));
+        let log = log.clone();
+        tokio::spawn(async move {
+            let r = proxy(connection_id, peer, client, camera, decoding, log).await;
+            log::info!("Connection {connection_id} from {peer} closed: {r:?}");
+        });
+    }
+}
+
+async fn proxy(
+    id: u32,
+    peer: SocketAddr,
+    client: TcpStream,
+    camera: TcpStream,
+    decoding: Arc<std::sync::Mutex<Decoding>>,
+    log: Arc<Log>,
+) -> Result<()> {
+    let (client_read, client_write) = client.into_split();
+    let (camera_read, camera_write) = camera.into_split();
+    write_log(&log, id, &format!("Connected from {peer}")).await?;
+    tokio::select! {
+        v = forward(id, true, client_read, camera_write, decoding.clone(), log.clone()) => v,
+        v = forward(id, false, camera_read, client_write, decoding, log.clone()) => v,
+    }
+}
+
+/// Copy the bytes from one side to the other and log what they decode to
+async fn forward(
+    id: u32,
+    to_camera: bool,
+    mut from: OwnedReadHalf,
+    mut to: OwnedWriteHalf,
+    decoding: Arc<std::sync::Mutex<Decoding>>,
+    log: Arc<Log>,
+) -> Result<()> {
+    let direction = if to_camera {
+        "client -> camera"
+    } else {
+        "camera -> client"
+    };
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = from.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        // Forward first so that decoding does not slow the conversation
+        to.write_all(&buf[..read]).await?;
+
+        let texts = {
+            let mut decoding = decoding.lock().unwrap();
+            let msgs = if to_camera {
+                decoding.sniffer.to_camera(&buf[..read])
+            } else {
+                decoding.sniffer.from_camera(&buf[..read])
+            };
+            let mut texts = vec![];
+            for msg in msgs {
+                match msg {
+                    Ok(msg) => {
+                        texts.push(format!("{direction} {}", describe_bc(&msg)));
+                        if let Some(recorder) = decoding.recorder.as_mut() {
+                            if let Err(e) = recorder.record(to_camera, msg) {
+                                texts.push(format!("{direction} could not be recorded: {e}"));
+                            }
+                        }
+                    }
+                    Err(e) => texts.push(format!("{direction} could not be decoded: {e}")),
+                }
+            }
+            texts
+        };
+        for text in texts {
+            write_log(&log, id, &text).await?;
+        }
+    }
+}
+
+async fn write_log(log: &Log, id: u32, text: &str) -> Result<()> {
+    let line = format!("[{:.3}] #{id} {text}\n", log.start.elapsed().as_secs_f64());
+    let mut file = log.file.lock().await;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
@@ -21,9 +21,17 @@ pub struct Opt {
 
 #[derive(Parser, Debug)]
 pub enum PtzCommand {
-    /// Move to a stored preset
-    Preset { preset_id: Option<u8> },
+    /// List, save or move to the stored presets
+    #[command(args_conflicts_with_subcommands = true)]
+    Preset {
+        #[command(subcommand)]
+        action: Option<PresetCommand>,
+        /// Move to this preset, kept for older scripts. Use `preset goto` instead
+        #[arg(hide = true)]
+        preset_id: Option<u8>,
+    },
     /// Assign the current position to a preset with a given name
+    #[command(hide = true)]
     Assign { preset_id: u8, name: String },
     /// Performs a movement in the given direction
     Control {
@@ -39,4 +47,29 @@ pub enum PtzCommand {
         /// The amount to zoom to
         amount: f32,
     },
+    /// Move the focus to a position, see `position` for the range
+    Focus {
+        /// The position to focus at
+        position: u32,
+    },
+    /// Print the current zoom and focus positions and their ranges
+    Position {
+        /// Print as json
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+pub enum PresetCommand {
+    /// Print the list of presets
+    List {
+        /// Print as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Save the current position as a preset with a given name
+    Save { preset_id: u8, name: String },
+    /// Move to a stored preset
+    Goto { preset_id: u8 },
 }
@@ -11,11 +11,16 @@
 /// # Rotate left by 32 at speed 10 (speed not supported on most camera)
 /// neolink ptz --config=config.toml CameraName control 32 left 10
 /// # Print the list of preset positions
-/// neolink ptz --config=config.toml CameraName preset
+/// neolink ptz --config=config.toml CameraName preset list
 /// # Move the camera to preset ID 0
-/// neolink ptz --config=config.toml CameraName preset 0
+/// neolink ptz --config=config.toml CameraName preset goto 0
 /// # Save the current position as preset ID 0 with name PresetName
-/// neolink ptz --config=config.toml CameraName assign 0 PresetName
+/// neolink ptz --config=config.toml CameraName preset save 0 PresetName
+/// # Zoom to 2.5x and focus at 120
+/// neolink ptz --config=config.toml CameraName zoom 2.5
+/// neolink ptz --config=config.toml CameraName focus 120
+/// # Print the current zoom and focus as json
+/// neolink ptz --config=config.toml CameraName position --json
 /// ```
 ///
 use anyhow::{Context, Result};
@@ -25,7 +30,7 @@ mod cmdline;
 
 use crate::common::NeoReactor;
 use crate::ptz::cmdline::CmdDirection;
-use crate::ptz::cmdline::PtzCommand;
+use crate::ptz::cmdline::{PresetCommand, PtzCommand};
 pub(crate) use cmdline::Opt;
 use neolink_core::bc_protocol::Direction;
 
@@ -35,39 +40,42 @@ use neolink_core::bc_protocol::Direction;
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
-    match opt.cmd {
-        PtzCommand::Preset { preset_id } => {
-            if let Some(preset_id) = preset_id {
-                camera
-                    .run_task(|cam| {
-                        Box::pin(async move {
-                            cam.moveto_ptz_preset(preset_id)
-                                .await
-                                .context("Unable to move to PTZ preset")?;
-                            Ok(())
-                        })
-                    })
-                    .await?;
-            } else {
-                let preset_list = camera
-                    .run_task(|cam| {
-                        Box::pin(async move {
-                            let preset_list = cam
-                                .get_ptz_preset()
-                                .await
-                                .context("Unable to get PTZ presets")?;
-                            Ok(preset_list)
-                        })
-                    })
-                    .await?;
+    let cmd = match opt.cmd {
+        // The older forms of the preset commands
+        PtzCommand::Preset {
+            action: None,
+            preset_id: Some(preset_id),
+        } => PtzCommand::Preset {
+            action: Some(PresetCommand::Goto { preset_id }),
+            preset_id: None,
+        },
+        PtzCommand::Assign { preset_id, name } => PtzCommand::Preset {
+            action: Some(PresetCommand::Save { preset_id, name }),
+            preset_id: None,
+        },
+        cmd => cmd,
+    };
 
-                println!("Available presets:\nID Name");
-                for preset in preset_list.preset_list.preset {
-                    println!("{:<2} {:?}", preset.id, preset.name);
-                }
-            }
+    match cmd {
+        PtzCommand::Preset {
+            action: Some(PresetCommand::Goto { preset_id }),
+            ..
+        } => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.moveto_ptz_preset(preset_id)
+                            .await
+                            .context("Unable to move to PTZ preset")?;
+                        Ok(())
+                    })
+                })
+                .await?;
         }
-        PtzCommand::Assign { preset_id, name } => {
+        PtzCommand::Preset {
+            action: Some(PresetCommand::Save { preset_id, name }),
+            ..
+        } => {
             camera
                 .run_task(|cam| {
                     let name = name.clone();
@@ -80,6 +88,33 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                 })
                 .await?;
         }
+        PtzCommand::Preset { action, .. } => {
+            let json = matches!(action, Some(PresetCommand::List { json: true }));
+            let preset_list = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        let preset_list = cam
+                            .get_ptz_preset()
+                            .await
+                            .context("Unable to get PTZ presets")?;
+                        Ok(preset_list)
+                    })
+                })
+                .await?;
+
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&preset_list.preset_list.preset)?
+                );
+            } else {
+                println!("Available presets:\nID Name");
+                for preset in preset_list.preset_list.preset {
+                    println!("{:<2} {:?}", preset.id, preset.name);
+                }
+            }
+        }
+        PtzCommand::Assign { .. } => unreachable!(),
         PtzCommand::Control {
             amount,
             command,
@@ -131,6 +166,50 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                 .await?;
             sleep(Duration::from_secs(1)).await;
         }
+        PtzCommand::Focus { position } => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.focus_to(position)
+                            .await
+                            .context("Unable to execute PTZ focus command")?;
+                        Ok(())
+                    })
+                })
+                .await?;
+            sleep(Duration::from_secs(1)).await;
+        }
+        PtzCommand::Position { json } => {
+            let zoom_focus = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        let zoom_focus = cam
+                            .get_zoom()
+                            .await
+                            .context("Unable to get the PTZ position")?;
+                        Ok(zoom_focus)
+                    })
+                })
+                .await?;
+
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serde_json::json!({
+                        "zoom": zoom_focus.zoom,
+                        "focus": zoom_focus.focus,
+                    }))?
+                );
+            } else {
+                println!("      Current Min    Max");
+                for (name, pos) in [("Zoom", &zoom_focus.zoom), ("Focus", &zoom_focus.focus)] {
+                    println!(
+                        "{:<5} {:<7} {:<6} {}",
+                        name, pos.cur_pos, pos.min_pos, pos.max_pos
+                    );
+                }
+            }
+        }
     };
 
     Ok(())
@@ -0,0 +1,23 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The record command will save the live stream of the camera to an mp4
+#[derive(Parser, Debug)]
+#[command(group(clap::ArgGroup::new("length").required(true).multiple(true).args(["duration", "until_motion_stops"])))]
+pub struct Opt {
+    /// The name of the camera to record. Must be a name in the config
+    pub camera: String,
+    /// The path of the mp4
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub output: PathBuf,
+    /// Record for this many seconds. With `--until-motion-stops` this is the longest recording
+    #[arg(short, long)]
+    pub duration: Option<u64>,
+    /// Record until the camera reports that motion has stopped
+    #[arg(long)]
+    pub until_motion_stops: bool,
+    /// The stream to record
+    #[arg(short, long, default_value = "main", value_parser = ["main", "sub", "extern"])]
+    pub stream: String,
+}
@@ -0,0 +1,121 @@
+///
+/// # Neolink Record
+///
+/// This module handles the record subcommand
+///
+/// The live stream is pulled from the camera and muxed into an mp4 as it
+/// arrives, no RTSP server or ffmpeg is needed. The recording stops after a
+/// duration or once the camera reports that motion has stopped. If there is
+/// no motion when the recording starts it continues through the next motion
+/// until that stops
+///
+/// # Usage
+///
+/// ```bash
+/// neolink record --config=config.toml CameraName --duration=60 --output=clip.mp4
+/// neolink record --config=config.toml CameraName --until-motion-stops --duration=600 --output=clip.mp4
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use futures::stream::StreamExt;
+use neolink_core::bc_protocol::StreamKind;
+use tokio::time::{sleep_until, timeout, Duration, Instant};
+use tokio_stream::wrappers::BroadcastStream;
+
+mod cmdline;
+mod mp4;
+
+use crate::common::{MdState, NeoReactor};
+pub(crate) use cmdline::Opt;
+pub(crate) use mp4::Recorder;
+
+/// Entry point for the record subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let stream_kind = match opt.stream.as_str() {
+        "main" => StreamKind::Main,
+        "sub" => StreamKind::Sub,
+        "extern" => StreamKind::Extern,
+        other => return Err(anyhow!("Unknown stream {other}")),
+    };
+
+    let mut md = if opt.until_motion_stops {
+        Some(camera.motion().await?)
+    } else {
+        None
+    };
+
+    // Holding the instance keeps the stream active until the recording is done
+    let stream = camera.stream(stream_kind).await?;
+    let mut stream_config = stream.config.clone();
+    let vid_format = timeout(
+        Duration::from_secs(15),
+        stream_config.wait_for(|config| config.vid_ready()),
+    )
+    .await
+    .with_context(|| "Timed out waiting for the stream")??
+    .vid_format;
+    // Audio is optional so only wait a short while for it
+    let _ = timeout(
+        Duration::from_secs(2),
+        stream_config.wait_for(|config| config.aud_ready()),
+    )
+    .await;
+    let aud_format = stream_config.borrow().aud_format;
+
+    let mut recorder = Recorder::new(vid_format, aud_format, &opt.output)?;
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+    let end = opt
+        .duration
+        .map(|duration| Instant::now() + Duration::from_secs(duration));
+    let mut seen_motion = md
+        .as_ref()
+        .is_some_and(|md| matches!(*md.borrow(), MdState::Start(..)));
+    let mut started = false;
+    log::info!("{}: Recording to {}", opt.camera, opt.output.display());
+
+    loop {
+        tokio::select! {
+            frame = vid.next() => {
+                // Lagged frames are skipped, the recording just drops a few frames
+                let Some(frame) = frame else {
+                    return Err(anyhow!("The stream stopped"));
+                };
+                let Ok(frame) = frame else {
+                    continue;
+                };
+                if !started && !frame.keyframe {
+                    continue;
+                }
+                started = true;
+                recorder.push_video(&frame)?;
+            }
+            Some(frame) = aud.next() => {
+                if let Ok(frame) = frame {
+                    recorder.push_audio(&frame)?;
+                }
+            }
+            _ = async { sleep_until(end.unwrap()).await }, if end.is_some() => {
+                break;
+            }
+            v = async { md.as_mut().unwrap().changed().await }, if md.is_some() => {
+                v?;
+                match *md.as_ref().unwrap().borrow() {
+                    MdState::Start(..) => seen_motion = true,
+                    MdState::Stop(..) if seen_motion => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+    drop(stream);
+
+    tokio::task::spawn_blocking(move || recorder.finish())
+        .await?
+        .context("Could not finish the mp4")?;
+    log::info!("{}: Saved {}", opt.camera, opt.output.display());
+    Ok(())
+}
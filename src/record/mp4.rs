@@ -0,0 +1,117 @@
+//! Muxes the live stream into an mp4 file as it arrives
+use anyhow::{anyhow, Context, Result};
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use std::{path::Path, time::Duration};
+
+use crate::common::{get_source, push_frame, AudFormat, StampedData, VidFormat};
+
+/// Writes the frames of a stream to a fragmented mp4
+///
+/// The mp4 is fragmented so that it can still be played if neolink is
+/// stopped before the recording finishes
+pub(crate) struct Recorder {
+    pipeline: Pipeline,
+    vid_source: AppSrc,
+    aud_source: Option<AppSrc>,
+    start: Option<Duration>,
+}
+
+impl Recorder {
+    pub(crate) fn new(vid_format: VidFormat, aud_format: AudFormat, mp4: &Path) -> Result<Self> {
+        gstreamer::init()
+            .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+        let parser = match vid_format {
+            VidFormat::H264 => "h264parse",
+            VidFormat::H265 => "h265parse",
+            VidFormat::None => return Err(anyhow!("Video format is not yet known")),
+        };
+        // Only AAC can be muxed as is, ADPCM would need transcoding
+        let audio = matches!(aud_format, AudFormat::Aac);
+        let mut launch_str = format!(
+            "mp4mux name=themux fragment-duration=1000 streamable=true \
+            ! filesink location=\"{}\" \
+            appsrc name=thevidsource format=time \
+            ! {parser} \
+            ! themux.",
+            mp4.display()
+        );
+        if audio {
+            launch_str.push_str(
+                " appsrc name=theaudsource format=time \
+                ! aacparse \
+                ! themux.",
+            );
+        }
+        let pipeline = launch_full(&launch_str, None, ParseFlags::empty()).context(
+            "Unable to load gstreamer pipeline ensure all gstramer plugins are installed",
+        )?;
+        let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+        let vid_source = get_source(&pipeline, "thevidsource")?;
+        let aud_source = if audio {
+            Some(get_source(&pipeline, "theaudsource")?)
+        } else {
+            None
+        };
+        pipeline.set_state(State::Playing)?;
+        Ok(Self {
+            pipeline,
+            vid_source,
+            aud_source,
+            start: None,
+        })
+    }
+
+    pub(crate) fn push_video(&mut self, frame: &StampedData) -> Result<()> {
+        let start = *self.start.get_or_insert(frame.ts);
+        push_frame(&self.vid_source, start, frame)
+    }
+
+    pub(crate) fn push_audio(&mut self, frame: &StampedData) -> Result<()> {
+        // Audio before the first video frame has nothing to play with
+        if let (Some(source), Some(start)) = (self.aud_source.as_ref(), self.start) {
+            if frame.ts >= start {
+                push_frame(source, start, frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Result<()> {
+        for source in std::iter::once(&self.vid_source).chain(self.aud_source.iter()) {
+            source
+                .end_of_stream()
+                .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        }
+        let bus = self
+            .pipeline
+            .bus()
+            .expect("Pipeline without bus. Shouldn't happen!");
+        let mut res = Ok(());
+        for msg in bus.iter_timed(ClockTime::from_seconds(30)) {
+            match msg.view() {
+                MessageView::Eos(..) => break,
+                MessageView::Error(err) => {
+                    res = Err(anyhow!(
+                        "Error from gstreamer while writing the mp4: {err:?}"
+                    ));
+                    break;
+                }
+                _ => (),
+            }
+        }
+        res
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipeline.set_state(State::Null) {
+            log::warn!("Error in gstreamer when setting state to Null: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//!
+//! # Neolink Recording
+//!
+//! This module records the streams of the cameras to disk alongside the rtsp
+//! server, whether or not anyone is watching them
+//!
+//! The files are set in the `[recording]` table and each camera that should be
+//! recorded enables it in its own `[cameras.recording]`
+//!
+//! ```toml
+//! [recording]
+//! directory = "/var/lib/neolink/recordings"
+//! segment_duration = 300
+//! format = "mkv"
+//!
+//! [[cameras]]
+//! name = "Garden"
+//!   [cameras.recording]
+//!   enabled = true
+//!   stream = "main"
+//!   mode = "continuous"
+//! ```
+//!
+//! The files are written to `<directory>/<camera>/<date>/<time>.<format>` with
+//! the date and time in UTC
+//!
+//! With `mode = "motion"` a camera is only recorded around motion, from
+//! `pre_roll` seconds before it to `post_roll` seconds after it. The
+//! `detections` such as `["people", "vehicle"]` limit it to those AI detections
+//!
+//! A camera with a `[cameras.schedule]` is only recorded in its active times,
+//! the file is finished when the schedule ends
+//!
+//! Jpeg thumbnails are written beside the files every `thumbnail_interval`
+//! seconds and at the start of each motion, for the timeline of the web ui
+//!
+use anyhow::anyhow;
+use futures::stream::StreamExt;
+use log::*;
+use neolink_core::bc_protocol::StreamKind;
+use std::collections::HashMap;
+use tokio::{
+    task::JoinSet,
+    time::{interval, sleep, timeout, Duration},
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+mod motion;
+mod thumbnail;
+mod writer;
+
+use crate::{
+    common::{MdState, NeoInstance, NeoReactor},
+    config::{CameraRecordingConfig, Config, RecordingConfig},
+    schedule::{self, Gate},
+    AnyResult,
+};
+use motion::{Frame, MotionTrigger};
+use thumbnail::Thumbnails;
+use writer::SegmentWriter;
+
+/// Seconds to wait before recording again after it fails
+const RETRY: Duration = Duration::from_secs(10);
+
+/// Record the cameras until cancelled
+///
+/// The recordings are restarted as the recording settings of the cameras change
+pub(crate) async fn main(
+    recording_config: RecordingConfig,
+    reactor: NeoReactor,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    info!(
+        "Recording the enabled cameras to {}",
+        recording_config.directory.display()
+    );
+    let mut config = reactor.config().await?;
+    let mut running: HashMap<String, (CameraRecordingConfig, CancellationToken)> = HashMap::new();
+    let mut set = JoinSet::new();
+    loop {
+        let wanted = recordings(&config.borrow_and_update());
+        running.retain(|name, (camera_recording, token)| {
+            if wanted.get(name) == Some(camera_recording) {
+                true
+            } else {
+                token.cancel();
+                false
+            }
+        });
+        for (name, camera_recording) in wanted.into_iter() {
+            if running.contains_key(&name) {
+                continue;
+            }
+            let token = cancel.child_token();
+            running.insert(name.clone(), (camera_recording.clone(), token.clone()));
+            let thread_reactor = reactor.clone();
+            let recording_config = recording_config.clone();
+            let span = tracing::info_span!("recording", camera = %name);
+            set.spawn(
+                async move {
+                    let camera = thread_reactor.get(&name).await?;
+                    camera_main(camera, &name, &camera_recording, &recording_config, token).await
+                }
+                .instrument(span),
+            );
+        }
+
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            v = config.changed() => v?,
+            Some(joined) = set.join_next() => {
+                if let Ok(Err(e)) = joined {
+                    error!("Recording failed: {e:?}");
+                }
+            }
+        }
+    }
+    // Let the recordings finish their files
+    while set.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// The recording settings of the enabled cameras that are recorded
+fn recordings(config: &Config) -> HashMap<String, CameraRecordingConfig> {
+    config
+        .cameras
+        .iter()
+        .filter(|cam_config| cam_config.enabled && cam_config.recording.enabled)
+        .map(|cam_config| (cam_config.name.clone(), cam_config.recording.clone()))
+        .collect()
+}
+
+/// Record one camera until cancelled, starting again when it fails
+async fn camera_main(
+    camera: NeoInstance,
+    name: &str,
+    camera_recording: &CameraRecordingConfig,
+    recording_config: &RecordingConfig,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let kind = match camera_recording.stream.as_str() {
+        "main" => StreamKind::Main,
+        "sub" => StreamKind::Sub,
+        "extern" => StreamKind::Extern,
+        other => return Err(anyhow!("Unknown stream {other}")),
+    };
+    let mut config = camera.config().await?;
+    while !cancel.is_cancelled() {
+        let scheduled = schedule::is_active(config.borrow().schedule.as_ref(), Gate::Recording);
+        if !scheduled {
+            info!("{name}: Not recording outside of the schedule");
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                v = schedule::changed(&mut config, Gate::Recording, false) => v?,
+            }
+            continue;
+        }
+
+        info!("{name}: Recording the {kind:?} stream");
+        // Stopped at the end of the schedule so that the file is finished
+        let stop = cancel.child_token();
+        let (result, _) = tokio::join!(
+            async {
+                let result = record(
+                    &camera,
+                    name,
+                    kind,
+                    camera_recording,
+                    recording_config,
+                    &stop,
+                )
+                .await;
+                stop.cancel();
+                result
+            },
+            async {
+                tokio::select! {
+                    _ = stop.cancelled() => AnyResult::Ok(()),
+                    v = schedule::changed(&mut config, Gate::Recording, true) => {
+                        stop.cancel();
+                        v
+                    }
+                }
+            },
+        );
+        match result {
+            Ok(()) => info!("{name}: Recording stopped"),
+            Err(e) => warn!("{name}: Recording failed: {e:?}"),
+        }
+        if !schedule::is_active(config.borrow().schedule.as_ref(), Gate::Recording) {
+            continue;
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = sleep(RETRY) => {},
+        }
+    }
+    Ok(())
+}
+
+async fn record(
+    camera: &NeoInstance,
+    name: &str,
+    kind: StreamKind,
+    camera_recording: &CameraRecordingConfig,
+    recording_config: &RecordingConfig,
+    cancel: &CancellationToken,
+) -> AnyResult<()> {
+    // Holding the instance keeps the stream active while recording
+    let stream = camera.stream(kind).await?;
+    let mut stream_config = stream.config.clone();
+    let vid_format = tokio::select! {
+        _ = cancel.cancelled() => return Ok(()),
+        v = timeout(
+            Duration::from_secs(15),
+            stream_config.wait_for(|config| config.vid_ready()),
+        ) => v.map_err(|_| anyhow!("Timed out waiting for the stream"))??.vid_format,
+    };
+    // Audio is optional so only wait a short while for it
+    let _ = timeout(
+        Duration::from_secs(2),
+        stream_config.wait_for(|config| config.aud_ready()),
+    )
+    .await;
+    let aud_format = stream_config.borrow().aud_format;
+
+    let directory = recording_config.directory.join(name);
+    let new_writer = || SegmentWriter::new(vid_format, aud_format, recording_config, &directory);
+    // In motion mode the writer only exists while there is motion
+    let (mut writer, mut trigger) = if camera_recording.mode == "motion" {
+        (None, Some(MotionTrigger::new(camera_recording)))
+    } else {
+        (Some(new_writer()?), None)
+    };
+    let mut thumbnails = Thumbnails::new(recording_config, &directory);
+    let mut md = camera.motion().await?;
+    let mut moving = false;
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+    let mut check = interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            frame = vid.next() => {
+                let Some(frame) = frame else {
+                    break;
+                };
+                // Lagged frames are skipped, they show as a glitch in the file
+                if let Ok(frame) = frame {
+                    match (writer.as_mut(), trigger.as_mut()) {
+                        (Some(writer), _) => {
+                            thumbnails.frame(vid_format, &frame);
+                            writer.push_video(&frame)?;
+                        }
+                        (None, Some(trigger)) => trigger.hold(Frame::Video(frame)),
+                        (None, None) => {}
+                    }
+                }
+            }
+            Some(frame) = aud.next() => {
+                if let Ok(frame) = frame {
+                    match (writer.as_mut(), trigger.as_mut()) {
+                        (Some(writer), _) => writer.push_audio(&frame)?,
+                        (None, Some(trigger)) => trigger.hold(Frame::Audio(frame)),
+                        (None, None) => {}
+                    }
+                }
+            }
+            v = md.changed() => {
+                v?;
+                let state = md.borrow_and_update();
+                let wanted = match trigger.as_ref() {
+                    Some(trigger) => trigger.wants(&state),
+                    None => matches!(*state, MdState::Start(..)),
+                };
+                drop(state);
+                if wanted && !moving {
+                    thumbnails.motion();
+                }
+                moving = wanted;
+                let Some(trigger) = trigger.as_mut() else {
+                    continue;
+                };
+                if wanted {
+                    trigger.keep();
+                    if writer.is_none() {
+                        info!("{name}: Motion, recording");
+                        let mut new = new_writer()?;
+                        for frame in trigger.take() {
+                            match frame {
+                                Frame::Video(frame) => new.push_video(&frame)?,
+                                Frame::Audio(frame) => new.push_audio(&frame)?,
+                            }
+                        }
+                        writer = Some(new);
+                    }
+                } else if writer.is_some() {
+                    trigger.stop_later();
+                }
+            }
+            _ = check.tick() => {
+                if let Some(writer) = writer.as_ref() {
+                    writer.check()?;
+                }
+                if trigger.as_mut().is_some_and(|trigger| trigger.should_stop()) {
+                    if let Some(finished) = writer.take() {
+                        info!("{name}: Motion stopped, finishing the recording");
+                        finished.finish().await?;
+                    }
+                }
+            }
+        }
+    }
+    if let Some(writer) = writer {
+        writer.finish().await?;
+    }
+    Ok(())
+}
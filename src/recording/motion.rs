@@ -0,0 +1,103 @@
+//! Decides when a motion recording starts and stops
+//!
+//! The frames of the last `pre_roll` seconds are held so that a recording
+//! starts a little before the motion, it then runs until `post_roll` seconds
+//! after the motion stops
+use std::collections::VecDeque;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    common::{MdState, StampedData},
+    config::CameraRecordingConfig,
+};
+
+pub(super) enum Frame {
+    Video(StampedData),
+    Audio(StampedData),
+}
+
+impl Frame {
+    fn data(&self) -> &StampedData {
+        match self {
+            Self::Video(data) | Self::Audio(data) => data,
+        }
+    }
+}
+
+pub(super) struct MotionTrigger {
+    pre_roll: Duration,
+    post_roll: Duration,
+    detections: Vec<String>,
+    /// The frames before the motion, from a keyframe on
+    buffer: VecDeque<Frame>,
+    /// When the recording stops after the motion stopped
+    stop_at: Option<Instant>,
+}
+
+impl MotionTrigger {
+    pub(super) fn new(camera_recording: &CameraRecordingConfig) -> Self {
+        Self {
+            pre_roll: Duration::from_secs(camera_recording.pre_roll),
+            post_roll: Duration::from_secs(camera_recording.post_roll),
+            detections: camera_recording.detections.clone(),
+            buffer: VecDeque::new(),
+            stop_at: None,
+        }
+    }
+
+    /// If the state is motion that should be recorded
+    pub(super) fn wants(&self, state: &MdState) -> bool {
+        match state {
+            MdState::Start(_, details) => {
+                self.detections.is_empty()
+                    || details
+                        .detections
+                        .iter()
+                        .any(|detection| self.detections.contains(detection))
+            }
+            MdState::Stop(_) | MdState::Unknown => false,
+        }
+    }
+
+    /// Hold a frame while nothing is recorded
+    pub(super) fn hold(&mut self, frame: Frame) {
+        let cutoff = frame.data().ts.saturating_sub(self.pre_roll);
+        self.buffer.push_back(frame);
+        // Keep the last keyframe before the cutoff so that the recording can start with it
+        if let Some(start) = self.buffer.iter().rposition(
+            |frame| matches!(frame, Frame::Video(data) if data.keyframe && data.ts <= cutoff),
+        ) {
+            self.buffer.drain(..start);
+        }
+    }
+
+    /// The held frames, to be written when the recording starts
+    pub(super) fn take(&mut self) -> VecDeque<Frame> {
+        std::mem::take(&mut self.buffer)
+    }
+
+    /// The motion continues or started again
+    pub(super) fn keep(&mut self) {
+        self.stop_at = None;
+    }
+
+    /// The motion stopped, the recording stops after the post roll
+    pub(super) fn stop_later(&mut self) {
+        if self.stop_at.is_none() {
+            self.stop_at = Some(Instant::now() + self.post_roll);
+        }
+    }
+
+    /// If the post roll is over
+    pub(super) fn should_stop(&mut self) -> bool {
+        if self
+            .stop_at
+            .is_some_and(|stop_at| Instant::now() >= stop_at)
+        {
+            self.stop_at = None;
+            true
+        } else {
+            false
+        }
+    }
+}
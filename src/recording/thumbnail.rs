@@ -0,0 +1,91 @@
+//! Takes the jpeg thumbnails of a recording
+//!
+//! A thumbnail is taken from a keyframe every `thumbnail_interval` seconds and
+//! at the start of each motion. They are written to
+//! `<date>/thumbnails/<time>.jpg` below the directory of the camera, or
+//! `<time>-motion.jpg` for motion, beside the files of the recording
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    common::{StampedData, VidFormat},
+    config::RecordingConfig,
+    image::frame_to_thumbnail,
+    utils::utc,
+};
+
+pub(super) struct Thumbnails {
+    directory: PathBuf,
+    width: u32,
+    interval: Option<Duration>,
+    /// When the next periodic thumbnail is taken
+    next: Instant,
+    /// A motion started so the next keyframe is its thumbnail
+    motion: bool,
+}
+
+impl Thumbnails {
+    pub(super) fn new(recording_config: &RecordingConfig, directory: &Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            width: recording_config.thumbnail_width,
+            interval: Some(Duration::from_secs(recording_config.thumbnail_interval))
+                .filter(|interval| !interval.is_zero()),
+            next: Instant::now(),
+            motion: false,
+        }
+    }
+
+    /// Take the next keyframe as the thumbnail of a motion
+    pub(super) fn motion(&mut self) {
+        self.motion = true;
+    }
+
+    /// Write a thumbnail of the frame if one is due
+    ///
+    /// The frame is decoded in the background so that the recording does not wait
+    pub(super) fn frame(&mut self, vid_format: VidFormat, frame: &StampedData) {
+        if self.width == 0 || !frame.keyframe {
+            return;
+        }
+        let suffix = if self.motion {
+            "-motion"
+        } else if self.interval.is_some_and(|_| Instant::now() >= self.next) {
+            ""
+        } else {
+            return;
+        };
+        self.motion = false;
+        if let Some(interval) = self.interval {
+            self.next = Instant::now() + interval;
+        }
+
+        let path = thumbnail_path(&self.directory, SystemTime::now(), suffix);
+        let width = self.width;
+        let data = frame.data.clone();
+        tokio::task::spawn_blocking(move || {
+            let res = frame_to_thumbnail(vid_format, &data, width).and_then(|jpeg| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, jpeg)?;
+                Ok(())
+            });
+            if let Err(e) = res {
+                log::warn!("Could not write the thumbnail {}: {e:?}", path.display());
+            }
+        });
+    }
+}
+
+/// The file of a thumbnail taken at `time`
+fn thumbnail_path(directory: &Path, time: SystemTime, suffix: &str) -> PathBuf {
+    let (year, month, day, hour, minute, second) = utc(time);
+    directory
+        .join(format!("{year:04}-{month:02}-{day:02}"))
+        .join("thumbnails")
+        .join(format!("{hour:02}-{minute:02}-{second:02}{suffix}.jpg"))
+}
@@ -0,0 +1,192 @@
+//! Writes a stream into files of a set length with `splitmuxsink`
+use anyhow::{anyhow, Context};
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageType, MessageView, ParseFlags, Pipeline,
+    State,
+};
+use gstreamer_app::AppSrc;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    common::{get_source, push_frame, AudFormat, StampedData, VidFormat},
+    config::RecordingConfig,
+    utils::utc,
+    AnyResult,
+};
+
+pub(super) struct SegmentWriter {
+    pipeline: Pipeline,
+    vid_source: AppSrc,
+    aud_source: Option<AppSrc>,
+    start: Option<Duration>,
+}
+
+impl SegmentWriter {
+    /// Start writing the files of a camera into its directory
+    pub(super) fn new(
+        vid_format: VidFormat,
+        aud_format: AudFormat,
+        recording_config: &RecordingConfig,
+        directory: &Path,
+    ) -> AnyResult<Self> {
+        gstreamer::init()
+            .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+        let parser = match vid_format {
+            VidFormat::H264 => "h264parse",
+            VidFormat::H265 => "h265parse",
+            VidFormat::None => return Err(anyhow!("Video format is not yet known")),
+        };
+        // The caps of the source and how to get AAC from it
+        let audio = match aud_format {
+            AudFormat::Aac => Some((String::new(), "aacparse")),
+            AudFormat::Adpcm(block_size) => Some((
+                format!(
+                    " caps=\"audio/x-adpcm,layout=dvi,block_align={block_size},channels=1,rate=8000\""
+                ),
+                "adpcmdec ! audioconvert ! audioresample ! avenc_aac ! aacparse",
+            )),
+            AudFormat::None => None,
+        };
+        let muxer = match recording_config.format.as_str() {
+            "mp4" => "mp4mux",
+            _ => "matroskamux",
+        };
+        let mut launch_str = format!(
+            "splitmuxsink name=thesink muxer-factory={muxer} max-size-time={} \
+            appsrc name=thevidsource is-live=true format=time \
+            ! {parser} \
+            ! queue \
+            ! thesink.video",
+            ClockTime::from_seconds(recording_config.segment_duration).nseconds()
+        );
+        if let Some((caps, audio)) = audio.as_ref() {
+            launch_str.push_str(&format!(
+                " appsrc name=theaudsource is-live=true format=time{caps} \
+                ! {audio} \
+                ! queue \
+                ! thesink.audio_0"
+            ));
+        }
+        let pipeline = launch_full(&launch_str, None, ParseFlags::empty()).context(
+            "Unable to load the recording pipeline, ensure all gstramer plugins are installed",
+        )?;
+        let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+
+        let sink = pipeline
+            .by_name("thesink")
+            .ok_or_else(|| anyhow!("Cannot find the splitmuxsink in the pipeline"))?;
+        let directory = directory.to_path_buf();
+        let extension = recording_config.format.clone();
+        sink.connect("format-location", false, move |_| {
+            let path = file_path(&directory, SystemTime::now(), &extension);
+            if let Some(parent) = path.parent() {
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    log::warn!("Could not create {}: {e}", parent.display());
+                }
+            }
+            log::debug!("Recording to {}", path.display());
+            Some(path.display().to_string().to_value())
+        });
+
+        let vid_source = get_source(&pipeline, "thevidsource")?;
+        let aud_source = if audio.is_some() {
+            Some(get_source(&pipeline, "theaudsource")?)
+        } else {
+            None
+        };
+        pipeline.set_state(State::Playing)?;
+        Ok(Self {
+            pipeline,
+            vid_source,
+            aud_source,
+            start: None,
+        })
+    }
+
+    pub(super) fn push_video(&mut self, frame: &StampedData) -> AnyResult<()> {
+        // The first file has to start at a keyframe
+        if self.start.is_none() && !frame.keyframe {
+            return Ok(());
+        }
+        let start = *self.start.get_or_insert(frame.ts);
+        push_frame(&self.vid_source, start, frame)
+    }
+
+    pub(super) fn push_audio(&mut self, frame: &StampedData) -> AnyResult<()> {
+        // Audio before the first video frame has nothing to play with
+        if let (Some(source), Some(start)) = (self.aud_source.as_ref(), self.start) {
+            if frame.ts >= start {
+                push_frame(source, start, frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Errors if writing the files failed
+    pub(super) fn check(&self) -> AnyResult<()> {
+        let bus = self
+            .pipeline
+            .bus()
+            .expect("Pipeline without bus. Shouldn't happen!");
+        if let Some(msg) = bus.pop_filtered(&[MessageType::Error]) {
+            if let MessageView::Error(err) = msg.view() {
+                return Err(anyhow!("Error from gstreamer: {}", err.error()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish the current file so that it can be played
+    ///
+    /// The end of the file is waited for off the runtime as it can take a while
+    pub(super) async fn finish(self) -> AnyResult<()> {
+        tokio::task::spawn_blocking(move || self.finish_blocking()).await?
+    }
+
+    fn finish_blocking(self) -> AnyResult<()> {
+        for source in std::iter::once(&self.vid_source).chain(self.aud_source.iter()) {
+            source
+                .end_of_stream()
+                .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        }
+        let bus = self
+            .pipeline
+            .bus()
+            .expect("Pipeline without bus. Shouldn't happen!");
+        for msg in bus.iter_timed(ClockTime::from_seconds(10)) {
+            match msg.view() {
+                MessageView::Eos(..) => return Ok(()),
+                MessageView::Error(err) => {
+                    return Err(anyhow!(
+                        "Error from gstreamer while finishing the recording: {err:?}"
+                    ));
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SegmentWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipeline.set_state(State::Null) {
+            log::warn!("Error in gstreamer when setting state to Null: {e:?}");
+        }
+    }
+}
+
+/// The file that a recording starting at `time` is written to
+///
+/// This is `<date>/<time>.<extension>` in UTC below the directory of the camera
+fn file_path(directory: &Path, time: SystemTime, extension: &str) -> PathBuf {
+    let (year, month, day, hour, minute, second) = utc(time);
+    directory
+        .join(format!("{year:04}-{month:02}-{day:02}"))
+        .join(format!("{hour:02}-{minute:02}-{second:02}.{extension}"))
+}
@@ -0,0 +1,173 @@
+//!
+//! # Neolink Reload
+//!
+//! This module watches the config file and applies its changes while neolink
+//! is running, so that a camera can be added, removed or given a new password
+//! without a restart
+//!
+//! The file and the files of its `include` are checked every few seconds and
+//! on unix they are also read again on a `SIGHUP`. Only the cameras whose
+//! config changed reconnect, the streams and recordings of the others carry
+//! on. A config that does not parse or
+//! validate is logged and the running config is kept
+//!
+//! It is on by default and can be turned off with `reload = false`
+//!
+//! Some tables are only read when neolink starts, such as `[http]` and
+//! `bind`, their changes are logged as needing a restart
+//!
+use anyhow::Context;
+use log::*;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+use crate::{
+    common::NeoReactor,
+    config::{config_files, Config},
+    AnyResult,
+};
+
+/// How often the file is checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watch the config file until the program stops
+pub(crate) async fn main(path: PathBuf, reactor: NeoReactor) -> AnyResult<()> {
+    info!("Watching {path:?} for changes");
+    let mut last_modified = modified(&path).await;
+    let mut ticker = interval(CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .context("Failed to listen for SIGHUP")?;
+
+    loop {
+        #[cfg(unix)]
+        let forced = tokio::select! {
+            _ = ticker.tick() => false,
+            _ = hangup.recv() => true,
+        };
+        #[cfg(not(unix))]
+        let forced = {
+            ticker.tick().await;
+            false
+        };
+
+        let now_modified = modified(&path).await;
+        if !forced && now_modified == last_modified {
+            continue;
+        }
+        last_modified = now_modified;
+
+        match reload(&path, &reactor).await {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("Config reloading was turned off in {path:?}, restart neolink to apply further changes");
+                return Ok(());
+            }
+            Err(e) => error!("Not applying the changes to {path:?}: {e:?}"),
+        }
+    }
+}
+
+/// When the config and each of the files it includes were changed
+async fn modified(path: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let files = config_files(path).unwrap_or_else(|_| vec![path.to_path_buf()]);
+    let mut modified = vec![];
+    for file in files {
+        let time = tokio::fs::metadata(&file)
+            .await
+            .ok()
+            .and_then(|metadata| metadata.modified().ok());
+        modified.push((file, time));
+    }
+    modified
+}
+
+/// Read the file and apply it, false if reloading has been turned off
+pub(crate) async fn reload(path: &Path, reactor: &NeoReactor) -> AnyResult<bool> {
+    let mut config = Config::from_file(path)?;
+    config.check(path)?;
+    let current = reactor.config().await?.borrow().clone();
+    // The cameras of `[onboard]` are not in the file
+    for camera in current.cameras.iter().filter(|cam| cam.onboarded) {
+        if !config.cameras.iter().any(|cam| {
+            cam.name == camera.name
+                || (cam.camera_uid.is_some() && cam.camera_uid == camera.camera_uid)
+        }) {
+            config.cameras.push(camera.clone());
+        }
+    }
+    if config == current {
+        debug!("The config in {path:?} has not changed");
+        return Ok(config.reload);
+    }
+
+    for camera in config.cameras.iter() {
+        match current.cameras.iter().find(|c| c.name == camera.name) {
+            None => info!("{}: Adding the camera", camera.name),
+            Some(old) if old != camera => info!("{}: Applying the new config", camera.name),
+            Some(_) => {}
+        }
+    }
+    for camera in current.cameras.iter() {
+        if !config.cameras.iter().any(|c| c.name == camera.name) {
+            info!("{}: Removing the camera", camera.name);
+        }
+    }
+    for table in restart_needed(&current, &config) {
+        warn!("The changes to `{table}` need a restart of neolink");
+    }
+
+    let reload = config.reload;
+    reactor.update_config(config).await?;
+    info!("Applied the changes to {path:?}");
+    Ok(reload)
+}
+
+/// The parts of the config that are only read when neolink starts
+fn restart_needed(current: &Config, new: &Config) -> Vec<&'static str> {
+    let mut tables = vec![];
+    if current.bind_addr != new.bind_addr || current.bind_port != new.bind_port {
+        tables.push("bind");
+    }
+    if current.certificate != new.certificate {
+        tables.push("certificate");
+    }
+    if current.http != new.http {
+        tables.push("http");
+    }
+    if current.onvif != new.onvif {
+        tables.push("onvif");
+    }
+    if current.webrtc != new.webrtc {
+        tables.push("webrtc");
+    }
+    if current.recording != new.recording {
+        tables.push("recording");
+    }
+    if current.events != new.events {
+        tables.push("events");
+    }
+    if current.hooks != new.hooks {
+        tables.push("hooks");
+    }
+    if current.sip != new.sip {
+        tables.push("sip");
+    }
+    if current.grpc != new.grpc {
+        tables.push("grpc");
+    }
+    if current.control != new.control {
+        tables.push("control");
+    }
+    if current.health != new.health {
+        tables.push("health");
+    }
+    if current.onboard != new.onboard {
+        tables.push("onboard");
+    }
+    tables
+}
@@ -5,6 +5,7 @@ use tokio::sync::mpsc::{channel as mpsc, Receiver as MpscReceiver};
 
 use crate::{
     common::{AudFormat, StreamConfig, VidFormat},
+    config::{CameraConfig, MjpegConfig},
     rtsp::gst::NeoMediaFactory,
     AnyResult,
 };
@@ -18,6 +19,62 @@ pub(super) struct ClientData {
     pub(super) aud: Option<ClientSourceData>,
 }
 
+/// User options that change how the video pipeline is built
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(super) struct PipelineOpts {
+    /// Extra pipeline description inserted after the parser
+    pub(super) fragment: Option<String>,
+    /// Cap on the served frame rate
+    pub(super) max_fps: Option<u32>,
+    /// Cap on the served width
+    pub(super) max_width: Option<u32>,
+    /// Cap on the served height
+    pub(super) max_height: Option<u32>,
+}
+
+impl PipelineOpts {
+    pub(super) fn from_config(config: &CameraConfig) -> Self {
+        Self {
+            fragment: config
+                .pipeline
+                .as_ref()
+                .filter(|fragment| !fragment.trim().is_empty())
+                .cloned(),
+            max_fps: config.max_fps,
+            max_width: config.max_width,
+            max_height: config.max_height,
+        }
+    }
+
+    /// The resolution to scale to if the stream is larger than the limits
+    fn scaled_resolution(&self, resolution: [u32; 2]) -> Option<[u32; 2]> {
+        let [width, height] = resolution;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let mut scale = 1f64;
+        if let Some(max_width) = self.max_width {
+            scale = scale.min(max_width as f64 / width as f64);
+        }
+        if let Some(max_height) = self.max_height {
+            scale = scale.min(max_height as f64 / height as f64);
+        }
+        if scale < 1f64 {
+            // Encoders want even dimensions
+            let new_width = ((width as f64 * scale) as u32 / 2 * 2).max(2);
+            let new_height = ((height as f64 * scale) as u32 / 2 * 2).max(2);
+            Some([new_width, new_height])
+        } else {
+            None
+        }
+    }
+
+    /// If the frame rate is larger than the limit
+    fn capped_fps(&self, fps: u32) -> Option<u32> {
+        self.max_fps.filter(|max_fps| *max_fps < fps)
+    }
+}
+
 pub(super) async fn make_dummy_factory(
     use_splash: bool,
     pattern: String,
@@ -36,10 +93,12 @@ pub(super) async fn make_dummy_factory(
 
 pub(super) async fn make_factory(
     stream_config: &StreamConfig,
+    opts: &PipelineOpts,
 ) -> AnyResult<(NeoMediaFactory, MpscReceiver<ClientData>)> {
     let (client_tx, client_rx) = mpsc(100);
     let factory = {
         let stream_config = stream_config.clone();
+        let opts = opts.clone();
 
         NeoMediaFactory::new_with_callback(move |element| {
             clear_bin(&element)?;
@@ -51,7 +110,7 @@ pub(super) async fn make_factory(
                     AnyResult::Ok(None)
                 }
                 VidFormat::H264 => {
-                    let app = build_h264(&element, &stream_config)?;
+                    let app = build_h264(&element, &stream_config, &opts)?;
                     app.set_callbacks(
                         AppSrcCallbacks::builder()
                             .seek_data(move |_, _seek_pos| true)
@@ -60,7 +119,7 @@ pub(super) async fn make_factory(
                     AnyResult::Ok(Some(app))
                 }
                 VidFormat::H265 => {
-                    let app = build_h265(&element, &stream_config)?;
+                    let app = build_h265(&element, &stream_config, &opts)?;
 
                     app.set_callbacks(
                         AppSrcCallbacks::builder()
@@ -108,6 +167,53 @@ pub(super) async fn make_factory(
     Ok((factory, client_rx))
 }
 
+/// Creates a factory that decodes the stream and serves it
+/// as a low rate MJPEG
+///
+/// There is no audio on this factory
+pub(super) async fn make_mjpeg_factory(
+    stream_config: &StreamConfig,
+    mjpeg_config: &MjpegConfig,
+    opts: &PipelineOpts,
+) -> AnyResult<(NeoMediaFactory, MpscReceiver<ClientData>)> {
+    let (client_tx, client_rx) = mpsc(100);
+    let factory = {
+        let stream_config = stream_config.clone();
+        let mjpeg_config = mjpeg_config.clone();
+        let opts = opts.clone();
+
+        NeoMediaFactory::new_with_callback(move |element| {
+            clear_bin(&element)?;
+            let vid = match stream_config.vid_format {
+                VidFormat::None => {
+                    // This should not be reachable
+                    log::debug!("Building unknown during mjpeg make factory");
+                    build_unknown(&element, "black")?;
+                    AnyResult::Ok(None)
+                }
+                VidFormat::H264 | VidFormat::H265 => {
+                    let app = build_mjpeg(&element, &stream_config, &mjpeg_config, &opts)?;
+                    app.set_callbacks(
+                        AppSrcCallbacks::builder()
+                            .seek_data(move |_, _seek_pos| true)
+                            .build(),
+                    );
+                    AnyResult::Ok(Some(app))
+                }
+            }?;
+
+            client_tx.blocking_send(ClientData {
+                vid: vid.map(|app| ClientSourceData { app }),
+                aud: None,
+            })?;
+            Ok(Some(element))
+        })
+        .await
+    }?;
+
+    Ok((factory, client_rx))
+}
+
 fn clear_bin(bin: &Element) -> Result<()> {
     let bin = bin
         .clone()
@@ -202,8 +308,112 @@ fn pipe_h264(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
     })
 }
 
-fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
+/// Decode, rescale/rerate and encode the video if it exceeds the limits
+fn pipe_limit(
+    bin: &Element,
+    linked: Linked,
+    stream_config: &StreamConfig,
+    opts: &PipelineOpts,
+) -> Result<Linked> {
+    let resolution = opts.scaled_resolution(stream_config.resolution);
+    let fps = opts.capped_fps(stream_config.fps);
+    if resolution.is_none() && fps.is_none() {
+        return Ok(linked);
+    }
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    log::debug!("Limiting stream to {resolution:?} at {fps:?} fps");
+
+    let (decoder, encoder, parser) = match stream_config.vid_format {
+        VidFormat::H264 => {
+            let encoder = make_element("x264enc", "limitencoder")?;
+            encoder.set_property_from_str("tune", "zerolatency");
+            encoder.set_property_from_str("speed-preset", "ultrafast");
+            (
+                make_element("avdec_h264", "limitdecoder")?,
+                encoder,
+                make_element("h264parse", "limitparser")?,
+            )
+        }
+        VidFormat::H265 => {
+            let encoder = make_element("x265enc", "limitencoder")?;
+            encoder.set_property_from_str("tune", "zerolatency");
+            encoder.set_property_from_str("speed-preset", "ultrafast");
+            (
+                make_element("avdec_h265", "limitdecoder")?,
+                encoder,
+                make_element("h265parse", "limitparser")?,
+            )
+        }
+        VidFormat::None => unreachable!(),
+    };
+    // Keep a keyframe every 2s so that clients can join quickly
+    let key_int = fps.unwrap_or(stream_config.fps).max(1) * 2;
+    encoder.set_property("key-int-max", key_int as i32);
+
+    let convert = make_element("videoconvert", "limitconvert")?;
+    let scale = make_element("videoscale", "limitscale")?;
+    let rate = make_element("videorate", "limitrate")?;
+    if let Some(fps) = fps {
+        rate.set_property("max-rate", fps as i32);
+    }
+    let filter = make_element("capsfilter", "limitfilter")?;
+    let mut caps = Caps::builder("video/x-raw");
+    if let Some([width, height]) = resolution {
+        caps = caps
+            .field("width", width as i32)
+            .field("height", height as i32);
+    }
+    filter.set_property("caps", caps.build());
+
+    bin.add_many([
+        &decoder, &convert, &scale, &rate, &filter, &encoder, &parser,
+    ])?;
+    Element::link_many([
+        &linked.output,
+        &decoder,
+        &convert,
+        &scale,
+        &rate,
+        &filter,
+        &encoder,
+        &parser,
+    ])?;
+    Ok(Linked {
+        appsrc: linked.appsrc,
+        output: parser,
+    })
+}
+
+/// Insert the user's pipeline fragment after the output of a linked pipe
+fn pipe_fragment(bin: &Element, linked: Linked, opts: &PipelineOpts) -> Result<Linked> {
+    let Some(fragment) = opts.fragment.as_ref() else {
+        return Ok(linked);
+    };
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    log::debug!("Inserting pipeline fragment: {fragment}");
+    let fragment_bin = gstreamer::parse::bin_from_description(fragment, true)
+        .with_context(|| format!("Could not parse the pipeline fragment `{fragment}`"))?
+        .upcast::<Element>();
+
+    bin.add_many([&fragment_bin])?;
+    Element::link_many([&linked.output, &fragment_bin])
+        .with_context(|| format!("Could not link the pipeline fragment `{fragment}`"))?;
+    Ok(Linked {
+        appsrc: linked.appsrc,
+        output: fragment_bin,
+    })
+}
+
+fn build_h264(bin: &Element, stream_config: &StreamConfig, opts: &PipelineOpts) -> Result<AppSrc> {
     let linked = pipe_h264(bin, stream_config)?;
+    let linked = pipe_limit(bin, linked, stream_config, opts)?;
+    let linked = pipe_fragment(bin, linked, opts)?;
 
     let bin = bin
         .clone()
@@ -253,8 +463,10 @@ fn pipe_h265(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
     })
 }
 
-fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
+fn build_h265(bin: &Element, stream_config: &StreamConfig, opts: &PipelineOpts) -> Result<AppSrc> {
     let linked = pipe_h265(bin, stream_config)?;
+    let linked = pipe_limit(bin, linked, stream_config, opts)?;
+    let linked = pipe_fragment(bin, linked, opts)?;
 
     let bin = bin
         .clone()
@@ -267,6 +479,66 @@ fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     Ok(linked.appsrc)
 }
 
+fn build_mjpeg(
+    bin: &Element,
+    stream_config: &StreamConfig,
+    mjpeg_config: &MjpegConfig,
+    opts: &PipelineOpts,
+) -> Result<AppSrc> {
+    let (linked, decoder) = match stream_config.vid_format {
+        VidFormat::H264 => (
+            pipe_h264(bin, stream_config)?,
+            make_element("avdec_h264", "viddecoder")?,
+        ),
+        VidFormat::H265 => (
+            pipe_h265(bin, stream_config)?,
+            make_element("avdec_h265", "viddecoder")?,
+        ),
+        VidFormat::None => unreachable!(),
+    };
+    let linked = pipe_fragment(bin, linked, opts)?;
+
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    log::debug!("Building Mjpeg Pipeline");
+
+    let convert = make_element("videoconvert", "vidconvert")?;
+    let scale = make_element("videoscale", "vidscale")?;
+    let rate = make_element("videorate", "vidrate")?;
+    rate.set_property("drop-only", true);
+    let filter = make_element("capsfilter", "vidfilter")?;
+    filter.set_property(
+        "caps",
+        Caps::builder("video/x-raw")
+            .field("width", mjpeg_config.width as i32)
+            .field(
+                "framerate",
+                gstreamer::Fraction::new(mjpeg_config.fps as i32, 1),
+            )
+            .build(),
+    );
+    let encoder = make_element("jpegenc", "videncoder")?;
+    encoder.set_property("quality", mjpeg_config.quality as i32);
+    let payload = make_element("rtpjpegpay", "pay0")?;
+
+    bin.add_many([
+        &decoder, &convert, &scale, &rate, &filter, &encoder, &payload,
+    ])?;
+    Element::link_many([
+        &linked.output,
+        &decoder,
+        &convert,
+        &scale,
+        &rate,
+        &filter,
+        &encoder,
+        &payload,
+    ])?;
+    Ok(linked.appsrc)
+}
+
 fn pipe_aac(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
     // Audio seems to run at about 800kbs
     let buffer_size = 512 * 1416;
@@ -542,6 +814,11 @@ fn make_element(kind: &str, name: &str) -> AnyResult<Element> {
             "imagefreeze" => "imagefreeze (gst-plugins-good)",
             "audiotestsrc" => "audiotestsrc (gst-plugins-base)",
             "decodebin" => "playback (gst-plugins-good)",
+            "videoconvert" => "videoconvertscale (gst-plugins-base)",
+            "videoscale" => "videoconvertscale (gst-plugins-base)",
+            "videorate" => "videorate (gst-plugins-base)",
+            "jpegenc" => "jpeg (gst-plugins-good)",
+            "rtpjpegpay" => "rtp (gst-plugins-good)",
             _ => "Unknown",
         };
         format!(
@@ -4,22 +4,31 @@
 //! expect issues
 
 use super::AnyResult;
-use crate::config::*;
+use crate::{common::RtspClientInfo, config::*};
 
 use anyhow::Context;
-use gstreamer::glib::{self, object_subclass, MainLoop, Object};
-use gstreamer_rtsp::RTSPAuthMethod;
+use gstreamer::glib::{
+    self, object_subclass,
+    translate::{FromGlib, IntoGlib, ToGlibPtr},
+    MainLoop, Object,
+};
+use gstreamer_rtsp::{RTSPAuthMethod, RTSPStatusCode};
 use gstreamer_rtsp_server::{
     gio::{TlsAuthenticationMode, TlsCertificate},
     prelude::*,
     subclass::prelude::*,
-    RTSPAuth, RTSPFilterResult, RTSPServer, RTSPToken, RTSP_TOKEN_MEDIA_FACTORY_ROLE,
+    RTSPAuth, RTSPClient, RTSPContext, RTSPFilterResult, RTSPServer, RTSPToken,
+    RTSP_TOKEN_MEDIA_FACTORY_ROLE,
 };
 use log::*;
 use std::{
     collections::{HashMap, HashSet},
     fs,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
 };
 use tokio::{
     sync::RwLock,
@@ -28,6 +37,9 @@ use tokio::{
 };
 use tokio_util::sync::CancellationToken;
 
+/// The realm of gstreamer's auth module when none is set
+const DEFAULT_AUTH_REALM: &str = "GStreamer RTSP Server";
+
 glib::wrapper! {
     /// The wrapped RTSPServer
     pub(crate) struct NeoRtspServer(ObjectSubclass<NeoRtspServerImpl>) @extends RTSPServer;
@@ -57,7 +69,25 @@ impl NeoRtspServer {
         auth.set_default_token(Some(&mut un_authtoken));
         factory.set_auth(Some(&auth));
 
-        factory.connect_client_connected(|_, client| {
+        factory.connect_client_connected(|server, client| {
+            let Some(server) = server.downcast_ref::<NeoRtspServer>() else {
+                return;
+            };
+            let id = server.imp().add_client(client);
+
+            let weak_server = server.downgrade();
+            client.connect_pre_play_request(move |_, ctx| match weak_server.upgrade() {
+                Some(server) => server.imp().play_client(id, ctx),
+                None => RTSPStatusCode::Ok,
+            });
+
+            let weak_server = server.downgrade();
+            client.connect_closed(move |_| {
+                if let Some(server) = weak_server.upgrade() {
+                    server.imp().remove_client(id);
+                }
+            });
+
             client.connect_new_session(|_, session| {
                 log::debug!("New Session");
                 // Session timeout too small causes us to drop
@@ -149,6 +179,14 @@ impl NeoRtspServer {
         self.imp().set_up_tls(config)
     }
 
+    /// Apply the authentication policy from the config
+    ///
+    /// This clears the users from the auth module so they
+    /// should be re-added afterwards
+    pub(crate) async fn set_up_auth(&self, config: &Config) -> AnyResult<()> {
+        self.imp().set_up_auth(config).await
+    }
+
     pub(crate) async fn add_user(&self, username: &str, password: &str) -> AnyResult<()> {
         self.imp().add_user(username, password).await
     }
@@ -160,16 +198,70 @@ impl NeoRtspServer {
     pub(crate) async fn get_users(&self) -> AnyResult<HashSet<String>> {
         self.imp().get_users().await
     }
+
+    /// Set the maximum number of clients on each path of a camera by its name
+    pub(crate) fn set_client_limits(&self, limits: HashMap<String, usize>) {
+        *self.imp().client_limits.lock().unwrap() = limits;
+    }
+
+    /// Refuse all new clients, such as while neolink is shutting down
+    pub(crate) fn refuse_clients(&self) {
+        self.imp().refuse_clients.store(true, Ordering::Relaxed);
+    }
+
+    /// Get the clients that are currently playing the camera
+    pub(crate) fn get_clients(&self, camera: &str) -> Vec<RtspClientInfo> {
+        self.imp().get_clients(camera)
+    }
+
+    /// Count frames of the camera that were dropped as a client fell behind
+    pub(crate) fn add_dropped_frames(&self, camera: &str, count: u64) {
+        *self
+            .imp()
+            .dropped_frames
+            .lock()
+            .unwrap()
+            .entry(camera.to_string())
+            .or_default() += count;
+    }
+
+    /// The frames of the camera that were dropped since the server started
+    pub(crate) fn get_dropped_frames(&self, camera: &str) -> u64 {
+        self.imp()
+            .dropped_frames
+            .lock()
+            .unwrap()
+            .get(camera)
+            .copied()
+            .unwrap_or_default()
+    }
 }
 
 unsafe impl Send for NeoRtspServer {}
 unsafe impl Sync for NeoRtspServer {}
 
+struct ClientEntry {
+    client: RTSPClient,
+    ip: String,
+    user: Option<String>,
+    camera: Option<String>,
+    path: Option<String>,
+    since: Instant,
+}
+
 #[derive(Default)]
 pub(crate) struct NeoRtspServerImpl {
     threads: RwLock<JoinSet<AnyResult<()>>>,
     users: RwLock<HashMap<String, String>>,
     main_loop: RwLock<Option<Arc<MainLoop>>>,
+    // These are accessed from the glib signals so use blocking locks
+    clients: Mutex<HashMap<u64, ClientEntry>>,
+    client_limits: Mutex<HashMap<String, usize>>,
+    dropped_frames: Mutex<HashMap<String, u64>>,
+    next_client_id: AtomicU64,
+    // When set credentials are known but not accepted by the auth module
+    block_credentials: AtomicBool,
+    refuse_clients: AtomicBool,
 }
 
 impl ObjectImpl for NeoRtspServerImpl {}
@@ -216,10 +308,65 @@ impl NeoRtspServerImpl {
         Ok(())
     }
 
+    pub(crate) async fn set_up_auth(&self, config: &Config) -> AnyResult<()> {
+        let mut locked_users = self.users.write().await;
+        let auth = self.obj().auth().unwrap_or_default();
+
+        // Remove all credentials they will be re-added under the new policy
+        for (username, old_basic) in locked_users.drain() {
+            auth.remove_basic(&old_basic);
+            auth.remove_digest(&username);
+        }
+
+        let methods = match config.auth_method.as_str() {
+            "basic" => RTSPAuthMethod::Basic,
+            "digest" => RTSPAuthMethod::Digest,
+            // The methods are flags in C but the bindings only have an enum
+            "both" => unsafe {
+                RTSPAuthMethod::from_glib(
+                    RTSPAuthMethod::Basic.into_glib() | RTSPAuthMethod::Digest.into_glib(),
+                )
+            },
+            _ => unreachable!(),
+        };
+        auth.set_supported_methods(methods);
+
+        // A realm that was removed from the config goes back to the default
+        auth.set_realm(Some(
+            config.auth_realm.as_deref().unwrap_or(DEFAULT_AUTH_REALM),
+        ));
+
+        if config.allow_anonymous {
+            let mut un_authtoken = RTSPToken::builder()
+                .field(RTSP_TOKEN_MEDIA_FACTORY_ROLE, "anonymous")
+                .build();
+            auth.set_default_token(Some(&mut un_authtoken));
+        } else {
+            auth.set_default_token(None);
+        }
+
+        let block = config.require_tls && config.certificate.is_none();
+        if block {
+            warn!("require_tls is set but there is no certificate. Logins will be refused");
+        }
+        self.block_credentials.store(block, Ordering::Relaxed);
+
+        self.obj().set_auth(Some(&auth));
+        Ok(())
+    }
+
     pub(crate) async fn add_user(&self, username: &str, password: &str) -> AnyResult<()> {
         let mut locked_users = self.users.write().await;
         let auth = self.obj().auth().unwrap();
 
+        if self.block_credentials.load(Ordering::Relaxed) {
+            // Still track the user so that permissions are computed
+            // correctly but never accept the credentials
+            let basic = RTSPAuth::make_basic(username, password);
+            locked_users.insert(username.to_string(), basic.to_string());
+            return Ok(());
+        }
+
         let token = RTSPToken::builder()
             .field(RTSP_TOKEN_MEDIA_FACTORY_ROLE, username)
             .build();
@@ -232,10 +379,12 @@ impl NeoRtspServerImpl {
             } else {
                 // Different password
                 auth.remove_basic(old_basic);
+                auth.remove_digest(username);
             }
         }
 
         auth.add_basic(basic.as_str(), &token);
+        auth.add_digest(username, password, &token);
 
         locked_users.insert(username.to_string(), basic.to_string());
         Ok(())
@@ -247,6 +396,7 @@ impl NeoRtspServerImpl {
 
         if let Some(old_basic) = locked_users.get(username) {
             auth.remove_basic(old_basic);
+            auth.remove_digest(username);
         }
 
         locked_users.remove(username);
@@ -257,4 +407,143 @@ impl NeoRtspServerImpl {
         let locked_users = self.users.read().await;
         Ok(locked_users.keys().cloned().collect())
     }
+
+    fn add_client(&self, client: &RTSPClient) -> u64 {
+        let id = self.next_client_id.fetch_add(1, Ordering::Relaxed);
+        let ip = client_ip(client).unwrap_or_default();
+        log::debug!("Client {id} connected from {ip}");
+        self.clients.lock().unwrap().insert(
+            id,
+            ClientEntry {
+                client: client.clone(),
+                ip,
+                user: None,
+                camera: None,
+                path: None,
+                since: Instant::now(),
+            },
+        );
+        id
+    }
+
+    fn remove_client(&self, id: u64) {
+        log::debug!("Client {id} disconnected");
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    /// Called before a client plays a stream
+    ///
+    /// This records what they are watching and refuses them if
+    /// the path already has too many clients or neolink is
+    /// shutting down
+    fn play_client(&self, id: u64, ctx: &RTSPContext) -> RTSPStatusCode {
+        let components = ctx
+            .uri()
+            .map(|uri| {
+                uri.decode_path_components()
+                    .iter()
+                    .filter(|c| !c.is_empty())
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let camera = components.first().cloned();
+        let path = format!("/{}", components.join("/"));
+        let user = ctx.token().and_then(|token| {
+            token
+                .string(RTSP_TOKEN_MEDIA_FACTORY_ROLE)
+                .map(|s| s.to_string())
+        });
+
+        if self.refuse_clients.load(Ordering::Relaxed) {
+            log::info!("Refusing client, neolink is shutting down");
+            return RTSPStatusCode::ServiceUnavailable;
+        }
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(camera) = camera.as_ref() {
+            if let Some(max) = self.client_limits.lock().unwrap().get(camera) {
+                // The main and sub streams each have their own limit
+                let count = clients
+                    .iter()
+                    .filter(|(cid, entry)| **cid != id && entry.path.as_ref() == Some(&path))
+                    .count();
+                if count >= *max {
+                    log::info!(
+                        "{camera}: Refusing client on {path}, already at the limit of {max}"
+                    );
+                    return RTSPStatusCode::ServiceUnavailable;
+                }
+            }
+        }
+        if let Some(entry) = clients.get_mut(&id) {
+            entry.camera = camera;
+            entry.path = Some(path);
+            entry.user = user;
+        }
+        RTSPStatusCode::Ok
+    }
+
+    fn get_clients(&self, camera: &str) -> Vec<RtspClientInfo> {
+        let clients = self.clients.lock().unwrap();
+        let mut infos = clients
+            .values()
+            .filter(|entry| entry.camera.as_deref() == Some(camera))
+            .map(|entry| RtspClientInfo {
+                ip: entry.ip.clone(),
+                user: entry
+                    .user
+                    .clone()
+                    .unwrap_or_else(|| "anonymous".to_string()),
+                path: entry.path.clone().unwrap_or_default(),
+                uptime: entry.since.elapsed().as_secs(),
+                bytes_sent: bytes_sent(&entry.client),
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|info| std::cmp::Reverse(info.uptime));
+        infos
+    }
+}
+
+/// The address of a client, the bindings have no `connection` for it
+fn client_ip(client: &RTSPClient) -> Option<String> {
+    unsafe {
+        let conn =
+            gstreamer_rtsp_server::ffi::gst_rtsp_client_get_connection(client.to_glib_none().0);
+        if conn.is_null() {
+            return None;
+        }
+        let ip = gstreamer_rtsp::ffi::gst_rtsp_connection_get_ip(conn);
+        if ip.is_null() {
+            return None;
+        }
+        Some(std::ffi::CStr::from_ptr(ip).to_string_lossy().into_owned())
+    }
+}
+
+/// Sums the rtp octets sent on all the streams of a client
+fn bytes_sent(client: &RTSPClient) -> u64 {
+    let mut total = 0;
+    for session in client.session_filter(None) {
+        for sessmedia in session.filter(None) {
+            let Some(media) = sessmedia.media() else {
+                continue;
+            };
+            for i in 0..media.n_streams() {
+                let Some(rtpsession) = media.stream(i).and_then(|stream| stream.rtpsession())
+                else {
+                    continue;
+                };
+                let stats = rtpsession.property::<gstreamer::Structure>("stats");
+                if let Ok(sources) = stats.get::<glib::ValueArray>("source-stats") {
+                    total += sources
+                        .iter()
+                        .filter_map(|source| source.get::<gstreamer::Structure>().ok())
+                        .filter(|source| source.get::<bool>("internal").unwrap_or(false))
+                        .map(|source| source.get::<u64>("octets-sent").unwrap_or(0))
+                        .sum::<u64>();
+                }
+            }
+        }
+    }
+    total
 }
@@ -61,27 +61,33 @@ use std::sync::Arc;
 use tokio::{
     sync::watch::channel as watch,
     task::JoinSet,
-    time::{interval, Duration},
+    time::{interval, sleep, Duration},
 };
 use tokio_stream::wrappers::IntervalStream;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
 mod cmdline;
 mod factory;
 mod gst;
 mod stream;
 
-use crate::common::{NeoInstance, NeoReactor};
+use crate::common::{NeoInstance, NeoReactor, TaskSupervisor};
+use crate::schedule::{self, Gate};
 use factory::*;
 use stream::*;
 
-use super::config::UserConfig;
+use super::config::{Config, UserConfig};
 pub(crate) use cmdline::Opt;
 use gst::NeoRtspServer;
 
 type AnyResult<T> = anyhow::Result<T, anyhow::Error>;
 
+/// How long the clients are given to get the end of their streams when
+/// neolink shuts down
+const END_OF_STREAM_DELAY: Duration = Duration::from_secs(1);
+
 /// Entry point for the rtsp subcommand
 ///
 /// Opt is the command line options
@@ -120,22 +126,55 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
             _ = thread_cancel.cancelled() => AnyResult::Ok(()),
             v = async {
                 let mut curr_users = HashSet::new();
+                let mut curr_auth = None;
                 loop {
 
-                    curr_users = thread_config.wait_for(|new_config|
+                    let config = thread_config.wait_for(|new_config|
                         new_config.users.iter().cloned().collect::<HashSet<_>>() != curr_users
-                    ).await?.users.iter().cloned().collect::<HashSet<_>>();
+                        || Some(AuthState::from(new_config)) != curr_auth
+                    ).await?.clone();
+                    curr_users = config.users.iter().cloned().collect::<HashSet<_>>();
+
+                    let new_auth = AuthState::from(&config);
+                    if Some(&new_auth) != curr_auth.as_ref() {
+                        if let Err(e) = thread_rtsp.set_up_auth(&config).await {
+                            log::error!("Could not setup auth: {e}");
+                        }
+                        curr_auth = Some(new_auth);
+                    }
 
-                    let config = thread_config.borrow().clone();
                     if let Err(e) = apply_users(&thread_rtsp, &curr_users).await {
-                        log::error!("Could not setup TLS: {e}");
+                        log::error!("Could not setup users: {e}");
                     }
 
-                    if config.certificate.is_none() && !curr_users.is_empty() {
+                    if config.certificate.is_none() && !curr_users.is_empty() && !config.require_tls {
                         warn!(
                             "Without a server certificate, usernames and passwords will be exchanged in plaintext!"
                         )
                     }
+                    if !config.allow_anonymous && curr_users.is_empty() {
+                        warn!("Anonymous access is disabled but no users are configured. No one can connect");
+                    }
+                }
+            } => v
+        }
+    });
+
+    // Thread for the client limits from the config
+    let mut thread_config = reactor.config().await?;
+    let thread_cancel = global_cancel.clone();
+    let thread_rtsp = rtsp.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            v = async {
+                let mut curr_limits = None;
+                loop {
+                    let limits = thread_config.wait_for(|new_config|
+                        Some(client_limits(new_config)) != curr_limits
+                    ).await.map(|config| client_limits(&config))?;
+                    thread_rtsp.set_client_limits(limits.clone());
+                    curr_limits = Some(limits);
                 }
             } => v
         }
@@ -156,9 +195,9 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
                 let mut config_names = HashSet::new();
                 loop {
                     config_names = thread_config.wait_for(|config| {
-                        let current_names = config.cameras.iter().filter(|a| a.enabled).map(|cam_config| cam_config.name.clone()).collect::<HashSet<_>>();
+                        let current_names = config.cameras.iter().filter(|a| a.enabled && a.rtsp).map(|cam_config| cam_config.name.clone()).collect::<HashSet<_>>();
                         current_names != config_names
-                    }).await.with_context(|| "Camera Config Watcher")?.clone().cameras.iter().filter(|a| a.enabled).map(|cam_config| cam_config.name.clone()).collect::<HashSet<_>>();
+                    }).await.with_context(|| "Camera Config Watcher")?.clone().cameras.iter().filter(|a| a.enabled && a.rtsp).map(|cam_config| cam_config.name.clone()).collect::<HashSet<_>>();
 
                     for name in config_names.iter() {
                         if ! cameras.contains_key(name) {
@@ -169,8 +208,10 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
                             let thread_rtsp2 = thread_rtsp.clone();
                             let thread_reactor2 = thread_reactor.clone();
                             let name = name.clone();
+                            let span = tracing::info_span!("rtsp", camera = %name);
                             set.spawn(async move {
                                 let camera = thread_reactor2.get(&name).await?;
+                                let mut supervisor = TaskSupervisor::new("rtsp", camera.clone());
                                 tokio::select!(
                                     _ = thread_global_cancel.cancelled() => {
                                         AnyResult::Ok(())
@@ -178,9 +219,12 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
                                     _ = local_cancel.cancelled() => {
                                         AnyResult::Ok(())
                                     },
-                                    v = camera_main(camera, &thread_rtsp2) => v,
+                                    v = async {
+                                        while supervisor.run(camera_main(camera.clone(), &thread_rtsp2)).await? {}
+                                        AnyResult::Ok(())
+                                    } => v,
                                 )
-                            }) ;
+                            }.instrument(span));
                         }
                     }
 
@@ -206,6 +250,24 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
     let thread_rtsp = rtsp.clone();
     set.spawn(async move { thread_rtsp.join().await });
 
+    // On shutdown refuse new clients and let the streams end before the
+    // server and the recordings stop
+    let thread_cancel = global_cancel.clone();
+    let thread_rtsp = rtsp.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {},
+            _ = crate::shutdown::requested() => {
+                info!("Stopping the RTSP Server");
+                thread_rtsp.refuse_clients();
+                sleep(END_OF_STREAM_DELAY).await;
+                thread_cancel.cancel();
+                thread_rtsp.quit().await?;
+            }
+        }
+        AnyResult::Ok(())
+    });
+
     while let Some(joined) = set
         .join_next()
         .await
@@ -228,6 +290,28 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
     Ok(())
 }
 
+/// The parts of the config that affect the auth module
+#[derive(PartialEq, Eq)]
+struct AuthState {
+    method: String,
+    allow_anonymous: bool,
+    realm: Option<String>,
+    require_tls: bool,
+    certificate: Option<String>,
+}
+
+impl From<&Config> for AuthState {
+    fn from(config: &Config) -> Self {
+        Self {
+            method: config.auth_method.clone(),
+            allow_anonymous: config.allow_anonymous,
+            realm: config.auth_realm.clone(),
+            require_tls: config.require_tls,
+            certificate: config.certificate.clone(),
+        }
+    }
+}
+
 /// This keeps the users in rtsp and the config in sync
 async fn apply_users(rtsp: &NeoRtspServer, curr_users: &HashSet<UserConfig>) -> AnyResult<()> {
     // Add those missing
@@ -247,6 +331,18 @@ async fn apply_users(rtsp: &NeoRtspServer, curr_users: &HashSet<UserConfig>) ->
     Ok(())
 }
 
+fn client_limits(config: &Config) -> HashMap<String, usize> {
+    config
+        .cameras
+        .iter()
+        .filter_map(|cam_config| {
+            cam_config
+                .max_clients
+                .map(|max| (cam_config.name.clone(), max))
+        })
+        .collect()
+}
+
 /// Top level camera entry point
 ///
 /// It checks which streams are supported and then starts them
@@ -290,20 +386,51 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
         AnyResult::Ok(())
     });
 
+    // Share the connected clients with the rest of neolink
+    let clients_camera = camera.clone();
+    let clients_rtsp = rtsp.clone();
+    let clients_name = name.clone();
+    set.spawn(async move {
+        let mut i = IntervalStream::new(interval(Duration::from_secs(5)));
+        let mut dropped = clients_rtsp.get_dropped_frames(&clients_name);
+        while i.next().await.is_some() {
+            clients_camera
+                .set_rtsp_clients(clients_rtsp.get_clients(&clients_name))
+                .await?;
+            let now_dropped = clients_rtsp.get_dropped_frames(&clients_name);
+            if now_dropped > dropped {
+                log::warn!(
+                    "{clients_name}: Dropped {} frames as the rtsp clients fell behind, {now_dropped} in total",
+                    now_dropped - dropped
+                );
+                dropped = now_dropped;
+            }
+        }
+        AnyResult::Ok(())
+    });
+
     let mut camera_config = camera.config().await?.clone();
     loop {
         let prev_stream_config = camera_config.borrow_and_update().stream;
+        let prev_stream_paths = camera_config.borrow().streams.clone();
         let prev_stream_users = camera_config.borrow().permitted_users.clone();
-        let active_streams = prev_stream_config
-            .as_stream_kinds()
+        let active_streams = camera_config
+            .borrow()
+            .stream_kinds()
             .drain(..)
             .collect::<HashSet<_>>();
         let use_splash = camera_config.borrow().use_splash;
         let splash_pattern = camera_config.borrow().splash_pattern.to_string();
+        let scheduled =
+            schedule::is_active(camera_config.borrow().schedule.as_ref(), Gate::Streaming);
+        let mut schedule_config = camera_config.clone();
+        if !scheduled {
+            log::info!("{name}: Not streaming outside of the schedule");
+        }
 
         // This select is for changes to camera_config.stream
         break tokio::select! {
-            v = camera_config.wait_for(|config| config.stream != prev_stream_config || config.permitted_users != prev_stream_users || config.use_splash != use_splash) => {
+            v = camera_config.wait_for(|config| config.stream != prev_stream_config || config.streams != prev_stream_paths || config.permitted_users != prev_stream_users || config.use_splash != use_splash) => {
                 if let Err(e) = v {
                     AnyResult::Err(e.into())
                 } else {
@@ -311,6 +438,14 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                     continue;
                 }
             },
+            v = schedule::changed(&mut schedule_config, Gate::Streaming, scheduled) => {
+                if let Err(e) = v {
+                    AnyResult::Err(e)
+                } else {
+                    // The schedule started or ended restart
+                    continue;
+                }
+            },
             v = async {
                 // This select handles enabling the right stream
                 // and setting up the users
@@ -336,18 +471,11 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                 let mut supported_streams_3 = supported_streams.clone();
                 tokio::select! {
                     v = async {
-                        let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/main"),
-                            format!("/{name}/Main"),
-                            format!("/{name}/mainStream"),
-                            format!("/{name}/MainStream"),
-                            format!("/{name}/Mainstream"),
-                            format!("/{name}/mainstream"),
-                        ];
-                        paths.push(
-                            format!("/{name}")
-                        );
+                        let (name, paths) = {
+                            let config = camera.config().await?;
+                            let config = config.borrow();
+                            (config.name.clone(), config.rtsp_paths(StreamKind::Main))
+                        };
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
                         //
@@ -364,23 +492,13 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
 
                         supported_streams_1.wait_for(|ss| ss.contains(&StreamKind::Main)).await?;
                         stream_main(camera.stream(StreamKind::Main).await?, camera.clone(), rtsp, &permitted_users, &paths).await
-                    }, if active_streams.contains(&StreamKind::Main) => v,
+                    }, if scheduled && active_streams.contains(&StreamKind::Main) => v,
                     v = async {
-                        let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/sub"),
-                            format!("/{name}/Sub"),
-                            format!("/{name}/subStream"),
-                            format!("/{name}/SubStream"),
-                            format!("/{name}/Substream"),
-                            format!("/{name}/substream"),
-                        ];
-                        if ! active_streams.contains(&StreamKind::Main) {
-                            paths.push(
-                                format!("/{name}")
-                            );
-                        }
-
+                        let (name, paths) = {
+                            let config = camera.config().await?;
+                            let config = config.borrow();
+                            (config.name.clone(), config.rtsp_paths(StreamKind::Sub))
+                        };
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
                         //
@@ -398,23 +516,13 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
 
                         supported_streams_2.wait_for(|ss| ss.contains(&StreamKind::Sub)).await?;
                         stream_main(camera.stream(StreamKind::Sub).await?,camera.clone(), rtsp, &permitted_users, &paths).await
-                    }, if active_streams.contains(&StreamKind::Sub) => v,
+                    }, if scheduled && active_streams.contains(&StreamKind::Sub) => v,
                     v = async {
-                        let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/extern"),
-                            format!("/{name}/Extern"),
-                            format!("/{name}/externStream"),
-                            format!("/{name}/ExternStream"),
-                            format!("/{name}/Externstream"),
-                            format!("/{name}/externstream"),
-                        ];
-                        if ! active_streams.contains(&StreamKind::Main) && ! active_streams.contains(&StreamKind::Sub) {
-                            paths.push(
-                                format!("/{name}")
-                            );
-                        }
-
+                        let (name, paths) = {
+                            let config = camera.config().await?;
+                            let config = config.borrow();
+                            (config.name.clone(), config.rtsp_paths(StreamKind::Extern))
+                        };
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
                         //
@@ -431,8 +539,18 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
 
                         supported_streams_3.wait_for(|ss| ss.contains(&StreamKind::Extern)).await?;
                         stream_main(camera.stream(StreamKind::Extern).await?,camera.clone(), rtsp, &permitted_users, &paths).await
-                    }, if active_streams.contains(&StreamKind::Extern) => v,
+                    }, if scheduled && active_streams.contains(&StreamKind::Extern) => v,
                     else => {
+                        if !scheduled {
+                            // Serve the splash until the schedule starts
+                            let config = camera.config().await?.borrow().clone();
+                            let mounts = rtsp
+                                .mount_points()
+                                .ok_or(anyhow!("RTSP server lacks mount point"))?;
+                            for path in active_streams.iter().flat_map(|kind| config.rtsp_paths(*kind)) {
+                                mounts.add_factory(&path, dummy_factory.clone());
+                            }
+                        }
                         // all disabled just wait here until config is changed
                         futures::future::pending().await
                     }
@@ -1,20 +1,29 @@
 use anyhow::{anyhow, Result};
+use bytes::Bytes;
 use gstreamer::{prelude::*, ClockTime, FlowError};
 use gstreamer_app::AppSrc;
 use gstreamer_rtsp_server::prelude::*;
-use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 use tokio::{
-    sync::{broadcast::channel as broadcast, watch::channel as watch},
+    sync::{broadcast::channel as broadcast, mpsc::error::TrySendError, watch::channel as watch},
     task::JoinSet,
     time::{sleep, Duration},
 };
-use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tokio_stream::{
+    wrappers::{BroadcastStream, ReceiverStream},
+    Stream, StreamExt,
+};
 use tokio_util::sync::CancellationToken;
 
 use crate::common::{Permit, StampedData, UseCounter, VidFormat};
 use crate::{
     common::{NeoInstance, StreamConfig, StreamInstance},
+    config::{BackpressureConfig, MjpegConfig},
     AnyResult,
 };
 
@@ -62,6 +71,9 @@ pub(super) async fn stream_main(
         }
 
         curr_pause = camera_config.borrow().pause.clone();
+        let curr_mjpeg = camera_config.borrow().mjpeg.clone();
+        let curr_backpressure = camera_config.borrow().backpressure.clone();
+        let curr_opts = PipelineOpts::from_config(&camera_config.borrow());
 
         let last_stream_config = stream_instance.config.borrow().clone();
         let mut thread_stream_config = stream_instance.config.clone();
@@ -121,7 +133,7 @@ pub(super) async fn stream_main(
                     v = async {
                         loop {
                             motion
-                                .wait_for(|md| matches!(md, crate::common::MdState::Start(_)))
+                                .wait_for(|md| matches!(md, crate::common::MdState::Start(..)))
                                 .await?;
                             log::info!("{}: Enabling Motion", thread_name);
                             thread_pause_affector_tx.send_modify(|current| {
@@ -147,6 +159,7 @@ pub(super) async fn stream_main(
             {
                 let mut pn = camera.push_notifications().await?;
                 let mut curr_pn = None;
+                let wake = Duration::from_secs(camera_config.borrow().push_wake);
                 let thread_name = name.clone();
                 let thread_pause_affector_tx = pause_affector_tx.clone();
                 let cancel = this_loop_cancel.clone();
@@ -169,7 +182,7 @@ pub(super) async fn stream_main(
                                         // If another PN during wait then go back to wait more
                                         continue;
                                     }
-                                    _ = sleep(Duration::from_secs(30)) => {}
+                                    _ = sleep(wake) => {}
                                 }
                                 log::info!("{}: Pausing Push Notification", thread_name);
                                 thread_pause_affector_tx.send_modify(|current| {
@@ -272,23 +285,41 @@ pub(super) async fn stream_main(
                 log::trace!("    From {:?} to {:?}", last_stream_config, v.clone());
                 continue;
             },
-            v = camera_config.wait_for(|new_conf| new_conf.pause != curr_pause ) => {
-                v?;
-                // If pause config changes restart
-                log::info!("{}: Pause Configuration Changed. Reloading Streams", &name);
+            v = camera_config.wait_for(|new_conf| {
+                new_conf.pause != curr_pause
+                    || new_conf.mjpeg != curr_mjpeg
+                    || new_conf.backpressure != curr_backpressure
+                    || PipelineOpts::from_config(new_conf) != curr_opts
+            }) => {
+                let v = v?;
+                // If the pause, mjpeg, backpressure or pipeline config changes restart
+                let changed = if v.pause != curr_pause {
+                    "Pause"
+                } else if v.mjpeg != curr_mjpeg {
+                    "Mjpeg"
+                } else if v.backpressure != curr_backpressure {
+                    "Backpressure"
+                } else {
+                    "Pipeline"
+                };
+                log::info!("{}: {} Configuration Changed. Reloading Streams", &name, changed);
                 continue;
             },
-            v = stream_run(&name, &stream_instance, rtsp, &last_stream_config, users, paths, client_count) => v,
+            v = stream_run(&name, &stream_instance, rtsp, &last_stream_config, &curr_mjpeg, &curr_opts, &curr_backpressure, users, paths, client_count) => v,
         };
     }
 }
 
 /// This handles the stream itself by creating the factory and pushing messages into it
+#[allow(clippy::too_many_arguments)]
 async fn stream_run(
     name: &str,
     stream_instance: &StreamInstance,
     rtsp: &NeoRtspServer,
     stream_config: &StreamConfig,
+    mjpeg_config: &MjpegConfig,
+    opts: &PipelineOpts,
+    backpressure: &BackpressureConfig,
     users: &HashSet<String>,
     paths: &[String],
     client_count: Permit,
@@ -303,7 +334,7 @@ async fn stream_run(
         .mount_points()
         .ok_or(anyhow!("RTSP server lacks mount point"))?;
     // Create the factory
-    let (factory, mut client_rx) = make_factory(stream_config).await?;
+    let (factory, client_rx) = make_factory(stream_config, opts).await?;
 
     factory.add_permitted_roles(users);
 
@@ -313,11 +344,36 @@ async fn stream_run(
     }
     log::info!("{}: Available at {}", name, paths.join(", "));
 
+    // Clients from all factories are handled the same way
+    let mut client_rx: Pin<Box<dyn Stream<Item = ClientData> + Send>> =
+        Box::pin(ReceiverStream::new(client_rx));
+
+    // Optional low rate mjpeg rendition on `<path>/mjpeg`
+    if mjpeg_config.enabled {
+        let (mjpeg_factory, mjpeg_client_rx) =
+            make_mjpeg_factory(stream_config, mjpeg_config, opts).await?;
+        mjpeg_factory.add_permitted_roles(users);
+
+        let mjpeg_paths = paths
+            .iter()
+            .map(|path| format!("{path}/mjpeg"))
+            .collect::<Vec<_>>();
+        for path in mjpeg_paths.iter() {
+            log::debug!("Path: {}", path);
+            mounts.add_factory(path, mjpeg_factory.clone());
+        }
+        log::info!("{}: Mjpeg available at {}", name, mjpeg_paths.join(", "));
+        client_rx = Box::pin(client_rx.merge(ReceiverStream::new(mjpeg_client_rx)));
+    }
+
     let stream_cancel = CancellationToken::new();
     let drop_guard = stream_cancel.clone().drop_guard();
     let mut set = JoinSet::new();
     // Wait for new media client data to come in from the factory
-    while let Some(mut client_data) = client_rx.recv().await {
+    while let Some(mut client_data) = tokio::select! {
+        v = client_rx.next() => v,
+        _ = crate::shutdown::requested() => None,
+    } {
         // New media created
         let vid = client_data.vid.take().map(|data| data.app);
         let aud = client_data.aud.take().map(|data| data.app);
@@ -420,6 +476,9 @@ async fn stream_run(
         // let fallback_time = Duration::from_secs(3);
         let framerate =
             Duration::from_millis(1000u64 / std::cmp::max(stream_config.fps as u64, 5u64));
+        let thread_backpressure = backpressure.clone();
+        let thread_rtsp = rtsp.clone();
+        let thread_name = name.to_string();
         if let Some(thread_vid) = thread_vid {
             set.spawn(async move {
                 thread_client_count.activate().await?;
@@ -427,6 +486,10 @@ async fn stream_run(
                     _ = thread_stream_cancel.cancelled() => {
                         AnyResult::Ok(())
                     },
+                    // End the stream of the client when neolink shuts down
+                    _ = crate::shutdown::requested() => {
+                        AnyResult::Ok(())
+                    },
                     v = send_to_appsrc(
                         pad_vid(
                             // insert_filler(
@@ -444,7 +507,9 @@ async fn stream_run(
                             // ),
                             thread_format,
                         ),
-                        &thread_vid
+                        &thread_vid,
+                        &thread_backpressure,
+                        move |count| thread_rtsp.add_dropped_frames(&thread_name, count),
                     ) => {
                         v
                     },
@@ -461,12 +526,19 @@ async fn stream_run(
         let thread_aud = aud.clone();
         let aud_framerate =
             Duration::from_millis(1000u64 / std::cmp::max(stream_config.fps as u64, 5u64));
+        let thread_backpressure = backpressure.clone();
+        let thread_rtsp = rtsp.clone();
+        let thread_name = name.to_string();
         if let Some(thread_aud) = thread_aud {
             set.spawn(async move {
                 let r = tokio::select! {
                     _ = thread_stream_cancel.cancelled() => {
                         AnyResult::Ok(())
                     },
+                    // End the stream of the client when neolink shuts down
+                    _ = crate::shutdown::requested() => {
+                        AnyResult::Ok(())
+                    },
                     v = send_to_appsrc(
                         frametime_stream(
                             hold_stream(
@@ -476,7 +548,10 @@ async fn stream_run(
                                 ts_rx,
                             ),
                             aud_framerate),
-                        &thread_aud) => {
+                        &thread_aud,
+                        &thread_backpressure,
+                        move |count| thread_rtsp.add_dropped_frames(&thread_name, count),
+                    ) => {
                         v
                     },
                 };
@@ -653,14 +728,14 @@ fn insert_filler<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
                 match format {
                     VidFormat::H264 => {
                         Some(Ok(StampedData {
-                            data: Arc::new(h264_filler(4096)),
+                            data: Bytes::from(h264_filler(4096)),
                             keyframe: false,
                             ts: last_ts,
                         }))
                     }
                     VidFormat::H265 => {
                         Some(Ok(StampedData {
-                            data: Arc::new(h265_filler(4096)),
+                            data: Bytes::from(h265_filler(4096)),
                             keyframe: false,
                             ts: last_ts,
                         }))
@@ -710,17 +785,17 @@ fn pad_vid<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
                     let pad_size: usize = (frame.data.len() + min_pad).div_ceil(4096) * 4096 - frame.data.len();
                     let frame = StampedData {
                             keyframe: frame.keyframe,
-                            data: Arc::new(
+                            data: Bytes::from(
                                 match format {
                                     VidFormat::H264 => {
                                         frame.data.iter().chain(
                                             h264_filler(pad_size).iter()
-                                        ).copied().collect()
+                                        ).copied().collect::<Vec<_>>()
                                     }
                                     VidFormat::H265 => {
                                         frame.data.iter().chain(
                                             h265_filler(pad_size).iter()
-                                        ).copied().collect()
+                                        ).copied().collect::<Vec<_>>()
                                     }
                                     VidFormat::None => unreachable!(),
                                 }
@@ -790,25 +865,87 @@ fn repeat_keyframe<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
     })
 }
 
+/// What is done when a client or its pipeline falls behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backpressure {
+    /// Drop the oldest frames until a keyframe
+    Drop,
+    /// Wait for the client to catch up
+    Block,
+    /// End the stream of the client
+    Disconnect,
+}
+
+impl Backpressure {
+    fn from_config(config: &BackpressureConfig) -> Self {
+        match config.policy.as_str() {
+            "block" => Backpressure::Block,
+            "disconnect" => Backpressure::Disconnect,
+            _ => Backpressure::Drop,
+        }
+    }
+}
+
 /// Takes a stream and sends it to an appsrc
+///
+/// The frames wait in a queue of `backpressure.queue` frames, when it is full
+/// or the appsrc is over its `max_bytes` the policy is used. `dropped` is
+/// called with the frames that were dropped
 async fn send_to_appsrc<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
     mut stream: T,
     appsrc: &AppSrc,
+    backpressure: &BackpressureConfig,
+    dropped: impl Fn(u64) + Send + 'static,
 ) -> AnyResult<()> {
     let mut ts_0 = Duration::MAX;
     let mut wait_for_iframe = true;
-    let mut pools: HashMap<usize, gstreamer::BufferPool> = Default::default();
     let mut paused = true;
     appsrc.set_state(gstreamer::State::Paused).unwrap();
 
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<StampedData>(2000);
+    let policy = Backpressure::from_config(backpressure);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<StampedData>(backpressure.queue);
+    // Set when the queue is full so that the oldest frames are dropped
+    let behind = Arc::new(AtomicBool::new(false));
 
     // Run blocking code on a seperate thread
     let appsrc = appsrc.clone();
+    let thread_behind = behind.clone();
     std::thread::spawn(move || {
         let r = (move || {
+            let mut dropping = false;
             while let Some(data) = rx.blocking_recv() {
                 check_live(&appsrc)?; // Stop if appsrc is dropped
+                if thread_behind.swap(false, Ordering::Relaxed) {
+                    dropping = true;
+                }
+                // The pipeline is not taking the frames
+                if !paused && appsrc.current_level_bytes() >= appsrc.max_bytes() {
+                    match policy {
+                        Backpressure::Drop => dropping = true,
+                        Backpressure::Block => {
+                            while appsrc.current_level_bytes() >= appsrc.max_bytes() / 2 {
+                                check_live(&appsrc)?;
+                                std::thread::sleep(Duration::from_millis(10));
+                            }
+                        }
+                        Backpressure::Disconnect => {
+                            log::info!(
+                                "Disconnecting the client of {} as it fell behind",
+                                appsrc.name()
+                            );
+                            return Ok(());
+                        }
+                    }
+                }
+                if dropping {
+                    if data.keyframe && appsrc.current_level_bytes() < appsrc.max_bytes() {
+                        log::debug!("Caught up on {}", appsrc.name());
+                        dropping = false;
+                    } else {
+                        dropped(1);
+                        continue;
+                    }
+                }
                 if wait_for_iframe && !data.keyframe {
                     continue;
                 } else if wait_for_iframe {
@@ -824,27 +961,13 @@ async fn send_to_appsrc<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
                     appsrc.name()
                 );
                 let buf = {
-                    // let mut gst_buf = pool.acquire_buffer(None).unwrap();
-                    let msg_size = data.data.len();
-                    let pool = pools.entry(msg_size).or_insert_with_key(|size| {
-                        let pool = gstreamer::BufferPool::new();
-                        let mut pool_config = pool.config();
-                        pool_config.set_params(None, (*size) as u32, 8, 0);
-                        pool.set_config(pool_config).unwrap();
-                        // let (allocator, alloc_parms) = pool.allocator().unwrap();
-                        pool.set_active(true).unwrap();
-                        pool
-                    });
-                    let mut gst_buf = pool.acquire_buffer(None).unwrap();
-                    // let mut gst_buf = gstreamer::Buffer::with_size(data.data.len()).unwrap();
+                    // The buffer wraps the bytes of the frame rather than copying them
+                    let mut gst_buf = gstreamer::Buffer::from_slice(data.data);
                     {
                         let gst_buf_mut = gst_buf.get_mut().unwrap();
                         let time = ClockTime::from_useconds(rt.as_micros() as u64);
-                        // gst_buf_mut.set_dts(ClockTime::from_useconds(dts));
                         gst_buf_mut.set_dts(time);
                         gst_buf_mut.set_pts(time);
-                        let mut gst_buf_data = gst_buf_mut.map_writable().unwrap();
-                        gst_buf_data.copy_from_slice(data.data.as_slice());
                     }
                     gst_buf
                 };
@@ -885,6 +1008,21 @@ async fn send_to_appsrc<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
 
     // Send to the blocking thread
     while let Some(Ok(data)) = stream.next().await {
+        let data = match policy {
+            Backpressure::Block => data,
+            _ => match tx.try_send(data) {
+                Ok(()) => continue,
+                Err(TrySendError::Full(data)) if policy == Backpressure::Drop => {
+                    behind.store(true, Ordering::Relaxed);
+                    data
+                }
+                Err(TrySendError::Full(_)) => {
+                    log::info!("Disconnecting a client as its queue is full");
+                    break;
+                }
+                Err(TrySendError::Closed(_)) => break,
+            },
+        };
         // Start on iframes
         if tx.send(data).await.is_err() {
             break;
@@ -0,0 +1,155 @@
+//!
+//! # Neolink Services
+//!
+//! This module runs the services that serve the cameras beside the rtsp and
+//! mqtt commands. They are started from the config once, even when both
+//! commands run together
+//!
+//! - `[http]` the http api, snapshots and hls
+//! - `[onvif]` the cameras as onvif devices
+//! - `[webrtc]` the webrtc streams
+//! - the outputs and `[recording]` of the cameras
+//! - `[events]` the event database
+//! - `[[webhooks]]` and `[hooks]`
+//! - `[sip]` the doorbell calls
+//! - `[grpc]` the gRPC api
+//!
+//! A service that fails or panics is logged and started again after a wait
+//! that starts at a second and doubles up to a minute, the others and the
+//! cameras keep running. They all stop when neolink shuts down
+//!
+use log::*;
+use std::future::Future;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+use crate::{
+    common::{NeoReactor, TaskSupervisor},
+    AnyResult,
+};
+
+/// Run the services in the config until `cancel` or until neolink shuts down
+pub(crate) async fn main(reactor: NeoReactor, cancel: CancellationToken) -> AnyResult<()> {
+    let config = reactor.config().await?.borrow().clone();
+    let mut set = JoinSet::new();
+
+    if let Some(http_config) = config.http.clone() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "http", &cancel, move |cancel| {
+            crate::http::main(http_config.clone(), reactor.clone(), cancel)
+        });
+    }
+    #[cfg(feature = "gstreamer")]
+    if let Some(onvif_config) = config.onvif.clone() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "onvif", &cancel, move |cancel| {
+            crate::onvif::main(onvif_config.clone(), reactor.clone(), cancel)
+        });
+    }
+    #[cfg(feature = "gstreamer")]
+    if let Some(webrtc_config) = config.webrtc.clone() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "webrtc", &cancel, move |cancel| {
+            crate::webrtc::main(webrtc_config.clone(), reactor.clone(), cancel)
+        });
+    }
+    #[cfg(feature = "gstreamer")]
+    {
+        let reactor = reactor.clone();
+        spawn(&mut set, "output", &cancel, move |cancel| {
+            crate::output::main(reactor.clone(), cancel)
+        });
+    }
+    #[cfg(feature = "gstreamer")]
+    if let Some(recording_config) = config.recording.clone() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "recording", &cancel, move |cancel| {
+            crate::recording::main(recording_config.clone(), reactor.clone(), cancel)
+        });
+    }
+    #[cfg(feature = "events")]
+    if let Some(events_config) = config.events.clone() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "events", &cancel, move |cancel| {
+            crate::events::record(events_config.clone(), reactor.clone(), cancel)
+        });
+    }
+    if !config.webhooks.is_empty() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "webhooks", &cancel, move |cancel| {
+            crate::webhooks::main(reactor.clone(), cancel)
+        });
+    }
+    if let Some(hooks_config) = config.hooks.clone() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "hooks", &cancel, move |cancel| {
+            crate::hooks::main(hooks_config.clone(), reactor.clone(), cancel)
+        });
+    }
+    #[cfg(feature = "gstreamer")]
+    if config.sip.is_some() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "sip", &cancel, move |cancel| {
+            crate::sip::main(reactor.clone(), cancel)
+        });
+    }
+    #[cfg(not(feature = "gstreamer"))]
+    if config.onvif.is_some()
+        || config.webrtc.is_some()
+        || config.recording.is_some()
+        || config.sip.is_some()
+    {
+        warn!("The [onvif], [webrtc], [recording] and [sip] services need neolink to be built with gstreamer");
+    }
+    #[cfg(not(feature = "events"))]
+    if config.events.is_some() {
+        warn!("The [events] database needs neolink to be built with the events feature");
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_config) = config.grpc.clone() {
+        let reactor = reactor.clone();
+        spawn(&mut set, "grpc", &cancel, move |cancel| {
+            crate::grpc::main(grpc_config.clone(), reactor.clone(), cancel)
+        });
+    }
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc.is_some() {
+        warn!("The [grpc] api needs neolink to be built with the grpc feature");
+    }
+
+    // The services finish on their own once cancelled, such as the
+    // recordings that close their files
+    tokio::select! {
+        _ = cancel.cancelled() => {},
+        _ = crate::shutdown::requested() => cancel.cancel(),
+    }
+    while set.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// Run a service, starting it again when it fails until `cancel`
+fn spawn<F, Fut>(set: &mut JoinSet<()>, name: &'static str, cancel: &CancellationToken, service: F)
+where
+    F: Fn(CancellationToken) -> Fut + Send + 'static,
+    Fut: Future<Output = AnyResult<()>> + Send + 'static,
+{
+    let cancel = cancel.clone();
+    let span = tracing::info_span!("service", service = name);
+    set.spawn(
+        async move {
+            let mut supervisor = TaskSupervisor::service(name, cancel.clone());
+            loop {
+                match supervisor.run(service(cancel.clone())).await {
+                    Ok(true) if !cancel.is_cancelled() => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        error!("The {name} service stopped: {e:?}");
+                        break;
+                    }
+                }
+            }
+        }
+        .instrument(span),
+    );
+}
@@ -0,0 +1,321 @@
+//!
+//! # Neolink Schedule
+//!
+//! This module reads the schedule of a camera, outside of its `active` times
+//! the camera is not streamed, recorded or its motion published, so that an
+//! indoor camera is only on while nobody is home
+//!
+//! ```toml
+//! [[cameras]]
+//! name = "Living Room"
+//!   [cameras.schedule]
+//!   active = ["Mon-Fri 08:00-18:00", "Sat,Sun 22:00-06:00"]
+//!   recording = false
+//! ```
+//!
+//! A range is the days such as `Mon-Fri` or `Sat,Sun` and the times in the
+//! local time of the server, without the days it is every day. A range that
+//! ends before it starts goes over midnight into the next day
+//!
+//! `streaming`, `recording` and `motion` choose what the schedule applies to,
+//! all of them by default. A camera without a schedule is always active
+//!
+use anyhow::{anyhow, Context};
+use chrono::{Datelike, Local, Timelike, Weekday};
+use std::str::FromStr;
+use tokio::{sync::watch::Receiver as WatchReceiver, time::sleep};
+
+use crate::{
+    config::{CameraConfig, ScheduleConfig},
+    AnyResult,
+};
+
+/// What a schedule can turn off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Streaming and recording are only used with gstreamer
+#[cfg_attr(not(feature = "gstreamer"), allow(dead_code))]
+pub(crate) enum Gate {
+    Streaming,
+    Recording,
+    Motion,
+}
+
+/// A range of `active` such as `Mon-Fri 08:00-18:00`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ActiveRange {
+    /// The days it starts on from Monday
+    days: [bool; 7],
+    /// Minutes from midnight
+    start: u32,
+    /// Minutes from midnight, before `start` when it goes over midnight
+    end: u32,
+}
+
+impl FromStr for ActiveRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The times are last, the days before them can have spaces such as `Sat, Sun`
+        let (days, times) = match s.trim().rsplit_once(char::is_whitespace) {
+            Some((days, times)) => (
+                parse_days(days)
+                    .with_context(|| format!("{s:?} should be like \"Mon-Fri 08:00-18:00\""))?,
+                times,
+            ),
+            None => ([true; 7], s.trim()),
+        };
+        let (start, end) = times
+            .split_once('-')
+            .with_context(|| format!("{times:?} should be like \"08:00-18:00\""))?;
+        Ok(ActiveRange {
+            days,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+}
+
+impl ActiveRange {
+    /// If it covers the minute of the day on the day from Monday
+    fn contains(&self, day: usize, minute: u32) -> bool {
+        let yesterday = (day + 6) % 7;
+        if self.start < self.end {
+            self.days[day] && (self.start..self.end).contains(&minute)
+        } else if self.start == self.end {
+            // The whole day
+            self.days[day]
+        } else {
+            (self.days[day] && minute >= self.start) || (self.days[yesterday] && minute < self.end)
+        }
+    }
+}
+
+fn parse_days(days: &str) -> AnyResult<[bool; 7]> {
+    let mut found = [false; 7];
+    for part in days.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+            None => (parse_day(part)?, parse_day(part)?),
+        };
+        // Ranges such as `Fri-Mon` go over the end of the week
+        let mut day = first;
+        loop {
+            found[day] = true;
+            if day == last {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Ok(found)
+}
+
+fn parse_day(day: &str) -> AnyResult<usize> {
+    Weekday::from_str(day.trim())
+        .map(|day| day.num_days_from_monday() as usize)
+        .map_err(|_| anyhow!("{day:?} is not a day, use Mon, Tue, Wed, Thu, Fri, Sat or Sun"))
+}
+
+/// Minutes from midnight of `HH:MM`, `24:00` is the end of the day
+fn parse_time(time: &str) -> AnyResult<u32> {
+    let (hours, minutes) = time
+        .trim()
+        .split_once(':')
+        .with_context(|| format!("{time:?} should be like \"08:00\""))?;
+    let hours = u32::from_str(hours).with_context(|| format!("{time:?} is not a time"))?;
+    let minutes = u32::from_str(minutes).with_context(|| format!("{time:?} is not a time"))?;
+    if minutes >= 60 || hours > 24 || (hours == 24 && minutes > 0) {
+        return Err(anyhow!("{time:?} is not a time"));
+    }
+    Ok(hours * 60 + minutes)
+}
+
+/// The ranges of `active`
+pub(crate) fn parse(active: &[String]) -> AnyResult<Vec<ActiveRange>> {
+    active.iter().map(|range| range.parse()).collect()
+}
+
+/// If the camera is active for this at the local time
+pub(crate) fn is_active(schedule: Option<&ScheduleConfig>, gate: Gate) -> bool {
+    let Some(schedule) = schedule else {
+        return true;
+    };
+    let applies = match gate {
+        Gate::Streaming => schedule.streaming,
+        Gate::Recording => schedule.recording,
+        Gate::Motion => schedule.motion,
+    };
+    if !applies {
+        return true;
+    }
+    let now = Local::now();
+    let day = now.weekday().num_days_from_monday() as usize;
+    let minute = now.hour() * 60 + now.minute();
+    // The config is validated so the ranges parse
+    parse(&schedule.active)
+        .unwrap_or_default()
+        .iter()
+        .any(|range| range.contains(day, minute))
+}
+
+/// Wait until the camera is no longer `active` for this or its schedule is
+/// changed
+#[cfg_attr(not(feature = "gstreamer"), allow(dead_code))]
+pub(crate) async fn changed(
+    config: &mut WatchReceiver<CameraConfig>,
+    gate: Gate,
+    active: bool,
+) -> AnyResult<()> {
+    let schedule = config.borrow_and_update().schedule.clone();
+    loop {
+        if is_active(schedule.as_ref(), gate) != active {
+            return Ok(());
+        }
+        // Check again at the start of the next minute
+        let wait = 60 - Local::now().second().min(59);
+        tokio::select! {
+            v = config.changed() => {
+                v?;
+                if config.borrow_and_update().schedule != schedule {
+                    return Ok(());
+                }
+            }
+            _ = sleep(std::time::Duration::from_secs(wait as u64)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MON: usize = 0;
+    const FRI: usize = 4;
+    const SAT: usize = 5;
+    const SUN: usize = 6;
+
+    fn range(s: &str) -> ActiveRange {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            range("Mon-Fri 08:00-18:00"),
+            ActiveRange {
+                days: [true, true, true, true, true, false, false],
+                start: 8 * 60,
+                end: 18 * 60,
+            }
+        );
+        assert_eq!(
+            range("Sat,Sun 22:00-06:00"),
+            ActiveRange {
+                days: [false, false, false, false, false, true, true],
+                start: 22 * 60,
+                end: 6 * 60,
+            }
+        );
+        // Without the days it is every day
+        assert_eq!(range("00:00-24:00").days, [true; 7]);
+        assert_eq!(range("00:00-24:00").end, 24 * 60);
+        // The days can be written out and have spaces around them
+        assert_eq!(
+            range("monday, Wed-Thu 7:05-7:30").days,
+            [true, false, true, true, false, false, false]
+        );
+        assert_eq!(range("Fri 7:05-7:30").start, 7 * 60 + 5);
+        assert_eq!(
+            range(" Sat, Sun  22:00-06:00 "),
+            range("Sat,Sun 22:00-06:00")
+        );
+    }
+
+    #[test]
+    fn test_parse_days_over_the_week() {
+        assert_eq!(
+            range("Fri-Mon 08:00-09:00").days,
+            [true, false, false, false, true, true, true]
+        );
+        assert_eq!(
+            range("Tue-Tue 08:00-09:00").days,
+            [false, true, false, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in [
+            "",
+            "Mon-Fri",
+            "Mon-Fri 08:00",
+            "Mon Fri 08:00-18:00",
+            "Someday 08:00-18:00",
+            "Mon- 08:00-18:00",
+            "08:00-25:00",
+            "24:01-08:00",
+            "08:60-09:00",
+            "8-9",
+            "aa:00-09:00",
+            "-1:00-09:00",
+        ] {
+            assert!(
+                ActiveRange::from_str(bad).is_err(),
+                "{:?} should not parse",
+                bad
+            );
+        }
+        assert!(parse(&["Mon 08:00-09:00".to_string(), "nope".to_string()]).is_err());
+        assert_eq!(parse(&[]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_contains() {
+        let work = range("Mon-Fri 08:00-18:00");
+        assert!(work.contains(MON, 8 * 60));
+        assert!(work.contains(FRI, 18 * 60 - 1));
+        // The end is not included
+        assert!(!work.contains(FRI, 18 * 60));
+        assert!(!work.contains(MON, 8 * 60 - 1));
+        assert!(!work.contains(SAT, 12 * 60));
+    }
+
+    #[test]
+    fn test_contains_over_midnight() {
+        let nights = range("Sat,Sun 22:00-06:00");
+        assert!(nights.contains(SAT, 23 * 60));
+        assert!(nights.contains(SUN, 5 * 60));
+        // The night of Sunday goes into Monday
+        assert!(nights.contains(MON, 5 * 60));
+        assert!(!nights.contains(MON, 6 * 60));
+        assert!(!nights.contains(MON, 23 * 60));
+        // Friday night is not in it, so neither is the morning of Saturday
+        assert!(!nights.contains(FRI, 23 * 60));
+        assert!(!nights.contains(SAT, 5 * 60));
+    }
+
+    #[test]
+    fn test_contains_whole_day() {
+        let sunday = range("Sun 00:00-00:00");
+        assert!(sunday.contains(SUN, 0));
+        assert!(sunday.contains(SUN, 24 * 60 - 1));
+        assert!(!sunday.contains(MON, 0));
+        let always = range("00:00-24:00");
+        assert!((0..7).all(|day| always.contains(day, 0) && always.contains(day, 24 * 60 - 1)));
+    }
+
+    #[test]
+    fn test_gates() {
+        // Never active, but only for the recordings
+        let schedule = ScheduleConfig {
+            active: vec![],
+            streaming: false,
+            recording: true,
+            motion: false,
+        };
+        assert!(is_active(None, Gate::Recording));
+        assert!(is_active(Some(&schedule), Gate::Streaming));
+        assert!(is_active(Some(&schedule), Gate::Motion));
+        assert!(!is_active(Some(&schedule), Gate::Recording));
+    }
+}
@@ -0,0 +1,47 @@
+use clap::Parser;
+use neolink_core::bc::xml::RecordTime;
+
+use crate::utils::parse_record_time;
+
+/// The sdcard command will show, format or search the storage of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Print as json
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub cmd: SdCardCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum SdCardCommand {
+    /// Print the size, free space and state of each storage device
+    Info,
+    /// Format a storage device, this erases all recordings on it
+    Format {
+        /// The number of the device as shown by `info`
+        #[arg(long, default_value = "0")]
+        number: u8,
+        /// Confirm that the recordings should be erased
+        #[arg(long)]
+        yes: bool,
+    },
+    /// List the recordings between two times
+    List {
+        /// Start of the search as `YYYY-MM-DD HH:MM[:SS]` in the camera's time
+        #[arg(long, value_parser = parse_record_time)]
+        from: RecordTime,
+        /// End of the search as `YYYY-MM-DD HH:MM[:SS]` in the camera's time
+        #[arg(long, value_parser = parse_record_time)]
+        to: RecordTime,
+        /// The stream of the recordings
+        #[arg(short, long, default_value = "main", value_parser = ["main", "sub"])]
+        stream: String,
+        /// Only list the recordings that were triggered by motion
+        #[arg(long)]
+        motion_only: bool,
+    },
+}
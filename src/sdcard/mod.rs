@@ -0,0 +1,186 @@
+///
+/// # Neolink SD Card
+///
+/// This module handles the sdcard subcommand
+///
+/// The subcommand prints the state of the camera's storage, formats it or
+/// lists the recordings on it. Each can print as json for scripts
+///
+/// # Usage
+///
+/// ```bash
+/// neolink sdcard --config=config.toml CameraName info
+/// neolink sdcard --config=config.toml CameraName format --yes
+/// neolink sdcard --config=config.toml CameraName list --from="2023-05-18 14:00" --to="2023-05-18 15:00" --json
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc::xml::RecordTime;
+use neolink_core::bc_protocol::StreamKind;
+use serde::Serialize;
+
+mod cmdline;
+
+use crate::{common::NeoReactor, AnyResult};
+pub(crate) use cmdline::Opt;
+use cmdline::SdCardCommand;
+
+#[derive(Serialize)]
+struct DeviceEntry {
+    number: u8,
+    capacity_mb: u64,
+    free_mb: Option<u64>,
+    mounted: bool,
+    formatted: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct FileEntry {
+    name: String,
+    size: Option<u64>,
+    record_type: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+}
+
+/// Entry point for the sdcard subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    match opt.cmd {
+        SdCardCommand::Info => {
+            let info = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.get_hdd_info()
+                            .await
+                            .context("Could not get the storage of the camera")
+                    })
+                })
+                .await?;
+            let devices = info
+                .hdd_info
+                .into_iter()
+                .map(|hdd| DeviceEntry {
+                    number: hdd.number,
+                    capacity_mb: hdd.capacity,
+                    free_mb: hdd.remain_size,
+                    mounted: hdd.mount == 1,
+                    formatted: hdd.format.map(|f| f == 1),
+                })
+                .collect::<Vec<_>>();
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&devices)?);
+            } else if devices.is_empty() {
+                println!("No storage device found");
+            } else {
+                for device in devices.iter() {
+                    println!(
+                        "{}: {} MB, {} MB free, {}{}",
+                        device.number,
+                        device.capacity_mb,
+                        device
+                            .free_mb
+                            .map(|free| free.to_string())
+                            .unwrap_or_else(|| "?".to_string()),
+                        if device.mounted {
+                            "mounted"
+                        } else {
+                            "not mounted"
+                        },
+                        if device.formatted == Some(false) {
+                            ", needs a format"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+        }
+        SdCardCommand::Format { number, yes } => {
+            if !yes {
+                return Err(anyhow!(
+                    "Formatting erases all recordings on the card, add --yes to confirm"
+                ));
+            }
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.format_hdd(number)
+                            .await
+                            .context("Could not format the storage of the camera")
+                    })
+                })
+                .await?;
+            if opt.json {
+                println!("{}", serde_json::json!({ "formatted": number }));
+            } else {
+                println!("Formatted storage device {number}");
+            }
+        }
+        SdCardCommand::List {
+            from,
+            to,
+            stream,
+            motion_only,
+        } => {
+            let stream = match stream.as_str() {
+                "sub" => StreamKind::Sub,
+                _ => StreamKind::Main,
+            };
+            let record_type = if motion_only {
+                "md, pir"
+            } else {
+                "manual, sched, io, md, pir"
+            };
+            let files = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        let files = cam
+                            .get_file_list(stream, from, to, record_type)
+                            .await
+                            .context("Could not list the recordings on the camera")?;
+                        AnyResult::Ok(files)
+                    })
+                })
+                .await?;
+            let files = files
+                .into_iter()
+                .filter_map(|file| {
+                    Some(FileEntry {
+                        name: file.name?,
+                        size: file.file_size,
+                        record_type: file.record_type,
+                        start: file.start_time.map(format_time),
+                        end: file.end_time.map(format_time),
+                    })
+                })
+                .collect::<Vec<_>>();
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&files)?);
+            } else {
+                for file in files.iter() {
+                    println!(
+                        "{}  {}  {:>10}  {:8}  {}",
+                        file.start.as_deref().unwrap_or("?"),
+                        file.end.as_deref().unwrap_or("?"),
+                        file.size.map(|s| s.to_string()).unwrap_or_default(),
+                        file.record_type.as_deref().unwrap_or(""),
+                        file.name
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn format_time(time: RecordTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    )
+}
@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The shell command will open a prompt to control the camera over one connection
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+}
+
+/// A line typed at the prompt
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true, disable_version_flag = true)]
+pub(super) struct Line {
+    #[command(subcommand)]
+    pub(super) cmd: ShellCommand,
+}
+
+#[derive(Parser, Debug)]
+pub(super) enum ShellCommand {
+    /// Reboot the camera, the shell reconnects once it is back
+    Reboot,
+    /// Print the model and firmware of the camera
+    Version,
+    /// Move the camera
+    Ptz {
+        /// The direction to move
+        #[arg(value_parser = ["left", "right", "up", "down", "stop"])]
+        direction: String,
+        /// The amount to move
+        #[arg(default_value = "32")]
+        amount: u32,
+        /// The speed to move at
+        #[arg(short, long, default_value = "32")]
+        speed: u32,
+    },
+    /// Move the camera to a preset
+    Preset { preset_id: u8 },
+    /// Zoom to a level such as 1.5
+    Zoom { amount: f32 },
+    /// Turn the status light on or off
+    Led {
+        #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
+        on: bool,
+    },
+    /// Turn the PIR on or off
+    Pir {
+        #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
+        on: bool,
+    },
+    /// Sound the siren once
+    Siren,
+    /// Play an audio file through the camera's speaker
+    #[cfg(feature = "gstreamer")]
+    Talk {
+        /// The file to play
+        file: std::path::PathBuf,
+        /// The volume from 0.0 to 1.0 or more to amplify
+        #[arg(short, long, default_value = "1.0")]
+        volume: f32,
+    },
+    /// Print each change of motion until `motion stop`
+    Motion {
+        #[arg(default_value = "watch", value_parser = ["watch", "stop"])]
+        action: String,
+    },
+    /// Leave the shell
+    #[command(alias = "quit")]
+    Exit,
+}
@@ -0,0 +1,238 @@
+///
+/// # Neolink Shell
+///
+/// This module handles the shell subcommand
+///
+/// The subcommand opens a prompt where commands are run on the camera over
+/// the one connection, so there is no login for each command. Type `help`
+/// for the list of commands
+///
+/// # Usage
+///
+/// ```bash
+/// neolink shell --config=config.toml CameraName
+/// ```
+///
+/// ```text
+/// > ptz left 64
+/// > led off
+/// > motion watch
+/// > exit
+/// ```
+///
+use anyhow::{Context, Result};
+use clap::Parser;
+use neolink_core::bc_protocol::Direction;
+use std::io::Write;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    task::JoinHandle,
+    time::{sleep, Duration},
+};
+
+mod cmdline;
+
+use crate::{
+    common::{MdState, NeoInstance, NeoReactor},
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+use cmdline::{Line, ShellCommand};
+
+/// Entry point for the shell subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut motion_watch: Option<JoinHandle<()>> = None;
+
+    loop {
+        print!("{}> ", opt.camera);
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() {
+            continue;
+        }
+        let cmd = match Line::try_parse_from(words) {
+            Ok(line) => line.cmd,
+            Err(e) => {
+                // Also covers `help`
+                let _ = e.print();
+                continue;
+            }
+        };
+        match cmd {
+            ShellCommand::Exit => break,
+            ShellCommand::Motion { action } => {
+                if let Some(watch) = motion_watch.take() {
+                    watch.abort();
+                }
+                if action == "watch" {
+                    motion_watch = Some(watch_motion(camera.clone()).await?);
+                }
+            }
+            cmd => {
+                // A failed command should not end the shell
+                if let Err(e) = run(&camera, cmd).await {
+                    println!("Error: {e:#}");
+                }
+            }
+        }
+    }
+
+    if let Some(watch) = motion_watch.take() {
+        watch.abort();
+    }
+    Ok(())
+}
+
+async fn run(camera: &NeoInstance, cmd: ShellCommand) -> Result<()> {
+    match cmd {
+        ShellCommand::Reboot => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.reboot()
+                            .await
+                            .context("Could not send reboot command to the camera")
+                    })
+                })
+                .await?;
+        }
+        ShellCommand::Version => {
+            let version = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.version()
+                            .await
+                            .context("Could not get the version of the camera")
+                    })
+                })
+                .await?;
+            println!(
+                "Model: {}, Firmware: {}",
+                version.model.as_deref().unwrap_or("unknown"),
+                version.firmwareVersion
+            );
+        }
+        ShellCommand::Ptz {
+            direction,
+            amount,
+            speed,
+        } => {
+            let direction = match direction.as_str() {
+                "left" => Direction::Left,
+                "right" => Direction::Right,
+                "up" => Direction::Up,
+                "down" => Direction::Down,
+                _ => Direction::Stop,
+            };
+            let speed = speed.max(1) as f32;
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.send_ptz(direction, speed)
+                            .await
+                            .context("Unable to execute PTZ move command")
+                    })
+                })
+                .await?;
+            if direction != Direction::Stop {
+                sleep(Duration::from_secs_f32(amount as f32 / speed)).await;
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.send_ptz(Direction::Stop, 0_f32)
+                                .await
+                                .context("Unable to execute PTZ move command")
+                        })
+                    })
+                    .await?;
+            }
+        }
+        ShellCommand::Preset { preset_id } => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.moveto_ptz_preset(preset_id)
+                            .await
+                            .context("Unable to move to the preset")
+                    })
+                })
+                .await?;
+        }
+        ShellCommand::Zoom { amount } => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.zoom_to((amount * 1000.0) as u32)
+                            .await
+                            .context("Unable to zoom")
+                    })
+                })
+                .await?;
+        }
+        ShellCommand::Led { on } => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.led_light_set(on)
+                            .await
+                            .context("Unable to set camera light state")
+                    })
+                })
+                .await?;
+        }
+        ShellCommand::Pir { on } => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.pir_set(on)
+                            .await
+                            .context("Unable to set camera PIR state")
+                    })
+                })
+                .await?;
+        }
+        ShellCommand::Siren => {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.siren()
+                            .await
+                            .context("Could not sound the siren of the camera")
+                    })
+                })
+                .await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        ShellCommand::Talk { file, volume } => {
+            crate::talk::play_file(camera, &file, volume).await?;
+        }
+        ShellCommand::Motion { .. } | ShellCommand::Exit => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Print the motion state each time it changes until the task is aborted
+async fn watch_motion(camera: NeoInstance) -> AnyResult<JoinHandle<()>> {
+    let mut md = camera.motion().await?;
+    Ok(tokio::spawn(async move {
+        while md.changed().await.is_ok() {
+            match &*md.borrow_and_update() {
+                MdState::Start(_, details) if details.detections.is_empty() => {
+                    println!("Motion started")
+                }
+                MdState::Start(_, details) => {
+                    println!("Motion started: {}", details.detections.join(", "))
+                }
+                MdState::Stop(_) => println!("Motion stopped"),
+                MdState::Unknown => {}
+            }
+        }
+    }))
+}
@@ -0,0 +1,135 @@
+//!
+//! # Neolink Shutdown
+//!
+//! This module stops neolink cleanly on a `SIGTERM` or `SIGINT`, so that a
+//! `docker stop` does not leave the cameras with a busy session for minutes
+//!
+//! The rtsp server stops taking new clients and sends the end of the streams
+//! to the current ones, the recordings finish their files and mqtt publishes
+//! that the cameras and neolink are offline. Then each camera is logged out.
+//! All of this has `shutdown_timeout` seconds before neolink exits anyway, a
+//! second signal exits at once
+//!
+//! ```toml
+//! shutdown_timeout = 10
+//! ```
+//!
+//! Only the commands that keep running, rtsp and mqtt, are stopped this way,
+//! the others exit on the signal as before
+//!
+use anyhow::{anyhow, Context};
+use log::*;
+use once_cell::sync::Lazy;
+use std::{
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
+
+use crate::{common::NeoReactor, AnyResult};
+
+/// Cancelled once neolink is shutting down
+static SHUTDOWN: Lazy<CancellationToken> = Lazy::new(CancellationToken::new);
+
+/// Set when neolink is stopping because something failed
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Ask the services to stop, such as when the Windows service is stopped
+pub(crate) fn request() {
+    SHUTDOWN.cancel();
+}
+
+/// Ask the services to stop because something failed, neolink exits with an
+/// error once they have stopped
+pub(crate) fn request_failed() {
+    FAILED.store(true, Ordering::Relaxed);
+    request();
+}
+
+/// An error if neolink was stopped by [`request_failed`]
+pub(crate) fn failure() -> AnyResult<()> {
+    if FAILED.load(Ordering::Relaxed) {
+        Err(anyhow!(
+            "Neolink was stopped by a failure, see the log above"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// If neolink is shutting down
+pub(crate) fn is_requested() -> bool {
+    SHUTDOWN.is_cancelled()
+}
+
+/// Wait until neolink is shutting down
+pub(crate) async fn requested() {
+    SHUTDOWN.cancelled().await
+}
+
+/// Wait for a `SIGTERM` or `SIGINT`, forever if they cannot be listened for
+async fn signal() {
+    if let Err(e) = wait_for_signal().await {
+        warn!("Not stopping cleanly on a signal: {e:?}");
+        futures::future::pending::<()>().await;
+    }
+}
+
+async fn wait_for_signal() -> AnyResult<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate =
+            signal(SignalKind::terminate()).context("Failed to listen for SIGTERM")?;
+        let mut interrupt =
+            signal(SignalKind::interrupt()).context("Failed to listen for SIGINT")?;
+        tokio::select! {
+            _ = terminate.recv() => debug!("Received SIGTERM"),
+            _ = interrupt.recv() => debug!("Received SIGINT"),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c()
+        .await
+        .context("Failed to listen for Ctrl-C")?;
+    Ok(())
+}
+
+/// Run the command until it ends or neolink is asked to stop, then give it
+/// the `shutdown_timeout` to finish and logout from the cameras
+pub(crate) async fn run<F>(command: F, reactor: NeoReactor) -> AnyResult<()>
+where
+    F: Future<Output = AnyResult<()>>,
+{
+    tokio::pin!(command);
+    tokio::select! {
+        v = &mut command => return v,
+        _ = signal() => {},
+        _ = requested() => {},
+    }
+
+    let seconds = reactor.config().await?.borrow().shutdown_timeout;
+    info!("Shutting down, waiting up to {seconds}s for the cameras to finish");
+    #[cfg(unix)]
+    crate::systemd::stopping();
+    request();
+
+    let graceful = async {
+        if let Err(e) = (&mut command).await {
+            warn!("The services did not stop cleanly: {e:?}");
+        }
+        reactor.shutdown().await
+    };
+    tokio::select! {
+        v = timeout(Duration::from_secs(seconds), graceful) => match v {
+            Ok(v) => {
+                v?;
+                info!("Logged out from the cameras");
+            }
+            Err(_) => warn!("Shutdown did not finish in {seconds}s, exiting anyway"),
+        },
+        _ = signal() => warn!("Exiting at once on the second signal"),
+    }
+    failure()
+}
@@ -0,0 +1,469 @@
+//! Places a call from a doorbell and bridges its audio until either side hangs up
+//!
+//! The call goes through the server over UDP. Its `INVITE` is answered to with
+//! digest auth when asked and is cancelled when no one answers in time
+use anyhow::{anyhow, Context};
+use neolink_core::bc_protocol::StreamKind;
+use std::net::{Ipv4Addr, UdpSocket as StdUdpSocket};
+use tokio::{
+    net::UdpSocket,
+    sync::broadcast::error::RecvError,
+    time::{sleep, sleep_until, timeout, Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::{
+    media::{Media, Rtp},
+    message::{authorization, param, sdp_audio, Message},
+};
+use crate::{common::NeoInstance, config::SipConfig, AnyResult};
+
+/// The first wait before a request is sent again, it doubles up to `T2`
+const T1: Duration = Duration::from_millis(500);
+const T2: Duration = Duration::from_secs(4);
+
+/// Call the extension and bridge the audio of the camera until the call ends
+pub(super) async fn call(
+    sip: &SipConfig,
+    camera: &NeoInstance,
+    name: &str,
+    extension: &str,
+    cancel: &CancellationToken,
+) -> AnyResult<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket
+        .connect((sip.server.as_str(), sip.port))
+        .await
+        .with_context(|| format!("Could not reach the SIP server {}:{}", sip.server, sip.port))?;
+    let local = socket.local_addr()?;
+    let host = sip.host.clone().unwrap_or_else(|| local.ip().to_string());
+    let domain = sip.domain.as_deref().unwrap_or(sip.server.as_str());
+    let target = if extension.starts_with("sip:") {
+        extension.to_string()
+    } else {
+        format!("sip:{extension}@{domain}")
+    };
+    let rtp_socket = rtp_socket()?;
+    let rtp_port = rtp_socket.local_addr()?.port();
+
+    // The talk is not needed to ring, the phone is only not heard without it
+    let talk_config = match crate::talk::talk_config(camera).await {
+        Ok(talk_config) => Some(talk_config),
+        Err(e) => {
+            log::warn!("{name}: The phone will not be heard: {e:?}");
+            None
+        }
+    };
+
+    let mut dialog = Dialog {
+        sip,
+        socket,
+        contact: format!("<sip:{}@{host}:{}>", sip.username, local.port()),
+        via: format!("{host}:{}", local.port()),
+        from: format!(
+            "\"{name}\" <sip:{}@{domain}>;tag={}",
+            sip.username,
+            random_id()
+        ),
+        to: format!("<{target}>"),
+        target,
+        call_id: format!("{}@{host}", random_id()),
+        cseq: 1,
+        remote_target: None,
+        route: vec![],
+        hung_up: false,
+    };
+    let Some(answer) = dialog.invite(&offer(&host, rtp_port)).await? else {
+        log::info!("{name}: No one answered the doorbell call");
+        return Ok(());
+    };
+    log::info!("{name}: The doorbell call was answered");
+
+    let r = bridge(
+        &mut dialog,
+        &answer,
+        camera,
+        name,
+        talk_config,
+        rtp_socket,
+        cancel,
+    )
+    .await;
+    dialog.bye().await;
+    r
+}
+
+/// Send the audio both ways until the phone hangs up or the call is too long
+async fn bridge(
+    dialog: &mut Dialog<'_>,
+    answer: &Message,
+    camera: &NeoInstance,
+    name: &str,
+    talk_config: Option<neolink_core::bc::xml::TalkConfig>,
+    rtp_socket: StdUdpSocket,
+    cancel: &CancellationToken,
+) -> AnyResult<()> {
+    let (remote_address, remote_port, alaw) = sdp_audio(&answer.body)?;
+    let rtp = Rtp {
+        socket: rtp_socket,
+        remote_address,
+        remote_port,
+        alaw,
+    };
+
+    let kinds = camera.config().await?.borrow().stream_kinds();
+    // The audio is the same in each stream so the smallest is taken
+    let kind = if kinds.contains(&StreamKind::Sub) {
+        StreamKind::Sub
+    } else {
+        kinds
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("The camera has no streams"))?
+    };
+    let mut stream = camera.stream(kind).await?;
+    let _ = timeout(
+        Duration::from_secs(2),
+        stream.config.wait_for(|config| config.aud_ready()),
+    )
+    .await;
+    let aud_format = stream.config.borrow().aud_format;
+
+    let (block_size, sample_rate) = talk_config
+        .as_ref()
+        .map(|talk_config| {
+            (
+                (talk_config.audio_config.length_per_encoder / 2) + 4,
+                talk_config.audio_config.sample_rate,
+            )
+        })
+        // The audio of the phone goes nowhere without talk
+        .unwrap_or((512, 16000));
+    let (mut media, rx) = Media::new(&rtp, aud_format, block_size, sample_rate)?;
+
+    let talking = async {
+        match talk_config {
+            Some(talk_config) => {
+                let r = camera
+                    .run_task(|cam| {
+                        let rx = rx.clone();
+                        let talk_config = talk_config.clone();
+                        Box::pin(async move {
+                            cam.talk_stream(rx, talk_config).await?;
+                            Ok(())
+                        })
+                    })
+                    .await;
+                if let Err(e) = r {
+                    log::warn!("{name}: Stopped sending the phone's audio to the camera: {e:?}");
+                }
+            }
+            None => drop(rx),
+        }
+        futures::future::pending::<()>().await
+    };
+    tokio::pin!(talking);
+    let ending = sleep(Duration::from_secs(dialog.sip.max_duration));
+    tokio::pin!(ending);
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            _ = &mut ending => {
+                log::info!("{name}: Hanging up the doorbell call after {}s", dialog.sip.max_duration);
+                return Ok(());
+            }
+            _ = &mut talking => {}
+            frame = stream.aud.recv() => match frame {
+                Ok(frame) => media.push(&frame)?,
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(anyhow!("The audio of the camera stopped")),
+            },
+            msg = dialog.recv() => {
+                let msg = msg?;
+                match (msg.method.as_str(), msg.cseq()) {
+                    ("BYE", _) => {
+                        dialog.send(&msg.ok()).await?;
+                        dialog.hung_up = true;
+                        log::info!("{name}: The phone hung up");
+                        return Ok(());
+                    }
+                    ("OPTIONS" | "INFO" | "NOTIFY", _) => dialog.send(&msg.ok()).await?,
+                    // The answer again, our ACK was lost
+                    ("", Some((cseq, "INVITE"))) if msg.status / 100 == 2 && cseq == dialog.cseq => {
+                        dialog.ack().await?
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+struct Dialog<'a> {
+    sip: &'a SipConfig,
+    socket: UdpSocket,
+    /// The `Contact` of our end
+    contact: String,
+    /// The `host:port` in our `Via`
+    via: String,
+    from: String,
+    /// Gets the tag of the phone once it answers
+    to: String,
+    /// The uri that is called
+    target: String,
+    call_id: String,
+    /// The `CSeq` of the last request
+    cseq: u32,
+    /// The `Contact` of the phone once it answers
+    remote_target: Option<String>,
+    route: Vec<String>,
+    /// The phone ended the call so it is not sent a `BYE`
+    hung_up: bool,
+}
+
+impl Dialog<'_> {
+    /// Send the `INVITE` until it is answered, refused or rings for too long
+    ///
+    /// This is `None` when no one answered
+    async fn invite(&mut self, sdp: &str) -> AnyResult<Option<Message>> {
+        let mut branch = branch();
+        let mut invite = self.request("INVITE", &self.target, &branch, &self.to, &[], sdp);
+        self.send(&invite).await?;
+        let mut authorised = false;
+        let mut interval = Some(T1);
+        let mut resend = Instant::now() + T1;
+        let give_up = Instant::now() + Duration::from_secs(self.sip.ring_timeout);
+        loop {
+            tokio::select! {
+                _ = sleep_until(resend), if interval.is_some() => {
+                    self.send(&invite).await?;
+                    let next = interval.unwrap_or(T1) * 2;
+                    interval = Some(next.min(T2));
+                    resend = Instant::now() + next.min(T2);
+                }
+                _ = sleep_until(give_up) => {
+                    return self.cancel(&branch).await;
+                }
+                msg = self.recv() => {
+                    let msg = msg?;
+                    if msg.cseq() != Some((self.cseq, "INVITE")) || msg.status == 0 {
+                        continue;
+                    }
+                    match msg.status {
+                        180 | 183 => {
+                            interval = None;
+                            log::debug!("The SIP call is ringing");
+                        }
+                        100..=199 => interval = None,
+                        200..=299 => {
+                            self.answered(&msg);
+                            self.ack().await?;
+                            return Ok(Some(msg));
+                        }
+                        401 | 407 if !authorised => {
+                            self.ack_failure(&branch, &msg).await?;
+                            let password = self.sip.password.as_deref().unwrap_or_default();
+                            let auth = authorization(&msg, "INVITE", &self.target, &self.sip.username, password)?;
+                            authorised = true;
+                            self.cseq += 1;
+                            branch = self::branch();
+                            invite = self.request("INVITE", &self.target, &branch, &self.to, &[auth], sdp);
+                            self.send(&invite).await?;
+                            interval = Some(T1);
+                            resend = Instant::now() + T1;
+                        }
+                        status => {
+                            self.ack_failure(&branch, &msg).await?;
+                            return Err(anyhow!("The call was refused with {status}"));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stop the ringing, the phone may have answered just before
+    async fn cancel(&mut self, branch: &str) -> AnyResult<Option<Message>> {
+        let cancel = self.request("CANCEL", &self.target, branch, &self.to, &[], "");
+        self.send(&cancel).await?;
+        let deadline = Instant::now() + T2;
+        while let Ok(msg) = timeout(deadline - Instant::now(), self.recv()).await {
+            let msg = msg?;
+            if msg.cseq() != Some((self.cseq, "INVITE")) || msg.status < 200 {
+                continue;
+            }
+            if msg.status < 300 {
+                self.answered(&msg);
+                self.ack().await?;
+                self.bye().await;
+            } else {
+                self.ack_failure(branch, &msg).await?;
+            }
+            break;
+        }
+        Ok(None)
+    }
+
+    /// Keep the dialog that the answer sets up
+    fn answered(&mut self, answer: &Message) {
+        if let Some(to) = answer.header("to") {
+            self.to = to.to_string();
+        }
+        self.remote_target = answer.contact().map(str::to_string);
+        // The route is the reverse of the record route for the caller
+        self.route = answer
+            .headers("record-route")
+            .flat_map(|route| route.split(','))
+            .map(|route| route.trim().to_string())
+            .collect();
+        self.route.reverse();
+    }
+
+    /// The `ACK` of an answer, which is its own transaction
+    async fn ack(&self) -> AnyResult<()> {
+        let uri = self.remote_target.as_deref().unwrap_or(&self.target);
+        let ack = self.request("ACK", uri, &branch(), &self.to, &[], "");
+        self.send(&ack).await
+    }
+
+    /// The `ACK` of a refusal, which is part of the `INVITE`
+    async fn ack_failure(&self, branch: &str, failure: &Message) -> AnyResult<()> {
+        let to = failure.header("to").unwrap_or(&self.to);
+        let ack = self.request("ACK", &self.target, branch, to, &[], "");
+        self.send(&ack).await
+    }
+
+    /// Hang up, unless the phone already did
+    async fn bye(&mut self) {
+        // Without the tag of the phone there is no call to end
+        if self.hung_up || param(&self.to, "tag").is_none() {
+            return;
+        }
+        self.hung_up = true;
+        self.cseq += 1;
+        let uri = self
+            .remote_target
+            .clone()
+            .unwrap_or_else(|| self.target.clone());
+        let bye = self.request("BYE", &uri, &branch(), &self.to, &[], "");
+        for wait in [T1, T1 * 2, T1 * 4] {
+            if self.send(&bye).await.is_err() {
+                return;
+            }
+            let replied = timeout(wait, async {
+                loop {
+                    match self.recv().await {
+                        Ok(msg) if msg.cseq() == Some((self.cseq, "BYE")) => break,
+                        Ok(_) => continue,
+                        Err(_) => break,
+                    }
+                }
+            })
+            .await;
+            if replied.is_ok() {
+                return;
+            }
+        }
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        uri: &str,
+        branch: &str,
+        to: &str,
+        extra: &[(&str, String)],
+        body: &str,
+    ) -> String {
+        // An ACK or CANCEL has the number of the INVITE
+        let mut request = format!(
+            "{method} {uri} SIP/2.0\r\n\
+            Via: SIP/2.0/UDP {};rport;branch={branch}\r\n\
+            Max-Forwards: 70\r\n\
+            From: {}\r\n\
+            To: {to}\r\n\
+            Call-ID: {}\r\n\
+            CSeq: {} {method}\r\n\
+            Contact: {}\r\n\
+            User-Agent: neolink/{}\r\n",
+            self.via,
+            self.from,
+            self.call_id,
+            self.cseq,
+            self.contact,
+            env!("NEOLINK_VERSION"),
+        );
+        if method != "CANCEL" {
+            for route in self.route.iter() {
+                request.push_str(&format!("Route: {route}\r\n"));
+            }
+        }
+        for (name, value) in extra.iter() {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if !body.is_empty() {
+            request.push_str("Content-Type: application/sdp\r\n");
+        }
+        request.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+        request
+    }
+
+    async fn send(&self, message: &str) -> AnyResult<()> {
+        self.socket.send(message.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// The next message from the server, ones that cannot be read are skipped
+    async fn recv(&self) -> AnyResult<Message> {
+        let mut buf = vec![0; 65535];
+        loop {
+            let len = self.socket.recv(&mut buf).await?;
+            match Message::parse(&buf[..len]) {
+                Ok(msg) => return Ok(msg),
+                // Keep alives are a lone CRLF
+                Err(e) if len > 4 => log::debug!("Skipped a SIP message: {e:?}"),
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+/// The sdp that offers G.711 audio on the port
+fn offer(host: &str, rtp_port: u16) -> String {
+    let session = (Uuid::new_v4().as_u128() >> 65) as u64;
+    format!(
+        "v=0\r\n\
+        o=neolink {session} {session} IN IP4 {host}\r\n\
+        s=neolink\r\n\
+        c=IN IP4 {host}\r\n\
+        t=0 0\r\n\
+        m=audio {rtp_port} RTP/AVP 0 8\r\n\
+        a=rtpmap:0 PCMU/8000\r\n\
+        a=rtpmap:8 PCMA/8000\r\n\
+        a=ptime:20\r\n\
+        a=sendrecv\r\n"
+    )
+}
+
+/// A new branch of a transaction, with the prefix of RFC 3261
+fn branch() -> String {
+    format!("z9hG4bK{}", random_id())
+}
+
+fn random_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// A socket on a free even port for the RTP, the odd one above it is RTCP
+fn rtp_socket() -> AnyResult<StdUdpSocket> {
+    for _ in 0..20 {
+        let socket = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .context("Could not find a free port for the SIP audio")?;
+        if socket.local_addr()?.port() % 2 == 0 {
+            return Ok(socket);
+        }
+    }
+    Err(anyhow!("Could not find a free even port for the SIP audio"))
+}
@@ -0,0 +1,176 @@
+//! The audio of a call
+//!
+//! The audio of the camera is sent to the phone as G.711 over RTP and the
+//! audio of the phone is made into the adpcm that the camera's talk takes
+use anyhow::{anyhow, Context};
+use crossbeam_channel::{bounded, Receiver};
+use gstreamer::{parse::launch_full, prelude::*, Caps, FlowError, ParseFlags, Pipeline, State};
+use gstreamer_app::{AppSink, AppSinkCallbacks, AppSrc};
+use gstreamer_rtsp_server::gio;
+use std::{net::UdpSocket, time::Duration};
+
+use crate::{
+    common::{push_frame, AudFormat, StampedData},
+    AnyResult,
+};
+
+/// Where the RTP goes and in what codec
+pub(super) struct Rtp {
+    /// Bound to the port of the offer, the pipeline receives on it
+    pub(super) socket: UdpSocket,
+    pub(super) remote_address: String,
+    pub(super) remote_port: u16,
+    /// `PCMA` rather than `PCMU`
+    pub(super) alaw: bool,
+}
+
+pub(super) struct Media {
+    pipeline: Pipeline,
+    source: Option<AppSrc>,
+    start: Option<Duration>,
+}
+
+impl Media {
+    /// Start the audio both ways, the adpcm for the camera is sent to the receiver
+    pub(super) fn new(
+        rtp: &Rtp,
+        aud_format: AudFormat,
+        block_size: u16,
+        sample_rate: u16,
+    ) -> AnyResult<(Self, Receiver<Vec<u8>>)> {
+        gstreamer::init()
+            .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+        let (encoding, payload, encode, depay) = if rtp.alaw {
+            ("PCMA", 8, "alawenc ! rtppcmapay", "rtppcmadepay ! alawdec")
+        } else {
+            (
+                "PCMU",
+                0,
+                "mulawenc ! rtppcmupay",
+                "rtppcmudepay ! mulawdec",
+            )
+        };
+        let mut launch_str = format!(
+            "udpsrc name=thertp caps=\"application/x-rtp,media=audio,clock-rate=8000,encoding-name={encoding},payload={payload}\" \
+            ! rtpjitterbuffer latency=60 \
+            ! {depay} \
+            ! audioconvert \
+            ! audioresample \
+            ! audio/x-raw,rate={sample_rate},channels=1 \
+            ! adpcmenc blockalign={block_size} layout=dvi \
+            ! appsink name=thesink"
+        );
+        // The phone hears nothing from a camera without a microphone
+        let decode = match aud_format {
+            AudFormat::Aac => Some((String::new(), "aacparse ! decodebin".to_string())),
+            AudFormat::Adpcm(block_size) => Some((
+                format!(
+                    " caps=\"audio/x-adpcm,layout=dvi,block_align={block_size},channels=1,rate=8000\""
+                ),
+                "adpcmdec".to_string(),
+            )),
+            AudFormat::None => None,
+        };
+        if let Some((caps, decode)) = decode.as_ref() {
+            launch_str.push_str(&format!(
+                " appsrc name=thesource format=time is-live=true{caps} \
+                ! {decode} \
+                ! audioconvert \
+                ! audioresample \
+                ! audio/x-raw,rate=8000,channels=1 \
+                ! {encode} \
+                ! udpsink host={} port={} sync=false async=false",
+                rtp.remote_address, rtp.remote_port
+            ));
+        }
+        log::debug!("{launch_str}");
+
+        let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+            .context("Unable to load the SIP pipeline ensure all gstramer plugins are installed")?;
+        let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+        pipeline
+            .by_name("thertp")
+            .ok_or_else(|| {
+                anyhow!("Cannot find udpsrc in gstreamer, check your gstreamer plugins")
+            })?
+            .set_property("socket", gio_socket(&rtp.socket)?);
+        let source = match decode {
+            Some(_) => Some(
+                pipeline
+                    .by_name("thesource")
+                    .and_then(|source| source.dynamic_cast::<AppSrc>().ok())
+                    .ok_or_else(|| {
+                        anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins")
+                    })?,
+            ),
+            None => None,
+        };
+        let sink = pipeline
+            .by_name("thesink")
+            .and_then(|sink| sink.dynamic_cast::<AppSink>().ok())
+            .ok_or_else(|| {
+                anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins")
+            })?;
+        sink.set_caps(Some(
+            &Caps::builder("audio/x-adpcm")
+                .field("layout", "dvi")
+                .field("block_align", block_size as i32)
+                .field("channels", 1i32)
+                .field("rate", sample_rate as i32)
+                .build(),
+        ));
+        let (tx, rx) = bounded(30);
+        sink.set_callbacks(
+            AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let sample = sink.pull_sample().map_err(|_| FlowError::Eos)?;
+                    let buffer = sample.buffer().ok_or(FlowError::Error)?;
+                    let map = buffer.map_readable().map_err(|_| FlowError::Error)?;
+                    // The camera takes the audio as fast as it plays so the
+                    // newest is dropped rather than delay the rest
+                    let _ = tx.try_send(map.as_slice().to_vec());
+                    Ok(gstreamer::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+        pipeline.set_state(State::Playing)?;
+        Ok((
+            Self {
+                pipeline,
+                source,
+                start: None,
+            },
+            rx,
+        ))
+    }
+
+    /// Send a frame of the camera's audio to the phone
+    pub(super) fn push(&mut self, frame: &StampedData) -> AnyResult<()> {
+        let Some(source) = self.source.as_ref() else {
+            return Ok(());
+        };
+        let start = *self.start.get_or_insert(frame.ts);
+        push_frame(source, start, frame)
+    }
+}
+
+/// A copy of the socket for udpsrc, which closes it when the pipeline stops
+fn gio_socket(socket: &UdpSocket) -> AnyResult<gio::Socket> {
+    let socket = socket.try_clone()?;
+    // SAFETY: The socket is a copy that is only owned by the gio socket
+    #[cfg(unix)]
+    let socket = unsafe { gio::Socket::from_fd(socket) }?;
+    #[cfg(windows)]
+    let socket = unsafe { gio::Socket::from_socket(socket) }?;
+    Ok(socket)
+}
+
+impl Drop for Media {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipeline.set_state(State::Null) {
+            log::warn!("Error in gstreamer when setting state to Null: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Reads and writes the SIP messages of a call
+//!
+//! Only what a caller needs is here: the requests that it sends, the replies
+//! and the `BYE` that it gets, and digest auth
+use anyhow::{anyhow, Context};
+use std::collections::HashMap;
+
+use crate::AnyResult;
+
+/// A SIP request or response
+pub(super) struct Message {
+    /// The method of a request, empty for a response
+    pub(super) method: String,
+    /// The status of a response, 0 for a request
+    pub(super) status: u16,
+    /// Header names are lower case and in their long form
+    headers: Vec<(String, String)>,
+    pub(super) body: String,
+}
+
+impl Message {
+    pub(super) fn parse(data: &[u8]) -> AnyResult<Self> {
+        let text = std::str::from_utf8(data).context("The SIP message is not text")?;
+        let (head, body) = text
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| anyhow!("The SIP message has no end of headers"))?;
+        let mut lines = head.split("\r\n");
+        let start = lines
+            .next()
+            .ok_or_else(|| anyhow!("The SIP message is empty"))?;
+        let (method, status) = match start.strip_prefix("SIP/2.0 ") {
+            Some(rest) => (
+                String::new(),
+                rest.split_whitespace()
+                    .next()
+                    .and_then(|status| status.parse().ok())
+                    .ok_or_else(|| anyhow!("The SIP status is invalid: {start}"))?,
+            ),
+            None => (
+                start
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                0,
+            ),
+        };
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (long_name(name.trim()), value.trim().to_string()))
+            .collect();
+        Ok(Self {
+            method,
+            status,
+            headers,
+            body: body.to_string(),
+        })
+    }
+
+    pub(super) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(super) fn headers<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The number and method of the `CSeq`
+    pub(super) fn cseq(&self) -> Option<(u32, &str)> {
+        let (number, method) = self.header("cseq")?.split_once(' ')?;
+        Some((number.trim().parse().ok()?, method.trim()))
+    }
+
+    /// The uri in the `Contact`
+    pub(super) fn contact(&self) -> Option<&str> {
+        uri(self.header("contact")?)
+    }
+
+    /// A `200 OK` to this request with its dialog headers
+    pub(super) fn ok(&self) -> String {
+        let mut reply = "SIP/2.0 200 OK\r\n".to_string();
+        for name in ["via", "from", "to", "call-id", "cseq"] {
+            for value in self.headers(name) {
+                reply.push_str(&format!("{}: {value}\r\n", title(name)));
+            }
+        }
+        reply.push_str("Content-Length: 0\r\n\r\n");
+        reply
+    }
+}
+
+/// The uri in a header like `"Hall" <sip:100@pbx>;tag=1`
+pub(super) fn uri(value: &str) -> Option<&str> {
+    match value.split_once('<') {
+        Some((_, rest)) => rest.split_once('>').map(|(uri, _)| uri),
+        None => value.split(';').next(),
+    }
+}
+
+/// A parameter of a header such as the `tag` of a `To`
+pub(super) fn param<'a>(value: &'a str, name: &str) -> Option<&'a str> {
+    // The parameters of the uri in the brackets are not those of the header
+    let params = value
+        .rsplit_once('>')
+        .map(|(_, rest)| rest)
+        .unwrap_or(value);
+    params.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+/// The `Authorization` of a `401` or `Proxy-Authorization` of a `407`
+pub(super) fn authorization(
+    challenge: &Message,
+    method: &str,
+    uri: &str,
+    username: &str,
+    password: &str,
+) -> AnyResult<(&'static str, String)> {
+    let (header, reply) = match challenge.status {
+        401 => ("www-authenticate", "Authorization"),
+        407 => ("proxy-authenticate", "Proxy-Authorization"),
+        status => return Err(anyhow!("{status} is not an auth challenge")),
+    };
+    let digest = challenge
+        .header(header)
+        .and_then(|value| value.strip_prefix("Digest "))
+        .ok_or_else(|| anyhow!("The server asked for auth that is not digest"))?;
+    let params = digest_params(digest);
+    let realm = params.get("realm").map(String::as_str).unwrap_or_default();
+    let nonce = params
+        .get("nonce")
+        .ok_or_else(|| anyhow!("The auth challenge has no nonce"))?;
+    if let Some(algorithm) = params.get("algorithm") {
+        if !algorithm.eq_ignore_ascii_case("md5") {
+            return Err(anyhow!("The digest algorithm {algorithm} is not supported"));
+        }
+    }
+
+    let ha1 = md5_hex(&format!("{username}:{realm}:{password}"));
+    let ha2 = md5_hex(&format!("{method}:{uri}"));
+    let mut value = format!(
+        "Digest username=\"{username}\", realm=\"{realm}\", nonce=\"{nonce}\", uri=\"{uri}\", algorithm=MD5"
+    );
+    let qop_auth = params
+        .get("qop")
+        .map(|qop| qop.split(',').any(|qop| qop.trim() == "auth"))
+        .unwrap_or(false);
+    let response = if qop_auth {
+        let cnonce = uuid::Uuid::new_v4().simple().to_string();
+        let nc = "00000001";
+        value.push_str(&format!(", qop=auth, nc={nc}, cnonce=\"{cnonce}\""));
+        md5_hex(&format!("{ha1}:{nonce}:{nc}:{cnonce}:auth:{ha2}"))
+    } else {
+        md5_hex(&format!("{ha1}:{nonce}:{ha2}"))
+    };
+    value.push_str(&format!(", response=\"{response}\""));
+    if let Some(opaque) = params.get("opaque") {
+        value.push_str(&format!(", opaque=\"{opaque}\""));
+    }
+    Ok((reply, value))
+}
+
+/// The `key=value` pairs of a digest challenge, values may be quoted
+fn digest_params(digest: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut rest = digest.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let after = after.trim_start();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        params.insert(key, value.trim().to_string());
+        rest = after.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+    params
+}
+
+fn md5_hex(text: &str) -> String {
+    format!("{:x}", md5::compute(text))
+}
+
+/// The long form of the compact header names
+fn long_name(name: &str) -> String {
+    match name.to_lowercase().as_str() {
+        "v" => "via".to_string(),
+        "f" => "from".to_string(),
+        "t" => "to".to_string(),
+        "i" => "call-id".to_string(),
+        "m" => "contact".to_string(),
+        "l" => "content-length".to_string(),
+        "c" => "content-type".to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn title(name: &str) -> &str {
+    match name {
+        "via" => "Via",
+        "from" => "From",
+        "to" => "To",
+        "call-id" => "Call-ID",
+        "cseq" => "CSeq",
+        name => name,
+    }
+}
+
+/// The address and port of the audio in an sdp and if it is `PCMA` rather than `PCMU`
+pub(super) fn sdp_audio(sdp: &str) -> AnyResult<(String, u16, bool)> {
+    let mut address = None;
+    let mut audio = None;
+    for line in sdp.lines().map(str::trim) {
+        if let Some(connection) = line.strip_prefix("c=IN IP4 ") {
+            // The connection of the audio comes after the one of the session
+            address = Some(
+                connection
+                    .split('/')
+                    .next()
+                    .unwrap_or(connection)
+                    .to_string(),
+            );
+        } else if let Some(media) = line.strip_prefix("m=audio ") {
+            let mut parts = media.split_whitespace();
+            let port = parts
+                .next()
+                .and_then(|port| port.parse::<u16>().ok())
+                .ok_or_else(|| anyhow!("The sdp audio has no port"))?;
+            let formats = parts.skip(1).collect::<Vec<_>>();
+            let alaw = match formats
+                .iter()
+                .find(|format| **format == "0" || **format == "8")
+            {
+                Some(format) => *format == "8",
+                None => return Err(anyhow!("The phone does not take PCMU or PCMA audio")),
+            };
+            audio = Some((port, alaw));
+        }
+    }
+    let (port, alaw) = audio.ok_or_else(|| anyhow!("The sdp has no audio"))?;
+    let address = address.ok_or_else(|| anyhow!("The sdp has no IPv4 address"))?;
+    Ok((address, port, alaw))
+}
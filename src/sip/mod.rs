@@ -0,0 +1,99 @@
+//!
+//! # Neolink SIP
+//!
+//! This module calls a SIP extension when the button of a doorbell is pressed
+//! so that it rings desk phones and intercom panels
+//!
+//! It is enabled with a `[sip]` table with the account to call from and the
+//! extension of each doorbell
+//!
+//! ```toml
+//! [sip]
+//! server = "pbx.local"
+//! username = "doorbell"
+//! password = "****"
+//! ring_timeout = 30
+//! max_duration = 120
+//!
+//! [[cameras]]
+//! name = "Door"
+//!   [cameras.sip]
+//!   extension = "100"
+//! ```
+//!
+//! Once answered the audio of the camera is sent to the phone and the phone
+//! is heard through the speaker of the camera with its talk. The audio is
+//! G.711 over RTP, PCMU or PCMA, whichever the phone takes. A doorbell makes
+//! one call at a time, presses during a call are ignored
+//!
+use log::*;
+use std::{collections::HashSet, sync::Arc};
+use tokio::sync::{mpsc::unbounded_channel, Mutex};
+use tokio_util::sync::CancellationToken;
+
+mod call;
+mod media;
+mod message;
+
+use crate::{
+    common::NeoReactor,
+    events::{watch, Event},
+    AnyResult,
+};
+
+/// Call the extensions of the doorbells when they are pressed until cancelled
+///
+/// The extensions are read from the config at each press so they follow its changes
+pub(crate) async fn main(reactor: NeoReactor, cancel: CancellationToken) -> AnyResult<()> {
+    let config = reactor.config().await?;
+    // The cameras that are in a call
+    let calling = Arc::new(Mutex::new(HashSet::<String>::new()));
+
+    let (tx, mut rx) = unbounded_channel();
+    let watching = watch(reactor.clone(), cancel.clone(), move |event| {
+        let _ = tx.send(event);
+    });
+    let calling_out = async {
+        while let Some(Event {
+            camera: name, kind, ..
+        }) = rx.recv().await
+        {
+            if kind != "doorbell" {
+                continue;
+            }
+            let (sip, extension) = {
+                let config = config.borrow();
+                let extension = config
+                    .cameras
+                    .iter()
+                    .find(|cam| cam.name == name)
+                    .and_then(|cam| cam.sip.extension.clone());
+                match (config.sip.clone(), extension) {
+                    (Some(sip), Some(extension)) => (sip, extension),
+                    _ => continue,
+                }
+            };
+            if !calling.lock().await.insert(name.clone()) {
+                debug!("{name}: Already calling {extension}");
+                continue;
+            }
+            let reactor = reactor.clone();
+            let calling = calling.clone();
+            let cancel = cancel.child_token();
+            tokio::spawn(async move {
+                info!("{name}: Calling {extension} for the doorbell");
+                let r = async {
+                    let camera = reactor.get(&name).await?;
+                    call::call(&sip, &camera, &name, &extension, &cancel).await
+                }
+                .await;
+                if let Err(e) = r {
+                    warn!("{name}: The doorbell call to {extension} failed: {e:?}");
+                }
+                calling.lock().await.remove(&name);
+            });
+        }
+    };
+    let (watched, _) = tokio::join!(watching, calling_out);
+    watched
+}
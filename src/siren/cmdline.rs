@@ -0,0 +1,14 @@
+use clap::Parser;
+
+/// The siren command will sound the siren of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Sound the siren for this many seconds instead of once
+    #[arg(short, long, conflicts_with = "stop")]
+    pub duration: Option<u64>,
+    /// Stop a siren that is sounding
+    #[arg(long)]
+    pub stop: bool,
+}
@@ -0,0 +1,62 @@
+///
+/// # Neolink Siren
+///
+/// This module handles the siren subcommand
+///
+/// Without options the siren sounds once. With a duration it sounds until
+/// the duration has passed, the command waits until then
+///
+/// # Usage
+///
+/// ```bash
+/// neolink siren --config=config.toml CameraName
+/// neolink siren --config=config.toml CameraName --duration=10
+/// neolink siren --config=config.toml CameraName --stop
+/// ```
+///
+use anyhow::{Context, Result};
+use tokio::time::{sleep, Duration};
+
+mod cmdline;
+
+use crate::common::{NeoInstance, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the siren subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    if opt.stop {
+        set_manual(&camera, false).await?;
+    } else if let Some(duration) = opt.duration {
+        set_manual(&camera, true).await?;
+        sleep(Duration::from_secs(duration)).await;
+        set_manual(&camera, false).await?;
+    } else {
+        camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.siren()
+                        .await
+                        .context("Could not sound the siren of the camera")
+                })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn set_manual(camera: &NeoInstance, on: bool) -> Result<()> {
+    camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.siren_manual(on)
+                    .await
+                    .context("Could not change the siren of the camera")
+            })
+        })
+        .await
+}
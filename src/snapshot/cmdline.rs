@@ -0,0 +1,16 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The snapshot command will save a jpeg of the camera's current view
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera to get the snapshot from. Must be a name in the config
+    pub camera: String,
+    /// The path of the jpeg. If not given, or `-`, the jpeg is written to stdout
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub output: Option<PathBuf>,
+    /// The stream to take the snapshot from
+    #[arg(short, long, default_value = "main", value_parser = ["main", "sub", "extern"])]
+    pub stream: String,
+}
@@ -0,0 +1,67 @@
+///
+/// # Neolink Snapshot
+///
+/// This module handles the snapshot subcommand
+///
+/// The jpeg is taken with the camera's SNAP command. If the camera does not
+/// support it, and neolink was built with gstreamer, a keyframe of the
+/// stream is decoded instead
+///
+/// # Usage
+///
+/// ```bash
+/// neolink snapshot --config=config.toml CameraName --output=snap.jpg
+/// neolink snapshot --config=config.toml CameraName --stream=sub > snap.jpg
+/// ```
+///
+use anyhow::{anyhow, Result};
+use neolink_core::bc_protocol::StreamKind;
+use tokio::io::AsyncWriteExt;
+
+mod cmdline;
+
+use crate::{common::NeoReactor, AnyResult};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the snapshot subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let stream = match opt.stream.as_str() {
+        "main" => StreamKind::Main,
+        "sub" => StreamKind::Sub,
+        "extern" => StreamKind::Extern,
+        other => return Err(anyhow!("Unknown stream {other}")),
+    };
+
+    let snap = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let data = cam.get_snapshot_from(stream).await?;
+                AnyResult::Ok(data)
+            })
+        })
+        .await;
+    let jpeg = match snap {
+        Ok(jpeg) => jpeg,
+        #[cfg(feature = "gstreamer")]
+        Err(e) => {
+            log::debug!("SNAP command failed, decoding the stream instead: {e:?}");
+            crate::image::jpeg_from_stream(&camera, stream).await?
+        }
+        #[cfg(not(feature = "gstreamer"))]
+        Err(e) => return Err(e),
+    };
+
+    match opt.output {
+        Some(path) if path.as_os_str() != "-" => tokio::fs::write(path, jpeg).await?,
+        _ => {
+            let mut stdout = tokio::io::stdout();
+            stdout.write_all(&jpeg).await?;
+            stdout.flush().await?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,12 +1,27 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
 
-fn onoff_parse(src: &str) -> Result<bool> {
+/// What to do with the status light
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Print the current mode
+    Get,
+    /// Turn the light on
+    On,
+    /// Turn the light off
+    Off,
+    /// Follow the camera's night schedule
+    Auto,
+}
+
+fn action_parse(src: &str) -> Result<Action> {
     match src {
-        "true" | "on" | "yes" => Ok(true),
-        "false" | "off" | "no" => Ok(false),
+        "get" => Ok(Action::Get),
+        "true" | "on" | "yes" => Ok(Action::On),
+        "false" | "off" | "no" => Ok(Action::Off),
+        "auto" => Ok(Action::Auto),
         _ => Err(anyhow!(
-            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            "Could not understand {}, check your input, should be get, auto, true/false, on/off or yes/no",
             src
         )),
     }
@@ -17,7 +32,8 @@ fn onoff_parse(src: &str) -> Result<bool> {
 pub struct Opt {
     /// The name of the camera to change the lights of. Must be a name in the config
     pub camera: String,
-    /// Whether to turn the light on or off
-    #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
-    pub on: bool,
+    /// Whether to turn the light on or off, let the camera turn it off at night with auto
+    /// or print the current mode with get
+    #[arg(value_parser = action_parse, action = clap::ArgAction::Set, name = "get|on|off|auto")]
+    pub action: Action,
 }
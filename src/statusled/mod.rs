@@ -13,13 +13,19 @@
 /// neolink status-light --config=config.toml CameraName on
 /// # Or off
 /// neolink status-light --config=config.toml CameraName off
+/// # Or let the camera turn it off at night
+/// neolink status-light --config=config.toml CameraName auto
+/// # Print the current mode
+/// neolink status-light --config=config.toml CameraName get
 /// ```
 ///
 use anyhow::{Context, Result};
+use neolink_core::bc_protocol::LightState;
 
 mod cmdline;
 
 use crate::common::NeoReactor;
+use cmdline::Action;
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the ledstatus subcommand
@@ -28,12 +34,37 @@ pub(crate) use cmdline::Opt;
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
-    let on = opt.on;
+    let state = match opt.action {
+        Action::Get => {
+            let state = camera
+                .run_task(|camera| {
+                    Box::pin(async move {
+                        camera
+                            .led_light_get()
+                            .await
+                            .context("Unable to get camera light state")
+                    })
+                })
+                .await?;
+            println!(
+                "{}",
+                match state {
+                    LightState::On => "on",
+                    LightState::Off => "off",
+                    LightState::Auto => "auto",
+                }
+            );
+            return Ok(());
+        }
+        Action::On => LightState::On,
+        Action::Off => LightState::Off,
+        Action::Auto => LightState::Auto,
+    };
     camera
         .run_task(|camera| {
             Box::pin(async move {
                 camera
-                    .led_light_set(on)
+                    .led_light_set_state(state)
                     .await
                     .context("Unable to set camera light state")
             })
@@ -0,0 +1,152 @@
+//!
+//! # Neolink Supervisor
+//!
+//! This module lets neolink run as a Home Assistant add-on without a toml
+//! config
+//!
+//! When neolink is started without `--config` under the Supervisor, which
+//! gives the add-on a `SUPERVISOR_TOKEN`, the config is read from the
+//! options of the add-on in `/data/options.json`. The options are the same as
+//! the tables of the toml config but written as json
+//!
+//! ```json
+//! {
+//!   "cameras": [
+//!     { "name": "Door", "username": "admin", "password": "****", "uid": "95270000ABCDEFGH" }
+//!   ]
+//! }
+//! ```
+//!
+//! Without an `mqtt` option the broker is taken from the mqtt service of the
+//! Supervisor, such as the Mosquitto add-on. Without an `http` option the web
+//! ui is served on the ingress port of the add-on so that it opens in the
+//! sidebar of Home Assistant. Requests through ingress are already
+//! authenticated by Home Assistant so they skip the users of the config
+//!
+use anyhow::{anyhow, Context};
+use log::*;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{net::IpAddr, path::Path};
+
+use crate::{config::Config, http::server::HttpRequest, AnyResult};
+
+/// Where the Supervisor writes the options of the add-on
+pub(crate) const OPTIONS: &str = "/data/options.json";
+const SUPERVISOR: &str = "http://supervisor";
+/// The address of the ingress proxy of the Supervisor
+const INGRESS_PEER: [u8; 4] = [172, 30, 32, 2];
+
+#[derive(Deserialize)]
+struct Reply<T> {
+    result: String,
+    #[serde(default)]
+    message: Option<String>,
+    data: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct MqttService {
+    host: String,
+    port: u16,
+    #[serde(default)]
+    ssl: bool,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AddonInfo {
+    #[serde(default)]
+    ingress: bool,
+    #[serde(default)]
+    ingress_port: u16,
+}
+
+/// If neolink is running as an add-on under the Supervisor
+pub(crate) fn detected() -> bool {
+    std::env::var_os("SUPERVISOR_TOKEN").is_some() && Path::new(OPTIONS).exists()
+}
+
+/// If the request came through the ingress proxy of Home Assistant
+pub(crate) fn is_ingress(request: &HttpRequest) -> bool {
+    request.peer == IpAddr::from(INGRESS_PEER)
+        && request.header("x-ingress-path").is_some()
+        && std::env::var_os("SUPERVISOR_TOKEN").is_some()
+}
+
+/// Read the config from the options of the add-on, filling the mqtt and http
+/// from the Supervisor when they are not given
+pub(crate) async fn config() -> AnyResult<Config> {
+    let options = tokio::fs::read_to_string(OPTIONS)
+        .await
+        .with_context(|| format!("Failed to read {OPTIONS}"))?;
+    let mut options: Value = serde_json::from_str(&options)
+        .with_context(|| format!("Failed to parse the {OPTIONS} options"))?;
+    let table = options
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("The {OPTIONS} options are not an object"))?;
+
+    let client = Client::new();
+    if !table.contains_key("mqtt") {
+        match service::<MqttService>(&client, "services/mqtt").await {
+            Ok(mqtt) => {
+                info!("Using the mqtt broker at {}:{}", mqtt.host, mqtt.port);
+                let mut server = json!({
+                    "broker_addr": mqtt.host,
+                    "port": mqtt.port,
+                    "tls": mqtt.ssl,
+                });
+                if let (Some(username), Some(password)) = (mqtt.username, mqtt.password) {
+                    server["credentials"] = json!([username, password]);
+                }
+                table.insert("mqtt".to_string(), server);
+            }
+            Err(e) => debug!("No mqtt service from the Supervisor: {e:?}"),
+        }
+    }
+    if !table.contains_key("http") {
+        match service::<AddonInfo>(&client, "addons/self/info").await {
+            Ok(AddonInfo {
+                ingress: true,
+                ingress_port,
+            }) if ingress_port > 0 => {
+                info!("Serving the web ui on the ingress port {ingress_port}");
+                table.insert(
+                    "http".to_string(),
+                    json!({ "port": ingress_port, "ui": true }),
+                );
+            }
+            Ok(_) => {}
+            Err(e) => debug!("No add-on info from the Supervisor: {e:?}"),
+        }
+    }
+
+    serde_json::from_value(options)
+        .with_context(|| format!("Failed to parse the {OPTIONS} options as a config"))
+}
+
+/// Get the data of an endpoint of the Supervisor api
+async fn service<T: for<'de> Deserialize<'de>>(client: &Client, path: &str) -> AnyResult<T> {
+    let token = std::env::var("SUPERVISOR_TOKEN").context("No SUPERVISOR_TOKEN")?;
+    let body = client
+        .get(format!("{SUPERVISOR}/{path}"))
+        .bearer_auth(token)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let reply: Reply<T> = serde_json::from_str(&body)
+        .with_context(|| format!("The Supervisor replied to {path} with {body}"))?;
+    match (reply.result.as_str(), reply.data) {
+        ("ok", Some(data)) => Ok(data),
+        _ => Err(anyhow!(
+            "The Supervisor replied to {path} with {}",
+            reply.message.unwrap_or(reply.result)
+        )),
+    }
+}
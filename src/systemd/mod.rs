@@ -0,0 +1,133 @@
+//!
+//! # Neolink Systemd
+//!
+//! This module tells systemd when neolink is ready and pings its watchdog so
+//! that a unit with `Type=notify` and `WatchdogSec=` is restarted when
+//! neolink stalls rather than only when it exits
+//!
+//! It does nothing unless systemd has set `NOTIFY_SOCKET`, which it does for
+//! `Type=notify` units
+//!
+//! ```ini
+//! [Service]
+//! Type=notify
+//! WatchdogSec=60
+//! ExecStart=/usr/local/bin/neolink rtsp --config /usr/local/etc/neolink_config.toml
+//! Restart=on-failure
+//! ```
+//!
+//! The watchdog is only pinged while every camera's thread answers in time,
+//! so a stuck camera task or a stalled runtime both stop the pings. A camera
+//! that is disconnected or has given up on its login still answers and does
+//! not stop them
+//!
+use anyhow::{anyhow, Context};
+use log::*;
+use std::os::unix::{ffi::OsStrExt, net::UnixDatagram};
+use tokio::time::{sleep, timeout, Duration};
+
+use crate::{
+    common::{NeoCamThreadState, NeoReactor},
+    AnyResult,
+};
+
+/// How long a camera's thread has to answer a liveness check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// If neolink was started by systemd as a `Type=notify` unit
+pub(crate) fn detected() -> bool {
+    std::env::var_os("NOTIFY_SOCKET").is_some()
+}
+
+/// Send READY and then ping the watchdog until the program stops
+pub(crate) async fn main(reactor: NeoReactor) -> AnyResult<()> {
+    notify("READY=1")?;
+    info!("Notified systemd that neolink is ready");
+
+    let Some(interval) = watchdog_interval() else {
+        debug!("The systemd watchdog is not enabled");
+        return Ok(());
+    };
+    // Ping twice per interval as systemd recommends
+    let interval = interval / 2;
+    debug!("Pinging the systemd watchdog every {interval:?}");
+    loop {
+        match liveness(&reactor).await {
+            Ok(status) => {
+                notify(&format!("WATCHDOG=1\nSTATUS={status}"))?;
+            }
+            Err(e) => {
+                warn!("Not pinging the systemd watchdog: {e}");
+                notify(&format!("STATUS={e}"))?;
+            }
+        }
+        sleep(interval).await;
+    }
+}
+
+/// Tell systemd that neolink is stopping
+pub(crate) fn stopping() {
+    if detected() {
+        let _ = notify("STOPPING=1");
+    }
+}
+
+/// The watchdog interval when it is enabled for this process
+fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    Some(Duration::from_micros(usec)).filter(|d| !d.is_zero())
+}
+
+/// Check that every camera's thread answers, the status is how many are connected
+async fn liveness(reactor: &NeoReactor) -> AnyResult<String> {
+    let names = timeout(CHECK_TIMEOUT, reactor.config())
+        .await
+        .map_err(|_| anyhow!("The cameras did not answer in time"))??
+        .borrow()
+        .cameras
+        .iter()
+        .filter(|cam| cam.enabled)
+        .map(|cam| cam.name.clone())
+        .collect::<Vec<_>>();
+    let mut connected = 0;
+    for name in names.iter() {
+        let state = timeout(CHECK_TIMEOUT, async {
+            let camera = reactor.get(name).await?;
+            camera.get_state().await
+        })
+        .await
+        .map_err(|_| anyhow!("{name}: The camera did not answer in time"))?;
+        if let Ok(NeoCamThreadState::Connected) = state {
+            connected += 1;
+        }
+    }
+    Ok(format!("{connected}/{} cameras connected", names.len()))
+}
+
+/// Send a message to the notify socket of systemd
+fn notify(message: &str) -> AnyResult<()> {
+    let path = std::env::var_os("NOTIFY_SOCKET").context("NOTIFY_SOCKET is not set")?;
+    let socket = UnixDatagram::unbound()?;
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        // An abstract socket
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(message.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(anyhow!("Abstract notify sockets are only on linux"));
+        }
+    } else {
+        socket.send_to(message.as_bytes(), &path)?;
+    }
+    Ok(())
+}
@@ -1,6 +1,27 @@
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
+
+/// Parse a latency such as `80ms` or `0.2s`, plain numbers are milliseconds
+fn parse_latency(src: &str) -> Result<Duration> {
+    let (number, scale) = if let Some(ms) = src.strip_suffix("ms") {
+        (ms, 1e-3)
+    } else if let Some(s) = src.strip_suffix('s') {
+        (s, 1.0)
+    } else {
+        (src, 1e-3)
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Expected a latency like 80ms or 0.2s"))?;
+    if number <= 0.0 {
+        return Err(anyhow!("The latency must be more than zero"));
+    }
+    Ok(Duration::from_secs_f64(number * scale))
+}
 
 /// The talk command will send audio for the camera to say
 ///
@@ -13,11 +34,11 @@ pub struct Opt {
     /// The name of the camera to talk through. Must be a name in the config
     pub camera: String,
     /// The path to the audio file.
-    #[arg(short, long, value_parser = PathBuf::from_str, conflicts_with = "microphone")]
+    #[arg(short, long, value_parser = PathBuf::from_str, conflicts_with_all = ["microphone", "mic"])]
     pub file_path: Option<PathBuf>,
     /// Use the microphone as the source. Defaults to autoaudiosrc - Which microphone depends
     /// on [gstreamer](https://gstreamer.freedesktop.org/documentation/autodetect/autoaudiosrc.html?gi-language=c#autoaudiosrc-page)
-    #[arg(short, long, conflicts_with = "file_path")]
+    #[arg(short, long, conflicts_with_all = ["file_path", "mic"])]
     pub microphone: bool,
     /// Use a specific microphone like "alsasrc device=hw:1"
     #[arg(
@@ -27,6 +48,14 @@ pub struct Opt {
         conflicts_with = "file_path"
     )]
     pub input_src: String,
+    /// Talk live from a microphone by its name as listed by the system such as the
+    /// ALSA, PulseAudio or WASAPI device name. `default` uses the system default
+    #[arg(long, num_args = 0..=1, default_missing_value = "default", value_name = "DEVICE")]
+    pub mic: Option<String>,
+    /// How much audio the microphone buffers such as `80ms`. Lower is more responsive
+    /// but may crackle
+    #[arg(long, default_value = "80ms", value_parser = parse_latency, requires = "mic")]
+    pub latency: Duration,
     /// Use to change the volume of the input
     #[arg(short, long, default_value = "1.0")]
     pub volume: f32,
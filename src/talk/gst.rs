@@ -2,10 +2,14 @@ use crate::AnyResult;
 use anyhow::{anyhow, Context, Result};
 use crossbeam_channel::{bounded, Receiver, Sender};
 use gstreamer::{
-    element_error, parse::launch_full, prelude::*, Caps, ClockTime, FlowError, FlowSuccess,
-    MessageView, ParseFlags, Pipeline, ResourceError, State,
+    element_error, glib, parse::launch_full, prelude::*, Caps, ClockTime, DeviceMonitor, Element,
+    ElementFactory, FlowError, FlowSuccess, MessageView, ParseFlags, Pipeline, ResourceError,
+    State, StructureRef,
 };
 use gstreamer_app::{AppSink, AppSinkCallbacks};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::{convert::TryFrom, time::Duration};
 use tokio::task::JoinSet;
 
 use byte_slice_cast::*;
@@ -21,6 +25,117 @@ pub(super) fn from_input(
     input(pipeline)
 }
 
+/// Decode an audio file
+///
+/// The path is set on the `filesrc` rather than written into the launch
+/// string so that spaces, quotes or `!` in it are not parsed as a pipeline
+#[allow(clippy::type_complexity)]
+pub(super) fn from_file(
+    path: &Path,
+    volume: f32,
+    block_align: u16,
+    sample_rate: u16,
+) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
+    let pipeline = create_pipeline("filesrc name=thefile", volume, block_align, sample_rate)?;
+    let filesrc = pipeline
+        .by_name("thefile")
+        .expect("There shoud be a `thefile`");
+    filesrc.set_property("location", path);
+    input(pipeline)
+}
+
+/// Capture from a microphone, the level of the audio is shown on stderr
+///
+/// `device` is matched against the names of the system's audio sources,
+/// `default` uses whichever gstreamer picks
+#[allow(clippy::type_complexity)]
+pub(super) fn from_mic(
+    device: &str,
+    latency: Duration,
+    volume: f32,
+    block_align: u16,
+    sample_rate: u16,
+) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    let launch_str = format!(
+        "audioconvert name=themicin \
+        ! audioresample \
+        ! audio/x-raw,rate={},channels=1 \
+        ! volume volume={:.2} \
+        ! level name=thelevel interval={} \
+        ! queue \
+        ! adpcmenc blockalign={} layout=dvi \
+        ! appsink name=thesink",
+        sample_rate,
+        volume,
+        Duration::from_millis(100).as_nanos(),
+        block_align
+    );
+    log::info!("{}", launch_str);
+    let pipeline = parse_pipeline(&launch_str, block_align, sample_rate)?;
+
+    let mic = mic_element(device, latency)?;
+    let mic_in = pipeline
+        .by_name("themicin")
+        .expect("There shoud be a `themicin`");
+    pipeline.add(&mic)?;
+    mic.link(&mic_in)
+        .context("The microphone cannot be linked to the pipeline")?;
+
+    input(pipeline)
+}
+
+/// Find the microphone by name and set its buffer to the latency
+fn mic_element(device: &str, latency: Duration) -> Result<Element> {
+    let element = if device == "default" {
+        ElementFactory::make("autoaudiosrc")
+            .name("themic")
+            .build()
+            .context("Unable to create autoaudiosrc ensure all gstramer plugins are installed")?
+    } else {
+        let monitor = DeviceMonitor::new();
+        monitor.add_filter(Some("Audio/Source"), None);
+        monitor.start().context("Unable to list the microphones")?;
+        let devices = monitor.devices();
+        monitor.stop();
+        let found = devices
+            .iter()
+            .find(|mic| mic.display_name() == device)
+            .or_else(|| {
+                devices
+                    .iter()
+                    .find(|mic| mic.display_name().eq_ignore_ascii_case(device))
+            });
+        let Some(found) = found else {
+            let names = devices
+                .iter()
+                .map(|mic| format!("\"{}\"", mic.display_name()))
+                .collect::<Vec<_>>();
+            return Err(anyhow!(
+                "No microphone called \"{}\", the microphones are: {}",
+                device,
+                names.join(", ")
+            ));
+        };
+        found
+            .create_element(Some("themic"))
+            .with_context(|| format!("Unable to open the microphone {}", device))?
+    };
+
+    // The audio sources of ALSA, PulseAudio, WASAPI etc all share these
+    // properties, autoaudiosrc picks its own
+    let micros = |duration: Duration| i64::try_from(duration.as_micros()).unwrap_or(i64::MAX);
+    if element.find_property("buffer-time").is_some() {
+        element.set_property("buffer-time", micros(latency));
+        element.set_property("latency-time", micros(latency / 4).max(1000));
+    } else {
+        log::debug!("The microphone {} cannot set its latency", device);
+    }
+    Ok(element)
+}
+
 #[allow(clippy::type_complexity)]
 fn input(pipeline: Pipeline) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
     let appsink = get_sink(&pipeline)?;
@@ -46,6 +161,11 @@ fn start_pipeline(pipeline: Pipeline) -> Result<()> {
     for msg in bus.iter_timed(ClockTime::NONE) {
         match msg.view() {
             MessageView::Eos(..) => break,
+            MessageView::Element(element) => {
+                if let Some(level) = element.structure().filter(|s| s.name() == "level") {
+                    show_level(level);
+                }
+            }
             MessageView::Error(err) => {
                 pipeline
                     .set_state(State::Null)
@@ -62,10 +182,39 @@ fn start_pipeline(pipeline: Pipeline) -> Result<()> {
     pipeline
         .set_state(State::Null)
         .context("Error in gstreamer when setting state to Null")?;
+    if pipeline.by_name("thelevel").is_some() && std::io::stderr().is_terminal() {
+        eprintln!();
+    }
 
     Ok(())
 }
 
+/// Draw the peak level from a `level` message as a bar on stderr
+fn show_level(level: &StructureRef) {
+    if !std::io::stderr().is_terminal() {
+        return;
+    }
+    let Ok(peaks) = level.get::<glib::ValueArray>("peak") else {
+        return;
+    };
+    let peak = peaks
+        .iter()
+        .filter_map(|peak| peak.get::<f64>().ok())
+        .fold(f64::NEG_INFINITY, f64::max);
+    // Show -60dB to 0dB, quieter is silence
+    const WIDTH: usize = 40;
+    let filled = (((peak + 60.0) / 60.0).clamp(0.0, 1.0) * WIDTH as f64).round() as usize;
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(
+        stderr,
+        "\r[{}{}] {:>6.1} dB",
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        peak.max(-99.9)
+    );
+    let _ = stderr.flush();
+}
+
 fn get_sink(pipeline: &Pipeline) -> Result<AppSink> {
     let sink = pipeline
         .by_name("thesink")
@@ -155,11 +304,15 @@ fn create_pipeline(
     );
 
     log::info!("{}", launch_str);
+    parse_pipeline(&launch_str, block_align, sample_rate)
+}
 
+/// Parse the launch string and set the ADPCM caps on its `thesink`
+fn parse_pipeline(launch_str: &str, block_align: u16, sample_rate: u16) -> Result<Pipeline> {
     // Parse the pipeline we want to probe from a static in-line string.
     // Here we give our audiotestsrc a name, so we can retrieve that element
     // from the resulting pipeline.
-    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+    let pipeline = launch_full(launch_str, None, ParseFlags::empty())
         .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?;
     let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
         anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
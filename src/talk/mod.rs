@@ -9,22 +9,96 @@
 ///
 /// ```bash
 /// neolink talk --config=config.toml --adpcm-file=data.adpcm --sample-rate=16000 --block-size=512 CameraName
+/// # Talk live through a microphone
+/// neolink talk --config=config.toml --mic="USB Audio" --latency=80ms CameraName
 /// ```
 ///
 use anyhow::{anyhow, Context, Result};
 use neolink_core::bc::xml::TalkConfig;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 mod cmdline;
 mod gst;
 
-use crate::common::NeoReactor;
+use crate::common::{NeoInstance, NeoReactor};
 pub(crate) use cmdline::Opt;
 
+/// Where the audio comes from
+enum Source {
+    /// An audio file in any format that gstreamer can decode
+    File(PathBuf),
+    /// A gstreamer source element such as `autoaudiosrc`
+    Launch(String),
+    /// A live microphone by name with a level meter
+    Mic { device: String, latency: Duration },
+}
+
 /// Entry point for the talk subcommand
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
+    let source = match (&opt.file_path, &opt.microphone, &opt.mic) {
+        (Some(path), false, None) => Source::File(path.clone()),
+        (None, true, None) => Source::Launch(opt.input_src.clone()),
+        (None, false, Some(device)) => Source::Mic {
+            device: device.clone(),
+            latency: opt.latency,
+        },
+        _ => {
+            return Err(anyhow!(
+                "One of --file-path, --microphone or --mic is needed"
+            ))
+        }
+    };
+    talk(&camera, source, opt.volume).await
+}
+
+/// Play a server-local audio file through the camera's speaker
+///
+/// Returns once the whole file has been sent
+pub(crate) async fn play_file(camera: &NeoInstance, path: &Path, volume: f32) -> Result<()> {
+    talk(camera, Source::File(path.to_path_buf()), volume).await
+}
+
+/// Send the audio of the source to the camera
+async fn talk(camera: &NeoInstance, source: Source, volume: f32) -> Result<()> {
+    let talk_config = talk_config(camera).await?;
+    let block_size = (talk_config.audio_config.length_per_encoder / 2) + 4;
+    let sample_rate = talk_config.audio_config.sample_rate;
+
+    let (mut set, rx) = match &source {
+        Source::File(path) => gst::from_file(path, volume, block_size, sample_rate)
+            .with_context(|| format!("Failed to setup gst with the file: {}", path.display()))?,
+        Source::Launch(input_src) => gst::from_input(input_src, volume, block_size, sample_rate)
+            .with_context(|| format!("Failed to setup gst with the input: {}", input_src))?,
+        Source::Mic { device, latency } => {
+            gst::from_mic(device, *latency, volume, block_size, sample_rate)
+                .with_context(|| format!("Failed to setup gst with the microphone: {}", device))?
+        }
+    };
+
+    camera
+        .run_task(|cam| {
+            let rx = rx.clone();
+            let talk_config = talk_config.clone();
+            Box::pin(async move {
+                cam.talk_stream(rx, talk_config).await?;
+                Ok(())
+            })
+        })
+        .await
+        .context("Talk stream ended early")?;
+
+    drop(rx);
+    while set.join_next().await.is_some() {}
+
+    Ok(())
+}
+
+/// The talk config of the camera for sending it adpcm
+pub(crate) async fn talk_config(camera: &NeoInstance) -> Result<TalkConfig> {
     let config = camera.config().await?.borrow().clone();
     let name = config.name.clone();
 
@@ -69,37 +143,5 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             name
         ));
     }
-
-    let (mut set, rx) = match (&opt.file_path, &opt.microphone) {
-        (Some(path), false) => gst::from_input(
-            &format!(
-                "filesrc location={}",
-                path.to_str().expect("File path not UTF8 complient")
-            ),
-            opt.volume,
-            block_size,
-            sample_rate,
-        )
-        .with_context(|| format!("Failed to setup gst with the file: {:?}", path))?,
-        (None, true) => gst::from_input(&opt.input_src, opt.volume, block_size, sample_rate)
-            .context("Failed to setup gst using the microphone")?,
-        _ => unreachable!(),
-    };
-
-    camera
-        .run_task(|cam| {
-            let rx = rx.clone();
-            let talk_config = talk_config.clone();
-            Box::pin(async move {
-                cam.talk_stream(rx, talk_config).await?;
-                Ok(())
-            })
-        })
-        .await
-        .context("Talk stream ended early")?;
-
-    drop(rx);
-    while set.join_next().await.is_some() {}
-
-    Ok(())
+    Ok(talk_config)
 }
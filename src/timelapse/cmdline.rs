@@ -0,0 +1,28 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::utils::parse_duration;
+
+/// The timelapse command will take a frame periodically and join them into an mp4
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Time between frames such as `60` or `5m`
+    #[arg(short, long, value_parser = parse_duration)]
+    pub interval: Duration,
+    /// How long to capture for such as `24h`
+    #[arg(short, long, value_parser = parse_duration)]
+    pub duration: Duration,
+    /// The path of the mp4
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub output: PathBuf,
+    /// Frames per second of the timelapse
+    #[arg(long, default_value = "25")]
+    pub fps: u32,
+    /// The stream to take the frames from
+    #[arg(short, long, default_value = "main", value_parser = ["main", "sub", "extern"])]
+    pub stream: String,
+}
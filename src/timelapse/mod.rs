@@ -0,0 +1,122 @@
+///
+/// # Neolink Timelapse
+///
+/// This module handles the timelapse subcommand
+///
+/// A keyframe of the stream is taken at each interval and muxed into an mp4
+/// at the chosen frame rate. Keyframes can be decoded on their own so the
+/// video is not re-encoded. The stream is only started for each frame so the
+/// camera is not streaming between frames
+///
+/// # Usage
+///
+/// ```bash
+/// neolink timelapse --config=config.toml CameraName --interval=60 --duration=24h --output=day.mp4
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use futures::stream::StreamExt;
+use neolink_core::bc_protocol::StreamKind;
+use tokio::time::{interval_at, timeout, Duration, Instant, MissedTickBehavior};
+use tokio_stream::wrappers::BroadcastStream;
+
+mod cmdline;
+
+use crate::{
+    common::{AudFormat, NeoInstance, NeoReactor, StampedData, VidFormat},
+    record::Recorder,
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the timelapse subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let stream_kind = match opt.stream.as_str() {
+        "main" => StreamKind::Main,
+        "sub" => StreamKind::Sub,
+        "extern" => StreamKind::Extern,
+        other => return Err(anyhow!("Unknown stream {other}")),
+    };
+    let frame_duration = Duration::from_secs(1) / opt.fps.max(1);
+    let total = (opt.duration.as_secs_f64() / opt.interval.as_secs_f64().max(1.0)).ceil() as u64;
+
+    let start = Instant::now();
+    let end = start + opt.duration;
+    let mut ticks = interval_at(start, opt.interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut recorder: Option<(Recorder, VidFormat)> = None;
+    let mut count = 0;
+
+    while ticks.tick().await < end {
+        let (vid_format, data) = match keyframe(&camera, stream_kind).await {
+            Ok(frame) => frame,
+            Err(e) => {
+                // One missed frame should not end a long timelapse
+                log::warn!("{}: Could not take a frame: {e:#}", opt.camera);
+                continue;
+            }
+        };
+        let (recorder, format) = match recorder.as_mut() {
+            Some(recorder) => recorder,
+            None => recorder.insert((
+                Recorder::new(vid_format, AudFormat::None, &opt.output)?,
+                vid_format,
+            )),
+        };
+        if *format != vid_format {
+            log::warn!(
+                "{}: The video format changed, skipping the frame",
+                opt.camera
+            );
+            continue;
+        }
+        recorder.push_video(&StampedData {
+            keyframe: true,
+            data,
+            ts: frame_duration * count,
+        })?;
+        count += 1;
+        log::info!("{}: Frame {count} of {total}", opt.camera);
+    }
+
+    let (recorder, _) = recorder.ok_or_else(|| anyhow!("No frames were taken"))?;
+    tokio::task::spawn_blocking(move || recorder.finish())
+        .await?
+        .context("Could not finish the mp4")?;
+    log::info!("{}: Saved {}", opt.camera, opt.output.display());
+    Ok(())
+}
+
+/// Start the stream just long enough to get its next keyframe
+async fn keyframe(camera: &NeoInstance, stream_kind: StreamKind) -> AnyResult<(VidFormat, Bytes)> {
+    let stream = camera.stream(stream_kind).await?;
+
+    let mut stream_config = stream.config.clone();
+    let vid_format = timeout(
+        Duration::from_secs(15),
+        stream_config.wait_for(|config| config.vid_ready()),
+    )
+    .await
+    .with_context(|| "Timed out waiting for the stream")??
+    .vid_format;
+
+    let mut frames = BroadcastStream::new(stream.vid.resubscribe());
+    let frame = timeout(Duration::from_secs(15), async {
+        while let Some(frame) = frames.next().await {
+            if let Ok(frame) = frame {
+                if frame.keyframe {
+                    return Some(frame.data);
+                }
+            }
+        }
+        None
+    })
+    .await
+    .with_context(|| "Timed out waiting for a keyframe")?
+    .with_context(|| "Stream ended before a keyframe")?;
+    Ok((vid_format, frame))
+}
@@ -0,0 +1,45 @@
+use clap::Parser;
+
+/// The users command will list and change the user accounts of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+
+    #[command(subcommand)]
+    pub cmd: UsersCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum UsersCommand {
+    /// List the accounts on the camera
+    List {
+        /// Print as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Add an account
+    Add {
+        /// The name of the new account
+        name: String,
+        /// The password, read from stdin if not given
+        #[arg(long)]
+        password: Option<String>,
+        /// Give the account full control of the camera instead of view only
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Delete an account
+    Delete {
+        /// The name of the account
+        name: String,
+    },
+    /// Change the password of an account
+    Passwd {
+        /// The name of the account
+        name: String,
+        /// The new password, read from stdin if not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+}
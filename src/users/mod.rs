@@ -0,0 +1,147 @@
+///
+/// # Neolink Users
+///
+/// This module handles the users subcommand
+///
+/// The subcommand lists, adds and deletes the accounts on the camera and can
+/// change their passwords. When no `--password` is given it is read from the
+/// first line of stdin so that it does not end up in the shell history
+///
+/// # Usage
+///
+/// ```bash
+/// neolink users --config=config.toml CameraName list
+/// neolink users --config=config.toml CameraName add viewer --password=secret
+/// echo secret | neolink users --config=config.toml CameraName passwd viewer
+/// neolink users --config=config.toml CameraName delete viewer
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc_protocol::UserLevel;
+use serde::Serialize;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+pub(crate) use cmdline::Opt;
+use cmdline::UsersCommand;
+
+#[derive(Serialize)]
+struct UserEntry {
+    name: String,
+    level: &'static str,
+    logged_in: bool,
+}
+
+/// Entry point for the users subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    match opt.cmd {
+        UsersCommand::List { json } => {
+            let users = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.get_users()
+                            .await
+                            .context("Could not get the users of the camera")
+                    })
+                })
+                .await?;
+            let users = users
+                .user
+                .into_iter()
+                .map(|user| UserEntry {
+                    name: user.user_name,
+                    level: match user.user_level {
+                        Some(1) => "admin",
+                        Some(0) => "guest",
+                        _ => "unknown",
+                    },
+                    logged_in: user.login_state == Some(1),
+                })
+                .collect::<Vec<_>>();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&users)?);
+            } else {
+                for user in users.iter() {
+                    println!(
+                        "{:20} {:8}{}",
+                        user.name,
+                        user.level,
+                        if user.logged_in { "logged in" } else { "" }
+                    );
+                }
+            }
+        }
+        UsersCommand::Add {
+            name,
+            password,
+            admin,
+        } => {
+            let password = password_or_stdin(password)?;
+            let level = if admin {
+                UserLevel::Admin
+            } else {
+                UserLevel::Guest
+            };
+            camera
+                .run_task(|cam| {
+                    let name = name.clone();
+                    let password = password.clone();
+                    Box::pin(async move {
+                        cam.add_user(&name, &password, level)
+                            .await
+                            .context("Could not add the user to the camera")
+                    })
+                })
+                .await?;
+        }
+        UsersCommand::Delete { name } => {
+            camera
+                .run_task(|cam| {
+                    let name = name.clone();
+                    Box::pin(async move {
+                        cam.delete_user(&name)
+                            .await
+                            .context("Could not delete the user from the camera")
+                    })
+                })
+                .await?;
+        }
+        UsersCommand::Passwd { name, password } => {
+            let password = password_or_stdin(password)?;
+            camera
+                .run_task(|cam| {
+                    let name = name.clone();
+                    let password = password.clone();
+                    Box::pin(async move {
+                        cam.set_user_password(&name, &password)
+                            .await
+                            .context("Could not change the password of the user")
+                    })
+                })
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn password_or_stdin(password: Option<String>) -> Result<String> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+    let mut line = String::new();
+    std::io::stdin()
+        .read_line(&mut line)
+        .context("Could not read the password from stdin")?;
+    let password = line.trim_end_matches(['\r', '\n']).to_string();
+    if password.is_empty() {
+        Err(anyhow!("The password must not be empty"))
+    } else {
+        Ok(password)
+    }
+}
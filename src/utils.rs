@@ -4,15 +4,33 @@ use log::*;
 
 use super::config::CameraConfig;
 use anyhow::{anyhow, Context, Error, Result};
+use neolink_core::bc::{
+    model::{Bc, BcBody, LegacyMsg, ModernMsg},
+    xml::{BcPayloads, RecordTime},
+};
 use neolink_core::bc_protocol::{
     BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods, MaxEncryption,
 };
+use once_cell::sync::Lazy;
+use regex::Regex;
 use std::{
+    collections::HashMap,
     fmt::{Display, Error as FmtError, Formatter},
     net::{IpAddr, ToSocketAddrs},
     str::FromStr,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+/// The configs of the cameras that only accepted the secondary password, it
+/// is tried first until the config changes so that each reconnect does not
+/// try the rejected password again
+static SECONDARY_ACCEPTED: Lazy<Mutex<HashMap<String, CameraConfig>>> = Lazy::new(Default::default);
+
+static RE_TIME: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\d{4})-(\d{1,2})-(\d{1,2})[ T](\d{1,2}):(\d{2})(?::(\d{2}))?$").unwrap()
+});
+
 pub(crate) fn timeout<F>(future: F) -> tokio::time::Timeout<F>
 where
     F: std::future::Future,
@@ -20,11 +38,50 @@ where
     tokio::time::timeout(tokio::time::Duration::from_secs(15), future)
 }
 
+/// The UTC date and time as `(year, month, day, hour, minute, second)`
+pub(crate) fn utc(time: SystemTime) -> (i64, u32, u32, u64, u64, u64) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (hour, minute, second) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+
+    // Days to civil date, from Howard Hinnant's date algorithms
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, hour, minute, second)
+}
+
+/// The time of a UTC date and time, the inverse of [`utc`]
+pub(crate) fn from_utc(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u64,
+    minute: u64,
+    second: u64,
+) -> SystemTime {
+    // Civil date to days, from Howard Hinnant's date algorithms
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146097 + doe - 719468).max(0) as u64;
+    UNIX_EPOCH + Duration::from_secs(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
 pub(crate) enum AddressOrUid {
     Address(String),
-    #[allow(dead_code)]
     Uid(String, DiscoveryMethods),
-    #[allow(dead_code)]
     AddressWithUid(String, String, DiscoveryMethods),
 }
 
@@ -59,14 +116,48 @@ impl AddressOrUid {
         }
     }
 
+    /// Where to connect to the camera, the address first and then the uid
+    ///
+    /// With `addresses` or `uids` each is tried on its own in order, otherwise
+    /// the address and uid are tried in one go as the camera's discovery does
+    pub(crate) fn from_config(camera_config: &CameraConfig) -> Result<Vec<Self>, Error> {
+        if camera_config.addresses.is_empty() && camera_config.uids.is_empty() {
+            return Ok(vec![Self::new(
+                &camera_config.camera_addr,
+                &camera_config.camera_uid,
+                &camera_config.discovery,
+            )?]);
+        }
+        let addresses = camera_config
+            .camera_addr
+            .iter()
+            .chain(camera_config.addresses.iter())
+            .map(|addr| AddressOrUid::Address(addr.clone()));
+        let uids = camera_config
+            .camera_uid
+            .iter()
+            .chain(camera_config.uids.iter())
+            .map(|uid| AddressOrUid::Uid(uid.clone(), camera_config.discovery));
+        Ok(addresses.chain(uids).collect())
+    }
+
     // Convience method to get the BcCamera with the appropiate method
     // from a camera_config
     pub(crate) async fn connect_camera(
         &self,
         camera_config: &CameraConfig,
+        username: &str,
+        password: Option<String>,
     ) -> Result<BcCamera, Error> {
+        let (address, uid, discovery) = match self {
+            AddressOrUid::Address(addr) => (Some(addr), None, camera_config.discovery),
+            AddressOrUid::Uid(uid, method) => (None, Some(uid.clone()), *method),
+            AddressOrUid::AddressWithUid(addr, uid, method) => {
+                (Some(addr), Some(uid.clone()), *method)
+            }
+        };
         let (port, addrs) = {
-            if let Some(addr_str) = camera_config.camera_addr.as_ref() {
+            if let Some(addr_str) = address {
                 match addr_str.to_socket_addrs() {
                     Ok(addr_iter) => {
                         let mut port = None;
@@ -92,12 +183,12 @@ impl AddressOrUid {
             channel_id: camera_config.channel_id,
             addrs,
             port,
-            uid: camera_config.camera_uid.clone(),
+            uid,
             protocol: ConnectionProtocol::TcpUdp,
-            discovery: camera_config.discovery,
+            discovery,
             credentials: Credentials {
-                username: camera_config.username.clone(),
-                password: camera_config.password.clone(),
+                username: username.to_string(),
+                password,
             },
             debug: camera_config.debug,
             max_discovery_retries: camera_config.max_discovery_retries,
@@ -110,26 +201,105 @@ impl AddressOrUid {
 }
 
 pub(crate) async fn connect_and_login(camera_config: &CameraConfig) -> Result<BcCamera> {
-    let camera_addr = AddressOrUid::new(
-        &camera_config.camera_addr,
-        &camera_config.camera_uid,
-        &camera_config.discovery,
+    let name = &camera_config.name;
+    let secondary_accepted = SECONDARY_ACCEPTED.lock().unwrap().get(name) == Some(camera_config);
+    if secondary_accepted {
+        match login_secondary(camera_config).await {
+            Err(e) if is_login_fail(&e) => {
+                warn!("{name}: The secondary password is no longer accepted, trying the password");
+                SECONDARY_ACCEPTED.lock().unwrap().remove(name);
+            }
+            result => return result,
+        }
+    }
+
+    let password = lookup_password(
+        name,
+        &camera_config.password,
+        &camera_config.password_cmd,
+        &camera_config.password_keyring,
     )
-    .unwrap();
-    info!(
-        "{}: Connecting to camera at {}",
-        camera_config.name, camera_addr
-    );
+    .await?;
+    match login(camera_config, &camera_config.username, password).await {
+        Err(e)
+            if is_login_fail(&e)
+                && camera_config.secondary_password.is_some()
+                && !secondary_accepted =>
+        {
+            warn!("{name}: The password was not accepted, trying the secondary password");
+            let camera = login_secondary(camera_config).await?;
+            warn!(
+                "{name}: Logged in with the secondary password, the password in the config should be updated"
+            );
+            SECONDARY_ACCEPTED
+                .lock()
+                .unwrap()
+                .insert(name.clone(), camera_config.clone());
+            Ok(camera)
+        }
+        Ok(camera) => {
+            SECONDARY_ACCEPTED.lock().unwrap().remove(name);
+            Ok(camera)
+        }
+        result => result,
+    }
+}
 
-    let camera = camera_addr
-        .connect_camera(camera_config)
-        .await
-        .with_context(|| {
-            format!(
-                "Failed to connect to camera {} at {} on channel {}",
-                camera_config.name, camera_addr, camera_config.channel_id
-            )
-        })?;
+async fn login_secondary(camera_config: &CameraConfig) -> Result<BcCamera> {
+    let username = camera_config
+        .secondary_username
+        .as_ref()
+        .unwrap_or(&camera_config.username);
+    login(
+        camera_config,
+        username,
+        camera_config.secondary_password.clone(),
+    )
+    .await
+}
+
+/// If the camera did not accept the username and password
+pub(crate) fn is_login_fail(e: &Error) -> bool {
+    matches!(
+        e.downcast_ref::<neolink_core::Error>(),
+        Some(neolink_core::Error::CameraLoginFail)
+    )
+}
+
+async fn login(
+    camera_config: &CameraConfig,
+    username: &str,
+    password: Option<String>,
+) -> Result<BcCamera> {
+    let camera_addrs = AddressOrUid::from_config(camera_config)?;
+    let mut connected = None;
+    for (i, camera_addr) in camera_addrs.iter().enumerate() {
+        info!(
+            "{}: Connecting to camera at {}",
+            camera_config.name, camera_addr
+        );
+        let result = camera_addr
+            .connect_camera(camera_config, username, password.clone())
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to connect to camera {} at {} on channel {}",
+                    camera_config.name, camera_addr, camera_config.channel_id
+                )
+            });
+        match result {
+            Ok(camera) => {
+                connected = Some(camera);
+                break;
+            }
+            Err(e) if i + 1 < camera_addrs.len() => {
+                warn!("{}: {e:#}, trying the next address", camera_config.name);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let camera =
+        connected.ok_or_else(|| anyhow!("{}: No address to connect to", camera_config.name))?;
 
     let max_encryption = match camera_config.max_encryption.to_lowercase().as_str() {
         "none" => MaxEncryption::None,
@@ -146,3 +316,142 @@ pub(crate) async fn connect_and_login(camera_config: &CameraConfig) -> Result<Bc
 
     Ok(camera)
 }
+
+/// Parse a duration such as `90`, `30s`, `15m`, `24h` or `7d`, plain numbers are seconds
+pub(crate) fn parse_duration(src: &str) -> Result<Duration> {
+    let (number, unit) = src.split_at(src.trim_end_matches(char::is_alphabetic).len());
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("Expected a duration like 60, 30s, 15m, 24h or 7d"))?;
+    let seconds = match unit {
+        "" | "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 60 * 60 * 24,
+        _ => return Err(anyhow!("Unknown unit {unit}, use s, m, h or d")),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parse a time given as `YYYY-MM-DD HH:MM[:SS]` on the command line
+pub(crate) fn parse_record_time(src: &str) -> Result<RecordTime> {
+    let caps = RE_TIME
+        .captures(src)
+        .ok_or_else(|| anyhow!("Expected a time like 2023-05-18 14:02:40"))?;
+    let num = |i: usize| {
+        caps.get(i)
+            .map(|m| m.as_str().parse::<u8>())
+            .unwrap_or(Ok(0))
+    };
+    Ok(RecordTime {
+        year: caps[1].parse()?,
+        month: num(2)?,
+        day: num(3)?,
+        hour: num(4)?,
+        minute: num(5)?,
+        second: num(6)?,
+    })
+}
+
+/// Describe a message for a person reading a log of the traffic
+///
+/// The first line is the header and the rest is the extension and payload
+/// as xml, binary payloads only have their size shown
+pub(crate) fn describe_bc(bc: &Bc) -> String {
+    let meta = &bc.meta;
+    let mut out = format!(
+        "msg_id {} (num {}, channel {}, code {}, class 0x{:04x})",
+        meta.msg_id, meta.msg_num, meta.channel_id, meta.response_code, meta.class
+    );
+    match &bc.body {
+        BcBody::LegacyMsg(LegacyMsg::LoginMsg { username, .. }) => {
+            out.push_str(&format!("\nLegacy login as {username}"));
+        }
+        BcBody::LegacyMsg(msg) => out.push_str(&format!("\nLegacy {msg:?}")),
+        BcBody::ModernMsg(ModernMsg { extension, payload }) => {
+            if let Some(extension) = extension {
+                match quick_xml::se::to_string(extension) {
+                    Ok(xml) => out.push_str(&format!("\n{xml}")),
+                    Err(e) => out.push_str(&format!("\nExtension: {e}")),
+                }
+            }
+            match payload {
+                Some(BcPayloads::BcXml(xml)) => match quick_xml::se::to_string(xml) {
+                    Ok(xml) => out.push_str(&format!("\n{xml}")),
+                    Err(e) => out.push_str(&format!("\nPayload: {e}")),
+                },
+                Some(BcPayloads::Binary(data)) => {
+                    out.push_str(&format!("\nBinary payload of {} bytes", data.len()))
+                }
+                None => {}
+            }
+        }
+    }
+    out
+}
+
+/// The password printed by `password_cmd` or held in the keyring under
+/// `password_keyring`, otherwise the `password` of the config
+///
+/// They are looked up on each connection so that the password is not kept in
+/// the config file and a changed password is picked up
+pub(crate) async fn lookup_password(
+    name: &str,
+    password: &Option<String>,
+    password_cmd: &Option<String>,
+    password_keyring: &Option<String>,
+) -> Result<Option<String>> {
+    if let Some(cmd) = password_cmd {
+        debug!("{name}: Running the password_cmd");
+        #[cfg(not(windows))]
+        let mut command = tokio::process::Command::new("sh");
+        #[cfg(not(windows))]
+        command.arg("-c");
+        #[cfg(windows)]
+        let mut command = tokio::process::Command::new("cmd");
+        #[cfg(windows)]
+        command.arg("/C");
+        let output = command
+            .arg(cmd)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(Duration::from_secs(30), output)
+            .await
+            .with_context(|| format!("{name}: The password_cmd did not finish in time"))?
+            .with_context(|| format!("{name}: Failed to run the password_cmd"))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{name}: The password_cmd failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let stdout = String::from_utf8(output.stdout)
+            .with_context(|| format!("{name}: The password_cmd did not print text"))?;
+        // Only the first line like `pass show` which puts other fields below
+        let password = stdout.lines().next().unwrap_or_default();
+        Ok(Some(password.to_string()))
+    } else if let Some(user) = password_keyring {
+        keyring_password(name, user).await.map(Some)
+    } else {
+        Ok(password.clone())
+    }
+}
+
+#[cfg(feature = "keyring")]
+async fn keyring_password(name: &str, user: &str) -> Result<String> {
+    debug!("{name}: Reading the password of {user} from the keyring");
+    let user = user.to_string();
+    // The keyring calls block on the OS
+    tokio::task::spawn_blocking(move || keyring::Entry::new("neolink", &user)?.get_password())
+        .await?
+        .with_context(|| format!("{name}: Failed to read the password from the keyring"))
+}
+
+#[cfg(not(feature = "keyring"))]
+async fn keyring_password(name: &str, _user: &str) -> Result<String> {
+    Err(anyhow!(
+        "{name}: password_keyring needs neolink to be built with the keyring feature"
+    ))
+}
@@ -0,0 +1,156 @@
+//!
+//! # Neolink Webhooks
+//!
+//! This module posts the events of the cameras to the urls in the
+//! `[[webhooks]]` of the config while the rtsp server runs
+//!
+//! ```toml
+//! [[webhooks]]
+//! url = "https://example.com/neolink"
+//! events = ["motion_start", "doorbell", "people"]
+//! cameras = ["Garden"]
+//! secret = "a-shared-secret"
+//! snapshot = true
+//! ```
+//!
+//! The body is the json of the event such as
+//! `{"time": 1714568700, "camera": "Garden", "kind": "motion_start", "detections": ["people"]}`
+//! with a `"snapshot"` of the camera as a base64 jpeg when it is asked for.
+//! With a `secret` the `X-Neolink-Signature` header is `sha256=<hex>` of the
+//! HMAC-SHA256 of the body
+//!
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hmac::{Hmac, Mac};
+use log::*;
+use reqwest::Client;
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::{sync::mpsc::unbounded_channel, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    common::NeoReactor,
+    config::WebhookConfig,
+    events::{watch, Event},
+    AnyResult,
+};
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    /// A base64 jpeg of the camera
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<String>,
+}
+
+/// Post the events of the cameras to the webhooks until cancelled
+///
+/// The webhooks are read from the config at each event so they follow its changes
+pub(crate) async fn main(reactor: NeoReactor, cancel: CancellationToken) -> AnyResult<()> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .user_agent(concat!("neolink/", env!("NEOLINK_VERSION")))
+        .build()?;
+    let config = reactor.config().await?;
+
+    let (tx, mut rx) = unbounded_channel();
+    let watching = watch(reactor.clone(), cancel, move |event| {
+        let _ = tx.send(event);
+    });
+    let posting = async {
+        while let Some(event) = rx.recv().await {
+            let webhooks = config
+                .borrow()
+                .webhooks
+                .iter()
+                .filter(|webhook| wants(webhook, &event))
+                .cloned()
+                .collect::<Vec<_>>();
+            if webhooks.is_empty() {
+                continue;
+            }
+            let client = client.clone();
+            let reactor = reactor.clone();
+            // Each event is posted on its own so a slow url does not hold up the others
+            tokio::spawn(async move {
+                let snapshot = if webhooks.iter().any(|webhook| webhook.snapshot) {
+                    snapshot(&reactor, &event).await
+                } else {
+                    None
+                };
+                for webhook in webhooks.iter() {
+                    let payload = Payload {
+                        event: &event,
+                        snapshot: snapshot.clone().filter(|_| webhook.snapshot),
+                    };
+                    if let Err(e) = post(&client, webhook, &payload).await {
+                        warn!(
+                            "{}: Failed to post the {} event to {}: {e:?}",
+                            event.camera, event.kind, webhook.url
+                        );
+                    }
+                }
+            });
+        }
+    };
+    let (watched, _) = tokio::join!(watching, posting);
+    watched
+}
+
+/// If the webhook is for the camera and kind of the event, or one of its detections
+fn wants(webhook: &WebhookConfig, event: &Event) -> bool {
+    (webhook.cameras.is_empty() || webhook.cameras.contains(&event.camera))
+        && (webhook.events.is_empty()
+            || webhook.events.contains(&event.kind)
+            || event
+                .detections
+                .iter()
+                .any(|detection| webhook.events.contains(detection)))
+}
+
+/// A base64 jpeg of the camera, `None` if it could not be taken such as when offline
+async fn snapshot(reactor: &NeoReactor, event: &Event) -> Option<String> {
+    if event.kind == "disconnected" {
+        return None;
+    }
+    let jpeg = async {
+        let camera = reactor.get(&event.camera).await?;
+        crate::http::snapshot::snapshot(&camera).await
+    }
+    .await;
+    match jpeg {
+        Ok(jpeg) => Some(BASE64.encode(jpeg)),
+        Err(e) => {
+            debug!("{}: No snapshot for the webhooks: {e:?}", event.camera);
+            None
+        }
+    }
+}
+
+async fn post(client: &Client, webhook: &WebhookConfig, payload: &Payload<'_>) -> AnyResult<()> {
+    let body = serde_json::to_vec(payload)?;
+    let mut request = client
+        .post(&webhook.url)
+        .header("Content-Type", "application/json")
+        .header("X-Neolink-Event", &payload.event.kind);
+    if let Some(secret) = webhook.secret.as_ref() {
+        request = request.header("X-Neolink-Signature", signature(secret, &body));
+    }
+    request.body(body).send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// `sha256=<hex>` of the HMAC-SHA256 of the body
+fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    let hex = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("sha256={hex}")
+}
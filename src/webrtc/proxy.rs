@@ -0,0 +1,126 @@
+//! Forwards the WHEP requests to the signalling server of a pipeline
+//!
+//! `whepserversink` runs its own http server, each pipeline gets one on a
+//! local port and the WHEP endpoints of neolink pass the requests through
+use anyhow::{anyhow, Context};
+use std::net::Ipv4Addr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep, timeout, Duration, Instant},
+};
+
+use crate::{http::server::HttpRequest, AnyResult};
+
+/// Headers of the request that the signalling server needs
+const REQUEST_HEADERS: &[&str] = &["content-type", "accept", "if-match"];
+
+pub(super) struct Reply {
+    pub(super) status: u16,
+    /// Header names are lower case
+    pub(super) headers: Vec<(String, String)>,
+    pub(super) body: Vec<u8>,
+}
+
+impl Reply {
+    pub(super) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Send the request to `path` on the local port and read the whole reply
+pub(super) async fn forward(port: u16, path: &str, request: &HttpRequest) -> AnyResult<Reply> {
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+        .await
+        .with_context(|| format!("Could not reach the WebRTC signaller on port {port}"))?;
+    let mut head = format!(
+        "{} {path} HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        request.method,
+        request.body.len()
+    );
+    for name in REQUEST_HEADERS.iter() {
+        if let Some(value) = request.header(name) {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&request.body).await?;
+
+    let mut reply = vec![];
+    timeout(Duration::from_secs(30), stream.read_to_end(&mut reply))
+        .await
+        .with_context(|| "Timed out waiting for the WebRTC signaller")??;
+    parse_reply(&reply)
+}
+
+/// Wait until the signalling server of a new pipeline accepts connections
+pub(super) async fn wait_listening(port: u16) -> AnyResult<()> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match TcpStream::connect((Ipv4Addr::LOCALHOST, port)).await {
+            Ok(_) => return Ok(()),
+            Err(e) if Instant::now() > deadline => {
+                return Err(anyhow!(
+                    "The WebRTC signaller did not start, check that the gstreamer webrtc plugins are installed: {e}"
+                ))
+            }
+            Err(_) => sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
+
+fn parse_reply(reply: &[u8]) -> AnyResult<Reply> {
+    let split = reply
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("The WebRTC signaller sent an incomplete reply"))?;
+    let head = String::from_utf8_lossy(&reply[..split]);
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| anyhow!("The WebRTC signaller sent an invalid status"))?;
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect::<Vec<_>>();
+
+    let mut body = reply[split + 4..].to_vec();
+    let chunked = headers
+        .iter()
+        .any(|(name, value)| name == "transfer-encoding" && value.contains("chunked"));
+    if chunked {
+        body = dechunk(&body)?;
+    }
+    Ok(Reply {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn dechunk(mut data: &[u8]) -> AnyResult<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line_end = data
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| anyhow!("Invalid chunk"))?;
+        let size = String::from_utf8_lossy(&data[..line_end]);
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16)?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        let chunk = data
+            .get(..size)
+            .ok_or_else(|| anyhow!("Chunk is shorter than its size"))?;
+        body.extend_from_slice(chunk);
+        data = data.get(size + 2..).unwrap_or(&[]);
+    }
+}
@@ -0,0 +1,307 @@
+//! A `whepserversink` pipeline for each stream that is being watched
+//!
+//! The pipeline is made on the first WHEP request for the stream and stays up
+//! while there are browsers connected to it. `webrtcsink` negotiates the codec
+//! with each browser, H264 is passed through and anything else is encoded
+use anyhow::{anyhow, Context};
+use futures::stream::StreamExt;
+use gstreamer::{parse::launch_full, prelude::*, ParseFlags, Pipeline, State};
+use gstreamer_app::AppSrc;
+use neolink_core::bc_protocol::StreamKind;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use tokio::{
+    sync::Mutex as AsyncMutex,
+    time::{interval, timeout, Duration, Instant},
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_util::sync::CancellationToken;
+
+use super::proxy::wait_listening;
+use crate::{
+    common::{get_source, AudFormat, NeoInstance, StampedData, VidFormat},
+    config::WebRtcConfig,
+    AnyResult,
+};
+
+/// How long a stream stays up without anyone watching
+const IDLE: Duration = Duration::from_secs(30);
+
+pub(super) struct Session {
+    /// The local port of the signalling server of the pipeline
+    pub(super) port: u16,
+    consumers: Arc<AtomicUsize>,
+    last_used: Mutex<Instant>,
+}
+
+impl Session {
+    /// Keep the session up while a browser is connecting
+    pub(super) fn touch(&self) {
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+
+    fn is_idle(&self) -> bool {
+        if self.consumers.load(Ordering::Relaxed) > 0 {
+            self.touch();
+            false
+        } else {
+            self.last_used.lock().unwrap().elapsed() > IDLE
+        }
+    }
+}
+
+type Key = (String, StreamKind);
+
+/// The running sessions by camera and stream
+#[derive(Clone, Default)]
+pub(super) struct Sessions(Arc<AsyncMutex<HashMap<Key, Arc<Session>>>>);
+
+impl Sessions {
+    /// The session of the stream if it is running
+    pub(super) async fn running(&self, name: &str, kind: StreamKind) -> Option<Arc<Session>> {
+        self.0.lock().await.get(&(name.to_string(), kind)).cloned()
+    }
+
+    /// The session of the stream, started if it is not running
+    pub(super) async fn get(
+        &self,
+        camera: &NeoInstance,
+        name: &str,
+        kind: StreamKind,
+        webrtc_config: &WebRtcConfig,
+        cancel: &CancellationToken,
+    ) -> AnyResult<Arc<Session>> {
+        let key = (name.to_string(), kind);
+        // Held while starting so that two browsers do not start the same stream
+        let mut sessions = self.0.lock().await;
+        if let Some(session) = sessions.get(&key) {
+            session.touch();
+            return Ok(session.clone());
+        }
+
+        let stream = camera.stream(kind).await?;
+        let mut stream_config = stream.config.clone();
+        let vid_format = timeout(
+            Duration::from_secs(15),
+            stream_config.wait_for(|config| config.vid_ready()),
+        )
+        .await
+        .with_context(|| "Timed out waiting for the stream")??
+        .vid_format;
+        // Audio is optional so only wait a short while for it
+        let _ = timeout(
+            Duration::from_secs(2),
+            stream_config.wait_for(|config| config.aud_ready()),
+        )
+        .await;
+        let aud_format = stream_config.borrow().aud_format;
+
+        let listener = reserve_port()?;
+        let port = listener.local_addr()?.port();
+        let pipeline = WebRtcPipeline::new(vid_format, aud_format, listener, webrtc_config)?;
+        let session = Arc::new(Session {
+            port,
+            consumers: pipeline.consumers.clone(),
+            last_used: Mutex::new(Instant::now()),
+        });
+        log::info!("{name}: Starting the WebRTC stream {kind:?}");
+
+        let thread_session = session.clone();
+        let thread_sessions = self.clone();
+        let stop = cancel.child_token();
+        let thread_cancel = stop.clone();
+        let thread_name = name.to_string();
+        tokio::task::spawn(async move {
+            let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+            let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+            let mut check = interval(Duration::from_secs(5));
+            let mut started = false;
+            loop {
+                tokio::select! {
+                    _ = thread_cancel.cancelled() => break,
+                    frame = vid.next() => {
+                        let Some(frame) = frame else {
+                            log::debug!("{thread_name}: The stream stopped");
+                            break;
+                        };
+                        // Lagged frames are skipped, the browser recovers at the next keyframe
+                        let Ok(frame) = frame else {
+                            continue;
+                        };
+                        if !started && !frame.keyframe {
+                            continue;
+                        }
+                        started = true;
+                        if let Err(e) = pipeline.push(&pipeline.vid_source, &frame) {
+                            log::warn!("{thread_name}: WebRTC stream failed: {e:?}");
+                            break;
+                        }
+                    }
+                    Some(frame) = aud.next() => {
+                        if let (Ok(frame), Some(source), true) = (frame, pipeline.aud_source.as_ref(), started) {
+                            let _ = pipeline.push(source, &frame);
+                        }
+                    }
+                    _ = check.tick() => {
+                        if thread_session.is_idle() {
+                            break;
+                        }
+                    }
+                }
+            }
+            log::info!("{thread_name}: Stopping the WebRTC stream {:?}", key.1);
+            let mut sessions = thread_sessions.0.lock().await;
+            if sessions
+                .get(&key)
+                .is_some_and(|session| Arc::ptr_eq(session, &thread_session))
+            {
+                sessions.remove(&key);
+            }
+            drop(pipeline);
+            drop(stream);
+        });
+
+        if let Err(e) = wait_listening(port).await {
+            stop.cancel();
+            return Err(e);
+        }
+        sessions.insert((name.to_string(), kind), session.clone());
+        Ok(session)
+    }
+}
+
+struct WebRtcPipeline {
+    pipeline: Pipeline,
+    vid_source: AppSrc,
+    aud_source: Option<AppSrc>,
+    /// The number of browsers connected
+    consumers: Arc<AtomicUsize>,
+}
+
+impl WebRtcPipeline {
+    fn new(
+        vid_format: VidFormat,
+        aud_format: AudFormat,
+        listener: TcpListener,
+        webrtc_config: &WebRtcConfig,
+    ) -> AnyResult<Self> {
+        let port = listener.local_addr()?.port();
+        gstreamer::init()
+            .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+        // Browsers do not play H265 so it is decoded for webrtcsink to encode
+        let video = match vid_format {
+            VidFormat::H264 => "h264parse",
+            VidFormat::H265 => "h265parse ! avdec_h265 ! videoconvert",
+            VidFormat::None => return Err(anyhow!("Video format is not yet known")),
+        };
+        // The caps of the source and how to decode it for webrtcsink to encode
+        let audio = match aud_format {
+            AudFormat::Aac => Some((String::new(), "aacparse ! avdec_aac")),
+            AudFormat::Adpcm(block_size) => Some((
+                format!(
+                    " caps=\"audio/x-adpcm,layout=dvi,block_align={block_size},channels=1,rate=8000\""
+                ),
+                "adpcmdec",
+            )),
+            AudFormat::None => None,
+        };
+        let mut launch_str = format!(
+            "whepserversink name=thesink signaller::host-addr=http://127.0.0.1:{port} \
+            appsrc name=thevidsource is-live=true do-timestamp=true format=time \
+            ! {video} ! queue ! thesink."
+        );
+        if let Some((caps, decoder)) = audio.as_ref() {
+            launch_str.push_str(&format!(
+                " appsrc name=theaudsource is-live=true do-timestamp=true format=time{caps} \
+                ! {decoder} ! audioconvert ! audioresample ! queue ! thesink."
+            ));
+        }
+        let pipeline = launch_full(&launch_str, None, ParseFlags::empty()).context(
+            "Unable to load the WebRTC pipeline, ensure the gstreamer webrtc plugins from gst-plugins-rs are installed",
+        )?;
+        let pipeline = pipeline.dynamic_cast::<Pipeline>().map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+
+        let sink = pipeline
+            .by_name("thesink")
+            .ok_or_else(|| anyhow!("Cannot find the whepserversink in the pipeline"))?;
+        sink.set_property_from_str("stun-server", &webrtc_config.stun_server);
+        if !webrtc_config.turn_servers.is_empty() {
+            let turn_servers = webrtc_config
+                .turn_servers
+                .iter()
+                .map(|server| format!("\"{server}\""))
+                .collect::<Vec<_>>()
+                .join(",");
+            sink.set_property_from_str("turn-servers", &format!("<{turn_servers}>"));
+        }
+        if webrtc_config.ice_transport_policy != "all" {
+            if sink.find_property("ice-transport-policy").is_some() {
+                sink.set_property_from_str(
+                    "ice-transport-policy",
+                    &webrtc_config.ice_transport_policy,
+                );
+            } else {
+                log::warn!("This version of webrtcsink cannot limit the ICE transport policy");
+            }
+        }
+
+        let consumers = Arc::new(AtomicUsize::new(0));
+        let added = consumers.clone();
+        sink.connect("consumer-added", false, move |_| {
+            added.fetch_add(1, Ordering::Relaxed);
+            None
+        });
+        let removed = consumers.clone();
+        sink.connect("consumer-removed", false, move |_| {
+            let _ = removed.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            });
+            None
+        });
+
+        let vid_source = get_source(&pipeline, "thevidsource")?;
+        let aud_source = if audio.is_some() {
+            Some(get_source(&pipeline, "theaudsource")?)
+        } else {
+            None
+        };
+        // The signaller binds the port itself when it starts and cannot be
+        // given the socket, so the port is held until just before then
+        drop(listener);
+        pipeline.set_state(State::Playing)?;
+        Ok(Self {
+            pipeline,
+            vid_source,
+            aud_source,
+            consumers,
+        })
+    }
+
+    fn push(&self, source: &AppSrc, frame: &StampedData) -> AnyResult<()> {
+        let buf = gstreamer::Buffer::from_slice(frame.data.clone());
+        source
+            .push_buffer(buf)
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        Ok(())
+    }
+}
+
+impl Drop for WebRtcPipeline {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipeline.set_state(State::Null) {
+            log::warn!("Error in gstreamer when setting state to Null: {e:?}");
+        }
+    }
+}
+
+/// Hold a local port for the signalling server of a pipeline
+fn reserve_port() -> AnyResult<TcpListener> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .with_context(|| "Could not find a free port for the WebRTC signaller")
+}
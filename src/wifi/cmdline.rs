@@ -0,0 +1,31 @@
+use clap::Parser;
+
+/// The wifi command will show, scan or change the wifi network of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Print as json
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub cmd: WifiCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum WifiCommand {
+    /// Print the network the camera is joined to and its signal
+    Status,
+    /// List the networks that the camera can see
+    Scan,
+    /// Join the camera to a network
+    Set {
+        /// Name of the network
+        #[arg(long)]
+        ssid: String,
+        /// Password of the network
+        #[arg(long)]
+        password: String,
+    },
+}
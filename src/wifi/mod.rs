@@ -0,0 +1,110 @@
+///
+/// # Neolink Wifi
+///
+/// This module handles the wifi subcommand
+///
+/// The subcommand prints the network the camera is on, scans for networks
+/// or joins the camera to a new one. The camera can be setup over ethernet
+/// or its current network and then moved to another without the app
+///
+/// # Usage
+///
+/// ```bash
+/// neolink wifi --config=config.toml CameraName status
+/// neolink wifi --config=config.toml CameraName scan --json
+/// neolink wifi --config=config.toml CameraName set --ssid=HomeNetwork --password=secret
+/// ```
+///
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+mod cmdline;
+
+use crate::{common::NeoReactor, AnyResult};
+pub(crate) use cmdline::Opt;
+use cmdline::WifiCommand;
+
+#[derive(Serialize)]
+struct Status {
+    ssid: Option<String>,
+    signal: Option<i32>,
+}
+
+/// Entry point for the wifi subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    match opt.cmd {
+        WifiCommand::Status => {
+            let status = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        // A wired camera has neither so report what there is
+                        let ssid = cam.get_wifi().await.ok().map(|wifi| wifi.ssid);
+                        let signal = cam.get_wifi_signal().await.ok().map(|s| s.signal);
+                        AnyResult::Ok(Status { ssid, signal })
+                    })
+                })
+                .await?;
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+            } else {
+                match (status.ssid, status.signal) {
+                    (None, None) => println!("The camera is not using wifi"),
+                    (ssid, signal) => println!(
+                        "Network: {}, Signal: {}",
+                        ssid.as_deref().unwrap_or("unknown"),
+                        signal
+                            .map(|s| format!("{s} dBm"))
+                            .unwrap_or_else(|| "unknown".to_string())
+                    ),
+                }
+            }
+        }
+        WifiCommand::Scan => {
+            let mut networks = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.scan_wifi()
+                            .await
+                            .context("Could not scan for wifi networks")
+                    })
+                })
+                .await?;
+            networks.sort_by_key(|network| std::cmp::Reverse(network.signal));
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&networks)?);
+            } else {
+                for network in networks.iter() {
+                    println!(
+                        "{:32} {:>8} {}",
+                        network.ssid,
+                        network
+                            .signal
+                            .map(|s| format!("{s} dBm"))
+                            .unwrap_or_default(),
+                        network.encryption.as_deref().unwrap_or("")
+                    );
+                }
+            }
+        }
+        WifiCommand::Set { ssid, password } => {
+            camera
+                .run_task(|cam| {
+                    let ssid = ssid.clone();
+                    let password = password.clone();
+                    Box::pin(async move {
+                        cam.set_wifi(&ssid, &password)
+                            .await
+                            .context("Could not set the wifi network of the camera")
+                    })
+                })
+                .await?;
+            println!("The camera will now join {ssid}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use clap::{Parser, ValueEnum};
+
+/// The windows-service command installs neolink as a Windows service that starts at boot
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The action to perform
+    #[command(subcommand)]
+    pub cmd: ServiceAction,
+}
+
+#[derive(Parser, Debug)]
+pub enum ServiceAction {
+    /// Install the service to run with the --config that is given, this needs an administrator
+    Install {
+        /// The name of the service
+        #[arg(long, default_value = "neolink")]
+        name: String,
+        /// The command that the service runs
+        #[arg(long, value_enum, default_value = "rtsp")]
+        command: ServiceCommand,
+    },
+    /// Stop and remove the service, this needs an administrator
+    Uninstall {
+        /// The name of the service
+        #[arg(long, default_value = "neolink")]
+        name: String,
+    },
+    /// Run as the service, this is what Windows starts
+    Run {
+        /// The name of the service
+        #[arg(long, default_value = "neolink")]
+        name: String,
+        /// The command to run
+        #[arg(long, value_enum, default_value = "rtsp")]
+        command: ServiceCommand,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ServiceCommand {
+    Rtsp,
+    Mqtt,
+    MqttRtsp,
+}
@@ -0,0 +1,246 @@
+///
+/// # Neolink Windows Service
+///
+/// This module handles the windows-service subcommand
+///
+/// It installs neolink as a Windows service so that it starts at boot without
+/// a wrapper such as NSSM. The service logs to the Windows event log under
+/// the name of the service
+///
+/// # Usage
+///
+/// ```bash
+/// # From an administrator prompt
+/// neolink windows-service --config=C:\neolink\neolink.toml install
+/// # To run the mqtt command instead of rtsp
+/// neolink windows-service --config=C:\neolink\neolink.toml install --command mqtt
+/// # To stop and remove it
+/// neolink windows-service uninstall
+/// ```
+///
+use anyhow::{anyhow, Context};
+use clap::ValueEnum;
+use log::*;
+use std::{ffi::OsString, path::PathBuf, sync::Mutex, time::Duration};
+use tokio::runtime::Handle;
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+mod cmdline;
+
+use crate::{cmdline::Command, AnyResult};
+pub(crate) use cmdline::*;
+
+/// What `run` was started with, Windows calls the service back without it
+struct Launch {
+    handle: Handle,
+    name: String,
+    config: PathBuf,
+    command: ServiceCommand,
+}
+
+static LAUNCH: Mutex<Option<Launch>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Entry point for the windows-service subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, config: Option<PathBuf>) -> AnyResult<()> {
+    match opt.cmd {
+        ServiceAction::Install { name, command } => {
+            install(&name, command, config.context("Must supply --config file")?)
+        }
+        ServiceAction::Uninstall { name } => uninstall(&name),
+        ServiceAction::Run { name, command } => {
+            *LAUNCH.lock().unwrap() = Some(Launch {
+                handle: Handle::current(),
+                name: name.clone(),
+                config: config.context("Must supply --config file")?,
+                command,
+            });
+            // Returns once the service has stopped
+            tokio::task::block_in_place(|| service_dispatcher::start(&name, ffi_service_main))
+                .with_context(|| {
+                    format!("Failed to start the {name} service, it can only be started by Windows")
+                })?;
+            Ok(())
+        }
+    }
+}
+
+/// Log to the event log when running as the service
+///
+/// Returns true if the logger was set up
+pub(crate) fn init_event_log(cmd: &Option<Command>) -> AnyResult<bool> {
+    match cmd {
+        Some(Command::WindowsService(Opt {
+            cmd: ServiceAction::Run { name, .. },
+        })) => {
+            eventlog::init(name, Level::Info)
+                .map_err(|e| anyhow!("Failed to log to the event log: {e:?}"))?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn install(name: &str, command: ServiceCommand, config: PathBuf) -> AnyResult<()> {
+    // The service does not start in the current directory
+    let config = std::env::current_dir()?.join(config);
+    if !config.is_file() {
+        return Err(anyhow!("The config {config:?} does not exist"));
+    }
+    let command = command
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .expect("Commands are not skipped");
+
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .context("Failed to open the service manager, is this an administrator prompt?")?;
+    let info = ServiceInfo {
+        name: OsString::from(name),
+        display_name: OsString::from(format!("Neolink ({name})")),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec![
+            "--config".into(),
+            config.clone().into(),
+            "windows-service".into(),
+            "run".into(),
+            "--name".into(),
+            name.into(),
+            "--command".into(),
+            command.clone().into(),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .with_context(|| format!("Failed to create the {name} service"))?;
+    service.set_description("A standards-compliant bridge to Reolink IP cameras")?;
+
+    if let Err(e) = eventlog::register(name) {
+        warn!("Failed to register with the event log, the logs may not show: {e:?}");
+    }
+    info!("Installed the {name} service to run `{command}` with the config {config:?}");
+    info!("Start it with `sc start {name}` or reboot");
+    Ok(())
+}
+
+fn uninstall(name: &str) -> AnyResult<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .context("Failed to open the service manager, is this an administrator prompt?")?;
+    let service = manager
+        .open_service(
+            name,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .with_context(|| format!("Failed to open the {name} service"))?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        info!("Stopping the {name} service");
+        service.stop()?;
+    }
+    service
+        .delete()
+        .with_context(|| format!("Failed to remove the {name} service"))?;
+
+    if let Err(e) = eventlog::deregister(name) {
+        warn!("Failed to remove the event log source: {e:?}");
+    }
+    info!("Removed the {name} service");
+    Ok(())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("The service stopped: {e:?}");
+    }
+}
+
+fn run_service() -> AnyResult<()> {
+    let Launch {
+        handle,
+        name,
+        config,
+        command: service_command,
+    } = LAUNCH
+        .lock()
+        .unwrap()
+        .take()
+        .context("The service was started twice")?;
+    let thread_name = name.clone();
+    let status = service_control_handler::register(&name, move |event| match event {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            // The services stop cleanly and logout of the cameras
+            info!("Stopping the {thread_name} service");
+            crate::shutdown::request();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    status.set_service_status(service_status(
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        0,
+    ))?;
+    info!("Running as the {name} service");
+
+    let result = match command(service_command) {
+        Ok(command) => handle.block_on(crate::run(Some(config), Some(command))),
+        Err(e) => Err(e),
+    };
+
+    status.set_service_status(service_status(
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+        if result.is_ok() { 0 } else { 1 },
+    ))?;
+    result
+}
+
+fn command(command: ServiceCommand) -> AnyResult<Command> {
+    match command {
+        #[cfg(feature = "gstreamer")]
+        ServiceCommand::Rtsp => Ok(Command::Rtsp(crate::rtsp::Opt {})),
+        ServiceCommand::Mqtt => Ok(Command::Mqtt(crate::mqtt::Opt {})),
+        #[cfg(feature = "gstreamer")]
+        ServiceCommand::MqttRtsp => Ok(Command::MqttRtsp(crate::mqtt::Opt {})),
+        #[cfg(not(feature = "gstreamer"))]
+        ServiceCommand::Rtsp | ServiceCommand::MqttRtsp => Err(anyhow!(
+            "The {command:?} command needs neolink to be built with gstreamer"
+        )),
+    }
+}
+
+fn service_status(
+    current_state: ServiceState,
+    controls_accepted: ServiceControlAccept,
+    exit_code: u32,
+) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(exit_code),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    }
+}